@@ -0,0 +1,433 @@
+//poleshift/src-tauri/src/auth/mod.rs
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::devtools::{traced, traced_blocking, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::now_ms;
+use crate::sync::{SyncCredentials, SyncQueue};
+
+/// Event carrying the `AuthState` each time the session changes.
+pub const AUTH_STATE_EVENT: &str = "auth-state";
+/// Keychain entry the session is kept in between runs.
+const KEYRING_SERVICE: &str = "poleshift";
+const KEYRING_ACCOUNT: &str = "supabase-session";
+/// How long before the access token expires it is renewed.
+const REFRESH_MARGIN_SECS: i64 = 5 * 60;
+/// Wait before retrying a renewal that could not get through, doubled on each further
+/// failure.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+/// Longest the refresher sleeps without a session to watch.
+const IDLE_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// A Supabase session. Not `Debug`, so its tokens cannot end up in a log line.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// When the access token expires, in seconds since the Unix epoch
+    pub expires_at: i64,
+    pub user_id: Option<String>,
+}
+
+/// Supabase project the session belongs to.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SupabaseProject {
+    pub supabase_url: String,
+    pub api_key: String,
+}
+
+/// What is kept in the keychain.
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    project: SupabaseProject,
+    session: Session,
+}
+
+/// Sign-in state, as emitted on `auth-state`.
+#[derive(Clone, Serialize)]
+pub struct AuthState {
+    pub signed_in: bool,
+    pub user_id: Option<String>,
+    pub expires_at: Option<i64>,
+    /// The current session, for the frontend's Supabase client to adopt
+    pub session: Option<Session>,
+}
+
+/// Holds the Supabase session and renews it before it expires.
+///
+/// The session is handed over by the frontend with `set_auth_session`, kept in the OS
+/// keychain across runs (see `restore_auth_session`) and passed on to the `SyncQueue`.
+/// A background thread, started with `start` from the app's setup, renews the access
+/// token `REFRESH_MARGIN_SECS` before it expires, so long processing runs do not fail
+/// mid-upload; it is the only place the refresh token is spent.
+#[derive(Default)]
+pub struct AuthManager {
+    project: Mutex<Option<SupabaseProject>>,
+    session: Mutex<Option<Session>>,
+    wake: Mutex<Option<Sender<()>>>,
+    renew_now: AtomicBool,
+}
+
+enum Refresh {
+    Renewed(Session),
+    /// The refresh token is no longer valid; the user has to sign in again
+    Rejected(String),
+    /// The server could not be reached, or asked to be tried again later
+    Offline(String),
+}
+
+fn keyring_error(e: keyring::Error) -> PoleshiftError {
+    PoleshiftError::DataError(format!("Keychain: {}", e))
+}
+
+fn keyring_entry() -> Result<keyring::Entry, PoleshiftError> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT).map_err(keyring_error)
+}
+
+fn now_secs() -> i64 {
+    now_ms() / 1000
+}
+
+impl AuthManager {
+    /// Starts the renewing thread.
+    pub fn start<R: Runtime>(&self, app_handle: AppHandle<R>) {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut wake) = self.wake.lock() {
+            *wake = Some(sender);
+        }
+        std::thread::spawn(move || {
+            let mut failures = 0;
+            let mut delay = IDLE_DELAY;
+            while let Ok(()) | Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(delay) {
+                let Some(auth) = app_handle.try_state::<AuthManager>() else {
+                    break;
+                };
+                delay = match auth.renew_if_due(&app_handle) {
+                    Ok(()) => {
+                        failures = 0;
+                        auth.until_renewal()
+                    }
+                    Err(e) => {
+                        println!("Session renewal failed: {}", e);
+                        failures += 1;
+                        retry_delay(failures)
+                    }
+                };
+            }
+        });
+    }
+
+    /// Asks the renewing thread to check the session now.
+    fn wake(&self) {
+        if let Some(sender) = self.wake.lock().ok().and_then(|wake| wake.clone()) {
+            let _ = sender.send(());
+        }
+    }
+
+    /// Renews the session at once, e.g. after the server turned its token down.
+    pub(crate) fn request_renewal(&self) {
+        self.renew_now.store(true, Ordering::Relaxed);
+        self.wake();
+    }
+
+    pub(crate) fn session(&self) -> Option<Session> {
+        self.session.lock().ok().and_then(|s| s.clone())
+    }
+
+    pub(crate) fn project(&self) -> Option<SupabaseProject> {
+        self.project.lock().ok().and_then(|p| p.clone())
+    }
+
+    fn state(&self) -> AuthState {
+        let session = self.session();
+        AuthState {
+            signed_in: session.is_some(),
+            user_id: session.as_ref().and_then(|s| s.user_id.clone()),
+            expires_at: session.as_ref().map(|s| s.expires_at),
+            session,
+        }
+    }
+
+    /// Time until the session is due for renewal.
+    fn until_renewal(&self) -> Duration {
+        match self.session() {
+            Some(session) => {
+                let secs = session.expires_at - REFRESH_MARGIN_SECS - now_secs();
+                Duration::from_secs(secs.max(0) as u64).min(IDLE_DELAY)
+            }
+            None => IDLE_DELAY,
+        }
+    }
+
+    /// Makes `session` of `project` current: kept in the keychain, passed to the sync
+    /// queue and announced on `auth-state`. `None` signs out.
+    fn set_session<R: Runtime>(
+        &self,
+        app_handle: &AppHandle<R>,
+        project: Option<SupabaseProject>,
+        session: Option<Session>,
+    ) {
+        let credentials = match (&project, &session) {
+            (Some(project), Some(session)) => Some(SyncCredentials {
+                supabase_url: project.supabase_url.clone(),
+                api_key: project.api_key.clone(),
+                access_token: session.access_token.clone(),
+            }),
+            _ => None,
+        };
+
+        // A keychain that cannot be used costs the next run its session, nothing else
+        let stored = match (project.clone(), session.clone()) {
+            (Some(project), Some(session)) => {
+                serde_json::to_string(&StoredSession { project, session })
+                    .map_err(PoleshiftError::from)
+                    .and_then(|json| keyring_entry()?.set_password(&json).map_err(keyring_error))
+            }
+            _ => keyring_entry().and_then(|entry| match entry.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(keyring_error(e)),
+            }),
+        };
+        if let Err(e) = stored {
+            println!("Could not keep the session: {}", e);
+        }
+
+        if let Ok(mut current) = self.project.lock() {
+            *current = project;
+        }
+        if let Ok(mut current) = self.session.lock() {
+            *current = session;
+        }
+        if let Some(queue) = app_handle.try_state::<SyncQueue>() {
+            queue.set_credentials(credentials);
+        }
+        self.wake();
+        let _ = app_handle.emit(AUTH_STATE_EVENT, self.state());
+    }
+
+    /// Takes in the outcome of a renewal; a rejected refresh token signs out.
+    fn apply_refresh<R: Runtime>(
+        &self,
+        app_handle: &AppHandle<R>,
+        refresh: Refresh,
+    ) -> Result<(), PoleshiftError> {
+        match refresh {
+            Refresh::Renewed(session) => {
+                self.set_session(app_handle, self.project(), Some(session));
+                Ok(())
+            }
+            Refresh::Rejected(error) => {
+                println!("Session ended: {}", error);
+                self.set_session(app_handle, None, None);
+                Ok(())
+            }
+            Refresh::Offline(error) => Err(PoleshiftError::DataError(format!(
+                "Could not reach Supabase to renew the session: {}",
+                error
+            ))),
+        }
+    }
+
+    /// Renews the session when it is close to expiring or a renewal was asked for.
+    fn renew_if_due<R: Runtime>(&self, app_handle: &AppHandle<R>) -> Result<(), PoleshiftError> {
+        let (Some(project), Some(session)) = (self.project(), self.session()) else {
+            return Ok(());
+        };
+        let forced = self.renew_now.swap(false, Ordering::Relaxed);
+        if !forced && session.expires_at - REFRESH_MARGIN_SECS > now_secs() {
+            return Ok(());
+        }
+        let refresh =
+            tauri::async_runtime::block_on(refresh_session(&project, &session.refresh_token));
+        self.apply_refresh(app_handle, refresh)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenUser {
+    id: String,
+}
+
+/// Reply of the Supabase token endpoint.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: i64,
+    expires_at: Option<i64>,
+    user: Option<TokenUser>,
+}
+
+/// Wait before the next renewal after `failures` failed ones in a row.
+fn retry_delay(failures: u32) -> Duration {
+    RETRY_DELAY
+        .saturating_mul(1 << failures.saturating_sub(1).min(10))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Whether the token endpoint answering `status` turned the refresh token down for
+/// good. Anything else, a timeout or rate limit (408, 429) included, is tried again.
+fn refresh_rejected(status: StatusCode) -> bool {
+    matches!(status, StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED)
+}
+
+/// Trades `refresh_token` for a new session.
+async fn refresh_session(project: &SupabaseProject, refresh_token: &str) -> Refresh {
+    let body = match serde_json::to_vec(&serde_json::json!({ "refresh_token": refresh_token })) {
+        Ok(body) => body,
+        Err(e) => return Refresh::Offline(e.to_string()),
+    };
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/auth/v1/token?grant_type=refresh_token",
+            project.supabase_url.trim_end_matches('/')
+        ))
+        .header("apikey", &project.api_key)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return Refresh::Offline(e.to_string()),
+    };
+    let status = response.status();
+    if refresh_rejected(status) {
+        return Refresh::Rejected(format!(
+            "{}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        ));
+    }
+    if !status.is_success() {
+        return Refresh::Offline(status.to_string());
+    }
+    let token = match response.bytes().await {
+        Ok(bytes) => serde_json::from_slice::<TokenResponse>(&bytes).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match token {
+        Ok(token) => Refresh::Renewed(Session {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token
+                .expires_at
+                .unwrap_or_else(|| now_secs() + token.expires_in),
+            user_id: token.user.map(|user| user.id),
+        }),
+        Err(e) => Refresh::Offline(e),
+    }
+}
+
+/// Hands over the session of a sign-in, or `None` on sign-out. From then on the
+/// backend renews it and announces each new one on `auth-state`.
+#[tauri::command(rename_all = "snake_case")]
+pub fn set_auth_session<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    auth: State<'_, AuthManager>,
+    project: SupabaseProject,
+    session: Option<Session>,
+) -> Result<StandardResponseNoFiles<AuthState>, PoleshiftError> {
+    traced_blocking(invocation, || {
+        // The frontend hands back each session it adopted from `auth-state`
+        let current = auth.session().map(|s| s.access_token);
+        if current != session.as_ref().map(|s| s.access_token.clone()) {
+            let project = session.as_ref().map(|_| project);
+            auth.set_session(&app_handle, project, session);
+        }
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: auth.state(),
+        })
+    })
+}
+
+/// Restores the session kept in the keychain by an earlier run, renewing it first
+/// when it has expired or is about to. Signed out when there is none, it belongs to
+/// another project or it can no longer be renewed.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn restore_auth_session<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    auth: State<'_, AuthManager>,
+    project: SupabaseProject,
+) -> Result<StandardResponseNoFiles<AuthState>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let stored = match keyring_entry()?.get_password() {
+            Ok(json) => serde_json::from_str::<StoredSession>(&json).ok(),
+            Err(keyring::Error::NoEntry) => None,
+            Err(e) => return Err(keyring_error(e)),
+        }
+        .filter(|stored| stored.project.supabase_url == project.supabase_url);
+
+        match stored {
+            Some(stored) if stored.session.expires_at - REFRESH_MARGIN_SECS <= now_secs() => {
+                match refresh_session(&project, &stored.session.refresh_token).await {
+                    // Offline: keep the stored session and let the refresher retry
+                    Refresh::Offline(e) => {
+                        println!("Restoring an expired session offline: {}", e);
+                        auth.set_session(&app_handle, Some(project), Some(stored.session));
+                    }
+                    refresh => {
+                        auth.set_session(&app_handle, Some(project), Some(stored.session));
+                        auth.apply_refresh(&app_handle, refresh)?;
+                    }
+                }
+            }
+            Some(stored) => auth.set_session(&app_handle, Some(project), Some(stored.session)),
+            None => auth.set_session(&app_handle, None, None),
+        }
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: auth.state(),
+        })
+    })
+    .await
+}
+
+/// Returns whether a session is held, whose, and when it expires.
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_auth_state(
+    invocation: Invocation,
+    auth: State<'_, AuthManager>,
+) -> Result<StandardResponseNoFiles<AuthState>, PoleshiftError> {
+    traced_blocking(invocation, || {
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: auth.state(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bad_request_and_unauthorized_end_the_session() {
+        assert!(refresh_rejected(StatusCode::BAD_REQUEST));
+        assert!(refresh_rejected(StatusCode::UNAUTHORIZED));
+        for status in [408, 429, 403, 500, 503] {
+            assert!(!refresh_rejected(StatusCode::from_u16(status).unwrap()));
+        }
+    }
+
+    #[test]
+    fn retries_back_off_up_to_the_limit() {
+        assert_eq!(retry_delay(1), RETRY_DELAY);
+        assert_eq!(retry_delay(2), RETRY_DELAY * 2);
+        assert_eq!(retry_delay(3), RETRY_DELAY * 4);
+        assert_eq!(retry_delay(40), MAX_RETRY_DELAY);
+    }
+}
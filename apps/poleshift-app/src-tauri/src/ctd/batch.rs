@@ -0,0 +1,416 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Window};
+
+use crate::ctd::cast::ProfileSample;
+use crate::ctd::delimited::DelimitedOptions;
+use crate::ctd::handle_ctd_data::{process_ctd_data, CTDReport, ChannelMapping, CtdRunArgs};
+use crate::ctd::options::CtdProcessingOptions;
+use crate::ctd::output_store::DEFAULT_PREVIEW_ROWS;
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Most files processed at once.
+const MAX_PARALLEL: usize = 16;
+/// How often the batch checks whether it was cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// One cast of a batch, with the IDs `handle_ctd_data` takes for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CtdBatchFile {
+    pub sample_id: String,
+    pub raw_data_id: String,
+    pub processed_data_id: String,
+    pub file_paths: Vec<String>,
+    #[serde(default)]
+    pub profile_samples: Vec<ProfileSample>,
+    /// ID of the file's job, for `cancel_job`; generated when not given
+    #[serde(default)]
+    pub job_id: Option<String>,
+}
+
+/// Settings shared by every file of a batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CtdBatchOptions {
+    /// Files processed at the same time
+    pub max_parallel: usize,
+    /// Job ID of the batch itself; cancelling it stops every file in it
+    pub batch_id: Option<String>,
+    pub channel_mappings: Vec<ChannelMapping>,
+    pub delimited: DelimitedOptions,
+    pub processing: CtdProcessingOptions,
+    /// Store each file's full-resolution rows and return previews, as with
+    /// `handle_ctd_data`; on by default, as a batch's rows would not fit the UI
+    pub persist_outputs: bool,
+    pub preview_rows: usize,
+}
+
+impl Default for CtdBatchOptions {
+    fn default() -> Self {
+        CtdBatchOptions {
+            max_parallel: 4,
+            batch_id: None,
+            channel_mappings: Vec::new(),
+            delimited: DelimitedOptions::default(),
+            processing: CtdProcessingOptions::default(),
+            persist_outputs: true,
+            preview_rows: DEFAULT_PREVIEW_ROWS,
+        }
+    }
+}
+
+impl CtdBatchOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if self.max_parallel == 0 || self.max_parallel > MAX_PARALLEL {
+            return Err(PoleshiftError::InvalidInput {
+                field: "options.max_parallel".to_string(),
+                reason: format!("must be between 1 and {}", MAX_PARALLEL),
+            });
+        }
+        self.processing.validate()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CtdBatchStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// Where one file of the batch stands.
+#[derive(Debug, Clone, Serialize)]
+pub struct CtdBatchFileStatus {
+    /// Position in the submitted list
+    pub index: usize,
+    /// The file's job ID, for `cancel_job` and its progress events
+    pub job_id: String,
+    pub processed_data_id: String,
+    pub file_paths: Vec<String>,
+    pub status: CtdBatchStatus,
+    pub error: Option<String>,
+    pub elapsed_secs: Option<f64>,
+}
+
+/// Batch-level counts, reported whenever a file starts or ends.
+#[derive(Debug, Clone, Copy)]
+struct CtdBatchProgress {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    cancelled: usize,
+    running: usize,
+}
+
+impl CtdBatchProgress {
+    fn percentage(&self) -> u8 {
+        let ended = self.succeeded + self.failed + self.cancelled;
+        (ended * 100 / self.total.max(1)) as u8
+    }
+
+    fn status_message(&self) -> String {
+        format!(
+            "{} of {} files done, {} running, {} failed, {} cancelled",
+            self.succeeded, self.total, self.running, self.failed, self.cancelled
+        )
+    }
+}
+
+#[derive(Serialize)]
+pub struct CtdBatchFileResult {
+    #[serde(flatten)]
+    pub file: CtdBatchFileStatus,
+    /// The file's report, when it succeeded
+    pub report: Option<CTDReport>,
+}
+
+#[derive(Serialize)]
+pub struct CtdBatchReport {
+    pub batch_id: String,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// In submission order
+    pub files: Vec<CtdBatchFileResult>,
+}
+
+/// Shared state of a running batch.
+struct BatchState {
+    batch_id: String,
+    files: Mutex<Vec<CtdBatchFileStatus>>,
+    reports: Mutex<Vec<Option<CTDReport>>>,
+    next: AtomicUsize,
+    finished: AtomicUsize,
+}
+
+impl BatchState {
+    fn update(
+        &self,
+        index: usize,
+        change: impl FnOnce(&mut CtdBatchFileStatus),
+    ) -> CtdBatchProgress {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        change(&mut files[index]);
+        let count = |status| files.iter().filter(|f| f.status == status).count();
+        CtdBatchProgress {
+            total: files.len(),
+            succeeded: count(CtdBatchStatus::Succeeded),
+            failed: count(CtdBatchStatus::Failed),
+            cancelled: count(CtdBatchStatus::Cancelled),
+            running: count(CtdBatchStatus::Running),
+        }
+    }
+}
+
+/// Reports `progress` on the batch's own job. A failed emit is only logged; it must
+/// not fail the files.
+fn report_batch_progress(window: &Window, batch: &JobHandle, progress: CtdBatchProgress) {
+    let reported = batch.report_progress(
+        window,
+        progress.percentage(),
+        &progress.status_message(),
+        "processing",
+    );
+    if let Err(e) = reported {
+        println!("Could not emit CTD batch progress: {}", e);
+    }
+}
+
+/// Everything a worker needs besides the file itself.
+struct BatchContext<'a> {
+    app_handle: &'a AppHandle,
+    window: &'a Window,
+    org_id: &'a str,
+    user_id: &'a str,
+    options: &'a CtdBatchOptions,
+    state: &'a BatchState,
+    files: &'a Mutex<Vec<Option<CtdBatchFile>>>,
+    handles: &'a [JobHandle],
+    batch: &'a JobHandle,
+}
+
+/// Takes files off the batch until none are left, processing each like
+/// `handle_ctd_data`. A failed file is recorded and the worker moves on.
+fn batch_worker(context: &BatchContext) {
+    let state = context.state;
+    loop {
+        let index = state.next.fetch_add(1, Ordering::SeqCst);
+        let Some(job) = context.handles.get(index) else {
+            return;
+        };
+        let file = context.files.lock().unwrap_or_else(|e| e.into_inner())[index].take();
+
+        // 1) Files cancelled while they waited are skipped
+        let progress = match file {
+            Some(file) if !job.is_cancelled() && !context.batch.is_cancelled() => {
+                report_batch_progress(
+                    context.window,
+                    context.batch,
+                    state.update(index, |f| f.status = CtdBatchStatus::Running),
+                );
+
+                // 2) Process the file; it emits its job's own progress
+                let started = Instant::now();
+                let options = context.options;
+                let args = CtdRunArgs {
+                    sample_id: file.sample_id,
+                    org_id: context.org_id.to_string(),
+                    user_id: context.user_id.to_string(),
+                    raw_data_id: file.raw_data_id,
+                    processed_data_id: file.processed_data_id,
+                    file_paths: file.file_paths,
+                    channel_mappings: options.channel_mappings.clone(),
+                    delimited: options.delimited.clone(),
+                    processing: options.processing.clone(),
+                    persist_outputs: options.persist_outputs,
+                    preview_rows: options.preview_rows,
+                    profile_samples: file.profile_samples,
+                };
+                let result = tauri::async_runtime::block_on(process_ctd_data(
+                    context.app_handle.clone(),
+                    args,
+                    job.clone(),
+                ));
+                let elapsed_secs = Some(started.elapsed().as_secs_f64());
+                let status = match &result {
+                    Ok(_) => CtdBatchStatus::Succeeded,
+                    Err(PoleshiftError::Cancelled(_)) => CtdBatchStatus::Cancelled,
+                    Err(_) => CtdBatchStatus::Failed,
+                };
+                let error = result.as_ref().err().map(|e| {
+                    println!("CTD batch file {} failed: {}", job.id(), e);
+                    e.to_string()
+                });
+                context
+                    .app_handle
+                    .state::<JobManager>()
+                    .finish(job.id(), result.as_ref().err());
+                if let Ok(response) = result {
+                    state.reports.lock().unwrap_or_else(|e| e.into_inner())[index] =
+                        Some(response.report);
+                }
+                state.update(index, |f| {
+                    f.status = status;
+                    f.error = error;
+                    f.elapsed_secs = elapsed_secs;
+                })
+            }
+            _ => {
+                let cancelled = PoleshiftError::Cancelled(job.id().to_string());
+                context
+                    .app_handle
+                    .state::<JobManager>()
+                    .finish(job.id(), Some(&cancelled));
+                state.update(index, |f| f.status = CtdBatchStatus::Cancelled)
+            }
+        };
+        state.finished.fetch_add(1, Ordering::SeqCst);
+        report_batch_progress(context.window, context.batch, progress);
+    }
+}
+
+fn run_batch(
+    app_handle: AppHandle,
+    org_id: String,
+    user_id: String,
+    files: Vec<CtdBatchFile>,
+    options: CtdBatchOptions,
+    batch: JobHandle,
+) -> Result<StandardResponseNoFiles<CtdBatchReport>, PoleshiftError> {
+    let window = app_handle
+        .get_window("main")
+        .ok_or(PoleshiftError::WindowNotFound)?;
+    let manager = app_handle.state::<JobManager>();
+
+    // 1) Register every file up front, so pending ones are listed and can be cancelled
+    let handles = manager.start_all("ctd", files.iter().map(|file| file.job_id.clone()))?;
+    let state = BatchState {
+        batch_id: batch.id().to_string(),
+        files: Mutex::new(
+            files
+                .iter()
+                .zip(&handles)
+                .enumerate()
+                .map(|(index, (file, job))| CtdBatchFileStatus {
+                    index,
+                    job_id: job.id().to_string(),
+                    processed_data_id: file.processed_data_id.clone(),
+                    file_paths: file.file_paths.clone(),
+                    status: CtdBatchStatus::Pending,
+                    error: None,
+                    elapsed_secs: None,
+                })
+                .collect(),
+        ),
+        reports: Mutex::new(files.iter().map(|_| None).collect()),
+        next: AtomicUsize::new(0),
+        finished: AtomicUsize::new(0),
+    };
+    let total = files.len();
+    let files = Mutex::new(files.into_iter().map(Some).collect::<Vec<_>>());
+    batch.set_stage("processing");
+    batch.set_reads_total(total as u64);
+
+    // 2) Run the workers; this thread passes a cancelled batch on to its files
+    let context = BatchContext {
+        app_handle: &app_handle,
+        window: &window,
+        org_id: &org_id,
+        user_id: &user_id,
+        options: &options,
+        state: &state,
+        files: &files,
+        handles: &handles,
+        batch: &batch,
+    };
+    let workers = options.max_parallel.min(total);
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| batch_worker(&context));
+        }
+        loop {
+            let finished = state.finished.load(Ordering::SeqCst);
+            batch.set_reads(finished as u64);
+            if finished >= total {
+                break;
+            }
+            if batch.is_cancelled() {
+                for job in &handles {
+                    manager.cancel(job.id());
+                }
+            }
+            std::thread::sleep(CANCEL_POLL_INTERVAL);
+        }
+    });
+
+    // 3) Collect the outcomes in submission order
+    let statuses = state.files.into_inner().unwrap_or_else(|e| e.into_inner());
+    let reports = state
+        .reports
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner());
+    let count = |status| statuses.iter().filter(|f| f.status == status).count();
+    let report = CtdBatchReport {
+        batch_id: state.batch_id,
+        succeeded: count(CtdBatchStatus::Succeeded),
+        failed: count(CtdBatchStatus::Failed),
+        cancelled: count(CtdBatchStatus::Cancelled),
+        files: statuses
+            .into_iter()
+            .zip(reports)
+            .map(|(file, report)| CtdBatchFileResult { file, report })
+            .collect(),
+    };
+    Ok(StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report,
+    })
+}
+
+/// Processes many CTD files, `options.max_parallel` at a time, e.g. every cast of a
+/// cruise at its end. Each file is processed as by `handle_ctd_data`, with the
+/// settings of `options`, and runs as its own job emitting its own progress; the
+/// batch reports on its own job's channel whenever a file starts or ends.
+///
+/// A failed file does not stop the batch: its error is recorded and the next file
+/// starts. `cancel_job` with a file's job ID cancels that file only; with the
+/// `batch_id` it cancels every file still running or waiting. The call returns once
+/// every file has ended, with each file's status and report.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn handle_ctd_batch(
+    invocation: Invocation,
+    app_handle: AppHandle,
+    org_id: String,
+    user_id: String,
+    files: Vec<CtdBatchFile>,
+    options: Option<CtdBatchOptions>,
+) -> Result<StandardResponseNoFiles<CtdBatchReport>, PoleshiftError> {
+    if files.is_empty() {
+        return Err(PoleshiftError::NoFiles);
+    }
+    let options = options.unwrap_or_default();
+    options.validate()?;
+
+    let trace_handle = app_handle.clone();
+    let manager = trace_handle.state::<JobManager>();
+    let batch = manager.start("ctd_batch", options.batch_id.clone())?;
+    let worker_batch = batch.clone();
+    let result = traced(invocation, Some(batch.id()), async move {
+        tauri::async_runtime::spawn_blocking(move || {
+            run_batch(app_handle, org_id, user_id, files, options, worker_batch)
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("CTD batch task failed: {}", e)))?
+    })
+    .await;
+    manager.finish(batch.id(), result.as_ref().err());
+    result
+}
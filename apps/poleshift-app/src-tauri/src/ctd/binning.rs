@@ -0,0 +1,201 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::ctd::flags::QartodFlag;
+use crate::ctd::handle_ctd_data::{ChannelReading, CtdChannel, ProcessedDataRow};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// What processed rows are binned by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinAxis {
+    /// Depth, in the depth channel's unit (usually m)
+    #[default]
+    Depth,
+    /// Sea pressure, or pressure when there is no sea pressure (usually dbar)
+    Pressure,
+}
+
+/// How the rows of a bin are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinStatistic {
+    #[default]
+    Mean,
+    Median,
+}
+
+/// How `handle_ctd_data` bins processed rows, e.g. 0.5 m or 1 dbar bins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BinningOptions {
+    pub axis: BinAxis,
+    /// Bin height, in the unit of `axis`
+    pub size: f64,
+    pub statistic: BinStatistic,
+    /// Bins with fewer rows are left out
+    pub min_samples: usize,
+}
+
+impl Default for BinningOptions {
+    fn default() -> Self {
+        BinningOptions {
+            axis: BinAxis::Depth,
+            size: 1.0,
+            statistic: BinStatistic::Mean,
+            min_samples: 1,
+        }
+    }
+}
+
+impl BinningOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if !(self.size.is_finite() && self.size > 0.0) {
+            return Err(PoleshiftError::InvalidInput {
+                field: "binning.size".to_string(),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+        if self.min_samples == 0 {
+            return Err(PoleshiftError::InvalidInput {
+                field: "binning.min_samples".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The mean or median of `values`, if there are any.
+fn summarize(values: impl Iterator<Item = f64>, statistic: BinStatistic) -> Option<f64> {
+    let mut values: Vec<f64> = values.filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    match statistic {
+        BinStatistic::Mean => Some(values.iter().sum::<f64>() / values.len() as f64),
+        BinStatistic::Median => {
+            values.sort_by(f64::total_cmp);
+            let middle = values.len() / 2;
+            if values.len().is_multiple_of(2) {
+                Some((values[middle - 1] + values[middle]) / 2.0)
+            } else {
+                Some(values[middle])
+            }
+        }
+    }
+}
+
+/// Combines the rows of one bin into a row at the bin's centre.
+fn bin_row(
+    members: &[&ProcessedDataRow],
+    center: f64,
+    options: &BinningOptions,
+) -> ProcessedDataRow {
+    let first = members[0];
+    // Values flagged as failed are left out
+    let stat = |channel: CtdChannel| {
+        let values = members
+            .iter()
+            .filter(|row| {
+                row.flags
+                    .get(&channel)
+                    .is_none_or(|flag| flag.flag != QartodFlag::Fail)
+            })
+            .filter_map(|row| row.value(channel));
+        summarize(values, options.statistic)
+    };
+    let tstamp = summarize(
+        members
+            .iter()
+            .filter_map(|row| row.tstamp.map(|t| t as f64)),
+        BinStatistic::Mean,
+    )
+    .map(|t| t.round() as i64);
+
+    // Extra channels, by every name any row of the bin has
+    let names: BTreeSet<&String> = members.iter().flat_map(|row| row.channels.keys()).collect();
+    let channels = names
+        .into_iter()
+        .map(|name| {
+            let readings = members.iter().filter_map(|row| row.channels.get(name));
+            let reading = ChannelReading {
+                value: summarize(readings.clone().filter_map(|r| r.value), options.statistic),
+                unit: readings.map(|r| r.unit.clone()).next().unwrap_or_default(),
+            };
+            (name.clone(), reading)
+        })
+        .collect();
+
+    let uncalibrated = first
+        .uncalibrated
+        .keys()
+        .filter_map(|&channel| {
+            let values = members
+                .iter()
+                .filter_map(|row| row.uncalibrated.get(&channel).copied());
+            Some((channel, summarize(values, options.statistic)?))
+        })
+        .collect();
+
+    let mut row = ProcessedDataRow {
+        tstamp,
+        channels,
+        flags: BTreeMap::new(),
+        uncalibrated,
+        id: Uuid::new_v4(),
+        ..first.clone()
+    };
+    for channel in CtdChannel::ALL {
+        *row.value_mut(channel) = stat(channel);
+    }
+    match options.axis {
+        BinAxis::Depth => row.depth = Some(center),
+        BinAxis::Pressure if first.sea_pressure.is_some() => row.sea_pressure = Some(center),
+        BinAxis::Pressure => row.pressure = Some(center),
+    }
+    row
+}
+
+/// Bins processed rows by depth or pressure, separately for every cast (so each
+/// downcast of a yo-yo cast gets its own profile). Bin `k` holds values from `k * size`
+/// up to `(k + 1) * size` and is reported at its centre; every other value is the
+/// mean or median of the bin's rows, leaving out values flagged as failed, and the
+/// timestamp their mean. Profiles and their bins keep the order the rows were recorded
+/// in. Rows without the binned value are left out, and binned rows carry no flags.
+pub(crate) fn bin_rows(
+    rows: &[ProcessedDataRow],
+    options: &BinningOptions,
+) -> Vec<ProcessedDataRow> {
+    let axis_value = |row: &ProcessedDataRow| match options.axis {
+        BinAxis::Depth => row.depth,
+        BinAxis::Pressure => row.sea_pressure.or(row.pressure),
+    };
+
+    // 1) Group rows by cast and bin, in order of their first row
+    let mut groups: Vec<(i64, Vec<&ProcessedDataRow>)> = Vec::new();
+    let mut group_of: HashMap<(Option<usize>, i64), usize> = HashMap::new();
+    for row in rows {
+        let Some(value) = axis_value(row).filter(|v| v.is_finite()) else {
+            continue;
+        };
+        let bin = (value / options.size).floor() as i64;
+        let index = *group_of.entry((row.cast_index, bin)).or_insert_with(|| {
+            groups.push((bin, Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(row);
+    }
+
+    // 2) One row per bin with enough rows
+    groups
+        .into_iter()
+        .filter(|(_, members)| members.len() >= options.min_samples)
+        .map(|(bin, members)| {
+            let center = (bin as f64 + 0.5) * options.size;
+            bin_row(&members, center, options)
+        })
+        .collect()
+}
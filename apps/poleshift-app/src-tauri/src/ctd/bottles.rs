@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::delimited::{
+    is_time_header, parse_time, pick_delimiter, split_fields, split_header, DelimitedOptions,
+};
+use crate::ctd::export::{present_variables, EXPORT_VARIABLES};
+use crate::ctd::flags::QartodFlag;
+use crate::ctd::format_iso8601;
+use crate::ctd::handle_ctd_data::ProcessedDataRow;
+use crate::ctd::rsk_metadata::{integer, select_columns};
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Header names taken for the bottle column of an event log.
+const BOTTLE_COLUMN_NAMES: &[&str] = &["bottle", "bottle number", "bottle_number", "btl", "niskin"];
+
+/// One bottle closure: its bottle and when or where it closed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BottleEvent {
+    pub bottle: String,
+    /// Closure time, in ms since the Unix epoch
+    #[serde(default)]
+    pub tstamp: Option<i64>,
+    /// Closure depth, in m, for logs without times
+    #[serde(default)]
+    pub depth: Option<f64>,
+    /// Sample the bottle's water went to, for joining with lab and sequencing data
+    #[serde(default)]
+    pub sample_id: Option<String>,
+}
+
+/// Where bottle closures are read from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum BottleEventSource {
+    /// Events of the given types in the `events` table of an RSK file, numbered as
+    /// bottles 1, 2, … in time order
+    Rsk {
+        file_path: String,
+        event_types: Vec<i64>,
+    },
+    /// A CSV/TSV log with a bottle column (`bottle`, `niskin`, …), and a time column,
+    /// a `depth` column or both; an optional `sample_id` column is kept
+    Csv { file_path: String },
+    /// Events as given
+    Events { events: Vec<BottleEvent> },
+}
+
+/// How CTD values are taken at each closure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BottleOptions {
+    /// Rows within this many seconds of a timed closure are averaged
+    pub window_secs: f64,
+    /// Rows within this many metres of an untimed closure's depth are averaged
+    pub depth_window: f64,
+    /// Sample of each bottle, by bottle, over any given in the log
+    pub bottle_samples: BTreeMap<String, String>,
+}
+
+impl Default for BottleOptions {
+    fn default() -> Self {
+        BottleOptions {
+            window_secs: 5.0,
+            depth_window: 1.0,
+            bottle_samples: BTreeMap::new(),
+        }
+    }
+}
+
+impl BottleOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        for (field, value) in [
+            ("window_secs", self.window_secs),
+            ("depth_window", self.depth_window),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(PoleshiftError::InvalidInput {
+                    field: format!("options.{}", field),
+                    reason: "must be 0 or more".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The CTD values at one bottle closure.
+#[derive(Debug, Clone, Serialize)]
+pub struct BottleSample {
+    pub bottle: String,
+    pub sample_id: Option<String>,
+    /// Closure time, in ms since the Unix epoch, and in ISO 8601 UTC
+    pub tstamp: Option<i64>,
+    pub time_utc: Option<String>,
+    /// Mean depth of the averaged rows, or the logged depth
+    pub depth: Option<f64>,
+    /// Rows averaged
+    pub scans: usize,
+    /// Mean of each variable over those rows, by export name; failed values are
+    /// left out
+    pub values: BTreeMap<String, f64>,
+    /// Standard deviation of each variable over those rows
+    pub std_devs: BTreeMap<String, f64>,
+}
+
+/// The bottle summary of a cast.
+#[derive(Debug, Clone, Serialize)]
+pub struct BottleSummary {
+    pub bottles: Vec<BottleSample>,
+    /// Unit of each variable, by export name
+    pub units: BTreeMap<String, String>,
+}
+
+/// Reads the closures of `types` from the `events` table of an RSK file.
+fn read_rsk_events(file_path: &str, types: &[i64]) -> Result<Vec<BottleEvent>, PoleshiftError> {
+    let conn = Connection::open(file_path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+    let mut tstamps: Vec<i64> = select_columns(&conn, "events", &["type", "tstamp"])?
+        .iter()
+        .filter(|row| integer(&row[0]).is_some_and(|kind| types.contains(&kind)))
+        .filter_map(|row| integer(&row[1]))
+        .collect();
+    tstamps.sort_unstable();
+    Ok(tstamps
+        .into_iter()
+        .enumerate()
+        .map(|(i, tstamp)| BottleEvent {
+            bottle: (i + 1).to_string(),
+            tstamp: Some(tstamp),
+            depth: None,
+            sample_id: None,
+        })
+        .collect())
+}
+
+/// Reads a delimited bottle log (see `BottleEventSource::Csv`).
+fn read_bottle_log(path: &Path) -> Result<Vec<BottleEvent>, PoleshiftError> {
+    let data_error = |reason: &str| {
+        PoleshiftError::DataError(format!("Could not read {}: {}", path.display(), reason))
+    };
+    let mut lines = BufReader::new(File::open(path)?).lines().filter(|line| {
+        line.as_ref()
+            .map_or(true, |l| !l.starts_with('#') && !l.trim().is_empty())
+    });
+    let header = lines.next().ok_or_else(|| data_error("no header line"))??;
+    let header = header.trim_start_matches('\u{feff}');
+    let delimiter = pick_delimiter(path, header, &DelimitedOptions::default());
+    let headers: Vec<String> = split_fields(header, delimiter)
+        .iter()
+        .map(|h| split_header(h).0.to_lowercase())
+        .collect();
+    let column = |names: &[&str]| headers.iter().position(|h| names.contains(&h.as_str()));
+
+    let bottle_index = column(BOTTLE_COLUMN_NAMES).ok_or_else(|| data_error("no bottle column"))?;
+    let time_index = headers.iter().position(|h| is_time_header(h));
+    let depth_index = column(&["depth"]);
+    let sample_index = column(&["sample_id", "sample id", "sample"]);
+    if time_index.is_none() && depth_index.is_none() {
+        return Err(data_error("no time or depth column"));
+    }
+
+    let mut events = Vec::new();
+    for line in lines {
+        let fields = split_fields(&line?, delimiter);
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| fields.get(i))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+        let Some(bottle) = field(Some(bottle_index)) else {
+            continue;
+        };
+        events.push(BottleEvent {
+            bottle: bottle.to_string(),
+            tstamp: field(time_index).and_then(|t| parse_time(t, None, None)),
+            depth: field(depth_index).and_then(|d| d.parse().ok()),
+            sample_id: field(sample_index).map(str::to_string),
+        });
+    }
+    Ok(events)
+}
+
+/// Mean and standard deviation of `values`.
+fn mean_std(values: &[f64]) -> Option<(f64, f64)> {
+    if values.is_empty() {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    Some((mean, variance.sqrt()))
+}
+
+/// Averages the rows around each closure: those within `window_secs` of its time,
+/// or for untimed closures within `depth_window` of its depth.
+pub(crate) fn match_bottles(
+    rows: &[ProcessedDataRow],
+    events: &[BottleEvent],
+    options: &BottleOptions,
+) -> BottleSummary {
+    let window_ms = (options.window_secs * 1000.0).round() as i64;
+    let bottles = events
+        .iter()
+        .map(|event| {
+            let matched: Vec<&ProcessedDataRow> = rows
+                .iter()
+                .filter(|row| match (event.tstamp, event.depth) {
+                    (Some(tstamp), _) => {
+                        row.tstamp.is_some_and(|t| (t - tstamp).abs() <= window_ms)
+                    }
+                    (None, Some(depth)) => row
+                        .depth
+                        .is_some_and(|d| (d - depth).abs() <= options.depth_window),
+                    (None, None) => false,
+                })
+                .collect();
+
+            let mut values = BTreeMap::new();
+            let mut std_devs = BTreeMap::new();
+            for variable in &EXPORT_VARIABLES {
+                let series: Vec<f64> = matched
+                    .iter()
+                    .filter(|row| {
+                        variable
+                            .channel
+                            .and_then(|channel| row.flags.get(&channel))
+                            .is_none_or(|flag| flag.flag != QartodFlag::Fail)
+                    })
+                    .filter_map(|row| (variable.value)(row))
+                    .collect();
+                if let Some((mean, std)) = mean_std(&series) {
+                    values.insert(variable.name.to_string(), mean);
+                    std_devs.insert(variable.name.to_string(), std);
+                }
+            }
+            BottleSample {
+                bottle: event.bottle.clone(),
+                sample_id: options
+                    .bottle_samples
+                    .get(&event.bottle)
+                    .cloned()
+                    .or_else(|| event.sample_id.clone()),
+                tstamp: event.tstamp,
+                time_utc: event.tstamp.map(|t| format_iso8601(t, 0)),
+                depth: values.get("depth").copied().or(event.depth),
+                scans: matched.len(),
+                values,
+                std_devs,
+            }
+        })
+        .collect();
+
+    let all: Vec<&ProcessedDataRow> = rows.iter().collect();
+    let units = present_variables(&all)
+        .into_iter()
+        .map(|variable| (variable.name.to_string(), variable.logged_units(&all)))
+        .collect();
+    BottleSummary { bottles, units }
+}
+
+/// Takes the CTD values at each bottle closure of a cast, from an RSK events table,
+/// a bottle log or events given by hand, into a bottle summary that joins with
+/// nutrient and sequencing samples by `sample_id`. `rows` are the cast's processed
+/// rows (see `match_bottles`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn extract_ctd_bottles(
+    invocation: Invocation,
+    rows: Vec<ProcessedDataRow>,
+    events: BottleEventSource,
+    options: Option<BottleOptions>,
+) -> Result<StandardResponseNoFiles<BottleSummary>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let options = options.unwrap_or_default();
+        options.validate()?;
+        let events = match events {
+            BottleEventSource::Rsk {
+                file_path,
+                event_types,
+            } => read_rsk_events(&file_path, &event_types)?,
+            BottleEventSource::Csv { file_path } => read_bottle_log(Path::new(&file_path))?,
+            BottleEventSource::Events { events } => events,
+        };
+        if events.is_empty() {
+            return Err(PoleshiftError::DataError(
+                "No bottle closures found".to_string(),
+            ));
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: match_bottles(&rows, &events, &options),
+        })
+    })
+    .await
+}
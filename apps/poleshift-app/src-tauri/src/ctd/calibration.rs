@@ -0,0 +1,134 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::handle_ctd_data::{CtdChannel, ProcessedDataRow};
+use crate::ctd::teos10::{derive_practical_salinity, derive_teos10};
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// A new calibration of one channel, e.g. chlorophyll fluorescence calibrated after
+/// the cruise against extracted samples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelCalibration {
+    pub channel: CtdChannel,
+    /// Polynomial coefficients from the constant term up: the recalibrated value is
+    /// c0 + c1·x + c2·x² + … of the value from the instrument's calibration
+    pub coefficients: Vec<f64>,
+    /// Unit of the recalibrated values, when it differs
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Where the coefficients come from
+    #[serde(default)]
+    pub reference: Option<String>,
+}
+
+impl ChannelCalibration {
+    fn apply(&self, x: f64) -> f64 {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0.0, |value, coefficient| value * x + coefficient)
+    }
+}
+
+pub fn validate_calibrations(calibrations: &[ChannelCalibration]) -> Result<(), PoleshiftError> {
+    let mut channels = BTreeSet::new();
+    for calibration in calibrations {
+        let invalid = |reason: &str| PoleshiftError::InvalidInput {
+            field: format!("calibrations.{:?}", calibration.channel),
+            reason: reason.to_string(),
+        };
+        if calibration.coefficients.is_empty()
+            || calibration.coefficients.iter().any(|c| !c.is_finite())
+        {
+            return Err(invalid("needs finite coefficients"));
+        }
+        if !channels.insert(calibration.channel) {
+            return Err(invalid("is calibrated twice"));
+        }
+    }
+    Ok(())
+}
+
+/// Applies `calibrations` to `rows`. The instrument's value of each recalibrated
+/// channel is kept in the row's `uncalibrated`, and a channel recalibrated before is
+/// recalibrated from that value, so new coefficients replace old ones rather than
+/// stacking. Returns the values changed.
+pub(crate) fn recalibrate(
+    rows: &mut [ProcessedDataRow],
+    calibrations: &[ChannelCalibration],
+) -> usize {
+    let mut modified = 0;
+    for row in rows.iter_mut() {
+        for calibration in calibrations {
+            let channel = calibration.channel;
+            let original = row
+                .uncalibrated
+                .get(&channel)
+                .copied()
+                .or(row.value(channel));
+            let Some(original) = original else {
+                continue;
+            };
+            row.uncalibrated.insert(channel, original);
+            *row.value_mut(channel) = Some(calibration.apply(original));
+            if let Some(unit) = &calibration.unit {
+                *row.unit_mut(channel) = unit.clone();
+            }
+            modified += 1;
+        }
+    }
+    modified
+}
+
+/// Rows recalibrated by `recalibrate_ctd_rows`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecalibrationReport {
+    pub rows: Vec<ProcessedDataRow>,
+    /// Values recalibrated
+    pub modified_points: usize,
+}
+
+/// Recalibrates already processed (or binned) rows with new coefficients, without
+/// reading the instrument file again (see `recalibrate`). When temperature,
+/// salinity, conductivity or pressure change, derived salinity and the TEOS-10
+/// columns are derived again, with `latitude` for gravity; QARTOD flags are kept as
+/// they were.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn recalibrate_ctd_rows(
+    invocation: Invocation,
+    rows: Vec<ProcessedDataRow>,
+    calibrations: Vec<ChannelCalibration>,
+    latitude: Option<f64>,
+) -> Result<StandardResponseNoFiles<RecalibrationReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        validate_calibrations(&calibrations)?;
+        let mut rows = rows;
+        let modified_points = recalibrate(&mut rows, &calibrations);
+        let state_changed = calibrations.iter().any(|calibration| {
+            matches!(
+                calibration.channel,
+                CtdChannel::Temperature
+                    | CtdChannel::Salinity
+                    | CtdChannel::Pressure
+                    | CtdChannel::SeaPressure
+                    | CtdChannel::Depth
+                    | CtdChannel::SpecificConductivity
+            )
+        });
+        if state_changed {
+            derive_practical_salinity(&mut rows, latitude);
+            derive_teos10(&mut rows, latitude);
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: RecalibrationReport {
+                rows,
+                modified_points,
+            },
+        })
+    })
+    .await
+}
@@ -0,0 +1,357 @@
+use serde::{Deserialize, Serialize};
+
+use crate::poleshift_common::types::PoleshiftError;
+
+/// What the instrument was doing during part of a cast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CastPhase {
+    /// Sitting near the surface before the cast, including a soak at depth
+    Soak,
+    Downcast,
+    Upcast,
+    /// Holding depth during the cast, e.g. at the bottom or between yo-yo profiles
+    Stationary,
+}
+
+/// Which cast phases become processed rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CastSelection {
+    #[default]
+    Downcast,
+    Upcast,
+    Both,
+}
+
+impl CastSelection {
+    pub fn keeps(self, phase: CastPhase) -> bool {
+        matches!(
+            (self, phase),
+            (
+                CastSelection::Downcast | CastSelection::Both,
+                CastPhase::Downcast
+            ) | (
+                CastSelection::Upcast | CastSelection::Both,
+                CastPhase::Upcast
+            )
+        )
+    }
+}
+
+/// How casts are split into phases.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CastOptions {
+    pub keep: CastSelection,
+    /// Depth rate in m/s above which the instrument counts as moving
+    pub min_speed: f64,
+    /// Width in seconds of the moving average applied to depth before its rate
+    pub smoothing_secs: f64,
+    /// Depth a descent or ascent must cover to count as a cast, in m
+    pub min_cast_depth: f64,
+    /// Deepest a soak is held, in m
+    pub max_soak_depth: f64,
+    /// Shortest hold at `max_soak_depth` or above that counts as a soak, in seconds
+    pub min_soak_secs: f64,
+    /// Samples shallower than this are out of the water and never kept, in m
+    pub min_depth: f64,
+    /// Downcasts starting shallower than this begin a new profile, in m
+    pub surface_depth: f64,
+}
+
+impl Default for CastOptions {
+    fn default() -> Self {
+        CastOptions {
+            keep: CastSelection::Downcast,
+            min_speed: 0.1,
+            smoothing_secs: 2.0,
+            min_cast_depth: 1.0,
+            max_soak_depth: 15.0,
+            min_soak_secs: 20.0,
+            min_depth: 0.1,
+            surface_depth: 5.0,
+        }
+    }
+}
+
+impl CastOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let positive = [
+            ("min_speed", self.min_speed),
+            ("smoothing_secs", self.smoothing_secs),
+            ("min_cast_depth", self.min_cast_depth),
+        ];
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(PoleshiftError::InvalidInput {
+                    field: format!("cast.{}", field),
+                    reason: "must be greater than 0".to_string(),
+                });
+            }
+        }
+        let non_negative = [
+            ("max_soak_depth", self.max_soak_depth),
+            ("min_soak_secs", self.min_soak_secs),
+            ("surface_depth", self.surface_depth),
+        ];
+        for (field, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(PoleshiftError::InvalidInput {
+                    field: format!("cast.{}", field),
+                    reason: "must not be negative".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A stretch of the cast in one phase, as reported in `CTDReport`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CastSegment {
+    pub phase: CastPhase,
+    pub start_tstamp: i64,
+    pub end_tstamp: i64,
+    pub start_depth: f64,
+    pub end_depth: f64,
+    pub samples: usize,
+}
+
+/// Where the profiles of a file were found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileSource {
+    /// The profile regions of an RSK file
+    Region,
+    /// The cast segments (see `profile_ranges`)
+    Depth,
+}
+
+/// One profile of a file that may hold several, e.g. a day of casts at different
+/// stations, as reported in `CTDReport`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CastProfile {
+    pub start_tstamp: i64,
+    pub end_tstamp: i64,
+    /// Deepest sample of the profile
+    pub max_depth: Option<f64>,
+    pub samples: usize,
+    pub source: ProfileSource,
+    /// Sample the profile's rows belong to
+    pub sample_id: String,
+}
+
+/// Assigns the rows of one profile, by its index in `CTDReport::profiles`, to a sample.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileSample {
+    pub profile: usize,
+    pub sample_id: String,
+}
+
+/// Time ranges of the profiles in `segments`: each downcast starting shallower than
+/// `surface_depth` begins one, and it takes in every segment up to the next. Downcasts
+/// starting deeper, as in a yo-yo cast, stay in the current profile; the soak belongs
+/// to none.
+pub(crate) fn profile_ranges(segments: &[CastSegment], surface_depth: f64) -> Vec<(i64, i64)> {
+    let mut ranges: Vec<(i64, i64)> = Vec::new();
+    for segment in segments {
+        match segment.phase {
+            CastPhase::Soak => {}
+            CastPhase::Downcast if ranges.is_empty() || segment.start_depth <= surface_depth => {
+                ranges.push((segment.start_tstamp, segment.end_tstamp))
+            }
+            _ => {
+                if let Some(range) = ranges.last_mut() {
+                    range.1 = segment.end_tstamp;
+                }
+            }
+        }
+    }
+    ranges
+}
+
+/// Index of the range of `ranges` (in time order) holding `tstamp`.
+pub(crate) fn profile_at(ranges: &[(i64, i64)], tstamp: i64) -> Option<usize> {
+    let after = ranges.partition_point(|(start, _)| *start <= tstamp);
+    let index = after.checked_sub(1)?;
+    (tstamp <= ranges[index].1).then_some(index)
+}
+
+/// Direction of travel of one sample, before phases are assigned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Down,
+    Up,
+    Still,
+}
+
+/// A run of samples `start..=end` with the same motion.
+struct Run {
+    motion: Motion,
+    start: usize,
+    end: usize,
+}
+
+/// Depth averaged over `window_ms` around each sample.
+fn smooth(tstamps: &[i64], depths: &[f64], window_ms: i64) -> Vec<f64> {
+    let half = window_ms / 2;
+    let (mut from, mut to, mut sum) = (0, 0, 0.0);
+    let mut smoothed = Vec::with_capacity(depths.len());
+    for &t in tstamps {
+        while to < tstamps.len() && tstamps[to] <= t + half {
+            sum += depths[to];
+            to += 1;
+        }
+        while tstamps[from] < t - half {
+            sum -= depths[from];
+            from += 1;
+        }
+        smoothed.push(sum / (to - from) as f64);
+    }
+    smoothed
+}
+
+/// Runs of equal motion, merged until neighbours differ.
+fn merge(runs: Vec<Run>) -> Vec<Run> {
+    let mut merged: Vec<Run> = Vec::with_capacity(runs.len());
+    for run in runs {
+        match merged.last_mut() {
+            Some(last) if last.motion == run.motion => last.end = run.end,
+            _ => merged.push(run),
+        }
+    }
+    merged
+}
+
+/// Splits a cast, given as (timestamp in ms, depth in m) samples in time order, into
+/// phases. Returns the segments and, for every sample, the index of its segment.
+///
+/// Depth is smoothed and differentiated; samples moving faster than `min_speed` are
+/// descending or ascending and the rest are still. Descents and ascents covering less
+/// than `min_cast_depth` count as still, and pauses within a descent (or ascent)
+/// belong to it. Stillness before the first descent is the soak, and so is a first
+/// descent to no deeper than `max_soak_depth` held for `min_soak_secs`, with the
+/// ascent back that may follow. Every remaining descent is a downcast and every
+/// ascent an upcast, so yo-yo casts give several of each.
+pub(crate) fn segment_cast(
+    samples: &[(i64, f64)],
+    options: &CastOptions,
+) -> (Vec<CastSegment>, Vec<usize>) {
+    if samples.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let tstamps: Vec<i64> = samples.iter().map(|(t, _)| *t).collect();
+    let depths: Vec<f64> = samples.iter().map(|(_, d)| *d).collect();
+    let smoothed = smooth(&tstamps, &depths, (options.smoothing_secs * 1000.0) as i64);
+
+    // 1) Motion of each sample from the smoothed depth rate
+    let last = samples.len() - 1;
+    let motions = (0..samples.len()).map(|i| {
+        let (before, after) = (i.saturating_sub(1), (i + 1).min(last));
+        let elapsed_secs = (tstamps[after] - tstamps[before]) as f64 / 1000.0;
+        let rate = if elapsed_secs > 0.0 {
+            (smoothed[after] - smoothed[before]) / elapsed_secs
+        } else {
+            0.0
+        };
+        if rate >= options.min_speed {
+            Motion::Down
+        } else if rate <= -options.min_speed {
+            Motion::Up
+        } else {
+            Motion::Still
+        }
+    });
+    let runs = merge(
+        motions
+            .enumerate()
+            .map(|(i, motion)| Run {
+                motion,
+                start: i,
+                end: i,
+            })
+            .collect(),
+    );
+
+    // 2) Short excursions inside a descent (or ascent), like heave, belong to it; other
+    // short excursions are still, and so are pauses between them
+    let covers = |run: &Run| (smoothed[run.end] - smoothed[run.start]).abs();
+    let absorb = |mut runs: Vec<Run>, short_only: bool| {
+        for i in 1..runs.len().saturating_sub(1) {
+            let (before, after) = (runs[i - 1].motion, runs[i + 1].motion);
+            if before == after
+                && before != Motion::Still
+                && (!short_only || covers(&runs[i]) < options.min_cast_depth)
+            {
+                runs[i].motion = before;
+            }
+        }
+        merge(runs)
+    };
+    let mut runs = absorb(runs, true);
+    for run in &mut runs {
+        if covers(run) < options.min_cast_depth {
+            run.motion = Motion::Still;
+        }
+    }
+    let runs = absorb(merge(runs), false);
+
+    // 3) Phases: the soak first, then casts and holds
+    let mut phases: Vec<CastPhase> = runs
+        .iter()
+        .map(|run| match run.motion {
+            Motion::Down => CastPhase::Downcast,
+            Motion::Up => CastPhase::Upcast,
+            Motion::Still => CastPhase::Stationary,
+        })
+        .collect();
+    let mut next = 0;
+    while next < runs.len() && runs[next].motion == Motion::Still {
+        phases[next] = CastPhase::Soak;
+        next += 1;
+    }
+    if let (Some(descent), Some(hold)) = (runs.get(next), runs.get(next + 1)) {
+        let held_secs = (tstamps[hold.end] - tstamps[hold.start]) as f64 / 1000.0;
+        if descent.motion == Motion::Down
+            && hold.motion == Motion::Still
+            && smoothed[hold.start] <= options.max_soak_depth
+            && held_secs >= options.min_soak_secs
+        {
+            phases[next] = CastPhase::Soak;
+            phases[next + 1] = CastPhase::Soak;
+            next += 2;
+            if runs.get(next).is_some_and(|run| run.motion == Motion::Up) {
+                phases[next] = CastPhase::Soak;
+                next += 1;
+            }
+            while next < runs.len() && runs[next].motion == Motion::Still {
+                phases[next] = CastPhase::Soak;
+                next += 1;
+            }
+        }
+    }
+
+    // 4) Segments, merging neighbours that ended up in the same phase
+    let mut segments: Vec<CastSegment> = Vec::new();
+    let mut sample_segments = vec![0; samples.len()];
+    for (run, phase) in runs.iter().zip(phases) {
+        match segments.last_mut() {
+            Some(segment) if segment.phase == phase => {
+                segment.end_tstamp = tstamps[run.end];
+                segment.end_depth = depths[run.end];
+                segment.samples += run.end - run.start + 1;
+            }
+            _ => segments.push(CastSegment {
+                phase,
+                start_tstamp: tstamps[run.start],
+                end_tstamp: tstamps[run.end],
+                start_depth: depths[run.start],
+                end_depth: depths[run.end],
+                samples: run.end - run.start + 1,
+            }),
+        }
+        sample_segments[run.start..=run.end].fill(segments.len() - 1);
+    }
+    (segments, sample_segments)
+}
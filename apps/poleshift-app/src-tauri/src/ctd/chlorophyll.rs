@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::handle_ctd_data::{CtdChannel, ProcessedDataRow};
+use crate::ctd::qc::{QcFilter, QcFilterReport};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// How the quenched layer of a daytime cast is found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum NpqCorrection {
+    /// The layer down to the deepest sample where PAR reaches `par_threshold`
+    /// (µmol photons m⁻² s⁻¹); casts whose PAR never does are left as they are
+    Par { par_threshold: f64 },
+    /// The top `quenching_depth` metres of casts started between `day_start_hour` and
+    /// `day_end_hour` local time, for instruments without a PAR sensor
+    TimeOfDay {
+        day_start_hour: f64,
+        day_end_hour: f64,
+        quenching_depth: f64,
+    },
+}
+
+/// Corrections of chlorophyll fluorescence, applied to each kept cast: the dark count
+/// first, then non-photochemical quenching. None run by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChlorophyllOptions {
+    /// Reading of the fluorometer in the dark, in the channel's unit, subtracted from
+    /// every value
+    pub dark_count: Option<f64>,
+    pub npq: Option<NpqCorrection>,
+}
+
+impl ChlorophyllOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let invalid = |field: &str, reason: &str| PoleshiftError::InvalidInput {
+            field: format!("chlorophyll.{}", field),
+            reason: reason.to_string(),
+        };
+        if self.dark_count.is_some_and(|dark| !dark.is_finite()) {
+            return Err(invalid("dark_count", "must be a number"));
+        }
+        match &self.npq {
+            Some(NpqCorrection::Par { par_threshold })
+                if !(par_threshold.is_finite() && *par_threshold > 0.0) =>
+            {
+                return Err(invalid("npq.par_threshold", "must be greater than 0"));
+            }
+            Some(NpqCorrection::TimeOfDay {
+                day_start_hour,
+                day_end_hour,
+                quenching_depth,
+            }) => {
+                for (field, hour) in [
+                    ("npq.day_start_hour", day_start_hour),
+                    ("npq.day_end_hour", day_end_hour),
+                ] {
+                    if !(0.0..=24.0).contains(hour) {
+                        return Err(invalid(field, "must be between 0 and 24"));
+                    }
+                }
+                if !(quenching_depth.is_finite() && *quenching_depth > 0.0) {
+                    return Err(invalid("npq.quenching_depth", "must be greater than 0"));
+                }
+            }
+            Some(NpqCorrection::Par { .. }) | None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Subtracts the dark count from every chlorophyll value; returns the values changed.
+fn subtract_dark_count(rows: &mut [ProcessedDataRow], dark_count: f64) -> usize {
+    let mut modified = 0;
+    for row in rows.iter_mut() {
+        if let Some(chlorophyll) = row.chlorophyll_a.as_mut() {
+            *chlorophyll -= dark_count;
+            modified += 1;
+        }
+    }
+    modified
+}
+
+/// Hour of the day of a timestamp at `utc_offset_minutes` from UTC.
+fn local_hour(tstamp: i64, utc_offset_minutes: i32) -> f64 {
+    let local_ms = tstamp + i64::from(utc_offset_minutes) * 60_000;
+    local_ms.rem_euclid(86_400_000) as f64 / 3_600_000.0
+}
+
+/// Bottom of the quenched layer of one cast, if it was quenched.
+fn quenching_depth(
+    cast: &[usize],
+    rows: &[ProcessedDataRow],
+    npq: &NpqCorrection,
+    utc_offset_minutes: i32,
+) -> Option<f64> {
+    match npq {
+        NpqCorrection::Par { par_threshold } => cast
+            .iter()
+            .map(|&i| &rows[i])
+            .filter(|row| row.par.is_some_and(|par| par >= *par_threshold))
+            .filter_map(|row| row.depth)
+            .reduce(f64::max),
+        NpqCorrection::TimeOfDay {
+            day_start_hour,
+            day_end_hour,
+            quenching_depth,
+        } => {
+            let start = cast.iter().find_map(|&i| rows[i].tstamp)?;
+            let hour = local_hour(start, utc_offset_minutes);
+            let daytime = if day_start_hour <= day_end_hour {
+                (*day_start_hour..*day_end_hour).contains(&hour)
+            } else {
+                hour >= *day_start_hour || hour < *day_end_hour
+            };
+            daytime.then_some(*quenching_depth)
+        }
+    }
+}
+
+/// Corrects quenching in each cast after Xing et al. (2012): within the quenched
+/// layer, values above the depth of its chlorophyll maximum are raised to that
+/// maximum. Returns the values changed.
+fn correct_npq(
+    rows: &mut [ProcessedDataRow],
+    npq: &NpqCorrection,
+    utc_offset_minutes: i32,
+) -> usize {
+    let mut casts: BTreeMap<Option<usize>, Vec<usize>> = BTreeMap::new();
+    for (i, row) in rows.iter().enumerate() {
+        casts.entry(row.cast_index).or_default().push(i);
+    }
+
+    let mut modified = 0;
+    for cast in casts.values() {
+        let Some(bottom) = quenching_depth(cast, rows, npq, utc_offset_minutes) else {
+            continue;
+        };
+        let maximum = cast
+            .iter()
+            .filter_map(|&i| Some((rows[i].depth?, rows[i].chlorophyll_a?)))
+            .filter(|(depth, _)| *depth <= bottom)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((max_depth, max_chlorophyll)) = maximum else {
+            continue;
+        };
+        for &i in cast {
+            let row = &mut rows[i];
+            if row.depth.is_some_and(|depth| depth < max_depth)
+                && row
+                    .chlorophyll_a
+                    .is_some_and(|chlorophyll| chlorophyll < max_chlorophyll)
+            {
+                row.chlorophyll_a = Some(max_chlorophyll);
+                modified += 1;
+            }
+        }
+    }
+    modified
+}
+
+/// Runs the chlorophyll corrections of `options` over the processed rows of the kept
+/// casts, reporting them like the QC filters. `utc_offset_minutes` places casts in the
+/// local day for `NpqCorrection::TimeOfDay`.
+pub(crate) fn correct_chlorophyll(
+    rows: &mut [ProcessedDataRow],
+    options: &ChlorophyllOptions,
+    utc_offset_minutes: Option<i32>,
+) -> Result<Vec<QcFilterReport>, PoleshiftError> {
+    let parameters = |value: serde_json::Result<serde_json::Value>| {
+        value.map_err(|e| PoleshiftError::Other(format!("Chlorophyll parameters: {}", e)))
+    };
+    let mut reports = Vec::new();
+    if let Some(dark_count) = options.dark_count {
+        reports.push(QcFilterReport {
+            filter: QcFilter::DarkCount,
+            channel: CtdChannel::ChlorophyllA,
+            parameters: parameters(serde_json::to_value(dark_count))?,
+            modified_points: subtract_dark_count(rows, dark_count),
+        });
+    }
+    if let Some(npq) = &options.npq {
+        reports.push(QcFilterReport {
+            filter: QcFilter::Npq,
+            channel: CtdChannel::ChlorophyllA,
+            parameters: parameters(serde_json::to_value(npq))?,
+            modified_points: correct_npq(rows, npq, utc_offset_minutes.unwrap_or(0)),
+        });
+    }
+    Ok(reports)
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::epoch_secs;
+use crate::ctd::handle_ctd_data::{Channel, ChannelMapping, CtdChannel, Scan};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Header names taken for the time column when `time_column` is not given.
+const TIME_COLUMN_NAMES: &[&str] = &[
+    "tstamp",
+    "timestamp",
+    "time",
+    "datetime",
+    "date_time",
+    "date time",
+    "time (utc)",
+    "time [utc]",
+];
+
+/// How the time column of a delimited file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    /// `2023-06-10T14:02:11Z`, `2023-06-10 14:02:11.250` or with a `+hh:mm` offset;
+    /// times without an offset are UTC
+    Iso8601,
+    UnixSeconds,
+    UnixMillis,
+    /// Seconds since `start_time`
+    ElapsedSeconds,
+}
+
+/// How to read a CSV/TSV CTD export.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DelimitedOptions {
+    /// Field separator; by default a tab for `.tsv`, otherwise whichever of `,`, `;`
+    /// and tab the header line holds most of
+    pub delimiter: Option<char>,
+    /// Lines before the header line, besides `#` and `%` comment lines
+    pub skip_lines: usize,
+    /// Header of the time column; by default the first of `tstamp`, `timestamp`,
+    /// `time`, `datetime` and the like
+    pub time_column: Option<String>,
+    /// By default guessed per value: ISO 8601 text, or Unix seconds or milliseconds by
+    /// magnitude
+    pub time_format: Option<TimeFormat>,
+    /// Start of the cast, in ISO 8601, for `elapsed_seconds`
+    pub start_time: Option<String>,
+    /// Which channel each column holds, by header. Columns not listed are still read
+    /// and matched through the channel-mapping table by their header
+    pub columns: HashMap<String, CtdChannel>,
+    /// Units of columns, by header, for headers that do not carry them as
+    /// `Name [unit]` or `Name (unit)`
+    pub units: HashMap<String, String>,
+}
+
+impl DelimitedOptions {
+    /// The column mapping as channel mappings, tried before any others.
+    pub(crate) fn channel_mappings(&self) -> Vec<ChannelMapping> {
+        self.columns
+            .iter()
+            .map(|(column, channel)| ChannelMapping {
+                channel: *channel,
+                names: vec![split_header(column).0],
+            })
+            .collect()
+    }
+}
+
+/// Whether a header names a time column (see `TIME_COLUMN_NAMES`).
+pub(crate) fn is_time_header(header: &str) -> bool {
+    let name = split_header(header).0.to_lowercase();
+    TIME_COLUMN_NAMES.contains(&name.as_str())
+}
+
+/// Splits `Name [unit]` or `Name (unit)` into the name and the unit.
+pub(crate) fn split_header(header: &str) -> (String, Option<String>) {
+    let header = header.trim();
+    for (open, close) in [('[', ']'), ('(', ')')] {
+        if let (Some(start), true) = (header.rfind(open), header.ends_with(close)) {
+            let unit = header[start + 1..header.len() - 1].trim();
+            if start > 0 && !unit.is_empty() {
+                return (header[..start].trim().to_string(), Some(unit.to_string()));
+            }
+        }
+    }
+    (header.to_string(), None)
+}
+
+/// Splits a line on `delimiter`, keeping delimiters inside double quotes and
+/// dropping the quotes.
+pub(crate) fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Parses an ISO 8601 date and time into milliseconds since the Unix epoch.
+pub(crate) fn parse_iso8601(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (date, rest) = text.split_at(text.find(['T', ' '])?);
+    let mut date_parts = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (
+        date_parts.next()??,
+        date_parts.next()??,
+        date_parts.next()??,
+    );
+
+    // Offset: `Z`, `+hh:mm`, `-hh:mm` or `+hhmm` after the clock
+    let rest = rest[1..].trim();
+    let (clock, offset_secs) = if let Some(clock) = rest.strip_suffix('Z') {
+        (clock, 0)
+    } else if let Some(at) = rest.rfind(['+', '-']) {
+        let offset = rest[at + 1..].replace(':', "");
+        let hours: i64 = offset.get(..2)?.parse().ok()?;
+        let minutes: i64 = offset
+            .get(2..)
+            .filter(|m| !m.is_empty())
+            .map_or(Some(0), |m| m.parse().ok())?;
+        let sign = if rest[at..].starts_with('-') { -1 } else { 1 };
+        (&rest[..at], sign * (hours * 3600 + minutes * 60))
+    } else {
+        (rest, 0)
+    };
+    let mut clock_parts = clock.split(':');
+    let hours: i64 = clock_parts.next()?.parse().ok()?;
+    let minutes: i64 = clock_parts.next()?.parse().ok()?;
+    let seconds: f64 = clock_parts.next().map_or(Some(0.0), |s| s.parse().ok())?;
+    let secs = epoch_secs(year, month, day, hours * 3600 + minutes * 60) - offset_secs;
+    Some(secs * 1000 + (seconds * 1000.0).round() as i64)
+}
+
+/// Milliseconds since the Unix epoch of a time value.
+pub(crate) fn parse_time(
+    value: &str,
+    format: Option<TimeFormat>,
+    start_ms: Option<i64>,
+) -> Option<i64> {
+    let value = value.trim();
+    let number = value.parse::<f64>().ok();
+    match format {
+        Some(TimeFormat::Iso8601) => parse_iso8601(value),
+        Some(TimeFormat::UnixSeconds) => number.map(|secs| (secs * 1000.0).round() as i64),
+        Some(TimeFormat::UnixMillis) => number.map(|ms| ms.round() as i64),
+        Some(TimeFormat::ElapsedSeconds) => {
+            number.map(|secs| start_ms.unwrap_or_default() + (secs * 1000.0).round() as i64)
+        }
+        // Milliseconds since 1970 pass 1e11 in 1973; seconds will not until 5138
+        None => match number {
+            Some(ms) if ms.abs() >= 1e11 => Some(ms.round() as i64),
+            Some(secs) => Some((secs * 1000.0).round() as i64),
+            None => parse_iso8601(value),
+        },
+    }
+}
+
+/// The delimiter `options` ask for, or the one the header line suggests.
+pub(crate) fn pick_delimiter(path: &Path, header: &str, options: &DelimitedOptions) -> char {
+    if let Some(delimiter) = options.delimiter {
+        return delimiter;
+    }
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    if extension.as_deref() == Some("tsv") {
+        return '\t';
+    }
+    ['\t', ';', ',']
+        .into_iter()
+        .max_by_key(|&d| header.matches(d).count())
+        .unwrap_or(',')
+}
+
+/// Reads a delimited (CSV/TSV) CTD export into channels and scans, as `read_rsk`
+/// does for RBR files. Every column besides the time column becomes a channel named
+/// by its header, with the unit from the header or `options.units`; columns without
+/// a single number are left out. Empty and non-numeric values are left empty.
+pub(crate) fn read_delimited(
+    path: &Path,
+    options: &DelimitedOptions,
+) -> Result<(Vec<Channel>, Vec<Scan>), PoleshiftError> {
+    let data_error = |reason: String| {
+        PoleshiftError::DataError(format!("Could not read {}: {}", path.display(), reason))
+    };
+    let start_ms = match (&options.start_time, options.time_format) {
+        (Some(start), _) => {
+            Some(
+                parse_iso8601(start).ok_or_else(|| PoleshiftError::InvalidInput {
+                    field: "start_time".to_string(),
+                    reason: "must be an ISO 8601 date and time".to_string(),
+                })?,
+            )
+        }
+        (None, Some(TimeFormat::ElapsedSeconds)) => {
+            return Err(PoleshiftError::InvalidInput {
+                field: "start_time".to_string(),
+                reason: "is required with elapsed_seconds".to_string(),
+            })
+        }
+        (None, _) => None,
+    };
+
+    // 1) Header line, after skipped and comment lines
+    let mut lines = BufReader::new(File::open(path)?)
+        .lines()
+        .skip(options.skip_lines)
+        .filter(|line| {
+            line.as_ref().map_or(true, |l| {
+                !l.starts_with('#') && !l.starts_with('%') && !l.trim().is_empty()
+            })
+        });
+    let header = lines
+        .next()
+        .ok_or_else(|| data_error("no header line".to_string()))??;
+    let header = header.trim_start_matches('\u{feff}');
+    let delimiter = pick_delimiter(path, header, options);
+    let headers: Vec<String> = split_fields(header, delimiter)
+        .into_iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+
+    // 2) The time column
+    let time_index = match &options.time_column {
+        Some(column) => headers.iter().position(|h| h == column.trim()),
+        None => headers.iter().position(|h| is_time_header(h)),
+    }
+    .ok_or_else(|| {
+        data_error(match &options.time_column {
+            Some(column) => format!("no `{}` column", column),
+            None => "no time column; name it with `time_column`".to_string(),
+        })
+    })?;
+
+    // 3) Scans, with every other column as a value
+    let mut scans: Vec<Scan> = Vec::new();
+    for (number, line) in lines.enumerate() {
+        let line = line?;
+        let fields = split_fields(&line, delimiter);
+        if fields.len() > headers.len() {
+            return Err(data_error(format!(
+                "data line {} has {} fields for {} columns",
+                number + 1,
+                fields.len(),
+                headers.len()
+            )));
+        }
+        let tstamp = fields
+            .get(time_index)
+            .and_then(|t| parse_time(t, options.time_format, start_ms));
+        let values = (0..headers.len())
+            .filter(|&i| i != time_index)
+            .map(|i| {
+                fields
+                    .get(i)
+                    .and_then(|v| v.trim().parse::<f64>().ok())
+                    .filter(|v| v.is_finite())
+            })
+            .collect();
+        scans.push((tstamp, values));
+    }
+    if scans.iter().all(|(tstamp, _)| tstamp.is_none()) {
+        return Err(data_error(format!(
+            "no readable times in the `{}` column",
+            headers[time_index]
+        )));
+    }
+
+    // 4) Channels, leaving out columns that hold no numbers (e.g. station names)
+    let value_columns: Vec<usize> = (0..headers.len()).filter(|&i| i != time_index).collect();
+    let numeric: Vec<bool> = (0..value_columns.len())
+        .map(|pos| scans.iter().any(|(_, values)| values[pos].is_some()))
+        .collect();
+    let channels = value_columns
+        .iter()
+        .zip(&numeric)
+        .filter(|(_, &numeric)| numeric)
+        .map(|(&i, _)| {
+            let (name, unit) = split_header(&headers[i]);
+            Channel {
+                channel_id: i as i32 + 1,
+                short_name: None,
+                units: options.units.get(&headers[i]).cloned().or(unit),
+                long_name: Some(name),
+                is_derived: None,
+                is_visible: Some(true),
+            }
+        })
+        .collect();
+    for (_, values) in &mut scans {
+        let mut keep = numeric.iter();
+        values.retain(|_| *keep.next().unwrap_or(&false));
+    }
+    Ok((channels, scans))
+}
@@ -0,0 +1,747 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use netcdf3::{DataSet, FileWriter, Version, NC_FILL_F64};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::ctd::flags::QartodFlag;
+use crate::ctd::format_iso8601;
+use crate::ctd::handle_ctd_data::{CtdChannel, ProcessedDataRow};
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::jobs::JobManager;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::now_ms;
+
+/// One cast to export: the processed (or binned) rows of one profile and where it
+/// was taken.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CtdExportCast {
+    /// Station or sample name, e.g. the sample ID
+    pub name: String,
+    /// Degrees north
+    pub latitude: Option<f64>,
+    /// Degrees east
+    pub longitude: Option<f64>,
+    pub rows: Vec<ProcessedDataRow>,
+}
+
+/// What an export wrote.
+#[derive(Debug, Clone, Serialize)]
+pub struct CtdExport {
+    pub path: String,
+    pub casts: usize,
+    pub rows: usize,
+    /// Variables written, by their name in the file
+    pub variables: Vec<String>,
+}
+
+/// A column of the processed rows, as written to export files.
+pub(crate) struct ExportVariable {
+    /// Name in the file (CF/ODV friendly)
+    pub(crate) name: &'static str,
+    pub(crate) long_name: &'static str,
+    /// CF standard name, when there is one
+    pub(crate) standard_name: Option<&'static str>,
+    pub(crate) value: fn(&ProcessedDataRow) -> Option<f64>,
+    /// Channel the values come from, which gives their unit and QARTOD flags;
+    /// derived variables have none
+    pub(crate) channel: Option<CtdChannel>,
+    /// Unit of derived variables
+    pub(crate) units: &'static str,
+}
+
+impl ExportVariable {
+    /// Unit of the variable as the instrument logged it, from the first row that
+    /// names one.
+    pub(crate) fn logged_units(&self, rows: &[&ProcessedDataRow]) -> String {
+        match self.channel {
+            Some(channel) => rows
+                .iter()
+                .map(|row| row.unit(channel))
+                .find(|unit| !unit.is_empty())
+                .unwrap_or_default()
+                .to_string(),
+            None => self.units.to_string(),
+        }
+    }
+
+    /// Unit of the variable in UDUNITS form.
+    pub(crate) fn units(&self, rows: &[&ProcessedDataRow]) -> String {
+        udunits(&self.logged_units(rows))
+    }
+
+    /// Whether any of `rows` carries a QARTOD flag for the variable.
+    pub(crate) fn is_flagged(&self, rows: &[&ProcessedDataRow]) -> bool {
+        self.channel
+            .is_some_and(|channel| rows.iter().any(|row| row.flags.contains_key(&channel)))
+    }
+}
+
+/// The variables of `EXPORT_VARIABLES` with a value in any of `rows`.
+pub(crate) fn present_variables(rows: &[&ProcessedDataRow]) -> Vec<&'static ExportVariable> {
+    EXPORT_VARIABLES
+        .iter()
+        .filter(|variable| rows.iter().any(|row| (variable.value)(row).is_some()))
+        .collect()
+}
+
+/// The variable of `EXPORT_VARIABLES` exported as `name`.
+pub(crate) fn export_variable(name: &str) -> Option<&'static ExportVariable> {
+    EXPORT_VARIABLES
+        .iter()
+        .find(|variable| variable.name == name)
+}
+
+/// Exported columns, in file order.
+pub(crate) const EXPORT_VARIABLES: [ExportVariable; 16] = [
+    ExportVariable {
+        name: "depth",
+        long_name: "Depth",
+        standard_name: Some("depth"),
+        value: |row| row.depth,
+        channel: Some(CtdChannel::Depth),
+        units: "",
+    },
+    ExportVariable {
+        name: "pressure",
+        long_name: "Pressure",
+        standard_name: Some("sea_water_pressure"),
+        value: |row| row.pressure,
+        channel: Some(CtdChannel::Pressure),
+        units: "",
+    },
+    ExportVariable {
+        name: "sea_pressure",
+        long_name: "Sea pressure",
+        standard_name: Some("sea_water_pressure_due_to_sea_water"),
+        value: |row| row.sea_pressure,
+        channel: Some(CtdChannel::SeaPressure),
+        units: "",
+    },
+    ExportVariable {
+        name: "temperature",
+        long_name: "In-situ temperature (ITS-90)",
+        standard_name: Some("sea_water_temperature"),
+        value: |row| row.temperature,
+        channel: Some(CtdChannel::Temperature),
+        units: "",
+    },
+    ExportVariable {
+        name: "salinity",
+        long_name: "Practical salinity",
+        standard_name: Some("sea_water_practical_salinity"),
+        value: |row| row.salinity,
+        channel: Some(CtdChannel::Salinity),
+        units: "",
+    },
+    ExportVariable {
+        name: "conductivity",
+        long_name: "Specific conductivity",
+        standard_name: None,
+        value: |row| row.specific_conductivity,
+        channel: Some(CtdChannel::SpecificConductivity),
+        units: "",
+    },
+    ExportVariable {
+        name: "speed_of_sound",
+        long_name: "Speed of sound",
+        standard_name: Some("speed_of_sound_in_sea_water"),
+        value: |row| row.speed_of_sound,
+        channel: Some(CtdChannel::SpeedOfSound),
+        units: "",
+    },
+    ExportVariable {
+        name: "chlorophyll_a",
+        long_name: "Chlorophyll a",
+        standard_name: None,
+        value: |row| row.chlorophyll_a,
+        channel: Some(CtdChannel::ChlorophyllA),
+        units: "",
+    },
+    ExportVariable {
+        name: "dissolved_oxygen",
+        long_name: "Dissolved oxygen",
+        standard_name: None,
+        value: |row| row.dissolved_oxygen,
+        channel: Some(CtdChannel::DissolvedOxygen),
+        units: "",
+    },
+    ExportVariable {
+        name: "turbidity",
+        long_name: "Turbidity",
+        standard_name: Some("sea_water_turbidity"),
+        value: |row| row.turbidity,
+        channel: Some(CtdChannel::Turbidity),
+        units: "",
+    },
+    ExportVariable {
+        name: "ph",
+        long_name: "pH",
+        standard_name: Some("sea_water_ph_reported_on_total_scale"),
+        value: |row| row.ph,
+        channel: Some(CtdChannel::Ph),
+        units: "",
+    },
+    ExportVariable {
+        name: "par",
+        long_name: "Photosynthetically active radiation",
+        standard_name: None,
+        value: |row| row.par,
+        channel: Some(CtdChannel::Par),
+        units: "",
+    },
+    ExportVariable {
+        name: "absolute_salinity",
+        long_name: "Absolute salinity (TEOS-10)",
+        standard_name: Some("sea_water_absolute_salinity"),
+        value: |row| row.absolute_salinity,
+        channel: None,
+        units: "g kg-1",
+    },
+    ExportVariable {
+        name: "conservative_temperature",
+        long_name: "Conservative temperature (TEOS-10)",
+        standard_name: Some("sea_water_conservative_temperature"),
+        value: |row| row.conservative_temperature,
+        channel: None,
+        units: "degC",
+    },
+    ExportVariable {
+        name: "sigma0",
+        long_name: "Potential density anomaly referenced to 0 dbar (TEOS-10)",
+        standard_name: None,
+        value: |row| row.sigma0,
+        channel: None,
+        units: "kg m-3",
+    },
+    ExportVariable {
+        name: "n_squared",
+        long_name: "Brunt-Vaisala frequency squared",
+        standard_name: Some("square_of_brunt_vaisala_frequency_in_sea_water"),
+        value: |row| row.n_squared,
+        channel: None,
+        units: "s-2",
+    },
+];
+
+/// An instrument unit in UDUNITS form where it is known to differ, e.g. `°C` as
+/// `degC` and `PSU` as `1`; others are kept as written.
+pub(crate) fn udunits(unit: &str) -> String {
+    match unit.trim() {
+        "°C" | "deg C" | "C" | "degrees C" => "degC".to_string(),
+        "PSU" | "psu" | "PSS-78" => "1".to_string(),
+        "m" | "meters" | "metres" => "m".to_string(),
+        "µg/l" | "ug/l" | "µg/L" | "ug/L" => "ug l-1".to_string(),
+        "µmol/l" | "umol/l" | "µmol/L" | "umol/L" => "umol l-1".to_string(),
+        "mg/l" | "mg/L" => "mg l-1".to_string(),
+        "mS/cm" => "mS cm-1".to_string(),
+        "µS/cm" | "uS/cm" => "uS cm-1".to_string(),
+        "S/m" => "S m-1".to_string(),
+        "m/s" => "m s-1".to_string(),
+        "µmol/m²/s" | "umol/m2/s" => "umol m-2 s-1".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Checks that there is something to export and that positions are on the globe.
+pub(crate) fn validate_casts(casts: &[CtdExportCast]) -> Result<(), PoleshiftError> {
+    if casts.iter().all(|cast| cast.rows.is_empty()) {
+        return Err(PoleshiftError::InvalidInput {
+            field: "casts".to_string(),
+            reason: "no rows to export".to_string(),
+        });
+    }
+    for cast in casts {
+        if cast
+            .latitude
+            .is_some_and(|lat| !(-90.0..=90.0).contains(&lat))
+            || cast
+                .longitude
+                .is_some_and(|lon| !(-180.0..=360.0).contains(&lon))
+        {
+            return Err(PoleshiftError::InvalidInput {
+                field: "casts".to_string(),
+                reason: format!("`{}` has a position off the globe", cast.name),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Time of a cast in ms since the Unix epoch: that of its first timed row.
+pub(crate) fn cast_time(cast: &CtdExportCast) -> Option<i64> {
+    cast.rows.iter().find_map(|row| row.tstamp)
+}
+
+fn netcdf_error(e: impl std::fmt::Debug) -> PoleshiftError {
+    PoleshiftError::DataError(format!("NetCDF: {:?}", e))
+}
+
+/// Writes `casts` to `path` as a CF-1.8 profile collection in a contiguous ragged
+/// array: one `profile` per cast with its time, position and station name, and the
+/// rows of all casts along `obs`, with `row_size` giving each cast's count.
+fn write_netcdf(
+    path: &Path,
+    casts: &[CtdExportCast],
+    title: &str,
+    attributes: &BTreeMap<String, String>,
+) -> Result<Vec<String>, PoleshiftError> {
+    let rows: Vec<&ProcessedDataRow> = casts.iter().flat_map(|cast| &cast.rows).collect();
+    let variables = present_variables(&rows);
+    let name_len = casts
+        .iter()
+        .map(|cast| cast.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    // 1) Definition: dimensions, coordinates, data and flag variables, attributes
+    let mut data_set = DataSet::new();
+    data_set
+        .add_fixed_dim("profile", casts.len())
+        .map_err(netcdf_error)?;
+    data_set
+        .add_fixed_dim("obs", rows.len())
+        .map_err(netcdf_error)?;
+    data_set
+        .add_fixed_dim("name_strlen", name_len)
+        .map_err(netcdf_error)?;
+
+    data_set
+        .add_var_u8("station_name", &["profile", "name_strlen"])
+        .map_err(netcdf_error)?;
+    data_set
+        .add_var_attr_string("station_name", "cf_role", "profile_id")
+        .map_err(netcdf_error)?;
+    data_set
+        .add_var_attr_string("station_name", "long_name", "Station or sample name")
+        .map_err(netcdf_error)?;
+    let coordinates = [
+        ("time", "time", "seconds since 1970-01-01T00:00:00Z", "T"),
+        ("lat", "latitude", "degrees_north", "Y"),
+        ("lon", "longitude", "degrees_east", "X"),
+    ];
+    for (name, standard_name, units, axis) in coordinates {
+        data_set
+            .add_var_f64(name, &["profile"])
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(name, "standard_name", standard_name)
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(name, "units", units)
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(name, "axis", axis)
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_f64(name, "_FillValue", vec![NC_FILL_F64])
+            .map_err(netcdf_error)?;
+    }
+    data_set
+        .add_var_i32("row_size", &["profile"])
+        .map_err(netcdf_error)?;
+    data_set
+        .add_var_attr_string(
+            "row_size",
+            "long_name",
+            "Number of observations per profile",
+        )
+        .map_err(netcdf_error)?;
+    data_set
+        .add_var_attr_string("row_size", "sample_dimension", "obs")
+        .map_err(netcdf_error)?;
+
+    let mut flagged = Vec::new();
+    for variable in &variables {
+        let name = variable.name;
+        data_set.add_var_f64(name, &["obs"]).map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(name, "long_name", variable.long_name)
+            .map_err(netcdf_error)?;
+        if let Some(standard_name) = variable.standard_name {
+            data_set
+                .add_var_attr_string(name, "standard_name", standard_name)
+                .map_err(netcdf_error)?;
+        }
+        data_set
+            .add_var_attr_string(name, "units", variable.units(&rows))
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_f64(name, "_FillValue", vec![NC_FILL_F64])
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(name, "coordinates", "time lat lon depth")
+            .map_err(netcdf_error)?;
+        if name == "depth" {
+            data_set
+                .add_var_attr_string(name, "positive", "down")
+                .map_err(netcdf_error)?;
+            data_set
+                .add_var_attr_string(name, "axis", "Z")
+                .map_err(netcdf_error)?;
+        }
+
+        // QARTOD flags of the variable's channel, when it was tested
+        let Some(channel) = variable.channel.filter(|_| variable.is_flagged(&rows)) else {
+            continue;
+        };
+        let qc_name = format!("{}_qc", name);
+        data_set
+            .add_var_i8(&qc_name, &["obs"])
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(&qc_name, "long_name", format!("QARTOD flag of {}", name))
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_i8(&qc_name, "flag_values", vec![1, 2, 3, 4, 9])
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(
+                &qc_name,
+                "flag_meanings",
+                "pass not_evaluated suspect fail missing",
+            )
+            .map_err(netcdf_error)?;
+        data_set
+            .add_var_attr_string(name, "ancillary_variables", &qc_name)
+            .map_err(netcdf_error)?;
+        flagged.push((qc_name, channel));
+    }
+
+    let globals = [
+        ("Conventions", "CF-1.8"),
+        ("featureType", "profile"),
+        ("title", title),
+        ("source", "CTD profiles processed in poleshift"),
+        ("date_created", &format_iso8601(now_ms(), 0)),
+    ];
+    for (name, value) in globals {
+        data_set
+            .add_global_attr_string(name, value)
+            .map_err(netcdf_error)?;
+    }
+    for (name, value) in attributes {
+        data_set
+            .add_global_attr_string(name, value)
+            .map_err(netcdf_error)?;
+    }
+
+    // 2) Data
+    let mut writer = FileWriter::create_new(path).map_err(netcdf_error)?;
+    writer
+        .set_def(&data_set, Version::Offset64Bit, 0)
+        .map_err(netcdf_error)?;
+
+    let names: Vec<u8> = casts
+        .iter()
+        .flat_map(|cast| {
+            let mut name = cast.name.as_bytes().to_vec();
+            name.resize(name_len, 0);
+            name
+        })
+        .collect();
+    writer
+        .write_var_u8("station_name", &names)
+        .map_err(netcdf_error)?;
+    let per_cast = |value: fn(&CtdExportCast) -> Option<f64>| -> Vec<f64> {
+        casts
+            .iter()
+            .map(|cast| value(cast).unwrap_or(NC_FILL_F64))
+            .collect()
+    };
+    writer
+        .write_var_f64(
+            "time",
+            &per_cast(|c| cast_time(c).map(|t| t as f64 / 1000.0)),
+        )
+        .map_err(netcdf_error)?;
+    writer
+        .write_var_f64("lat", &per_cast(|c| c.latitude))
+        .map_err(netcdf_error)?;
+    writer
+        .write_var_f64("lon", &per_cast(|c| c.longitude))
+        .map_err(netcdf_error)?;
+    let row_sizes: Vec<i32> = casts.iter().map(|cast| cast.rows.len() as i32).collect();
+    writer
+        .write_var_i32("row_size", &row_sizes)
+        .map_err(netcdf_error)?;
+
+    for variable in &variables {
+        let values: Vec<f64> = rows
+            .iter()
+            .map(|row| (variable.value)(row).unwrap_or(NC_FILL_F64))
+            .collect();
+        writer
+            .write_var_f64(variable.name, &values)
+            .map_err(netcdf_error)?;
+    }
+    for (qc_name, channel) in &flagged {
+        let codes: Vec<i8> = rows
+            .iter()
+            .map(|row| row.flags.get(channel).map_or(2, |f| f.flag.code()))
+            .collect();
+        writer.write_var_i8(qc_name, &codes).map_err(netcdf_error)?;
+    }
+    writer.close().map_err(netcdf_error)?;
+
+    Ok(variables
+        .iter()
+        .map(|variable| variable.name.to_string())
+        .chain(flagged.into_iter().map(|(qc_name, _)| qc_name))
+        .collect())
+}
+
+/// Writes processed casts to `output_path` as CF-compliant NetCDF (a profile
+/// collection in a contiguous ragged array), for archive submission. Each cast's
+/// time is that of its first row; `attributes` become global attributes, e.g.
+/// `institution` or `project`. Runs as an `export` job.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn export_ctd_netcdf<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    casts: Vec<CtdExportCast>,
+    output_path: String,
+    title: Option<String>,
+    attributes: Option<BTreeMap<String, String>>,
+) -> Result<StandardResponseNoFiles<CtdExport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        validate_casts(&casts)?;
+        let jobs = app_handle.state::<JobManager>();
+        jobs.run("export", None, |_| async move {
+            let path = Path::new(&output_path);
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            let variables = write_netcdf(
+                path,
+                &casts,
+                title.as_deref().unwrap_or("CTD profiles"),
+                &attributes.unwrap_or_default(),
+            )?;
+            Ok(export_report(output_path, &casts, variables))
+        })
+        .await
+    })
+    .await
+}
+
+/// ODV quality code of a QARTOD flag, in ODV's generic scheme.
+fn odv_quality(flag: Option<QartodFlag>) -> u8 {
+    match flag {
+        Some(QartodFlag::Pass) => 0,
+        Some(QartodFlag::Suspect) => 4,
+        Some(QartodFlag::Fail) => 8,
+        Some(QartodFlag::NotEvaluated) | Some(QartodFlag::Missing) | None => 1,
+    }
+}
+
+/// A CSV field, quoted when it holds a comma, quote or line break.
+pub(crate) fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn format_value(value: Option<f64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Writes `casts` as CSV: one row per processed (or binned) row, with the station,
+/// position and UTC time of its cast, then a `name [unit]` column per variable and a
+/// `name_qc` column of QARTOD codes for each tested one.
+fn write_csv(path: &Path, casts: &[CtdExportCast]) -> Result<Vec<String>, PoleshiftError> {
+    let rows: Vec<&ProcessedDataRow> = casts.iter().flat_map(|cast| &cast.rows).collect();
+    let variables = present_variables(&rows);
+    let mut out = BufWriter::new(File::create(path)?);
+
+    let mut header = vec![
+        "station".to_string(),
+        "latitude [degrees_north]".to_string(),
+        "longitude [degrees_east]".to_string(),
+        "time [UTC]".to_string(),
+    ];
+    for variable in &variables {
+        header.push(csv_field(&format!(
+            "{} [{}]",
+            variable.name,
+            variable.logged_units(&rows)
+        )));
+        if variable.is_flagged(&rows) {
+            header.push(format!("{}_qc", variable.name));
+        }
+    }
+    writeln!(out, "{}", header.join(","))?;
+
+    for cast in casts {
+        for row in &cast.rows {
+            let mut fields = vec![
+                csv_field(&cast.name),
+                format_value(cast.latitude),
+                format_value(cast.longitude),
+                row.tstamp.map(|t| format_iso8601(t, 0)).unwrap_or_default(),
+            ];
+            for variable in &variables {
+                fields.push(format_value((variable.value)(row)));
+                if variable.is_flagged(&rows) {
+                    let flag = variable.channel.and_then(|channel| row.flags.get(&channel));
+                    fields.push(flag.map(|f| f.flag.code().to_string()).unwrap_or_default());
+                }
+            }
+            writeln!(out, "{}", fields.join(","))?;
+        }
+    }
+    out.flush()?;
+
+    Ok(variables
+        .iter()
+        .map(|variable| variable.name.to_string())
+        .collect())
+}
+
+/// Writes `casts` as an ODV generic spreadsheet (tab-separated, `Profiles` data
+/// type): the cruise, station, type `C`, time and position metadata columns on every
+/// row, then a `name [unit]` column per variable, depth first as the primary
+/// variable, each followed by its `QV:ODV` quality column.
+fn write_odv(
+    path: &Path,
+    casts: &[CtdExportCast],
+    cruise: &str,
+) -> Result<Vec<String>, PoleshiftError> {
+    let rows: Vec<&ProcessedDataRow> = casts.iter().flat_map(|cast| &cast.rows).collect();
+    let variables = present_variables(&rows);
+    if variables.first().map(|variable| variable.name) != Some("depth") {
+        return Err(PoleshiftError::InvalidInput {
+            field: "casts".to_string(),
+            reason: "ODV profiles need depths".to_string(),
+        });
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "//<Encoding>UTF-8</Encoding>")?;
+    writeln!(out, "//<Creator>poleshift</Creator>")?;
+    writeln!(out, "//<DataField>Ocean</DataField>")?;
+    writeln!(out, "//<DataType>Profiles</DataType>")?;
+    let mut header = vec![
+        "Cruise".to_string(),
+        "Station".to_string(),
+        "Type".to_string(),
+        "yyyy-mm-ddThh:mm:ss.sss".to_string(),
+        "Longitude [degrees_east]".to_string(),
+        "Latitude [degrees_north]".to_string(),
+        "Bot. Depth [m]".to_string(),
+    ];
+    for variable in &variables {
+        header.push(format!(
+            "{} [{}]",
+            variable.long_name,
+            variable.logged_units(&rows)
+        ));
+        header.push("QV:ODV".to_string());
+    }
+    writeln!(out, "{}", header.join("\t"))?;
+
+    // ODV takes its station names and cruise from the text as is, so tabs and line
+    // breaks would split columns
+    let clean = |text: &str| text.replace(['\t', '\n', '\r'], " ");
+    for cast in casts {
+        let time = cast_time(cast)
+            .map(|t| format_iso8601(t, 0).trim_end_matches('Z').to_string())
+            .unwrap_or_default();
+        for row in &cast.rows {
+            let mut fields = vec![
+                clean(cruise),
+                clean(&cast.name),
+                "C".to_string(),
+                time.clone(),
+                format_value(cast.longitude),
+                format_value(cast.latitude),
+                String::new(),
+            ];
+            for variable in &variables {
+                let value = (variable.value)(row);
+                let flag = variable
+                    .channel
+                    .and_then(|channel| row.flags.get(&channel))
+                    .map(|f| f.flag);
+                fields.push(format_value(value));
+                fields.push(odv_quality(flag).to_string());
+            }
+            writeln!(out, "{}", fields.join("\t"))?;
+        }
+    }
+    out.flush()?;
+
+    Ok(variables
+        .iter()
+        .map(|variable| variable.name.to_string())
+        .collect())
+}
+
+fn export_report(
+    output_path: String,
+    casts: &[CtdExportCast],
+    variables: Vec<String>,
+) -> StandardResponseNoFiles<CtdExport> {
+    StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report: CtdExport {
+            path: output_path,
+            casts: casts.len(),
+            rows: casts.iter().map(|cast| cast.rows.len()).sum(),
+            variables,
+        },
+    }
+}
+
+/// Writes processed casts to `output_path` as analysis-ready CSV, one row per
+/// processed (or binned) row and a column per variable. Runs as an `export` job.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn export_ctd_csv<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    casts: Vec<CtdExportCast>,
+    output_path: String,
+) -> Result<StandardResponseNoFiles<CtdExport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        validate_casts(&casts)?;
+        let jobs = app_handle.state::<JobManager>();
+        jobs.run("export", None, |_| async move {
+            let variables = write_csv(Path::new(&output_path), &casts)?;
+            Ok(export_report(output_path, &casts, variables))
+        })
+        .await
+    })
+    .await
+}
+
+/// Writes processed casts to `output_path` as an ODV generic spreadsheet, ready to
+/// import into Ocean Data View as profiles of `cruise`. Runs as an `export` job.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn export_ctd_odv<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    casts: Vec<CtdExportCast>,
+    output_path: String,
+    cruise: Option<String>,
+) -> Result<StandardResponseNoFiles<CtdExport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        validate_casts(&casts)?;
+        let jobs = app_handle.state::<JobManager>();
+        jobs.run("export", None, |_| async move {
+            let variables = write_odv(
+                Path::new(&output_path),
+                &casts,
+                cruise.as_deref().unwrap_or("poleshift"),
+            )?;
+            Ok(export_report(output_path, &casts, variables))
+        })
+        .await
+    })
+    .await
+}
@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::handle_ctd_data::{CtdChannel, ProcessedDataRow};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// QARTOD quality flag of a value, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QartodFlag {
+    /// 1: passed every test run on it
+    Pass,
+    /// 2: no test could be run on it, e.g. the first value of a spike test
+    NotEvaluated,
+    /// 3: failed a suspect threshold; kept, but worth a look
+    Suspect,
+    /// 4: failed a fail threshold; left out of binned profiles
+    Fail,
+    /// 9: the value is empty
+    Missing,
+}
+
+impl QartodFlag {
+    /// The QARTOD code of the flag.
+    pub(crate) fn code(self) -> i8 {
+        match self {
+            QartodFlag::Pass => 1,
+            QartodFlag::NotEvaluated => 2,
+            QartodFlag::Suspect => 3,
+            QartodFlag::Fail => 4,
+            QartodFlag::Missing => 9,
+        }
+    }
+}
+
+/// A QARTOD test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QartodTest {
+    GrossRange,
+    Spike,
+    RateOfChange,
+    FlatLine,
+}
+
+/// The flag of one value, with the tests that raised it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueFlag {
+    pub flag: QartodFlag,
+    /// Tests that found the value suspect or failed it
+    pub tests: Vec<QartodTest>,
+    /// Whether `flag` was set by an override rather than the tests
+    #[serde(default)]
+    pub overridden: bool,
+}
+
+/// Values outside `fail_min..=fail_max` fail; values inside it but outside
+/// `suspect_min..=suspect_max` are suspect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrossRangeTest {
+    pub fail_min: f64,
+    pub fail_max: f64,
+    pub suspect_min: Option<f64>,
+    pub suspect_max: Option<f64>,
+}
+
+/// Flags a value by how far it sits from the mean of its neighbours, in the channel's
+/// unit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpikeTest {
+    pub suspect: f64,
+    pub fail: f64,
+}
+
+/// Flags a value as suspect when it changed faster than `max_rate` per second since the
+/// previous value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateOfChangeTest {
+    pub max_rate: f64,
+}
+
+/// Flags a value whose channel has stayed within `tolerance` of it for `suspect_secs`
+/// or `fail_secs`, as a stuck sensor would.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlatLineTest {
+    pub tolerance: f64,
+    pub suspect_secs: f64,
+    pub fail_secs: f64,
+}
+
+/// The tests run on one channel; thresholds are in the channel's unit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelTests {
+    pub channel: CtdChannel,
+    #[serde(default)]
+    pub gross_range: Option<GrossRangeTest>,
+    #[serde(default)]
+    pub spike: Option<SpikeTest>,
+    #[serde(default)]
+    pub rate_of_change: Option<RateOfChangeTest>,
+    #[serde(default)]
+    pub flat_line: Option<FlatLineTest>,
+}
+
+/// Sets the flag of one value by hand, e.g. to pass a real feature a test failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagOverride {
+    /// Timestamp of the row, in ms since the epoch
+    pub tstamp: i64,
+    pub channel: CtdChannel,
+    pub flag: QartodFlag,
+}
+
+/// QARTOD tests per channel and overrides of their flags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlagOptions {
+    pub tests: Vec<ChannelTests>,
+    pub overrides: Vec<FlagOverride>,
+}
+
+impl Default for FlagOptions {
+    /// Gross range tests on temperature (°C) and practical salinity at the limits of
+    /// seawater; spike, rate-of-change and flat-line thresholds depend on the sensor
+    /// and the site, so those tests are only run when given.
+    fn default() -> Self {
+        FlagOptions {
+            tests: vec![
+                ChannelTests {
+                    channel: CtdChannel::Temperature,
+                    gross_range: Some(GrossRangeTest {
+                        fail_min: -2.5,
+                        fail_max: 40.0,
+                        suspect_min: Some(-2.0),
+                        suspect_max: Some(35.0),
+                    }),
+                    spike: None,
+                    rate_of_change: None,
+                    flat_line: None,
+                },
+                ChannelTests {
+                    channel: CtdChannel::Salinity,
+                    gross_range: Some(GrossRangeTest {
+                        fail_min: 0.0,
+                        fail_max: 42.0,
+                        suspect_min: Some(2.0),
+                        suspect_max: Some(41.0),
+                    }),
+                    spike: None,
+                    rate_of_change: None,
+                    flat_line: None,
+                },
+            ],
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl FlagOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let invalid =
+            |channel: CtdChannel, test: &str, reason: &str| PoleshiftError::InvalidInput {
+                field: format!("flags.tests.{:?}.{}", channel, test),
+                reason: reason.to_string(),
+            };
+        for tests in &self.tests {
+            let channel = tests.channel;
+            if let Some(range) = &tests.gross_range {
+                if range.fail_min > range.fail_max {
+                    return Err(invalid(
+                        channel,
+                        "gross_range",
+                        "fail_min is above fail_max",
+                    ));
+                }
+            }
+            if let Some(spike) = &tests.spike {
+                if !(spike.suspect >= 0.0 && spike.fail >= spike.suspect) {
+                    return Err(invalid(channel, "spike", "needs 0 <= suspect <= fail"));
+                }
+            }
+            if let Some(rate) = &tests.rate_of_change {
+                if !(rate.max_rate.is_finite() && rate.max_rate > 0.0) {
+                    return Err(invalid(
+                        channel,
+                        "rate_of_change",
+                        "max_rate must be positive",
+                    ));
+                }
+            }
+            if let Some(flat) = &tests.flat_line {
+                if !(flat.tolerance >= 0.0
+                    && flat.suspect_secs > 0.0
+                    && flat.fail_secs >= flat.suspect_secs)
+                {
+                    return Err(invalid(
+                        channel,
+                        "flat_line",
+                        "needs tolerance >= 0 and 0 < suspect_secs <= fail_secs",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Flag of a measure of a value, e.g. the size of a spike, against its thresholds.
+fn threshold_flag(measure: f64, suspect: f64, fail: f64) -> QartodFlag {
+    if measure >= fail {
+        QartodFlag::Fail
+    } else if measure >= suspect {
+        QartodFlag::Suspect
+    } else {
+        QartodFlag::Pass
+    }
+}
+
+/// Runs one channel's tests over its values (`times` in ms), returning a flag per
+/// test per value.
+fn run_tests(
+    tests: &ChannelTests,
+    times: &[i64],
+    values: &[f64],
+) -> Vec<Vec<(QartodTest, QartodFlag)>> {
+    let mut results = vec![Vec::new(); values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let result = &mut results[i];
+        if let Some(range) = &tests.gross_range {
+            let flag = if value < range.fail_min || value > range.fail_max {
+                QartodFlag::Fail
+            } else if range.suspect_min.is_some_and(|min| value < min)
+                || range.suspect_max.is_some_and(|max| value > max)
+            {
+                QartodFlag::Suspect
+            } else {
+                QartodFlag::Pass
+            };
+            result.push((QartodTest::GrossRange, flag));
+        }
+        if let Some(spike) = &tests.spike {
+            let flag = match (i.checked_sub(1), values.get(i + 1)) {
+                (Some(before), Some(after)) => {
+                    let size = (value - (values[before] + after) / 2.0).abs();
+                    threshold_flag(size, spike.suspect, spike.fail)
+                }
+                _ => QartodFlag::NotEvaluated,
+            };
+            result.push((QartodTest::Spike, flag));
+        }
+        if let Some(rate) = &tests.rate_of_change {
+            let flag = match i.checked_sub(1) {
+                Some(before) if times[i] > times[before] => {
+                    let secs = (times[i] - times[before]) as f64 / 1000.0;
+                    if (value - values[before]).abs() / secs > rate.max_rate {
+                        QartodFlag::Suspect
+                    } else {
+                        QartodFlag::Pass
+                    }
+                }
+                _ => QartodFlag::NotEvaluated,
+            };
+            result.push((QartodTest::RateOfChange, flag));
+        }
+        if let Some(flat) = &tests.flat_line {
+            let mut start = i;
+            while start > 0 && (values[start - 1] - value).abs() <= flat.tolerance {
+                start -= 1;
+            }
+            let flat_secs = (times[i] - times[start]) as f64 / 1000.0;
+            let flag = threshold_flag(flat_secs, flat.suspect_secs, flat.fail_secs);
+            result.push((QartodTest::FlatLine, flag));
+        }
+    }
+    results
+}
+
+/// Flags the values of `rows` with QARTOD tests (gross range, spike, rate of change
+/// and flat line), channel by channel in row order, then applies the overrides. Each
+/// tested channel gets a flag in every row's `flags`: the worst its tests gave,
+/// `not_evaluated` when none could run and `missing` for empty values. Nothing is
+/// dropped; binning leaves out failed values.
+pub(crate) fn flag_rows(rows: &mut [ProcessedDataRow], options: &FlagOptions) {
+    for tests in &options.tests {
+        let channel = tests.channel;
+        let (indices, values): (Vec<usize>, Vec<f64>) = rows
+            .iter()
+            .enumerate()
+            .filter_map(|(i, row)| row.value(channel).map(|value| (i, value)))
+            .unzip();
+        let times: Vec<i64> = indices
+            .iter()
+            .map(|&i| rows[i].tstamp.unwrap_or_default())
+            .collect();
+        let results = run_tests(tests, &times, &values);
+
+        for row in rows.iter_mut() {
+            row.flags.insert(
+                channel,
+                ValueFlag {
+                    flag: QartodFlag::Missing,
+                    tests: Vec::new(),
+                    overridden: false,
+                },
+            );
+        }
+        for (&i, result) in indices.iter().zip(results) {
+            let evaluated = result.iter().map(|(_, flag)| *flag);
+            let flag = evaluated
+                .filter(|flag| *flag != QartodFlag::NotEvaluated)
+                .max()
+                .unwrap_or(QartodFlag::NotEvaluated);
+            let tests = result
+                .iter()
+                .filter(|(_, flag)| matches!(flag, QartodFlag::Suspect | QartodFlag::Fail))
+                .map(|(test, _)| *test)
+                .collect();
+            rows[i].flags.insert(
+                channel,
+                ValueFlag {
+                    flag,
+                    tests,
+                    overridden: false,
+                },
+            );
+        }
+    }
+
+    for flag_override in &options.overrides {
+        let matching = rows
+            .iter_mut()
+            .filter(|row| row.tstamp == Some(flag_override.tstamp));
+        for row in matching {
+            let value_flag = row.flags.entry(flag_override.channel).or_insert(ValueFlag {
+                flag: flag_override.flag,
+                tests: Vec::new(),
+                overridden: true,
+            });
+            value_flag.flag = flag_override.flag;
+            value_flag.overridden = true;
+        }
+    }
+}
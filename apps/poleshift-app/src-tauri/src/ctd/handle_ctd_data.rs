@@ -0,0 +1,1333 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::ctd::binning::bin_rows;
+use crate::ctd::calibration::recalibrate;
+use crate::ctd::cast::{
+    profile_at, profile_ranges, segment_cast, CastPhase, CastProfile, CastSegment, ProfileSample,
+    ProfileSource,
+};
+use crate::ctd::chlorophyll::correct_chlorophyll;
+use crate::ctd::delimited::{self, DelimitedOptions};
+use crate::ctd::flags::{flag_rows, ValueFlag};
+use crate::ctd::format_iso8601;
+use crate::ctd::mooring::{moored_series, MooredSeries, ProcessingMode};
+use crate::ctd::options::CtdProcessingOptions;
+use crate::ctd::output_store::{
+    ctd_store_path, downsample, persist_ctd_outputs, CtdStoreSummary, DEFAULT_PREVIEW_ROWS,
+};
+use crate::ctd::qc::{apply_qc, QcFilterReport};
+use crate::ctd::rsk_metadata::{read_rsk_metadata, InstrumentMetadata};
+use crate::ctd::seabird;
+use crate::ctd::teos10::{derive_practical_salinity, derive_teos10, SalinitySource};
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::results_store::{results_store_path, save_result, ResultDetail, ResultKeys, ResultKind};
+use crate::settings::current_settings;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Window};
+use uuid::Uuid;
+// ---------------------------------------------------------------------------
+// Structures
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize)]
+pub struct CTDReport {
+    /// The raw data from each channel, combining all channels by timestamp
+    pub raw_data: Vec<RawDataRow>,
+    /// The final processed data rows after combining channels and applying filters
+    pub processed_data: Vec<ProcessedDataRow>,
+    /// `processed_data` averaged into depth or pressure bins, per cast; empty unless
+    /// `processing.binning` is set
+    pub binned_data: Vec<ProcessedDataRow>,
+    /// Every channel the instrument logged, in file order
+    pub channels: Vec<MeasuredChannel>,
+    /// The instrument, its calibrations and deployments, for RSK files
+    pub instrument: Option<InstrumentMetadata>,
+    /// Phases of the cast in time order: soak, downcasts, upcasts and holds
+    pub casts: Vec<CastSegment>,
+    /// Separate profiles in the file, e.g. one per station, each with the sample its
+    /// rows were assigned to
+    pub profiles: Vec<CastProfile>,
+    /// QC filters run on the processed rows, with their parameters and how many
+    /// values each changed
+    pub qc: Vec<QcFilterReport>,
+    /// Scans read and how many of them became processed rows, and why the rest did not
+    pub summary: ProcessingSummary,
+    /// Averages and gaps over time, in `ProcessingMode::Moored`
+    pub time_series: Option<MooredSeries>,
+    /// Where the full-resolution rows went with `persist_outputs`; the rows above are
+    /// then an evenly spaced preview of them
+    pub store: Option<CtdStoreSummary>,
+}
+
+/// Why a scan did not become a processed row.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// The scan has no timestamp
+    NoTimestamp,
+    /// The scan has no depth, so it cannot be placed in the cast
+    NoDepth,
+    /// Recorded in a cast phase that is not kept
+    Soak,
+    Downcast,
+    Upcast,
+    Stationary,
+    /// Not deeper than `cast.min_depth`
+    AboveMinDepth,
+}
+
+impl DropReason {
+    fn phase(phase: CastPhase) -> DropReason {
+        match phase {
+            CastPhase::Soak => DropReason::Soak,
+            CastPhase::Downcast => DropReason::Downcast,
+            CastPhase::Upcast => DropReason::Upcast,
+            CastPhase::Stationary => DropReason::Stationary,
+        }
+    }
+}
+
+/// What became of the scans of a file.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ProcessingSummary {
+    /// Scans read
+    pub rows_in: usize,
+    /// Processed rows kept
+    pub rows_kept: usize,
+    /// Scans that were not kept, by reason
+    pub dropped: BTreeMap<DropReason, usize>,
+}
+
+/// A channel logged by the instrument.
+#[derive(Serialize, Clone, Debug)]
+pub struct MeasuredChannel {
+    /// Key of the channel in the rows' `channels`
+    pub name: String,
+    pub unit: String,
+    /// The core field it fills, if any; other channels are only in `channels`
+    pub mapped_to: Option<CtdChannel>,
+    pub is_derived: bool,
+}
+
+/// The value of one channel in a row.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelReading {
+    pub value: Option<f64>,
+    pub unit: String,
+}
+
+/// A single row of “raw” data combining multiple channel values.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RawDataRow {
+    // Required base fields
+    pub(crate) tstamp: Option<i64>,
+    // `tstamp` in ISO 8601, in UTC and in the vessel's local time
+    #[serde(default)]
+    time_utc: Option<String>,
+    #[serde(default)]
+    time_local: Option<String>,
+    depth: Option<f64>,
+    pressure: Option<f64>,
+    sea_pressure: Option<f64>,
+    temperature: Option<f64>,
+    chlorophyll_a: Option<f64>,
+    salinity: Option<f64>,
+    speed_of_sound: Option<f64>,
+    specific_conductivity: Option<f64>,
+    dissolved_oxygen: Option<f64>,
+    turbidity: Option<f64>,
+    ph: Option<f64>,
+    par: Option<f64>,
+
+    // Units for each channel
+    depth_unit: String,
+    pressure_unit: String,
+    sea_pressure_unit: String,
+    temperature_unit: String,
+    chlorophyll_a_unit: String,
+    salinity_unit: String,
+    speed_of_sound_unit: String,
+    specific_conductivity_unit: String,
+    dissolved_oxygen_unit: String,
+    turbidity_unit: String,
+    ph_unit: String,
+    par_unit: String,
+
+    // Channels not mapped to a field above, by name
+    #[serde(default)]
+    channels: BTreeMap<String, ChannelReading>,
+
+    // IDs for traceability
+    id: Uuid,
+    sample_id: String,
+    org_id: String,
+    user_id: String,
+    raw_data_id: String,
+}
+
+/// A measured quantity of `RawDataRow`/`ProcessedDataRow`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CtdChannel {
+    Depth,
+    Pressure,
+    SeaPressure,
+    Temperature,
+    ChlorophyllA,
+    Salinity,
+    SpeedOfSound,
+    SpecificConductivity,
+    DissolvedOxygen,
+    Turbidity,
+    Ph,
+    Par,
+}
+
+impl CtdChannel {
+    pub(crate) const ALL: [CtdChannel; 12] = [
+        CtdChannel::Depth,
+        CtdChannel::Pressure,
+        CtdChannel::SeaPressure,
+        CtdChannel::Temperature,
+        CtdChannel::ChlorophyllA,
+        CtdChannel::Salinity,
+        CtdChannel::SpeedOfSound,
+        CtdChannel::SpecificConductivity,
+        CtdChannel::DissolvedOxygen,
+        CtdChannel::Turbidity,
+        CtdChannel::Ph,
+        CtdChannel::Par,
+    ];
+}
+
+/// Instrument channels that feed `channel`, by long name or short name (the RSK short
+/// name, or the Sea-Bird variable code such as `t090C`), case-insensitive and most
+/// preferred first.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChannelMapping {
+    pub channel: CtdChannel,
+    pub names: Vec<String>,
+}
+
+/// The default channel-mapping table, with the long names RBR instruments log and the
+/// variable codes of Sea-Bird CNV files. Sea-Bird pressure is relative to the surface,
+/// so it is sea pressure.
+const DEFAULT_CHANNEL_NAMES: &[(CtdChannel, &[&str])] = &[
+    (CtdChannel::Depth, &["depth", "depSM", "depFM"]),
+    (CtdChannel::Pressure, &["pressure"]),
+    (
+        CtdChannel::SeaPressure,
+        &["sea pressure", "prdM", "prDM", "prSM"],
+    ),
+    (
+        CtdChannel::Temperature,
+        &["temperature", "t090C", "tv290C", "t068C"],
+    ),
+    (
+        CtdChannel::ChlorophyllA,
+        &[
+            "chlorophyll a",
+            "chlorophyll",
+            "flECO-AFL",
+            "flSP",
+            "flC",
+            "wetStar",
+        ],
+    ),
+    (CtdChannel::Salinity, &["salinity", "sal00"]),
+    (
+        CtdChannel::SpeedOfSound,
+        &["speed of sound", "svCM", "svDM", "svWM"],
+    ),
+    (
+        CtdChannel::SpecificConductivity,
+        &["specific conductivity", "specc"],
+    ),
+    (
+        CtdChannel::DissolvedOxygen,
+        &[
+            "dissolved o2 concentration",
+            "dissolved oxygen concentration",
+            "dissolved oxygen",
+            "oxygen concentration",
+            "sbeox0Mg/L",
+            "sbeox0ML/L",
+            "sbeox0Mm/L",
+            "sbox0Mm/Kg",
+        ],
+    ),
+    (
+        CtdChannel::Turbidity,
+        &["turbidity", "turbWETntu0", "seaTurbMtr", "obs"],
+    ),
+    (CtdChannel::Ph, &["ph"]),
+    (
+        CtdChannel::Par,
+        &["par", "photosynthetically active radiation"],
+    ),
+];
+
+/// The default channel-mapping table.
+pub fn default_channel_mappings() -> Vec<ChannelMapping> {
+    DEFAULT_CHANNEL_NAMES
+        .iter()
+        .map(|(channel, names)| ChannelMapping {
+            channel: *channel,
+            names: names.iter().map(|n| n.to_string()).collect(),
+        })
+        .collect()
+}
+
+/// Matches your Channels table in the DB. Other formats describe their channels
+/// the same way.
+#[derive(Serialize, Clone)]
+pub(crate) struct Channel {
+    pub channel_id: i32,
+    pub short_name: Option<String>,
+    pub long_name: Option<String>,
+    pub units: Option<String>,
+    pub is_derived: Option<bool>,
+    pub is_visible: Option<bool>,
+}
+
+/// One scan: its timestamp (ms since the epoch) and a value per channel, in the
+/// order of the channels.
+pub(crate) type Scan = (Option<i64>, Vec<Option<f64>>);
+
+/// Scans of an RSK file read between progress updates.
+const RSK_CHUNK_ROWS: usize = 50_000;
+
+/// Lowercased extension of `path`, or an empty string.
+fn file_extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// A single row of “processed” data combining multiple channel values.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProcessedDataRow {
+    // Required base fields
+    pub(crate) tstamp: Option<i64>,
+    // `tstamp` in ISO 8601, in UTC and in the vessel's local time
+    #[serde(default)]
+    pub(crate) time_utc: Option<String>,
+    #[serde(default)]
+    pub(crate) time_local: Option<String>,
+    pub(crate) depth: Option<f64>,
+    pub(crate) pressure: Option<f64>,
+    pub(crate) sea_pressure: Option<f64>,
+    pub(crate) temperature: Option<f64>,
+    pub(crate) chlorophyll_a: Option<f64>,
+    pub(crate) salinity: Option<f64>,
+    pub(crate) speed_of_sound: Option<f64>,
+    pub(crate) specific_conductivity: Option<f64>,
+    pub(crate) dissolved_oxygen: Option<f64>,
+    pub(crate) turbidity: Option<f64>,
+    pub(crate) ph: Option<f64>,
+    pub(crate) par: Option<f64>,
+
+    // Units for each channel
+    pub(crate) depth_unit: String,
+    pub(crate) pressure_unit: String,
+    pub(crate) sea_pressure_unit: String,
+    pub(crate) temperature_unit: String,
+    pub(crate) chlorophyll_a_unit: String,
+    pub(crate) salinity_unit: String,
+    pub(crate) speed_of_sound_unit: String,
+    pub(crate) specific_conductivity_unit: String,
+    pub(crate) dissolved_oxygen_unit: String,
+    pub(crate) turbidity_unit: String,
+    pub(crate) ph_unit: String,
+    pub(crate) par_unit: String,
+
+    // Channels not mapped to a field above, by name
+    #[serde(default)]
+    pub(crate) channels: BTreeMap<String, ChannelReading>,
+
+    // TEOS-10 variables derived from salinity, temperature and pressure: absolute
+    // salinity (g/kg), conservative temperature (°C), potential density anomaly
+    // (kg/m³) and Brunt-Väisälä frequency squared (1/s²)
+    #[serde(default)]
+    pub(crate) absolute_salinity: Option<f64>,
+    #[serde(default)]
+    pub(crate) conservative_temperature: Option<f64>,
+    #[serde(default)]
+    pub(crate) sigma0: Option<f64>,
+    #[serde(default)]
+    pub(crate) n_squared: Option<f64>,
+    // Whether `salinity` was logged or derived from conductivity
+    #[serde(default)]
+    pub(crate) salinity_source: Option<SalinitySource>,
+
+    // QARTOD flag of each tested channel's value
+    #[serde(default)]
+    pub(crate) flags: BTreeMap<CtdChannel, ValueFlag>,
+
+    // Values of recalibrated channels as the instrument's calibration gave them
+    #[serde(default)]
+    pub(crate) uncalibrated: BTreeMap<CtdChannel, f64>,
+
+    // Cast phase the row was recorded in, and its segment in `CTDReport::casts`
+    #[serde(default)]
+    pub(crate) cast_phase: Option<CastPhase>,
+    #[serde(default)]
+    pub(crate) cast_index: Option<usize>,
+    // Profile the row belongs to in `CTDReport::profiles`
+    #[serde(default)]
+    pub(crate) profile_index: Option<usize>,
+    // Why the row would have been dropped, for rows kept with
+    // `processing.keep_dropped_rows`
+    #[serde(default)]
+    pub(crate) dropped: Option<DropReason>,
+
+    // IDs for traceability
+    pub(crate) id: Uuid,
+    pub(crate) sample_id: String,
+    pub(crate) org_id: String,
+    pub(crate) user_id: String,
+    pub(crate) processed_data_id: String,
+}
+
+impl ProcessedDataRow {
+    /// The value of a mapped channel.
+    pub(crate) fn value(&self, channel: CtdChannel) -> Option<f64> {
+        match channel {
+            CtdChannel::Depth => self.depth,
+            CtdChannel::Pressure => self.pressure,
+            CtdChannel::SeaPressure => self.sea_pressure,
+            CtdChannel::Temperature => self.temperature,
+            CtdChannel::ChlorophyllA => self.chlorophyll_a,
+            CtdChannel::Salinity => self.salinity,
+            CtdChannel::SpeedOfSound => self.speed_of_sound,
+            CtdChannel::SpecificConductivity => self.specific_conductivity,
+            CtdChannel::DissolvedOxygen => self.dissolved_oxygen,
+            CtdChannel::Turbidity => self.turbidity,
+            CtdChannel::Ph => self.ph,
+            CtdChannel::Par => self.par,
+        }
+    }
+
+    /// The value of a mapped channel, for filters that work on any channel.
+    pub(crate) fn value_mut(&mut self, channel: CtdChannel) -> &mut Option<f64> {
+        match channel {
+            CtdChannel::Depth => &mut self.depth,
+            CtdChannel::Pressure => &mut self.pressure,
+            CtdChannel::SeaPressure => &mut self.sea_pressure,
+            CtdChannel::Temperature => &mut self.temperature,
+            CtdChannel::ChlorophyllA => &mut self.chlorophyll_a,
+            CtdChannel::Salinity => &mut self.salinity,
+            CtdChannel::SpeedOfSound => &mut self.speed_of_sound,
+            CtdChannel::SpecificConductivity => &mut self.specific_conductivity,
+            CtdChannel::DissolvedOxygen => &mut self.dissolved_oxygen,
+            CtdChannel::Turbidity => &mut self.turbidity,
+            CtdChannel::Ph => &mut self.ph,
+            CtdChannel::Par => &mut self.par,
+        }
+    }
+
+    /// The unit of a mapped channel, to change it.
+    pub(crate) fn unit_mut(&mut self, channel: CtdChannel) -> &mut String {
+        match channel {
+            CtdChannel::Depth => &mut self.depth_unit,
+            CtdChannel::Pressure => &mut self.pressure_unit,
+            CtdChannel::SeaPressure => &mut self.sea_pressure_unit,
+            CtdChannel::Temperature => &mut self.temperature_unit,
+            CtdChannel::ChlorophyllA => &mut self.chlorophyll_a_unit,
+            CtdChannel::Salinity => &mut self.salinity_unit,
+            CtdChannel::SpeedOfSound => &mut self.speed_of_sound_unit,
+            CtdChannel::SpecificConductivity => &mut self.specific_conductivity_unit,
+            CtdChannel::DissolvedOxygen => &mut self.dissolved_oxygen_unit,
+            CtdChannel::Turbidity => &mut self.turbidity_unit,
+            CtdChannel::Ph => &mut self.ph_unit,
+            CtdChannel::Par => &mut self.par_unit,
+        }
+    }
+
+    /// The unit of a mapped channel.
+    pub(crate) fn unit(&self, channel: CtdChannel) -> &str {
+        match channel {
+            CtdChannel::Depth => &self.depth_unit,
+            CtdChannel::Pressure => &self.pressure_unit,
+            CtdChannel::SeaPressure => &self.sea_pressure_unit,
+            CtdChannel::Temperature => &self.temperature_unit,
+            CtdChannel::ChlorophyllA => &self.chlorophyll_a_unit,
+            CtdChannel::Salinity => &self.salinity_unit,
+            CtdChannel::SpeedOfSound => &self.speed_of_sound_unit,
+            CtdChannel::SpecificConductivity => &self.specific_conductivity_unit,
+            CtdChannel::DissolvedOxygen => &self.dissolved_oxygen_unit,
+            CtdChannel::Turbidity => &self.turbidity_unit,
+            CtdChannel::Ph => &self.ph_unit,
+            CtdChannel::Par => &self.par_unit,
+        }
+    }
+}
+
+/// Names the channels by long name, then short name, then column; a name already
+/// taken gets the short name appended, so two sensors of the same kind stay apart.
+fn channel_names(channels: &[Channel]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(channels.len());
+    for ch in channels {
+        let short = ch
+            .short_name
+            .clone()
+            .unwrap_or_else(|| format!("channel{:02}", ch.channel_id));
+        let mut name = ch
+            .long_name
+            .as_deref()
+            .map(|ln| ln.trim().to_string())
+            .filter(|ln| !ln.is_empty())
+            .unwrap_or_else(|| short.clone());
+        if names.contains(&name) {
+            name = format!("{} ({})", name, short);
+        }
+        names.push(name);
+    }
+    names
+}
+
+/// Picks the instrument channel of each `CtdChannel`: `mappings` are tried before the
+/// default table, and within a mapping the names in order. Returns each channel's
+/// position in `channels` and its unit.
+fn resolve_channels(
+    channels: &[Channel],
+    mappings: &[ChannelMapping],
+) -> HashMap<CtdChannel, (usize, String)> {
+    let defaults = default_channel_mappings();
+    let mut resolved = HashMap::new();
+    for mapping in mappings.iter().chain(&defaults) {
+        if resolved.contains_key(&mapping.channel) {
+            continue;
+        }
+        let matches = |field: &Option<String>, name: &str| {
+            field
+                .as_deref()
+                .is_some_and(|f| f.trim().eq_ignore_ascii_case(name.trim()))
+        };
+        let found = mapping.names.iter().find_map(|name| {
+            channels
+                .iter()
+                .position(|ch| matches(&ch.long_name, name) || matches(&ch.short_name, name))
+        });
+        if let Some(pos) = found {
+            let unit = channels[pos].units.clone().unwrap_or_default();
+            resolved.insert(mapping.channel, (pos, unit));
+        }
+    }
+    resolved
+}
+
+/// Time ranges of the profiles an RSK file marks in its `region` table, in time order;
+/// empty when it marks none (or predates regions).
+fn read_rsk_profiles(file_path: &str) -> Result<Vec<(i64, i64)>, PoleshiftError> {
+    let db_connection =
+        Connection::open(file_path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+    let has_regions: bool = db_connection
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'region'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    if !has_regions {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = db_connection
+        .prepare("SELECT tstamp1, tstamp2 FROM region WHERE type = 'PROFILE' ORDER BY tstamp1")
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let profiles = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    Ok(profiles)
+}
+
+/// ISO 8601 forms of a timestamp: in UTC, and at `utc_offset_minutes` when given.
+fn display_times(
+    tstamp: Option<i64>,
+    utc_offset_minutes: Option<i32>,
+) -> (Option<String>, Option<String>) {
+    let Some(tstamp) = tstamp else {
+        return (None, None);
+    };
+    (
+        Some(format_iso8601(tstamp, 0)),
+        utc_offset_minutes.map(|offset| format_iso8601(tstamp, offset)),
+    )
+}
+
+/// Reads the channels of an RBR RSK file (an SQLite database) and every scan of them.
+fn read_rsk(
+    file_path: &str,
+    window: &Window,
+    job: &JobHandle,
+) -> Result<(Vec<Channel>, Vec<Scan>), PoleshiftError> {
+    // -----------------------------------------------------------------------
+    // 1. Query DB for channels & channel data
+    // -----------------------------------------------------------------------
+    let channels = {
+        let db_connection =
+            Connection::open(file_path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+
+        // 1a. Get channel metadata
+        let mut stmt = db_connection
+            .prepare(
+                "SELECT channelID, shortName, longName, units, isDerived, isVisible
+                 FROM Channels",
+            )
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+        let channels: Vec<Channel> = stmt
+            .query_map([], |row| {
+                Ok(Channel {
+                    channel_id: row.get(0)?,
+                    short_name: row.get(1)?,
+                    long_name: row.get(2)?,
+                    units: row.get(3)?,
+                    is_derived: row.get(4)?,
+                    is_visible: row.get(5)?,
+                })
+            })
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+        job.report_progress(window, 20, "Reading channel metadata...", "processing")?;
+
+        channels
+    };
+
+    // -----------------------------------------------------------------------
+    // 1b. Read data from "data" table. We'll just read *all columns* via a
+    //     dynamic query or each column we care about. For demonstration,
+    //     we read them all and then pick out the columns we have channel IDs for.
+    // -----------------------------------------------------------------------
+    //   Example approach: "SELECT tstamp, channel01, channel02, ..."
+    //   because we want them all in a single pass. However, if your DB
+    //   has many channels or naming patterns, you can do multiple queries.
+
+    let db_connection =
+        Connection::open(file_path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+
+    // Build a dynamic list of columns to select. We'll always select "tstamp"
+    // but also select "channelNN" for each channelID from 1..=some_max.
+    // For safety, you might only do so for channels that exist.
+    // This is a minimal example:
+    let mut columns = vec!["tstamp".to_owned()];
+    for ch in &channels {
+        columns.push(format!("\"channel{:02}\"", ch.channel_id));
+    }
+    let columns_joined = columns.join(", ");
+
+    // Moored deployments hold millions of scans, so they are streamed off a cursor in
+    // time order (sorting them afterwards is then cheap) with progress every chunk.
+    let total: i64 = db_connection
+        .query_row("SELECT COUNT(*) FROM data", [], |row| row.get(0))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    job.set_reads_total(total.max(0) as u64);
+    // SELECT tstamp, "channel01", "channel02", ... FROM data ORDER BY tstamp
+    let query = format!("SELECT {columns_joined} FROM data ORDER BY tstamp");
+    let mut stmt = db_connection
+        .prepare(&query)
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let mut rows = stmt
+        .query([])
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    let mut all_data: Vec<Scan> = Vec::with_capacity(total.max(0) as usize);
+    while let Some(row) = rows
+        .next()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+    {
+        // The first column is tstamp, then one Option<f64> per channel
+        let tstamp_val = row
+            .get::<_, Option<i64>>(0)
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        let channel_values = (1..=channels.len())
+            .map(|idx| row.get::<_, Option<f64>>(idx))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        all_data.push((tstamp_val, channel_values));
+
+        if all_data.len().is_multiple_of(RSK_CHUNK_ROWS) {
+            job.check_cancelled()?;
+            job.add_reads(RSK_CHUNK_ROWS as u64);
+            let progress = 20 + (10 * all_data.len() as i64 / total.max(1)).min(9) as u8;
+            job.report_progress(
+                window,
+                progress,
+                &format!("Reading scans ({} of {})...", all_data.len(), total),
+                "processing",
+            )?;
+        }
+    }
+
+    Ok((channels, all_data))
+}
+
+// ---------------------------------------------------------------------------
+// Main command
+// ---------------------------------------------------------------------------
+
+/// Reads a CTD file into raw rows and processed rows. The format is
+/// picked by extension: Sea-Bird `.cnv` (see `read_cnv`), delimited `.csv`, `.tsv` and
+/// `.txt` exports read as `delimited` says (see `read_delimited`), and RBR RSK files
+/// otherwise.
+///
+/// Channels are picked by name through a channel-mapping table; the defaults cover
+/// depth, pressure, sea pressure, temperature, chlorophyll a, salinity, speed of
+/// sound, specific conductivity, dissolved oxygen, turbidity, pH and PAR.
+/// `channel_mappings`, and the column mapping of `delimited`, are tried before the
+/// defaults, so instruments that name a channel differently need no code change.
+/// Channels the instrument did not log are left empty.
+///
+/// Channels that map to none of these (e.g. a phycoerythrin fluorometer) are kept in
+/// each row's `channels`, by name with their unit, and the report's `channels` lists
+/// everything the instrument measured. For RSK files the report's `instrument` holds
+/// the serial number, firmware, calibrations and deployments (see `read_rsk_metadata`),
+/// so the provenance of each profile is saved with it.
+///
+/// Every row's `tstamp` is in milliseconds since the Unix epoch, UTC, whatever the
+/// format (RSK files holding seconds are scaled by their deployment epochs). Rows
+/// also carry it as ISO 8601 in `time_utc`, and in the vessel's local time in
+/// `time_local` when `processing.utc_offset_minutes` is given.
+///
+/// The cast is split into soak, downcasts, upcasts and holds by its depth rate (see
+/// `segment_cast`), reported as `casts`. Processed rows are the downcasts by default;
+/// `processing.cast.keep` picks upcasts or both instead, and each row carries its
+/// `cast_phase`. Before that, `processing.qc` can despike channels, align them in
+/// time and correct conductivity for cell thermal mass (see `apply_qc`); the report's
+/// `qc` lists each filter run and the values it changed. With `processing.binning`
+/// the processed rows are also averaged into depth or pressure bins, returned as
+/// `binned_data` (see `bin_rows`).
+///
+/// `processing.calibrations` replaces the instrument's calibration of channels, e.g.
+/// chlorophyll calibrated against extracted samples, in the processed rows; each
+/// keeps the value it replaced in `uncalibrated` (see `recalibrate`), and the raw rows
+/// are left as logged.
+///
+/// When the file has no salinity channel, processed rows get practical salinity
+/// derived from conductivity, temperature and pressure, marked by `salinity_source`
+/// (see `derive_practical_salinity`).
+///
+/// Processed and binned rows carry TEOS-10 absolute salinity, conservative
+/// temperature, sigma0 and N² (see `derive_teos10`); `processing.latitude` sets the
+/// gravity N² uses. Processed values are QARTOD-flagged per channel rather than
+/// dropped (see `flag_rows`), with `processing.flags` setting the tests and any
+/// overrides.
+///
+/// Long deployments can hold more rows than the UI should receive: with
+/// `persist_outputs` every raw, processed and binned row is written to a local store
+/// (see `persist_ctd_outputs`) and the report carries at most `preview_rows` of each
+/// (2000 by default), read in full with `query_ctd_raw_data` and
+/// `query_ctd_processed_data`.
+///
+/// The run is registered with the job manager (as `job_id` when given) with the rows
+/// read and built so far, emitted on `job://{job_id}/progress` while reading and
+/// building them, and stops at the next chunk or stage once `cancel_job` is called.
+///
+/// With `processing.mode` set to moored, e.g. for an RBR logger on a mooring, rows are
+/// not filtered by cast phase or depth and no profiles are found; the report's
+/// `time_series` holds hourly and daily means, minima and maxima and the gaps in the
+/// record instead (see `moored_series`), and binning is skipped.
+///
+/// A file holding several casts, e.g. a day of stations, is split into profiles:
+/// those an RSK file marks in its regions, else one per downcast starting near the
+/// surface (see `profile_ranges`), reported as `profiles` with their time ranges.
+/// `profile_samples` assigns a profile's raw and processed rows to another sample;
+/// rows of unassigned profiles stay with `sample_id`.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command(rename_all = "snake_case")]
+pub async fn handle_ctd_data(
+    invocation: Invocation,
+    app_handle: AppHandle,
+    sample_id: String,
+    org_id: String,
+    user_id: String,
+    raw_data_id: String,
+    processed_data_id: String,
+    file_paths: Vec<String>,
+    channel_mappings: Option<Vec<ChannelMapping>>,
+    delimited: Option<DelimitedOptions>,
+    processing: Option<CtdProcessingOptions>,
+    persist_outputs: Option<bool>,
+    preview_rows: Option<usize>,
+    profile_samples: Option<Vec<ProfileSample>>,
+    job_id: Option<String>,
+) -> Result<StandardResponseNoFiles<CTDReport>, PoleshiftError> {
+    let args = CtdRunArgs {
+        sample_id,
+        org_id,
+        user_id,
+        raw_data_id,
+        processed_data_id,
+        file_paths,
+        channel_mappings: channel_mappings.unwrap_or_default(),
+        delimited: delimited.unwrap_or_default(),
+        processing: processing.unwrap_or_default(),
+        persist_outputs: persist_outputs.unwrap_or(false),
+        preview_rows: preview_rows.unwrap_or(DEFAULT_PREVIEW_ROWS),
+        profile_samples: profile_samples.unwrap_or_default(),
+    };
+    let trace_handle = app_handle.clone();
+    let jobs = trace_handle.state::<JobManager>();
+    let job = jobs.start("ctd", job_id)?;
+    let result = traced(
+        invocation,
+        Some(job.id()),
+        process_ctd_data(app_handle, args, job.clone()),
+    )
+    .await;
+    jobs.finish(job.id(), result.as_ref().err());
+    result
+}
+
+/// Arguments of a `handle_ctd_data` invocation with their defaults applied, and of
+/// each file of a `handle_ctd_batch`.
+#[derive(Debug)]
+pub(crate) struct CtdRunArgs {
+    pub sample_id: String,
+    pub org_id: String,
+    pub user_id: String,
+    pub raw_data_id: String,
+    pub processed_data_id: String,
+    pub file_paths: Vec<String>,
+    pub channel_mappings: Vec<ChannelMapping>,
+    pub delimited: DelimitedOptions,
+    pub processing: CtdProcessingOptions,
+    pub persist_outputs: bool,
+    pub preview_rows: usize,
+    pub profile_samples: Vec<ProfileSample>,
+}
+
+/// Processes a CTD file as `handle_ctd_data` describes, on a blocking thread: reading
+/// and processing a long deployment takes a while and must not hold up the async
+/// runtime.
+pub(crate) async fn process_ctd_data(
+    app_handle: AppHandle,
+    args: CtdRunArgs,
+    job: JobHandle,
+) -> Result<StandardResponseNoFiles<CTDReport>, PoleshiftError> {
+    tauri::async_runtime::spawn_blocking(move || process_ctd_file(app_handle, args, job))
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("CTD processing task failed: {}", e)))?
+}
+
+/// `report` as the results store keeps it at `detail`: a summary leaves out the rows.
+fn stored_report(
+    report: &CTDReport,
+    detail: ResultDetail,
+) -> Result<serde_json::Value, PoleshiftError> {
+    let mut stored = serde_json::to_value(report)?;
+    if let (ResultDetail::Summary, Some(fields)) = (detail, stored.as_object_mut()) {
+        for rows in ["raw_data", "processed_data", "binned_data"] {
+            fields.remove(rows);
+        }
+    }
+    Ok(stored)
+}
+
+fn process_ctd_file(
+    app_handle: AppHandle,
+    args: CtdRunArgs,
+    job: JobHandle,
+) -> Result<StandardResponseNoFiles<CTDReport>, PoleshiftError> {
+    let CtdRunArgs {
+        sample_id,
+        org_id,
+        user_id,
+        raw_data_id,
+        processed_data_id,
+        file_paths,
+        channel_mappings,
+        delimited,
+        processing,
+        persist_outputs,
+        preview_rows,
+        profile_samples,
+    } = args;
+
+    // 1. Basic checks
+    if file_paths.is_empty() {
+        return Err(PoleshiftError::NoFiles);
+    }
+    if let Some(mapping) = channel_mappings.iter().find(|m| m.names.is_empty()) {
+        return Err(PoleshiftError::InvalidInput {
+            field: "channel_mappings".to_string(),
+            reason: format!("the mapping of {:?} names no channel", mapping.channel),
+        });
+    }
+    processing.validate()?;
+    let file_path = &file_paths[0];
+
+    // Get the main window so we can emit progress updates.
+    let window = app_handle
+        .get_window("main")
+        .ok_or_else(|| PoleshiftError::WindowNotFound)?;
+
+    job.report_progress(&window, 10, "Opening CTD file...", "processing")?;
+    job.set_stage("reading");
+
+    // -----------------------------------------------------------------------
+    // 2. Read the channels and their scans, by file format
+    // -----------------------------------------------------------------------
+    let mut channel_mappings = channel_mappings;
+    let mut region_profiles = Vec::new();
+    let mut instrument = None;
+    let (channels, all_data) = match file_extension(file_path).as_str() {
+        "cnv" => seabird::read_cnv(Path::new(file_path))?,
+        "csv" | "tsv" | "txt" => {
+            channel_mappings.splice(0..0, delimited.channel_mappings());
+            delimited::read_delimited(Path::new(file_path), &delimited)?
+        }
+        "hex" => {
+            return Err(PoleshiftError::DataError(
+                "Sea-Bird .hex files hold raw counts; convert them to .cnv with SBE Data \
+                 Processing first"
+                    .to_string(),
+            ))
+        }
+        _ => {
+            region_profiles = read_rsk_profiles(file_path)?;
+            let metadata = read_rsk_metadata(file_path)?;
+            let (channels, mut scans) = read_rsk(file_path, &window, &job)?;
+            let scale = metadata.tstamp_scale(scans.first().and_then(|(tstamp, _)| *tstamp));
+            if scale != 1 {
+                for (tstamp, _) in &mut scans {
+                    *tstamp = tstamp.map(|t| t * scale);
+                }
+                for (start, end) in &mut region_profiles {
+                    (*start, *end) = (*start * scale, *end * scale);
+                }
+            }
+            instrument = Some(metadata);
+            (channels, scans)
+        }
+    };
+
+    // Pick the channels of our RawDataRow / ProcessedDataRow by the mapping table;
+    // the rest are carried by name in the rows' `channels`
+    let resolved = resolve_channels(&channels, &channel_mappings);
+    let unit_of = |channel: CtdChannel| {
+        resolved
+            .get(&channel)
+            .map(|(_, unit)| unit.clone())
+            .unwrap_or_default()
+    };
+    let measured: Vec<MeasuredChannel> = channel_names(&channels)
+        .into_iter()
+        .zip(&channels)
+        .enumerate()
+        .map(|(pos, (name, ch))| MeasuredChannel {
+            name,
+            unit: ch.units.clone().unwrap_or_default(),
+            mapped_to: resolved
+                .iter()
+                .find(|(_, (mapped, _))| *mapped == pos)
+                .map(|(channel, _)| *channel),
+            is_derived: ch.is_derived.unwrap_or(false),
+        })
+        .collect();
+
+    job.report_progress(&window, 30, "Reading raw measurements...", "processing")?;
+    job.check_cancelled()?;
+    job.set_stage("building rows");
+    job.set_reads(0);
+    job.set_reads_total(all_data.len() as u64);
+
+    // -----------------------------------------------------------------------
+    // 3. Build RAW data rows
+    //    We'll combine the channels we specifically care about (depth, pressure, etc.)
+    //    into a RawDataRow for each tstamp.
+    // -----------------------------------------------------------------------
+    let mut raw_rows: Vec<RawDataRow> = Vec::new();
+
+    for (scan_index, (maybe_ts, channel_vals)) in all_data.iter().enumerate() {
+        if (scan_index + 1).is_multiple_of(RSK_CHUNK_ROWS) {
+            job.check_cancelled()?;
+            job.set_reads(scan_index as u64 + 1);
+            job.emit_progress(&window)?;
+        }
+        // maybe_ts is Option<i64>; if it's None, skip or handle as you like
+        if let Some(ts) = maybe_ts {
+            // channel_vals is in the same order as "channels"
+            // so channel_vals[i] corresponds to channels[i].
+            // We want specific channels (depth, pressure, etc.)
+
+            // Helper function: the value of a mapped channel, if it was found
+            let value_of = |channel: CtdChannel| -> Option<f64> {
+                let (pos, _) = resolved.get(&channel)?;
+                channel_vals[*pos]
+            };
+            let extra_channels = measured
+                .iter()
+                .zip(channel_vals)
+                .filter(|(ch, _)| ch.mapped_to.is_none())
+                .map(|(ch, value)| {
+                    let reading = ChannelReading {
+                        value: *value,
+                        unit: ch.unit.clone(),
+                    };
+                    (ch.name.clone(), reading)
+                })
+                .collect();
+            let new_id = Uuid::new_v4(); // generate a fresh UUID here
+            let (time_utc, time_local) = display_times(Some(*ts), processing.utc_offset_minutes);
+            raw_rows.push(RawDataRow {
+                tstamp: Some(*ts),
+                time_utc,
+                time_local,
+                depth: value_of(CtdChannel::Depth),
+                pressure: value_of(CtdChannel::Pressure),
+                sea_pressure: value_of(CtdChannel::SeaPressure),
+                temperature: value_of(CtdChannel::Temperature),
+                chlorophyll_a: value_of(CtdChannel::ChlorophyllA),
+                salinity: value_of(CtdChannel::Salinity),
+                speed_of_sound: value_of(CtdChannel::SpeedOfSound),
+                specific_conductivity: value_of(CtdChannel::SpecificConductivity),
+                dissolved_oxygen: value_of(CtdChannel::DissolvedOxygen),
+                turbidity: value_of(CtdChannel::Turbidity),
+                ph: value_of(CtdChannel::Ph),
+                par: value_of(CtdChannel::Par),
+
+                depth_unit: unit_of(CtdChannel::Depth),
+                pressure_unit: unit_of(CtdChannel::Pressure),
+                sea_pressure_unit: unit_of(CtdChannel::SeaPressure),
+                temperature_unit: unit_of(CtdChannel::Temperature),
+                chlorophyll_a_unit: unit_of(CtdChannel::ChlorophyllA),
+                salinity_unit: unit_of(CtdChannel::Salinity),
+                speed_of_sound_unit: unit_of(CtdChannel::SpeedOfSound),
+                specific_conductivity_unit: unit_of(CtdChannel::SpecificConductivity),
+                dissolved_oxygen_unit: unit_of(CtdChannel::DissolvedOxygen),
+                turbidity_unit: unit_of(CtdChannel::Turbidity),
+                ph_unit: unit_of(CtdChannel::Ph),
+                par_unit: unit_of(CtdChannel::Par),
+
+                channels: extra_channels,
+
+                id: new_id,
+                sample_id: sample_id.clone(),
+                org_id: org_id.clone(),
+                user_id: user_id.clone(),
+                raw_data_id: raw_data_id.clone(),
+            });
+        }
+    }
+
+    // Sort raw data by ascending timestamp
+    raw_rows.sort_by_key(|r| r.tstamp);
+    job.set_reads(all_data.len() as u64);
+    job.report_progress(&window, 40, "Detecting cast phases...", "processing")?;
+    job.check_cancelled()?;
+    job.set_stage("processing");
+    job.emit_progress(&window)?;
+
+    // -----------------------------------------------------------------------
+    // 4. Now build PROCESSED data rows from the cast phases that are kept
+    // -----------------------------------------------------------------------
+    // We'll clone from raw_rows into processed_rows, then segment the cast:
+    let processed_rows: Vec<ProcessedDataRow> = raw_rows
+        .iter()
+        .map(|rr| {
+            let new_id = Uuid::new_v4(); // generate a fresh UUID here
+            println!("Processed data id end: {}", processed_data_id.clone());
+            ProcessedDataRow {
+                tstamp: rr.tstamp,
+                time_utc: rr.time_utc.clone(),
+                time_local: rr.time_local.clone(),
+                depth: rr.depth,
+                pressure: rr.pressure,
+                sea_pressure: rr.sea_pressure,
+                temperature: rr.temperature,
+                chlorophyll_a: rr.chlorophyll_a,
+                salinity: rr.salinity,
+                speed_of_sound: rr.speed_of_sound,
+                specific_conductivity: rr.specific_conductivity,
+                dissolved_oxygen: rr.dissolved_oxygen,
+                turbidity: rr.turbidity,
+                ph: rr.ph,
+                par: rr.par,
+
+                depth_unit: rr.depth_unit.clone(),
+                pressure_unit: rr.pressure_unit.clone(),
+                sea_pressure_unit: rr.sea_pressure_unit.clone(),
+                temperature_unit: rr.temperature_unit.clone(),
+                chlorophyll_a_unit: rr.chlorophyll_a_unit.clone(),
+                salinity_unit: rr.salinity_unit.clone(),
+                speed_of_sound_unit: rr.speed_of_sound_unit.clone(),
+                specific_conductivity_unit: rr.specific_conductivity_unit.clone(),
+                dissolved_oxygen_unit: rr.dissolved_oxygen_unit.clone(),
+                turbidity_unit: rr.turbidity_unit.clone(),
+                ph_unit: rr.ph_unit.clone(),
+                par_unit: rr.par_unit.clone(),
+
+                channels: rr.channels.clone(),
+
+                absolute_salinity: None,
+                conservative_temperature: None,
+                sigma0: None,
+                n_squared: None,
+                salinity_source: rr.salinity.map(|_| SalinitySource::Instrument),
+
+                flags: BTreeMap::new(),
+                uncalibrated: BTreeMap::new(),
+
+                cast_phase: None,
+                cast_index: None,
+                profile_index: None,
+                dropped: None,
+
+                id: new_id,
+                sample_id: rr.sample_id.clone(),
+                org_id: rr.org_id.clone(),
+                user_id: rr.user_id.clone(),
+                processed_data_id: processed_data_id.clone(),
+            }
+        })
+        .collect();
+
+    // We already sorted raw_rows by tstamp, so processed_rows is also sorted;
+    // rows without a depth cannot be placed in the cast. New calibrations apply to
+    // every row
+    let mut processed_rows = processed_rows;
+    recalibrate(&mut processed_rows, &processing.calibrations);
+    let mut summary = ProcessingSummary {
+        rows_in: all_data.len(),
+        ..ProcessingSummary::default()
+    };
+    // A moored logger stays at one depth, so its rows are kept whatever their depth
+    let moored = processing.mode == ProcessingMode::Moored;
+    let mut dropped_rows: Vec<ProcessedDataRow> = Vec::new();
+    let mut located: Vec<ProcessedDataRow> = Vec::with_capacity(processed_rows.len());
+    for mut row in processed_rows {
+        if moored || row.depth.is_some() {
+            located.push(row);
+        } else {
+            row.dropped = Some(DropReason::NoDepth);
+            dropped_rows.push(row);
+        }
+    }
+
+    // Then despiking and sensor corrections, on the whole time series, and salinity
+    // from the corrected conductivity where the file has none
+    let mut qc = apply_qc(&mut located, &processing.qc)?;
+    derive_practical_salinity(&mut located, processing.latitude);
+    let samples: Vec<(i64, f64)> = located
+        .iter()
+        .map(|row| {
+            (
+                row.tstamp.unwrap_or_default(),
+                row.depth.unwrap_or_default(),
+            )
+        })
+        .collect();
+    let cast_options = &processing.cast;
+    let (casts, sample_casts) = if moored {
+        (Vec::new(), Vec::new())
+    } else {
+        segment_cast(&samples, cast_options)
+    };
+
+    // Separate profiles, from the file's regions or else the cast segments, and the
+    // sample each one's rows go to
+    let (ranges, source) = if moored {
+        (Vec::new(), ProfileSource::Depth)
+    } else if region_profiles.is_empty() {
+        (
+            profile_ranges(&casts, cast_options.surface_depth),
+            ProfileSource::Depth,
+        )
+    } else {
+        (region_profiles, ProfileSource::Region)
+    };
+    let mut profile_sample_ids = vec![sample_id.clone(); ranges.len()];
+    for assignment in &profile_samples {
+        let Some(profile_sample_id) = profile_sample_ids.get_mut(assignment.profile) else {
+            return Err(PoleshiftError::InvalidInput {
+                field: "profile_samples".to_string(),
+                reason: format!(
+                    "there is no profile {}; the file holds {}",
+                    assignment.profile,
+                    ranges.len()
+                ),
+            });
+        };
+        *profile_sample_id = assignment.sample_id.clone();
+    }
+
+    // Rows of the kept phases below `min_depth`, or every row of a moored logger; the
+    // rest are set aside with the reason they were dropped
+    let mut cast_filtered: Vec<ProcessedDataRow> = if moored {
+        std::mem::take(&mut located)
+    } else {
+        Vec::with_capacity(located.len())
+    };
+    for (mut row, cast) in located.into_iter().zip(sample_casts) {
+        let phase = casts[cast].phase;
+        row.cast_phase = Some(phase);
+        row.cast_index = Some(cast);
+        row.profile_index = profile_at(&ranges, row.tstamp.unwrap_or_default());
+        if let Some(profile) = row.profile_index {
+            row.sample_id = profile_sample_ids[profile].clone();
+        }
+        if !cast_options.keep.keeps(phase) {
+            row.dropped = Some(DropReason::phase(phase));
+        } else if !row
+            .depth
+            .is_some_and(|depth| depth > cast_options.min_depth)
+        {
+            row.dropped = Some(DropReason::AboveMinDepth);
+        }
+        if row.dropped.is_some() {
+            dropped_rows.push(row);
+        } else {
+            cast_filtered.push(row);
+        }
+    }
+    for row in &mut raw_rows {
+        if let Some(profile) = profile_at(&ranges, row.tstamp.unwrap_or_default()) {
+            row.sample_id = profile_sample_ids[profile].clone();
+        }
+    }
+    let profiles: Vec<CastProfile> = ranges
+        .iter()
+        .zip(profile_sample_ids)
+        .enumerate()
+        .map(|(index, (&(start_tstamp, end_tstamp), sample_id))| {
+            let rows = cast_filtered
+                .iter()
+                .filter(|row| row.profile_index == Some(index));
+            CastProfile {
+                start_tstamp,
+                end_tstamp,
+                max_depth: rows.clone().filter_map(|row| row.depth).reduce(f64::max),
+                samples: rows.count(),
+                source,
+                sample_id,
+            }
+        })
+        .collect();
+    qc.extend(correct_chlorophyll(
+        &mut cast_filtered,
+        &processing.chlorophyll,
+        processing.utc_offset_minutes,
+    )?);
+    derive_teos10(&mut cast_filtered, processing.latitude);
+    if moored {
+        // N² needs a vertical gradient, which a logger at one depth does not see
+        for row in &mut cast_filtered {
+            row.n_squared = None;
+        }
+    }
+    flag_rows(&mut cast_filtered, &processing.flags);
+    let time_series = moored.then(|| {
+        moored_series(
+            &cast_filtered,
+            &processing.mooring,
+            processing.utc_offset_minutes,
+        )
+    });
+
+    // Binned profiles, one per kept cast, when asked for; their TEOS-10 columns are
+    // derived again from the binned values
+    let binned_data = match &processing.binning {
+        Some(binning) if !moored => {
+            job.report_progress(&window, 45, "Binning profiles...", "processing")?;
+            job.check_cancelled()?;
+            job.set_stage("binning");
+            let mut binned = bin_rows(&cast_filtered, binning);
+            derive_teos10(&mut binned, processing.latitude);
+            for row in &mut binned {
+                (row.time_utc, row.time_local) =
+                    display_times(row.tstamp, processing.utc_offset_minutes);
+            }
+            binned
+        }
+        _ => Vec::new(),
+    };
+
+    // What became of every scan; dropped rows go back in time order when asked for,
+    // without derived columns or flags
+    let no_timestamp = all_data.len() - raw_rows.len();
+    if no_timestamp > 0 {
+        summary
+            .dropped
+            .insert(DropReason::NoTimestamp, no_timestamp);
+    }
+    for row in &dropped_rows {
+        if let Some(reason) = row.dropped {
+            *summary.dropped.entry(reason).or_default() += 1;
+        }
+    }
+    summary.rows_kept = cast_filtered.len();
+    if processing.keep_dropped_rows {
+        cast_filtered.append(&mut dropped_rows);
+        cast_filtered.sort_by_key(|row| row.tstamp);
+    }
+
+    // -----------------------------------------------------------------------
+    // 5. Build and return the final CTDReport
+    // -----------------------------------------------------------------------
+    let (raw_data, processed_data, binned_data, store) = if persist_outputs {
+        job.report_progress(&window, 48, "Storing full-resolution rows...", "processing")?;
+        job.check_cancelled()?;
+        job.set_stage("storing");
+        let store = persist_ctd_outputs(
+            &ctd_store_path(&app_handle, &processed_data_id)?,
+            &raw_rows,
+            &cast_filtered,
+            &binned_data,
+        )?;
+        (
+            downsample(&raw_rows, preview_rows),
+            downsample(&cast_filtered, preview_rows),
+            downsample(&binned_data, preview_rows),
+            Some(store),
+        )
+    } else {
+        (raw_rows, cast_filtered, binned_data, None)
+    };
+    let report = CTDReport {
+        raw_data,
+        processed_data,
+        binned_data,
+        channels: measured,
+        instrument,
+        casts,
+        profiles,
+        qc,
+        summary,
+        time_series,
+        store,
+    };
+
+    // 6. Keep the report in the local results store; a failed save is only logged
+    let keys = ResultKeys {
+        processed_data_id: processed_data_id.clone(),
+        kind: ResultKind::Ctd,
+        sample_id,
+        org_id: Some(org_id),
+        user_id: Some(user_id),
+        raw_data_id: Some(raw_data_id),
+    };
+    let detail = current_settings(&app_handle).stored_result_detail;
+    let saved = stored_report(&report, detail)
+        .and_then(|stored| save_result(&results_store_path(&app_handle)?, &keys, &stored, None));
+    if let Err(e) = saved {
+        println!("Could not store result {}: {}", processed_data_id, e);
+    }
+
+    job.report_progress(&window, 50, "Processing complete...", "processing")?;
+
+    Ok(StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report,
+    })
+}
@@ -0,0 +1,70 @@
+pub mod batch;
+pub mod binning;
+pub mod bottles;
+pub mod calibration;
+pub mod cast;
+pub mod chlorophyll;
+pub mod delimited;
+pub mod export;
+pub mod flags;
+pub mod handle_ctd_data;
+pub mod mooring;
+pub mod options;
+pub mod output_store;
+pub mod qc;
+pub mod rsk_metadata;
+pub mod sample_match;
+pub(crate) mod seabird;
+pub mod section;
+pub(crate) mod teos10;
+
+/// Seconds since the Unix epoch of a civil UTC date (Hinnant's days-from-civil).
+pub(crate) fn epoch_secs(year: i64, month: i64, day: i64, secs_of_day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era - 719_468) * 86_400 + secs_of_day
+}
+
+/// Civil UTC date and seconds of the day of seconds since the Unix epoch (Hinnant's
+/// civil-from-days).
+pub(crate) fn civil_from_epoch_secs(secs: i64) -> (i64, i64, i64, i64) {
+    let days = secs.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day, secs.rem_euclid(86_400))
+}
+
+/// ISO 8601 form of a timestamp in ms since the Unix epoch, at `offset_minutes` from
+/// UTC: `2023-06-10T14:02:11.250Z`, or `2023-06-11T03:02:11.250+13:00` with an offset.
+pub(crate) fn format_iso8601(tstamp_ms: i64, offset_minutes: i32) -> String {
+    let local_ms = tstamp_ms + i64::from(offset_minutes) * 60_000;
+    let (year, month, day, secs_of_day) = civil_from_epoch_secs(local_ms.div_euclid(1000));
+    let offset = if offset_minutes == 0 {
+        "Z".to_string()
+    } else {
+        let sign = if offset_minutes < 0 { '-' } else { '+' };
+        let minutes = offset_minutes.unsigned_abs();
+        format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}{}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        local_ms.rem_euclid(1000),
+        offset
+    )
+}
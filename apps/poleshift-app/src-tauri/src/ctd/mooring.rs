@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::export::present_variables;
+use crate::ctd::flags::QartodFlag;
+use crate::ctd::format_iso8601;
+use crate::ctd::handle_ctd_data::ProcessedDataRow;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// What the instrument was deployed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingMode {
+    /// Profiles through the water column, split into cast phases
+    #[default]
+    Profile,
+    /// A logger at a fixed depth, e.g. on a mooring: every row is kept and summarized
+    /// in time rather than depth
+    Moored,
+}
+
+/// Length of the periods a moored series is averaged over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AveragingInterval {
+    Hourly,
+    Daily,
+}
+
+impl AveragingInterval {
+    fn millis(self) -> i64 {
+        match self {
+            AveragingInterval::Hourly => 3_600_000,
+            AveragingInterval::Daily => 86_400_000,
+        }
+    }
+}
+
+/// Time-series products of a moored deployment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MooredOptions {
+    pub intervals: Vec<AveragingInterval>,
+    /// Spacing between consecutive samples taken as a gap, in seconds; three sample
+    /// intervals when not given
+    pub max_gap_secs: Option<f64>,
+}
+
+impl Default for MooredOptions {
+    fn default() -> Self {
+        MooredOptions {
+            intervals: vec![AveragingInterval::Hourly, AveragingInterval::Daily],
+            max_gap_secs: None,
+        }
+    }
+}
+
+impl MooredOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if self
+            .max_gap_secs
+            .is_some_and(|gap| !(gap.is_finite() && gap > 0.0))
+        {
+            return Err(PoleshiftError::InvalidInput {
+                field: "mooring.max_gap_secs".to_string(),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Statistics of one variable over a period.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodStats {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub count: usize,
+}
+
+/// One averaging period.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeSeriesPeriod {
+    /// Start of the period, in ms since the Unix epoch, and in ISO 8601 UTC
+    pub start_tstamp: i64,
+    pub time_utc: String,
+    /// Rows in the period
+    pub rows: usize,
+    /// By export name; failed values are left out
+    pub values: BTreeMap<String, PeriodStats>,
+}
+
+/// A series averaged over one interval.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeSeriesProduct {
+    pub interval: AveragingInterval,
+    pub periods: Vec<TimeSeriesPeriod>,
+}
+
+/// A stretch with no samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeGap {
+    /// Last sample before the gap and first after it, in ms since the Unix epoch
+    pub start_tstamp: i64,
+    pub end_tstamp: i64,
+    pub duration_secs: f64,
+    /// Samples the usual interval would have taken in the gap
+    pub missing_samples: usize,
+}
+
+/// Summary of a moored deployment over time.
+#[derive(Debug, Clone, Serialize)]
+pub struct MooredSeries {
+    pub start_tstamp: Option<i64>,
+    pub end_tstamp: Option<i64>,
+    /// Median spacing of the samples, in seconds
+    pub sample_interval_secs: Option<f64>,
+    /// Unit of each variable, by export name
+    pub units: BTreeMap<String, String>,
+    pub products: Vec<TimeSeriesProduct>,
+    pub gaps: Vec<TimeGap>,
+}
+
+/// Averages `rows` (in time order) into periods of `interval`, aligned to
+/// `utc_offset_minutes` so daily means follow the local day.
+fn average(
+    rows: &[&ProcessedDataRow],
+    interval: AveragingInterval,
+    utc_offset_minutes: i64,
+) -> Vec<TimeSeriesPeriod> {
+    let length = interval.millis();
+    let offset = utc_offset_minutes * 60_000;
+    let mut periods: BTreeMap<i64, Vec<&ProcessedDataRow>> = BTreeMap::new();
+    for row in rows {
+        if let Some(tstamp) = row.tstamp {
+            let start = (tstamp + offset).div_euclid(length) * length - offset;
+            periods.entry(start).or_default().push(row);
+        }
+    }
+
+    let variables = present_variables(rows);
+    periods
+        .into_iter()
+        .map(|(start_tstamp, members)| {
+            let mut values = BTreeMap::new();
+            for variable in &variables {
+                let series: Vec<f64> = members
+                    .iter()
+                    .filter(|row| {
+                        variable
+                            .channel
+                            .and_then(|channel| row.flags.get(&channel))
+                            .is_none_or(|flag| flag.flag != QartodFlag::Fail)
+                    })
+                    .filter_map(|row| (variable.value)(row))
+                    .collect();
+                if series.is_empty() {
+                    continue;
+                }
+                values.insert(
+                    variable.name.to_string(),
+                    PeriodStats {
+                        mean: series.iter().sum::<f64>() / series.len() as f64,
+                        min: series.iter().copied().fold(f64::INFINITY, f64::min),
+                        max: series.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                        count: series.len(),
+                    },
+                );
+            }
+            TimeSeriesPeriod {
+                start_tstamp,
+                time_utc: format_iso8601(start_tstamp, 0),
+                rows: members.len(),
+                values,
+            }
+        })
+        .collect()
+}
+
+/// Summarizes the rows of a moored deployment: the series averaged over each of
+/// `options.intervals`, with the mean, minimum and maximum of each variable per
+/// period, and the gaps between samples longer than `options.max_gap_secs`.
+pub(crate) fn moored_series(
+    rows: &[ProcessedDataRow],
+    options: &MooredOptions,
+    utc_offset_minutes: Option<i32>,
+) -> MooredSeries {
+    let rows: Vec<&ProcessedDataRow> = rows.iter().filter(|row| row.tstamp.is_some()).collect();
+    let tstamps: Vec<i64> = rows.iter().filter_map(|row| row.tstamp).collect();
+
+    // 1) The usual sample interval, and the gaps longer than allowed
+    let mut steps: Vec<i64> = tstamps
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .filter(|step| *step > 0)
+        .collect();
+    steps.sort_unstable();
+    let sample_interval_ms = steps.get(steps.len() / 2).copied();
+    let max_gap_ms = options
+        .max_gap_secs
+        .map(|secs| secs * 1000.0)
+        .or(sample_interval_ms.map(|ms| 3.0 * ms as f64));
+    let gaps = match max_gap_ms {
+        Some(max_gap_ms) => tstamps
+            .windows(2)
+            .filter(|pair| (pair[1] - pair[0]) as f64 > max_gap_ms)
+            .map(|pair| {
+                let duration_ms = pair[1] - pair[0];
+                TimeGap {
+                    start_tstamp: pair[0],
+                    end_tstamp: pair[1],
+                    duration_secs: duration_ms as f64 / 1000.0,
+                    missing_samples: sample_interval_ms
+                        .map_or(0, |interval| (duration_ms / interval - 1).max(0) as usize),
+                }
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    // 2) Averages over each interval
+    let offset = i64::from(utc_offset_minutes.unwrap_or(0));
+    let products = options
+        .intervals
+        .iter()
+        .map(|&interval| TimeSeriesProduct {
+            interval,
+            periods: average(&rows, interval, offset),
+        })
+        .collect();
+
+    MooredSeries {
+        start_tstamp: tstamps.first().copied(),
+        end_tstamp: tstamps.last().copied(),
+        sample_interval_secs: sample_interval_ms.map(|ms| ms as f64 / 1000.0),
+        units: present_variables(&rows)
+            .into_iter()
+            .map(|variable| (variable.name.to_string(), variable.logged_units(&rows)))
+            .collect(),
+        products,
+        gaps,
+    }
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::binning::BinningOptions;
+use crate::ctd::calibration::{validate_calibrations, ChannelCalibration};
+use crate::ctd::cast::CastOptions;
+use crate::ctd::chlorophyll::ChlorophyllOptions;
+use crate::ctd::flags::FlagOptions;
+use crate::ctd::mooring::{MooredOptions, ProcessingMode};
+use crate::ctd::qc::QcOptions;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// How `handle_ctd_data` turns raw rows into processed rows.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CtdProcessingOptions {
+    /// Profiling casts, or a logger at a fixed depth
+    pub mode: ProcessingMode,
+    /// Time-series products of `ProcessingMode::Moored`
+    pub mooring: MooredOptions,
+    /// New calibrations of channels, applied before anything else
+    pub calibrations: Vec<ChannelCalibration>,
+    /// Cast segmentation and which phases are kept
+    pub cast: CastOptions,
+    /// Despiking and sensor corrections, applied before the cast is segmented
+    pub qc: QcOptions,
+    /// Dark-count and quenching corrections of chlorophyll, applied to the kept casts
+    pub chlorophyll: ChlorophyllOptions,
+    /// Average the processed rows into bins as well
+    pub binning: Option<BinningOptions>,
+    /// QARTOD tests flagging processed values, and overrides of their flags
+    pub flags: FlagOptions,
+    /// Latitude of the cast in degrees north, for gravity in N²
+    pub latitude: Option<f64>,
+    /// Offset of the vessel's local time from UTC in minutes, for the rows'
+    /// `time_local`
+    pub utc_offset_minutes: Option<i32>,
+    /// Return the rows that are not kept (upcast, no depth, …) among the processed
+    /// rows, marked with why they were dropped, rather than leaving them out
+    pub keep_dropped_rows: bool,
+}
+
+impl CtdProcessingOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        validate_calibrations(&self.calibrations)?;
+        self.cast.validate()?;
+        self.mooring.validate()?;
+        self.qc.validate()?;
+        self.chlorophyll.validate()?;
+        self.flags.validate()?;
+        if let Some(binning) = &self.binning {
+            binning.validate()?;
+        }
+        if let Some(latitude) = self.latitude {
+            if !(-90.0..=90.0).contains(&latitude) {
+                return Err(PoleshiftError::InvalidInput {
+                    field: "latitude".to_string(),
+                    reason: "must be between -90 and 90".to_string(),
+                });
+            }
+        }
+        if let Some(offset) = self.utc_offset_minutes {
+            if !(-12 * 60..=14 * 60).contains(&offset) {
+                return Err(PoleshiftError::InvalidInput {
+                    field: "utc_offset_minutes".to_string(),
+                    reason: "must be between -720 (UTC-12) and 840 (UTC+14)".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::ctd::handle_ctd_data::{ProcessedDataRow, RawDataRow};
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{query_page, Page};
+use crate::krakenuniq::raw_sequence_store::create_store;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::parse_uuid;
+
+/// Rows of each kind `handle_ctd_data` sends back when its outputs are stored and no
+/// `preview_rows` is given.
+pub(crate) const DEFAULT_PREVIEW_ROWS: usize = 2_000;
+
+/// Where the full-resolution rows of a CTD file went and how many there are.
+#[derive(Debug, Clone, Serialize)]
+pub struct CtdStoreSummary {
+    pub path: String,
+    pub raw_rows: usize,
+    pub processed_rows: usize,
+    pub binned_rows: usize,
+}
+
+/// Location of the CTD output store for `processed_data_id` in the app data directory.
+pub(crate) fn ctd_store_path<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    processed_data_id: &str,
+) -> Result<PathBuf, PoleshiftError> {
+    // The ID becomes a file name, so only accept real UUIDs
+    let id = parse_uuid("processed_data_id", processed_data_id)?;
+    Ok(app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join("ctd")
+        .join(format!("{}.sqlite", id)))
+}
+
+/// Writes `rows` into `table`, one JSON row each, keyed by timestamp and cast.
+fn write_rows<T: Serialize>(
+    conn: &mut Connection,
+    table: &str,
+    rows: &[T],
+    key: impl Fn(&T) -> (Option<i64>, Option<usize>),
+) -> Result<(), PoleshiftError> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE {table} (
+            tstamp INTEGER,
+            cast_index INTEGER,
+            row TEXT NOT NULL
+        );
+        CREATE INDEX {table}_cast_index ON {table} (cast_index);"
+    ))
+    .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    {
+        let mut stmt = tx
+            .prepare(&format!("INSERT INTO {table} VALUES (?1, ?2, ?3)"))
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        for row in rows {
+            let (tstamp, cast_index) = key(row);
+            stmt.execute(params![
+                tstamp,
+                cast_index.map(|c| c as i64),
+                serde_json::to_string(row)?
+            ])
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        }
+    }
+    tx.commit()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))
+}
+
+/// Writes the raw, processed and binned rows of one CTD file into a fresh SQLite file
+/// at `path`, in time order.
+pub(crate) fn persist_ctd_outputs(
+    path: &Path,
+    raw: &[RawDataRow],
+    processed: &[ProcessedDataRow],
+    binned: &[ProcessedDataRow],
+) -> Result<CtdStoreSummary, PoleshiftError> {
+    let mut conn = create_store(path)?;
+    write_rows(&mut conn, "raw_data", raw, |row| (row.tstamp, None))?;
+    write_rows(&mut conn, "processed_data", processed, |row| {
+        (row.tstamp, row.cast_index)
+    })?;
+    write_rows(&mut conn, "binned_data", binned, |row| {
+        (row.tstamp, row.cast_index)
+    })?;
+    Ok(CtdStoreSummary {
+        path: path.to_string_lossy().to_string(),
+        raw_rows: raw.len(),
+        processed_rows: processed.len(),
+        binned_rows: binned.len(),
+    })
+}
+
+/// At most `max_rows` of `rows`, evenly spaced and keeping the first and last, for
+/// plotting a long deployment without sending every row.
+pub(crate) fn downsample<T: Clone>(rows: &[T], max_rows: usize) -> Vec<T> {
+    if rows.len() <= max_rows {
+        return rows.to_vec();
+    }
+    match max_rows {
+        0 => Vec::new(),
+        1 => rows[..1].to_vec(),
+        _ => (0..max_rows)
+            .map(|i| rows[i * (rows.len() - 1) / (max_rows - 1)].clone())
+            .collect(),
+    }
+}
+
+pub(crate) fn open_ctd_store(path: &Path) -> Result<Connection, PoleshiftError> {
+    if !path.exists() {
+        return Err(PoleshiftError::DataError(format!(
+            "No stored CTD outputs at {}",
+            path.display()
+        )));
+    }
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| PoleshiftError::IoError(e.to_string()))
+}
+
+fn row_from_json<T: DeserializeOwned>(row: &rusqlite::Row) -> rusqlite::Result<T> {
+    let json: String = row.get(2)?;
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e)))
+}
+
+/// Returns a page of the stored raw CTD rows, in time order.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn query_ctd_raw_data<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<StandardResponseNoFiles<Page<RawDataRow>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_ctd_store(&ctd_store_path(&app_handle, &processed_data_id)?)?;
+        let page = query_page(&conn, "raw_data", "", &[], offset, limit, row_from_json)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: page,
+        })
+    })
+    .await
+}
+
+/// Returns a page of the stored processed CTD rows, or of the binned rows with
+/// `binned`, in time order; optionally only those of the cast at `cast_index`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn query_ctd_processed_data<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    cast_index: Option<i64>,
+    binned: Option<bool>,
+) -> Result<StandardResponseNoFiles<Page<ProcessedDataRow>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_ctd_store(&ctd_store_path(&app_handle, &processed_data_id)?)?;
+        let table = if binned.unwrap_or(false) {
+            "binned_data"
+        } else {
+            "processed_data"
+        };
+        let page = match cast_index {
+            Some(cast_index) => query_page(
+                &conn,
+                table,
+                "WHERE cast_index = ?1",
+                &[&cast_index],
+                offset,
+                limit,
+                row_from_json,
+            )?,
+            None => query_page(&conn, table, "", &[], offset, limit, row_from_json)?,
+        };
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: page,
+        })
+    })
+    .await
+}
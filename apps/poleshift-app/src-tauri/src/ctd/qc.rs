@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::handle_ctd_data::{CtdChannel, ProcessedDataRow};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Scales the median absolute deviation to a standard deviation for normal data.
+const MAD_TO_SIGMA: f64 = 1.4826;
+
+/// How spikes are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DespikeMethod {
+    /// Every value becomes the median of its window, smoothing the profile
+    Median,
+    /// Only values further than `threshold` robust standard deviations from the median
+    /// of their window become that median
+    #[default]
+    Hampel,
+}
+
+/// Despiking of channels, e.g. against bubbles and fish passing the sensors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DespikeOptions {
+    pub method: DespikeMethod,
+    /// Samples in the window, odd and centred on the value
+    pub window: usize,
+    /// Hampel threshold, in robust standard deviations (1.4826 × MAD)
+    pub threshold: f64,
+    pub channels: Vec<CtdChannel>,
+}
+
+impl Default for DespikeOptions {
+    fn default() -> Self {
+        DespikeOptions {
+            method: DespikeMethod::Hampel,
+            window: 7,
+            threshold: 3.0,
+            channels: vec![
+                CtdChannel::Temperature,
+                CtdChannel::Salinity,
+                CtdChannel::SpecificConductivity,
+                CtdChannel::ChlorophyllA,
+                CtdChannel::DissolvedOxygen,
+                CtdChannel::Turbidity,
+            ],
+        }
+    }
+}
+
+/// Advances a channel in time against the others, e.g. conductivity to match the
+/// slower temperature sensor (Sea-Bird's Align CTD).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlignOptions {
+    pub channel: CtdChannel,
+    /// Seconds the channel is moved earlier; negative values delay it
+    pub advance_secs: f64,
+}
+
+/// Correction of specific conductivity for the heat stored in the conductivity cell
+/// (Lueck & Picklo, 1990, as in Sea-Bird's Cell Thermal Mass).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalMassOptions {
+    /// Amplitude of the thermal anomaly
+    pub alpha: f64,
+    /// Time constant of the thermal anomaly, in seconds (1 / beta)
+    pub tau_secs: f64,
+}
+
+impl Default for ThermalMassOptions {
+    fn default() -> Self {
+        ThermalMassOptions {
+            alpha: 0.03,
+            tau_secs: 7.0,
+        }
+    }
+}
+
+/// QC filters applied to processed rows, in the order despike, align, thermal mass.
+/// None run by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QcOptions {
+    pub despike: Option<DespikeOptions>,
+    pub align: Vec<AlignOptions>,
+    pub thermal_mass: Option<ThermalMassOptions>,
+}
+
+impl QcOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let invalid = |field: &str, reason: &str| PoleshiftError::InvalidInput {
+            field: format!("qc.{}", field),
+            reason: reason.to_string(),
+        };
+        if let Some(despike) = &self.despike {
+            if despike.window < 3 || despike.window.is_multiple_of(2) {
+                return Err(invalid("despike.window", "must be odd and at least 3"));
+            }
+            if !(despike.threshold.is_finite() && despike.threshold > 0.0) {
+                return Err(invalid("despike.threshold", "must be greater than 0"));
+            }
+        }
+        if self.align.iter().any(|a| !a.advance_secs.is_finite()) {
+            return Err(invalid("align.advance_secs", "must be a number of seconds"));
+        }
+        if let Some(thermal_mass) = &self.thermal_mass {
+            if !(thermal_mass.alpha.is_finite() && thermal_mass.alpha > 0.0) {
+                return Err(invalid("thermal_mass.alpha", "must be greater than 0"));
+            }
+            if !(thermal_mass.tau_secs.is_finite() && thermal_mass.tau_secs > 0.0) {
+                return Err(invalid("thermal_mass.tau_secs", "must be greater than 0"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A QC filter, as reported in `CTDReport`.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QcFilter {
+    Despike,
+    Align,
+    ThermalMass,
+    /// Chlorophyll dark-count offset (see `ChlorophyllOptions`)
+    DarkCount,
+    /// Chlorophyll non-photochemical quenching correction
+    Npq,
+}
+
+/// What one filter did to one channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct QcFilterReport {
+    pub filter: QcFilter,
+    pub channel: CtdChannel,
+    /// The filter's options as given
+    pub parameters: serde_json::Value,
+    /// Values the filter changed or emptied
+    pub modified_points: usize,
+}
+
+/// Rows holding `channel`, with its values, in row order.
+fn series(rows: &[ProcessedDataRow], channel: CtdChannel) -> (Vec<usize>, Vec<f64>) {
+    rows.iter()
+        .enumerate()
+        .filter_map(|(i, row)| row.value(channel).map(|value| (i, value)))
+        .unzip()
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// Despikes one channel over windows of its own samples; returns the values changed.
+fn despike(rows: &mut [ProcessedDataRow], channel: CtdChannel, options: &DespikeOptions) -> usize {
+    let (indices, values) = series(rows, channel);
+    let half = options.window / 2;
+    let mut modified = 0;
+    for (pos, &value) in values.iter().enumerate() {
+        let window = &values[pos.saturating_sub(half)..(pos + half + 1).min(values.len())];
+        let mut sorted = window.to_vec();
+        let centre = median(&mut sorted);
+        let replace = match options.method {
+            DespikeMethod::Median => true,
+            DespikeMethod::Hampel => {
+                let mut deviations: Vec<f64> = window.iter().map(|v| (v - centre).abs()).collect();
+                let sigma = MAD_TO_SIGMA * median(&mut deviations);
+                (value - centre).abs() > options.threshold * sigma
+            }
+        };
+        if replace && centre != value {
+            *rows[indices[pos]].value_mut(channel) = Some(centre);
+            modified += 1;
+        }
+    }
+    modified
+}
+
+/// Moves one channel `advance_secs` earlier by linear interpolation in time; rows the
+/// shifted series does not reach are emptied. Returns the values changed.
+fn align(rows: &mut [ProcessedDataRow], options: &AlignOptions) -> usize {
+    let (indices, values) = series(rows, options.channel);
+    let times: Vec<i64> = indices
+        .iter()
+        .map(|&i| rows[i].tstamp.unwrap_or_default())
+        .collect();
+    let advance_ms = (options.advance_secs * 1000.0).round() as i64;
+    let mut modified = 0;
+    for (pos, &i) in indices.iter().enumerate() {
+        let target = times[pos] + advance_ms;
+        let after = times.partition_point(|&t| t < target);
+        let shifted = if after < times.len() && times[after] == target {
+            Some(values[after])
+        } else if after == 0 || after == times.len() {
+            None
+        } else {
+            let (t0, t1) = (times[after - 1], times[after]);
+            let fraction = (target - t0) as f64 / (t1 - t0) as f64;
+            Some(values[after - 1] + fraction * (values[after] - values[after - 1]))
+        };
+        if shifted != Some(values[pos]) {
+            *rows[i].value_mut(options.channel) = shifted;
+            modified += 1;
+        }
+    }
+    modified
+}
+
+/// Conductivity units in S/m.
+pub(crate) fn siemens_per_metre(unit: &str) -> Option<f64> {
+    match unit.trim().replace('µ', "u").to_lowercase().as_str() {
+        "s/m" => Some(1.0),
+        "ms/cm" => Some(0.1),
+        "us/cm" => Some(1e-4),
+        _ => None,
+    }
+}
+
+/// Applies the cell thermal mass correction to specific conductivity, with the time
+/// step taken between consecutive samples holding both it and temperature. Returns the
+/// values changed.
+fn thermal_mass(
+    rows: &mut [ProcessedDataRow],
+    options: &ThermalMassOptions,
+) -> Result<usize, PoleshiftError> {
+    let channel = CtdChannel::SpecificConductivity;
+    let Some(first) = rows.iter().find(|row| row.specific_conductivity.is_some()) else {
+        return Ok(0);
+    };
+    let scale =
+        siemens_per_metre(first.unit(channel)).ok_or_else(|| PoleshiftError::InvalidInput {
+            field: "qc.thermal_mass".to_string(),
+            reason: format!(
+                "specific conductivity is in `{}`, not S/m, mS/cm or µS/cm",
+                first.unit(channel)
+            ),
+        })?;
+
+    let beta = 1.0 / options.tau_secs;
+    let mut previous: Option<(i64, f64)> = None;
+    let mut correction = 0.0;
+    let mut modified = 0;
+    for row in rows.iter_mut() {
+        let (Some(tstamp), Some(temperature), Some(conductivity)) =
+            (row.tstamp, row.temperature, row.specific_conductivity)
+        else {
+            continue;
+        };
+        if let Some((previous_tstamp, previous_temperature)) = previous {
+            let dt = (tstamp - previous_tstamp) as f64 / 1000.0;
+            let a = 2.0 * options.alpha / (dt * beta + 2.0);
+            let b = 1.0 - 2.0 * a / options.alpha;
+            // Sensitivity of conductivity to temperature, in S/m per °C
+            let dc_dt = 0.1 * (1.0 + 0.006 * (temperature - 20.0));
+            correction = -b * correction + a * dc_dt * (temperature - previous_temperature);
+            if correction != 0.0 {
+                row.specific_conductivity = Some(conductivity + correction / scale);
+                modified += 1;
+            }
+        }
+        previous = Some((tstamp, temperature));
+    }
+    Ok(modified)
+}
+
+/// Runs the QC filters of `options` over `rows`, in time order: despiking of each
+/// listed channel, then each alignment, then the thermal mass correction. Salinity is
+/// not recomputed from corrected conductivity or temperature. Returns what each filter
+/// did, with its parameters.
+pub(crate) fn apply_qc(
+    rows: &mut [ProcessedDataRow],
+    options: &QcOptions,
+) -> Result<Vec<QcFilterReport>, PoleshiftError> {
+    let parameters = |value: serde_json::Result<serde_json::Value>| {
+        value.map_err(|e| PoleshiftError::Other(format!("QC parameters: {}", e)))
+    };
+    let mut reports = Vec::new();
+
+    // 1) Despiking, channel by channel
+    if let Some(despike_options) = &options.despike {
+        let params = parameters(serde_json::to_value(despike_options))?;
+        for &channel in &despike_options.channels {
+            reports.push(QcFilterReport {
+                filter: QcFilter::Despike,
+                channel,
+                parameters: params.clone(),
+                modified_points: despike(rows, channel, despike_options),
+            });
+        }
+    }
+
+    // 2) Alignments
+    for align_options in &options.align {
+        reports.push(QcFilterReport {
+            filter: QcFilter::Align,
+            channel: align_options.channel,
+            parameters: parameters(serde_json::to_value(align_options))?,
+            modified_points: align(rows, align_options),
+        });
+    }
+
+    // 3) Thermal mass
+    if let Some(thermal_mass_options) = &options.thermal_mass {
+        reports.push(QcFilterReport {
+            filter: QcFilter::ThermalMass,
+            channel: CtdChannel::SpecificConductivity,
+            parameters: parameters(serde_json::to_value(thermal_mass_options))?,
+            modified_points: thermal_mass(rows, thermal_mass_options)?,
+        });
+    }
+    Ok(reports)
+}
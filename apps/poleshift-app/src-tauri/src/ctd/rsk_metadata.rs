@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::poleshift_common::types::PoleshiftError;
+
+/// A calibration of one instrument channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct Calibration {
+    /// Position of the channel on the instrument, from 1
+    pub channel_order: Option<i64>,
+    /// Long name of the channel, when the file maps it
+    pub channel: Option<String>,
+    /// e.g. `factory` or `field`
+    pub kind: Option<String>,
+    /// When the calibration was made, in ms since the epoch
+    pub tstamp: Option<i64>,
+    pub equation: Option<String>,
+}
+
+/// One deployment of the instrument, with the span it logged over.
+#[derive(Debug, Clone, Serialize)]
+pub struct Deployment {
+    pub deployment_id: Option<i64>,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    /// When the data was downloaded, in ms since the epoch
+    pub time_of_download: Option<i64>,
+    pub sample_size: Option<i64>,
+    /// First and last sample, in ms since the epoch (from `epochs`)
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+}
+
+/// Provenance of an RSK file: the instrument that logged it, its calibrations and
+/// its deployments.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InstrumentMetadata {
+    pub serial_id: Option<String>,
+    pub model: Option<String>,
+    pub firmware_version: Option<String>,
+    pub firmware_type: Option<String>,
+    pub part_number: Option<String>,
+    /// Version of the RSK schema (`dbInfo`)
+    pub rsk_version: Option<String>,
+    pub calibrations: Vec<Calibration>,
+    pub deployments: Vec<Deployment>,
+}
+
+impl InstrumentMetadata {
+    /// Factor taking the timestamps of an RSK file to ms since the Unix epoch, from the
+    /// first scan's. Ruskin writes ms, but some exports hold seconds; the deployments'
+    /// epochs bound the logged span, so a scan that only falls inside them once scaled
+    /// from seconds is taken as seconds.
+    pub(crate) fn tstamp_scale(&self, first_tstamp: Option<i64>) -> i64 {
+        let Some(first) = first_tstamp else {
+            return 1;
+        };
+        let within = |tstamp: i64| {
+            self.deployments.iter().any(|d| {
+                d.start_time.is_some_and(|start| tstamp >= start)
+                    && d.end_time.is_none_or(|end| tstamp <= end)
+            })
+        };
+        if !within(first) && within(first.saturating_mul(1000)) {
+            1000
+        } else {
+            1
+        }
+    }
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Text(text) => Some(text.clone()),
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Real(r) => Some(r.to_string()),
+        Value::Null | Value::Blob(_) => None,
+    }
+}
+
+pub(crate) fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Integer(i) => Some(*i),
+        Value::Real(r) => Some(*r as i64),
+        Value::Text(text) => text.trim().parse().ok(),
+        Value::Null | Value::Blob(_) => None,
+    }
+}
+
+/// The given columns of every row of `table`, in rowid order. RSK schemas differ
+/// between Ruskin versions, so columns the table lacks read as NULL and a missing
+/// table reads as no rows.
+pub(crate) fn select_columns(
+    conn: &Connection,
+    table: &str,
+    columns: &[&str],
+) -> Result<Vec<Vec<Value>>, PoleshiftError> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({table})"))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let existing: Vec<String> = stmt
+        .query_map([], |row| row.get(1))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    if existing.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let selected: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            if existing.iter().any(|e| e.eq_ignore_ascii_case(column)) {
+                format!("\"{column}\"")
+            } else {
+                "NULL".to_string()
+            }
+        })
+        .collect();
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM {table}", selected.join(", ")))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let rows = stmt
+        .query_map([], |row| {
+            (0..columns.len())
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    Ok(rows)
+}
+
+/// Reads the `instruments`, `calibrations`, `deployments` and `epochs` tables of an
+/// RSK file. Files logged by several instruments report the first.
+pub(crate) fn read_rsk_metadata(file_path: &str) -> Result<InstrumentMetadata, PoleshiftError> {
+    let conn = Connection::open(file_path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+    let mut metadata = InstrumentMetadata::default();
+
+    if let Some(instrument) = select_columns(
+        &conn,
+        "instruments",
+        &[
+            "serialID",
+            "model",
+            "firmwareVersion",
+            "firmwareType",
+            "partNumber",
+        ],
+    )?
+    .first()
+    {
+        metadata.serial_id = text(&instrument[0]);
+        metadata.model = text(&instrument[1]);
+        metadata.firmware_version = text(&instrument[2]);
+        metadata.firmware_type = text(&instrument[3]);
+        metadata.part_number = text(&instrument[4]);
+    }
+    metadata.rsk_version = select_columns(&conn, "dbInfo", &["version"])?
+        .last()
+        .and_then(|row| text(&row[0]));
+
+    // Channel names by their order on the instrument, for the calibrations
+    let channel_ids: HashMap<i64, i64> =
+        select_columns(&conn, "instrumentChannels", &["channelOrder", "channelID"])?
+            .iter()
+            .filter_map(|row| Some((integer(&row[0])?, integer(&row[1])?)))
+            .collect();
+    let channel_names: HashMap<i64, String> =
+        select_columns(&conn, "channels", &["channelID", "longName"])?
+            .iter()
+            .filter_map(|row| Some((integer(&row[0])?, text(&row[1])?)))
+            .collect();
+    metadata.calibrations = select_columns(
+        &conn,
+        "calibrations",
+        &["channelOrder", "type", "tstamp", "equation"],
+    )?
+    .iter()
+    .map(|row| {
+        let channel_order = integer(&row[0]);
+        Calibration {
+            channel_order,
+            channel: channel_order
+                .and_then(|order| channel_ids.get(&order))
+                .and_then(|id| channel_names.get(id))
+                .cloned(),
+            kind: text(&row[1]),
+            tstamp: integer(&row[2]),
+            equation: text(&row[3]),
+        }
+    })
+    .collect();
+
+    let epochs: HashMap<i64, (Option<i64>, Option<i64>)> =
+        select_columns(&conn, "epochs", &["deploymentID", "startTime", "endTime"])?
+            .iter()
+            .filter_map(|row| Some((integer(&row[0])?, (integer(&row[1]), integer(&row[2])))))
+            .collect();
+    metadata.deployments = select_columns(
+        &conn,
+        "deployments",
+        &[
+            "deploymentID",
+            "name",
+            "comment",
+            "timeOfDownload",
+            "sampleSize",
+        ],
+    )?
+    .iter()
+    .map(|row| {
+        let deployment_id = integer(&row[0]);
+        let (start_time, end_time) = deployment_id
+            .and_then(|id| epochs.get(&id).copied())
+            .unwrap_or_default();
+        Deployment {
+            deployment_id,
+            name: text(&row[1]),
+            comment: text(&row[2]),
+            time_of_download: integer(&row[3]),
+            sample_size: integer(&row[4]),
+            start_time,
+            end_time,
+        }
+    })
+    .collect();
+
+    Ok(metadata)
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::export::export_variable;
+use crate::ctd::flags::QartodFlag;
+use crate::ctd::handle_ctd_data::ProcessedDataRow;
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Where a sample was collected: at a depth, at a time, or both.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampleCollection {
+    pub sample_id: String,
+    /// Collection depth, in m
+    #[serde(default)]
+    pub depth: Option<f64>,
+    /// Collection time, in ms since the Unix epoch
+    #[serde(default)]
+    pub tstamp: Option<i64>,
+}
+
+/// How a value is taken from the rows around the collection point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleMatchMethod {
+    /// Linear interpolation between the rows either side, or the nearest row when only
+    /// one side is within tolerance
+    #[default]
+    Interpolate,
+    Nearest,
+}
+
+/// How CTD values are matched to a sample.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SampleMatchOptions {
+    pub method: SampleMatchMethod,
+    /// Rows further than this from the collection depth are not used, in m
+    pub depth_tolerance: f64,
+    /// Rows further than this from the collection time are not used, for samples
+    /// without a depth, in seconds
+    pub time_tolerance_secs: f64,
+    /// Variables to match, by their export name
+    pub variables: Vec<String>,
+}
+
+impl Default for SampleMatchOptions {
+    fn default() -> Self {
+        SampleMatchOptions {
+            method: SampleMatchMethod::Interpolate,
+            depth_tolerance: 2.0,
+            time_tolerance_secs: 60.0,
+            variables: vec![
+                "temperature".to_string(),
+                "salinity".to_string(),
+                "chlorophyll_a".to_string(),
+            ],
+        }
+    }
+}
+
+impl SampleMatchOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let invalid = |field: &str, reason: String| PoleshiftError::InvalidInput {
+            field: format!("options.{}", field),
+            reason,
+        };
+        for (field, value) in [
+            ("depth_tolerance", self.depth_tolerance),
+            ("time_tolerance_secs", self.time_tolerance_secs),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(invalid(field, "must be 0 or more".to_string()));
+            }
+        }
+        if let Some(name) = self
+            .variables
+            .iter()
+            .find(|name| export_variable(name).is_none())
+        {
+            return Err(invalid(
+                "variables",
+                format!("`{}` is not a CTD variable", name),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What the collection point was matched on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchAxis {
+    Depth,
+    Time,
+}
+
+/// One variable at the collection point.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchedValue {
+    pub value: f64,
+    pub units: String,
+    /// Distance from the collection point to the nearest row used, in m or seconds
+    pub offset: f64,
+}
+
+/// CTD values at a sample's collection point, for its metadata.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleCtdMatch {
+    pub sample_id: String,
+    pub depth: Option<f64>,
+    pub tstamp: Option<i64>,
+    pub matched_on: MatchAxis,
+    /// Profile of the cast the values come from, when the collection time picked one
+    pub profile_index: Option<usize>,
+    /// By export name
+    pub values: BTreeMap<String, MatchedValue>,
+    /// Variables with no row within tolerance
+    pub missing: Vec<String>,
+}
+
+/// The value at `x` of `(x, y)` points sorted by `x`, with the distance to the nearest
+/// point used; none when no point is within `tolerance`.
+fn value_at(
+    points: &[(f64, f64)],
+    x: f64,
+    tolerance: f64,
+    method: SampleMatchMethod,
+) -> Option<(f64, f64)> {
+    let after = points.partition_point(|(px, _)| *px < x);
+    let below = after
+        .checked_sub(1)
+        .map(|i| points[i])
+        .filter(|(px, _)| x - px <= tolerance);
+    let above = points
+        .get(after)
+        .copied()
+        .filter(|(px, _)| px - x <= tolerance);
+    let nearest = |a: (f64, f64), b: (f64, f64)| {
+        if x - a.0 <= b.0 - x {
+            (a.1, x - a.0)
+        } else {
+            (b.1, b.0 - x)
+        }
+    };
+    match (below, above, method) {
+        (Some((x0, y0)), Some((x1, y1)), SampleMatchMethod::Interpolate) => {
+            let value = if x1 == x0 {
+                (y0 + y1) / 2.0
+            } else {
+                y0 + (x - x0) / (x1 - x0) * (y1 - y0)
+            };
+            Some((value, (x - x0).min(x1 - x)))
+        }
+        (Some(a), Some(b), SampleMatchMethod::Nearest) => Some(nearest(a, b)),
+        (Some((x0, y0)), None, _) => Some((y0, x - x0)),
+        (None, Some((x1, y1)), _) => Some((y1, x1 - x)),
+        (None, None, _) => None,
+    }
+}
+
+/// Matches a sample to the rows of its station's cast. With a collection depth the
+/// values are taken at that depth, within the profile holding the row nearest the
+/// collection time when there is one; with only a time, at that time. Failed values
+/// and rows kept with `keep_dropped_rows` are left out.
+pub(crate) fn match_sample(
+    rows: &[ProcessedDataRow],
+    sample: &SampleCollection,
+    options: &SampleMatchOptions,
+) -> Result<SampleCtdMatch, PoleshiftError> {
+    // 1) The axis, and the rows the sample can come from
+    let profile_index = sample.tstamp.and_then(|tstamp| {
+        rows.iter()
+            .filter_map(|row| Some(((row.tstamp? - tstamp).abs(), row.profile_index?)))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, profile)| profile)
+    });
+    let (matched_on, x, tolerance) = match (sample.depth, sample.tstamp) {
+        (Some(depth), _) => (MatchAxis::Depth, depth, options.depth_tolerance),
+        (None, Some(tstamp)) => (
+            MatchAxis::Time,
+            tstamp as f64 / 1000.0,
+            options.time_tolerance_secs,
+        ),
+        (None, None) => {
+            return Err(PoleshiftError::InvalidInput {
+                field: "sample".to_string(),
+                reason: "needs a collection depth or time".to_string(),
+            });
+        }
+    };
+    let candidates: Vec<&ProcessedDataRow> = rows
+        .iter()
+        .filter(|row| {
+            row.dropped.is_none()
+                && (matched_on == MatchAxis::Time
+                    || profile_index.is_none()
+                    || row.profile_index == profile_index)
+        })
+        .collect();
+    let axis = |row: &ProcessedDataRow| match matched_on {
+        MatchAxis::Depth => row.depth,
+        MatchAxis::Time => row.tstamp.map(|t| t as f64 / 1000.0),
+    };
+
+    // 2) Each variable at the collection point
+    let mut values = BTreeMap::new();
+    let mut missing = Vec::new();
+    for name in &options.variables {
+        let Some(variable) = export_variable(name) else {
+            continue;
+        };
+        let mut points: Vec<(f64, f64)> = candidates
+            .iter()
+            .filter(|row| {
+                variable
+                    .channel
+                    .and_then(|channel| row.flags.get(&channel))
+                    .is_none_or(|flag| flag.flag != QartodFlag::Fail)
+            })
+            .filter_map(|row| Some((axis(row)?, (variable.value)(row)?)))
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        match value_at(&points, x, tolerance, options.method) {
+            Some((value, offset)) => {
+                values.insert(
+                    variable.name.to_string(),
+                    MatchedValue {
+                        value,
+                        units: variable.logged_units(&candidates),
+                        offset,
+                    },
+                );
+            }
+            None => missing.push(variable.name.to_string()),
+        }
+    }
+
+    Ok(SampleCtdMatch {
+        sample_id: sample.sample_id.clone(),
+        depth: sample.depth,
+        tstamp: sample.tstamp,
+        matched_on,
+        profile_index: profile_index.filter(|_| matched_on == MatchAxis::Depth),
+        values,
+        missing,
+    })
+}
+
+/// Takes the CTD values (temperature, salinity and chlorophyll by default) at a
+/// sequencing sample's collection depth or time from its station's processed cast,
+/// for attaching to the sample's metadata (see `match_sample`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn match_ctd_to_sample(
+    invocation: Invocation,
+    rows: Vec<ProcessedDataRow>,
+    sample: SampleCollection,
+    options: Option<SampleMatchOptions>,
+) -> Result<StandardResponseNoFiles<SampleCtdMatch>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let options = options.unwrap_or_default();
+        options.validate()?;
+        let report = match_sample(&rows, &sample, &options)?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
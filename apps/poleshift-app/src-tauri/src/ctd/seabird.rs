@@ -0,0 +1,243 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::ctd::epoch_secs;
+use crate::ctd::handle_ctd_data::{Channel, Scan};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Width of a value column in CNV files written by SBE Data Processing. Wide values
+/// can fill the column, so rows are split by width when they have the expected length.
+const CNV_COLUMN_WIDTH: usize = 11;
+/// 2000-01-01T00:00:00Z in seconds, the epoch of `timeK` and `timeQ`.
+const SEABIRD_EPOCH_SECS: f64 = 946_684_800.0;
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// A column of a CNV file, from its `# name N = code: description [unit]` line.
+struct CnvColumn {
+    code: String,
+    description: String,
+    unit: Option<String>,
+}
+
+/// How the scans of a CNV file are placed in time.
+enum ScanTime {
+    /// A column of seconds since the Unix epoch (`timeY`)
+    Unix(usize),
+    /// A column of seconds since 2000-01-01 (`timeK`, `timeQ`)
+    Since2000(usize),
+    /// A column of seconds, minutes or hours (`timeS`, `timeM`, `timeH`) since the start
+    Elapsed(usize, f64),
+    /// A column of Julian days (`timeJ`), 1 at the start of the year of the start
+    JulianDays(usize),
+    /// The sample interval in seconds, counted from the start
+    Interval(f64),
+}
+
+/// Parses a Sea-Bird header time, `Jun 10 2023 14:02:11`, into seconds since the
+/// Unix epoch. Anything after the time (e.g. `[Instrument's time stamp, header]`) is
+/// ignored.
+fn parse_header_time(text: &str) -> Option<i64> {
+    let mut parts = text.split_whitespace();
+    let month_name = parts.next()?.to_lowercase();
+    let month = MONTHS.iter().position(|m| month_name.starts_with(m))? as i64 + 1;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|p| p.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (clock.next()??, clock.next()??, clock.next()??);
+    Some(epoch_secs(
+        year,
+        month,
+        day,
+        hours * 3600 + minutes * 60 + seconds,
+    ))
+}
+
+/// Seconds since the Unix epoch of January 1 of the year `secs` falls in.
+fn year_start(secs: i64) -> i64 {
+    let mut year = 1970 + secs.div_euclid(31_556_952);
+    while epoch_secs(year, 1, 1, 0) > secs {
+        year -= 1;
+    }
+    while epoch_secs(year + 1, 1, 1, 0) <= secs {
+        year += 1;
+    }
+    epoch_secs(year, 1, 1, 0)
+}
+
+/// Parses `code: description [unit]`.
+fn parse_column(text: &str) -> CnvColumn {
+    let (code, rest) = text.split_once(':').unwrap_or((text, ""));
+    let (description, unit) = match (rest.find('['), rest.rfind(']')) {
+        (Some(open), Some(close)) if open < close => (
+            &rest[..open],
+            Some(rest[open + 1..close].trim().to_string()),
+        ),
+        _ => (rest, None),
+    };
+    CnvColumn {
+        code: code.trim().to_string(),
+        description: description.trim().to_string(),
+        unit,
+    }
+}
+
+/// Columns that place or count scans rather than measure anything.
+fn is_bookkeeping(code: &str) -> bool {
+    code.starts_with("time") || matches!(code, "scan" | "flag" | "nbin")
+}
+
+/// Splits a data line into its values, by column width when the line has exactly the
+/// width of `columns` columns and by whitespace otherwise.
+fn split_values(line: &str, columns: usize) -> Vec<&str> {
+    if line.len() == columns * CNV_COLUMN_WIDTH && line.is_ascii() {
+        (0..columns)
+            .map(|i| line[i * CNV_COLUMN_WIDTH..(i + 1) * CNV_COLUMN_WIDTH].trim())
+            .collect()
+    } else {
+        line.split_whitespace().collect()
+    }
+}
+
+/// Reads a Sea-Bird CNV file (SBE 19, 19plus, 25, 911 casts after SBE Data
+/// Processing) into channels and scans, as `read_rsk` does for RBR files.
+///
+/// Each `# name` column becomes a channel, its description the long name and its
+/// variable code (`t090C`, `sal00`) the short name; time, scan and flag columns only
+/// place the scans. Timestamps come from a Unix or Sea-Bird time column, from an
+/// elapsed-time or Julian-day column counted from `start_time`, or from the sample
+/// interval. Values equal to `bad_flag` are left empty.
+pub(crate) fn read_cnv(path: &Path) -> Result<(Vec<Channel>, Vec<Scan>), PoleshiftError> {
+    let data_error = |reason: String| {
+        PoleshiftError::DataError(format!(
+            "{} is not a readable CNV file: {}",
+            path.display(),
+            reason
+        ))
+    };
+    let mut lines = BufReader::new(File::open(path)?).lines();
+
+    // 1) Header: columns, bad-value flag, start time and sample interval
+    let mut columns: Vec<CnvColumn> = Vec::new();
+    let mut bad_flag: Option<f64> = None;
+    let mut start_time: Option<i64> = None;
+    let mut system_time: Option<i64> = None;
+    let mut interval_secs: Option<f64> = None;
+    let mut header_ended = false;
+    for line in lines.by_ref() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.starts_with("*END*") {
+            header_ended = true;
+            break;
+        }
+        if let Some(setting) = line.strip_prefix('#') {
+            let Some((key, value)) = setting.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key.starts_with("name ") {
+                columns.push(parse_column(value));
+            } else if key == "bad_flag" {
+                bad_flag = value.parse().ok();
+            } else if key == "start_time" {
+                start_time = parse_header_time(value);
+            } else if key == "interval" {
+                if let Some(("seconds", secs)) = value.split_once(':').map(|(u, v)| (u.trim(), v)) {
+                    interval_secs = secs.trim().parse().ok();
+                }
+            } else if key == "file_type" && value != "ascii" {
+                return Err(data_error(format!("{} data is not supported", value)));
+            }
+        } else if let Some((key, value)) = line.trim_start_matches('*').split_once('=') {
+            if key.trim() == "System UTC" || key.trim() == "NMEA UTC (Time)" {
+                system_time = system_time.or_else(|| parse_header_time(value));
+            }
+        }
+    }
+    if !header_ended {
+        return Err(data_error("no *END* line after the header".to_string()));
+    }
+    if columns.is_empty() {
+        return Err(data_error("no `# name` columns in the header".to_string()));
+    }
+
+    // 2) Pick how scans are timed, preferring absolute time columns
+    let start = start_time.or(system_time);
+    let column_of = |code: &str| columns.iter().position(|c| c.code == code);
+    let scan_time = if let Some(i) = column_of("timeY") {
+        ScanTime::Unix(i)
+    } else if let Some(i) = column_of("timeK").or_else(|| column_of("timeQ")) {
+        ScanTime::Since2000(i)
+    } else if let (Some(i), Some(_)) = (column_of("timeS"), start) {
+        ScanTime::Elapsed(i, 1.0)
+    } else if let (Some(i), Some(_)) = (column_of("timeM"), start) {
+        ScanTime::Elapsed(i, 60.0)
+    } else if let (Some(i), Some(_)) = (column_of("timeH"), start) {
+        ScanTime::Elapsed(i, 3600.0)
+    } else if let (Some(i), Some(_)) = (column_of("timeJ"), start) {
+        ScanTime::JulianDays(i)
+    } else if let (Some(secs), Some(_)) = (interval_secs, start) {
+        ScanTime::Interval(secs)
+    } else {
+        return Err(data_error(
+            "no time column, and no start time with a sample interval in seconds".to_string(),
+        ));
+    };
+    let start_ms = start.unwrap_or_default() as f64 * 1000.0;
+
+    // 3) Channels: every measured column
+    let measured: Vec<usize> = (0..columns.len())
+        .filter(|&i| !is_bookkeeping(&columns[i].code))
+        .collect();
+    let channels: Vec<Channel> = measured
+        .iter()
+        .map(|&i| Channel {
+            channel_id: i as i32 + 1,
+            short_name: Some(columns[i].code.clone()),
+            long_name: Some(columns[i].description.clone()).filter(|d| !d.is_empty()),
+            units: columns[i].unit.clone(),
+            is_derived: None,
+            is_visible: Some(true),
+        })
+        .collect();
+
+    // 4) Scans
+    let mut scans: Vec<Scan> = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values: Vec<Option<f64>> = split_values(&line, columns.len())
+            .into_iter()
+            .map(|v| v.parse::<f64>().ok().filter(|v| Some(*v) != bad_flag))
+            .collect();
+        if values.len() != columns.len() {
+            return Err(data_error(format!(
+                "data line {} has {} values for {} columns",
+                index + 1,
+                values.len(),
+                columns.len()
+            )));
+        }
+        let value = |i: usize| values[i];
+        let tstamp_ms = match scan_time {
+            ScanTime::Unix(i) => value(i).map(|secs| secs * 1000.0),
+            ScanTime::Since2000(i) => value(i).map(|secs| (secs + SEABIRD_EPOCH_SECS) * 1000.0),
+            ScanTime::Elapsed(i, scale) => value(i).map(|t| start_ms + t * scale * 1000.0),
+            ScanTime::JulianDays(i) => value(i).map(|days| {
+                let year_ms = year_start(start.unwrap_or_default()) as f64 * 1000.0;
+                year_ms + (days - 1.0) * 86_400_000.0
+            }),
+            ScanTime::Interval(secs) => Some(start_ms + scans.len() as f64 * secs * 1000.0),
+        };
+        scans.push((
+            tstamp_ms.map(|ms| ms.round() as i64),
+            measured.iter().map(|&i| value(i)).collect(),
+        ));
+    }
+    Ok((channels, scans))
+}
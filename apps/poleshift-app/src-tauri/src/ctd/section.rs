@@ -0,0 +1,305 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::export::{export_variable, validate_casts, CtdExportCast, ExportVariable};
+use crate::ctd::flags::QartodFlag;
+use crate::ctd::handle_ctd_data::ProcessedDataRow;
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Mean radius of the Earth, in km.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+/// Most cells a section may have per variable.
+const MAX_SECTION_CELLS: usize = 2_000_000;
+
+/// How casts are gridded into a section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SectionOptions {
+    /// Spacing of the depth grid, in m
+    pub depth_step: f64,
+    /// Points of the distance grid, from the first station to the last
+    pub distance_points: usize,
+    /// Variables to grid, by their export name (e.g. `temperature`, `sigma0`)
+    pub variables: Vec<String>,
+    /// Stations further apart than this are not interpolated between, in km
+    pub max_gap_km: Option<f64>,
+}
+
+impl Default for SectionOptions {
+    fn default() -> Self {
+        SectionOptions {
+            depth_step: 1.0,
+            distance_points: 100,
+            variables: vec![
+                "temperature".to_string(),
+                "salinity".to_string(),
+                "sigma0".to_string(),
+            ],
+            max_gap_km: None,
+        }
+    }
+}
+
+impl SectionOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let invalid = |field: &str, reason: String| PoleshiftError::InvalidInput {
+            field: format!("options.{}", field),
+            reason,
+        };
+        if !(self.depth_step.is_finite() && self.depth_step > 0.0) {
+            return Err(invalid("depth_step", "must be greater than 0".to_string()));
+        }
+        if self.distance_points < 2 {
+            return Err(invalid("distance_points", "must be at least 2".to_string()));
+        }
+        if self
+            .max_gap_km
+            .is_some_and(|gap| !(gap.is_finite() && gap > 0.0))
+        {
+            return Err(invalid("max_gap_km", "must be greater than 0".to_string()));
+        }
+        for name in &self.variables {
+            if export_variable(name).is_none_or(|variable| variable.name == "depth") {
+                return Err(invalid(
+                    "variables",
+                    format!("`{}` cannot be gridded", name),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A station of a section.
+#[derive(Debug, Clone, Serialize)]
+pub struct SectionStation {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Along-track distance from the first station, in km
+    pub distance_km: f64,
+    /// Deepest sample of the cast, in m
+    pub max_depth: Option<f64>,
+}
+
+/// One variable gridded over the section.
+#[derive(Debug, Clone, Serialize)]
+pub struct SectionVariable {
+    pub name: String,
+    pub units: String,
+    /// Values by depth, then distance: `values[depth][distance]`; empty where no
+    /// station brackets the cell
+    pub values: Vec<Vec<Option<f64>>>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// A distance × depth section across stations, for contour plotting.
+#[derive(Debug, Clone, Serialize)]
+pub struct CtdSection {
+    pub stations: Vec<SectionStation>,
+    pub distances_km: Vec<f64>,
+    pub depths: Vec<f64>,
+    pub variables: Vec<SectionVariable>,
+}
+
+/// Great-circle distance between two positions in degrees, in km.
+fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let dphi = phi2 - phi1;
+    let dlambda = (lon2 - lon1).to_radians();
+    let a = (dphi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (dlambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Linear interpolation of sorted `(x, y)` points at `x`; none outside them.
+fn interpolate(points: &[(f64, f64)], x: f64) -> Option<f64> {
+    let after = points.partition_point(|(px, _)| *px < x);
+    match (after.checked_sub(1).map(|i| points[i]), points.get(after)) {
+        (_, Some(&(x1, y1))) if x1 == x => Some(y1),
+        (Some((x0, y0)), Some(&(x1, y1))) => Some(y0 + (x - x0) / (x1 - x0) * (y1 - y0)),
+        _ => None,
+    }
+}
+
+/// One cast's values of `variable` on the depth grid: rows with a depth and a value
+/// that did not fail QC, averaged where depths repeat, then interpolated linearly
+/// within the cast's depth range.
+fn grid_profile(
+    rows: &[ProcessedDataRow],
+    variable: &ExportVariable,
+    depths: &[f64],
+) -> Vec<Option<f64>> {
+    let mut points: Vec<(f64, f64)> = rows
+        .iter()
+        .filter(|row| {
+            variable
+                .channel
+                .and_then(|channel| row.flags.get(&channel))
+                .is_none_or(|flag| flag.flag != QartodFlag::Fail)
+        })
+        .filter_map(|row| Some((row.depth?, (variable.value)(row)?)))
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut merged: Vec<(f64, f64, usize)> = Vec::new();
+    for (depth, value) in points {
+        match merged.last_mut() {
+            Some((last_depth, sum, count)) if *last_depth == depth => {
+                *sum += value;
+                *count += 1;
+            }
+            _ => merged.push((depth, value, 1)),
+        }
+    }
+    let profile: Vec<(f64, f64)> = merged
+        .into_iter()
+        .map(|(depth, sum, count)| (depth, sum / count as f64))
+        .collect();
+    depths
+        .iter()
+        .map(|&depth| interpolate(&profile, depth))
+        .collect()
+}
+
+/// Grids casts into a distance × depth section. Stations are taken in the order
+/// given, at their cumulative great-circle distance; each cast is interpolated onto
+/// the depth grid, then each depth across the distance grid between the two
+/// stations around it. Cells outside the casts' depth ranges, or between stations
+/// further apart than `max_gap_km`, are left empty; failed values are left out.
+pub(crate) fn build_section(
+    casts: &[CtdExportCast],
+    options: &SectionOptions,
+) -> Result<CtdSection, PoleshiftError> {
+    // 1) Stations along the track
+    let mut stations: Vec<SectionStation> = Vec::with_capacity(casts.len());
+    for cast in casts {
+        let (Some(latitude), Some(longitude)) = (cast.latitude, cast.longitude) else {
+            return Err(PoleshiftError::InvalidInput {
+                field: "casts".to_string(),
+                reason: format!("`{}` has no position", cast.name),
+            });
+        };
+        let distance_km = stations.last().map_or(0.0, |previous| {
+            previous.distance_km
+                + haversine_km(previous.latitude, previous.longitude, latitude, longitude)
+        });
+        stations.push(SectionStation {
+            name: cast.name.clone(),
+            latitude,
+            longitude,
+            distance_km,
+            max_depth: cast
+                .rows
+                .iter()
+                .filter_map(|row| row.depth)
+                .reduce(f64::max),
+        });
+    }
+    let total_km = stations.last().map_or(0.0, |station| station.distance_km);
+    if stations.len() < 2 || total_km <= 0.0 {
+        return Err(PoleshiftError::InvalidInput {
+            field: "casts".to_string(),
+            reason: "a section needs at least two stations at different positions".to_string(),
+        });
+    }
+
+    // 2) The grid
+    let max_depth = stations
+        .iter()
+        .filter_map(|station| station.max_depth)
+        .reduce(f64::max)
+        .unwrap_or(0.0);
+    let depth_points = (max_depth / options.depth_step).floor() as usize + 1;
+    if depth_points.saturating_mul(options.distance_points) > MAX_SECTION_CELLS {
+        return Err(PoleshiftError::InvalidInput {
+            field: "options".to_string(),
+            reason: format!(
+                "{} × {} cells is too fine a grid; raise depth_step or lower distance_points",
+                depth_points, options.distance_points
+            ),
+        });
+    }
+    let depths: Vec<f64> = (0..depth_points)
+        .map(|i| i as f64 * options.depth_step)
+        .collect();
+    let distances_km: Vec<f64> = (0..options.distance_points)
+        .map(|i| total_km * i as f64 / (options.distance_points - 1) as f64)
+        .collect();
+
+    // 3) Each variable, profiles first and then across stations
+    let rows: Vec<&ProcessedDataRow> = casts.iter().flat_map(|cast| &cast.rows).collect();
+    let variables = options
+        .variables
+        .par_iter()
+        .filter_map(|name| export_variable(name))
+        .map(|variable| {
+            let profiles: Vec<Vec<Option<f64>>> = casts
+                .iter()
+                .map(|cast| grid_profile(&cast.rows, variable, &depths))
+                .collect();
+            let values: Vec<Vec<Option<f64>>> = (0..depths.len())
+                .map(|d| {
+                    distances_km
+                        .iter()
+                        .map(|&distance| {
+                            let after = stations
+                                .partition_point(|station| station.distance_km < distance)
+                                .min(stations.len() - 1);
+                            let before = after.saturating_sub(1);
+                            let (s0, s1) = (&stations[before], &stations[after]);
+                            let (v0, v1) = (profiles[before][d], profiles[after][d]);
+                            if distance >= s1.distance_km {
+                                return v1;
+                            }
+                            let span = s1.distance_km - s0.distance_km;
+                            if options.max_gap_km.is_some_and(|gap| span > gap) {
+                                return None;
+                            }
+                            let fraction = (distance - s0.distance_km) / span;
+                            Some(v0? + fraction * (v1? - v0?))
+                        })
+                        .collect()
+                })
+                .collect();
+            let all = values.iter().flatten().flatten().copied();
+            SectionVariable {
+                name: variable.name.to_string(),
+                units: variable.logged_units(&rows),
+                min: all.clone().reduce(f64::min),
+                max: all.reduce(f64::max),
+                values,
+            }
+        })
+        .collect();
+
+    Ok(CtdSection {
+        stations,
+        distances_km,
+        depths,
+        variables,
+    })
+}
+
+/// Grids processed casts from several stations, in track order, into a distance ×
+/// depth section per variable (see `build_section`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn build_ctd_section(
+    invocation: Invocation,
+    casts: Vec<CtdExportCast>,
+    options: Option<SectionOptions>,
+) -> Result<StandardResponseNoFiles<CtdSection>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let options = options.unwrap_or_default();
+        options.validate()?;
+        validate_casts(&casts)?;
+        let report = build_section(&casts, &options)?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
@@ -0,0 +1,206 @@
+use gsw::conversions::{ct_from_pt, p_from_z, sr_from_sp};
+use gsw::earth::gravity;
+use gsw::practical_salinity::sp_from_c;
+use gsw::volume::{sigma0, specvol_alpha_beta};
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::handle_ctd_data::ProcessedDataRow;
+use crate::ctd::qc::siemens_per_metre;
+
+/// Gravity for N² when the latitude of the cast is not known: the mean over the
+/// ocean that GSW uses (Griffies, 2004), in m/s².
+const STANDARD_GRAVITY: f64 = 9.7963;
+/// Atmospheric pressure at sea level, in dbar.
+const ATMOSPHERE_DBAR: f64 = 10.1325;
+const DBAR_TO_PA: f64 = 1.0e4;
+/// ITS-90 to IPTS-68 temperature scale factor.
+const T68_PER_T90: f64 = 1.00024;
+/// Temperature coefficient RBR instruments normalize specific conductivity to 25 °C
+/// with, per °C.
+const SPECIFIC_CONDUCTIVITY_COEFFICIENT: f64 = 0.0191;
+/// Names of in-situ conductivity channels in a row's `channels`: RBR's long name and
+/// Sea-Bird's variable codes.
+const CONDUCTIVITY_CHANNEL_NAMES: &[&str] = &[
+    "conductivity",
+    "c0mS/cm",
+    "c0S/m",
+    "c0uS/cm",
+    "c1mS/cm",
+    "c1S/m",
+];
+
+/// Where a row's practical salinity comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SalinitySource {
+    /// A salinity channel of the file
+    Instrument,
+    /// PSS-78 from conductivity, temperature and pressure (see
+    /// `derive_practical_salinity`)
+    Derived,
+}
+
+/// Adiabatic lapse rate in °C/dbar from practical salinity, IPTS-68 temperature and
+/// sea pressure (Bryden, 1973, as in UNESCO technical paper 44).
+fn adiabatic_lapse_rate(sp: f64, t68: f64, p: f64) -> f64 {
+    let ds = sp - 35.0;
+    (3.5803e-5 + (8.5258e-6 + (-6.836e-8 + 6.6228e-10 * t68) * t68) * t68)
+        + (1.8932e-6 - 4.2393e-8 * t68) * ds
+        + ((1.8741e-8 + (-6.7795e-10 + (8.733e-12 - 5.4481e-14 * t68) * t68) * t68)
+            + (-1.1351e-10 + 2.7759e-12 * t68) * ds)
+            * p
+        + (-4.6206e-13 + (1.8676e-14 - 2.1687e-16 * t68) * t68) * p * p
+}
+
+/// Potential temperature (ITS-90) referenced to the surface, by integrating the
+/// adiabatic lapse rate with Fofonoff's (1977) Runge-Kutta scheme. Within a
+/// millidegree of TEOS-10's `pt0_from_t` to 1000 dbar.
+fn potential_temperature(sp: f64, t90: f64, p: f64) -> f64 {
+    let root2 = std::f64::consts::SQRT_2;
+    let dp = -p;
+    let mut dth = dp * adiabatic_lapse_rate(sp, t90 * T68_PER_T90, p);
+    let mut th = t90 * T68_PER_T90 + 0.5 * dth;
+    let mut q = dth;
+    dth = dp * adiabatic_lapse_rate(sp, th, p + 0.5 * dp);
+    th += (1.0 - 1.0 / root2) * (dth - q);
+    q = (2.0 - root2) * dth + (-2.0 + 3.0 / root2) * q;
+    dth = dp * adiabatic_lapse_rate(sp, th, p + 0.5 * dp);
+    th += (1.0 + 1.0 / root2) * (dth - q);
+    q = (2.0 + root2) * dth + (-2.0 - 3.0 / root2) * q;
+    dth = dp * adiabatic_lapse_rate(sp, th, p + dp);
+    (th + (dth - 2.0 * q) / 6.0) / T68_PER_T90
+}
+
+/// In-situ conductivity of a row in mS/cm: a conductivity channel, else specific
+/// conductivity taken back to the row's temperature.
+fn conductivity_ms_cm(row: &ProcessedDataRow) -> Option<f64> {
+    let measured = row.channels.iter().find_map(|(name, reading)| {
+        CONDUCTIVITY_CHANNEL_NAMES
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(name))
+            .then_some(reading)
+    });
+    if let Some(reading) = measured {
+        return Some(reading.value? * siemens_per_metre(&reading.unit)? * 10.0);
+    }
+    let specific = row.specific_conductivity? * siemens_per_metre(&row.specific_conductivity_unit)?;
+    let temperature = row.temperature?;
+    Some(specific * 10.0 * (1.0 + SPECIFIC_CONDUCTIVITY_COEFFICIENT * (temperature - 25.0)))
+}
+
+/// Fills in the practical salinity (PSS-78) of rows whose file has no salinity
+/// channel, from conductivity, temperature (ITS-90) and sea pressure, and marks it
+/// as derived; rows derived before are derived again, e.g. after a recalibration.
+/// Conductivity is a logged in-situ conductivity channel (`conductivity`, `c0S/m`,
+/// …) when there is one, else specific conductivity taken back from 25 °C with
+/// RBR's coefficient. Returns the rows filled.
+pub(crate) fn derive_practical_salinity(
+    rows: &mut [ProcessedDataRow],
+    latitude: Option<f64>,
+) -> usize {
+    let mut derived = 0;
+    for row in rows.iter_mut() {
+        if row.salinity_source == Some(SalinitySource::Instrument) {
+            continue;
+        }
+        let salinity = match (
+            conductivity_ms_cm(row),
+            row.temperature,
+            sea_pressure(row, latitude),
+        ) {
+            (Some(c), Some(t), Some(p)) => {
+                sp_from_c(c, t, p.max(0.0)).ok().filter(|sp| sp.is_finite())
+            }
+            _ => None,
+        };
+        row.salinity = salinity;
+        row.salinity_source = salinity.map(|_| SalinitySource::Derived);
+        if salinity.is_some() {
+            if row.salinity_unit.is_empty() {
+                row.salinity_unit = "PSU".to_string();
+            }
+            derived += 1;
+        }
+    }
+    derived
+}
+
+/// Potential density anomaly (kg/m³) from practical salinity, in-situ temperature
+/// (ITS-90) and sea pressure (dbar), as `derive_teos10` fills `sigma0`.
+pub(crate) fn sigma0_from_sp(sp: f64, t90: f64, p: f64) -> Option<f64> {
+    if sp < 0.0 {
+        return None;
+    }
+    let sa = sr_from_sp(sp);
+    let ct = ct_from_pt(sa, potential_temperature(sp, t90, p)).ok()?;
+    sigma0(sa, ct).ok()
+}
+
+/// Sea pressure of a row in dbar: sea pressure, else pressure less an atmosphere, else
+/// from depth.
+fn sea_pressure(row: &ProcessedDataRow, latitude: Option<f64>) -> Option<f64> {
+    row.sea_pressure
+        .or_else(|| row.pressure.map(|p| p - ATMOSPHERE_DBAR))
+        .or_else(|| {
+            let depth = row.depth?;
+            p_from_z(-depth, latitude.unwrap_or(45.0), None, None).ok()
+        })
+}
+
+/// Fills the TEOS-10 columns of `rows` from practical salinity, in-situ temperature
+/// (ITS-90) and pressure (dbar): absolute salinity, conservative temperature and
+/// potential density anomaly `sigma0`, with the 75-term GSW polynomials. Rows missing
+/// any of the three are left without them.
+///
+/// Absolute salinity is taken as Reference Salinity, since the salinity anomaly needs
+/// the cast's position in the GSW atlas. `n_squared` is the Brunt-Väisälä frequency
+/// squared between each row's neighbours in the same cast, so `rows` should be a
+/// profile in recorded order; it is noisy at full resolution and smoother on binned
+/// rows. Gravity is taken at `latitude`, or standard gravity without it.
+pub(crate) fn derive_teos10(rows: &mut [ProcessedDataRow], latitude: Option<f64>) {
+    // 1) Absolute salinity, conservative temperature and sigma0 per row
+    let mut states: Vec<(usize, f64, f64, f64)> = Vec::new();
+    for (i, row) in rows.iter_mut().enumerate() {
+        let p = sea_pressure(row, latitude);
+        let state = match (row.salinity, row.temperature, p) {
+            (Some(sp), Some(t), Some(p)) if sp >= 0.0 => {
+                let sa = sr_from_sp(sp);
+                ct_from_pt(sa, potential_temperature(sp, t, p))
+                    .ok()
+                    .map(|ct| (sa, ct, p))
+            }
+            _ => None,
+        };
+        row.absolute_salinity = state.map(|(sa, _, _)| sa);
+        row.conservative_temperature = state.map(|(_, ct, _)| ct);
+        row.sigma0 = state.and_then(|(sa, ct, _)| sigma0(sa, ct).ok());
+        row.n_squared = None;
+        if let Some((sa, ct, p)) = state {
+            states.push((i, sa, ct, p));
+        }
+    }
+
+    // 2) N² from the rows on either side, within a cast
+    for k in 0..states.len() {
+        let (i, ..) = states[k];
+        let same_cast = |other: usize| rows[states[other].0].cast_index == rows[i].cast_index;
+        let before = k.checked_sub(1).filter(|&j| same_cast(j)).unwrap_or(k);
+        let after = Some(k + 1)
+            .filter(|&j| j < states.len() && same_cast(j))
+            .unwrap_or(k);
+        let ((_, sa0, ct0, p0), (_, sa1, ct1, p1)) = (states[before], states[after]);
+        let dp = p1 - p0;
+        if dp.abs() < f64::EPSILON {
+            continue;
+        }
+        let (sa, ct, p) = ((sa0 + sa1) / 2.0, (ct0 + ct1) / 2.0, (p0 + p1) / 2.0);
+        let Ok((specvol, alpha, beta)) = specvol_alpha_beta(sa, ct, p) else {
+            continue;
+        };
+        let g = latitude
+            .and_then(|lat| gravity(lat, p).ok())
+            .unwrap_or(STANDARD_GRAVITY);
+        rows[i].n_squared =
+            Some(g * g / specvol * (beta * (sa1 - sa0) - alpha * (ct1 - ct0)) / (dp * DBAR_TO_PA));
+    }
+}
@@ -0,0 +1,327 @@
+//poleshift/src-tauri/src/demo/mod.rs
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::ctd::handle_ctd_data::{process_ctd_data, CTDReport, CtdRunArgs};
+use crate::ctd::output_store::DEFAULT_PREVIEW_ROWS;
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::demo::{
+    build_demo_result, DemoIds, DemoRead, DEMO_TAXA, DEMO_UNCLASSIFIED_WEIGHT,
+};
+use crate::krakenuniq::{KrakenUniqResult, NodeIds};
+use crate::poleshift_common::jobs::JobManager;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::parse_uuid;
+
+/// Database version the IDs of demo report rows are derived from.
+const DEMO_DATABASE_VERSION: &str = "demo";
+/// Label attached to every demo dataset so it is never mistaken for real data.
+const DEMO_LABEL: &str = "DEMO DATA - synthetic values, not collected by an instrument";
+
+/// 2025-01-15T12:00:00Z in milliseconds, the start of the demo cast.
+const DEMO_CAST_START_MS: i64 = 1_736_942_400_000;
+const DEMO_SAMPLE_HZ: i64 = 4;
+const DEMO_FASTQ_FILES: usize = 3;
+const DEMO_READS_PER_FILE: usize = 200;
+
+#[derive(Serialize)]
+pub struct DemoDataset {
+    pub demo: bool,
+    pub label: String,
+    pub sample_id: String,
+    pub raw_data_id: String,
+    pub processed_data_id: String,
+    pub ctd_file_path: String,
+    pub fastq_file_paths: Vec<String>,
+    pub ctd: CTDReport,
+    pub sequencing: KrakenUniqResult,
+}
+
+/// Generates a synthetic CTD cast (as an RSK-style SQLite file), a small gzipped FASTQ
+/// set and the matching classification result, then runs them through the regular
+/// processing code so every downstream view can be explored without instruments.
+/// `deterministic_ids` derives the report row IDs as `handle_sequence_data` does.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn generate_demo_data(
+    invocation: Invocation,
+    app_handle: AppHandle,
+    user_id: String,
+    org_id: String,
+    seed: Option<u64>,
+    deterministic_ids: Option<bool>,
+) -> Result<StandardResponseNoFiles<DemoDataset>, PoleshiftError> {
+    traced(invocation, None, async move {
+        parse_uuid("user_id", &user_id)?;
+        parse_uuid("org_id", &org_id)?;
+        let sample_id = Uuid::new_v4().to_string();
+        let raw_data_id = Uuid::new_v4().to_string();
+        let processed_data_id = Uuid::new_v4().to_string();
+
+        let demo_dir = app_handle
+            .path()
+            .app_data_dir()
+            .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+            .join("demo")
+            .join(&sample_id);
+        std::fs::create_dir_all(&demo_dir)?;
+
+        let mut rng = StdRng::seed_from_u64(seed.unwrap_or(42));
+
+        // 1) CTD cast
+        let ctd_path = demo_dir.join("demo_cast.rsk");
+        write_demo_rsk(&ctd_path, &mut rng)?;
+
+        // 2) FASTQ set with known taxon assignments
+        let (fastq_paths, reads) = write_demo_fastq(&demo_dir, &mut rng)?;
+        drop(rng);
+
+        // 3) Run the CTD file through the normal handler
+        let ctd_file_path = ctd_path.to_string_lossy().to_string();
+        let jobs = app_handle.state::<JobManager>();
+        let job = jobs.start("ctd", None)?;
+        let args = CtdRunArgs {
+            sample_id: sample_id.clone(),
+            org_id: org_id.clone(),
+            user_id: user_id.clone(),
+            raw_data_id: raw_data_id.clone(),
+            processed_data_id: processed_data_id.clone(),
+            file_paths: vec![ctd_file_path.clone()],
+            channel_mappings: Vec::new(),
+            delimited: Default::default(),
+            processing: Default::default(),
+            persist_outputs: false,
+            preview_rows: DEFAULT_PREVIEW_ROWS,
+            profile_samples: Vec::new(),
+        };
+        let ctd = process_ctd_data(app_handle.clone(), args, job.clone()).await;
+        jobs.finish(job.id(), ctd.as_ref().err());
+        let ctd = ctd?.report;
+
+        // 4) Parse the reads and attach the synthesized classification
+        let ids = DemoIds {
+            user_id: &user_id,
+            org_id: &org_id,
+            sample_id: &sample_id,
+            raw_data_id: &raw_data_id,
+            processed_data_id: &processed_data_id,
+        };
+        let node_ids = if deterministic_ids.unwrap_or(false) {
+            NodeIds::Deterministic {
+                sample_id: &sample_id,
+                database_version: DEMO_DATABASE_VERSION,
+            }
+        } else {
+            NodeIds::Random
+        };
+        let sequencing = build_demo_result(&fastq_paths, &reads, &ids, &node_ids)?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: DemoDataset {
+                demo: true,
+                label: DEMO_LABEL.to_string(),
+                sample_id,
+                raw_data_id,
+                processed_data_id,
+                ctd_file_path,
+                fastq_file_paths: fastq_paths,
+                ctd,
+                sequencing,
+            },
+        })
+    })
+    .await
+}
+
+/// Writes a polar-style profile: a one-minute surface soak, a 1 m/s downcast to
+/// 120 m and an upcast, using the same Channels/data layout as an RBR RSK file.
+fn write_demo_rsk(path: &Path, rng: &mut StdRng) -> Result<(), PoleshiftError> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let mut conn = Connection::open(path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+
+    let channels: [(i32, &str, &str, &str); 9] = [
+        (1, "cond05", "Conductivity", "mS/cm"),
+        (2, "temp14", "Temperature", "°C"),
+        (3, "pres24", "Pressure", "dbar"),
+        (4, "fluo01", "Chlorophyll a", "µg/l"),
+        (5, "pres08", "Sea pressure", "dbar"),
+        (6, "dpth01", "Depth", "m"),
+        (7, "sal_00", "Salinity", "PSU"),
+        (8, "sos_00", "Speed of sound", "m/s"),
+        (9, "scon00", "Specific conductivity", "µS/cm"),
+    ];
+
+    let column_defs: Vec<String> = channels
+        .iter()
+        .map(|(id, ..)| format!("channel{:02} REAL", id))
+        .collect();
+    conn.execute_batch(&format!(
+        "CREATE TABLE Channels (
+            channelID INTEGER PRIMARY KEY,
+            shortName TEXT,
+            longName TEXT,
+            units TEXT,
+            isDerived INTEGER,
+            isVisible INTEGER
+        );
+        CREATE TABLE data (tstamp INTEGER PRIMARY KEY, {});",
+        column_defs.join(", ")
+    ))
+    .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    for (id, short, long, units) in channels {
+        let derived = matches!(id, 5..=9);
+        tx.execute(
+            "INSERT INTO Channels VALUES (?1, ?2, ?3, ?4, ?5, 1)",
+            params![id, short, long, units, derived],
+        )
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    }
+
+    {
+        let mut stmt = tx
+            .prepare("INSERT INTO data VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+        let soak_s = 60;
+        let max_depth = 120.0;
+        let descent_s = 120;
+        let total_s = soak_s + 2 * descent_s;
+
+        for i in 0..(total_s * DEMO_SAMPLE_HZ) {
+            let t = i as f64 / DEMO_SAMPLE_HZ as f64;
+            let depth: f64 = if t < soak_s as f64 {
+                1.5 + rng.gen_range(-0.2..0.2)
+            } else if t < (soak_s + descent_s) as f64 {
+                1.5 + (t - soak_s as f64) / descent_s as f64 * (max_depth - 1.5)
+            } else {
+                max_depth - (t - (soak_s + descent_s) as f64) / descent_s as f64 * (max_depth - 1.0)
+            };
+            let depth = depth.max(0.2);
+
+            // Warm, fresh surface layer over a thermocline/halocline near 40 m
+            let mixed = 1.0 / (1.0 + ((depth - 40.0) / 8.0).exp());
+            let temperature = -1.2 + 2.7 * mixed + rng.gen_range(-0.005..0.005);
+            let salinity = 34.5 - 0.7 * mixed + rng.gen_range(-0.002..0.002);
+            let chlorophyll =
+                (0.2 + 2.5 * (-((depth - 25.0) / 10.0).powi(2)).exp() + rng.gen_range(-0.05..0.05))
+                    .max(0.0);
+            let sea_pressure = depth * 1.0083;
+            let pressure = sea_pressure + 10.1325;
+            let conductivity = 27.0 + 0.9 * temperature + 0.8 * (salinity - 34.0);
+            let specific_conductivity =
+                conductivity / (1.0 + 0.0191 * (temperature - 25.0)) * 1000.0;
+            let speed_of_sound = 1449.2 + 4.6 * temperature - 0.055 * temperature.powi(2)
+                + 0.00029 * temperature.powi(3)
+                + (1.34 - 0.01 * temperature) * (salinity - 35.0)
+                + 0.016 * depth;
+
+            let tstamp = DEMO_CAST_START_MS + i * (1000 / DEMO_SAMPLE_HZ);
+            stmt.execute(params![
+                tstamp,
+                conductivity,
+                temperature,
+                pressure,
+                chlorophyll,
+                sea_pressure,
+                depth,
+                salinity,
+                speed_of_sound,
+                specific_conductivity,
+            ])
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        }
+    }
+    tx.commit()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Writes `DEMO_FASTQ_FILES` gzipped FASTQ files with Nanopore-style headers.
+/// Returns the file paths and the taxon each read was generated for.
+fn write_demo_fastq(
+    dir: &Path,
+    rng: &mut StdRng,
+) -> Result<(Vec<String>, Vec<DemoRead>), PoleshiftError> {
+    let total_weight: f64 =
+        DEMO_TAXA.iter().map(|t| t.weight).sum::<f64>() + DEMO_UNCLASSIFIED_WEIGHT;
+    let run_id = Uuid::new_v4().simple().to_string();
+
+    let mut paths = Vec::new();
+    let mut reads = Vec::new();
+
+    for file_index in 0..DEMO_FASTQ_FILES {
+        let path: PathBuf = dir.join(format!("demo_reads_{}.fastq.gz", file_index));
+        let mut writer = GzEncoder::new(BufWriter::new(File::create(&path)?), Compression::fast());
+
+        for read_index in 0..DEMO_READS_PER_FILE {
+            // Pick the source taxon by weight
+            let mut pick = rng.gen_range(0.0..total_weight);
+            let mut tax_id = 0;
+            for taxon in DEMO_TAXA {
+                if pick < taxon.weight {
+                    tax_id = taxon.tax_id;
+                    break;
+                }
+                pick -= taxon.weight;
+            }
+
+            let length = rng.gen_range(800..1600);
+            let sequence: String = (0..length)
+                .map(|_| ['A', 'C', 'G', 'T'][rng.gen_range(0..4)])
+                .collect();
+            let quality: String = (0..length)
+                .map(|_| (33 + rng.gen_range(8..30)) as u8 as char)
+                .collect();
+
+            let read_id = Uuid::new_v4().to_string();
+            let read_number = file_index * DEMO_READS_PER_FILE + read_index;
+            let start_time = format!(
+                "2025-01-15T14:{:02}:{:02}Z",
+                (read_number / 60) % 60,
+                read_number % 60
+            );
+            writeln!(
+                writer,
+                "@{} runid={} read={} ch={} start_time={} flow_cell_id=DEMO00001 \
+                 protocol_group_id=poleshift_demo sample_id=demo barcode=barcode01 \
+                 barcode_alias=barcode01 basecall_model_version_id=demo\n{}\n+\n{}",
+                read_id,
+                run_id,
+                read_number,
+                rng.gen_range(1..=512),
+                start_time,
+                sequence,
+                quality
+            )?;
+
+            reads.push(DemoRead {
+                read_id,
+                tax_id,
+                length,
+            });
+        }
+
+        writer.finish()?.flush()?;
+        paths.push(path.to_string_lossy().to_string());
+    }
+
+    Ok((paths, reads))
+}
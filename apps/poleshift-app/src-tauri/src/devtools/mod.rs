@@ -0,0 +1,354 @@
+//poleshift/src-tauri/src/devtools/mod.rs
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::Serialize;
+use tauri::ipc::{CommandArg, CommandItem, InvokeBody, InvokeError};
+use tauri::{AppHandle, Manager, Runtime, State, Wry};
+
+use crate::error_reporting::{redact, ErrorReporter, Reportable};
+use crate::poleshift_common::types::PoleshiftError;
+use crate::poleshift_common::utils::now_ms;
+use crate::settings::{current_settings, save_settings};
+
+/// Number of finished invocations kept when dev mode is first enabled.
+pub const DEFAULT_TRACE_CAPACITY: usize = 50;
+
+/// An event emitted while a traced command was running.
+#[derive(Debug, Clone, Serialize)]
+pub struct TracedEvent {
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub at_ms: i64,
+}
+
+/// One recorded command invocation.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandTraceEntry {
+    pub id: u64,
+    pub command: String,
+    pub inputs: serde_json::Value,
+    pub started_at_ms: i64,
+    pub duration_ms: Option<u128>,
+    pub output_size_bytes: Option<usize>,
+    pub success: Option<bool>,
+    pub error: Option<String>,
+    pub events: Vec<TracedEvent>,
+}
+
+/// Records the last N command invocations while dev mode is on.
+///
+/// Registered as managed state, and switched on and off by the `dev_mode` setting;
+/// when dev mode is off every method is a cheap no-op. Every command runs its body
+/// through `traced` (or `traced_blocking`) except `get_command_trace` and
+/// `clear_command_trace`, which would otherwise fill the trace they read.
+pub struct CommandInspector {
+    enabled: AtomicBool,
+    capacity: AtomicUsize,
+    next_id: AtomicU64,
+    in_flight: Mutex<HashMap<u64, (Instant, CommandTraceEntry)>>,
+    /// Trace ID of the command each running job belongs to
+    job_traces: Mutex<HashMap<String, u64>>,
+    completed: Mutex<VecDeque<CommandTraceEntry>>,
+}
+
+impl Default for CommandInspector {
+    fn default() -> Self {
+        CommandInspector {
+            enabled: AtomicBool::new(false),
+            capacity: AtomicUsize::new(DEFAULT_TRACE_CAPACITY),
+            next_id: AtomicU64::new(1),
+            in_flight: Mutex::new(HashMap::new()),
+            job_traces: Mutex::new(HashMap::new()),
+            completed: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl CommandInspector {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool, capacity: Option<usize>) {
+        if let Some(capacity) = capacity {
+            self.capacity.store(capacity.max(1), Ordering::Relaxed);
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    /// Starts tracing a command, whose events are those of `job_id`. Returns `None`
+    /// when dev mode is off.
+    pub fn begin(
+        &self,
+        command: &str,
+        inputs: serde_json::Value,
+        job_id: Option<&str>,
+    ) -> Option<u64> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = CommandTraceEntry {
+            id,
+            command: command.to_string(),
+            inputs,
+            started_at_ms: now_ms(),
+            duration_ms: None,
+            output_size_bytes: None,
+            success: None,
+            error: None,
+            events: Vec::new(),
+        };
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.insert(id, (Instant::now(), entry));
+        }
+        if let (Some(job_id), Ok(mut job_traces)) = (job_id, self.job_traces.lock()) {
+            job_traces.insert(job_id.to_string(), id);
+        }
+        Some(id)
+    }
+
+    /// Attaches an event emitted by job `job_id` to the trace of the command running
+    /// it, if that command is traced.
+    pub fn record_event(&self, job_id: &str, event: &str, payload: &serde_json::Value) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Some(id) = self
+            .job_traces
+            .lock()
+            .ok()
+            .and_then(|job_traces| job_traces.get(job_id).copied())
+        else {
+            return;
+        };
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            if let Some((_, entry)) = in_flight.get_mut(&id) {
+                entry.events.push(TracedEvent {
+                    event: event.to_string(),
+                    payload: payload.clone(),
+                    at_ms: now_ms(),
+                });
+            }
+        }
+    }
+
+    /// Completes a trace started with [`CommandInspector::begin`].
+    pub fn finish<T: Serialize, E: Display>(&self, id: Option<u64>, result: &Result<T, E>) {
+        let Some(id) = id else { return };
+        if let Ok(mut job_traces) = self.job_traces.lock() {
+            job_traces.retain(|_, trace_id| *trace_id != id);
+        }
+        let Some((started, mut entry)) = self
+            .in_flight
+            .lock()
+            .ok()
+            .and_then(|mut in_flight| in_flight.remove(&id))
+        else {
+            return;
+        };
+
+        entry.duration_ms = Some(started.elapsed().as_millis());
+        match result {
+            Ok(output) => {
+                entry.success = Some(true);
+                entry.output_size_bytes = serde_json::to_vec(output).ok().map(|v| v.len());
+            }
+            Err(e) => {
+                entry.success = Some(false);
+                entry.error = Some(e.to_string());
+            }
+        }
+
+        // Dev mode was turned off while the command ran, e.g. by `set_dev_mode` itself
+        if !self.is_enabled() {
+            return;
+        }
+        if let Ok(mut completed) = self.completed.lock() {
+            completed.push_back(entry);
+            let capacity = self.capacity.load(Ordering::Relaxed);
+            while completed.len() > capacity {
+                completed.pop_front();
+            }
+        }
+    }
+
+    /// Finished invocations (oldest first) followed by any still running.
+    pub fn snapshot(&self) -> Vec<CommandTraceEntry> {
+        let mut entries: Vec<CommandTraceEntry> = self
+            .completed
+            .lock()
+            .map(|c| c.iter().cloned().collect())
+            .unwrap_or_default();
+        if let Ok(in_flight) = self.in_flight.lock() {
+            let mut running: Vec<CommandTraceEntry> =
+                in_flight.values().map(|(_, e)| e.clone()).collect();
+            running.sort_by_key(|e| e.id);
+            entries.extend(running);
+        }
+        entries
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut completed) = self.completed.lock() {
+            completed.clear();
+        }
+    }
+}
+
+/// The command being invoked and its arguments, taken from the IPC message by adding an
+/// `invocation: Invocation<R>` parameter to a command; the webview does not pass it.
+/// Arguments are redacted as they are read (see `error_reporting::redact`), so tokens,
+/// emails and full paths never reach the trace or an error report.
+pub struct Invocation<R: Runtime = Wry> {
+    command: &'static str,
+    inputs: serde_json::Value,
+    app_handle: AppHandle<R>,
+}
+
+impl<'de, R: Runtime> CommandArg<'de, R> for Invocation<R> {
+    fn from_command(item: CommandItem<'de, R>) -> Result<Self, InvokeError> {
+        let inputs = match item.message.payload() {
+            InvokeBody::Json(args) => redact(args),
+            // Binary payloads are only sized
+            InvokeBody::Raw(bytes) => serde_json::json!({ "raw_bytes": bytes.len() }),
+        };
+        Ok(Invocation {
+            command: item.name,
+            inputs,
+            app_handle: item.message.webview().app_handle().clone(),
+        })
+    }
+}
+
+impl<R: Runtime> Invocation<R> {
+    /// Starts tracking the command with the `ErrorReporter` and the inspector.
+    fn begin(&self, job_id: Option<&str>) -> (Option<u64>, Option<u64>) {
+        let report_id = self
+            .app_handle
+            .try_state::<ErrorReporter>()
+            .map(|reporter| reporter.begin(self.command, &self.inputs));
+        let trace_id = self
+            .app_handle
+            .try_state::<CommandInspector>()
+            .and_then(|inspector| inspector.begin(self.command, self.inputs.clone(), job_id));
+        (report_id, trace_id)
+    }
+
+    fn finish<T: Serialize, E: Reportable>(
+        &self,
+        (report_id, trace_id): (Option<u64>, Option<u64>),
+        result: &Result<T, E>,
+    ) {
+        if let Some(inspector) = self.app_handle.try_state::<CommandInspector>() {
+            inspector.finish(trace_id, result);
+        }
+        if let (Some(reporter), Some(id)) =
+            (self.app_handle.try_state::<ErrorReporter>(), report_id)
+        {
+            reporter.finish(id, result);
+        }
+    }
+}
+
+/// Runs a command body and records it in the inspector when dev mode is on, with the
+/// progress events of its job `job_id`. A failure is also captured by the
+/// `ErrorReporter`.
+pub async fn traced<R, T, E, F>(
+    invocation: Invocation<R>,
+    job_id: Option<&str>,
+    body: F,
+) -> Result<T, E>
+where
+    R: Runtime,
+    T: Serialize,
+    E: Reportable,
+    F: Future<Output = Result<T, E>>,
+{
+    let ids = invocation.begin(job_id);
+    let result = body.await;
+    invocation.finish(ids, &result);
+    result
+}
+
+/// `traced` for the body of a synchronous command.
+pub fn traced_blocking<R, T, E>(
+    invocation: Invocation<R>,
+    body: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E>
+where
+    R: Runtime,
+    T: Serialize,
+    E: Reportable,
+{
+    let ids = invocation.begin(None);
+    let result = body();
+    invocation.finish(ids, &result);
+    result
+}
+
+/// Turns dev mode on or off and keeps the choice in the `dev_mode` setting, so it
+/// holds across restarts; turning it off discards recorded traces.
+#[tauri::command(rename_all = "snake_case")]
+pub fn set_dev_mode<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    enabled: bool,
+    capacity: Option<usize>,
+) -> Result<(), PoleshiftError> {
+    traced_blocking(invocation, || {
+        let mut settings = current_settings(&app_handle);
+        settings.dev_mode = enabled;
+        if let Some(capacity) = capacity {
+            settings.dev_trace_capacity = capacity.max(1);
+        }
+        settings.validate()?;
+        save_settings(&app_handle, &settings)
+    })
+}
+
+/// Returns the recorded command invocations, oldest first.
+#[tauri::command]
+pub fn get_command_trace(inspector: State<'_, CommandInspector>) -> Vec<CommandTraceEntry> {
+    inspector.snapshot()
+}
+
+#[tauri::command]
+pub fn clear_command_trace(inspector: State<'_, CommandInspector>) {
+    inspector.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_events_on_the_trace_of_their_job() {
+        let inspector = CommandInspector::default();
+        inspector.set_enabled(true, None);
+        let merge = inspector.begin("merge_fastq_files", serde_json::json!({}), Some("a"));
+        let upload = inspector.begin("upload_raw_file", serde_json::json!({}), Some("b"));
+
+        inspector.record_event("a", "job://a/progress", &serde_json::json!({ "pct": 50 }));
+        inspector.record_event("c", "job://c/progress", &serde_json::json!({}));
+        inspector.finish(merge, &Ok::<_, String>(()));
+        inspector.record_event("a", "job://a/progress", &serde_json::json!({ "pct": 100 }));
+
+        let entries = inspector.snapshot();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, merge.unwrap());
+        assert_eq!(entries[0].events.len(), 1);
+        assert_eq!(entries[0].events[0].event, "job://a/progress");
+        assert_eq!(entries[1].id, upload.unwrap());
+        assert!(entries[1].events.is_empty());
+    }
+}
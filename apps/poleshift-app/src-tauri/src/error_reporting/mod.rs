@@ -0,0 +1,512 @@
+//poleshift/src-tauri/src/error_reporting/mod.rs
+
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, Runtime, State};
+use uuid::Uuid;
+
+use crate::devtools::{traced, traced_blocking, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::now_ms;
+
+/// Where reports are sent once the user agrees; set at build time. Without it reports
+/// are only kept locally.
+const REPORT_ENDPOINT: Option<&str> = option_env!("POLESHIFT_ERROR_REPORT_URL");
+/// Reports kept on disk; the oldest are removed beyond this.
+const MAX_STORED_REPORTS: usize = 200;
+const CONSENT_FILE: &str = "consent.json";
+/// Failed commands kept for the report of their error (see `ErrorReporter::finish`).
+const MAX_FAILED_COMMANDS: usize = 16;
+const REDACTED: &str = "[redacted]";
+/// Argument names whose values are never stored, matched case-insensitively.
+const SENSITIVE_KEYS: [&str; 9] = [
+    "email",
+    "user_id",
+    "org_id",
+    "api_key",
+    "token",
+    "password",
+    "secret",
+    "authorization",
+    "cookie",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Panic,
+    Error,
+}
+
+/// One captured failure, as stored and submitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorReport {
+    pub id: String,
+    pub kind: ErrorKind,
+    /// Command that failed, or that was running when a panic happened (the latest
+    /// started when several were)
+    pub command: Option<String>,
+    /// The command's arguments, redacted (see `redact`)
+    pub args: Value,
+    pub message: String,
+    /// `file:line` of a panic
+    pub location: Option<String>,
+    pub backtrace: Option<String>,
+    pub app_version: String,
+    pub os: String,
+    pub created_at_ms: i64,
+    pub submitted: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ConsentFile {
+    consent: bool,
+}
+
+/// Errors worth a report; cancellations, rejected input and denied permissions are
+/// expected outcomes.
+pub trait Reportable: Display {
+    fn is_reportable(&self) -> bool {
+        true
+    }
+}
+
+impl Reportable for PoleshiftError {
+    fn is_reportable(&self) -> bool {
+        !matches!(
+            self,
+            PoleshiftError::Cancelled(_)
+                | PoleshiftError::InvalidInput { .. }
+                | PoleshiftError::PermissionDenied { .. }
+        )
+    }
+}
+
+impl Reportable for String {}
+
+/// Hands command errors to the app's `ErrorReporter`; set by `install`.
+type CommandErrorHook = Box<dyn Fn(&PoleshiftError) + Send + Sync>;
+static COMMAND_ERRORS: OnceLock<CommandErrorHook> = OnceLock::new();
+
+/// Reports an error a command returns, as it is sent to the webview (see the
+/// `Serialize` impl of `PoleshiftError`). Does nothing before `install`.
+pub(crate) fn report_command_error(error: &PoleshiftError) {
+    if let Some(report) = COMMAND_ERRORS.get() {
+        report(error);
+    }
+}
+
+/// Frees the submission slot of an `ErrorReporter` when dropped.
+struct Submission(Arc<AtomicBool>);
+
+impl Drop for Submission {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+fn home_dir() -> Option<String> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+        .filter(|home| home.len() > 1)
+}
+
+/// Replaces the home directory in `text` with `~`, so user names stay local.
+fn redact_text(text: &str) -> String {
+    match home_dir() {
+        Some(home) => text.replace(&home, "~"),
+        None => text.to_string(),
+    }
+}
+
+fn is_absolute_path(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    text.starts_with('/')
+        || text.starts_with("\\\\")
+        || (bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == b":\\")
+}
+
+/// Strips personal details from command arguments: values of `SENSITIVE_KEYS` and
+/// anything that looks like an email are dropped, and absolute paths keep only their
+/// file name.
+pub(crate) fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| {
+                let key_lower = key.to_lowercase();
+                let value = if SENSITIVE_KEYS.iter().any(|s| key_lower.contains(s)) {
+                    Value::String(REDACTED.to_string())
+                } else {
+                    redact(value)
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::String(text) if text.contains('@') && !text.contains(char::is_whitespace) => {
+            Value::String(REDACTED.to_string())
+        }
+        Value::String(text) if is_absolute_path(text) => {
+            let name = text.rsplit(['/', '\\']).next().unwrap_or_default();
+            Value::String(format!(".../{}", name))
+        }
+        other => other.clone(),
+    }
+}
+
+/// Captures panics and failed commands into a local report store (`error_reports`
+/// in the app data directory), and submits them once the user has agreed to it.
+///
+/// Registered as managed state and started with `install` from the app's setup, after
+/// which a panic anywhere is written to the store before the previous hook runs, and
+/// the error of any command is stored as it is sent to the webview. Commands run their
+/// bodies through `devtools::traced`, which tracks them while in flight, so a report
+/// names the command and its (redacted) arguments.
+#[derive(Default)]
+pub struct ErrorReporter {
+    dir: Mutex<Option<PathBuf>>,
+    app_version: Mutex<String>,
+    consent: AtomicBool,
+    next_id: AtomicU64,
+    in_flight: Mutex<Vec<(u64, String, Value)>>,
+    /// Error message, command and arguments of traced commands that failed, until
+    /// their error is reported
+    failed: Mutex<VecDeque<(String, String, Value)>>,
+    /// Whether reports are being submitted; one submission runs at a time
+    submitting: Arc<AtomicBool>,
+}
+
+impl ErrorReporter {
+    /// Opens the report store, reads the user's consent, installs the panic hook
+    /// and submits reports left from earlier runs when consent was given.
+    pub fn install<R: Runtime>(&self, app_handle: &AppHandle<R>) -> Result<(), PoleshiftError> {
+        let dir = app_handle
+            .path()
+            .app_data_dir()
+            .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+            .join("error_reports");
+        fs::create_dir_all(&dir)?;
+        let consent = fs::read_to_string(dir.join(CONSENT_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str::<ConsentFile>(&content).ok())
+            .unwrap_or_default()
+            .consent;
+        self.consent.store(consent, Ordering::Relaxed);
+        if let Ok(mut current) = self.dir.lock() {
+            *current = Some(dir);
+        }
+        if let Ok(mut version) = self.app_version.lock() {
+            *version = app_handle.package_info().version.to_string();
+        }
+
+        let error_handle = app_handle.clone();
+        let _ = COMMAND_ERRORS.set(Box::new(move |error| {
+            if let Some(reporter) = error_handle.try_state::<ErrorReporter>() {
+                reporter.record_error(error);
+            }
+        }));
+
+        let hook_handle = app_handle.clone();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if let Some(reporter) = hook_handle.try_state::<ErrorReporter>() {
+                reporter.record_panic(info);
+            }
+            previous(info);
+        }));
+
+        if consent {
+            self.submit_pending();
+        }
+        Ok(())
+    }
+
+    /// Tracks a command as in flight; `args` are redacted before they are kept.
+    pub fn begin(&self, command: &str, args: &Value) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.push((id, command.to_string(), redact(args)));
+        }
+        id
+    }
+
+    /// Ends a command started with `begin`. When it failed, its name and arguments are
+    /// kept for the report made as its error is sent (see `record_error`).
+    pub fn finish<T, E: Reportable>(&self, id: u64, result: &Result<T, E>) {
+        let entry = self.in_flight.lock().ok().and_then(|mut in_flight| {
+            let index = in_flight.iter().position(|(other, _, _)| *other == id)?;
+            Some(in_flight.remove(index))
+        });
+        let (Some((_, command, args)), Err(e)) = (entry, result) else {
+            return;
+        };
+        if !e.is_reportable() {
+            return;
+        }
+        if let Ok(mut failed) = self.failed.lock() {
+            failed.push_back((e.to_string(), command, args));
+            while failed.len() > MAX_FAILED_COMMANDS {
+                failed.pop_front();
+            }
+        }
+    }
+
+    /// Stores the error a command returned, naming the command when it was traced.
+    fn record_error(&self, error: &PoleshiftError) {
+        if !error.is_reportable() {
+            return;
+        }
+        let message = error.to_string();
+        let (command, args) = self
+            .failed
+            .lock()
+            .ok()
+            .and_then(|mut failed| {
+                let index = failed.iter().position(|(other, _, _)| *other == message)?;
+                failed.remove(index)
+            })
+            .map_or((None, Value::Null), |(_, command, args)| {
+                (Some(command), args)
+            });
+        let report = self.report(ErrorKind::Error, command, args, message);
+        self.store(&report);
+        if self.consent.load(Ordering::Relaxed) {
+            self.submit_pending();
+        }
+    }
+
+    fn report(
+        &self,
+        kind: ErrorKind,
+        command: Option<String>,
+        args: Value,
+        message: String,
+    ) -> ErrorReport {
+        ErrorReport {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            command,
+            args,
+            message: redact_text(&message),
+            location: None,
+            backtrace: None,
+            app_version: self
+                .app_version
+                .try_lock()
+                .map(|v| v.clone())
+                .unwrap_or_default(),
+            os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+            created_at_ms: now_ms(),
+            submitted: false,
+        }
+    }
+
+    /// Stores a panic. Uses `try_lock` throughout, as the panicking thread may hold
+    /// one of the reporter's locks.
+    fn record_panic(&self, info: &PanicHookInfo<'_>) {
+        let message = if let Some(text) = info.payload().downcast_ref::<&str>() {
+            text.to_string()
+        } else if let Some(text) = info.payload().downcast_ref::<String>() {
+            text.clone()
+        } else {
+            "panic without a message".to_string()
+        };
+        let (command, args) = self
+            .in_flight
+            .try_lock()
+            .ok()
+            .and_then(|in_flight| in_flight.last().cloned())
+            .map_or((None, Value::Null), |(_, command, args)| {
+                (Some(command), args)
+            });
+        let mut report = self.report(ErrorKind::Panic, command, args, message);
+        report.location = info
+            .location()
+            .map(|l| redact_text(&format!("{}:{}", l.file(), l.line())));
+        report.backtrace = Some(redact_text(&Backtrace::force_capture().to_string()));
+        self.store(&report);
+    }
+
+    fn dir(&self) -> Option<PathBuf> {
+        self.dir.try_lock().ok().and_then(|dir| dir.clone())
+    }
+
+    /// Writes a report to the store, dropping the oldest beyond `MAX_STORED_REPORTS`.
+    /// Failures are ignored: there is nowhere left to report them.
+    fn store(&self, report: &ErrorReport) {
+        let Some(dir) = self.dir() else { return };
+        if let Ok(json) = serde_json::to_vec_pretty(report) {
+            let _ = fs::write(dir.join(format!("{}.json", report.id)), json);
+        }
+        let reports = read_reports(&dir);
+        for old in reports.iter().skip(MAX_STORED_REPORTS) {
+            let _ = fs::remove_file(dir.join(format!("{}.json", old.id)));
+        }
+    }
+
+    /// Stored reports, newest first.
+    pub fn list(&self) -> Vec<ErrorReport> {
+        self.dir().map(|dir| read_reports(&dir)).unwrap_or_default()
+    }
+
+    pub fn set_consent(&self, consent: bool) -> Result<(), PoleshiftError> {
+        let dir = self.dir().ok_or_else(|| {
+            PoleshiftError::DataError("Error reporting is not set up".to_string())
+        })?;
+        fs::write(
+            dir.join(CONSENT_FILE),
+            serde_json::to_vec(&ConsentFile { consent })?,
+        )?;
+        self.consent.store(consent, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Takes the submission slot, unless reports are already being submitted.
+    fn start_submission(&self) -> Option<Submission> {
+        self.submitting
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| Submission(self.submitting.clone()))
+    }
+
+    /// Submits the reports not yet sent in the background, unless a submission is
+    /// already running.
+    fn submit_pending(&self) {
+        let (Some(dir), Some(_)) = (self.dir(), REPORT_ENDPOINT) else {
+            return;
+        };
+        if let Some(submission) = self.start_submission() {
+            tauri::async_runtime::spawn(async move {
+                let _ = submit_reports(&dir).await;
+                drop(submission);
+            });
+        }
+    }
+}
+
+/// Stored reports, newest first; unreadable files are skipped.
+fn read_reports(dir: &Path) -> Vec<ErrorReport> {
+    let mut reports: Vec<ErrorReport> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "json")
+                && path.file_name().is_some_and(|name| name != CONSENT_FILE)
+        })
+        .filter_map(|path| serde_json::from_slice(&fs::read(path).ok()?).ok())
+        .collect();
+    reports.sort_by_key(|report| std::cmp::Reverse(report.created_at_ms));
+    reports
+}
+
+/// Posts each unsent report to `REPORT_ENDPOINT` and marks it sent. Returns how many
+/// were sent.
+async fn submit_reports(dir: &Path) -> Result<usize, PoleshiftError> {
+    let endpoint = REPORT_ENDPOINT.ok_or_else(|| {
+        PoleshiftError::DataError("This build has no error report endpoint".to_string())
+    })?;
+    let client = reqwest::Client::new();
+    let mut sent = 0;
+    for mut report in read_reports(dir).into_iter().filter(|r| !r.submitted) {
+        client
+            .post(endpoint)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&report)?)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| PoleshiftError::DataError(format!("Submitting a report: {}", e)))?;
+        report.submitted = true;
+        fs::write(
+            dir.join(format!("{}.json", report.id)),
+            serde_json::to_vec_pretty(&report)?,
+        )?;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+/// Lists the stored error reports, newest first, for the user to review before
+/// agreeing to send them.
+#[tauri::command(rename_all = "snake_case")]
+pub fn list_error_reports(
+    invocation: Invocation,
+    reporter: State<'_, ErrorReporter>,
+) -> Result<StandardResponseNoFiles<Vec<ErrorReport>>, PoleshiftError> {
+    traced_blocking(invocation, || {
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: reporter.list(),
+        })
+    })
+}
+
+/// Records whether the user agrees to error reports being sent; kept across runs.
+#[tauri::command(rename_all = "snake_case")]
+pub fn set_error_reporting_consent(
+    invocation: Invocation,
+    reporter: State<'_, ErrorReporter>,
+    consent: bool,
+) -> Result<(), PoleshiftError> {
+    traced_blocking(invocation, || reporter.set_consent(consent))
+}
+
+/// Sends the stored reports not yet sent. Refused without the user's consent.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn submit_error_reports(
+    invocation: Invocation,
+    reporter: State<'_, ErrorReporter>,
+) -> Result<StandardResponseNoFiles<usize>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if !reporter.consent.load(Ordering::Relaxed) {
+            return Err(PoleshiftError::InvalidInput {
+                field: "consent".to_string(),
+                reason: "error reports are only sent once the user agrees".to_string(),
+            });
+        }
+        let dir = reporter.dir().ok_or_else(|| {
+            PoleshiftError::DataError("Error reporting is not set up".to_string())
+        })?;
+        let Some(_submission) = reporter.start_submission() else {
+            return Err(PoleshiftError::DataError(
+                "Error reports are already being sent".to_string(),
+            ));
+        };
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: submit_reports(&dir).await?,
+        })
+    })
+    .await
+}
+
+/// Deletes every stored report.
+#[tauri::command(rename_all = "snake_case")]
+pub fn clear_error_reports(
+    invocation: Invocation,
+    reporter: State<'_, ErrorReporter>,
+) -> Result<(), PoleshiftError> {
+    traced_blocking(invocation, || {
+        let Some(dir) = reporter.dir() else {
+            return Ok(());
+        };
+        for report in read_reports(&dir) {
+            fs::remove_file(dir.join(format!("{}.json", report.id)))?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,97 @@
+//poleshift/src-tauri/src/fastq_tools/mod.rs
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::io::merge;
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+#[derive(Debug, Serialize)]
+pub struct MergeSummary {
+    pub output_path: String,
+    pub files_merged: usize,
+    pub records_written: usize,
+}
+
+/// Merges plain and gzipped FASTQ inputs (in the given order) into one `.fastq.gz`.
+/// Runs as a job (ID `job_id`, or a generated one) reporting each merged file on
+/// `job://{job_id}/progress`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn merge_fastq_files<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    file_paths: Vec<String>,
+    output_path: String,
+    job_id: Option<String>,
+) -> Result<StandardResponseNoFiles<MergeSummary>, PoleshiftError> {
+    let trace_handle = app_handle.clone();
+    let jobs = trace_handle.state::<JobManager>();
+    let job = jobs.start("merge", job_id)?;
+    let result = traced(
+        invocation,
+        Some(job.id()),
+        run_merge(app_handle, file_paths, output_path, job.clone()),
+    )
+    .await;
+    jobs.finish(job.id(), result.as_ref().err());
+    result
+}
+
+async fn run_merge<R: Runtime>(
+    app_handle: AppHandle<R>,
+    file_paths: Vec<String>,
+    output_path: String,
+    job: JobHandle,
+) -> Result<StandardResponseNoFiles<MergeSummary>, PoleshiftError> {
+    if file_paths.is_empty() {
+        return Err(PoleshiftError::NoFiles);
+    }
+
+    let window = app_handle
+        .get_window("main")
+        .ok_or_else(|| PoleshiftError::WindowNotFound)?;
+
+    job.set_stage("merging");
+    job.report_progress(&window, 0, "Merging FASTQ files...", "processing")?;
+
+    let inputs: Vec<PathBuf> = file_paths.iter().map(PathBuf::from).collect();
+    let output = PathBuf::from(&output_path);
+    if merge::output_is_input(&inputs, &output) {
+        return Err(PoleshiftError::InvalidInput {
+            field: "output_path".to_string(),
+            reason: "must not be one of the files being merged".to_string(),
+        });
+    }
+    let file_count = inputs.len();
+
+    let progress_window = window.clone();
+    let progress_job = job.clone();
+    let records_written = tauri::async_runtime::spawn_blocking(move || {
+        merge::merge_fastq_files(&inputs, &output, |index, path: &Path, records| {
+            let pct = (((index + 1) * 100) / file_count).min(100) as u8;
+            let message = format!(
+                "Merged {} ({} records)",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                records
+            );
+            let _ = progress_job.report_progress(&progress_window, pct, &message, "processing");
+        })
+    })
+    .await
+    .map_err(|e| PoleshiftError::Other(e.to_string()))??;
+
+    job.report_progress(&window, 100, "Merge complete", "complete")?;
+
+    Ok(StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report: MergeSummary {
+            output_path,
+            files_merged: file_count,
+            records_written,
+        },
+    })
+}
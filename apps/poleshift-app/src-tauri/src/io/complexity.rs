@@ -0,0 +1,106 @@
+// io/complexity.rs
+use serde::{Deserialize, Serialize};
+
+/// Triplet alphabet used by both scores; anything outside ACGT breaks a triplet.
+const TRIPLETS: usize = 64;
+/// Window length of the DUST score, as in the original algorithm.
+const DUST_WINDOW: usize = 64;
+
+/// How read complexity is scored.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplexityMethod {
+    /// Shannon entropy of the trinucleotide composition, normalised to 0..=1.
+    /// Reads scoring *below* the threshold are dropped (0.5 is a reasonable start).
+    Entropy,
+    /// DUST score, `sum(c * (c - 1) / 2) / (l - 1)` over the `l` trinucleotides of each
+    /// 64 bp window, averaged across the read. Reads scoring *above* the threshold are
+    /// dropped (20 is a reasonable start).
+    Dust,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComplexityOptions {
+    pub method: ComplexityMethod,
+    pub threshold: f64,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ComplexityStats {
+    pub reads_in: usize,
+    pub reads_removed: usize,
+    pub bases_removed: u64,
+}
+
+fn triplet_counts(sequence: &str) -> ([u32; TRIPLETS], u32) {
+    let mut counts = [0u32; TRIPLETS];
+    let mut total = 0;
+    let code = |b: u8| match b.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' | b'U' => Some(3),
+        _ => None,
+    };
+
+    for window in sequence.as_bytes().windows(3) {
+        if let (Some(a), Some(b), Some(c)) = (code(window[0]), code(window[1]), code(window[2])) {
+            counts[a * 16 + b * 4 + c] += 1;
+            total += 1;
+        }
+    }
+    (counts, total)
+}
+
+/// Trinucleotide entropy of `sequence`, 0 for homopolymers and 1 for maximally mixed reads.
+pub fn entropy_score(sequence: &str) -> f64 {
+    let (counts, total) = triplet_counts(sequence);
+    if total < 2 {
+        return 0.0;
+    }
+    let total = total as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    // Short reads cannot contain all 64 triplets, so normalise by what is attainable
+    entropy / total.min(TRIPLETS as f64).log2()
+}
+
+/// Mean windowed DUST score of `sequence`; higher means lower complexity.
+pub fn dust_score(sequence: &str) -> f64 {
+    let scores: Vec<f64> = sequence
+        .as_bytes()
+        .chunks(DUST_WINDOW)
+        .filter(|chunk| chunk.len() >= DUST_WINDOW / 2 || chunk.len() == sequence.len())
+        .map(|chunk| window_dust_score(&String::from_utf8_lossy(chunk)))
+        .collect();
+    if scores.is_empty() {
+        return 0.0;
+    }
+    scores.iter().sum::<f64>() / scores.len() as f64
+}
+
+fn window_dust_score(sequence: &str) -> f64 {
+    let (counts, total) = triplet_counts(sequence);
+    if total < 2 {
+        return 0.0;
+    }
+    let repeats: f64 = counts
+        .iter()
+        .map(|&c| c as f64 * (c as f64 - 1.0) / 2.0)
+        .sum();
+    repeats / (total as f64 - 1.0)
+}
+
+/// Whether `sequence` fails the low-complexity filter.
+pub fn is_low_complexity(sequence: &str, options: &ComplexityOptions) -> bool {
+    match options.method {
+        ComplexityMethod::Entropy => entropy_score(sequence) < options.threshold,
+        ComplexityMethod::Dust => dust_score(sequence) > options.threshold,
+    }
+}
@@ -27,44 +27,47 @@ impl<R: Read> FastqReader<R> {
         }
     }
 
-    /// Collects all records into a vector for parallel processing
-    pub fn collect_records(&mut self) -> Result<Vec<FastqRecord>, ParseError> {
-        let mut records = Vec::new();
+    /// Reads the next record, returning `Ok(None)` at EOF
+    pub fn read_record(&mut self) -> Result<Option<FastqRecord>, ParseError> {
+        // Read the four lines of a FASTQ record
+        let header = match self.read_next_line()? {
+            Some(line) if line.starts_with('@') => line[0..].to_string(),
+            Some(_) => return Err(ParseError::Fastq(FastqError::MissingHeader)),
+            None => return Ok(None), // EOF
+        };
 
-        loop {
-            // Read the four lines of a FASTQ record
-            let header = match self.read_next_line()? {
-                Some(line) if line.starts_with('@') => line[0..].to_string(),
-                Some(_) => return Err(ParseError::Fastq(FastqError::MissingHeader)),
-                None => break, // EOF
-            };
+        let sequence = match self.read_next_line()? {
+            Some(line) => line,
+            None => return Err(ParseError::Fastq(FastqError::MissingSequence)),
+        };
 
-            let sequence = match self.read_next_line()? {
-                Some(line) => line,
-                None => return Err(ParseError::Fastq(FastqError::MissingSequence)),
-            };
+        // Skip the + line but verify it exists
+        match self.read_next_line()? {
+            Some(line) if line.starts_with('+') => (),
+            Some(_) => return Err(ParseError::Fastq(FastqError::MissingQuality)),
+            None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
+        };
 
-            // Skip the + line but verify it exists
-            match self.read_next_line()? {
-                Some(line) if line.starts_with('+') => (),
-                Some(_) => return Err(ParseError::Fastq(FastqError::MissingQuality)),
-                None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
-            };
+        let quality_string = match self.read_next_line()? {
+            Some(line) => line,
+            None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
+        };
 
-            let quality_string = match self.read_next_line()? {
-                Some(line) => line,
-                None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
-            };
+        // Convert quality string to quality scores
+        let quality: Vec<u8> = quality_string.as_bytes().to_vec();
 
-            // Convert quality string to quality scores
-            let quality: Vec<u8> = quality_string.as_bytes().to_vec();
+        Ok(Some(FastqRecord {
+            header,
+            sequence,
+            quality,
+        }))
+    }
 
-            let record = FastqRecord {
-                header,
-                sequence,
-                quality,
-            };
+    /// Collects all records into a vector for parallel processing
+    pub fn collect_records(&mut self) -> Result<Vec<FastqRecord>, ParseError> {
+        let mut records = Vec::new();
 
+        while let Some(record) = self.read_record()? {
             records.push(record);
         }
 
@@ -47,13 +47,13 @@ impl<R: Read> FastqGzReader<R> {
         }
     }
 
-    /// Collect all FASTQ records in the gzipped file. Each record is four lines:
+    /// Read the next FASTQ record. Each record is four lines:
     /// 1) Header (starting with '@')
     /// 2) Sequence
     /// 3) Plus line (starting with '+')
     /// 4) Quality scores
     ///
-    /// Returns a vector of [`FastqRecord`] if successfully parsed.
+    /// Returns `Ok(None)` once EOF is reached on a record boundary.
     ///
     /// # Errors
     ///
@@ -61,45 +61,54 @@ impl<R: Read> FastqGzReader<R> {
     /// - The header line does not start with '@'
     /// - Any line is missing (truncated file)
     /// - The quality line is missing
-    pub fn collect_records(&mut self) -> Result<Vec<FastqRecord>, ParseError> {
-        let mut records = Vec::new();
+    pub fn read_record(&mut self) -> Result<Option<FastqRecord>, ParseError> {
+        // 1) Read header line. Must begin with '@'
+        let header_line = match self.read_next_line()? {
+            Some(line) if line.starts_with('@') => {
+                // skip the '@' character and store the rest
+                line[0..].to_string()
+            }
+            Some(_) => return Err(ParseError::Fastq(FastqError::MissingHeader)),
+            None => return Ok(None), // EOF encountered
+        };
 
-        loop {
-            // 1) Read header line. Must begin with '@'
-            let header_line = match self.read_next_line()? {
-                Some(line) if line.starts_with('@') => {
-                    // skip the '@' character and store the rest
-                    line[0..].to_string()
-                }
-                Some(_) => return Err(ParseError::Fastq(FastqError::MissingHeader)),
-                None => break, // EOF encountered
-            };
+        // 2) Read sequence line
+        let seq_line = match self.read_next_line()? {
+            Some(line) => line,
+            None => return Err(ParseError::Fastq(FastqError::MissingSequence)),
+        };
 
-            // 2) Read sequence line
-            let seq_line = match self.read_next_line()? {
-                Some(line) => line,
-                None => return Err(ParseError::Fastq(FastqError::MissingSequence)),
-            };
+        // 3) Read plus line (must begin with '+')
+        match self.read_next_line()? {
+            Some(line) if line.starts_with('+') => (),
+            Some(_) | None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
+        }
 
-            // 3) Read plus line (must begin with '+')
-            match self.read_next_line()? {
-                Some(line) if line.starts_with('+') => (),
-                Some(_) | None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
-            }
+        // 4) Read quality line
+        let qual_line = match self.read_next_line()? {
+            Some(line) => line,
+            None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
+        };
 
-            // 4) Read quality line
-            let qual_line = match self.read_next_line()? {
-                Some(line) => line,
-                None => return Err(ParseError::Fastq(FastqError::MissingQuality)),
-            };
+        Ok(Some(FastqRecord {
+            header: header_line,
+            sequence: seq_line,
+            // Convert quality ASCII string into raw bytes
+            quality: qual_line.into_bytes(),
+        }))
+    }
 
-            let record = FastqRecord {
-                header: header_line,
-                sequence: seq_line,
-                // Convert quality ASCII string into raw bytes
-                quality: qual_line.into_bytes(),
-            };
+    /// Collect all FASTQ records in the gzipped file.
+    ///
+    /// Returns a vector of [`FastqRecord`] if successfully parsed.
+    ///
+    /// # Errors
+    ///
+    /// See [`FastqGzReader::read_record`].
+    pub fn collect_records(&mut self) -> Result<Vec<FastqRecord>, ParseError> {
+        let mut records = Vec::new();
 
+        while let Some(record) = self.read_record()? {
             records.push(record);
         }
 
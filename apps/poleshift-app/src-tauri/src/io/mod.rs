@@ -1,7 +1,12 @@
 use thiserror::Error;
 
+pub mod complexity;
 pub mod fastq;
 pub mod fastqgz;
+pub mod merge;
+pub mod packed;
+pub mod source;
+pub mod umi;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FastqRecord {
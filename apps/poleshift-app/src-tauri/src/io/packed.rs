@@ -0,0 +1,157 @@
+// io/packed.rs
+use serde::{Serialize, Serializer};
+
+/// 4-bit nucleotide alphabet (same code order as BAM), index = code.
+const FOUR_BIT_ALPHABET: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+/// 2-bit alphabet used when a read only contains A/C/G/T.
+const TWO_BIT_ALPHABET: &[u8; 4] = b"ACGT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedEncoding {
+    /// Four bases per byte, only A/C/G/T
+    TwoBit,
+    /// Two bases per byte, IUPAC ambiguity codes allowed
+    FourBit,
+}
+
+/// A nucleotide sequence stored at 2 or 4 bits per base.
+///
+/// Packing is lossy: lowercase bases are upper-cased (soft-masking is dropped) and
+/// characters outside the IUPAC alphabet are stored as `N`, so `unpack` returns the
+/// upper-case IUPAC form of the input. Serializes as that string, so the JSON sent
+/// to the frontend is unchanged for ordinary reads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedSequence {
+    len: usize,
+    encoding: PackedEncoding,
+    data: Vec<u8>,
+}
+
+impl PackedSequence {
+    /// Packs a sequence, choosing 2-bit packing whenever possible.
+    pub fn pack(sequence: &str) -> Self {
+        let bases = sequence.as_bytes();
+        let two_bit = bases
+            .iter()
+            .all(|b| matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T'));
+
+        if two_bit {
+            let mut data = vec![0u8; bases.len().div_ceil(4)];
+            for (i, base) in bases.iter().enumerate() {
+                let code = match base.to_ascii_uppercase() {
+                    b'A' => 0,
+                    b'C' => 1,
+                    b'G' => 2,
+                    _ => 3,
+                };
+                data[i / 4] |= code << ((i % 4) * 2);
+            }
+            PackedSequence {
+                len: bases.len(),
+                encoding: PackedEncoding::TwoBit,
+                data,
+            }
+        } else {
+            let mut data = vec![0u8; bases.len().div_ceil(2)];
+            for (i, base) in bases.iter().enumerate() {
+                let upper = base.to_ascii_uppercase();
+                let code = FOUR_BIT_ALPHABET
+                    .iter()
+                    .position(|&c| c == upper)
+                    .unwrap_or(15) as u8;
+                data[i / 2] |= code << ((i % 2) * 4);
+            }
+            PackedSequence {
+                len: bases.len(),
+                encoding: PackedEncoding::FourBit,
+                data,
+            }
+        }
+    }
+
+    /// Number of bases in the sequence.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes used by the packed representation.
+    pub fn packed_size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the base at `index`.
+    pub fn base(&self, index: usize) -> Option<char> {
+        if index >= self.len {
+            return None;
+        }
+        let base = match self.encoding {
+            PackedEncoding::TwoBit => {
+                let code = (self.data[index / 4] >> ((index % 4) * 2)) & 0b11;
+                TWO_BIT_ALPHABET[code as usize]
+            }
+            PackedEncoding::FourBit => {
+                let code = (self.data[index / 2] >> ((index % 2) * 4)) & 0b1111;
+                FOUR_BIT_ALPHABET[code as usize]
+            }
+        };
+        Some(base as char)
+    }
+
+    /// Decodes the sequence back into a `String`.
+    pub fn unpack(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        (0..self.len).filter_map(|i| self.base(i)).collect()
+    }
+}
+
+impl std::fmt::Display for PackedSequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.unpack())
+    }
+}
+
+impl Serialize for PackedSequence {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.unpack())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_acgt_at_two_bits() {
+        for sequence in ["", "A", "ACGTA", "GATTACAGATTACA"] {
+            let packed = PackedSequence::pack(sequence);
+            assert_eq!(packed.unpack(), sequence);
+            assert_eq!(packed.len(), sequence.len());
+            assert_eq!(packed.packed_size(), sequence.len().div_ceil(4));
+        }
+    }
+
+    #[test]
+    fn upper_cases_mixed_case_bases() {
+        let packed = PackedSequence::pack("acgTtgCA");
+        assert_eq!(packed.unpack(), "ACGTTGCA");
+        assert_eq!(packed.packed_size(), 2);
+    }
+
+    #[test]
+    fn keeps_ambiguity_codes_and_stores_other_bytes_as_n() {
+        let packed = PackedSequence::pack("ACGTRYKMSWBDHVN");
+        assert_eq!(packed.unpack(), "ACGTRYKMSWBDHVN");
+        assert_eq!(PackedSequence::pack("acn").unpack(), "ACN");
+        assert_eq!(PackedSequence::pack("AC*G-U").unpack(), "ACNGNN");
+        assert_eq!(PackedSequence::pack("ryk").base(1), Some('Y'));
+    }
+}
@@ -0,0 +1,43 @@
+// io/source.rs
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::fastq::FastqReader;
+use super::fastqgz::FastqGzReader;
+use super::{FastqRecord, ParseError};
+
+/// A FASTQ reader over a plain or gzipped file.
+///
+/// Compression is detected from the gzip magic bytes rather than the file extension,
+/// so mislabelled files and concatenated gzip members are both handled.
+pub enum FastqSource {
+    Plain(FastqReader<File>),
+    Gzip(FastqGzReader<File>),
+}
+
+impl FastqSource {
+    /// Opens `path`, sniffing the first two bytes for the gzip signature.
+    pub fn open(path: &Path) -> Result<Self, ParseError> {
+        let mut magic = [0u8; 2];
+        let is_gz = {
+            let mut probe = File::open(path)?;
+            probe.read(&mut magic)? == 2 && magic == [0x1f, 0x8b]
+        };
+
+        let file = File::open(path)?;
+        Ok(if is_gz {
+            FastqSource::Gzip(FastqGzReader::new(file))
+        } else {
+            FastqSource::Plain(FastqReader::new(file))
+        })
+    }
+
+    /// Reads the next record, returning `Ok(None)` at EOF
+    pub fn read_record(&mut self) -> Result<Option<FastqRecord>, ParseError> {
+        match self {
+            FastqSource::Plain(reader) => reader.read_record(),
+            FastqSource::Gzip(reader) => reader.read_record(),
+        }
+    }
+}
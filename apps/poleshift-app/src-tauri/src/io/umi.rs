@@ -0,0 +1,222 @@
+// io/umi.rs
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::FastqRecord;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Header token used to carry an extracted UMI through rewritten FASTQ files.
+pub const UMI_HEADER_KEY: &str = "umi";
+
+/// Where a read's UMI is found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UmiSource {
+    /// A `key=VALUE` token in the header, e.g. `umi=ACGTACGTACGT`
+    HeaderTag { key: String },
+    /// The read name suffix after the last `separator`, e.g. `@read1_ACGTACGTACGT`
+    ReadNameSuffix { separator: char },
+    /// Leading bases described by `N` (UMI base) and `X` (linker base, discarded).
+    /// Both are trimmed from the sequence and quality.
+    LeadingBases { pattern: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UmiOptions {
+    pub source: UmiSource,
+    /// Reads whose UMI is not exactly this long are treated as having no UMI
+    pub expected_length: Option<usize>,
+    /// Collapse reads sharing a UMI down to the best-quality read
+    #[serde(default)]
+    pub deduplicate: bool,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct UmiStats {
+    pub reads_in: usize,
+    pub reads_with_umi: usize,
+    pub unique_umis: usize,
+    pub duplicates_removed: usize,
+}
+
+impl UmiOptions {
+    /// Checks that a `LeadingBases` pattern is made of `N` and `X` only, with at least
+    /// one `N`, and can yield a UMI of `expected_length`.
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let UmiSource::LeadingBases { pattern } = &self.source else {
+            return Ok(());
+        };
+        let invalid = |reason: String| PoleshiftError::InvalidInput {
+            field: "umi.pattern".to_string(),
+            reason,
+        };
+        if let Some(c) = pattern
+            .chars()
+            .find(|c| !matches!(c, 'N' | 'n' | 'X' | 'x'))
+        {
+            return Err(invalid(format!("'{}' is neither N nor X", c)));
+        }
+        let umi_bases = pattern
+            .chars()
+            .filter(|c| c.eq_ignore_ascii_case(&'N'))
+            .count();
+        if umi_bases == 0 {
+            return Err(invalid("must contain at least one N".to_string()));
+        }
+        match self.expected_length {
+            Some(len) if len != umi_bases => Err(invalid(format!(
+                "has {} UMI bases but expected_length is {}",
+                umi_bases, len
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Extracts the UMI from `record` according to `options`. When the UMI is part of the
+/// sequence, its leading bases are trimmed once the UMI is accepted. Returns `None`,
+/// leaving the record as it was, when no valid UMI is present.
+pub fn extract_umi(record: &mut FastqRecord, options: &UmiOptions) -> Option<String> {
+    let umi = match &options.source {
+        UmiSource::HeaderTag { key } => {
+            let prefix = format!("{}=", key);
+            record
+                .header
+                .split_whitespace()
+                .find_map(|token| token.strip_prefix(prefix.as_str()))
+                .map(|v| v.to_string())
+        }
+        UmiSource::ReadNameSuffix { separator } => record
+            .header
+            .split_whitespace()
+            .next()
+            .and_then(|name| name.rsplit_once(*separator))
+            .map(|(_, suffix)| suffix.to_string()),
+        UmiSource::LeadingBases { pattern } => {
+            let pattern = pattern.as_bytes();
+            if record.sequence.len() <= pattern.len() {
+                return None;
+            }
+            // Bases are ASCII; anything else is no UMI, and would not be cut on a
+            // character boundary below
+            let leading = &record.sequence.as_bytes()[..pattern.len()];
+            if !leading.is_ascii() {
+                return None;
+            }
+            Some(
+                leading
+                    .iter()
+                    .zip(pattern)
+                    .filter(|(_, p)| p.eq_ignore_ascii_case(&b'N'))
+                    .map(|(b, _)| *b as char)
+                    .collect(),
+            )
+        }
+    }?;
+
+    if umi.is_empty() || options.expected_length.is_some_and(|len| umi.len() != len) {
+        return None;
+    }
+    if let UmiSource::LeadingBases { pattern } = &options.source {
+        record.sequence.drain(..pattern.len());
+        record
+            .quality
+            .drain(..pattern.len().min(record.quality.len()));
+    }
+    Some(umi)
+}
+
+/// Appends `umi=VALUE` to the header unless it is already present.
+pub fn tag_header(record: &mut FastqRecord, umi: &str) {
+    let token = format!("{}={}", UMI_HEADER_KEY, umi);
+    if !record.header.split_whitespace().any(|t| t == token) {
+        record.header.push(' ');
+        record.header.push_str(&token);
+    }
+}
+
+fn mean_quality(record: &FastqRecord) -> f64 {
+    if record.quality.is_empty() {
+        return 0.0;
+    }
+    record.quality.iter().map(|&q| q as f64).sum::<f64>() / record.quality.len() as f64
+}
+
+/// Picks the read kept for every UMI, the one with the highest mean quality (the
+/// first of them on a tie). Reads are offered with their index in input order, then
+/// asked about with the same index, so the reads themselves are never held.
+#[derive(Debug, Default)]
+pub struct UmiDeduplicator {
+    best: HashMap<String, (usize, f64)>,
+}
+
+impl UmiDeduplicator {
+    pub fn offer(&mut self, index: usize, record: &FastqRecord, umi: &str) {
+        let quality = mean_quality(record);
+        let entry = self.best.entry(umi.to_string()).or_insert((index, quality));
+        if quality > entry.1 {
+            *entry = (index, quality);
+        }
+    }
+
+    /// Whether read `index` is the one kept for `umi`; a UMI never offered keeps it.
+    pub fn keeps(&self, index: usize, umi: &str) -> bool {
+        self.best.get(umi).is_none_or(|(best, _)| *best == index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leading_bases(pattern: &str, expected_length: Option<usize>) -> UmiOptions {
+        UmiOptions {
+            source: UmiSource::LeadingBases {
+                pattern: pattern.to_string(),
+            },
+            expected_length,
+            deduplicate: false,
+        }
+    }
+
+    fn record(sequence: &str) -> FastqRecord {
+        FastqRecord {
+            header: "@read1".to_string(),
+            sequence: sequence.to_string(),
+            quality: vec![b'I'; sequence.len()],
+        }
+    }
+
+    #[test]
+    fn rejects_leading_bases_that_are_not_ascii() {
+        let mut read = record("AC\u{e9}TTTGGCC");
+        assert_eq!(extract_umi(&mut read, &leading_bases("NNN", None)), None);
+        assert_eq!(read.sequence, "AC\u{e9}TTTGGCC");
+    }
+
+    #[test]
+    fn trims_leading_bases_only_for_an_accepted_umi() {
+        let mut accepted = record("ACGTTTGGCC");
+        let umi = extract_umi(&mut accepted, &leading_bases("NNNNXX", Some(4)));
+        assert_eq!(umi.as_deref(), Some("ACGT"));
+        assert_eq!(accepted.sequence, "GGCC");
+        assert_eq!(accepted.quality.len(), 4);
+
+        let mut rejected = record("ACGTTTGGCC");
+        assert_eq!(
+            extract_umi(&mut rejected, &leading_bases("NNNNXX", Some(6))),
+            None
+        );
+        assert_eq!(rejected.sequence, "ACGTTTGGCC");
+        assert_eq!(rejected.quality.len(), 10);
+    }
+
+    #[test]
+    fn rejects_unusable_patterns() {
+        assert!(leading_bases("NNNNXX", Some(4)).validate().is_ok());
+        assert!(leading_bases("NNAN", None).validate().is_err());
+        assert!(leading_bases("XX", None).validate().is_err());
+        assert!(leading_bases("NNNNXX", Some(6)).validate().is_err());
+    }
+}
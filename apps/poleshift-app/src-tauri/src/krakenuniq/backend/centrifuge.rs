@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use krakenuniq_rs::{ClassificationResults, OutputLine};
+
+use crate::krakenuniq::backend::sidecar::{in_work_dir, run_executable};
+use crate::krakenuniq::backend::{ClassificationBackend, ClassificationRequest};
+use crate::krakenuniq::report_parser::parse_kraken_report;
+use crate::poleshift_common::jobs::JobHandle;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Suffixes of the files a Centrifuge index prefix stands for.
+pub(super) const CENTRIFUGE_INDEX_SUFFIXES: [&str; 3] = [".1.cf", ".2.cf", ".3.cf"];
+
+/// Classifies by running `centrifuge` against an existing Centrifuge index, then
+/// `centrifuge-kreport` to turn its per-read output into a Kraken-style report.
+///
+/// Centrifuge scores reads by alignment rather than k-mers, so the report has no
+/// k-mer, duplication or coverage figures and those are 0.
+pub(crate) struct CentrifugeBackend {
+    pub executable: PathBuf,
+    /// The index prefix, as passed to `centrifuge -x`
+    pub index: PathBuf,
+}
+
+impl CentrifugeBackend {
+    /// `centrifuge-kreport` next to the `centrifuge` executable, else from `PATH`.
+    fn kreport_executable(&self) -> PathBuf {
+        let name = if cfg!(windows) {
+            "centrifuge-kreport.exe"
+        } else {
+            "centrifuge-kreport"
+        };
+        self.executable
+            .parent()
+            .map(|dir| dir.join(name))
+            .filter(|path| path.is_file())
+            .unwrap_or_else(|| PathBuf::from(name))
+    }
+}
+
+impl ClassificationBackend for CentrifugeBackend {
+    fn classify(
+        &self,
+        request: ClassificationRequest,
+        job: &JobHandle,
+    ) -> Result<ClassificationResults, PoleshiftError> {
+        in_work_dir(|work_dir| {
+            let index = self.index.to_string_lossy().to_string();
+            let output = work_dir.join("output.tsv");
+
+            // 1) Classify, keeping one assignment per read as KrakenUniq does
+            let mut args = vec![
+                "-x".to_string(),
+                index.clone(),
+                "-k".to_string(),
+                "1".to_string(),
+                "-S".to_string(),
+                output.to_string_lossy().to_string(),
+                "--report-file".to_string(),
+                work_dir.join("summary.tsv").to_string_lossy().to_string(),
+            ];
+            if let Some(threads) = request.options.threads {
+                args.extend(["-p".to_string(), threads.to_string()]);
+            }
+            let inputs: Vec<String> = request
+                .config
+                .input_files
+                .iter()
+                .map(|f| f.to_string_lossy().to_string())
+                .collect();
+            args.extend(["-U".to_string(), inputs.join(",")]);
+            run_executable(&self.executable, &args, None, &request, job, work_dir)?;
+
+            // 2) Build the report tree from the per-read output
+            let report = work_dir.join("report.tsv");
+            let args = vec![
+                "-x".to_string(),
+                index,
+                output.to_string_lossy().to_string(),
+            ];
+            run_executable(
+                &self.kreport_executable(),
+                &args,
+                Some(&report),
+                &request,
+                job,
+                work_dir,
+            )?;
+
+            // 3) Read back the outputs
+            let mut output_lines = read_centrifuge_output(&output)?;
+            if request.options.only_classified_output {
+                output_lines.retain(|line| line.status == 'C');
+            }
+            Ok(ClassificationResults {
+                kraken_output_lines: output_lines,
+                kraken_report_rows: Some(parse_kraken_report(&report)?),
+            })
+        })
+    }
+}
+
+/// Parses Centrifuge per-read output (`readID, seqID, taxID, score, 2ndBestScore,
+/// hitLength, queryLength, numMatches`). Only the first line of each read is kept;
+/// the hit list holds the best sequence and its hit length.
+fn read_centrifuge_output(path: &Path) -> Result<Vec<OutputLine>, PoleshiftError> {
+    let mut lines = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 8 {
+            continue;
+        }
+        let Ok(tax_id) = fields[2].trim().parse::<u32>() else {
+            // The column header line
+            continue;
+        };
+        if !seen.insert(fields[0].to_string()) {
+            continue;
+        }
+        lines.push(OutputLine {
+            status: if tax_id == 0 { 'U' } else { 'C' },
+            read_id: fields[0].to_string(),
+            tax_id,
+            length: fields[6].trim().parse().unwrap_or(0),
+            hitlist: format!("{}:{}", fields[1], fields[5].trim()),
+        });
+    }
+    Ok(lines)
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use krakenuniq_rs::classify::classify_reads::classify_reads_parallel;
+use krakenuniq_rs::classify::classify_sequence::TaxonCounts;
+use krakenuniq_rs::classify::classify_stats::build_kraken_report;
+use krakenuniq_rs::fastq::read_fastq_records;
+use krakenuniq_rs::krakendb::{KrakenDB, KrakenDBIndex};
+use krakenuniq_rs::taxdb::read_taxdb_and_counts;
+use krakenuniq_rs::ClassificationResults;
+
+use crate::krakenuniq::backend::{ClassificationBackend, ClassificationRequest};
+use crate::poleshift_common::jobs::JobHandle;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// NCBI tax ID the report is rooted at.
+const ROOT_TAX_ID: u32 = 1;
+/// Reads classified between checks for cancellation and the deadline.
+const READS_PER_BATCH: usize = 100_000;
+
+/// Classifies with the bundled `krakenuniq-rs`. The database, its index and the
+/// taxonomy are loaded once; the reads of each input file are then classified against
+/// them in batches, so after each batch the job's read count moves on, and a cancelled
+/// or timed-out run stops before the next. The report is built once over the counts of
+/// all files.
+pub(crate) struct InProcessBackend;
+
+impl ClassificationBackend for InProcessBackend {
+    fn classify(
+        &self,
+        request: ClassificationRequest,
+        job: &JobHandle,
+    ) -> Result<ClassificationResults, PoleshiftError> {
+        let ClassificationRequest {
+            config,
+            options,
+            deadline,
+            ..
+        } = request;
+        let mut pool = rayon::ThreadPoolBuilder::new();
+        if let Some(threads) = options.threads {
+            pool = pool.num_threads(threads);
+        }
+        let pool = pool.build().map_err(|e| {
+            PoleshiftError::Other(format!("Failed to start classifier threads: {}", e))
+        })?;
+
+        // 1) Load the database and taxonomy, once for all files
+        job.check_cancelled()?;
+        let mut db = KrakenDB::new()
+            .open_file(&config.db_file)
+            .map_err(|e| PoleshiftError::Other(e.to_string()))?;
+        let index = KrakenDBIndex::from_slice(Arc::from(std::fs::read(&config.idx_file)?))
+            .map_err(|e| PoleshiftError::Other(e.to_string()))?;
+        db.index_ptr = Some(Arc::new(index));
+        let db = Arc::new(db);
+        let taxonomy = read_taxdb_and_counts(&config.taxdb_file, &config.counts_file)
+            .map_err(|e| PoleshiftError::Other(e.to_string()))?;
+        let parent_map = Arc::new(taxonomy.parent_map);
+
+        // 2) Classify each file against it, adding up the taxon counts
+        let mut output_lines = Vec::new();
+        let mut taxon_counts = TaxonCounts::default();
+        let mut total_reads = 0usize;
+        for input in &config.input_files {
+            let reads = read_fastq_records(input.clone())?;
+            for batch in reads.chunks(READS_PER_BATCH) {
+                job.check_cancelled()?;
+                if deadline.is_some_and(|d| Instant::now() >= d) {
+                    return Err(PoleshiftError::Other(
+                        "Classification timed out and was stopped".to_string(),
+                    ));
+                }
+                // The classifier parallelises on the current rayon pool
+                let (_, _, lines, counts) = pool.install(|| {
+                    classify_reads_parallel(
+                        db.clone(),
+                        parent_map.clone(),
+                        batch,
+                        /* print_sequence_in_kraken = */ false,
+                        options.only_classified_output,
+                    )
+                });
+                for (tax_id, counts) in counts {
+                    let total = taxon_counts.entry(tax_id).or_default();
+                    total.read_count += counts.read_count;
+                    for (kmer, occurrences) in counts.kmer_counts {
+                        *total.kmer_counts.entry(kmer).or_insert(0) += occurrences;
+                    }
+                }
+                total_reads += batch.len();
+                job.add_reads(batch.len() as u64);
+                output_lines.extend(lines);
+            }
+        }
+        job.check_cancelled()?;
+
+        // 3) One report over all files
+        let (report_rows, _) = build_kraken_report(
+            &taxon_counts,
+            &parent_map,
+            Some(&taxonomy.name_map),
+            Some(&taxonomy.rank_map),
+            ROOT_TAX_ID,
+            total_reads as u32,
+            &taxonomy.total_counts,
+            &taxonomy.direct_counts,
+        );
+        Ok(ClassificationResults {
+            kraken_output_lines: output_lines,
+            kraken_report_rows: Some(report_rows),
+        })
+    }
+}
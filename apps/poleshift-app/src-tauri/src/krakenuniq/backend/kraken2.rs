@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use krakenuniq_rs::ClassificationResults;
+
+use crate::krakenuniq::backend::sidecar::{gzipped_inputs, in_work_dir, run_executable};
+use crate::krakenuniq::backend::{ClassificationBackend, ClassificationRequest};
+use crate::krakenuniq::report_parser::{parse_kraken_report, parse_kraken_uniq_output};
+use crate::poleshift_common::jobs::JobHandle;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Files every Kraken2 database directory holds.
+pub(super) const KRAKEN2_DATABASE_FILES: [&str; 3] = ["hash.k2d", "opts.k2d", "taxo.k2d"];
+
+/// Classifies by running a `kraken2` executable against an existing Kraken2 database,
+/// so labs do not need to build a KrakenUniq database first.
+///
+/// Kraken2 counts minimizers rather than unique k-mers and knows nothing of database
+/// coverage, so `kmers` holds the distinct minimizers, `dup` the minimizers per
+/// distinct one and `cov` is 0.
+pub(crate) struct Kraken2Backend {
+    pub executable: PathBuf,
+    /// The database directory
+    pub database: PathBuf,
+}
+
+impl Kraken2Backend {
+    fn arguments(
+        &self,
+        request: &ClassificationRequest,
+        work_dir: &Path,
+        gzipped: bool,
+    ) -> Vec<String> {
+        let options = &request.options;
+        let mut args = vec![
+            "--db".to_string(),
+            self.database.to_string_lossy().to_string(),
+            "--report".to_string(),
+            work_dir.join("report.tsv").to_string_lossy().to_string(),
+            "--report-minimizer-data".to_string(),
+            "--output".to_string(),
+            work_dir.join("output.tsv").to_string_lossy().to_string(),
+        ];
+        if let Some(threads) = options.threads {
+            args.extend(["--threads".to_string(), threads.to_string()]);
+        }
+        if let Some(min_hits) = options.min_hits {
+            args.extend(["--minimum-hit-groups".to_string(), min_hits.to_string()]);
+        }
+        if options.quick {
+            args.push("--quick".to_string());
+        }
+        if gzipped {
+            args.push("--gzip-compressed".to_string());
+        }
+        args.extend(
+            request
+                .config
+                .input_files
+                .iter()
+                .map(|f| f.to_string_lossy().to_string()),
+        );
+        args
+    }
+}
+
+impl ClassificationBackend for Kraken2Backend {
+    fn classify(
+        &self,
+        request: ClassificationRequest,
+        job: &JobHandle,
+    ) -> Result<ClassificationResults, PoleshiftError> {
+        in_work_dir(|work_dir| {
+            // 1) Run kraken2
+            let gzipped = gzipped_inputs(&request.config)?;
+            let args = self.arguments(&request, work_dir, gzipped);
+            run_executable(&self.executable, &args, None, &request, job, work_dir)?;
+
+            // 2) Read back the outputs; kraken2 has no flag to leave unclassified reads out
+            let mut output_lines = parse_kraken_uniq_output(&work_dir.join("output.tsv"))?;
+            if request.options.only_classified_output {
+                output_lines.retain(|line| line.status == 'C');
+            }
+            Ok(ClassificationResults {
+                kraken_output_lines: output_lines,
+                kraken_report_rows: Some(parse_kraken_report(&work_dir.join("report.tsv"))?),
+            })
+        })
+    }
+}
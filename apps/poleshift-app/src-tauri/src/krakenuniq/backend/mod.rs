@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use krakenuniq_rs::ClassificationResults;
+use serde::{Deserialize, Serialize};
+
+use crate::krakenuniq::options::ClassificationOptions;
+use crate::krakenuniq::preload::DatabaseLoadPlan;
+use crate::poleshift_common::jobs::JobHandle;
+use crate::poleshift_common::types::{KrakenConfig, PoleshiftError};
+
+mod centrifuge;
+mod in_process;
+mod kraken2;
+pub mod probe;
+mod sidecar;
+
+use centrifuge::CentrifugeBackend;
+use in_process::InProcessBackend;
+use kraken2::Kraken2Backend;
+use sidecar::SidecarBackend;
+
+/// Which classifier implementation the reads are handed to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// The `krakenuniq-rs` library linked into the app
+    #[default]
+    InProcess,
+    /// A `krakenuniq` executable run as a child process
+    Sidecar,
+    /// A `kraken2` executable, against the Kraken2 database at `database_path`
+    Kraken2,
+    /// A `centrifuge` executable, against the index prefix at `database_path`
+    Centrifuge,
+}
+
+impl BackendKind {
+    /// Name of the executable the backend runs, if it runs one.
+    pub fn executable_name(self) -> Option<&'static str> {
+        match self {
+            BackendKind::InProcess => None,
+            BackendKind::Sidecar => Some("krakenuniq"),
+            BackendKind::Kraken2 => Some("kraken2"),
+            BackendKind::Centrifuge => Some("centrifuge"),
+        }
+    }
+
+    /// Whether the backend classifies against the bundled KrakenUniq database. The
+    /// others bring their own, so no load plan is made for them.
+    pub fn uses_krakenuniq_database(self) -> bool {
+        matches!(self, BackendKind::InProcess | BackendKind::Sidecar)
+    }
+}
+
+/// Everything a backend needs for one classification.
+pub(crate) struct ClassificationRequest {
+    pub config: KrakenConfig,
+    pub options: ClassificationOptions,
+    /// Load strategy chosen under the memory budget, if one was planned
+    pub load_plan: Option<DatabaseLoadPlan>,
+    /// When the caller stops waiting (`timeout_secs`)
+    pub deadline: Option<Instant>,
+}
+
+/// A classifier the pipeline can run reads through. Whatever the tool, results come
+/// back in KrakenUniq's shape, so reports are stored and shown the same way. `classify`
+/// is called on a worker thread; backends that can stop early watch `job` for cancellation and give up at
+/// the request's `deadline`.
+pub(crate) trait ClassificationBackend: Send + Sync {
+    fn classify(
+        &self,
+        request: ClassificationRequest,
+        job: &JobHandle,
+    ) -> Result<ClassificationResults, PoleshiftError>;
+}
+
+/// The backend selected by `options.backend`.
+pub(crate) fn backend_for(
+    options: &ClassificationOptions,
+    resource_dir: &Path,
+) -> Arc<dyn ClassificationBackend> {
+    match options.backend {
+        BackendKind::InProcess => Arc::new(InProcessBackend),
+        BackendKind::Sidecar => Arc::new(SidecarBackend {
+            executable: sidecar_executable(options, resource_dir),
+        }),
+        // `validate` makes sure `database_path` is set for these two
+        BackendKind::Kraken2 => Arc::new(Kraken2Backend {
+            executable: sidecar_executable(options, resource_dir),
+            database: PathBuf::from(options.database_path.clone().unwrap_or_default()),
+        }),
+        BackendKind::Centrifuge => Arc::new(CentrifugeBackend {
+            executable: sidecar_executable(options, resource_dir),
+            index: PathBuf::from(options.database_path.clone().unwrap_or_default()),
+        }),
+    }
+}
+
+/// The database files `options.backend` classifies against: the KrakenUniq files of
+/// `config`, or the Kraken2 database or Centrifuge index at `options.database_path`.
+pub(crate) fn database_files(
+    options: &ClassificationOptions,
+    config: &KrakenConfig,
+) -> Vec<PathBuf> {
+    let external = options.database_path.as_deref().unwrap_or_default();
+    match options.backend {
+        BackendKind::InProcess | BackendKind::Sidecar => [
+            &config.db_file,
+            &config.idx_file,
+            &config.taxdb_file,
+            &config.counts_file,
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect(),
+        BackendKind::Kraken2 => kraken2::KRAKEN2_DATABASE_FILES
+            .iter()
+            .map(|file| Path::new(external).join(file))
+            .collect(),
+        BackendKind::Centrifuge => centrifuge::CENTRIFUGE_INDEX_SUFFIXES
+            .iter()
+            .map(|suffix| PathBuf::from(format!("{}{}", external, suffix)))
+            .collect(),
+    }
+}
+
+/// `classifier_path` if the settings give one, else the backend's executable bundled
+/// under `resources/bin`, else the executable of that name from `PATH`.
+fn sidecar_executable(options: &ClassificationOptions, resource_dir: &Path) -> PathBuf {
+    if let Some(path) = &options.classifier_path {
+        return PathBuf::from(path);
+    }
+    let name = options.backend.executable_name().unwrap_or("krakenuniq");
+    let name = if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    };
+    let bundled = resource_dir.join("bin").join(&name);
+    if bundled.is_file() {
+        bundled
+    } else {
+        PathBuf::from(name)
+    }
+}
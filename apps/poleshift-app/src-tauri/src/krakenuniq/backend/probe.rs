@@ -0,0 +1,263 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::backend::centrifuge::CENTRIFUGE_INDEX_SUFFIXES;
+use crate::krakenuniq::backend::kraken2::KRAKEN2_DATABASE_FILES;
+use crate::krakenuniq::backend::{sidecar_executable, BackendKind};
+use crate::krakenuniq::options::ClassificationOptions;
+use crate::krakenuniq::preload::{available_memory, plan_load, DatabaseLoadPlan};
+use crate::poleshift_common::types::{KrakenConfig, PoleshiftError, StandardResponseNoFiles};
+use crate::settings::{current_settings, resources_dir};
+
+/// How long `--version` may take before the executable is considered hung.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Leading bytes of a Kraken 1 / KrakenUniq k-mer table.
+const KDB_MAGIC: &[u8] = b"JFLISTDN";
+/// Leading bytes of the two minimizer index versions.
+const IDX_MAGICS: [&[u8]; 2] = [b"KRAKIDX", b"KRAKIX2"];
+
+/// Whether a classifier backend can run against a database, and what it needs.
+#[derive(Debug, Serialize)]
+pub struct ClassifierProbe {
+    pub backend: BackendKind,
+    /// The executable that would be run, for the backends that run one
+    pub executable: Option<String>,
+    /// The classifier could be started
+    pub available: bool,
+    pub version: Option<String>,
+    pub database_id: String,
+    /// All database files are present and of the expected format
+    pub database_compatible: bool,
+    /// Cores the classifier can use
+    pub threads_available: usize,
+    /// Memory to preload the whole database
+    pub memory_required_bytes: u64,
+    pub available_memory_bytes: Option<u64>,
+    /// How the database would be loaded under the detected memory; only planned for
+    /// the KrakenUniq database
+    pub load_plan: Option<DatabaseLoadPlan>,
+    /// Everything that would stop a run, worded for the user
+    pub problems: Vec<String>,
+}
+
+/// First line `executable --version` prints, or why it could not be run.
+fn sidecar_version(executable: &Path) -> Result<String, String> {
+    let mut child = Command::new(executable)
+        .arg("--version")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            format!(
+                "Classifier {} could not be started: {}",
+                executable.display(),
+                e
+            )
+        })?;
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < VERSION_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(100))
+            }
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "Classifier {} did not answer --version within {} s",
+                    executable.display(),
+                    VERSION_TIMEOUT.as_secs()
+                ));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    // --version output is a line or two, well within the pipe buffers
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut output);
+    }
+    if let Some(mut stderr) = child.stderr.take() {
+        let _ = stderr.read_to_string(&mut output);
+    }
+    let first_line = output
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .map(str::to_string);
+    match first_line {
+        Some(line) if status.success() => Ok(line),
+        Some(line) => Err(format!(
+            "Classifier {} failed ({}): {}",
+            executable.display(),
+            status,
+            line
+        )),
+        None => Err(format!(
+            "Classifier {} printed no version ({})",
+            executable.display(),
+            status
+        )),
+    }
+}
+
+/// Checks that a database file exists and starts with one of `magics`. A file still
+/// waiting to be decompressed passes; its format is checked on a later probe.
+fn check_database_file(path: &str, magics: &[&[u8]], problems: &mut Vec<String>) {
+    let path = Path::new(path);
+    if !path.is_file() {
+        if !Path::new(&format!("{}.gz", path.display())).is_file() {
+            problems.push(format!("Database file {} is missing", path.display()));
+        }
+        return;
+    }
+    if magics.is_empty() {
+        return;
+    }
+    let mut header = [0u8; 8];
+    let read = File::open(path).and_then(|mut f| f.read(&mut header));
+    match read {
+        Ok(n) if magics.iter().any(|m| header[..n].starts_with(m)) => {}
+        Ok(_) => problems.push(format!(
+            "{} is not a KrakenUniq database file",
+            path.display()
+        )),
+        Err(e) => problems.push(format!("{} cannot be read: {}", path.display(), e)),
+    }
+}
+
+/// Checks that the chosen classifier backend can be started and that its database is
+/// complete: `database_id` in KrakenUniq format, or the Kraken2 database or Centrifuge
+/// index at `database_path`. Reports the version, cores and memory it would work
+/// with. Problems are listed in `problems` rather than returned as errors, so the UI
+/// can explain a missing or incompatible classifier before a run.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn probe_classifier<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    backend: Option<BackendKind>,
+    database_id: Option<String>,
+    database_path: Option<String>,
+) -> Result<StandardResponseNoFiles<ClassifierProbe>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let resource_dir = resources_dir(&app_handle)?;
+        let backend = backend.unwrap_or_default();
+        let settings = current_settings(&app_handle);
+        let database_id = database_id.unwrap_or_else(|| settings.database_id.clone());
+        let mut problems = Vec::new();
+
+        // 1) Can the classifier itself run?
+        let (executable, version) = match backend {
+            BackendKind::InProcess => (None, Ok("bundled krakenuniq-rs".to_string())),
+            _ => {
+                let options = settings.classification_options(Some(ClassificationOptions {
+                    backend,
+                    ..ClassificationOptions::default()
+                }));
+                let executable = sidecar_executable(&options, &resource_dir);
+                let version = sidecar_version(&executable);
+                (Some(executable.to_string_lossy().to_string()), version)
+            }
+        };
+        let version = version.map_err(|e| problems.push(e)).ok();
+
+        // 2) Is the database complete and in the expected format?
+        let config = KrakenConfig::for_database(&resource_dir, &database_id, Vec::new())?;
+        let before = problems.len();
+        let external_files: Vec<String> = match (backend, database_path.as_deref()) {
+            (BackendKind::InProcess | BackendKind::Sidecar, _) => Vec::new(),
+            (_, None) => {
+                problems.push(format!("The {:?} backend needs a database_path", backend));
+                Vec::new()
+            }
+            (BackendKind::Kraken2, Some(dir)) => KRAKEN2_DATABASE_FILES
+                .iter()
+                .map(|f| Path::new(dir).join(f).to_string_lossy().to_string())
+                .collect(),
+            (BackendKind::Centrifuge, Some(prefix)) => CENTRIFUGE_INDEX_SUFFIXES
+                .iter()
+                .map(|suffix| format!("{}{}", prefix, suffix))
+                .collect(),
+        };
+        if backend.uses_krakenuniq_database() {
+            check_database_file(&config.db_file, &[KDB_MAGIC], &mut problems);
+            check_database_file(&config.idx_file, &IDX_MAGICS, &mut problems);
+            check_database_file(&config.taxdb_file, &[], &mut problems);
+            if backend == BackendKind::InProcess {
+                check_database_file(&config.counts_file, &[], &mut problems);
+            }
+        }
+        for file in &external_files {
+            check_database_file(file, &[], &mut problems);
+        }
+        let database_compatible = problems.len() == before;
+
+        // 3) What it would need from this machine. Kraken2 and Centrifuge load their
+        // database whole, so it simply has to fit
+        let load_plan = if backend.uses_krakenuniq_database() {
+            plan_load(&config, None, backend)
+                .map_err(|e| problems.push(e.to_string()))
+                .ok()
+        } else {
+            None
+        };
+        let available_memory_bytes = match &load_plan {
+            Some(plan) => plan.available_memory_bytes,
+            None => available_memory(),
+        };
+        let memory_required_bytes = match &load_plan {
+            Some(plan) => plan.database_bytes,
+            None => external_files
+                .iter()
+                .filter_map(|f| std::fs::metadata(f).ok())
+                .map(|m| m.len())
+                .sum(),
+        };
+        if load_plan.is_none()
+            && available_memory_bytes.is_some_and(|available| available < memory_required_bytes)
+        {
+            problems.push(format!(
+                "The database needs {} MB but only {} MB of memory is free",
+                memory_required_bytes.div_ceil(1024 * 1024),
+                available_memory_bytes.unwrap_or_default() / (1024 * 1024)
+            ));
+        }
+        if let Some(plan) = load_plan.as_ref().filter(|plan| !plan.supported) {
+            problems.push(format!(
+                "The database needs {} MB but only {} MB of memory is free",
+                plan.database_bytes.div_ceil(1024 * 1024),
+                plan.available_memory_bytes.unwrap_or_default() / (1024 * 1024)
+            ));
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: ClassifierProbe {
+                backend,
+                executable,
+                available: version.is_some(),
+                version,
+                database_id,
+                database_compatible,
+                threads_available: std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1),
+                memory_required_bytes,
+                available_memory_bytes,
+                load_plan,
+                problems,
+            },
+        })
+    })
+    .await
+}
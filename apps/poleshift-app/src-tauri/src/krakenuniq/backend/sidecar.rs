@@ -0,0 +1,247 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+use krakenuniq_rs::ClassificationResults;
+use uuid::Uuid;
+
+use crate::krakenuniq::backend::{ClassificationBackend, ClassificationRequest};
+use crate::krakenuniq::preload::LoadStrategy;
+use crate::krakenuniq::report_parser::{parse_kraken_uniq_output, parse_kraken_uniq_report};
+use crate::poleshift_common::jobs::JobHandle;
+use crate::poleshift_common::types::{KrakenConfig, PoleshiftError};
+
+/// How often the running executable is checked for exit and cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Trailing stderr lines quoted when the executable fails.
+const STDERR_TAIL_LINES: usize = 20;
+/// Prefix of the per-run scratch directories in the temp dir.
+const WORK_DIR_PREFIX: &str = "poleshift-krakenuniq-";
+/// Scratch directories older than this were left behind by a crash.
+const STALE_WORK_DIR_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Owns the running executable and kills it when dropped, so no exit path (error,
+/// panic or an abandoned worker) leaves a classifier process behind.
+struct ChildGuard(Child);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        if matches!(self.0.try_wait(), Ok(None)) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+/// Removes scratch directories of earlier runs that never cleaned up after themselves.
+fn remove_stale_work_dirs() {
+    let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > STALE_WORK_DIR_AGE);
+        if stale
+            && entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(WORK_DIR_PREFIX)
+        {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Classifies by running a `krakenuniq` executable and reading back its report and
+/// per-read output. Unlike the bundled library it honours min-hits, quick mode,
+/// exact counting and partial preloading, and it is killed when the job is cancelled.
+pub(crate) struct SidecarBackend {
+    pub executable: PathBuf,
+}
+
+impl SidecarBackend {
+    fn arguments(
+        &self,
+        request: &ClassificationRequest,
+        work_dir: &Path,
+        gzipped: bool,
+    ) -> Vec<String> {
+        let (config, options) = (&request.config, &request.options);
+        let db_dir = Path::new(&config.db_file)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        let mut args = vec![
+            "--db".to_string(),
+            db_dir.to_string_lossy().to_string(),
+            "--report-file".to_string(),
+            work_dir.join("report.tsv").to_string_lossy().to_string(),
+            "--output".to_string(),
+            work_dir.join("output.tsv").to_string_lossy().to_string(),
+        ];
+        if let Some(threads) = options.threads {
+            args.extend(["--threads".to_string(), threads.to_string()]);
+        }
+        if let Some(min_hits) = options.min_hits {
+            args.extend(["--min-hits".to_string(), min_hits.to_string()]);
+        }
+        if options.quick {
+            args.push("--quick".to_string());
+        }
+        if options.exact_counting {
+            args.push("--exact".to_string());
+        }
+        if options.only_classified_output {
+            args.push("--only-classified-output".to_string());
+        }
+
+        // Without any preload flag the database is memory-mapped
+        let chunk_bytes = request
+            .load_plan
+            .as_ref()
+            .and_then(|plan| match plan.strategy {
+                LoadStrategy::Chunked => plan.chunk_bytes,
+                _ => None,
+            });
+        let strategy = request.load_plan.as_ref().map(|plan| plan.strategy);
+        if let Some(size) = &options.preload_size {
+            args.extend(["--preload-size".to_string(), size.clone()]);
+        } else if let Some(bytes) = chunk_bytes {
+            let mb = bytes.div_ceil(1024 * 1024).max(1);
+            args.extend(["--preload-size".to_string(), format!("{}M", mb)]);
+        } else if strategy != Some(LoadStrategy::Mmap) {
+            args.push("--preload".to_string());
+        }
+
+        if gzipped {
+            args.push("--gzip-compressed".to_string());
+        }
+        args.extend(
+            config
+                .input_files
+                .iter()
+                .map(|f| f.to_string_lossy().to_string()),
+        );
+        args
+    }
+
+    fn run(
+        &self,
+        request: &ClassificationRequest,
+        job: &JobHandle,
+        work_dir: &Path,
+    ) -> Result<ClassificationResults, PoleshiftError> {
+        // 1) Run the executable
+        let gzipped = gzipped_inputs(&request.config)?;
+        let args = self.arguments(request, work_dir, gzipped);
+        run_executable(&self.executable, &args, None, request, job, work_dir)?;
+
+        // 2) Read back the outputs
+        Ok(ClassificationResults {
+            kraken_output_lines: parse_kraken_uniq_output(&work_dir.join("output.tsv"))?,
+            kraken_report_rows: Some(parse_kraken_uniq_report(&work_dir.join("report.tsv"))?),
+        })
+    }
+}
+
+impl ClassificationBackend for SidecarBackend {
+    fn classify(
+        &self,
+        request: ClassificationRequest,
+        job: &JobHandle,
+    ) -> Result<ClassificationResults, PoleshiftError> {
+        in_work_dir(|work_dir| self.run(&request, job, work_dir))
+    }
+}
+
+/// Runs `f` in a fresh scratch directory, which is removed afterwards whether `f`
+/// succeeded or not.
+pub(super) fn in_work_dir<T>(
+    f: impl FnOnce(&Path) -> Result<T, PoleshiftError>,
+) -> Result<T, PoleshiftError> {
+    remove_stale_work_dirs();
+    let work_dir = std::env::temp_dir().join(format!("{}{}", WORK_DIR_PREFIX, Uuid::new_v4()));
+    std::fs::create_dir_all(&work_dir)?;
+    let result = f(&work_dir);
+    if let Err(e) = std::fs::remove_dir_all(&work_dir) {
+        println!("Could not remove {}: {}", work_dir.display(), e);
+    }
+    result
+}
+
+/// True if all input files are gzipped. Mixed inputs are rejected, as the executables
+/// take one compression flag for all of them.
+pub(super) fn gzipped_inputs(config: &KrakenConfig) -> Result<bool, PoleshiftError> {
+    let inputs = &config.input_files;
+    let gzipped = inputs
+        .iter()
+        .filter(|f| f.extension().is_some_and(|e| e == "gz"))
+        .count();
+    if gzipped != 0 && gzipped != inputs.len() {
+        return Err(PoleshiftError::DataError(
+            "The classifier executable cannot mix gzipped and plain input files".to_string(),
+        ));
+    }
+    Ok(gzipped != 0)
+}
+
+/// Runs `executable` with `args` to completion, with stdout going to `stdout` (or
+/// nowhere) and stderr to a file in `work_dir`, so neither can fill a pipe. The
+/// process is killed when the job is cancelled or the request's deadline passes.
+pub(super) fn run_executable(
+    executable: &Path,
+    args: &[String],
+    stdout: Option<&Path>,
+    request: &ClassificationRequest,
+    job: &JobHandle,
+    work_dir: &Path,
+) -> Result<(), PoleshiftError> {
+    let stderr_path = work_dir.join("stderr.log");
+    let stdout = match stdout {
+        Some(path) => Stdio::from(File::create(path)?),
+        None => Stdio::null(),
+    };
+    let mut child = ChildGuard(
+        Command::new(executable)
+            .args(args)
+            .stdout(stdout)
+            .stderr(File::create(&stderr_path)?)
+            .spawn()
+            .map_err(|e| {
+                PoleshiftError::Other(format!("Could not start {}: {}", executable.display(), e))
+            })?,
+    );
+
+    // Returning early drops the guard, which kills the process
+    let status = loop {
+        if let Some(status) = child.0.try_wait()? {
+            break status;
+        }
+        if job.is_cancelled() {
+            return Err(PoleshiftError::Cancelled(job.id().to_string()));
+        }
+        if request.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(PoleshiftError::Other(format!(
+                "{} timed out and was stopped",
+                executable.display()
+            )));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    if !status.success() {
+        let stderr = std::fs::read_to_string(&stderr_path).unwrap_or_default();
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+        return Err(PoleshiftError::Other(format!(
+            "{} exited with {}: {}",
+            executable.display(),
+            status,
+            tail
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::poleshift_common::types::PoleshiftError;
+use crate::poleshift_common::utils::parse_uuid;
+use krakenuniq_rs::{ClassificationResults, OutputLine, ReportRow};
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::{AppHandle, Manager, Runtime};
+
+fn db_error(e: rusqlite::Error) -> PoleshiftError {
+    PoleshiftError::DataError(e.to_string())
+}
+
+/// Location of the checkpoint for `processed_data_id` in the app data directory.
+pub(crate) fn checkpoint_path<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    processed_data_id: &str,
+) -> Result<PathBuf, PoleshiftError> {
+    // The ID becomes a file name, so only accept real UUIDs
+    let id = parse_uuid("processed_data_id", processed_data_id)?;
+    Ok(app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join("checkpoints")
+        .join(format!("{}.sqlite", id)))
+}
+
+/// Size and modification time identify an unchanged input file.
+pub(crate) fn fingerprint(file: &Path) -> Result<(i64, i64), PoleshiftError> {
+    let meta = std::fs::metadata(file)?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    Ok((meta.len() as i64, modified))
+}
+
+/// Per-file classification results of one run, kept on disk so an interrupted run
+/// can skip the files it already classified.
+pub(crate) struct Checkpoint {
+    conn: Connection,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at `path`, creating it if needed. A checkpoint written
+    /// against another database is discarded.
+    pub fn open(path: &Path, database_id: &str) -> Result<Self, PoleshiftError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS files (
+                path TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                modified INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS report_rows (
+                path TEXT NOT NULL,
+                tax_id INTEGER NOT NULL,
+                parent_tax_id INTEGER,
+                tax_name TEXT NOT NULL,
+                rank TEXT NOT NULL,
+                depth INTEGER NOT NULL,
+                reads INTEGER NOT NULL,
+                tax_reads INTEGER NOT NULL,
+                kmers INTEGER NOT NULL,
+                dup REAL NOT NULL,
+                cov REAL NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS output_lines (
+                path TEXT NOT NULL,
+                status TEXT NOT NULL,
+                read_id TEXT NOT NULL,
+                tax_id INTEGER NOT NULL,
+                length INTEGER NOT NULL,
+                hitlist TEXT NOT NULL
+            );",
+        )
+        .map_err(db_error)?;
+
+        let stored: Option<String> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'database_id'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        if stored.as_deref() != Some(database_id) {
+            conn.execute_batch(
+                "DELETE FROM files; DELETE FROM report_rows; DELETE FROM output_lines;",
+            )
+            .map_err(db_error)?;
+            conn.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('database_id', ?1)",
+                params![database_id],
+            )
+            .map_err(db_error)?;
+        }
+        Ok(Checkpoint { conn })
+    }
+
+    /// True if `file` was classified before and has not changed since.
+    pub fn is_complete(&self, file: &Path) -> Result<bool, PoleshiftError> {
+        let (size, modified) = fingerprint(file)?;
+        let stored: Option<(i64, i64)> = self
+            .conn
+            .query_row(
+                "SELECT size, modified FROM files WHERE path = ?1",
+                params![file.to_string_lossy()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(db_error)?;
+        Ok(stored == Some((size, modified)))
+    }
+
+    /// Stores the results of `file`. The file only counts as complete once the whole
+    /// transaction is committed.
+    pub fn record(
+        &mut self,
+        file: &Path,
+        results: &ClassificationResults,
+    ) -> Result<(), PoleshiftError> {
+        let (size, modified) = fingerprint(file)?;
+        self.store(&file.to_string_lossy(), size, modified, results)
+    }
+
+    /// Stores the results of a whole run as a single entry; used by the result cache,
+    /// whose entries hold one run each.
+    pub fn record_run(&mut self, results: &ClassificationResults) -> Result<(), PoleshiftError> {
+        self.store("", 0, 0, results)
+    }
+
+    fn store(
+        &mut self,
+        path: &str,
+        size: i64,
+        modified: i64,
+        results: &ClassificationResults,
+    ) -> Result<(), PoleshiftError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        {
+            tx.execute("DELETE FROM report_rows WHERE path = ?1", params![path])
+                .map_err(db_error)?;
+            tx.execute("DELETE FROM output_lines WHERE path = ?1", params![path])
+                .map_err(db_error)?;
+
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO report_rows VALUES
+                     (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                )
+                .map_err(db_error)?;
+            for row in results.kraken_report_rows.iter().flatten() {
+                stmt.execute(params![
+                    path,
+                    row.tax_id,
+                    row.parent_tax_id,
+                    row.tax_name,
+                    row.rank,
+                    row.depth as i64,
+                    row.reads as i64,
+                    row.tax_reads as i64,
+                    row.kmers as i64,
+                    row.dup,
+                    row.cov,
+                ])
+                .map_err(db_error)?;
+            }
+
+            let mut stmt = tx
+                .prepare("INSERT INTO output_lines VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+                .map_err(db_error)?;
+            for line in &results.kraken_output_lines {
+                stmt.execute(params![
+                    path,
+                    line.status.to_string(),
+                    line.read_id,
+                    line.tax_id,
+                    line.length as i64,
+                    line.hitlist,
+                ])
+                .map_err(db_error)?;
+            }
+
+            tx.execute(
+                "INSERT OR REPLACE INTO files (path, size, modified) VALUES (?1, ?2, ?3)",
+                params![path, size, modified],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)
+    }
+
+    /// Combines the stored per-file results into one (see `merge_report_rows`).
+    pub fn merged_results(&self) -> Result<ClassificationResults, PoleshiftError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT tax_id, parent_tax_id, tax_name, rank, depth, reads, tax_reads,
+                        kmers, dup, cov
+                 FROM report_rows ORDER BY rowid",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(ReportRow {
+                    pct: 0.0,
+                    reads: row.get::<_, i64>(5)? as u64,
+                    tax_reads: row.get::<_, i64>(6)? as u64,
+                    kmers: row.get::<_, i64>(7)? as u64,
+                    dup: row.get(8)?,
+                    cov: row.get(9)?,
+                    tax_id: row.get(0)?,
+                    rank: row.get(3)?,
+                    tax_name: row.get(2)?,
+                    depth: row.get::<_, i64>(4)? as usize,
+                    parent_tax_id: row.get(1)?,
+                    children_tax_ids: Vec::new(),
+                })
+            })
+            .map_err(db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_error)?;
+
+        let report_rows = merge_report_rows(rows);
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT status, read_id, tax_id, length, hitlist
+                 FROM output_lines ORDER BY rowid",
+            )
+            .map_err(db_error)?;
+        let output_lines = stmt
+            .query_map([], |row| {
+                let status: String = row.get(0)?;
+                Ok(OutputLine {
+                    status: status.chars().next().unwrap_or('U'),
+                    read_id: row.get(1)?,
+                    tax_id: row.get(2)?,
+                    length: row.get::<_, i64>(3)? as usize,
+                    hitlist: row.get(4)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_error)?;
+
+        Ok(ClassificationResults {
+            kraken_output_lines: output_lines,
+            kraken_report_rows: Some(report_rows),
+        })
+    }
+}
+
+/// Merges report rows of separately classified files into one report, as if the files
+/// had been classified together.
+///
+/// Read counts add up exactly. Unique k-mer counts cannot be merged without the
+/// k-mers themselves, so `kmers` is the sum over files (an upper bound), `cov` the
+/// highest per-file coverage and `dup` the k-mer weighted mean.
+pub(crate) fn merge_report_rows(rows: impl IntoIterator<Item = ReportRow>) -> Vec<ReportRow> {
+    // 1) Merge rows by tax ID, keeping the order of first appearance
+    let mut order: Vec<u32> = Vec::new();
+    let mut merged: HashMap<u32, ReportRow> = HashMap::new();
+    for row in rows {
+        match merged.get_mut(&row.tax_id) {
+            Some(existing) => {
+                let kmers = existing.kmers + row.kmers;
+                if kmers > 0 {
+                    existing.dup = (existing.dup * existing.kmers as f64
+                        + row.dup * row.kmers as f64)
+                        / kmers as f64;
+                }
+                existing.kmers = kmers;
+                existing.reads += row.reads;
+                existing.tax_reads += row.tax_reads;
+                existing.cov = existing.cov.max(row.cov);
+            }
+            None => {
+                order.push(row.tax_id);
+                merged.insert(row.tax_id, row);
+            }
+        }
+    }
+
+    // 2) Rebuild child links and percentages for the merged tree
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for tax_id in &order {
+        if let Some(parent) = merged[tax_id].parent_tax_id {
+            children.entry(parent).or_default().push(*tax_id);
+        }
+    }
+    let total: u64 = merged
+        .values()
+        .filter(|row| row.parent_tax_id.is_none())
+        .map(|row| row.reads)
+        .sum();
+    order
+        .iter()
+        .filter_map(|tax_id| merged.remove(tax_id))
+        .map(|mut row| {
+            row.children_tax_ids = children.remove(&row.tax_id).unwrap_or_default();
+            if total > 0 {
+                row.pct = (row.reads as f64 * 100.0 / total as f64) as f32;
+            }
+            row
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(tax_id: u32, parent_tax_id: Option<u32>, reads: u64, kmers: u64, dup: f64) -> ReportRow {
+        ReportRow {
+            pct: 0.0,
+            reads,
+            tax_reads: reads,
+            kmers,
+            dup,
+            cov: kmers as f64 / 1000.0,
+            tax_id,
+            rank: "species".to_string(),
+            tax_name: format!("taxon {}", tax_id),
+            depth: 1,
+            parent_tax_id,
+            children_tax_ids: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn merges_rows_of_separate_files_by_tax_id() {
+        let merged = merge_report_rows(vec![
+            row(1, None, 10, 100, 1.0),
+            row(2, Some(1), 10, 100, 1.0),
+            row(1, None, 30, 300, 2.0),
+            row(3, Some(1), 30, 200, 1.0),
+        ]);
+
+        assert_eq!(
+            merged.iter().map(|r| r.tax_id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        let root = &merged[0];
+        assert_eq!((root.reads, root.tax_reads, root.kmers), (40, 40, 400));
+        assert_eq!(root.dup, 1.75);
+        assert_eq!(root.cov, 0.3);
+        assert_eq!(root.children_tax_ids, vec![2, 3]);
+        assert_eq!(root.pct, 100.0);
+        assert_eq!(merged[1].pct, 25.0);
+    }
+}
@@ -0,0 +1,50 @@
+/// Most parent links followed when checking whether a taxon lies in a clade; guards
+/// against cycles in a malformed taxonomy.
+const MAX_DEPTH: usize = 256;
+
+/// True if `tax_id` is `ancestor` or lies below it, following `parent_of`.
+fn in_clade(tax_id: u32, ancestor: u32, parent_of: &impl Fn(u32) -> Option<u32>) -> bool {
+    let mut current = tax_id;
+    for _ in 0..MAX_DEPTH {
+        if current == ancestor {
+            return true;
+        }
+        match parent_of(current) {
+            Some(parent) if parent != current => current = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Confidence of a read's assignment to `tax_id`: the fraction of its k-mers whose
+/// taxon lies in the clade of `tax_id`, as Kraken2 scores `--confidence`.
+///
+/// `hit_data` is the classifier's hit list of `tax_id:count` pairs. Ambiguous k-mers
+/// (`A:count`) are left out of the total and k-mers without a hit (`0:count`) count
+/// against the read. `None` for unclassified reads and hit lists without any k-mers.
+pub(crate) fn read_confidence(
+    hit_data: &str,
+    tax_id: u32,
+    parent_of: impl Fn(u32) -> Option<u32>,
+) -> Option<f64> {
+    if tax_id == 0 {
+        return None;
+    }
+    let mut total = 0u64;
+    let mut supporting = 0u64;
+    for hit in hit_data.split_whitespace() {
+        // Paired reads separate the mates with `|:|`; ambiguous hits have no tax ID
+        let Some((taxon, count)) = hit.rsplit_once(':') else {
+            continue;
+        };
+        let (Ok(taxon), Ok(count)) = (taxon.parse::<u32>(), count.parse::<u64>()) else {
+            continue;
+        };
+        total += count;
+        if taxon != 0 && in_clade(taxon, tax_id, &parent_of) {
+            supporting += count;
+        }
+    }
+    (total > 0).then(|| supporting as f64 / total as f64)
+}
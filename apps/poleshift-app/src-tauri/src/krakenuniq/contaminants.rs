@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::krakenuniq::extract_reads::subtree_tax_ids;
+use crate::krakenuniq::{ProcessedKrakenUniqReport, ProcessedKrakenUniqStdout, RawSequence};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// NCBI tax ID of "root"; removing it would remove every classified read.
+const ROOT_TAX_ID: u64 = 1;
+
+/// Taxa whose reads are dropped after classification, e.g. human (9606), PhiX
+/// (10847) or known lab contaminants.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContaminantOptions {
+    pub tax_ids: Vec<u64>,
+    /// Also drop reads assigned anywhere below the listed taxa
+    #[serde(default = "default_include_descendants")]
+    pub include_descendants: bool,
+}
+
+fn default_include_descendants() -> bool {
+    true
+}
+
+impl ContaminantOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if self.tax_ids.iter().any(|&id| id == 0 || id == ROOT_TAX_ID) {
+            return Err(PoleshiftError::DataError(
+                "Contaminant taxa cannot include unclassified (0) or root (1)".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What the contaminant filter removed from one sample.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ContaminantSummary {
+    /// Every tax ID that was filtered, including descendants of the listed taxa
+    pub tax_ids: Vec<u64>,
+    pub reads_removed: usize,
+    pub bases_removed: u64,
+    pub report_rows_removed: usize,
+}
+
+/// Drops the reads assigned to the contaminant taxa from the per-read output and the
+/// raw reads, and removes those taxa from the report. Clade read counts of the
+/// remaining ancestors and all percentages are recomputed, so the report reads as if
+/// the contaminant reads had never been sequenced.
+pub(crate) fn remove_contaminants(
+    options: &ContaminantOptions,
+    report: &mut Vec<ProcessedKrakenUniqReport>,
+    stdout: &mut Vec<ProcessedKrakenUniqStdout>,
+    raw_sequences: &mut Vec<RawSequence>,
+) -> ContaminantSummary {
+    // 1) Expand the listed taxa to everything they cover in this report
+    let mut contaminant_ids: HashSet<u64> = HashSet::new();
+    for &tax_id in &options.tax_ids {
+        contaminant_ids.extend(subtree_tax_ids(report, tax_id, options.include_descendants));
+    }
+
+    // 2) Per-read output and raw reads
+    let mut removed_reads: HashSet<String> = HashSet::new();
+    let mut reads_removed = 0usize;
+    stdout.retain(|line| {
+        let keep = line.tax_id < 0 || !contaminant_ids.contains(&(line.tax_id as u64));
+        if !keep {
+            removed_reads.insert(line.feature_id.clone());
+            reads_removed += 1;
+        }
+        keep
+    });
+    let mut bases_removed = 0u64;
+    raw_sequences.retain(|seq| {
+        let keep = !removed_reads.contains(&seq.feature_id);
+        if !keep {
+            bases_removed += seq.sequence.len() as u64;
+        }
+        keep
+    });
+
+    // 3) Subtract each removed clade from its surviving ancestors
+    let index_by_id: HashMap<String, usize> = report
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row.id.clone(), i))
+        .collect();
+    let parent_of = |row: &ProcessedKrakenUniqReport| {
+        row.parent_id
+            .and_then(|p| index_by_id.get(&p.to_string()).copied())
+    };
+    let mut clade_reads_removed: HashMap<usize, u64> = HashMap::new();
+    for row in report.iter() {
+        if !contaminant_ids.contains(&row.tax_id) {
+            continue;
+        }
+        // Only the top of a removed clade; its clade count already covers the rest
+        let parent = parent_of(row);
+        if parent.is_some_and(|p| contaminant_ids.contains(&report[p].tax_id)) {
+            continue;
+        }
+        let reads = row.reads;
+        let mut ancestor = parent;
+        while let Some(i) = ancestor {
+            *clade_reads_removed.entry(i).or_default() += reads;
+            ancestor = parent_of(&report[i]);
+        }
+    }
+    for (i, removed) in clade_reads_removed {
+        let row = &mut report[i];
+        row.reads = row.reads.saturating_sub(removed);
+    }
+
+    // 4) Drop the contaminant rows and their links, then recompute percentages
+    let removed_row_ids: HashSet<String> = report
+        .iter()
+        .filter(|row| contaminant_ids.contains(&row.tax_id))
+        .map(|row| row.id.clone())
+        .collect();
+    report.retain(|row| !removed_row_ids.contains(&row.id));
+    for row in report.iter_mut() {
+        row.children_ids
+            .retain(|child| !removed_row_ids.contains(&child.to_string()));
+    }
+    let total: u64 = report
+        .iter()
+        .filter(|row| row.parent_id.is_none())
+        .map(|row| row.reads)
+        .sum();
+    if total > 0 {
+        for row in report.iter_mut() {
+            row.percentage = (row.reads as f64 * 100.0 / total as f64) as f32;
+        }
+    }
+
+    let mut tax_ids: Vec<u64> = contaminant_ids.into_iter().collect();
+    tax_ids.sort_unstable();
+    ContaminantSummary {
+        tax_ids,
+        reads_removed,
+        bases_removed,
+        report_rows_removed: removed_row_ids.len(),
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::krakenuniq::{
+    confidence::read_confidence,
+    e_score,
+    parse_fastq_files::parse_fastq_files,
+    raw_sequence_store::summarize_raw_sequences,
+    run_metadata::RunMetadata,
+    taxdb::{annotate_lineages, TaxDb},
+    EScoreFormula, KrakenUniqResult, NodeIds, ProcessedKrakenUniqReport, ProcessedKrakenUniqStdout,
+};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// A node of the small, fixed taxonomy used for demo classifications.
+pub(crate) struct DemoTaxon {
+    pub tax_id: u32,
+    pub parent_tax_id: Option<u32>,
+    pub name: &'static str,
+    pub rank: &'static str,
+    /// Relative share of reads assigned directly to this node
+    pub weight: f64,
+}
+
+/// A plausible polar surface-water community. Tax IDs follow NCBI.
+#[rustfmt::skip]
+pub(crate) const DEMO_TAXA: &[DemoTaxon] = &[
+    DemoTaxon { tax_id: 1, parent_tax_id: None, name: "root", rank: "no rank", weight: 0.01 },
+    DemoTaxon { tax_id: 2, parent_tax_id: Some(1), name: "Bacteria", rank: "superkingdom", weight: 0.02 },
+    DemoTaxon { tax_id: 1224, parent_tax_id: Some(2), name: "Proteobacteria", rank: "phylum", weight: 0.03 },
+    DemoTaxon { tax_id: 28211, parent_tax_id: Some(1224), name: "Alphaproteobacteria", rank: "class", weight: 0.04 },
+    DemoTaxon { tax_id: 54526, parent_tax_id: Some(28211), name: "Pelagibacterales", rank: "order", weight: 0.02 },
+    DemoTaxon { tax_id: 198251, parent_tax_id: Some(54526), name: "Candidatus Pelagibacter", rank: "genus", weight: 0.18 },
+    DemoTaxon { tax_id: 1236, parent_tax_id: Some(1224), name: "Gammaproteobacteria", rank: "class", weight: 0.04 },
+    DemoTaxon { tax_id: 135622, parent_tax_id: Some(1236), name: "Alteromonadales", rank: "order", weight: 0.02 },
+    DemoTaxon { tax_id: 53246, parent_tax_id: Some(135622), name: "Pseudoalteromonas", rank: "genus", weight: 0.08 },
+    DemoTaxon { tax_id: 976, parent_tax_id: Some(2), name: "Bacteroidetes", rank: "phylum", weight: 0.03 },
+    DemoTaxon { tax_id: 49546, parent_tax_id: Some(976), name: "Flavobacteriaceae", rank: "family", weight: 0.05 },
+    DemoTaxon { tax_id: 53458, parent_tax_id: Some(49546), name: "Polaribacter", rank: "genus", weight: 0.14 },
+    DemoTaxon { tax_id: 1117, parent_tax_id: Some(2), name: "Cyanobacteria", rank: "phylum", weight: 0.02 },
+    DemoTaxon { tax_id: 1129, parent_tax_id: Some(1117), name: "Synechococcus", rank: "genus", weight: 0.06 },
+    DemoTaxon { tax_id: 2759, parent_tax_id: Some(1), name: "Eukaryota", rank: "superkingdom", weight: 0.02 },
+    DemoTaxon { tax_id: 2836, parent_tax_id: Some(2759), name: "Bacillariophyta", rank: "phylum", weight: 0.03 },
+    DemoTaxon { tax_id: 2857, parent_tax_id: Some(2836), name: "Fragilariopsis", rank: "genus", weight: 0.09 },
+    DemoTaxon { tax_id: 2864, parent_tax_id: Some(2759), name: "Dinophyceae", rank: "class", weight: 0.04 },
+    DemoTaxon { tax_id: 2157, parent_tax_id: Some(1), name: "Archaea", rank: "superkingdom", weight: 0.01 },
+    DemoTaxon { tax_id: 28890, parent_tax_id: Some(2157), name: "Euryarchaeota", rank: "phylum", weight: 0.02 },
+];
+
+/// Share of demo reads left unclassified.
+pub(crate) const DEMO_UNCLASSIFIED_WEIGHT: f64 = 0.05;
+
+/// A synthetic read and the taxon it was generated for (0 = unclassified).
+pub(crate) struct DemoRead {
+    pub read_id: String,
+    pub tax_id: u32,
+    pub length: usize,
+}
+
+pub(crate) struct DemoIds<'a> {
+    pub user_id: &'a str,
+    pub org_id: &'a str,
+    pub sample_id: &'a str,
+    pub raw_data_id: &'a str,
+    pub processed_data_id: &'a str,
+}
+
+/// Builds the classification result the real pipeline would produce for the demo reads.
+///
+/// The FASTQ files are parsed by the same code path as real runs; only the
+/// classifier output is synthesized from the known read assignments. Report rows get
+/// their IDs from `node_ids`, as in real runs.
+pub(crate) fn build_demo_result(
+    file_paths: &[String],
+    reads: &[DemoRead],
+    ids: &DemoIds,
+    node_ids: &NodeIds,
+) -> Result<KrakenUniqResult, PoleshiftError> {
+    let raw_sequences = parse_fastq_files(
+        file_paths,
+        ids.user_id.to_string(),
+        ids.org_id.to_string(),
+        ids.raw_data_id.to_string(),
+        ids.sample_id.to_string(),
+    )?;
+
+    // Direct read and k-mer counts per taxon
+    let mut tax_reads: HashMap<u32, u64> = HashMap::new();
+    let mut tax_kmers: HashMap<u32, u64> = HashMap::new();
+    for read in reads {
+        *tax_reads.entry(read.tax_id).or_default() += 1;
+        *tax_kmers.entry(read.tax_id).or_default() += read.length.saturating_sub(30) as u64;
+    }
+
+    // Clade read counts, accumulated up the parent chain
+    let parents: HashMap<u32, Option<u32>> = DEMO_TAXA
+        .iter()
+        .map(|t| (t.tax_id, t.parent_tax_id))
+        .collect();
+    let mut clade_reads: HashMap<u32, u64> = HashMap::new();
+    for (&tax_id, &count) in tax_reads.iter().filter(|(id, _)| **id != 0) {
+        let mut current = Some(tax_id);
+        while let Some(id) = current {
+            *clade_reads.entry(id).or_default() += count;
+            current = parents.get(&id).copied().flatten();
+        }
+    }
+
+    let total_reads = reads.len().max(1) as f64;
+    let taxon_ids: HashMap<u32, Uuid> = DEMO_TAXA
+        .iter()
+        .map(|t| (t.tax_id, node_ids.id(t.tax_id)))
+        .collect();
+
+    let mut report = Vec::new();
+    let unclassified = tax_reads.get(&0).copied().unwrap_or(0);
+    if unclassified > 0 {
+        report.push(ProcessedKrakenUniqReport {
+            id: node_ids.id(0).to_string(),
+            percentage: (unclassified as f64 / total_reads * 100.0) as f32,
+            reads: unclassified,
+            tax_reads: unclassified,
+            kmers: 0,
+            duplication: 0.0,
+            tax_name: "unclassified".to_string(),
+            parent_id: None,
+            children_ids: Vec::new(),
+            processed_data_id: ids.processed_data_id.to_string(),
+            user_id: ids.user_id.to_string(),
+            org_id: ids.org_id.to_string(),
+            sample_id: ids.sample_id.to_string(),
+            tax_id: 0,
+            rank: "no rank".to_string(),
+            coverage: 0.0,
+            e_score: 0.0,
+            lineage: None,
+            lineage_ranks: None,
+        });
+    }
+
+    for taxon in DEMO_TAXA {
+        let clade = clade_reads.get(&taxon.tax_id).copied().unwrap_or(0);
+        if clade == 0 {
+            continue;
+        }
+        let direct = tax_reads.get(&taxon.tax_id).copied().unwrap_or(0);
+        let kmers = tax_kmers.get(&taxon.tax_id).copied().unwrap_or(0);
+        let coverage = (kmers as f64 / 1.0e6).min(1.0);
+
+        report.push(ProcessedKrakenUniqReport {
+            id: taxon_ids[&taxon.tax_id].to_string(),
+            percentage: (clade as f64 / total_reads * 100.0) as f32,
+            reads: clade,
+            tax_reads: direct,
+            kmers,
+            duplication: 1.2,
+            tax_name: taxon.name.to_string(),
+            parent_id: taxon.parent_tax_id.and_then(|p| taxon_ids.get(&p).copied()),
+            children_ids: DEMO_TAXA
+                .iter()
+                .filter(|c| {
+                    c.parent_tax_id == Some(taxon.tax_id)
+                        && clade_reads.get(&c.tax_id).copied().unwrap_or(0) > 0
+                })
+                .map(|c| taxon_ids[&c.tax_id])
+                .collect(),
+            processed_data_id: ids.processed_data_id.to_string(),
+            user_id: ids.user_id.to_string(),
+            org_id: ids.org_id.to_string(),
+            sample_id: ids.sample_id.to_string(),
+            tax_id: taxon.tax_id as u64,
+            rank: taxon.rank.to_string(),
+            coverage,
+            e_score: e_score(
+                EScoreFormula::default(),
+                direct as f64,
+                kmers as f64,
+                coverage,
+            ),
+            lineage: None,
+            lineage_ranks: None,
+        });
+    }
+
+    // The demo taxonomy stands in for the database's taxDB
+    let taxdb = TaxDb::from_nodes(DEMO_TAXA.iter().map(|t| {
+        (
+            t.tax_id,
+            t.parent_tax_id.unwrap_or(t.tax_id),
+            t.name,
+            t.rank,
+        )
+    }));
+    annotate_lineages(&mut report, &taxdb);
+
+    let stdout = reads
+        .iter()
+        .map(|read| {
+            let kmers = read.length.saturating_sub(30);
+            let hit_data = format!("{}:{}", read.tax_id, kmers);
+            ProcessedKrakenUniqStdout {
+                id: Uuid::new_v4().to_string(),
+                classified: read.tax_id != 0,
+                feature_id: read.read_id.clone(),
+                tax_id: read.tax_id as i32,
+                read_length: read.length as i32,
+                hit_data: hit_data.clone(),
+                user_id: ids.user_id.to_string(),
+                org_id: ids.org_id.to_string(),
+                sample_id: ids.sample_id.to_string(),
+                processed_data_id: ids.processed_data_id.to_string(),
+                confidence: read_confidence(&hit_data, read.tax_id, |t| {
+                    taxdb.get(t).map(|node| node.parent_tax_id)
+                }),
+            }
+        })
+        .collect();
+
+    let summary = summarize_raw_sequences(&raw_sequences, None);
+    let run_metadata = RunMetadata::from_sequences(&raw_sequences);
+    let mut raw_sequences = raw_sequences;
+    run_metadata.strip_shared_fields(&mut raw_sequences);
+
+    let mut result = KrakenUniqResult::new(report, stdout, raw_sequences, summary);
+    result.run_metadata = Some(run_metadata);
+    Ok(result)
+}
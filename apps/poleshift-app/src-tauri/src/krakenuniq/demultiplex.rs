@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::io::merge::write_fastq_record;
+use crate::io::source::FastqSource;
+use crate::krakenuniq::parse_fastq_files::parse_nanopore_header;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Key used for reads whose header carries no `barcode=` token.
+pub(crate) const UNBARCODED: &str = "unclassified";
+/// Most distinct barcodes one run may hold, the largest ONT kit's 96 plus unclassified
+/// reads; each holds an open file while splitting.
+pub(crate) const MAX_BARCODES: usize = 97;
+/// Longest barcode accepted from a header.
+const MAX_BARCODE_LEN: usize = 64;
+
+/// File name stem of `barcode`: letters, digits and `-` are kept, every other byte is
+/// written as `_` and its hex code, so distinct barcodes never share a stem and none
+/// can lead out of the directory it is joined to.
+pub(crate) fn barcode_file_stem(barcode: &str) -> Result<String, PoleshiftError> {
+    if barcode.is_empty() || barcode.len() > MAX_BARCODE_LEN {
+        return Err(PoleshiftError::InvalidInput {
+            field: "barcode".to_string(),
+            reason: format!(
+                "'{}' must be 1 to {} bytes long",
+                barcode.escape_debug(),
+                MAX_BARCODE_LEN
+            ),
+        });
+    }
+    let mut stem = String::with_capacity(barcode.len());
+    for byte in barcode.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' => stem.push(byte as char),
+            _ => stem.push_str(&format!("_{:02X}", byte)),
+        }
+    }
+    Ok(stem)
+}
+
+/// Reads of one barcode, written out as their own FASTQ file.
+#[derive(Debug)]
+pub(crate) struct BarcodeGroup {
+    pub path: String,
+    pub read_count: usize,
+}
+
+/// Streams every read in `inputs` into `out_dir/<stem>.fastq.gz`, one file per barcode
+/// seen in the Nanopore headers (see `barcode_file_stem`). Groups are keyed (and
+/// ordered) by barcode. More than `MAX_BARCODES` barcodes, or two whose files would
+/// clash on a case-insensitive file system, are rejected.
+pub(crate) fn split_by_barcode(
+    inputs: &[String],
+    out_dir: &Path,
+) -> Result<BTreeMap<String, BarcodeGroup>, PoleshiftError> {
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut writers: BTreeMap<String, (GzEncoder<BufWriter<File>>, PathBuf, usize)> =
+        BTreeMap::new();
+    // Lowercased stems to the barcode holding them
+    let mut stems: HashMap<String, String> = HashMap::new();
+
+    for input in inputs {
+        let mut source = FastqSource::open(&PathBuf::from(input))?;
+        while let Some(record) = source.read_record()? {
+            let barcode = parse_nanopore_header(&record.header).barcode;
+            let barcode = if barcode.is_empty() {
+                UNBARCODED.to_string()
+            } else {
+                barcode
+            };
+
+            if !writers.contains_key(&barcode) {
+                if writers.len() >= MAX_BARCODES {
+                    return Err(PoleshiftError::InvalidInput {
+                        field: "demultiplex".to_string(),
+                        reason: format!("the reads hold more than {} barcodes", MAX_BARCODES),
+                    });
+                }
+                let file_stem = barcode_file_stem(&barcode)?;
+                if let Some(other) = stems.insert(file_stem.to_lowercase(), barcode.clone()) {
+                    return Err(PoleshiftError::InvalidInput {
+                        field: "demultiplex".to_string(),
+                        reason: format!(
+                            "barcodes '{}' and '{}' differ only in case",
+                            other, barcode
+                        ),
+                    });
+                }
+                let path = out_dir.join(format!("{}.fastq.gz", file_stem));
+                let writer =
+                    GzEncoder::new(BufWriter::new(File::create(&path)?), Compression::fast());
+                writers.insert(barcode.clone(), (writer, path, 0));
+            }
+
+            let (writer, _, count) = writers
+                .get_mut(&barcode)
+                .expect("writer was inserted above");
+            write_fastq_record(writer, &record)?;
+            *count += 1;
+        }
+    }
+
+    let mut groups = BTreeMap::new();
+    for (barcode, (writer, path, read_count)) in writers {
+        writer.finish()?.flush()?;
+        groups.insert(
+            barcode,
+            BarcodeGroup {
+                path: path.to_string_lossy().to_string(),
+                read_count,
+            },
+        );
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn write_reads(dir: &Path, barcodes: &[String]) -> String {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("reads.fastq");
+        let mut fastq = String::new();
+        for (index, barcode) in barcodes.iter().enumerate() {
+            fastq.push_str(&format!(
+                "@read{} barcode={}\nACGT\n+\nIIII\n",
+                index, barcode
+            ));
+        }
+        std::fs::write(&path, fastq).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("poleshift-demux-{}-{}", name, std::process::id()))
+    }
+
+    proptest! {
+        #[test]
+        fn stems_are_safe_and_distinct(a in "\\PC{1,16}", b in "\\PC{1,16}") {
+            let (stem_a, stem_b) = (barcode_file_stem(&a).unwrap(), barcode_file_stem(&b).unwrap());
+            prop_assert!(stem_a.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            prop_assert_eq!(a == b, stem_a == stem_b);
+        }
+    }
+
+    #[test]
+    fn stems_of_former_collisions_differ() {
+        assert_ne!(
+            barcode_file_stem("bc/01").unwrap(),
+            barcode_file_stem("bc_01").unwrap()
+        );
+        assert_eq!(barcode_file_stem("../x").unwrap(), "_2E_2E_2Fx");
+        assert!(barcode_file_stem(&"b".repeat(MAX_BARCODE_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_more_than_max_barcodes() {
+        let dir = scratch_dir("cap");
+        let barcodes: Vec<String> = (0..=MAX_BARCODES).map(|i| format!("bc{}", i)).collect();
+        let input = write_reads(&dir, &barcodes);
+        let result = split_by_barcode(&[input], &dir.join("out"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(result, Err(PoleshiftError::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn rejects_barcodes_differing_only_in_case() {
+        let dir = scratch_dir("case");
+        let input = write_reads(&dir, &["BC01".to_string(), "bc01".to_string()]);
+        let result = split_by_barcode(&[input], &dir.join("out"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(result, Err(PoleshiftError::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn splits_reads_by_barcode() {
+        let dir = scratch_dir("split");
+        let barcodes = ["bc/01", "bc_01", "bc/01"].map(String::from);
+        let input = write_reads(&dir, &barcodes);
+        let groups = split_by_barcode(&[input], &dir.join("out")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(groups["bc/01"].read_count, 2);
+        assert_eq!(groups["bc_01"].read_count, 1);
+        assert_ne!(groups["bc/01"].path, groups["bc_01"].path);
+    }
+}
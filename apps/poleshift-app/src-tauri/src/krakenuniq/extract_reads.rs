@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params_from_iter, Connection};
+use serde::Serialize;
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::io::merge::write_fastq_record;
+use crate::io::FastqRecord;
+use crate::krakenuniq::output_store::{
+    load_report, open_store, output_store_path, require_confidence,
+};
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Tax ID the classifier gives reads it could not assign.
+pub(crate) const UNCLASSIFIED_TAX_ID: u64 = 0;
+
+#[derive(Debug, Serialize)]
+pub struct ReadExtraction {
+    pub output_path: String,
+    /// Tax IDs whose reads were selected
+    pub tax_ids: Vec<u64>,
+    pub reads_written: usize,
+}
+
+/// Tax IDs of `tax_id` and, with `include_descendants`, everything below it in the report.
+pub(crate) fn subtree_tax_ids(
+    report: &[ProcessedKrakenUniqReport],
+    tax_id: u64,
+    include_descendants: bool,
+) -> HashSet<u64> {
+    let mut selected = HashSet::from([tax_id]);
+    if !include_descendants {
+        return selected;
+    }
+
+    let mut children: HashMap<String, Vec<&ProcessedKrakenUniqReport>> = HashMap::new();
+    for row in report {
+        if let Some(parent) = row.parent_id {
+            children.entry(parent.to_string()).or_default().push(row);
+        }
+    }
+
+    let mut stack: Vec<&ProcessedKrakenUniqReport> =
+        report.iter().filter(|row| row.tax_id == tax_id).collect();
+    while let Some(row) = stack.pop() {
+        for &child in children.get(&row.id).map(Vec::as_slice).unwrap_or(&[]) {
+            if selected.insert(child.tax_id) {
+                stack.push(child);
+            }
+        }
+    }
+    selected
+}
+
+/// Writes the stored reads of `processed_data_id` assigned to `tax_ids` as FASTQ,
+/// gzip-compressed when `output` ends in `.gz`. Headers carry the assigned tax ID.
+/// Classified reads below `min_confidence` are skipped.
+fn write_reads(
+    store: &Path,
+    tax_ids: &HashSet<u64>,
+    min_confidence: Option<f64>,
+    output: &Path,
+) -> Result<usize, PoleshiftError> {
+    let conn = open_store(store)?;
+    require_confidence(&conn, min_confidence)?;
+
+    // 1) Read IDs assigned to the selected taxa
+    let mut assigned: HashMap<String, i64> = HashMap::new();
+    {
+        let sql = match min_confidence {
+            Some(_) => {
+                "SELECT feature_id, tax_id FROM stdout
+                 WHERE confidence IS NULL OR confidence >= ?1"
+            }
+            None => "SELECT feature_id, tax_id FROM stdout",
+        };
+        let mut stmt = conn
+            .prepare(sql)
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        let mut rows = stmt
+            .query(params_from_iter(min_confidence))
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        while let Some(row) = rows
+            .next()
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        {
+            let tax_id: i64 = row
+                .get(1)
+                .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+            if tax_id >= 0 && tax_ids.contains(&(tax_id as u64)) {
+                let feature_id: String = row
+                    .get(0)
+                    .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+                assigned.insert(feature_id, tax_id);
+            }
+        }
+    }
+
+    // 2) Stream the matching raw reads into the output file
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = BufWriter::new(File::create(output)?);
+    if output.extension().is_some_and(|ext| ext == "gz") {
+        let mut writer = GzEncoder::new(file, Compression::default());
+        let written = copy_assigned_reads(&conn, &assigned, &mut writer)?;
+        writer.finish()?.flush()?;
+        Ok(written)
+    } else {
+        let mut writer = file;
+        let written = copy_assigned_reads(&conn, &assigned, &mut writer)?;
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+fn copy_assigned_reads<W: Write>(
+    conn: &Connection,
+    assigned: &HashMap<String, i64>,
+    writer: &mut W,
+) -> Result<usize, PoleshiftError> {
+    let mut written = 0usize;
+    let mut stmt = conn
+        .prepare("SELECT feature_id, sequence, quality FROM raw_sequences ORDER BY rowid")
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let mut rows = stmt
+        .query([])
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    while let Some(row) = rows
+        .next()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+    {
+        let feature_id: String = row
+            .get(0)
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        let Some(tax_id) = assigned.get(&feature_id) else {
+            continue;
+        };
+        let sequence: String = row
+            .get(1)
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        let quality: String = row
+            .get(2)
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        write_fastq_record(
+            writer,
+            &FastqRecord {
+                header: format!("{} taxid={}", feature_id, tax_id),
+                sequence,
+                quality: quality.into_bytes(),
+            },
+        )?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Writes the reads of a stored classification that were assigned to `tax_id` to a
+/// FASTQ file, e.g. for BLASTing suspicious hits. Reads of all descendant taxa are
+/// included unless `include_descendants` is false, and reads below `min_confidence`
+/// are left out. Pass `tax_id` 0 for the unclassified bin.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn extract_reads_by_taxon<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    tax_id: u64,
+    output_path: String,
+    include_descendants: Option<bool>,
+    min_confidence: Option<f64>,
+) -> Result<StandardResponseNoFiles<ReadExtraction>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let store = output_store_path(&app_handle, &processed_data_id)?;
+
+        let tax_ids = if tax_id == UNCLASSIFIED_TAX_ID {
+            HashSet::from([UNCLASSIFIED_TAX_ID])
+        } else {
+            let report = load_report(&store)?;
+            if !report.iter().any(|row| row.tax_id == tax_id) {
+                return Err(PoleshiftError::DataError(format!(
+                    "Tax ID {} is not in the report of {}",
+                    tax_id, processed_data_id
+                )));
+            }
+            subtree_tax_ids(&report, tax_id, include_descendants.unwrap_or(true))
+        };
+
+        let output = PathBuf::from(&output_path);
+        let selected = tax_ids.clone();
+        let reads_written = tauri::async_runtime::spawn_blocking(move || {
+            write_reads(&store, &selected, min_confidence, &output)
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Read extraction task failed: {}", e)))??;
+
+        let mut tax_ids: Vec<u64> = tax_ids.into_iter().collect();
+        tax_ids.sort_unstable();
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: ReadExtraction {
+                output_path,
+                tax_ids,
+                reads_written,
+            },
+        })
+    })
+    .await
+}
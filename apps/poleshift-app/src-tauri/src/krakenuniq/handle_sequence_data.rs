@@ -1,23 +1,56 @@
 // src/lib/hooks/useTauriDataProcessor.rs
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{remove_file, File};
 use std::io::copy;
-use std::path::PathBuf; // Needed to serialize Vec<String> -> JSON array string
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant}; // Needed to serialize Vec<String> -> JSON array string
 
 use flate2::read::GzDecoder;
-use tauri::{AppHandle, Manager, Runtime};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, Window};
 use uuid::Uuid; // <-- ADD THIS
 
+use crate::devtools::{traced, Invocation};
+use crate::io::complexity::ComplexityOptions;
+use crate::io::umi::UmiOptions;
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
 use crate::poleshift_common::types::{KrakenConfig, PoleshiftError, StandardResponseNoFiles};
-use crate::poleshift_common::utils::emit_progress;
+use crate::poleshift_common::utils::{parse_uuid, validate_path_id};
+use crate::results_store::{
+    results_store_path, save_result, RawSequenceMetadata, ResultKeys, ResultKind,
+};
+use crate::settings::{current_settings, resources_dir};
+use crate::splashscreen::{resolve_database_dir, DEFAULT_DATABASE_ID};
 
 // Pull in these items from your own modules:
 use crate::krakenuniq::{
-    parse_fastq_files::parse_fastq_files, KrakenUniqResult, ProcessedKrakenUniqReport,
-    ProcessedKrakenUniqStdout,
+    backend::{backend_for, ClassificationBackend, ClassificationRequest},
+    checkpoint::{checkpoint_path, Checkpoint},
+    confidence::read_confidence,
+    contaminants::{remove_contaminants, ContaminantOptions},
+    demultiplex::{barcode_file_stem, split_by_barcode},
+    e_score,
+    options::ClassificationOptions,
+    output_store::{output_store_path, persist_outputs},
+    parse_fastq_files::parse_fastq_files,
+    preload::{plan_load, DatabaseLoadPlan},
+    preprocess::{preprocess_reads, PreprocessingOptions},
+    raw_sequence_store::{self, summarize_raw_sequences},
+    result_cache::{result_cache_dir, ResultCache},
+    run_metadata::RunMetadata,
+    taxdb::{annotate_lineages, TaxDb},
+    taxonomy_service::TaxonomyCache,
+    EScoreFormula, KrakenUniqResult, NodeIds, ProcessedKrakenUniqReport, ProcessedKrakenUniqStdout,
 };
-use krakenuniq_rs::{classify_reads, ClassificationResults};
+use krakenuniq_rs::ClassificationResults;
+
+/// How often a running classification checks for cancellation.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often job progress is emitted while the classifier runs.
+const JOB_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
 
 impl KrakenConfig {
     pub fn hardcoded(resource_dir: PathBuf, input_files: Vec<String>) -> Self {
@@ -39,8 +72,23 @@ impl KrakenConfig {
                 .into_iter()
                 .map(|file| PathBuf::from(file))
                 .collect(),
+            database_id: DEFAULT_DATABASE_ID.to_string(),
         }
     }
+
+    /// Like `hardcoded`, but for the files of the named database set from the manifest.
+    pub fn for_database(
+        resource_dir: &Path,
+        database_id: &str,
+        input_files: Vec<String>,
+    ) -> Result<Self, PoleshiftError> {
+        let database_dir =
+            resolve_database_dir(resource_dir, database_id).map_err(PoleshiftError::DataError)?;
+        Ok(Self {
+            database_id: database_id.to_string(),
+            ..Self::hardcoded(database_dir, input_files)
+        })
+    }
 }
 
 /// Decompresses a file if a `.gz` variant exists, and then deletes the `.gz`.
@@ -71,7 +119,7 @@ fn maybe_decompress(file_path: &str) -> Result<(), PoleshiftError> {
         copy(&mut d, &mut out_file).map_err(|e| {
             PoleshiftError::Other(format!("Failed to decompress {}: {}", gz_path.display(), e))
         })?;
-        
+
         // Now that decompression was successful, remove the `.gz` file
         remove_file(&gz_path).map_err(|e| {
             PoleshiftError::Other(format!(
@@ -86,7 +134,7 @@ fn maybe_decompress(file_path: &str) -> Result<(), PoleshiftError> {
 }
 
 /// Decompress the four main Kraken DB files if needed, then delete the `.gz` files.
-fn maybe_decompress_config_files(config: &KrakenConfig) -> Result<(), PoleshiftError> {
+pub(crate) fn maybe_decompress_config_files(config: &KrakenConfig) -> Result<(), PoleshiftError> {
     maybe_decompress(&config.db_file)?;
     maybe_decompress(&config.idx_file)?;
     maybe_decompress(&config.taxdb_file)?;
@@ -94,9 +142,119 @@ fn maybe_decompress_config_files(config: &KrakenConfig) -> Result<(), PoleshiftE
     Ok(())
 }
 
-/// Our command to handle sequence data; decompresses DB files first, then calls `classify_reads`.
+/// Arguments of a single `handle_sequence_data` invocation, and of each job given to
+/// `queue_classification_jobs`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SequenceRunArgs {
+    file_paths: Vec<String>,
+    pub(crate) processed_data_id: String,
+    raw_data_id: String,
+    user_id: String,
+    org_id: String,
+    sample_id: String,
+    persist_raw_sequences: Option<bool>,
+    umi: Option<UmiOptions>,
+    low_complexity: Option<ComplexityOptions>,
+    drop_superseded_simplex: Option<bool>,
+    demultiplex: Option<bool>,
+    barcode_sample_ids: Option<HashMap<String, String>>,
+    pub(crate) job_id: Option<String>,
+    classification_options: Option<ClassificationOptions>,
+    database_id: Option<String>,
+    persist_outputs: Option<bool>,
+    contaminants: Option<ContaminantOptions>,
+    checkpoint: Option<bool>,
+    force: Option<bool>,
+    deterministic_ids: Option<bool>,
+}
+
+/// What `handle_sequence_data` returns: one result, or one result per barcode when
+/// demultiplexing. Untagged, so the single-sample payload is unchanged.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SequenceDataReport {
+    Single(KrakenUniqResult),
+    Demultiplexed(BTreeMap<String, KrakenUniqResult>),
+}
+
+/// The ids a classification result is stamped with.
+pub(crate) struct SampleIds<'a> {
+    pub processed_data_id: &'a str,
+    pub raw_data_id: &'a str,
+    pub user_id: &'a str,
+    pub org_id: &'a str,
+    pub sample_id: &'a str,
+}
+
+/// Checks that every ID stamped on the outputs is a UUID, so a malformed one fails the
+/// call with the field it came from instead of aborting mid-run. `raw_data_id` names
+/// the sidecar and cache files, so it only has to be safe in a file name.
+pub(crate) fn validate_ids(
+    ids: &SampleIds,
+    barcode_sample_ids: Option<&HashMap<String, String>>,
+) -> Result<(), PoleshiftError> {
+    parse_uuid("processed_data_id", ids.processed_data_id)?;
+    validate_path_id("raw_data_id", ids.raw_data_id)?;
+    parse_uuid("user_id", ids.user_id)?;
+    parse_uuid("org_id", ids.org_id)?;
+    parse_uuid("sample_id", ids.sample_id)?;
+    for (barcode, sample_id) in barcode_sample_ids.into_iter().flatten() {
+        parse_uuid(&format!("barcode_sample_ids[{}]", barcode), sample_id)?;
+    }
+    Ok(())
+}
+
+/// Our command to handle sequence data; decompresses DB files first, then runs the classifier
+/// backend selected in `classification_options` (in-process by default).
+///
+/// When `persist_raw_sequences` is true the parsed reads are written to a SQLite sidecar
+/// in the app data directory and only their summary is returned. When `umi` is given,
+/// reads first pass through the QC stage, which extracts (and optionally deduplicates
+/// by) their UMIs before classification; `low_complexity` adds a filter to the same
+/// stage that drops homopolymer-heavy and repetitive reads. `drop_superseded_simplex`
+/// adds a step to it that leaves out simplex reads whose duplex offspring is present,
+/// so they count neither in `raw_sequences` nor in the report.
+///
+/// With `demultiplex` the reads are grouped by their header barcode and each group is
+/// classified separately. Each barcode gets its own `processed_data_id`, and its
+/// `sample_id` is looked up in `barcode_sample_ids` (falling back to `sample_id`).
+///
+/// The run is registered as a job (ID `job_id`, or a generated one reported in every
+/// progress event, on `job://{job_id}/progress`) and can be stopped with `cancel_job`.
+/// `classification_options` tunes the classifier itself and is validated up front.
+/// `database_id` selects the reference database set (see `list_databases`).
+///
+/// With `persist_outputs` the report, per-read output and raw reads are written to a
+/// SQLite output store per `processed_data_id` instead of being returned; the result
+/// then only carries counts and the store location, and the UI pages through the rows
+/// with `query_classification_report`, `query_classified_reads` and `query_raw_sequences`.
+///
+/// `contaminants` lists taxa (e.g. human, PhiX) whose reads are removed right after
+/// classification, before anything is returned or stored; the report is adjusted to
+/// match and the removed counts are reported in `contaminants` on the result.
+///
+/// With `checkpoint` the input files are classified one at a time and each file's
+/// results are saved under the app data directory as soon as it finishes. Calling
+/// again with the same `processed_data_id` after a crash skips the files already done
+/// and merges everything into one report; the checkpoint is deleted once the run
+/// succeeds. Only single-sample runs without preprocessing can be checkpointed.
+///
+/// Classifier results are cached under the app data directory, keyed by the contents
+/// of the input files, the database files and the options that affect the results.
+/// A run identical to an earlier one reuses its results instead of classifying again,
+/// and reports the key as `cached_classification`; `force` classifies regardless and
+/// replaces the cached entry. See `list_result_cache` and `clear_result_cache`.
+///
+/// Report rows get random IDs by default. With `deterministic_ids` each row's ID is a
+/// UUIDv5 of the sample ID, the tax ID and the database, so classifying a sample
+/// again against the same database reproduces the IDs already uploaded.
+///
+/// The database load strategy under `classification_options.memory_budget_mb` (or the
+/// detected free memory) is reported as `database_load`; see `plan_database_load`.
+#[allow(clippy::too_many_arguments)]
 #[tauri::command(rename_all = "snake_case")]
 pub async fn handle_sequence_data<R: Runtime>(
+    invocation: Invocation<R>,
     app_handle: AppHandle<R>,
     file_paths: Vec<String>,
     processed_data_id: String,
@@ -104,74 +262,653 @@ pub async fn handle_sequence_data<R: Runtime>(
     user_id: String,
     org_id: String,
     sample_id: String,
-) -> Result<StandardResponseNoFiles<KrakenUniqResult>, PoleshiftError> {
+    persist_raw_sequences: Option<bool>,
+    umi: Option<UmiOptions>,
+    low_complexity: Option<ComplexityOptions>,
+    drop_superseded_simplex: Option<bool>,
+    demultiplex: Option<bool>,
+    barcode_sample_ids: Option<HashMap<String, String>>,
+    job_id: Option<String>,
+    classification_options: Option<ClassificationOptions>,
+    database_id: Option<String>,
+    persist_outputs: Option<bool>,
+    contaminants: Option<ContaminantOptions>,
+    checkpoint: Option<bool>,
+    force: Option<bool>,
+    deterministic_ids: Option<bool>,
+) -> Result<StandardResponseNoFiles<SequenceDataReport>, PoleshiftError> {
+    let args = SequenceRunArgs {
+        file_paths,
+        processed_data_id,
+        raw_data_id,
+        user_id,
+        org_id,
+        sample_id,
+        persist_raw_sequences,
+        umi,
+        low_complexity,
+        drop_superseded_simplex,
+        demultiplex,
+        barcode_sample_ids,
+        job_id,
+        classification_options,
+        database_id,
+        persist_outputs,
+        contaminants,
+        checkpoint,
+        force,
+        deterministic_ids,
+    };
+    let trace_handle = app_handle.clone();
+    let jobs = trace_handle.state::<JobManager>();
+    let job = jobs.start("classification", args.job_id.clone())?;
+    let result = traced(
+        invocation,
+        Some(job.id()),
+        process_sequence_data(app_handle, args, job.clone()),
+    )
+    .await;
+    jobs.finish(job.id(), result.as_ref().err());
+    result
+}
+
+/// Runs the whole pipeline on the blocking pool, so multi-hour classifications do not
+/// hold up the async runtime (and with it every other command).
+async fn process_sequence_data<R: Runtime>(
+    app_handle: AppHandle<R>,
+    args: SequenceRunArgs,
+    job: JobHandle,
+) -> Result<StandardResponseNoFiles<SequenceDataReport>, PoleshiftError> {
+    tauri::async_runtime::spawn_blocking(move || run_sequence_pipeline(app_handle, args, job))
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Sequence processing task failed: {}", e)))?
+}
+
+/// The whole pipeline for one invocation; the caller registers and finishes `job`.
+pub(crate) fn run_sequence_pipeline<R: Runtime>(
+    app_handle: AppHandle<R>,
+    args: SequenceRunArgs,
+    job: JobHandle,
+) -> Result<StandardResponseNoFiles<SequenceDataReport>, PoleshiftError> {
+    let SequenceRunArgs {
+        file_paths,
+        processed_data_id,
+        raw_data_id,
+        user_id,
+        org_id,
+        sample_id,
+        persist_raw_sequences,
+        umi,
+        low_complexity,
+        drop_superseded_simplex,
+        demultiplex,
+        barcode_sample_ids,
+        classification_options,
+        database_id,
+        persist_outputs,
+        contaminants,
+        checkpoint,
+        force,
+        deterministic_ids,
+        ..
+    } = args;
+
     if file_paths.is_empty() {
         return Err(PoleshiftError::NoFiles);
     }
+    // 1) Reject malformed IDs before any work is done
+    validate_ids(
+        &SampleIds {
+            processed_data_id: &processed_data_id,
+            raw_data_id: &raw_data_id,
+            user_id: &user_id,
+            org_id: &org_id,
+            sample_id: &sample_id,
+        },
+        barcode_sample_ids.as_ref(),
+    )?;
+    let settings = current_settings(&app_handle);
+    let classification_options = settings.classification_options(classification_options);
+    classification_options.validate()?;
+    if let Some(contaminants) = &contaminants {
+        contaminants.validate()?;
+    }
+    if let Some(umi) = &umi {
+        umi.validate()?;
+    }
+    let database_id = database_id.unwrap_or(settings.database_id);
+    let checkpoint = checkpoint.unwrap_or(false);
+    let drop_superseded_simplex = drop_superseded_simplex.unwrap_or(false);
+    if checkpoint
+        && (demultiplex.unwrap_or(false)
+            || umi.is_some()
+            || low_complexity.is_some()
+            || drop_superseded_simplex)
+    {
+        return Err(PoleshiftError::DataError(
+            "Checkpointing is only supported for single-sample runs without preprocessing"
+                .to_string(),
+        ));
+    }
 
     let window = app_handle
         .get_window("main")
         .ok_or_else(|| PoleshiftError::WindowNotFound)?;
+    job.emit_progress(&window)?;
 
-    emit_progress(&window, 10, "Resolving database paths...", "processing")?;
+    job.report_progress(&window, 10, "Resolving database paths...", "processing")?;
 
     // 2) Resolve paths for resources and temporary storage
-    let resource_dir = app_handle
+    let resource_dir = resources_dir(&app_handle)?;
+    println!("resource_dir: {:?}", resource_dir);
+    let cache_dir = app_handle
+        .path()
+        .app_cache_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?;
+    let raw_sidecar_dir = app_handle
         .path()
-        .resource_dir()
+        .app_data_dir()
         .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
-        .join("./resources");
-    println!("resource_dir: {:?}", resource_dir);
+        .join("raw_sequences");
+    let output_target = |processed_data_id: &str, sidecar_name: &str| {
+        Ok::<_, PoleshiftError>(if persist_outputs.unwrap_or(false) {
+            OutputTarget::Store(output_store_path(&app_handle, processed_data_id)?)
+        } else if persist_raw_sequences.unwrap_or(false) {
+            OutputTarget::RawSidecar(raw_sidecar_dir.join(format!("{}.sqlite", sidecar_name)))
+        } else {
+            OutputTarget::Inline
+        })
+    };
 
-    emit_progress(
+    job.report_progress(
         &window,
         20,
         "Decompressing database files if necessary...",
         "processing",
     )?;
 
-    // 3) Build a local `KrakenConfig`
-    let config = KrakenConfig::hardcoded(resource_dir, file_paths.clone());
-
-    // 4) Attempt to decompress the DB files if they are gzipped
-    maybe_decompress_config_files(&config)?;
-
-    emit_progress(&window, 30, "Starting classification...", "processing")?;
-
-    // 5) Perform classification using `classify_reads`
-    let classification_results: ClassificationResults = match classify_reads(
-        &config.db_file,
-        &config.idx_file,
-        &config.counts_file,
-        &config.taxdb_file,
-        config.input_files,
-        /* print_sequence_in_kraken = */ false,
-        /* only_classified_kraken_output = */ false,
-        /* generate_report = */ true,
-    ) {
-        Ok(results) => results,
+    // 3) Optional QC stage; its output replaces the inputs for everything below
+    let preprocessing_options = PreprocessingOptions {
+        umi,
+        low_complexity,
+        drop_superseded_simplex,
+    };
+    let (input_paths, preprocessing) = if preprocessing_options.is_enabled() {
+        job.report_progress(&window, 25, "Preprocessing reads...", "processing")?;
+        job.set_stage("preprocessing");
+        let preprocessed_path = cache_dir
+            .join("preprocessed")
+            .join(format!("{}.fastq.gz", raw_data_id));
+        let summary = preprocess_reads(&file_paths, &preprocessed_path, &preprocessing_options)?;
+        (
+            vec![preprocessed_path.to_string_lossy().to_string()],
+            Some(summary),
+        )
+    } else {
+        (file_paths.clone(), None)
+    };
+
+    job.check_cancelled()?;
+
+    // 4) Resolve the selected database and decompress its files if they are gzipped
+    let database_config = KrakenConfig::for_database(&resource_dir, &database_id, Vec::new())?;
+    maybe_decompress_config_files(&database_config)?;
+
+    // The in-process classifier always preloads the whole database. An explicit memory
+    // budget the backend cannot meet fails the run here; a shortfall in detected memory
+    // only warns, as the OS may still make room by swapping. Kraken2 and Centrifuge
+    // bring their own database, so nothing is planned for them
+    let memory_budget_mb = classification_options.memory_budget_mb;
+    let database_load = if !classification_options.backend.uses_krakenuniq_database() {
+        None
+    } else {
+        match plan_load(
+            &database_config,
+            memory_budget_mb,
+            classification_options.backend,
+        ) {
+            Ok(plan) if !plan.supported && memory_budget_mb.is_some() => {
+                return Err(PoleshiftError::DataError(format!(
+                    "The database needs {} MB but the memory budget is {} MB, and the \
+                     in-process classifier can only preload the whole database ({:?} loading \
+                     would be needed)",
+                    plan.database_bytes.div_ceil(1024 * 1024),
+                    memory_budget_mb.unwrap_or_default(),
+                    plan.strategy
+                )));
+            }
+            Ok(plan) => {
+                if !plan.supported {
+                    println!(
+                        "Database may not fit in available memory: {}",
+                        plan.tradeoff
+                    );
+                }
+                Some(plan)
+            }
+            Err(e) if memory_budget_mb.is_none() => {
+                println!("Database may not fit in available memory: {}", e);
+                None
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    // Lineages are a nicety; a taxDB that cannot be read leaves them empty
+    let taxonomy = app_handle.state::<TaxonomyCache>();
+    let taxdb = match taxonomy.get(Path::new(&database_config.taxdb_file)) {
+        Ok(taxdb) => Some(taxdb),
         Err(e) => {
-            println!("Error during classification: {}", e);
-            return Err(PoleshiftError::Other(e.to_string()));
+            println!("Could not load taxDB for lineages: {}", e);
+            None
         }
     };
 
-    emit_progress(
+    let classifier = ClassifierRun {
+        window: &window,
+        job: &job,
+        resource_dir: &resource_dir,
+        database_id: &database_id,
+        options: &classification_options,
+        backend: backend_for(&classification_options, &resource_dir),
+        load_plan: database_load.as_ref(),
+        contaminants: contaminants.as_ref(),
+        taxdb: taxdb.as_deref().map(|index| &index.taxdb),
+        cache: Some(ResultCache::new(
+            result_cache_dir(&app_handle)?,
+            force.unwrap_or(false),
+        )),
+        deterministic_ids: deterministic_ids.unwrap_or(false),
+    };
+
+    // 5) Single sample: classify all reads together
+    if !demultiplex.unwrap_or(false) {
+        job.report_progress(&window, 30, "Starting classification...", "processing")?;
+        let ids = SampleIds {
+            processed_data_id: &processed_data_id,
+            raw_data_id: &raw_data_id,
+            user_id: &user_id,
+            org_id: &org_id,
+            sample_id: &sample_id,
+        };
+        let target = output_target(&processed_data_id, &raw_data_id)?;
+        let checkpoint_path = if checkpoint {
+            Some(checkpoint_path(&app_handle, &processed_data_id)?)
+        } else {
+            None
+        };
+        let mut final_kraken_result = classify_sample(
+            &classifier,
+            input_paths,
+            &ids,
+            target,
+            checkpoint_path.as_deref(),
+        )?;
+        final_kraken_result.preprocessing = preprocessing;
+        final_kraken_result.database_load = database_load;
+        store_result(&app_handle, &ids, &final_kraken_result);
+        if let Some(path) = checkpoint_path {
+            // The outputs are complete, so there is nothing left to resume
+            if let Err(e) = std::fs::remove_file(&path) {
+                println!("Could not remove checkpoint {}: {}", path.display(), e);
+            }
+        }
+
+        job.report_progress(&window, 50, "Processing complete...", "processing")?;
+
+        return Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: SequenceDataReport::Single(final_kraken_result),
+        });
+    }
+
+    // 6) Demultiplexed: split the reads by barcode, then classify each group
+    job.report_progress(
         &window,
-        40,
-        "Classification complete. Preparing final data...",
+        25,
+        "Demultiplexing reads by barcode...",
         "processing",
     )?;
+    job.set_stage("demultiplexing");
+    let groups = split_by_barcode(&input_paths, &cache_dir.join("demux").join(&raw_data_id))?;
+    job.set_reads_total(groups.values().map(|g| g.read_count as u64).sum());
+    let barcode_sample_ids = barcode_sample_ids.unwrap_or_default();
+    let group_count = groups.len().max(1);
+
+    let mut results = BTreeMap::new();
+    for (index, (barcode, group)) in groups.into_iter().enumerate() {
+        job.report_progress(
+            &window,
+            30 + (index * 20 / group_count) as u8,
+            &format!(
+                "Classifying {} ({} reads, {}/{})...",
+                barcode,
+                group.read_count,
+                index + 1,
+                group_count
+            ),
+            "processing",
+        )?;
+
+        let barcode_processed_data_id = Uuid::new_v4().to_string();
+        let ids = SampleIds {
+            processed_data_id: &barcode_processed_data_id,
+            raw_data_id: &raw_data_id,
+            user_id: &user_id,
+            org_id: &org_id,
+            sample_id: barcode_sample_ids
+                .get(&barcode)
+                .map(String::as_str)
+                .unwrap_or(&sample_id),
+        };
+        let target = output_target(
+            &barcode_processed_data_id,
+            &format!("{}_{}", raw_data_id, barcode_file_stem(&barcode)?),
+        )?;
+        job.check_cancelled()?;
+        let mut result = classify_sample(&classifier, vec![group.path], &ids, target, None)?;
+        result.preprocessing = preprocessing.clone();
+        result.database_load = database_load.clone();
+        store_result(&app_handle, &ids, &result);
+        results.insert(barcode, result);
+    }
+
+    job.report_progress(&window, 50, "Processing complete...", "processing")?;
+
+    Ok(StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report: SequenceDataReport::Demultiplexed(results),
+    })
+}
+
+/// Runs the selected backend on a worker thread, emitting job progress while it runs.
+///
+/// Backends watch the job and the request's deadline themselves: the in-process
+/// classifier stops between batches of reads, the executable backends kill their
+/// process. Once the job is cancelled or times out, the worker is joined before the
+/// error is returned, so no classification keeps running behind a finished job.
+pub(crate) fn run_classification<R: Runtime>(
+    classifier: &ClassifierRun<R>,
+    config: KrakenConfig,
+) -> Result<ClassificationResults, PoleshiftError> {
+    let (window, job) = (classifier.window, classifier.job);
+    let request = ClassificationRequest {
+        config,
+        options: classifier.options.clone(),
+        load_plan: classifier.load_plan.cloned(),
+        deadline: classifier
+            .options
+            .timeout_secs
+            .map(|secs| Instant::now() + Duration::from_secs(secs)),
+    };
+    let deadline = request.deadline;
+    let backend = classifier.backend.clone();
+    let worker_job = job.clone();
+
+    let (sender, receiver) = mpsc::channel();
+    let worker = std::thread::spawn(move || {
+        let result = backend.classify(request, &worker_job);
+        // The receiver is gone if the job was cancelled
+        let _ = sender.send(result);
+    });
+
+    let mut last_emit = Instant::now();
+    loop {
+        match receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+            Ok(Ok(results)) => return Ok(results),
+            Ok(Err(e)) => {
+                println!("Error during classification: {}", e);
+                return Err(e);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let stopped = if job.is_cancelled() {
+                    Some(PoleshiftError::Cancelled(job.id().to_string()))
+                } else if deadline.is_some_and(|d| Instant::now() >= d) {
+                    Some(PoleshiftError::Other(format!(
+                        "Classification timed out after {} s",
+                        classifier.options.timeout_secs.unwrap_or_default()
+                    )))
+                } else {
+                    None
+                };
+                if let Some(e) = stopped {
+                    // The backend sees the same flag and deadline and is stopping too
+                    let _ = worker.join();
+                    return Err(e);
+                }
+                if last_emit.elapsed() >= JOB_PROGRESS_INTERVAL {
+                    job.emit_progress(window)?;
+                    last_emit = Instant::now();
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(PoleshiftError::Other(
+                    "Classification worker exited without a result".to_string(),
+                ));
+            }
+        }
+    }
+}
+
+/// What every classification of one invocation shares.
+pub(crate) struct ClassifierRun<'a, R: Runtime> {
+    pub window: &'a Window<R>,
+    pub job: &'a JobHandle,
+    pub resource_dir: &'a Path,
+    pub database_id: &'a str,
+    pub options: &'a ClassificationOptions,
+    pub backend: Arc<dyn ClassificationBackend>,
+    pub load_plan: Option<&'a DatabaseLoadPlan>,
+    pub contaminants: Option<&'a ContaminantOptions>,
+    pub taxdb: Option<&'a TaxDb>,
+    /// Where finished classifications are cached and looked up, if anywhere
+    pub cache: Option<ResultCache>,
+    /// Derive report row IDs from the sample, tax ID and database (see `NodeIds`)
+    pub deterministic_ids: bool,
+}
+
+impl<R: Runtime> ClassifierRun<'_, R> {
+    /// The database results depend on: an explicit path of a non-bundled backend, or
+    /// else the bundled database ID.
+    fn database_version(&self) -> &str {
+        self.options
+            .database_path
+            .as_deref()
+            .unwrap_or(self.database_id)
+    }
+}
+
+/// Where the outputs of one classification go.
+pub(crate) enum OutputTarget {
+    /// Everything is returned in the result
+    Inline,
+    /// Raw reads go to a SQLite sidecar at this path; report and stdout are returned
+    RawSidecar(PathBuf),
+    /// Report, stdout and raw reads all go to an output store at this path
+    Store(PathBuf),
+}
+
+/// Classifies each of `input_paths` on its own, recording every finished file in the
+/// checkpoint at `path` and skipping files it already holds, then merges the results.
+fn classify_with_checkpoint<R: Runtime>(
+    classifier: &ClassifierRun<R>,
+    input_paths: &[String],
+    path: &Path,
+) -> Result<ClassificationResults, PoleshiftError> {
+    // Results against another database must not be resumed from
+    let mut checkpoint = Checkpoint::open(path, classifier.database_version())?;
+    for (index, input) in input_paths.iter().enumerate() {
+        let file = Path::new(input);
+        if checkpoint.is_complete(file)? {
+            println!("Resuming: {} was already classified", input);
+            continue;
+        }
+        println!(
+            "Classifying file {}/{}: {}",
+            index + 1,
+            input_paths.len(),
+            input
+        );
+        let config = KrakenConfig::for_database(
+            classifier.resource_dir,
+            classifier.database_id,
+            vec![input.clone()],
+        )?;
+        let results = run_classification(classifier, config)?;
+        checkpoint.record(file, &results)?;
+        classifier.job.check_cancelled()?;
+    }
+    checkpoint.merged_results()
+}
+
+/// Saves a sample's taxa to the local results store, with the counts and run header of
+/// its reads but not the reads themselves. A failed save is logged; the result is
+/// still returned.
+fn store_result<R: Runtime>(app_handle: &AppHandle<R>, ids: &SampleIds, result: &KrakenUniqResult) {
+    let keys = ResultKeys {
+        processed_data_id: ids.processed_data_id.to_string(),
+        kind: ResultKind::Classification,
+        sample_id: ids.sample_id.to_string(),
+        org_id: Some(ids.org_id.to_string()),
+        user_id: Some(ids.user_id.to_string()),
+        raw_data_id: Some(ids.raw_data_id.to_string()),
+    };
+    let saved = (|| {
+        let report = serde_json::json!({
+            "processed_kraken_uniq_report": result.processed_kraken_uniq_report,
+            "database_id": result.database_id,
+            "output_store": result.output_store,
+            "preprocessing": result.preprocessing,
+            "contaminants": result.contaminants,
+        });
+        let metadata = RawSequenceMetadata {
+            summary: serde_json::to_value(&result.raw_sequences_summary)?,
+            run_metadata: result
+                .run_metadata
+                .as_ref()
+                .map(serde_json::to_value)
+                .transpose()?,
+        };
+        save_result(
+            &results_store_path(app_handle)?,
+            &keys,
+            &report,
+            Some(&metadata),
+        )
+    })();
+    if let Err(e) = saved {
+        println!("Could not store result {}: {}", ids.processed_data_id, e);
+    }
+}
+
+/// Classifies `input_paths` as one sample and assembles its result, keeping the
+/// outputs out of the result as `target` asks. With a `checkpoint` path the files are
+/// classified one by one and resumed from there (see `classify_with_checkpoint`).
+fn classify_sample<R: Runtime>(
+    classifier: &ClassifierRun<R>,
+    input_paths: Vec<String>,
+    ids: &SampleIds,
+    target: OutputTarget,
+    checkpoint: Option<&Path>,
+) -> Result<KrakenUniqResult, PoleshiftError> {
+    let (window, job) = (classifier.window, classifier.job);
+    let config = KrakenConfig::for_database(
+        classifier.resource_dir,
+        classifier.database_id,
+        input_paths.clone(),
+    )?;
+
+    let database_id = config.database_id.clone();
+
+    // 1) Perform classification with the selected backend, unless an identical run
+    // is cached
+    job.set_stage("classifying");
+    // Backends may count reads as they go; the count is settled once they finish
+    let reads_before = job.progress().reads_processed;
+    let cache_key = classifier
+        .cache
+        .as_ref()
+        .map(|cache| cache.key(&config, classifier.options))
+        .transpose()?;
+    let cached = match (&classifier.cache, &cache_key) {
+        (Some(cache), Some(key)) => cache.get(key),
+        _ => None,
+    };
+    let cached_classification = cached.as_ref().and(cache_key.clone());
+    let classification_results = match (cached, checkpoint) {
+        (Some(results), _) => {
+            println!(
+                "Reusing cached classification {}",
+                cache_key.as_deref().unwrap_or("")
+            );
+            results
+        }
+        (None, Some(path)) => classify_with_checkpoint(classifier, &input_paths, path)?,
+        (None, None) => run_classification(classifier, config)?,
+    };
+    if cached_classification.is_none() {
+        if let (Some(cache), Some(key)) = (&classifier.cache, &cache_key) {
+            // A failed write only costs the next identical run its shortcut
+            if let Err(e) = cache.put(key, &classification_results) {
+                println!("Could not cache classification {}: {}", key, e);
+            }
+        }
+    }
+    job.set_reads(reads_before + classification_results.kraken_output_lines.len() as u64);
+    job.set_stage("parsing");
+    job.emit_progress(window)?;
+    job.check_cancelled()?;
+
+    let node_ids = if classifier.deterministic_ids {
+        NodeIds::Deterministic {
+            sample_id: ids.sample_id,
+            database_version: classifier.database_version(),
+        }
+    } else {
+        NodeIds::Random
+    };
+    let mut result = assemble_result(
+        classification_results,
+        &input_paths,
+        ids,
+        target,
+        &node_ids,
+        classifier.options.e_score_formula,
+        classifier.taxdb,
+        classifier.contaminants,
+        job,
+    )?;
+    result.database_id = Some(database_id);
+    result.cached_classification = cached_classification;
+    Ok(result)
+}
 
-    // 6) Parse FASTQ data for "raw_sequences"
+/// Turns classifier results into a sample's result: reads from `input_paths` become
+/// its raw sequences, report rows get IDs from `node_ids`, rows and reads get the
+/// sample's IDs, and the outputs go where `target` says. Rows are scored with
+/// `e_score_formula`. Shared by classification and by imports of results classified
+/// elsewhere.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn assemble_result(
+    classification_results: ClassificationResults,
+    input_paths: &[String],
+    ids: &SampleIds,
+    target: OutputTarget,
+    node_ids: &NodeIds,
+    e_score_formula: EScoreFormula,
+    taxdb: Option<&TaxDb>,
+    contaminants: Option<&ContaminantOptions>,
+    job: &JobHandle,
+) -> Result<KrakenUniqResult, PoleshiftError> {
+    // 1) Parse FASTQ data for "raw_sequences"
     let raw_sequences_parsed = parse_fastq_files(
-        &file_paths,
-        user_id.clone(),
-        org_id.clone(),
-        raw_data_id.clone(),
-        sample_id.clone(),
+        input_paths,
+        ids.user_id.to_string(),
+        ids.org_id.to_string(),
+        ids.raw_data_id.to_string(),
+        ids.sample_id.to_string(),
     );
-    let raw_sequence_entries = match raw_sequences_parsed {
+    let mut raw_sequence_entries = match raw_sequences_parsed {
         Ok(rows) => rows,
         Err(msg) => {
             println!("Error parsing sequence data: {}", msg);
@@ -179,14 +916,23 @@ pub async fn handle_sequence_data<R: Runtime>(
         }
     };
 
-    // 7) Replace numeric tax IDs with newly generated UUIDs
+    let processed_data_id = parse_uuid("processed_data_id", ids.processed_data_id)?.to_string();
+    let user_id = parse_uuid("user_id", ids.user_id)?.to_string();
+    let org_id = parse_uuid("org_id", ids.org_id)?.to_string();
+    let sample_id = parse_uuid("sample_id", ids.sample_id)?.to_string();
+
+    // 2) Replace numeric tax IDs with UUIDs
     let kraken_report_rows = classification_results
         .kraken_report_rows
         .unwrap_or_default();
+    let report_parents: HashMap<u32, u32> = kraken_report_rows
+        .iter()
+        .filter_map(|row| row.parent_tax_id.map(|parent| (row.tax_id, parent)))
+        .collect();
 
     let mut row_with_assigned_ids = Vec::new();
     for row in kraken_report_rows {
-        let assigned_id = Uuid::new_v4();
+        let assigned_id = node_ids.id(row.tax_id);
         row_with_assigned_ids.push((row, assigned_id));
     }
 
@@ -195,7 +941,7 @@ pub async fn handle_sequence_data<R: Runtime>(
         .map(|(row, assigned_uuid)| (row.tax_id, *assigned_uuid))
         .collect();
 
-    let processed_kraken_uniq_report: Vec<ProcessedKrakenUniqReport> = row_with_assigned_ids
+    let mut processed_kraken_uniq_report: Vec<ProcessedKrakenUniqReport> = row_with_assigned_ids
         .into_iter()
         .map(|(row, assigned_id)| {
             let parent_uuid = row
@@ -208,50 +954,48 @@ pub async fn handle_sequence_data<R: Runtime>(
                 .filter_map(|child_tax_id| tax_id_to_uuid.get(child_tax_id).cloned())
                 .collect();
 
-            // Calculate e-score
-            let tax_reads_f64 = row.tax_reads as f64;
-            let kmers_f64 = row.kmers as f64;
-            let coverage_f64 = row.cov as f64;
-
-            // Calculate double exponential of coverage
-            let double_exp_cov = coverage_f64.exp().exp();
-
-            // Calculate final e-score
-            let e_score = if kmers_f64 > 0.0 {
-                (tax_reads_f64 / kmers_f64) * double_exp_cov
-            } else {
-                0.0
-            };
+            let e_score = e_score(
+                e_score_formula,
+                row.tax_reads as f64,
+                row.kmers as f64,
+                row.cov,
+            );
 
             ProcessedKrakenUniqReport {
                 id: String::from(assigned_id),
                 percentage: row.pct,
-                reads: row.reads.to_string(),
-                tax_reads: row.tax_reads.to_string(),
-                kmers: row.kmers.to_string(),
-                duplication: row.dup.to_string(),
+                reads: row.reads,
+                tax_reads: row.tax_reads,
+                kmers: row.kmers,
+                duplication: row.dup,
                 tax_name: row.tax_name,
                 parent_id: parent_uuid,
                 children_ids: child_uuids,
-                processed_data_id: String::from(
-                    Uuid::parse_str(&processed_data_id).expect("Invalid processed_data_id UUID"),
-                ),
-                user_id: String::from(Uuid::parse_str(&user_id).expect("Invalid user_id UUID")),
-                org_id: String::from(Uuid::parse_str(&org_id).expect("Invalid org_id UUID")),
-                sample_id: String::from(
-                    Uuid::parse_str(&sample_id).expect("Invalid sample_id UUID"),
-                ),
+                processed_data_id: processed_data_id.clone(),
+                user_id: user_id.clone(),
+                org_id: org_id.clone(),
+                sample_id: sample_id.clone(),
                 tax_id: row.tax_id as u64,
                 rank: row.rank,
-                coverage: row.cov.to_string(),
+                coverage: row.cov,
                 e_score,
+                lineage: None,
+                lineage_ranks: None,
             }
         })
         .collect();
 
-    // 8) Transform classification output lines -> ProcessedKrakenUniqStdout
-    // 8) Transform classification output lines -> ProcessedKrakenUniqStdout
-    let processed_kraken_uniq_stdout = classification_results
+    if let Some(taxdb) = taxdb {
+        annotate_lineages(&mut processed_kraken_uniq_report, taxdb);
+    }
+
+    // 3) Transform classification output lines -> ProcessedKrakenUniqStdout, scoring
+    // each read against the full taxonomy where available, else the report's tree
+    let parent_of = |tax_id: u32| match taxdb.and_then(|taxdb| taxdb.get(tax_id)) {
+        Some(node) => Some(node.parent_tax_id),
+        None => report_parents.get(&tax_id).copied(),
+    };
+    let mut processed_kraken_uniq_stdout = classification_results
         .kraken_output_lines
         .iter()
         .map(|line| ProcessedKrakenUniqStdout {
@@ -260,34 +1004,140 @@ pub async fn handle_sequence_data<R: Runtime>(
             tax_id: line.tax_id as i32,
             read_length: line.length as i32,
             hit_data: line.hitlist.to_string(),
-            user_id: Uuid::parse_str(&user_id)
-                .expect("Invalid user_id UUID")
-                .to_string(),
-            org_id: Uuid::parse_str(&org_id)
-                .expect("Invalid org_id UUID")
-                .to_string(),
-            sample_id: Uuid::parse_str(&sample_id)
-                .expect("Invalid sample_id UUID")
-                .to_string(),
+            user_id: user_id.clone(),
+            org_id: org_id.clone(),
+            sample_id: sample_id.clone(),
             feature_id: line.read_id.to_string(),
-            processed_data_id: Uuid::parse_str(&processed_data_id)
-                .expect("Invalid processed_data_id UUID")
-                .to_string(),
+            processed_data_id: processed_data_id.clone(),
+            confidence: read_confidence(&line.hitlist, line.tax_id, parent_of),
         })
         .collect::<Vec<_>>();
 
-    emit_progress(&window, 50, "Processing complete...", "processing")?;
+    // 4) Drop contaminant reads before anything is returned or stored
+    let contaminants = contaminants.map(|options| {
+        remove_contaminants(
+            options,
+            &mut processed_kraken_uniq_report,
+            &mut processed_kraken_uniq_stdout,
+            &mut raw_sequence_entries,
+        )
+    });
+
+    // 5) Aggregate the run-level header fields once for the whole sample
+    let run_metadata = RunMetadata::from_sequences(&raw_sequence_entries);
 
-    // 9) Construct final result
-    let final_kraken_result = KrakenUniqResult {
-        processed_kraken_uniq_report: processed_kraken_uniq_report,
-        processed_kraken_uniq_stdout: processed_kraken_uniq_stdout,
-        raw_sequences: raw_sequence_entries,
+    // 6) Optionally move outputs out of the IPC payload into SQLite
+    let mut output_store = None;
+    let (report, stdout, mut raw_sequences, raw_sequences_summary) = match target {
+        OutputTarget::Inline => {
+            let summary = summarize_raw_sequences(&raw_sequence_entries, None);
+            (
+                processed_kraken_uniq_report,
+                processed_kraken_uniq_stdout,
+                raw_sequence_entries,
+                summary,
+            )
+        }
+        OutputTarget::RawSidecar(store_path) => {
+            raw_sequence_store::persist_raw_sequences(&store_path, &raw_sequence_entries)?;
+            let summary = summarize_raw_sequences(
+                &raw_sequence_entries,
+                Some(store_path.to_string_lossy().to_string()),
+            );
+            (
+                processed_kraken_uniq_report,
+                processed_kraken_uniq_stdout,
+                Vec::new(),
+                summary,
+            )
+        }
+        OutputTarget::Store(store_path) => {
+            job.set_stage("storing");
+            let stored = persist_outputs(
+                &store_path,
+                &processed_kraken_uniq_report,
+                &processed_kraken_uniq_stdout,
+                &raw_sequence_entries,
+            )?;
+            let summary = summarize_raw_sequences(&raw_sequence_entries, Some(stored.path.clone()));
+            output_store = Some(stored);
+            (Vec::new(), Vec::new(), Vec::new(), summary)
+        }
     };
 
-    // 10) Return in the `StandardResponseNoFiles`
-    Ok(StandardResponseNoFiles {
-        status: "Success".to_string(),
-        report: final_kraken_result,
-    })
+    run_metadata.strip_shared_fields(&mut raw_sequences);
+
+    // 7) Construct final result
+    let mut result = KrakenUniqResult::new(report, stdout, raw_sequences, raw_sequences_summary);
+    result.run_metadata = Some(run_metadata);
+    result.output_store = output_store;
+    result.contaminants = contaminants;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poleshift_common::utils::MAX_PATH_ID_LEN;
+    use proptest::prelude::*;
+
+    const ID: &str = "6f1c2a3e-8d4b-4f5a-9c7e-1b2d3e4f5a6b";
+
+    fn ids(raw_data_id: &str, processed_data_id: &str) -> Result<(), PoleshiftError> {
+        validate_ids(
+            &SampleIds {
+                processed_data_id,
+                raw_data_id,
+                user_id: ID,
+                org_id: ID,
+                sample_id: ID,
+            },
+            None,
+        )
+    }
+
+    fn rejected_field(result: Result<(), PoleshiftError>) -> Option<String> {
+        match result {
+            Err(PoleshiftError::InvalidInput { field, .. }) => Some(field),
+            _ => None,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn accepts_uuids(raw in any::<u128>(), processed in any::<u128>()) {
+            let raw = Uuid::from_u128(raw).to_string();
+            let processed = Uuid::from_u128(processed).to_string();
+            prop_assert!(ids(&raw, &processed).is_ok());
+        }
+
+        #[test]
+        fn rejects_raw_data_ids_that_leave_the_directory(
+            prefix in "[a-z0-9]{0,8}",
+            separator in prop::sample::select(vec!["..", "/", "\\", "\0", "../", "/etc/"]),
+            suffix in "[a-z0-9]{0,8}",
+        ) {
+            let raw = format!("{}{}{}", prefix, separator, suffix);
+            prop_assert_eq!(rejected_field(ids(&raw, ID)), Some("raw_data_id".to_string()));
+        }
+
+        #[test]
+        fn rejects_overlong_raw_data_ids(raw in "[a-zA-Z0-9_-]{65,200}") {
+            prop_assert!(raw.len() > MAX_PATH_ID_LEN);
+            prop_assert_eq!(rejected_field(ids(&raw, ID)), Some("raw_data_id".to_string()));
+        }
+
+        #[test]
+        fn rejects_malformed_uuids_with_their_field(processed in "[^0-9a-fA-F-]{1,40}") {
+            prop_assert_eq!(
+                rejected_field(ids(ID, &processed)),
+                Some("processed_data_id".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_empty_raw_data_id() {
+        assert_eq!(rejected_field(ids("", ID)), Some("raw_data_id".to_string()));
+    }
 }
@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+use uuid::Uuid;
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{load_report, output_store_path};
+use crate::krakenuniq::taxdb::canonical_rank;
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Largest report `benchmark_taxonomy_hierarchy` generates.
+const MAX_BENCHMARK_TAXA: usize = 5_000_000;
+
+/// A taxon of the report with the taxa below it, for the hierarchy tree.
+#[derive(Debug, Serialize)]
+pub struct TaxonomyNode {
+    pub id: String,
+    pub tax_id: u64,
+    pub name: String,
+    pub rank: String,
+    pub percentage: f32,
+    pub reads: u64,
+    pub tax_reads: u64,
+    /// In report order
+    pub children: Vec<TaxonomyNode>,
+}
+
+impl From<&ProcessedKrakenUniqReport> for TaxonomyNode {
+    fn from(row: &ProcessedKrakenUniqReport) -> Self {
+        TaxonomyNode {
+            id: row.id.clone(),
+            tax_id: row.tax_id,
+            name: row.tax_name.trim().to_string(),
+            rank: row.rank.clone(),
+            percentage: row.percentage,
+            reads: row.reads,
+            tax_reads: row.tax_reads,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Nests `nodes` under the node at their `parents` index, in linear time: each node
+/// is moved into its parent once its own children are in place, so no subtree is
+/// searched for or copied. Nodes without a parent are the roots; nodes only reachable
+/// through a cycle are left out.
+fn assemble<T>(
+    nodes: Vec<T>,
+    parents: &[Option<usize>],
+    set_children: impl Fn(&mut T, Vec<T>),
+) -> Vec<T> {
+    // 1) Children of each node, by index
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    let mut roots = Vec::new();
+    for (i, parent) in parents.iter().enumerate() {
+        match parent.filter(|&p| p != i) {
+            Some(parent) => children[parent].push(i),
+            None => roots.push(i),
+        }
+    }
+
+    // 2) Nodes bottom-up, with a post-order walk from each root
+    let mut nodes: Vec<Option<T>> = nodes.into_iter().map(Some).collect();
+    let mut tree = Vec::with_capacity(roots.len());
+    for root in roots {
+        let mut stack = vec![(root, false)];
+        while let Some((i, children_done)) = stack.pop() {
+            if children_done {
+                let below: Vec<T> = children[i]
+                    .iter()
+                    .filter_map(|&child| nodes[child].take())
+                    .collect();
+                if let Some(node) = nodes[i].as_mut() {
+                    set_children(node, below);
+                }
+            } else {
+                stack.push((i, true));
+                stack.extend(children[i].iter().map(|&child| (child, false)));
+            }
+        }
+        tree.extend(nodes[root].take());
+    }
+
+    let unlinked = nodes.iter().filter(|node| node.is_some()).count();
+    if unlinked > 0 {
+        println!(
+            "Left {} taxa linked only through a cycle out of the hierarchy",
+            unlinked
+        );
+    }
+    tree
+}
+
+/// Builds the report's tree from each row's `parent_id` (see `assemble`). Rows whose
+/// parent is not in the report are roots.
+pub(crate) fn build_hierarchy(rows: &[ProcessedKrakenUniqReport]) -> Vec<TaxonomyNode> {
+    let by_id: HashMap<&str, usize> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row.id.as_str(), i))
+        .collect();
+    let parents: Vec<Option<usize>> = rows
+        .iter()
+        .map(|row| {
+            row.parent_id
+                .and_then(|p| by_id.get(p.to_string().as_str()).copied())
+        })
+        .collect();
+    let nodes = rows.iter().map(TaxonomyNode::from).collect();
+    assemble(nodes, &parents, |node, children| node.children = children)
+}
+
+/// Returns the stored classification report as a tree of taxa, top-level taxa first
+/// (see `build_hierarchy`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn build_taxonomy_hierarchy<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+) -> Result<StandardResponseNoFiles<Vec<TaxonomyNode>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let report = load_report(&output_store_path(&app_handle, &processed_data_id)?)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: build_hierarchy(&report),
+        })
+    })
+    .await
+}
+
+/// One sample's counts of a taxon in a merged tree; zero where the sample lacks it.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SampleCounts {
+    pub reads: u64,
+    pub tax_reads: u64,
+    pub percentage: f32,
+}
+
+/// A taxon of several samples' trees, matched by tax ID.
+#[derive(Debug, Serialize)]
+pub struct MergedTaxonomyNode {
+    pub tax_id: u64,
+    pub name: String,
+    pub rank: String,
+    /// In the order of the merged samples
+    pub samples: Vec<SampleCounts>,
+    pub children: Vec<MergedTaxonomyNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergedHierarchy {
+    /// The merged samples, in the order of each node's `samples`
+    pub processed_data_ids: Vec<String>,
+    pub tree: Vec<MergedTaxonomyNode>,
+    /// Taxa the samples place under different parents, e.g. after a taxonomy update;
+    /// the first sample's placement is kept
+    pub parent_conflicts: usize,
+}
+
+/// Merges the reports of several samples into one tree whose nodes carry each
+/// sample's counts, matching taxa by tax ID. Parents are taken from the first sample
+/// holding a taxon.
+pub(crate) fn merge_hierarchies(
+    reports: &[Vec<ProcessedKrakenUniqReport>],
+) -> (Vec<MergedTaxonomyNode>, usize) {
+    // 1) A node per tax ID, with each sample's counts and the parent's tax ID
+    let mut index: HashMap<u64, usize> = HashMap::new();
+    let mut nodes: Vec<MergedTaxonomyNode> = Vec::new();
+    let mut parent_tax_ids: Vec<Option<u64>> = Vec::new();
+    let mut parent_conflicts = 0;
+    for (sample, rows) in reports.iter().enumerate() {
+        let tax_ids: HashMap<&str, u64> = rows
+            .iter()
+            .map(|row| (row.id.as_str(), row.tax_id))
+            .collect();
+        for row in rows {
+            let parent = row
+                .parent_id
+                .and_then(|p| tax_ids.get(p.to_string().as_str()).copied());
+            let i = match index.get(&row.tax_id) {
+                Some(&i) => {
+                    if parent_tax_ids[i] != parent {
+                        parent_conflicts += 1;
+                    }
+                    i
+                }
+                None => {
+                    index.insert(row.tax_id, nodes.len());
+                    nodes.push(MergedTaxonomyNode {
+                        tax_id: row.tax_id,
+                        name: row.tax_name.trim().to_string(),
+                        rank: row.rank.clone(),
+                        samples: vec![SampleCounts::default(); reports.len()],
+                        children: Vec::new(),
+                    });
+                    parent_tax_ids.push(parent);
+                    nodes.len() - 1
+                }
+            };
+            nodes[i].samples[sample] = SampleCounts {
+                reads: row.reads,
+                tax_reads: row.tax_reads,
+                percentage: row.percentage,
+            };
+        }
+    }
+
+    // 2) The tree
+    let parents: Vec<Option<usize>> = parent_tax_ids
+        .iter()
+        .map(|parent| parent.and_then(|tax_id| index.get(&tax_id).copied()))
+        .collect();
+    let tree = assemble(nodes, &parents, |node, children| node.children = children);
+    (tree, parent_conflicts)
+}
+
+/// Merges the stored classifications of several samples into one tree whose nodes
+/// carry every sample's reads and percentages, for side-by-side comparison (see
+/// `merge_hierarchies`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn merge_taxonomy_hierarchies<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_ids: Vec<String>,
+) -> Result<StandardResponseNoFiles<MergedHierarchy>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if processed_data_ids.is_empty() {
+            return Err(PoleshiftError::InvalidInput {
+                field: "processed_data_ids".to_string(),
+                reason: "must name at least one classification".to_string(),
+            });
+        }
+        let reports = processed_data_ids
+            .iter()
+            .map(|id| load_report(&output_store_path(&app_handle, id)?))
+            .collect::<Result<Vec<_>, PoleshiftError>>()?;
+        let (tree, parent_conflicts) = merge_hierarchies(&reports);
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: MergedHierarchy {
+                processed_data_ids,
+                tree,
+                parent_conflicts,
+            },
+        })
+    })
+    .await
+}
+
+/// Which subtrees `prune_taxonomy_hierarchy` removes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PruneOptions {
+    /// Taxa with fewer clade reads are removed with everything below them
+    pub min_reads: u64,
+    /// Taxa below this share of the reads are removed likewise, in percent
+    pub min_percentage: f32,
+}
+
+/// How `collapse_taxonomy_hierarchy` reduces the ranks shown.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CollapseOptions {
+    /// Taxa of these ranks are hidden: their children move up to their parent, which
+    /// takes their direct reads
+    pub hide_ranks: Vec<String>,
+    /// Taxa of these ranks become leaves holding the reads of everything below them,
+    /// e.g. `species` to fold strains, assemblies and sequences into their species
+    pub fold_ranks: Vec<String>,
+}
+
+/// A reduced hierarchy, and how many taxa the reduction took out.
+#[derive(Debug, Serialize)]
+pub struct ReducedHierarchy {
+    pub tree: Vec<TaxonomyNode>,
+    pub removed: usize,
+}
+
+fn subtree_size(node: &TaxonomyNode) -> usize {
+    1 + node.children.iter().map(subtree_size).sum::<usize>()
+}
+
+/// Drops the nodes below either threshold of `options`, with their subtrees. Clade
+/// reads never grow downwards, so nothing below a dropped node would have been kept.
+pub(crate) fn prune(
+    nodes: Vec<TaxonomyNode>,
+    options: &PruneOptions,
+    removed: &mut usize,
+) -> Vec<TaxonomyNode> {
+    nodes
+        .into_iter()
+        .filter_map(|mut node| {
+            if node.reads < options.min_reads || node.percentage < options.min_percentage {
+                *removed += subtree_size(&node);
+                return None;
+            }
+            node.children = prune(std::mem::take(&mut node.children), options, removed);
+            Some(node)
+        })
+        .collect()
+}
+
+/// Hides the ranks in `hide` and folds those in `fold` among `nodes`; returns the
+/// nodes to show in their place and the direct reads of hidden nodes, which go to
+/// their parent.
+fn collapse_level(
+    nodes: Vec<TaxonomyNode>,
+    hide: &[String],
+    fold: &[String],
+    removed: &mut usize,
+) -> (Vec<TaxonomyNode>, u64) {
+    let mut shown = Vec::with_capacity(nodes.len());
+    let mut lifted_reads = 0;
+    for mut node in nodes {
+        let rank = canonical_rank(&node.rank);
+        if fold.contains(&rank) {
+            *removed += subtree_size(&node) - 1;
+            node.tax_reads = node.reads;
+            node.children.clear();
+        } else {
+            let (children, reads) =
+                collapse_level(std::mem::take(&mut node.children), hide, fold, removed);
+            node.children = children;
+            node.tax_reads += reads;
+        }
+        if hide.contains(&rank) {
+            *removed += 1;
+            lifted_reads += node.tax_reads;
+            shown.append(&mut node.children);
+        } else {
+            shown.push(node);
+        }
+    }
+    (shown, lifted_reads)
+}
+
+/// Hides and folds the ranks of `options`. A taxon of a rank both hidden and folded
+/// goes with its subtree, its clade reads going to its parent.
+pub(crate) fn collapse(
+    nodes: Vec<TaxonomyNode>,
+    options: &CollapseOptions,
+    removed: &mut usize,
+) -> Vec<TaxonomyNode> {
+    let ranks = |ranks: &[String]| -> Vec<String> {
+        ranks.iter().map(|rank| canonical_rank(rank)).collect()
+    };
+    collapse_level(
+        nodes,
+        &ranks(&options.hide_ranks),
+        &ranks(&options.fold_ranks),
+        removed,
+    )
+    .0
+}
+
+/// Returns the stored classification's tree without the taxa below `options`'
+/// minimum reads or percentage, so huge reports stay quick to draw.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn prune_taxonomy_hierarchy<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    options: PruneOptions,
+) -> Result<StandardResponseNoFiles<ReducedHierarchy>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if !(options.min_percentage.is_finite() && (0.0..=100.0).contains(&options.min_percentage))
+        {
+            return Err(PoleshiftError::InvalidInput {
+                field: "min_percentage".to_string(),
+                reason: "must be between 0 and 100".to_string(),
+            });
+        }
+        let report = load_report(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let mut removed = 0;
+        let tree = prune(build_hierarchy(&report), &options, &mut removed);
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: ReducedHierarchy { tree, removed },
+        })
+    })
+    .await
+}
+
+/// Returns the stored classification's tree with the ranks of `options` hidden or
+/// folded into their parents (see `collapse`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn collapse_taxonomy_hierarchy<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    options: CollapseOptions,
+) -> Result<StandardResponseNoFiles<ReducedHierarchy>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let report = load_report(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let mut removed = 0;
+        let tree = collapse(build_hierarchy(&report), &options, &mut removed);
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: ReducedHierarchy { tree, removed },
+        })
+    })
+    .await
+}
+
+#[derive(Debug, Serialize)]
+pub struct HierarchyBenchmark {
+    pub taxa: usize,
+    pub roots: usize,
+    pub elapsed_ms: f64,
+    pub nanos_per_taxon: f64,
+    /// Every taxon ended up in the tree exactly once
+    pub complete: bool,
+}
+
+/// Times `build_hierarchy` on a generated report of `taxa` taxa (100,000 by default),
+/// each under a random earlier taxon and named from a small pool so that siblings
+/// share names, and checks that the tree holds every taxon.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn benchmark_taxonomy_hierarchy(
+    invocation: Invocation,
+    taxa: Option<usize>,
+) -> Result<StandardResponseNoFiles<HierarchyBenchmark>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let taxa = taxa.unwrap_or(100_000);
+        if taxa == 0 || taxa > MAX_BENCHMARK_TAXA {
+            return Err(PoleshiftError::InvalidInput {
+                field: "taxa".to_string(),
+                reason: format!("must be between 1 and {}", MAX_BENCHMARK_TAXA),
+            });
+        }
+
+        let report = tauri::async_runtime::spawn_blocking(move || {
+            // 1) A random tree under a single root
+            let mut rng = rand::thread_rng();
+            let ids: Vec<Uuid> = (0..taxa).map(|_| Uuid::new_v4()).collect();
+            let rows: Vec<ProcessedKrakenUniqReport> = (0..taxa)
+                .map(|i| ProcessedKrakenUniqReport {
+                    id: ids[i].to_string(),
+                    percentage: 0.0,
+                    reads: 1,
+                    tax_reads: 1,
+                    kmers: 0,
+                    duplication: 0.0,
+                    tax_name: format!("taxon {}", i % 64),
+                    parent_id: (i > 0).then(|| ids[rng.gen_range(0..i)]),
+                    children_ids: Vec::new(),
+                    processed_data_id: String::new(),
+                    user_id: String::new(),
+                    org_id: String::new(),
+                    sample_id: String::new(),
+                    tax_id: i as u64 + 1,
+                    rank: "no rank".to_string(),
+                    coverage: 0.0,
+                    e_score: 0.0,
+                    lineage: None,
+                    lineage_ranks: None,
+                })
+                .collect();
+
+            // 2) Time the build, then count what it holds
+            let started = Instant::now();
+            let tree = build_hierarchy(&rows);
+            let elapsed = started.elapsed();
+            let mut nodes = 0;
+            let mut stack: Vec<&TaxonomyNode> = tree.iter().collect();
+            while let Some(node) = stack.pop() {
+                nodes += 1;
+                stack.extend(&node.children);
+            }
+            HierarchyBenchmark {
+                taxa,
+                roots: tree.len(),
+                elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+                nanos_per_taxon: elapsed.as_nanos() as f64 / taxa as f64,
+                complete: nodes == taxa,
+            }
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Benchmark task failed: {}", e)))?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
@@ -0,0 +1,161 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use krakenuniq_rs::ClassificationResults;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::contaminants::ContaminantOptions;
+use crate::krakenuniq::handle_sequence_data::{
+    assemble_result, validate_ids, OutputTarget, SampleIds,
+};
+use crate::krakenuniq::output_store::output_store_path;
+use crate::krakenuniq::report_parser::{parse_kraken_uniq_output, parse_report, ReportFormat};
+use crate::krakenuniq::taxonomy_service::{TaxonomyCache, TaxonomyIndex};
+use crate::krakenuniq::{EScoreFormula, KrakenUniqResult, NodeIds};
+use crate::poleshift_common::jobs::JobManager;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// An imported classification, with what was read from the files.
+#[derive(Debug, Serialize)]
+pub struct ImportedClassification {
+    #[serde(flatten)]
+    pub result: KrakenUniqResult,
+    pub report_format: ReportFormat,
+    /// Report lines that could not be read as rows
+    pub skipped_report_lines: usize,
+}
+
+/// The taxDB of `database_id` for lineages, if it is installed and readable.
+fn load_taxdb<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    database_id: &str,
+) -> Option<Arc<TaxonomyIndex>> {
+    app_handle
+        .state::<TaxonomyCache>()
+        .for_database(app_handle, database_id)
+        .map_err(|e| println!("Could not load taxDB for lineages: {}", e))
+        .ok()
+}
+
+/// Imports a classification run outside poleshift (e.g. on a cluster) as if it had
+/// been classified here. `report_path` may be a KrakenUniq, Kraken/Kraken2 or Bracken
+/// report (see `inspect_report_file`); `output_path` is the matching per-read output,
+/// if there is one. Reads in `file_paths` become the sample's raw sequences.
+///
+/// The rows and reads are stamped with the given IDs exactly as a native run, so the
+/// result is uploaded the same way. `database_id` names the database the run used,
+/// and adds lineages when that database is installed. With `persist_outputs` the
+/// outputs go to the output store instead of being returned, and `contaminants`
+/// filters them as in `handle_sequence_data`. `deterministic_ids` derives the report
+/// row IDs from the sample, tax ID and `database_id`, which it then requires, so
+/// importing the same run twice gives the same IDs.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command(rename_all = "snake_case")]
+pub async fn import_external_classification<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    report_path: String,
+    output_path: Option<String>,
+    file_paths: Option<Vec<String>>,
+    processed_data_id: String,
+    raw_data_id: String,
+    user_id: String,
+    org_id: String,
+    sample_id: String,
+    database_id: Option<String>,
+    persist_outputs: Option<bool>,
+    contaminants: Option<ContaminantOptions>,
+    deterministic_ids: Option<bool>,
+) -> Result<StandardResponseNoFiles<ImportedClassification>, PoleshiftError> {
+    traced(invocation, None, async move {
+        // 1) Reject malformed IDs and options before reading anything
+        let ids = SampleIds {
+            processed_data_id: &processed_data_id,
+            raw_data_id: &raw_data_id,
+            user_id: &user_id,
+            org_id: &org_id,
+            sample_id: &sample_id,
+        };
+        validate_ids(&ids, None)?;
+        if let Some(contaminants) = &contaminants {
+            contaminants.validate()?;
+        }
+        let deterministic_ids = deterministic_ids.unwrap_or(false);
+        if deterministic_ids && database_id.is_none() {
+            return Err(PoleshiftError::InvalidInput {
+                field: "database_id".to_string(),
+                reason: "is required with deterministic_ids".to_string(),
+            });
+        }
+        let target = if persist_outputs.unwrap_or(false) {
+            OutputTarget::Store(output_store_path(&app_handle, &processed_data_id)?)
+        } else {
+            OutputTarget::Inline
+        };
+
+        let jobs = app_handle.state::<JobManager>();
+        let job = jobs.start("import", None)?;
+        let worker_job = job.clone();
+        let task_handle = app_handle.clone();
+        let report = tauri::async_runtime::spawn_blocking(move || {
+            // 2) Read the classifier's files
+            worker_job.set_stage("parsing");
+            let report = parse_report(Path::new(&report_path))?;
+            let output_lines = match &output_path {
+                Some(path) => parse_kraken_uniq_output(Path::new(path))?,
+                None => Vec::new(),
+            };
+            let taxdb = database_id
+                .as_deref()
+                .and_then(|id| load_taxdb(&task_handle, id));
+
+            // 3) Assemble the result as for a sample classified here
+            let ids = SampleIds {
+                processed_data_id: &processed_data_id,
+                raw_data_id: &raw_data_id,
+                user_id: &user_id,
+                org_id: &org_id,
+                sample_id: &sample_id,
+            };
+            let node_ids = match database_id.as_deref() {
+                Some(database_version) if deterministic_ids => NodeIds::Deterministic {
+                    sample_id: &sample_id,
+                    database_version,
+                },
+                _ => NodeIds::Random,
+            };
+            let mut result = assemble_result(
+                ClassificationResults {
+                    kraken_output_lines: output_lines,
+                    kraken_report_rows: Some(report.rows),
+                },
+                &file_paths.unwrap_or_default(),
+                &ids,
+                target,
+                &node_ids,
+                EScoreFormula::default(),
+                taxdb.as_deref().map(|index| &index.taxdb),
+                contaminants.as_ref(),
+                &worker_job,
+            )?;
+            result.database_id = database_id;
+            Ok::<_, PoleshiftError>(ImportedClassification {
+                result,
+                report_format: report.format,
+                skipped_report_lines: report.skipped_lines,
+            })
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Import task failed: {}", e)))
+        .and_then(|report| report);
+        jobs.finish(job.id(), report.as_ref().err());
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: report?,
+        })
+    })
+    .await
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Longest k-mer that fits the 2-bit encoding in a `u64`.
+pub(crate) const MAX_K: usize = 31;
+/// Default k-mer size of `benchmark_kmer_hashing`, as in the bundled sketches.
+const BENCHMARK_K: usize = 21;
+/// Largest sequence `benchmark_kmer_hashing` generates.
+const MAX_BENCHMARK_BASES: usize = 500_000_000;
+
+/// Thomas Wang's invertible 64-bit integer hash, applied to 2-bit encoded canonical
+/// k-mers (A=0, C=1, G=2, T=3). Sketch files must be built with the same function.
+pub(crate) fn kmer_hash(mut key: u64) -> u64 {
+    key = (!key).wrapping_add(key << 21);
+    key ^= key >> 24;
+    key = key.wrapping_add(key << 3).wrapping_add(key << 8);
+    key ^= key >> 14;
+    key = key.wrapping_add(key << 2).wrapping_add(key << 4);
+    key ^= key >> 28;
+    key.wrapping_add(key << 31)
+}
+
+/// `kmer_hash` four lanes at a time with AVX2. Every step of the hash is a 64-bit
+/// shift, add or xor, so the lanes give exactly the scalar results.
+#[cfg(all(feature = "simd-hashing", target_arch = "x86_64"))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    /// # Safety
+    /// The CPU must support AVX2, and `out` must be at least as long as `keys`.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn hash_batch(keys: &[u64], out: &mut [u64]) {
+        let chunks = keys.len() / 4;
+        let ones = _mm256_set1_epi64x(-1);
+        for chunk in 0..chunks {
+            let offset = chunk * 4;
+            let mut key = _mm256_loadu_si256(keys.as_ptr().add(offset) as *const __m256i);
+            key = _mm256_add_epi64(_mm256_xor_si256(key, ones), _mm256_slli_epi64(key, 21));
+            key = _mm256_xor_si256(key, _mm256_srli_epi64(key, 24));
+            key = _mm256_add_epi64(
+                _mm256_add_epi64(key, _mm256_slli_epi64(key, 3)),
+                _mm256_slli_epi64(key, 8),
+            );
+            key = _mm256_xor_si256(key, _mm256_srli_epi64(key, 14));
+            key = _mm256_add_epi64(
+                _mm256_add_epi64(key, _mm256_slli_epi64(key, 2)),
+                _mm256_slli_epi64(key, 4),
+            );
+            key = _mm256_xor_si256(key, _mm256_srli_epi64(key, 28));
+            key = _mm256_add_epi64(key, _mm256_slli_epi64(key, 31));
+            _mm256_storeu_si256(out.as_mut_ptr().add(offset) as *mut __m256i, key);
+        }
+        for i in chunks * 4..keys.len() {
+            out[i] = super::kmer_hash(keys[i]);
+        }
+    }
+}
+
+/// How k-mers are hashed. The SIMD path is compiled in with the `simd-hashing`
+/// feature and picked at runtime only on CPUs that support it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashPath {
+    Scalar,
+    #[cfg_attr(
+        not(all(feature = "simd-hashing", target_arch = "x86_64")),
+        allow(dead_code)
+    )]
+    Avx2,
+}
+
+impl HashPath {
+    /// The fastest path this build and CPU support.
+    pub fn detect() -> Self {
+        #[cfg(all(feature = "simd-hashing", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return HashPath::Avx2;
+            }
+        }
+        HashPath::Scalar
+    }
+
+    /// Hashes `keys` into `out`, which must be at least as long.
+    fn hash_batch(self, keys: &[u64], out: &mut [u64]) {
+        match self {
+            #[cfg(all(feature = "simd-hashing", target_arch = "x86_64"))]
+            // SAFETY: `detect` only picks AVX2 when the CPU has it
+            HashPath::Avx2 => unsafe { avx2::hash_batch(keys, out) },
+            // Without the feature `detect` never picks AVX2
+            _ => {
+                for (hash, &key) in out.iter_mut().zip(keys) {
+                    *hash = kmer_hash(key);
+                }
+            }
+        }
+    }
+}
+
+/// Hashes the canonical k-mers of sequences, reusing its buffers between calls.
+///
+/// The 2-bit encoding rolls along the sequence one base at a time and cannot be
+/// vectorized, so the k-mers of a sequence are encoded first and then hashed as one
+/// batch on the chosen `HashPath`.
+pub(crate) struct KmerHasher {
+    path: HashPath,
+    kmers: Vec<u64>,
+    hashes: Vec<u64>,
+}
+
+impl KmerHasher {
+    pub fn new(path: HashPath) -> Self {
+        KmerHasher {
+            path,
+            kmers: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> HashPath {
+        self.path
+    }
+
+    /// The hashes of the canonical k-mers of `sequence`, in order. K-mers with
+    /// anything other than ACGT are skipped.
+    pub fn hash_sequence(&mut self, sequence: &[u8], k: usize) -> &[u64] {
+        let mask = (1u64 << (2 * k)) - 1;
+        let shift = 2 * (k as u64 - 1);
+        let (mut forward, mut reverse, mut valid) = (0u64, 0u64, 0usize);
+        self.kmers.clear();
+        for &base in sequence {
+            let code = match base {
+                b'A' | b'a' => 0,
+                b'C' | b'c' => 1,
+                b'G' | b'g' => 2,
+                b'T' | b't' => 3,
+                _ => {
+                    valid = 0;
+                    continue;
+                }
+            };
+            forward = ((forward << 2) | code) & mask;
+            reverse = (reverse >> 2) | ((3 - code) << shift);
+            valid += 1;
+            if valid >= k {
+                self.kmers.push(forward.min(reverse));
+            }
+        }
+        self.hashes.resize(self.kmers.len(), 0);
+        self.path.hash_batch(&self.kmers, &mut self.hashes);
+        &self.hashes
+    }
+
+    /// Adds the hashes of `sequence` below `max_hash` to `counts`.
+    pub fn sketch_sequence(
+        &mut self,
+        sequence: &[u8],
+        k: usize,
+        max_hash: u64,
+        counts: &mut HashMap<u64, u64>,
+    ) {
+        for &hash in self.hash_sequence(sequence, k) {
+            if hash < max_hash {
+                *counts.entry(hash).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Timing of one hashing path in `benchmark_kmer_hashing`.
+#[derive(Debug, Serialize)]
+pub struct HashPathTiming {
+    pub path: HashPath,
+    pub elapsed_ms: f64,
+    pub nanos_per_kmer: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KmerHashBenchmark {
+    pub k: usize,
+    pub bases: usize,
+    pub kmers: usize,
+    /// The path sketching uses on this machine
+    pub detected_path: HashPath,
+    /// Scalar first; the SIMD path follows when available
+    pub timings: Vec<HashPathTiming>,
+    /// How many times faster the detected path is than the scalar one
+    pub speedup: f64,
+    /// Every path produced the same hashes
+    pub identical: bool,
+}
+
+fn time_path(path: HashPath, sequence: &[u8], k: usize) -> (HashPathTiming, Vec<u64>) {
+    let mut hasher = KmerHasher::new(path);
+    let started = Instant::now();
+    let hashes = hasher.hash_sequence(sequence, k);
+    let elapsed = started.elapsed();
+    let hashes = hashes.to_vec();
+    let timing = HashPathTiming {
+        path,
+        elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+        nanos_per_kmer: if hashes.is_empty() {
+            0.0
+        } else {
+            elapsed.as_nanos() as f64 / hashes.len() as f64
+        },
+    };
+    (timing, hashes)
+}
+
+/// Compares the scalar k-mer hashing path with the SIMD one on `bases` random bases
+/// (10 million by default), so the gain can be checked on the machine at hand.
+/// Timings include encoding the k-mers, which both paths share.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn benchmark_kmer_hashing(
+    invocation: Invocation,
+    k: Option<usize>,
+    bases: Option<usize>,
+) -> Result<StandardResponseNoFiles<KmerHashBenchmark>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let k = k.unwrap_or(BENCHMARK_K);
+        if k == 0 || k > MAX_K {
+            return Err(PoleshiftError::InvalidInput {
+                field: "k".to_string(),
+                reason: format!("must be between 1 and {}", MAX_K),
+            });
+        }
+        let bases = bases.unwrap_or(10_000_000);
+        if bases > MAX_BENCHMARK_BASES {
+            return Err(PoleshiftError::InvalidInput {
+                field: "bases".to_string(),
+                reason: format!("must be at most {}", MAX_BENCHMARK_BASES),
+            });
+        }
+
+        let report = tauri::async_runtime::spawn_blocking(move || {
+            // 1) A random sequence, so no path benefits from repetition
+            let mut rng = rand::thread_rng();
+            let sequence: Vec<u8> = (0..bases).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect();
+
+            // 2) Time every available path on it
+            let detected_path = HashPath::detect();
+            let (scalar, expected) = time_path(HashPath::Scalar, &sequence, k);
+            let mut timings = vec![scalar];
+            let mut identical = true;
+            if detected_path != HashPath::Scalar {
+                let (timing, hashes) = time_path(detected_path, &sequence, k);
+                identical = hashes == expected;
+                timings.push(timing);
+            }
+            let fastest = timings.last().map(|t| t.elapsed_ms).unwrap_or_default();
+            KmerHashBenchmark {
+                k,
+                bases,
+                kmers: expected.len(),
+                detected_path,
+                speedup: if fastest > 0.0 {
+                    timings[0].elapsed_ms / fastest
+                } else {
+                    1.0
+                },
+                timings,
+                identical,
+            }
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Benchmark task failed: {}", e)))?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
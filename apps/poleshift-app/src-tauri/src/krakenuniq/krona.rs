@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{load_report, output_store_path};
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Hosted Krona script, used when no copy is bundled with the app.
+const KRONA_SCRIPT_URL: &str = "http://krona.sourceforge.net/src/krona-2.0.js";
+/// Bundled Krona script, relative to the resources directory.
+const KRONA_SCRIPT_RESOURCE: &str = "krona/krona-2.0.js";
+/// NCBI tax ID of "root"; left out of lineages as every taxon shares it.
+const ROOT_TAX_ID: u64 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct KronaExport {
+    pub text_path: String,
+    pub html_path: Option<String>,
+    /// True when the Krona script was inlined, so the HTML works offline
+    pub html_self_contained: bool,
+    pub taxa: usize,
+}
+
+/// Indexes the report as a tree: row index by row id, and child row indices per row.
+struct ReportTree<'a> {
+    rows: &'a [ProcessedKrakenUniqReport],
+    children: HashMap<usize, Vec<usize>>,
+    roots: Vec<usize>,
+    by_id: HashMap<&'a str, usize>,
+}
+
+impl<'a> ReportTree<'a> {
+    fn new(rows: &'a [ProcessedKrakenUniqReport]) -> Self {
+        let by_id: HashMap<&str, usize> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| (row.id.as_str(), i))
+            .collect();
+        let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut roots = Vec::new();
+        for (i, row) in rows.iter().enumerate() {
+            let parent = row
+                .parent_id
+                .and_then(|p| by_id.get(p.to_string().as_str()).copied());
+            match parent {
+                Some(parent) => children.entry(parent).or_default().push(i),
+                None => roots.push(i),
+            }
+        }
+        ReportTree {
+            rows,
+            children,
+            roots,
+            by_id,
+        }
+    }
+
+    /// Names from the top of the tree down to `index`, without "root".
+    fn lineage(&self, index: usize) -> Vec<&'a str> {
+        let mut names = Vec::new();
+        let mut current = Some(index);
+        while let Some(i) = current {
+            let row = &self.rows[i];
+            if row.tax_id != ROOT_TAX_ID {
+                names.push(row.tax_name.trim());
+            }
+            current = row
+                .parent_id
+                .and_then(|p| self.by_id.get(p.to_string().as_str()).copied())
+                // Guard against cycles in malformed reports
+                .filter(|_| names.len() <= self.rows.len());
+        }
+        names.reverse();
+        names
+    }
+}
+
+/// Krona text format: one line per taxon, its direct read count followed by its lineage.
+pub(crate) fn krona_text(rows: &[ProcessedKrakenUniqReport]) -> String {
+    let tree = ReportTree::new(rows);
+    let mut out = String::new();
+    for (i, row) in rows.iter().enumerate() {
+        let reads = row.tax_reads as f64;
+        if reads <= 0.0 {
+            continue;
+        }
+        let lineage = if row.tax_id == 0 {
+            vec!["Unclassified"]
+        } else {
+            tree.lineage(i)
+        };
+        let _ = writeln!(out, "{}\t{}", reads, lineage.join("\t"));
+    }
+    out
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_html_node(out: &mut String, tree: &ReportTree, index: usize) {
+    let row = &tree.rows[index];
+    let name = if row.tax_id == 0 {
+        "Unclassified"
+    } else {
+        row.tax_name.trim()
+    };
+    let _ = write!(
+        out,
+        "<node name=\"{}\"><magnitude><val>{}</val></magnitude>",
+        escape_xml(name),
+        row.reads as f64
+    );
+    if let Some(children) = tree.children.get(&index) {
+        for &child in children {
+            write_html_node(out, tree, child);
+        }
+    }
+    out.push_str("</node>\n");
+}
+
+/// Krona HTML chart. `script` is either the Krona JavaScript to inline or `None` to
+/// reference the hosted copy.
+pub(crate) fn krona_html(
+    rows: &[ProcessedKrakenUniqReport],
+    title: &str,
+    script: Option<&str>,
+) -> String {
+    let tree = ReportTree::new(rows);
+
+    // Krona wants a single root; "root" itself takes that place when present
+    let mut body = String::new();
+    let root_row = tree
+        .roots
+        .iter()
+        .copied()
+        .find(|&i| rows[i].tax_id == ROOT_TAX_ID);
+    let total: f64 = tree.roots.iter().map(|&i| rows[i].reads as f64).sum();
+    let _ = writeln!(
+        body,
+        "<node name=\"{}\"><magnitude><val>{}</val></magnitude>",
+        escape_xml(title),
+        total
+    );
+    for &i in &tree.roots {
+        if Some(i) == root_row {
+            for &child in tree.children.get(&i).map(Vec::as_slice).unwrap_or(&[]) {
+                write_html_node(&mut body, &tree, child);
+            }
+        } else {
+            write_html_node(&mut body, &tree, i);
+        }
+    }
+    body.push_str("</node>\n");
+
+    let script_tag = match script {
+        Some(js) => format!("<script>{}</script>", js),
+        None => format!("<script src=\"{}\"></script>", KRONA_SCRIPT_URL),
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en" lang="en">
+<head>
+<meta charset="utf-8"/>
+<title>{title}</title>
+{script_tag}
+</head>
+<body>
+<noscript>Javascript must be enabled to view this page.</noscript>
+<div style="display:none">
+<krona collapse="true" key="true">
+<attributes magnitude="magnitude">
+<attribute display="Reads">magnitude</attribute>
+</attributes>
+<datasets><dataset>{title}</dataset></datasets>
+{body}</krona>
+</div>
+</body>
+</html>
+"#,
+        title = escape_xml(title),
+        script_tag = script_tag,
+        body = body,
+    )
+}
+
+/// Writes the stored report of `processed_data_id` as Krona text to `output_path`,
+/// and with `html` also as a Krona chart next to it (`.html`). The chart inlines the
+/// Krona script when it is bundled with the app, so it opens without poleshift or a
+/// network connection; otherwise it loads the script from the Krona site.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn export_krona<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    output_path: String,
+    html: Option<bool>,
+    title: Option<String>,
+) -> Result<StandardResponseNoFiles<KronaExport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let rows = load_report(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let title = title.unwrap_or_else(|| processed_data_id.clone());
+
+        // 1) Krona text
+        let text_path = PathBuf::from(&output_path);
+        std::fs::write(&text_path, krona_text(&rows))?;
+
+        // 2) Optional HTML chart
+        let mut html_path = None;
+        let mut html_self_contained = false;
+        if html.unwrap_or(false) {
+            let script_path = app_handle
+                .path()
+                .resource_dir()
+                .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+                .join("resources")
+                .join(KRONA_SCRIPT_RESOURCE);
+            let script = std::fs::read_to_string(&script_path).ok();
+            html_self_contained = script.is_some();
+
+            let path = text_path.with_extension("html");
+            std::fs::write(&path, krona_html(&rows, &title, script.as_deref()))?;
+            html_path = Some(path.to_string_lossy().to_string());
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: KronaExport {
+                text_path: text_path.to_string_lossy().to_string(),
+                html_path,
+                html_self_contained,
+                taxa: rows.len(),
+            },
+        })
+    })
+    .await
+}
@@ -1,27 +1,183 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::io::packed::PackedSequence;
+use contaminants::ContaminantSummary;
+use output_store::OutputStoreSummary;
+use preload::DatabaseLoadPlan;
+use preprocess::PreprocessingSummary;
+use run_metadata::RunMetadata;
+
+pub mod backend;
+mod checkpoint;
+mod confidence;
+pub mod contaminants;
+pub(crate) mod demo;
+mod demultiplex;
+pub mod extract_reads;
 pub mod handle_sequence_data;
+pub mod hierarchy;
+pub mod import;
+pub mod kmer_hash;
+pub mod krona;
+pub mod negative_control;
+pub mod options;
+pub mod output_store;
 mod parse_fastq_files;
+pub mod preload;
+mod preprocess;
+pub mod queue;
+pub(crate) mod raw_sequence_store;
+pub mod refilter;
+pub mod report_parser;
+pub mod result_cache;
+pub mod run_metadata;
+pub mod screen;
+pub(crate) mod taxdb;
+pub mod taxonomy_search;
+pub mod taxonomy_service;
+pub mod tree_export;
+pub mod watch;
 
 #[derive(Debug, Serialize)]
 pub struct KrakenUniqResult {
     processed_kraken_uniq_report: Vec<ProcessedKrakenUniqReport>,
     processed_kraken_uniq_stdout: Vec<ProcessedKrakenUniqStdout>,
     raw_sequences: Vec<RawSequence>,
+    raw_sequences_summary: RawSequenceSummary,
+    /// Run-level header fields; values shared by every read are blanked on `raw_sequences`
+    run_metadata: Option<RunMetadata>,
+    /// Reference database set the reads were classified against
+    database_id: Option<String>,
+    /// Location and row counts of the output store, when outputs were persisted
+    output_store: Option<OutputStoreSummary>,
+    /// Counts from the pre-classification QC stage, when it ran
+    preprocessing: Option<PreprocessingSummary>,
+    /// Reads and taxa dropped by the contaminant filter, when it ran
+    contaminants: Option<ContaminantSummary>,
+    /// How the database was loaded under the memory budget
+    database_load: Option<DatabaseLoadPlan>,
+    /// Result-cache key of the classification when it was reused from an earlier,
+    /// identical run instead of being run again
+    cached_classification: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+impl KrakenUniqResult {
+    pub fn new(
+        processed_kraken_uniq_report: Vec<ProcessedKrakenUniqReport>,
+        processed_kraken_uniq_stdout: Vec<ProcessedKrakenUniqStdout>,
+        raw_sequences: Vec<RawSequence>,
+        raw_sequences_summary: RawSequenceSummary,
+    ) -> Self {
+        KrakenUniqResult {
+            processed_kraken_uniq_report,
+            processed_kraken_uniq_stdout,
+            raw_sequences,
+            raw_sequences_summary,
+            run_metadata: None,
+            database_id: None,
+            output_store: None,
+            preprocessing: None,
+            contaminants: None,
+            database_load: None,
+            cached_classification: None,
+        }
+    }
+}
+
+/// Ratio of reads and k-mers an e-score is built on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EScoreFormula {
+    /// `tax_reads / kmers`, the app's scale
+    #[default]
+    ReadsPerKmer,
+    /// `kmers / tax_reads`, as the old report parser scored rows; for comparing with
+    /// results scored that way
+    KmersPerRead,
+}
+
+/// E-score of a report row: the `formula` ratio times exp(exp(coverage)).
+///
+/// Every e-score in the app (classification, demo data, refiltering, merged
+/// checkpoints) comes from here, so rows from different paths can be compared and
+/// filtered on the same scale. `tax_reads` are the reads assigned directly to the
+/// taxon, `kmers` its unique k-mers and `coverage` the fraction of its k-mers seen.
+/// Rows whose ratio has a zero denominator (no k-mers, or no reads for
+/// `KmersPerRead`) or with a non-finite input (e.g. an "NA" coverage) score 0.
+pub fn e_score(formula: EScoreFormula, tax_reads: f64, kmers: f64, coverage: f64) -> f64 {
+    if !(tax_reads.is_finite() && kmers.is_finite() && coverage.is_finite()) {
+        return 0.0;
+    }
+    let (numerator, denominator) = match formula {
+        EScoreFormula::ReadsPerKmer => (tax_reads, kmers),
+        EScoreFormula::KmersPerRead => (kmers, tax_reads),
+    };
+    if denominator <= 0.0 {
+        return 0.0;
+    }
+
+    // Calculate double exponential of coverage
+    let double_exp_cov = coverage.exp().exp();
+    (numerator / denominator) * double_exp_cov
+}
+
+/// Namespace of deterministic report node IDs. Changing it changes every derived ID,
+/// so re-processed samples would no longer match the rows already uploaded.
+const NODE_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6f1c2a4e_9b7d_4c31_8e52_d0a73f9b14c6);
+
+/// How the report rows of a sample get their IDs.
+pub(crate) enum NodeIds<'a> {
+    /// A fresh random ID per row and run
+    Random,
+    /// A UUIDv5 of the sample, the tax ID and the database version, so processing a
+    /// sample again against the same database reproduces every ID and uploads of the
+    /// same sample can be joined (or upserted) across runs
+    Deterministic {
+        sample_id: &'a str,
+        database_version: &'a str,
+    },
+}
+
+impl NodeIds<'_> {
+    pub fn id(&self, tax_id: u32) -> Uuid {
+        match self {
+            NodeIds::Random => Uuid::new_v4(),
+            NodeIds::Deterministic {
+                sample_id,
+                database_version,
+            } => {
+                let name = format!("{}:{}:{}", sample_id, tax_id, database_version);
+                Uuid::new_v5(&NODE_ID_NAMESPACE, name.as_bytes())
+            }
+        }
+    }
+}
+
+/// A report row. Payloads from before the counts were typed carry `reads`, `tax_reads`,
+/// `kmers`, `duplication` and `coverage` as strings; they are read all the same.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ProcessedKrakenUniqReport {
     pub id: String,
     pub percentage: f32,
-    pub reads: String,
-    pub tax_reads: String,
-    pub kmers: String,
-    pub duplication: String,
+    /// Reads in the clade rooted at this taxon
+    #[serde(deserialize_with = "deserialize_count")]
+    pub reads: u64,
+    /// Reads assigned directly to this taxon
+    #[serde(deserialize_with = "deserialize_count")]
+    pub tax_reads: u64,
+    /// Unique k-mers of the clade
+    #[serde(deserialize_with = "deserialize_count")]
+    pub kmers: u64,
+    /// Average times each unique k-mer was seen
+    #[serde(deserialize_with = "deserialize_real")]
+    pub duplication: f64,
     pub tax_name: String,
     pub parent_id: Option<Uuid>,
-    #[serde(serialize_with = "serialize_uuid_vec")]
+    #[serde(
+        serialize_with = "serialize_uuid_vec",
+        deserialize_with = "deserialize_uuid_vec"
+    )]
     pub children_ids: Vec<Uuid>,
     pub processed_data_id: String,
     pub user_id: String,
@@ -29,8 +185,16 @@ pub struct ProcessedKrakenUniqReport {
     pub sample_id: String,
     pub tax_id: u64,
     pub rank: String,
-    pub coverage: String,
+    /// Fraction of the clade's k-mers in the database that were seen
+    #[serde(deserialize_with = "deserialize_real")]
+    pub coverage: f64,
     pub e_score: f64,
+    /// Names of the ranked ancestors down to this taxon, joined with ';'
+    #[serde(default)]
+    pub lineage: Option<String>,
+    /// Ranks matching `lineage`, joined with ';'
+    #[serde(default)]
+    pub lineage_ranks: Option<String>,
 }
 
 // Updated serialization function to output Postgres array format
@@ -52,6 +216,67 @@ where
     serializer.serialize_str(&postgres_array)
 }
 
+/// `children_ids` as `serialize_uuid_vec` writes them, or as a JSON array.
+fn deserialize_uuid_vec<'de, D>(deserializer: D) -> Result<Vec<Uuid>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum UuidList {
+        Postgres(String),
+        Json(Vec<Uuid>),
+    }
+    match UuidList::deserialize(deserializer)? {
+        UuidList::Json(uuids) => Ok(uuids),
+        UuidList::Postgres(array) => array
+            .trim_matches(|c| c == '{' || c == '}')
+            .split(',')
+            .map(|id| id.trim().trim_matches('"'))
+            .filter(|id| !id.is_empty())
+            .map(|id| Uuid::parse_str(id).map_err(serde::de::Error::custom))
+            .collect(),
+    }
+}
+
+/// A number as any JSON number or a numeric string, the forms report counts were
+/// sent in over time.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LegacyNumber {
+    Unsigned(u64),
+    Signed(i64),
+    Real(f64),
+    Text(String),
+}
+
+/// A count, read like `output_store::count_column`: negatives become 0, and so does
+/// text that is not a number.
+fn deserialize_count<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match LegacyNumber::deserialize(deserializer)? {
+        LegacyNumber::Unsigned(v) => v,
+        LegacyNumber::Signed(v) => v.max(0) as u64,
+        LegacyNumber::Real(v) => v.max(0.0) as u64,
+        LegacyNumber::Text(v) => v.trim().parse().unwrap_or(0),
+    })
+}
+
+/// Like `deserialize_count`, for fractional values.
+fn deserialize_real<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match LegacyNumber::deserialize(deserializer)? {
+        LegacyNumber::Unsigned(v) => v as f64,
+        LegacyNumber::Signed(v) => v as f64,
+        LegacyNumber::Real(v) => v,
+        LegacyNumber::Text(v) => v.trim().parse().unwrap_or(0.0),
+    })
+}
+
 #[derive(Debug, Serialize)]
 pub struct ProcessedKrakenUniqStdout {
     pub id: String,
@@ -64,6 +289,9 @@ pub struct ProcessedKrakenUniqStdout {
     pub org_id: String,
     pub sample_id: String,
     pub processed_data_id: String,
+    /// Fraction of the read's k-mers supporting the assigned taxon; `None` for
+    /// unclassified reads and reads stored before confidence was recorded
+    pub confidence: Option<f64>,
 }
 
 /// The struct we will finally return to the frontend (instead of StandardResponse).
@@ -72,7 +300,7 @@ pub struct RawSequence {
     pub id: String,
     pub feature_id: String,
     // pub metadata: String,
-    pub sequence: String,
+    pub sequence: PackedSequence,
     pub quality: String,
     pub quality_median: f64,
     pub run_id: String,
@@ -86,8 +314,128 @@ pub struct RawSequence {
     pub basecall_model_version_id: String,
     pub flow_cell_id: String,
     pub protocol_group_id: String,
+    /// Unique molecular identifier, when UMI extraction was enabled
+    pub umi: Option<String>,
+    /// For a duplex read, the read names of its simplex parents
+    pub duplex_parent_ids: Vec<String>,
+    /// For a simplex read, the name of the duplex read that supersedes it
+    pub duplex_child_id: Option<String>,
     pub user_id: String,
     pub org_id: String,
     pub sample_id: String,
     pub raw_data_id: String,
 }
+
+/// Aggregate view of the parsed reads, returned even when the reads themselves
+/// are kept out of the IPC payload.
+#[derive(Debug, Serialize)]
+pub struct RawSequenceSummary {
+    pub read_count: usize,
+    pub total_bases: u64,
+    pub packed_bytes: u64,
+    pub mean_length: f64,
+    pub mean_quality_median: f64,
+    /// Path of the SQLite sidecar when reads were persisted instead of returned
+    pub store_path: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const DEFAULT: EScoreFormula = EScoreFormula::ReadsPerKmer;
+
+    #[test]
+    fn scores_reads_per_kmer_by_default() {
+        let expected = (10.0 / 400.0) * 0.5f64.exp().exp();
+        assert!((e_score(DEFAULT, 10.0, 400.0, 0.5) - expected).abs() < 1e-12);
+        assert_eq!(EScoreFormula::default(), DEFAULT);
+    }
+
+    #[test]
+    fn kmers_per_read_is_the_inverse_ratio() {
+        let forward = e_score(DEFAULT, 10.0, 400.0, 0.5);
+        let inverse = e_score(EScoreFormula::KmersPerRead, 10.0, 400.0, 0.5);
+        let double_exp = 0.5f64.exp().exp();
+        assert!(((forward / double_exp) * (inverse / double_exp) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn zero_denominators_score_zero() {
+        assert_eq!(e_score(DEFAULT, 10.0, 0.0, 0.5), 0.0);
+        assert_eq!(e_score(DEFAULT, 0.0, 400.0, 0.5), 0.0);
+        assert_eq!(e_score(EScoreFormula::KmersPerRead, 0.0, 400.0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn non_finite_inputs_score_zero() {
+        for formula in [DEFAULT, EScoreFormula::KmersPerRead] {
+            assert_eq!(e_score(formula, 10.0, 400.0, f64::NAN), 0.0);
+            assert_eq!(e_score(formula, f64::INFINITY, 400.0, 0.5), 0.0);
+        }
+    }
+
+    fn report_row(reads: Value, duplication: Value, children_ids: Value) -> Value {
+        json!({
+            "id": "7b0a2d0e-0000-4000-8000-000000000001",
+            "percentage": 12.5,
+            "reads": reads,
+            "tax_reads": reads,
+            "kmers": "900",
+            "duplication": duplication,
+            "tax_name": "Bacteria",
+            "parent_id": null,
+            "children_ids": children_ids,
+            "processed_data_id": "p",
+            "user_id": "u",
+            "org_id": "o",
+            "sample_id": "s",
+            "tax_id": 2,
+            "rank": "superkingdom",
+            "coverage": "0.25",
+            "e_score": 3.0,
+        })
+    }
+
+    #[test]
+    fn reads_report_rows_with_string_counts() {
+        let child = "7b0a2d0e-0000-4000-8000-000000000002";
+        let row: ProcessedKrakenUniqReport = serde_json::from_value(report_row(
+            json!("40"),
+            json!("1.50"),
+            json!(format!("{{\"{}\"}}", child)),
+        ))
+        .unwrap();
+        assert_eq!((row.reads, row.tax_reads, row.kmers), (40, 40, 900));
+        assert_eq!((row.duplication, row.coverage), (1.5, 0.25));
+        assert_eq!(row.children_ids, vec![Uuid::parse_str(child).unwrap()]);
+        assert_eq!(row.lineage, None);
+    }
+
+    #[test]
+    fn reads_report_rows_with_numeric_counts() {
+        let row: ProcessedKrakenUniqReport =
+            serde_json::from_value(report_row(json!(40), json!(2), json!([]))).unwrap();
+        assert_eq!((row.reads, row.duplication), (40, 2.0));
+        let negative: ProcessedKrakenUniqReport =
+            serde_json::from_value(report_row(json!(-3), json!(1.5), json!("{}"))).unwrap();
+        assert_eq!(negative.reads, 0);
+        assert!(negative.children_ids.is_empty());
+    }
+
+    #[test]
+    fn report_rows_round_trip_through_json() {
+        let row: ProcessedKrakenUniqReport = serde_json::from_value(report_row(
+            json!(40),
+            json!(1.5),
+            json!(["7b0a2d0e-0000-4000-8000-000000000002"]),
+        ))
+        .unwrap();
+        let again: ProcessedKrakenUniqReport =
+            serde_json::from_value(serde_json::to_value(&row).unwrap()).unwrap();
+        assert_eq!(again.reads, row.reads);
+        assert_eq!(again.children_ids, row.children_ids);
+        assert_eq!(again.coverage, row.coverage);
+    }
+}
@@ -0,0 +1,241 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{load_report, output_store_path};
+use crate::krakenuniq::refilter::{refilter, ReportThresholds};
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlMethod {
+    /// Subtract each taxon's control reads from its sample reads
+    #[default]
+    Subtract,
+    /// Remove taxa found in most controls unless far more abundant in the sample
+    Prevalence,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlAggregate {
+    /// The highest count over the controls; the conservative choice
+    #[default]
+    Max,
+    Mean,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ControlOptions {
+    pub method: ControlMethod,
+    /// How the controls' counts of a taxon are combined for `Subtract`
+    pub aggregate: ControlAggregate,
+    /// Scale control counts to the sample's read depth before subtracting
+    pub scale_to_depth: bool,
+    /// Share of controls a taxon must be found in to count as a contaminant, for
+    /// `Prevalence`
+    pub min_prevalence: f64,
+    /// A prevalent taxon stays if its relative abundance in the sample is at least
+    /// this many times its mean in the controls, for `Prevalence`
+    pub abundance_ratio: f64,
+}
+
+impl Default for ControlOptions {
+    fn default() -> Self {
+        ControlOptions {
+            method: ControlMethod::default(),
+            aggregate: ControlAggregate::default(),
+            scale_to_depth: false,
+            min_prevalence: 0.5,
+            abundance_ratio: 10.0,
+        }
+    }
+}
+
+/// What happened to one taxon found in the controls.
+#[derive(Debug, Serialize)]
+pub struct ControlTaxon {
+    pub tax_id: u64,
+    pub tax_name: String,
+    /// Reads assigned directly to the taxon in the sample
+    pub sample_reads: u64,
+    /// The controls' direct reads of the taxon, combined as `aggregate` says
+    pub control_reads: f64,
+    /// Share of controls the taxon was found in
+    pub prevalence: f64,
+    pub reads_removed: u64,
+    /// Every sample read of the taxon was removed
+    pub removed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecontaminatedReport {
+    pub processed_data_id: String,
+    pub control_ids: Vec<String>,
+    /// The sample's report with the control reads taken out and clade counts,
+    /// percentages and e-scores recomputed
+    pub rows: Vec<ProcessedKrakenUniqReport>,
+    pub reads_removed: u64,
+    /// Taxa of the sample that were also in a control, most reads removed first
+    pub taxa: Vec<ControlTaxon>,
+}
+
+/// Direct reads per tax ID and the total reads of a report.
+fn direct_reads(report: &[ProcessedKrakenUniqReport]) -> (HashMap<u64, u64>, u64) {
+    let reads = report
+        .iter()
+        .map(|row| (row.tax_id, row.tax_reads))
+        .collect();
+    let total = report
+        .iter()
+        .filter(|row| row.parent_id.is_none())
+        .map(|row| row.reads)
+        .sum();
+    (reads, total)
+}
+
+fn subtract_controls(
+    processed_data_id: &str,
+    control_ids: &[String],
+    sample: &[ProcessedKrakenUniqReport],
+    controls: &[Vec<ProcessedKrakenUniqReport>],
+    options: &ControlOptions,
+) -> DecontaminatedReport {
+    let (mut remaining, sample_total) = direct_reads(sample);
+    let controls: Vec<(HashMap<u64, u64>, u64)> =
+        controls.iter().map(|c| direct_reads(c)).collect();
+    let relative = |reads: u64, total: u64| {
+        if total > 0 {
+            reads as f64 / total as f64
+        } else {
+            0.0
+        }
+    };
+
+    // 1) Decide per taxon how many of its direct reads go. Unclassified (0) and root
+    // (1) are left alone, as in the other report filters
+    let mut taxa = Vec::new();
+    for row in sample.iter().filter(|row| row.tax_id > 1) {
+        let in_controls: Vec<(u64, u64)> = controls
+            .iter()
+            .map(|(reads, total)| (reads.get(&row.tax_id).copied().unwrap_or(0), *total))
+            .collect();
+        let found = in_controls.iter().filter(|(reads, _)| *reads > 0).count();
+        if found == 0 {
+            continue;
+        }
+        let prevalence = found as f64 / controls.len() as f64;
+
+        let scaled: Vec<f64> = in_controls
+            .iter()
+            .map(|&(reads, total)| {
+                if options.scale_to_depth && total > 0 {
+                    reads as f64 * sample_total as f64 / total as f64
+                } else {
+                    reads as f64
+                }
+            })
+            .collect();
+        let control_reads = match options.aggregate {
+            ControlAggregate::Max => scaled.iter().copied().fold(0.0, f64::max),
+            ControlAggregate::Mean => scaled.iter().sum::<f64>() / scaled.len() as f64,
+        };
+
+        let reads_removed = match options.method {
+            ControlMethod::Subtract => (control_reads.ceil() as u64).min(row.tax_reads),
+            ControlMethod::Prevalence => {
+                let control_abundance = in_controls
+                    .iter()
+                    .map(|&(reads, total)| relative(reads, total))
+                    .sum::<f64>()
+                    / in_controls.len() as f64;
+                let sample_abundance = relative(row.tax_reads, sample_total);
+                let contaminant = prevalence >= options.min_prevalence
+                    && sample_abundance < control_abundance * options.abundance_ratio;
+                if contaminant {
+                    row.tax_reads
+                } else {
+                    0
+                }
+            }
+        };
+        if let Some(reads) = remaining.get_mut(&row.tax_id) {
+            *reads -= reads_removed;
+        }
+        taxa.push(ControlTaxon {
+            tax_id: row.tax_id,
+            tax_name: row.tax_name.trim().to_string(),
+            sample_reads: row.tax_reads,
+            control_reads,
+            prevalence,
+            reads_removed,
+            removed: reads_removed > 0 && reads_removed == row.tax_reads,
+        });
+    }
+    taxa.sort_by_key(|t| Reverse(t.reads_removed));
+
+    // 2) Rebuild the report from what is left; emptied taxa drop out and their
+    // descendants are re-parented
+    let rebuilt = refilter(sample, &remaining, &ReportThresholds::default());
+
+    DecontaminatedReport {
+        processed_data_id: processed_data_id.to_string(),
+        control_ids: control_ids.to_vec(),
+        rows: rebuilt.rows,
+        reads_removed: taxa.iter().map(|t| t.reads_removed).sum(),
+        taxa,
+    }
+}
+
+/// Removes the reads of field blanks and other negative controls from the stored report
+/// of `processed_data_id`. `Subtract` takes each taxon's control count (the highest or
+/// mean over `control_ids`) off its sample count; `Prevalence` drops taxa found in most
+/// controls unless they are much more abundant in the sample. The stored report is
+/// left untouched.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn subtract_control<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    control_ids: Vec<String>,
+    options: Option<ControlOptions>,
+) -> Result<StandardResponseNoFiles<DecontaminatedReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if control_ids.is_empty() {
+            return Err(PoleshiftError::InvalidInput {
+                field: "control_ids".to_string(),
+                reason: "at least one control is needed".to_string(),
+            });
+        }
+        if control_ids.contains(&processed_data_id) {
+            return Err(PoleshiftError::InvalidInput {
+                field: "control_ids".to_string(),
+                reason: "a sample cannot be its own control".to_string(),
+            });
+        }
+        let options = options.unwrap_or_default();
+
+        let sample = load_report(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let controls = control_ids
+            .iter()
+            .map(|id| load_report(&output_store_path(&app_handle, id)?))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: subtract_controls(
+                &processed_data_id,
+                &control_ids,
+                &sample,
+                &controls,
+                &options,
+            ),
+        })
+    })
+    .await
+}
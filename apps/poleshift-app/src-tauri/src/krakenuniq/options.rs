@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use crate::krakenuniq::backend::BackendKind;
+use crate::krakenuniq::EScoreFormula;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Upper bound on `threads`, well above any laptop we ship to.
+pub(crate) const MAX_THREADS: usize = 256;
+
+/// Classifier settings chosen by the user for a single run.
+///
+/// `threads` and `only_classified_output` are applied directly, and `memory_budget_mb`
+/// picks the database load strategy (see `preload::plan_load`). The bundled
+/// `krakenuniq-rs` does not yet expose min-hits, quick mode, exact counting or
+/// preloading, so with the in-process backend those are validated and rejected rather
+/// than silently ignored; the sidecar backend passes them on to `krakenuniq`. Kraken2
+/// takes min-hits (as minimum hit groups) and quick mode; Centrifuge takes none of them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassificationOptions {
+    /// Worker threads for the classifier; defaults to one per core
+    pub threads: Option<usize>,
+    /// Minimum k-mer hits required to classify a read
+    pub min_hits: Option<u32>,
+    /// Stop at the first `min_hits` hits instead of scanning the whole read
+    pub quick: bool,
+    /// Count unique k-mers exactly instead of with HyperLogLog
+    pub exact_counting: bool,
+    /// Amount of the database to preload, e.g. `"8G"`
+    pub preload_size: Option<String>,
+    /// Leave unclassified reads out of the per-read output
+    pub only_classified_output: bool,
+    /// Memory the database may use; defaults to the available system memory
+    pub memory_budget_mb: Option<u64>,
+    /// Classifier implementation to run
+    pub backend: BackendKind,
+    /// Executable for the sidecar, Kraken2 and Centrifuge backends, from the
+    /// `classifier_path` setting; never taken from a call, which could otherwise have
+    /// the app run any program. Defaults to a bundled one, then to `PATH`
+    #[serde(skip)]
+    pub classifier_path: Option<String>,
+    /// Kraken2 database directory or Centrifuge index prefix; required by those
+    /// backends, which do not use the bundled KrakenUniq database
+    pub database_path: Option<String>,
+    /// Give up on a classification that runs longer than this
+    pub timeout_secs: Option<u64>,
+    /// How report rows are scored; see `e_score`
+    pub e_score_formula: EScoreFormula,
+}
+
+impl ClassificationOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if let Some(threads) = self.threads {
+            if threads == 0 || threads > MAX_THREADS {
+                return Err(PoleshiftError::DataError(format!(
+                    "threads must be between 1 and {}, got {}",
+                    MAX_THREADS, threads
+                )));
+            }
+        }
+
+        if self.timeout_secs == Some(0) {
+            return Err(PoleshiftError::DataError(
+                "timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.memory_budget_mb == Some(0) {
+            return Err(PoleshiftError::DataError(
+                "memory_budget_mb must be greater than 0".to_string(),
+            ));
+        }
+
+        if let Some(size) = &self.preload_size {
+            let digits = size.trim_end_matches(['K', 'M', 'G', 'k', 'm', 'g']);
+            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                return Err(PoleshiftError::DataError(format!(
+                    "preload_size must be a number with an optional K, M or G suffix, got '{}'",
+                    size
+                )));
+            }
+        }
+        if !self.backend.uses_krakenuniq_database()
+            && self
+                .database_path
+                .as_deref()
+                .is_none_or(|p| p.trim().is_empty())
+        {
+            return Err(PoleshiftError::InvalidInput {
+                field: "database_path".to_string(),
+                reason: format!("required by the {:?} backend", self.backend),
+            });
+        }
+        if self.backend == BackendKind::Sidecar {
+            return Ok(());
+        }
+
+        let mut unsupported = Vec::new();
+        if self.min_hits.is_some() && self.backend != BackendKind::Kraken2 {
+            unsupported.push("min_hits");
+        }
+        if self.quick && self.backend != BackendKind::Kraken2 {
+            unsupported.push("quick");
+        }
+        if self.exact_counting {
+            unsupported.push("exact_counting");
+        }
+        if self.preload_size.is_some() {
+            unsupported.push("preload_size");
+        }
+        if !unsupported.is_empty() {
+            return Err(PoleshiftError::DataError(format!(
+                "Not supported by the {:?} backend: {}",
+                self.backend,
+                unsupported.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    #[test]
+    fn classifier_path_is_not_taken_from_a_call() {
+        let options: ClassificationOptions =
+            serde_json::from_str(r#"{"backend": "sidecar", "classifier_path": "/tmp/anything"}"#)
+                .unwrap();
+        assert_eq!(options.classifier_path, None);
+
+        let settings = Settings {
+            classifier_path: Some("/opt/krakenuniq/bin/krakenuniq".to_string()),
+            ..Settings::default()
+        };
+        let options = settings.classification_options(Some(options));
+        assert_eq!(
+            options.classifier_path.as_deref(),
+            Some("/opt/krakenuniq/bin/krakenuniq")
+        );
+    }
+}
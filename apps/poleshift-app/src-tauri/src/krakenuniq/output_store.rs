@@ -0,0 +1,476 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+use uuid::Uuid;
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::raw_sequence_store::{
+    create_store, raw_sequence_from_row, write_raw_sequences,
+};
+use crate::krakenuniq::{ProcessedKrakenUniqReport, ProcessedKrakenUniqStdout, RawSequence};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::parse_uuid;
+
+/// Rows returned by a page query when no `limit` is given.
+const DEFAULT_PAGE_SIZE: usize = 500;
+/// Largest page a single query may return.
+const MAX_PAGE_SIZE: usize = 10_000;
+
+/// Where a persisted classification went and how much it holds.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputStoreSummary {
+    pub path: String,
+    pub report_rows: usize,
+    pub stdout_rows: usize,
+    pub raw_sequences: usize,
+}
+
+/// One page of rows from an output store.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub total: usize,
+    pub offset: usize,
+    pub rows: Vec<T>,
+}
+
+/// Location of the output store for `processed_data_id` in the app data directory.
+pub(crate) fn output_store_path<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    processed_data_id: &str,
+) -> Result<PathBuf, PoleshiftError> {
+    // The ID becomes a file name, so only accept real UUIDs
+    let id = parse_uuid("processed_data_id", processed_data_id)?;
+    Ok(app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join("classifications")
+        .join(format!("{}.sqlite", id)))
+}
+
+/// Writes the report, per-read output and raw reads of one classification into a
+/// fresh SQLite file at `path`.
+pub(crate) fn persist_outputs(
+    path: &Path,
+    report: &[ProcessedKrakenUniqReport],
+    stdout: &[ProcessedKrakenUniqStdout],
+    raw_sequences: &[RawSequence],
+) -> Result<OutputStoreSummary, PoleshiftError> {
+    let mut conn = create_store(path)?;
+    conn.execute_batch(
+        "CREATE TABLE report (
+            id TEXT PRIMARY KEY,
+            percentage REAL NOT NULL,
+            reads INTEGER NOT NULL,
+            tax_reads INTEGER NOT NULL,
+            kmers INTEGER NOT NULL,
+            duplication REAL NOT NULL,
+            tax_name TEXT NOT NULL,
+            parent_id TEXT,
+            children_ids TEXT NOT NULL,
+            processed_data_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            org_id TEXT NOT NULL,
+            sample_id TEXT NOT NULL,
+            tax_id INTEGER NOT NULL,
+            rank TEXT NOT NULL,
+            coverage REAL NOT NULL,
+            e_score REAL NOT NULL,
+            lineage TEXT,
+            lineage_ranks TEXT
+        );
+        CREATE TABLE stdout (
+            id TEXT PRIMARY KEY,
+            classified INTEGER NOT NULL,
+            feature_id TEXT NOT NULL,
+            tax_id INTEGER NOT NULL,
+            read_length INTEGER NOT NULL,
+            hit_data TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            org_id TEXT NOT NULL,
+            sample_id TEXT NOT NULL,
+            processed_data_id TEXT NOT NULL,
+            confidence REAL
+        );
+        CREATE INDEX stdout_tax_id ON stdout (tax_id);",
+    )
+    .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    {
+        let mut stmt = tx
+            .prepare(
+                "INSERT INTO report VALUES
+                 (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+                  ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+            )
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        for row in report {
+            let children: Vec<String> = row.children_ids.iter().map(|id| id.to_string()).collect();
+            stmt.execute(params![
+                row.id,
+                row.percentage,
+                row.reads as i64,
+                row.tax_reads as i64,
+                row.kmers as i64,
+                row.duplication,
+                row.tax_name,
+                row.parent_id.map(|id| id.to_string()),
+                serde_json::to_string(&children)?,
+                row.processed_data_id,
+                row.user_id,
+                row.org_id,
+                row.sample_id,
+                row.tax_id as i64,
+                row.rank,
+                row.coverage,
+                row.e_score,
+                row.lineage,
+                row.lineage_ranks,
+            ])
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        }
+
+        let mut stmt = tx
+            .prepare("INSERT INTO stdout VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)")
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        for line in stdout {
+            stmt.execute(params![
+                line.id,
+                line.classified,
+                line.feature_id,
+                line.tax_id,
+                line.read_length,
+                line.hit_data,
+                line.user_id,
+                line.org_id,
+                line.sample_id,
+                line.processed_data_id,
+                line.confidence,
+            ])
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        }
+    }
+    tx.commit()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    write_raw_sequences(&mut conn, raw_sequences)?;
+
+    Ok(OutputStoreSummary {
+        path: path.to_string_lossy().to_string(),
+        report_rows: report.len(),
+        stdout_rows: stdout.len(),
+        raw_sequences: raw_sequences.len(),
+    })
+}
+
+pub(crate) fn open_store(path: &Path) -> Result<Connection, PoleshiftError> {
+    if !path.exists() {
+        return Err(PoleshiftError::DataError(format!(
+            "No stored classification at {}",
+            path.display()
+        )));
+    }
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| PoleshiftError::IoError(e.to_string()))
+}
+
+/// Runs a count query and a paged select sharing the same `filter` and parameters.
+pub(crate) fn query_page<T, F>(
+    conn: &Connection,
+    table: &str,
+    filter: &str,
+    filter_params: &[&dyn rusqlite::ToSql],
+    offset: Option<usize>,
+    limit: Option<usize>,
+    map_row: F,
+) -> Result<Page<T>, PoleshiftError>
+where
+    F: FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+{
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let total: i64 = conn
+        .query_row(
+            &format!("SELECT COUNT(*) FROM {} {}", table, filter),
+            filter_params,
+            |row| row.get(0),
+        )
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT * FROM {} {} ORDER BY rowid LIMIT {} OFFSET {}",
+            table, filter, limit, offset
+        ))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let rows = stmt
+        .query_map(filter_params, map_row)
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    Ok(Page {
+        total: total as usize,
+        offset,
+        rows,
+    })
+}
+
+/// Stores written before the report counts were typed hold them as TEXT; both forms
+/// are accepted so those stay readable.
+fn count_column(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<u64> {
+    Ok(match row.get::<_, Value>(idx)? {
+        Value::Integer(v) => v.max(0) as u64,
+        Value::Real(v) => v.max(0.0) as u64,
+        Value::Text(v) => v.trim().parse().unwrap_or(0),
+        _ => 0,
+    })
+}
+
+/// Like `count_column`, for fractional values.
+fn real_column(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<f64> {
+    Ok(match row.get::<_, Value>(idx)? {
+        Value::Integer(v) => v as f64,
+        Value::Real(v) => v,
+        Value::Text(v) => v.trim().parse().unwrap_or(0.0),
+        _ => 0.0,
+    })
+}
+
+fn report_from_row(row: &rusqlite::Row) -> rusqlite::Result<ProcessedKrakenUniqReport> {
+    let parent_id: Option<String> = row.get(7)?;
+    let children_ids: String = row.get(8)?;
+    let children_ids: Vec<String> = serde_json::from_str(&children_ids).unwrap_or_default();
+    Ok(ProcessedKrakenUniqReport {
+        id: row.get(0)?,
+        percentage: row.get::<_, f64>(1)? as f32,
+        reads: count_column(row, 2)?,
+        tax_reads: count_column(row, 3)?,
+        kmers: count_column(row, 4)?,
+        duplication: real_column(row, 5)?,
+        tax_name: row.get(6)?,
+        parent_id: parent_id.and_then(|id| Uuid::parse_str(&id).ok()),
+        children_ids: children_ids
+            .iter()
+            .filter_map(|id| Uuid::parse_str(id).ok())
+            .collect(),
+        processed_data_id: row.get(9)?,
+        user_id: row.get(10)?,
+        org_id: row.get(11)?,
+        sample_id: row.get(12)?,
+        tax_id: row.get::<_, i64>(13)? as u64,
+        rank: row.get(14)?,
+        coverage: real_column(row, 15)?,
+        e_score: row.get(16)?,
+        // Stores written before lineages were recorded have no such columns
+        lineage: row.get::<_, Option<String>>(17).unwrap_or(None),
+        lineage_ranks: row.get::<_, Option<String>>(18).unwrap_or(None),
+    })
+}
+
+/// Reads the whole stored report at `path`, in report order.
+pub(crate) fn load_report(path: &Path) -> Result<Vec<ProcessedKrakenUniqReport>, PoleshiftError> {
+    let conn = open_store(path)?;
+    let mut stmt = conn
+        .prepare("SELECT * FROM report ORDER BY rowid")
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let rows = stmt
+        .query_map([], report_from_row)
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    Ok(rows)
+}
+
+fn stdout_from_row(row: &rusqlite::Row) -> rusqlite::Result<ProcessedKrakenUniqStdout> {
+    Ok(ProcessedKrakenUniqStdout {
+        id: row.get(0)?,
+        classified: row.get(1)?,
+        feature_id: row.get(2)?,
+        tax_id: row.get(3)?,
+        read_length: row.get(4)?,
+        hit_data: row.get(5)?,
+        user_id: row.get(6)?,
+        org_id: row.get(7)?,
+        sample_id: row.get(8)?,
+        processed_data_id: row.get(9)?,
+        // Stores written before confidence was recorded have no such column
+        confidence: row.get::<_, Option<f64>>(10).unwrap_or(None),
+    })
+}
+
+/// Whether the stdout table of `conn` has a `confidence` column, i.e. the store was
+/// written after per-read confidence was recorded.
+fn has_confidence(conn: &Connection) -> Result<bool, PoleshiftError> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('stdout') WHERE name = 'confidence'",
+        [],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+    .map_err(|e| PoleshiftError::DataError(e.to_string()))
+}
+
+/// Fails if `min_confidence` is set but the store in `conn` predates confidence.
+pub(crate) fn require_confidence(
+    conn: &Connection,
+    min_confidence: Option<f64>,
+) -> Result<(), PoleshiftError> {
+    if min_confidence.is_some() && !has_confidence(conn)? {
+        return Err(PoleshiftError::DataError(
+            "This classification was stored before per-read confidence was recorded; \
+             run it again to filter by confidence"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns a page of the stored classification report, in report order.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn query_classification_report<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<StandardResponseNoFiles<Page<ProcessedKrakenUniqReport>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_store(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let page = query_page(&conn, "report", "", &[], offset, limit, report_from_row)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: page,
+        })
+    })
+    .await
+}
+
+/// Returns a page of the stored per-read classifications, optionally only those
+/// assigned to `tax_id`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn query_classified_reads<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    tax_id: Option<i32>,
+) -> Result<StandardResponseNoFiles<Page<ProcessedKrakenUniqStdout>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_store(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let page = match tax_id {
+            Some(tax_id) => query_page(
+                &conn,
+                "stdout",
+                "WHERE tax_id = ?1",
+                &[&tax_id],
+                offset,
+                limit,
+                stdout_from_row,
+            )?,
+            None => query_page(&conn, "stdout", "", &[], offset, limit, stdout_from_row)?,
+        };
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: page,
+        })
+    })
+    .await
+}
+
+/// Returns a page of the stored raw reads.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn query_raw_sequences<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<StandardResponseNoFiles<Page<RawSequence>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_store(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let page = query_page(
+            &conn,
+            "raw_sequences",
+            "",
+            &[],
+            offset,
+            limit,
+            raw_sequence_from_row,
+        )?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: page,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_reports_stored_before_lineages() {
+        let path = std::env::temp_dir().join(format!(
+            "poleshift-legacy-report-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE report (
+                id TEXT PRIMARY KEY, percentage REAL, reads TEXT, tax_reads TEXT,
+                kmers TEXT, duplication TEXT, tax_name TEXT, parent_id TEXT,
+                children_ids TEXT, processed_data_id TEXT, user_id TEXT, org_id TEXT,
+                sample_id TEXT, tax_id INTEGER, rank TEXT, coverage TEXT, e_score REAL
+            );
+            INSERT INTO report VALUES ('a', 12.5, '40', '10', '900', '1.5', 'Bacteria',
+                NULL, '[]', 'p', 'u', 'o', 's', 2, 'superkingdom', '0.25', 3.0);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let report = load_report(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.len(), 1);
+        assert_eq!((report[0].reads, report[0].tax_reads), (40, 10));
+        assert_eq!(report[0].lineage, None);
+        assert_eq!(report[0].lineage_ranks, None);
+    }
+
+    #[test]
+    fn reads_typed_report_counts() {
+        let path = std::env::temp_dir().join(format!(
+            "poleshift-typed-report-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let row: ProcessedKrakenUniqReport = serde_json::from_value(serde_json::json!({
+            "id": "a", "percentage": 12.5, "reads": 40, "tax_reads": 10, "kmers": 900,
+            "duplication": 1.5, "tax_name": "Bacteria", "parent_id": null,
+            "children_ids": [], "processed_data_id": "p", "user_id": "u", "org_id": "o",
+            "sample_id": "s", "tax_id": 2, "rank": "superkingdom", "coverage": 0.25,
+            "e_score": 3.0, "lineage": "Bacteria", "lineage_ranks": "superkingdom",
+        }))
+        .unwrap();
+        persist_outputs(&path, &[row], &[], &[]).unwrap();
+
+        let report = load_report(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            (report[0].reads, report[0].tax_reads, report[0].kmers),
+            (40, 10, 900)
+        );
+        assert_eq!((report[0].duplication, report[0].coverage), (1.5, 0.25));
+        assert_eq!(report[0].lineage.as_deref(), Some("Bacteria"));
+    }
+}
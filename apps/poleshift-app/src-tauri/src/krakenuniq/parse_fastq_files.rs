@@ -1,8 +1,11 @@
 use crate::io::fastq::FastqReader;
 use crate::io::fastqgz::FastqGzReader;
+use crate::io::packed::PackedSequence;
+use crate::io::umi::UMI_HEADER_KEY;
 use crate::io::{ParseError, Validate};
 use crate::krakenuniq::RawSequence;
 use rayon::prelude::*;
+use std::collections::HashMap;
 use std::fs::File;
 use uuid::Uuid;
 
@@ -22,90 +25,120 @@ fn median_quality(scores: &[u8]) -> f64 {
     }
 }
 
+/// Fields parsed out of a Nanopore FASTQ header.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct NanoporeHeader {
+    pub run_id: String,
+    pub read: i32,
+    pub ch: i32,
+    pub start_time: String,
+    pub sample_id_fastq: String,
+    pub barcode: String,
+    pub barcode_alias: String,
+    pub parent_read_id: String,
+    pub basecall_model_version_id: String,
+    pub flow_cell_id: String,
+    pub protocol_group_id: String,
+    pub umi: Option<String>,
+    /// Simplex parents of a duplex read, named `<template>;<complement>` by the basecaller
+    pub duplex_parent_ids: Vec<String>,
+}
+
 /// A helper function that splits a FASTQ header into key-value pairs.
 /// For example, a typical Nanopore header might look like:
 ///
 /// @<parent_read_id> runid=<run_id> read=123 ch=456 start_time=2024-01-01T12:34:56Z sampleid=SAMPLE1 ...
 ///
 /// We'll parse each space-delimited token to see if it matches runid=..., read=..., etc.
-fn parse_nanopore_header(
-    header: &str,
-) -> (
-    String, // run_id
-    i32,    // read
-    i32,    // ch
-    String, // start_time
-    String, // sample_id_fastq
-    String, // barcode
-    String, // barcode_alias
-    String, // parent_read_id
-    String, // basecall_model_version_id
-    String,
-    String,
-) {
-    let mut run_id = String::new();
-    let mut read = 0;
-    let mut ch = 0;
-    let mut start_time = String::new();
-    let mut sample_id_fastq = String::new();
-    let mut barcode = String::new();
-    let mut barcode_alias = String::new();
-    let mut parent_read_id = String::new();
-    let mut basecall_model_version_id = String::new();
-    let mut protocol_group_id = String::new();
-    let mut flow_cell_id = String::new();
+pub(crate) fn parse_nanopore_header(header: &str) -> NanoporeHeader {
+    let mut parsed = NanoporeHeader::default();
+    let umi_prefix = format!("{}=", UMI_HEADER_KEY);
 
     // Split on whitespace and iterate
-    let parts: Vec<&str> = header.split_whitespace().collect();
-    for part in parts {
+    for part in header.split_whitespace() {
         // If the FASTQ header starts with "@", it could be your "parent_read_id".
         // e.g. "@f5ad7a72-81c1-4fce-a0db-fa31daf5d669"
         if part.starts_with('@') {
             // We skip '@' symbol
-            parent_read_id = part.trim_start_matches('@').to_string();
+            parsed.parent_read_id = part.trim_start_matches('@').to_string();
+            if parsed.parent_read_id.contains(';') {
+                parsed.duplex_parent_ids = parsed
+                    .parent_read_id
+                    .split(';')
+                    .filter(|id| !id.is_empty())
+                    .map(|id| id.to_string())
+                    .collect();
+            }
         } else if let Some(value) = part.strip_prefix("runid=") {
-            run_id = value.to_string();
+            parsed.run_id = value.to_string();
         } else if let Some(value) = part.strip_prefix("read=") {
-            read = value.parse().unwrap_or_default();
+            parsed.read = value.parse().unwrap_or_default();
         } else if let Some(value) = part.strip_prefix("ch=") {
-            ch = value.parse().unwrap_or_default();
+            parsed.ch = value.parse().unwrap_or_default();
         } else if let Some(value) = part.strip_prefix("start_time=") {
-            start_time = value.to_string();
+            parsed.start_time = value.to_string();
         } else if let Some(value) = part.strip_prefix("sample_id=") {
-            sample_id_fastq = value.to_string();
+            parsed.sample_id_fastq = value.to_string();
         } else if let Some(value) = part.strip_prefix("barcode=") {
-            barcode = value.to_string();
+            parsed.barcode = value.to_string();
         } else if let Some(value) = part.strip_prefix("barcode_alias=") {
-            barcode_alias = value.to_string();
+            parsed.barcode_alias = value.to_string();
         } else if let Some(value) = part.strip_prefix("flow_cell_id=") {
-            flow_cell_id = value.to_string();
+            parsed.flow_cell_id = value.to_string();
         } else if let Some(value) = part.strip_prefix("protocol_group_id=") {
-            protocol_group_id = value.to_string();
+            parsed.protocol_group_id = value.to_string();
         } else if let Some(value) = part.strip_prefix("basecall_model_version_id=") {
-            basecall_model_version_id = value.to_string();
+            parsed.basecall_model_version_id = value.to_string();
+        } else if let Some(value) = part.strip_prefix(umi_prefix.as_str()) {
+            parsed.umi = Some(value.to_string());
         }
         // If other fields exist that you need to parse, handle them similarly
     }
 
-    (
-        run_id,
-        read,
-        ch,
-        start_time,
-        sample_id_fastq,
-        barcode,
-        barcode_alias,
-        parent_read_id,
-        basecall_model_version_id,
-        flow_cell_id,
-        protocol_group_id,
-    )
+    parsed
+}
+
+/// Links duplex reads to their simplex parents from the same run.
+///
+/// Every parent found in `sequences` gets `duplex_child_id` set to the duplex read's
+/// name. Returns the number of parents linked. Parents are dropped, where asked, by
+/// the preprocessing stage, before classification, so they are not counted twice.
+pub(crate) fn reconcile_duplex_reads(sequences: &mut [RawSequence]) -> usize {
+    // (run_id, read name) -> duplex read name
+    let mut children: HashMap<(String, String), String> = HashMap::new();
+    for seq in sequences.iter() {
+        for parent in &seq.duplex_parent_ids {
+            children.insert(
+                (seq.run_id.clone(), parent.clone()),
+                seq.parent_read_id.clone(),
+            );
+        }
+    }
+    if children.is_empty() {
+        return 0;
+    }
+
+    let mut linked = 0;
+    for seq in sequences.iter_mut() {
+        if !seq.duplex_parent_ids.is_empty() {
+            continue;
+        }
+        let key = (seq.run_id.clone(), seq.parent_read_id.clone());
+        if let Some(child) = children.get(&key) {
+            seq.duplex_child_id = Some(child.clone());
+            linked += 1;
+        }
+    }
+    linked
 }
 
 /// Parse all sequences from the given file paths and return a flat `Vec<RawSequence>`.
 ///
 /// In a real-world app, you might want more robust file-extension checks.
 /// Here, we check only for ".gz".
+///
+/// Duplex reads are reconciled with their simplex parents once all files are read,
+/// see `reconcile_duplex_reads`.
 pub fn parse_fastq_files(
     file_paths: &[String],
     user_id: String,
@@ -139,42 +172,33 @@ pub fn parse_fastq_files(
             let qual_median = median_quality(&rec.quality);
 
             // Parse fields from the FASTQ header
-            let (
-                run_id,
-                read,
-                ch,
-                start_time,
-                sample_id_fastq,
-                barcode,
-                barcode_alias,
-                parent_read_id,
-                basecall_model_version_id,
-                flow_cell_id,
-                protocol_group_id,
-            ) = parse_nanopore_header(&rec.header);
+            let header = parse_nanopore_header(&rec.header);
 
             // You can also decide how you want to populate `id`, `feature_id`, `metadata`, etc.
             // For demonstration, let's store the entire header in `metadata`,
             // and put the parent_read_id into `id`.
             let raw_seq = RawSequence {
                 id: String::from(Uuid::new_v4()), // or rec.header.clone(), etc.
-                feature_id: parent_read_id.clone(),
+                feature_id: header.parent_read_id.clone(),
                 // metadata: rec.header.clone(), // store the raw header
-                sequence: rec.sequence.clone(),
+                sequence: PackedSequence::pack(&rec.sequence),
                 // Convert the ASCII Phred+33 scores to a human-readable string
                 quality: String::from_utf8_lossy(&rec.quality).to_string(),
                 quality_median: qual_median,
-                run_id,
-                read,
-                ch,
-                start_time,
-                sample_id_fastq,
-                barcode,
-                barcode_alias,
-                parent_read_id,
-                basecall_model_version_id,
-                flow_cell_id,
-                protocol_group_id,
+                run_id: header.run_id,
+                read: header.read,
+                ch: header.ch,
+                start_time: header.start_time,
+                sample_id_fastq: header.sample_id_fastq,
+                barcode: header.barcode,
+                barcode_alias: header.barcode_alias,
+                parent_read_id: header.parent_read_id,
+                basecall_model_version_id: header.basecall_model_version_id,
+                flow_cell_id: header.flow_cell_id,
+                protocol_group_id: header.protocol_group_id,
+                umi: header.umi,
+                duplex_parent_ids: header.duplex_parent_ids,
+                duplex_child_id: None,
                 user_id: user_id.clone(),
                 org_id: org_id.clone(),
                 sample_id: sample_id.clone(),
@@ -185,5 +209,11 @@ pub fn parse_fastq_files(
         }
     }
 
+    // Link duplex reads to parents, which may live in a different file of the same run
+    let linked = reconcile_duplex_reads(&mut all_sequences);
+    if linked > 0 {
+        println!("Linked {} simplex reads to duplex offspring", linked);
+    }
+
     Ok(all_sequences)
 }
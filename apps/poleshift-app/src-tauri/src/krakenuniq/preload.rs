@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::backend::BackendKind;
+use crate::poleshift_common::types::{KrakenConfig, PoleshiftError, StandardResponseNoFiles};
+use crate::settings::{current_settings, resources_dir};
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+/// With memory mapping at least this fraction of the k-mer table should stay cached,
+/// or page faults dominate and chunking is faster.
+const MIN_MMAP_RESIDENT_FRACTION: f64 = 0.25;
+/// Rough throughput of a memory-mapped run relative to a preloaded one on an SSD.
+const MMAP_RELATIVE_SPEED: f64 = 0.3;
+
+/// How the k-mer database is brought into memory for classification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadStrategy {
+    /// Read the whole database into RAM up front
+    Full,
+    /// Map the database and let the OS page it in on demand
+    Mmap,
+    /// Load one slice of the database at a time and scan all reads against each
+    Chunked,
+}
+
+/// The load strategy picked for a database under a memory budget, and what it costs.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseLoadPlan {
+    pub database_id: String,
+    /// Size of the k-mer table, index, counts and taxonomy files together
+    pub database_bytes: u64,
+    /// Memory available to new allocations, if it could be detected
+    pub available_memory_bytes: Option<u64>,
+    /// The explicit budget, or else the available memory
+    pub budget_bytes: Option<u64>,
+    pub strategy: LoadStrategy,
+    /// Slices the k-mer table is split into; 1 unless chunked
+    pub chunks: u64,
+    /// Size of each slice when chunked
+    pub chunk_bytes: Option<u64>,
+    /// Estimated throughput relative to a full preload
+    pub relative_speed: f64,
+    /// One-line summary of the speed tradeoff for the UI
+    pub tradeoff: String,
+    /// Whether the chosen backend can run with this strategy. The in-process classifier
+    /// always preloads the full database, so it only supports `Full`.
+    pub supported: bool,
+}
+
+/// Size of a database file, or of its `.gz` (a lower bound) before the first run has
+/// decompressed it.
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(path)
+        .or_else(|_| std::fs::metadata(format!("{}.gz", path)))
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
+
+/// Memory the OS could hand to a new process without swapping, in bytes.
+#[cfg(target_os = "linux")]
+pub fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Memory the OS could hand to a new process without swapping, in bytes: free,
+/// inactive and speculative pages as reported by `vm_stat`.
+#[cfg(target_os = "macos")]
+pub fn available_memory() -> Option<u64> {
+    let output = std::process::Command::new("vm_stat").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let page_size: u64 = text
+        .lines()
+        .next()?
+        .split("page size of ")
+        .nth(1)?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    let pages = |label: &str| -> u64 {
+        text.lines()
+            .find(|l| l.starts_with(label))
+            .and_then(|l| l.rsplit(':').next())
+            .and_then(|v| v.trim().trim_end_matches('.').parse().ok())
+            .unwrap_or(0)
+    };
+    let free = pages("Pages free") + pages("Pages inactive") + pages("Pages speculative");
+    Some(free * page_size)
+}
+
+/// Memory the OS could hand to a new process without swapping, in bytes.
+#[cfg(target_os = "windows")]
+pub fn available_memory() -> Option<u64> {
+    let output = std::process::Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "(Get-CimInstance Win32_OperatingSystem).FreePhysicalMemory",
+        ])
+        .output()
+        .ok()?;
+    let kb: u64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn available_memory() -> Option<u64> {
+    None
+}
+
+/// Picks the load strategy for the database in `config`.
+///
+/// The database is preloaded when it fits in the budget. Otherwise the index, counts
+/// and taxonomy must stay resident and the k-mer table is memory-mapped if enough of
+/// it can stay cached, or else split into chunks that each fit. Without a budget the
+/// detected available memory is used; if that is unknown too, the database is
+/// preloaded as before.
+pub fn plan_load(
+    config: &KrakenConfig,
+    memory_budget_mb: Option<u64>,
+    backend: BackendKind,
+) -> Result<DatabaseLoadPlan, PoleshiftError> {
+    let kmer_table = file_size(&config.db_file);
+    let resident = file_size(&config.idx_file)
+        + file_size(&config.counts_file)
+        + file_size(&config.taxdb_file);
+    let database_bytes = kmer_table + resident;
+    let available_memory_bytes = available_memory();
+    let budget_bytes = memory_budget_mb
+        .map(|mb| mb * BYTES_PER_MB)
+        .or(available_memory_bytes);
+
+    let mut plan = DatabaseLoadPlan {
+        database_id: config.database_id.clone(),
+        database_bytes,
+        available_memory_bytes,
+        budget_bytes,
+        strategy: LoadStrategy::Full,
+        chunks: 1,
+        chunk_bytes: None,
+        relative_speed: 1.0,
+        tradeoff: "Whole database held in memory; fastest".to_string(),
+        supported: true,
+    };
+    let Some(budget) = budget_bytes.filter(|&b| b < database_bytes) else {
+        return Ok(plan);
+    };
+
+    if budget <= resident {
+        return Err(PoleshiftError::DataError(format!(
+            "{} MB of memory cannot hold the database index ({} MB)",
+            budget / BYTES_PER_MB,
+            resident.div_ceil(BYTES_PER_MB)
+        )));
+    }
+    let cacheable = budget - resident;
+    if cacheable as f64 >= kmer_table as f64 * MIN_MMAP_RESIDENT_FRACTION {
+        plan.strategy = LoadStrategy::Mmap;
+        plan.relative_speed = MMAP_RELATIVE_SPEED;
+        plan.tradeoff =
+            "Database paged in from disk on demand; roughly 3x slower, more on a hard drive"
+                .to_string();
+    } else {
+        let chunks = kmer_table.div_ceil(cacheable);
+        plan.strategy = LoadStrategy::Chunked;
+        plan.chunks = chunks;
+        plan.chunk_bytes = Some(kmer_table.div_ceil(chunks));
+        plan.relative_speed = 1.0 / chunks as f64;
+        plan.tradeoff = format!(
+            "Reads scanned once per database chunk ({} chunks); roughly {}x slower",
+            chunks, chunks
+        );
+    }
+    plan.supported = backend == BackendKind::Sidecar;
+    Ok(plan)
+}
+
+/// The load strategy a classification against `database_id` would use under
+/// `memory_budget_mb` (default: the detected available memory) with `backend`, so the
+/// UI can show the expected speed before a run is started.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn plan_database_load<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    database_id: Option<String>,
+    memory_budget_mb: Option<u64>,
+    backend: Option<BackendKind>,
+) -> Result<StandardResponseNoFiles<DatabaseLoadPlan>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let resource_dir = resources_dir(&app_handle)?;
+        let database_id = database_id.unwrap_or_else(|| current_settings(&app_handle).database_id);
+        let config = KrakenConfig::for_database(&resource_dir, &database_id, Vec::new())?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: plan_load(&config, memory_budget_mb, backend.unwrap_or_default())?,
+        })
+    })
+    .await
+}
@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+
+use crate::io::complexity::{is_low_complexity, ComplexityOptions, ComplexityStats};
+use crate::io::merge::write_fastq_record;
+use crate::io::source::FastqSource;
+use crate::io::umi::{extract_umi, tag_header, UmiDeduplicator, UmiOptions, UmiStats};
+use crate::io::FastqRecord;
+use crate::krakenuniq::parse_fastq_files::parse_nanopore_header;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Counts reported by the pre-classification QC stage.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PreprocessingSummary {
+    pub umi: Option<UmiStats>,
+    pub low_complexity: Option<ComplexityStats>,
+    /// Simplex reads left out because their duplex offspring is among the reads
+    pub superseded_simplex_removed: Option<usize>,
+}
+
+/// Options for the pre-classification QC stage. The stage only runs when at least
+/// one step is enabled.
+#[derive(Debug, Default)]
+pub(crate) struct PreprocessingOptions {
+    pub umi: Option<UmiOptions>,
+    pub low_complexity: Option<ComplexityOptions>,
+    /// Leave out simplex reads whose duplex offspring is present
+    pub drop_superseded_simplex: bool,
+}
+
+impl PreprocessingOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.umi.is_some() || self.low_complexity.is_some() || self.drop_superseded_simplex
+    }
+}
+
+/// `(run_id, read name)` of every simplex read a duplex read in `inputs` was basecalled
+/// from; parents and offspring may be in different files of the run.
+fn superseded_simplex_reads(
+    inputs: &[String],
+) -> Result<HashSet<(String, String)>, PoleshiftError> {
+    let mut superseded = HashSet::new();
+    for input in inputs {
+        let mut source = FastqSource::open(&PathBuf::from(input))?;
+        while let Some(record) = source.read_record()? {
+            let header = parse_nanopore_header(&record.header);
+            for parent in header.duplex_parent_ids {
+                superseded.insert((header.run_id.clone(), parent));
+            }
+        }
+    }
+    Ok(superseded)
+}
+
+/// What the read filters of `filtered_reads` dropped.
+#[derive(Default)]
+struct FilterCounts {
+    superseded_removed: usize,
+    complexity: ComplexityStats,
+}
+
+/// Streams the reads of `inputs`, in order, to `visit`, leaving out those in
+/// `superseded` and low-complexity ones. When UMIs are enabled each read comes with
+/// its UMI, already extracted and tagged onto the header.
+fn filtered_reads(
+    inputs: &[String],
+    options: &PreprocessingOptions,
+    superseded: &HashSet<(String, String)>,
+    mut visit: impl FnMut(FastqRecord, Option<String>) -> Result<(), PoleshiftError>,
+) -> Result<FilterCounts, PoleshiftError> {
+    let mut counts = FilterCounts::default();
+    for input in inputs {
+        let mut source = FastqSource::open(&PathBuf::from(input))?;
+        while let Some(mut record) = source.read_record()? {
+            if !superseded.is_empty() {
+                let header = parse_nanopore_header(&record.header);
+                if superseded.contains(&(header.run_id, header.parent_read_id)) {
+                    counts.superseded_removed += 1;
+                    continue;
+                }
+            }
+            if let Some(complexity_options) = &options.low_complexity {
+                counts.complexity.reads_in += 1;
+                if is_low_complexity(&record.sequence, complexity_options) {
+                    counts.complexity.reads_removed += 1;
+                    counts.complexity.bases_removed += record.sequence.len() as u64;
+                    continue;
+                }
+            }
+
+            let umi = match &options.umi {
+                Some(umi_options) => {
+                    let umi = extract_umi(&mut record, umi_options);
+                    if let Some(ref value) = umi {
+                        tag_header(&mut record, value);
+                    }
+                    umi
+                }
+                None => None,
+            };
+            visit(record, umi)?;
+        }
+    }
+    Ok(counts)
+}
+
+/// Runs the enabled QC steps over all inputs and writes the surviving reads to a
+/// single gzipped FASTQ at `output`, which then replaces the inputs for
+/// classification and parsing.
+///
+/// Reads are streamed rather than held in memory; UMI deduplication reads the inputs
+/// twice, first to find the read kept for every UMI.
+pub(crate) fn preprocess_reads(
+    inputs: &[String],
+    output: &Path,
+    options: &PreprocessingOptions,
+) -> Result<PreprocessingSummary, PoleshiftError> {
+    let mut summary = PreprocessingSummary::default();
+
+    // 1) Find the simplex reads superseded by their duplex offspring
+    let superseded = if options.drop_superseded_simplex {
+        superseded_simplex_reads(inputs)?
+    } else {
+        HashSet::new()
+    };
+
+    // 2) With UMI-aware deduplication, pick the read kept for every UMI
+    let deduplicator = match &options.umi {
+        Some(umi_options) if umi_options.deduplicate => {
+            let mut deduplicator = UmiDeduplicator::default();
+            let mut index = 0;
+            filtered_reads(inputs, options, &superseded, |record, umi| {
+                if let Some(umi) = &umi {
+                    deduplicator.offer(index, &record, umi);
+                }
+                index += 1;
+                Ok(())
+            })?;
+            Some(deduplicator)
+        }
+        _ => None,
+    };
+
+    // 3) Write the surviving reads
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut writer = GzEncoder::new(BufWriter::new(File::create(output)?), Compression::fast());
+    let mut umi_stats = UmiStats::default();
+    let mut unique_umis = HashSet::new();
+    let mut index = 0;
+    let counts = filtered_reads(inputs, options, &superseded, |record, umi| {
+        let read = index;
+        index += 1;
+        umi_stats.reads_in += 1;
+        if let Some(umi) = umi {
+            umi_stats.reads_with_umi += 1;
+            if deduplicator
+                .as_ref()
+                .is_some_and(|deduplicator| !deduplicator.keeps(read, &umi))
+            {
+                umi_stats.duplicates_removed += 1;
+                return Ok(());
+            }
+            unique_umis.insert(umi);
+        }
+        write_fastq_record(&mut writer, &record)?;
+        Ok(())
+    })?;
+    writer.finish()?.flush()?;
+
+    if options.umi.is_some() {
+        umi_stats.unique_umis = unique_umis.len();
+        summary.umi = Some(umi_stats);
+    }
+    if options.low_complexity.is_some() {
+        summary.low_complexity = Some(counts.complexity);
+    }
+    if options.drop_superseded_simplex {
+        summary.superseded_simplex_removed = Some(counts.superseded_removed);
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::umi::UmiSource;
+    use crate::krakenuniq::parse_fastq_files::parse_fastq_files;
+
+    const UUID: &str = "00000000-0000-0000-0000-000000000000";
+
+    fn write_reads(path: &Path, names: &[&str]) {
+        let mut fastq = String::new();
+        for name in names {
+            fastq.push_str(&format!(
+                "@{} runid=run1 ch=1\nACGTACGT\n+\nIIIIIIII\n",
+                name
+            ));
+        }
+        std::fs::write(path, fastq).unwrap();
+    }
+
+    fn raw_read_count(path: &Path) -> usize {
+        let path = path.to_string_lossy().to_string();
+        let uuid = || UUID.to_string();
+        parse_fastq_files(&[path], uuid(), uuid(), "raw".to_string(), uuid())
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    fn drops_superseded_simplex_reads_before_classification() {
+        let dir = std::env::temp_dir().join(format!("poleshift-duplex-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // One duplex pair in the first file, its offspring and an unrelated read in
+        // the second
+        let (first, second) = (dir.join("a.fastq"), dir.join("b.fastq"));
+        write_reads(&first, &["template", "complement"]);
+        write_reads(&second, &["template;complement", "other"]);
+        let inputs = [&first, &second].map(|p| p.to_string_lossy().to_string());
+
+        let kept = dir.join("kept.fastq.gz");
+        let options = PreprocessingOptions {
+            drop_superseded_simplex: true,
+            ..PreprocessingOptions::default()
+        };
+        let summary = preprocess_reads(&inputs, &kept, &options).unwrap();
+        assert_eq!(summary.superseded_simplex_removed, Some(2));
+        // The classifier reads this file too, so both see the same two reads
+        assert_eq!(raw_read_count(&kept), 2);
+
+        let all = dir.join("all.fastq.gz");
+        preprocess_reads(&inputs, &all, &PreprocessingOptions::default()).unwrap();
+        assert_eq!(raw_read_count(&all), 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_best_read_of_each_umi() {
+        let dir = std::env::temp_dir().join(format!("poleshift-umi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("reads.fastq");
+        std::fs::write(
+            &input,
+            "@a umi=AAAA\nACGT\n+\n####\n\
+             @b umi=AAAA\nACGT\n+\nIIII\n\
+             @c umi=CCCC\nACGT\n+\nIIII\n\
+             @d\nACGT\n+\nIIII\n",
+        )
+        .unwrap();
+        let inputs = [input.to_string_lossy().to_string()];
+
+        let output = dir.join("deduplicated.fastq.gz");
+        let options = PreprocessingOptions {
+            umi: Some(UmiOptions {
+                source: UmiSource::HeaderTag {
+                    key: "umi".to_string(),
+                },
+                expected_length: None,
+                deduplicate: true,
+            }),
+            ..PreprocessingOptions::default()
+        };
+        let summary = preprocess_reads(&inputs, &output, &options).unwrap();
+        let umi = summary.umi.unwrap();
+        assert_eq!(umi.reads_in, 4);
+        assert_eq!(umi.reads_with_umi, 3);
+        assert_eq!(umi.unique_umis, 2);
+        assert_eq!(umi.duplicates_removed, 1);
+
+        let mut names = Vec::new();
+        let mut source = FastqSource::open(&output).unwrap();
+        while let Some(record) = source.read_record().unwrap() {
+            names.push(record.header.split_whitespace().next().unwrap().to_string());
+        }
+        assert_eq!(names, ["@b", "@c", "@d"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, Window};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::handle_sequence_data::{
+    run_sequence_pipeline, SequenceDataReport, SequenceRunArgs,
+};
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Most samples classified at once; each run already uses every core.
+const MAX_PARALLEL: usize = 4;
+/// How often the queue checks whether it was cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct QueueOptions {
+    /// Samples classified at the same time
+    pub max_parallel: usize,
+    /// Job ID of the queue itself; cancelling it stops every job in it
+    pub queue_id: Option<String>,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        QueueOptions {
+            max_parallel: 1,
+            queue_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuedJobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// Where one sample of the queue stands.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedJob {
+    /// Position in the submitted list
+    pub index: usize,
+    /// The job's ID, for `cancel_job` and its `job://{job_id}/progress` events
+    pub job_id: String,
+    pub processed_data_id: String,
+    pub status: QueuedJobStatus,
+    pub error: Option<String>,
+    pub elapsed_secs: Option<f64>,
+}
+
+/// Queue-level counts, reported whenever a job starts or ends.
+#[derive(Debug, Clone, Copy)]
+struct QueueProgress {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    cancelled: usize,
+    running: usize,
+}
+
+impl QueueProgress {
+    fn percentage(&self) -> u8 {
+        let ended = self.succeeded + self.failed + self.cancelled;
+        (ended * 100 / self.total.max(1)) as u8
+    }
+
+    fn status_message(&self) -> String {
+        format!(
+            "{} of {} samples done, {} running, {} failed, {} cancelled",
+            self.succeeded, self.total, self.running, self.failed, self.cancelled
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuedJobResult {
+    #[serde(flatten)]
+    pub job: QueuedJob,
+    /// The job's result, when it succeeded
+    pub report: Option<SequenceDataReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueReport {
+    pub queue_id: String,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// In submission order
+    pub jobs: Vec<QueuedJobResult>,
+}
+
+/// Shared state of a running queue.
+struct QueueState {
+    queue_id: String,
+    jobs: Mutex<Vec<QueuedJob>>,
+    reports: Mutex<Vec<Option<SequenceDataReport>>>,
+    next: AtomicUsize,
+    finished: AtomicUsize,
+}
+
+impl QueueState {
+    fn update(&self, index: usize, change: impl FnOnce(&mut QueuedJob)) -> QueueProgress {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        change(&mut jobs[index]);
+        let count = |status| jobs.iter().filter(|j| j.status == status).count();
+        QueueProgress {
+            total: jobs.len(),
+            succeeded: count(QueuedJobStatus::Succeeded),
+            failed: count(QueuedJobStatus::Failed),
+            cancelled: count(QueuedJobStatus::Cancelled),
+            running: count(QueuedJobStatus::Running),
+        }
+    }
+}
+
+/// Reports `progress` on the queue's own job. A failed emit is only logged; it must
+/// not fail the samples.
+fn report_queue_progress<R: Runtime>(
+    window: &Window<R>,
+    queue: &JobHandle,
+    progress: QueueProgress,
+) {
+    let reported = queue.report_progress(
+        window,
+        progress.percentage(),
+        &progress.status_message(),
+        "processing",
+    );
+    if let Err(e) = reported {
+        println!("Could not emit queue progress: {}", e);
+    }
+}
+
+/// Takes jobs off the queue until none are left, running each through the whole
+/// sequence pipeline. A failed job is recorded and the worker moves on.
+fn queue_worker<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    window: &Window<R>,
+    state: &QueueState,
+    args: &Mutex<Vec<Option<SequenceRunArgs>>>,
+    handles: &[JobHandle],
+    queue: &JobHandle,
+) {
+    loop {
+        let index = state.next.fetch_add(1, Ordering::SeqCst);
+        let Some(job) = handles.get(index) else {
+            return;
+        };
+        let run_args = args.lock().unwrap_or_else(|e| e.into_inner())[index].take();
+
+        // 1) Jobs cancelled while they waited are skipped
+        let progress = match run_args {
+            Some(run_args) if !job.is_cancelled() && !queue.is_cancelled() => {
+                report_queue_progress(
+                    window,
+                    queue,
+                    state.update(index, |j| j.status = QueuedJobStatus::Running),
+                );
+
+                // 2) Run the sample; the pipeline emits the job's own progress
+                let started = Instant::now();
+                let result = run_sequence_pipeline(app_handle.clone(), run_args, job.clone());
+                let elapsed_secs = Some(started.elapsed().as_secs_f64());
+                let status = match &result {
+                    Ok(_) => QueuedJobStatus::Succeeded,
+                    Err(PoleshiftError::Cancelled(_)) => QueuedJobStatus::Cancelled,
+                    Err(_) => QueuedJobStatus::Failed,
+                };
+                let error = result.as_ref().err().map(|e| {
+                    println!("Queued job {} failed: {}", job.id(), e);
+                    e.to_string()
+                });
+                app_handle
+                    .state::<JobManager>()
+                    .finish(job.id(), result.as_ref().err());
+                if let Ok(response) = result {
+                    state.reports.lock().unwrap_or_else(|e| e.into_inner())[index] =
+                        Some(response.report);
+                }
+                state.update(index, |j| {
+                    j.status = status;
+                    j.error = error;
+                    j.elapsed_secs = elapsed_secs;
+                })
+            }
+            _ => {
+                let cancelled = PoleshiftError::Cancelled(job.id().to_string());
+                app_handle
+                    .state::<JobManager>()
+                    .finish(job.id(), Some(&cancelled));
+                state.update(index, |j| j.status = QueuedJobStatus::Cancelled)
+            }
+        };
+        state.finished.fetch_add(1, Ordering::SeqCst);
+        report_queue_progress(window, queue, progress);
+    }
+}
+
+fn run_queue<R: Runtime>(
+    app_handle: AppHandle<R>,
+    jobs: Vec<SequenceRunArgs>,
+    options: QueueOptions,
+    queue: JobHandle,
+) -> Result<StandardResponseNoFiles<QueueReport>, PoleshiftError> {
+    let window = app_handle
+        .get_window("main")
+        .ok_or(PoleshiftError::WindowNotFound)?;
+    let manager = app_handle.state::<JobManager>();
+
+    // 1) Register every job up front, so pending ones are listed and can be cancelled
+    let handles = manager.start_all(
+        "classification",
+        jobs.iter().map(|args| args.job_id.clone()),
+    )?;
+    let state = QueueState {
+        queue_id: queue.id().to_string(),
+        jobs: Mutex::new(
+            jobs.iter()
+                .zip(&handles)
+                .enumerate()
+                .map(|(index, (args, job))| QueuedJob {
+                    index,
+                    job_id: job.id().to_string(),
+                    processed_data_id: args.processed_data_id.clone(),
+                    status: QueuedJobStatus::Pending,
+                    error: None,
+                    elapsed_secs: None,
+                })
+                .collect(),
+        ),
+        reports: Mutex::new(jobs.iter().map(|_| None).collect()),
+        next: AtomicUsize::new(0),
+        finished: AtomicUsize::new(0),
+    };
+    let total = jobs.len();
+    let args = Mutex::new(jobs.into_iter().map(Some).collect::<Vec<_>>());
+    queue.set_stage("classifying");
+
+    // 2) Run the workers; this thread passes a cancelled queue on to its jobs
+    let workers = options.max_parallel.min(total);
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| queue_worker(&app_handle, &window, &state, &args, &handles, &queue));
+        }
+        while state.finished.load(Ordering::SeqCst) < total {
+            if queue.is_cancelled() {
+                for job in &handles {
+                    manager.cancel(job.id());
+                }
+            }
+            std::thread::sleep(CANCEL_POLL_INTERVAL);
+        }
+    });
+
+    // 3) Collect the outcomes in submission order
+    let queued = state.jobs.into_inner().unwrap_or_else(|e| e.into_inner());
+    let reports = state
+        .reports
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner());
+    let count = |status| queued.iter().filter(|j| j.status == status).count();
+    let report = QueueReport {
+        queue_id: state.queue_id,
+        succeeded: count(QueuedJobStatus::Succeeded),
+        failed: count(QueuedJobStatus::Failed),
+        cancelled: count(QueuedJobStatus::Cancelled),
+        jobs: queued
+            .into_iter()
+            .zip(reports)
+            .map(|(job, report)| QueuedJobResult { job, report })
+            .collect(),
+    };
+    Ok(StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report,
+    })
+}
+
+/// Classifies many samples one after another (or `max_parallel` at a time), so a
+/// batch can be left to run unattended. Each entry of `jobs` takes the same arguments
+/// as `handle_sequence_data` and runs as its own job, emitting its own
+/// `job://{job_id}/progress` events; the queue reports on its own job's channel
+/// whenever a job starts or ends.
+///
+/// A failed job does not stop the queue: its error is recorded and the next job starts.
+/// `cancel_job` with a job's ID cancels that job only; with `queue_id` it cancels
+/// everything still running or waiting. The call returns once every job has ended,
+/// with each job's status and result.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn queue_classification_jobs<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    jobs: Vec<SequenceRunArgs>,
+    options: Option<QueueOptions>,
+) -> Result<StandardResponseNoFiles<QueueReport>, PoleshiftError> {
+    if jobs.is_empty() {
+        return Err(PoleshiftError::NoFiles);
+    }
+    let options = options.unwrap_or_default();
+    if options.max_parallel == 0 || options.max_parallel > MAX_PARALLEL {
+        return Err(PoleshiftError::InvalidInput {
+            field: "max_parallel".to_string(),
+            reason: format!("must be between 1 and {}", MAX_PARALLEL),
+        });
+    }
+
+    let trace_handle = app_handle.clone();
+    let manager = trace_handle.state::<JobManager>();
+    let queue = manager.start("classification_queue", options.queue_id.clone())?;
+    let worker_queue = queue.clone();
+    let result = traced(invocation, Some(queue.id()), async move {
+        tauri::async_runtime::spawn_blocking(move || {
+            run_queue(app_handle, jobs, options, worker_queue)
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Classification queue task failed: {}", e)))?
+    })
+    .await;
+    manager.finish(queue.id(), result.as_ref().err());
+    result
+}
@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::io::packed::PackedSequence;
+use crate::krakenuniq::{RawSequence, RawSequenceSummary};
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Builds the summary returned across the Tauri boundary in place of the full reads.
+pub fn summarize_raw_sequences(
+    sequences: &[RawSequence],
+    store_path: Option<String>,
+) -> RawSequenceSummary {
+    let read_count = sequences.len();
+    let total_bases: u64 = sequences.iter().map(|s| s.sequence.len() as u64).sum();
+    let packed_bytes: u64 = sequences
+        .iter()
+        .map(|s| s.sequence.packed_size() as u64)
+        .sum();
+
+    let (mean_length, mean_quality_median) = if read_count > 0 {
+        (
+            total_bases as f64 / read_count as f64,
+            sequences.iter().map(|s| s.quality_median).sum::<f64>() / read_count as f64,
+        )
+    } else {
+        (0.0, 0.0)
+    };
+
+    RawSequenceSummary {
+        read_count,
+        total_bases,
+        packed_bytes,
+        mean_length,
+        mean_quality_median,
+        store_path,
+    }
+}
+
+/// Writes all raw sequences into a SQLite sidecar at `path`, replacing any previous file.
+pub fn persist_raw_sequences(path: &Path, sequences: &[RawSequence]) -> Result<(), PoleshiftError> {
+    let mut conn = create_store(path)?;
+    write_raw_sequences(&mut conn, sequences)
+}
+
+/// Creates a fresh SQLite file at `path`, removing any previous one.
+pub(crate) fn create_store(path: &Path) -> Result<Connection, PoleshiftError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Connection::open(path).map_err(|e| PoleshiftError::IoError(e.to_string()))
+}
+
+/// Creates the `raw_sequences` table in `conn` and fills it.
+pub(crate) fn write_raw_sequences(
+    conn: &mut Connection,
+    sequences: &[RawSequence],
+) -> Result<(), PoleshiftError> {
+    conn.execute_batch(
+        "CREATE TABLE raw_sequences (
+            id TEXT PRIMARY KEY,
+            feature_id TEXT NOT NULL,
+            sequence TEXT NOT NULL,
+            quality TEXT NOT NULL,
+            quality_median REAL NOT NULL,
+            run_id TEXT,
+            read INTEGER,
+            ch INTEGER,
+            start_time TEXT,
+            sample_id_fastq TEXT,
+            barcode TEXT,
+            barcode_alias TEXT,
+            parent_read_id TEXT,
+            basecall_model_version_id TEXT,
+            flow_cell_id TEXT,
+            protocol_group_id TEXT,
+            umi TEXT,
+            duplex_parent_ids TEXT,
+            duplex_child_id TEXT,
+            user_id TEXT,
+            org_id TEXT,
+            sample_id TEXT,
+            raw_data_id TEXT
+        );",
+    )
+    .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    {
+        let mut stmt = tx
+            .prepare(
+                "INSERT INTO raw_sequences VALUES
+                 (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12,
+                  ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23)",
+            )
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+        for seq in sequences {
+            stmt.execute(params![
+                seq.id,
+                seq.feature_id,
+                seq.sequence.unpack(),
+                seq.quality,
+                seq.quality_median,
+                seq.run_id,
+                seq.read,
+                seq.ch,
+                seq.start_time,
+                seq.sample_id_fastq,
+                seq.barcode,
+                seq.barcode_alias,
+                seq.parent_read_id,
+                seq.basecall_model_version_id,
+                seq.flow_cell_id,
+                seq.protocol_group_id,
+                seq.umi,
+                seq.duplex_parent_ids.join(";"),
+                seq.duplex_child_id,
+                seq.user_id,
+                seq.org_id,
+                seq.sample_id,
+                seq.raw_data_id,
+            ])
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        }
+    }
+    tx.commit()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Reads back one row of the `raw_sequences` table, in column order.
+pub(crate) fn raw_sequence_from_row(row: &rusqlite::Row) -> rusqlite::Result<RawSequence> {
+    let sequence: String = row.get(2)?;
+    let duplex_parent_ids: String = row.get::<_, Option<String>>(17)?.unwrap_or_default();
+    Ok(RawSequence {
+        id: row.get(0)?,
+        feature_id: row.get(1)?,
+        sequence: PackedSequence::pack(&sequence),
+        quality: row.get(3)?,
+        quality_median: row.get(4)?,
+        run_id: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+        read: row.get::<_, Option<i32>>(6)?.unwrap_or_default(),
+        ch: row.get::<_, Option<i32>>(7)?.unwrap_or_default(),
+        start_time: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+        sample_id_fastq: row.get::<_, Option<String>>(9)?.unwrap_or_default(),
+        barcode: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+        barcode_alias: row.get::<_, Option<String>>(11)?.unwrap_or_default(),
+        parent_read_id: row.get::<_, Option<String>>(12)?.unwrap_or_default(),
+        basecall_model_version_id: row.get::<_, Option<String>>(13)?.unwrap_or_default(),
+        flow_cell_id: row.get::<_, Option<String>>(14)?.unwrap_or_default(),
+        protocol_group_id: row.get::<_, Option<String>>(15)?.unwrap_or_default(),
+        umi: row.get(16)?,
+        duplex_parent_ids: duplex_parent_ids
+            .split(';')
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_string())
+            .collect(),
+        duplex_child_id: row.get(18)?,
+        user_id: row.get::<_, Option<String>>(19)?.unwrap_or_default(),
+        org_id: row.get::<_, Option<String>>(20)?.unwrap_or_default(),
+        sample_id: row.get::<_, Option<String>>(21)?.unwrap_or_default(),
+        raw_data_id: row.get::<_, Option<String>>(22)?.unwrap_or_default(),
+    })
+}
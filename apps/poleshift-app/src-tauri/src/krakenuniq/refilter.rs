@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::params_from_iter;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+use uuid::Uuid;
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{
+    load_report, open_store, output_store_path, require_confidence,
+};
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::krakenuniq::{e_score, EScoreFormula};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::settings::current_settings;
+
+/// Minimum values a taxon needs to stay in the report. Unset thresholds do not filter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportThresholds {
+    pub min_e_score: Option<f64>,
+    pub min_percentage: Option<f32>,
+    pub min_kmers: Option<u64>,
+    /// Classified reads below this per-read confidence are left out before counting
+    pub min_confidence: Option<f64>,
+    /// How the re-derived rows are scored for `min_e_score`
+    pub e_score_formula: EScoreFormula,
+}
+
+impl ReportThresholds {
+    /// Unclassified (0) and root (1) always pass, so every read keeps a home.
+    fn passes(&self, row: &ProcessedKrakenUniqReport) -> bool {
+        if row.tax_id <= 1 {
+            return true;
+        }
+        self.min_e_score.is_none_or(|min| row.e_score >= min)
+            && self.min_percentage.is_none_or(|min| row.percentage >= min)
+            && self.min_kmers.is_none_or(|min| row.kmers >= min)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefilteredReport {
+    pub rows: Vec<ProcessedKrakenUniqReport>,
+    /// Taxa of the stored report that did not make it into `rows`
+    pub taxa_removed: usize,
+    /// Reads moved from a removed taxon up to its nearest remaining ancestor
+    pub reads_reassigned: u64,
+    /// Reads left out for falling below `min_confidence`
+    pub reads_below_confidence: u64,
+}
+
+/// Reads per tax ID in the stored per-read output, counting only reads at or above
+/// `min_confidence`, and the number of reads that were not. Taxa whose reads all fell
+/// below it are kept with a count of 0, so they do not fall back to the report.
+fn reads_per_tax_id(
+    store: &Path,
+    min_confidence: Option<f64>,
+) -> Result<(HashMap<u64, u64>, u64), PoleshiftError> {
+    let conn = open_store(store)?;
+    require_confidence(&conn, min_confidence)?;
+    let sql = match min_confidence {
+        // Unclassified reads have no confidence and always count
+        Some(_) => {
+            "SELECT tax_id, SUM(confidence IS NULL OR confidence >= ?1), COUNT(*)
+             FROM stdout GROUP BY tax_id"
+        }
+        None => "SELECT tax_id, COUNT(*), COUNT(*) FROM stdout GROUP BY tax_id",
+    };
+    let mut stmt = conn
+        .prepare(sql)
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let rows = stmt
+        .query_map(params_from_iter(min_confidence), |row| {
+            Ok((
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, i64>(2)? as u64,
+            ))
+        })
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    let below: u64 = rows.iter().map(|&(_, kept, all)| all - kept).sum();
+    let counts = rows
+        .into_iter()
+        .map(|(tax_id, kept, _)| (tax_id, kept))
+        .collect();
+    Ok((counts, below))
+}
+
+/// Rebuilds `report` keeping only taxa that meet `thresholds`. Reads assigned to a
+/// removed taxon move to its nearest remaining ancestor, surviving descendants are
+/// re-parented the same way, and clade counts, percentages and e-scores are
+/// recomputed from the per-read counts in `direct_reads`.
+pub(crate) fn refilter(
+    report: &[ProcessedKrakenUniqReport],
+    direct_reads: &HashMap<u64, u64>,
+    thresholds: &ReportThresholds,
+) -> RefilteredReport {
+    let index_by_id: HashMap<String, usize> = report
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row.id.clone(), i))
+        .collect();
+    let parent_of = |i: usize| {
+        report[i]
+            .parent_id
+            .and_then(|p| index_by_id.get(&p.to_string()).copied())
+    };
+    let passing: Vec<bool> = report.iter().map(|row| thresholds.passes(row)).collect();
+    let nearest_passing = |i: usize| {
+        let mut current = Some(i);
+        while let Some(c) = current {
+            if passing[c] {
+                return Some(c);
+            }
+            current = parent_of(c);
+        }
+        None
+    };
+
+    // 1) Direct reads per surviving row. Without per-read output for a taxon (e.g. the
+    // unclassified bin when only classified reads were kept) fall back to the report
+    let mut tax_reads = vec![0u64; report.len()];
+    let mut reads_reassigned = 0u64;
+    for (i, row) in report.iter().enumerate() {
+        let reads = direct_reads
+            .get(&row.tax_id)
+            .copied()
+            .unwrap_or(row.tax_reads);
+        if let Some(target) = nearest_passing(i) {
+            tax_reads[target] += reads;
+            if target != i {
+                reads_reassigned += reads;
+            }
+        }
+    }
+
+    // 2) New parents, then clade reads by walking each row's new ancestry
+    let new_parent: Vec<Option<usize>> = (0..report.len())
+        .map(|i| parent_of(i).and_then(nearest_passing))
+        .collect();
+    let mut clade_reads = vec![0u64; report.len()];
+    for i in (0..report.len()).filter(|&i| passing[i]) {
+        let mut current = Some(i);
+        while let Some(c) = current {
+            clade_reads[c] += tax_reads[i];
+            current = new_parent[c];
+        }
+    }
+    let keep: Vec<bool> = (0..report.len())
+        .map(|i| passing[i] && clade_reads[i] > 0)
+        .collect();
+
+    let total: u64 = (0..report.len())
+        .filter(|&i| keep[i] && new_parent[i].is_none())
+        .map(|i| clade_reads[i])
+        .sum();
+    let mut children: HashMap<usize, Vec<Uuid>> = HashMap::new();
+    for i in (0..report.len()).filter(|&i| keep[i]) {
+        if let (Some(parent), Ok(id)) = (new_parent[i], Uuid::parse_str(&report[i].id)) {
+            children.entry(parent).or_default().push(id);
+        }
+    }
+
+    // 3) Assemble the surviving rows in report order
+    let rows: Vec<ProcessedKrakenUniqReport> = report
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| keep[i])
+        .map(|(i, row)| ProcessedKrakenUniqReport {
+            id: row.id.clone(),
+            percentage: if total > 0 {
+                (clade_reads[i] as f64 * 100.0 / total as f64) as f32
+            } else {
+                0.0
+            },
+            reads: clade_reads[i],
+            tax_reads: tax_reads[i],
+            kmers: row.kmers,
+            duplication: row.duplication,
+            tax_name: row.tax_name.clone(),
+            parent_id: new_parent[i].and_then(|p| Uuid::parse_str(&report[p].id).ok()),
+            children_ids: children.remove(&i).unwrap_or_default(),
+            processed_data_id: row.processed_data_id.clone(),
+            user_id: row.user_id.clone(),
+            org_id: row.org_id.clone(),
+            sample_id: row.sample_id.clone(),
+            tax_id: row.tax_id,
+            rank: row.rank.clone(),
+            coverage: row.coverage,
+            e_score: e_score(
+                thresholds.e_score_formula,
+                tax_reads[i] as f64,
+                row.kmers as f64,
+                row.coverage,
+            ),
+            lineage: row.lineage.clone(),
+            lineage_ranks: row.lineage_ranks.clone(),
+        })
+        .collect();
+
+    RefilteredReport {
+        taxa_removed: report.len() - rows.len(),
+        reads_reassigned,
+        reads_below_confidence: 0,
+        rows,
+    }
+}
+
+/// Re-derives the report of a stored classification at new thresholds from its
+/// per-read output, without running the classifier again. The stored report is left
+/// untouched, so the UI can call this on every move of a threshold slider. With
+/// `min_confidence` low-confidence reads are dropped from the counts first. Without
+/// `thresholds` the `report_thresholds` setting applies.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn refilter_report<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    thresholds: Option<ReportThresholds>,
+) -> Result<StandardResponseNoFiles<RefilteredReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let thresholds =
+            thresholds.unwrap_or_else(|| current_settings(&app_handle).report_thresholds);
+        let store = output_store_path(&app_handle, &processed_data_id)?;
+        let report = load_report(&store)?;
+        let (direct_reads, below) = reads_per_tax_id(&store, thresholds.min_confidence)?;
+
+        let mut refiltered = refilter(&report, &direct_reads, &thresholds);
+        refiltered.reads_below_confidence = below;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: refiltered,
+        })
+    })
+    .await
+}
@@ -0,0 +1,326 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use krakenuniq_rs::{OutputLine, ReportRow};
+use serde::{Deserialize, Serialize};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::taxdb::canonical_rank;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Lines looked at when guessing the layout of a report file.
+const DETECT_LINES: usize = 50;
+
+/// Layout of a taxonomic report file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// `%, reads, taxReads, kmers, dup, cov, taxID, rank, taxName`
+    KrakenUniq,
+    /// Kraken, Kraken2 and `centrifuge-kreport`: `%, clade reads, direct reads, rank
+    /// code, tax ID, name`, with `minimizers, distinct minimizers` before the rank code
+    /// when Kraken2 ran with `--report-minimizer-data`
+    Kraken,
+    /// Bracken abundance estimates: `name, taxonomy_id, taxonomy_lvl,
+    /// kraken_assigned_reads, added_reads, new_est_reads, fraction_total_reads`
+    Bracken,
+}
+
+/// A report file read into KrakenUniq's row shape.
+pub(crate) struct ParsedReport {
+    pub format: ReportFormat,
+    pub rows: Vec<ReportRow>,
+    /// Lines that were neither comments, headers nor rows of `format`
+    pub skipped_lines: usize,
+}
+
+/// Rank of a Kraken report rank code. Letters are the standard ranks; codes with a
+/// number (`G1`, `S2`) are unranked levels below them.
+fn rank_from_code(code: &str) -> String {
+    let code = code.trim();
+    if code.len() > 1 && code[1..].chars().all(|c| c.is_ascii_digit()) {
+        "no rank".to_string()
+    } else {
+        canonical_rank(code)
+    }
+}
+
+/// Indentation depth of a report name, two spaces per level.
+fn name_depth(name: &str) -> usize {
+    (name.len() - name.trim_start_matches(' ').len()) / 2
+}
+
+/// Appends `row` to a report read top-down, linking it to the last row above its
+/// `depth`. `ancestors` holds (depth, index into rows) of the current chain.
+fn push_indented(
+    rows: &mut Vec<ReportRow>,
+    ancestors: &mut Vec<(usize, usize)>,
+    mut row: ReportRow,
+) {
+    while ancestors.last().is_some_and(|&(d, _)| d >= row.depth) {
+        ancestors.pop();
+    }
+    if let Some(&(_, parent)) = ancestors.last() {
+        row.parent_tax_id = Some(rows[parent].tax_id);
+        rows[parent].children_tax_ids.push(row.tax_id);
+    }
+    ancestors.push((row.depth, rows.len()));
+    rows.push(row);
+}
+
+/// The layout a report line fits, if any. Column headers are recognised too.
+fn line_format(fields: &[&str]) -> Option<ReportFormat> {
+    let number = |i: usize| {
+        fields
+            .get(i)
+            .is_some_and(|f| f.trim().parse::<f64>().is_ok())
+    };
+    let tax_id = |i: usize| {
+        fields
+            .get(i)
+            .is_some_and(|f| f.trim().parse::<u32>().is_ok())
+    };
+    match fields.len() {
+        9 if fields[0].trim() == "%" || (number(0) && tax_id(6)) => Some(ReportFormat::KrakenUniq),
+        7 if fields[1].trim() == "taxonomy_id" || (tax_id(1) && number(6)) => {
+            Some(ReportFormat::Bracken)
+        }
+        6 if number(0) && tax_id(4) => Some(ReportFormat::Kraken),
+        8 if number(0) && tax_id(6) => Some(ReportFormat::Kraken),
+        _ => None,
+    }
+}
+
+/// True for the column header line of `format`, which is skipped without a count.
+fn is_header(format: ReportFormat, fields: &[&str]) -> bool {
+    match format {
+        ReportFormat::KrakenUniq | ReportFormat::Kraken => fields[0].trim().parse::<f64>().is_err(),
+        ReportFormat::Bracken => fields.get(1).is_some_and(|f| f.trim() == "taxonomy_id"),
+    }
+}
+
+/// Parses one line of `format` into a row, or `None` if it does not fit.
+fn parse_line(format: ReportFormat, fields: &[&str]) -> Option<ReportRow> {
+    let number = |i: usize| fields[i].trim().parse::<f64>().ok();
+    let row =
+        |pct: f64, reads: f64, tax_reads: f64, tax_id: u32, rank: String, name: &str| ReportRow {
+            pct: pct as f32,
+            reads: reads as u64,
+            tax_reads: tax_reads as u64,
+            kmers: 0,
+            dup: 0.0,
+            cov: 0.0,
+            tax_id,
+            rank,
+            tax_name: name.trim().to_string(),
+            depth: name_depth(name),
+            parent_tax_id: None,
+            children_tax_ids: Vec::new(),
+        };
+    match (format, fields.len()) {
+        (ReportFormat::KrakenUniq, 9) => {
+            let tax_id = fields[6].trim().parse().ok()?;
+            let mut parsed = row(
+                number(0)?,
+                number(1)?,
+                number(2)?,
+                tax_id,
+                fields[7].trim().to_string(),
+                fields[8],
+            );
+            parsed.kmers = number(3)? as u64;
+            parsed.dup = number(4).unwrap_or(0.0);
+            parsed.cov = number(5).unwrap_or(0.0);
+            Some(parsed)
+        }
+        // Kraken2 counts minimizers rather than unique k-mers: `kmers` holds the
+        // distinct minimizers and `dup` the minimizers per distinct one
+        (ReportFormat::Kraken, 6 | 8) => {
+            let rank_at = if fields.len() == 8 { 5 } else { 3 };
+            let tax_id = fields[rank_at + 1].trim().parse().ok()?;
+            let mut parsed = row(
+                number(0)?,
+                number(1)?,
+                number(2)?,
+                tax_id,
+                rank_from_code(fields[rank_at]),
+                fields[rank_at + 2],
+            );
+            if rank_at == 5 {
+                let (minimizers, distinct) = (number(3)?, number(4)?);
+                parsed.kmers = distinct as u64;
+                if distinct > 0.0 {
+                    parsed.dup = minimizers / distinct;
+                }
+            }
+            Some(parsed)
+        }
+        // Bracken lists the taxa of one level, flat; reads are the re-estimated ones
+        (ReportFormat::Bracken, 7) => {
+            let tax_id = fields[1].trim().parse().ok()?;
+            let reads = number(5)?;
+            Some(row(
+                number(6)? * 100.0,
+                reads,
+                reads,
+                tax_id,
+                rank_from_code(fields[2]),
+                fields[0].trim(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Guesses the layout of the report at `path` from its first rows.
+pub(crate) fn detect_report_format(path: &Path) -> Result<ReportFormat, PoleshiftError> {
+    let reader = BufReader::new(File::open(path)?);
+    for line in reader.lines().take(DETECT_LINES) {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let Some(format) = line_format(&fields) {
+            return Ok(format);
+        }
+    }
+    Err(PoleshiftError::DataError(format!(
+        "{} is not a KrakenUniq, Kraken or Bracken report",
+        path.display()
+    )))
+}
+
+/// Parses the report at `path` as `format`. Trees are rebuilt from the two-space
+/// indentation of the names; Bracken rows have no tree and are all top-level.
+pub(crate) fn parse_report_as(
+    path: &Path,
+    format: ReportFormat,
+) -> Result<ParsedReport, PoleshiftError> {
+    let mut rows: Vec<ReportRow> = Vec::new();
+    let mut ancestors: Vec<(usize, usize)> = Vec::new();
+    let mut skipped_lines = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        match parse_line(format, &fields) {
+            Some(row) => push_indented(&mut rows, &mut ancestors, row),
+            None if is_header(format, &fields) => {}
+            None => skipped_lines += 1,
+        }
+    }
+    if skipped_lines > 0 {
+        println!(
+            "Skipped {} lines of {} that are not {:?} report rows",
+            skipped_lines,
+            path.display(),
+            format
+        );
+    }
+    Ok(ParsedReport {
+        format,
+        rows,
+        skipped_lines,
+    })
+}
+
+/// Detects the layout of the report at `path` and parses it.
+pub(crate) fn parse_report(path: &Path) -> Result<ParsedReport, PoleshiftError> {
+    parse_report_as(path, detect_report_format(path)?)
+}
+
+/// Parses a KrakenUniq report, as written by `krakenuniq --report-file`.
+pub(crate) fn parse_kraken_uniq_report(path: &Path) -> Result<Vec<ReportRow>, PoleshiftError> {
+    Ok(parse_report_as(path, ReportFormat::KrakenUniq)?.rows)
+}
+
+/// Parses a Kraken-style report, as written by Kraken2 or `centrifuge-kreport`.
+pub(crate) fn parse_kraken_report(path: &Path) -> Result<Vec<ReportRow>, PoleshiftError> {
+    Ok(parse_report_as(path, ReportFormat::Kraken)?.rows)
+}
+
+/// Parses KrakenUniq per-read output: `C/U, read ID, tax ID, length, hit list`.
+/// Paired lengths (`150|148`) are added up. Kraken2 writes the same format.
+pub(crate) fn parse_kraken_uniq_output(path: &Path) -> Result<Vec<OutputLine>, PoleshiftError> {
+    let mut lines = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.splitn(5, '\t').collect();
+        if fields.len() < 4 {
+            continue;
+        }
+        let tax_id = fields[2].trim().parse().map_err(|_| {
+            PoleshiftError::DataError(format!("Bad tax ID in classifier output: {}", line))
+        })?;
+        lines.push(OutputLine {
+            status: fields[0].chars().next().unwrap_or('U'),
+            read_id: fields[1].to_string(),
+            tax_id,
+            length: fields[3]
+                .split('|')
+                .filter_map(|l| l.trim().parse::<usize>().ok())
+                .sum(),
+            hitlist: fields.get(4).map(|h| h.to_string()).unwrap_or_default(),
+        });
+    }
+    Ok(lines)
+}
+
+/// What `inspect_report_file` found in a report.
+#[derive(Debug, Serialize)]
+pub struct ReportFileSummary {
+    pub file_path: String,
+    pub format: ReportFormat,
+    pub taxa: usize,
+    /// Reads of the top-level rows, i.e. all reads the report accounts for
+    pub total_reads: u64,
+    /// Ranks present, in order of first appearance
+    pub ranks: Vec<String>,
+    pub skipped_lines: usize,
+}
+
+/// Detects whether a report file generated outside poleshift is a KrakenUniq, Kraken
+/// (Kraken 1, Kraken2, `centrifuge-kreport`) or Bracken report and summarises what it
+/// holds, so the UI can confirm the file before importing it.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn inspect_report_file(
+    invocation: Invocation,
+    file_path: String,
+) -> Result<StandardResponseNoFiles<ReportFileSummary>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let report = tauri::async_runtime::spawn_blocking(move || {
+            let parsed = parse_report(Path::new(&file_path))?;
+            let mut ranks: Vec<String> = Vec::new();
+            for row in &parsed.rows {
+                if !ranks.contains(&row.rank) {
+                    ranks.push(row.rank.clone());
+                }
+            }
+            Ok::<_, PoleshiftError>(ReportFileSummary {
+                format: parsed.format,
+                taxa: parsed.rows.len(),
+                total_reads: parsed
+                    .rows
+                    .iter()
+                    .filter(|row| row.parent_tax_id.is_none())
+                    .map(|row| row.reads)
+                    .sum(),
+                ranks,
+                skipped_lines: parsed.skipped_lines,
+                file_path,
+            })
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Report inspection task failed: {}", e)))??;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
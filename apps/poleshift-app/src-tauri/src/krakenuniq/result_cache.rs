@@ -0,0 +1,263 @@
+use std::cmp::Reverse;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use krakenuniq_rs::ClassificationResults;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::backend::database_files;
+use crate::krakenuniq::checkpoint::{fingerprint, Checkpoint};
+use crate::krakenuniq::options::ClassificationOptions;
+use crate::poleshift_common::types::{KrakenConfig, PoleshiftError, StandardResponseNoFiles};
+
+/// Bumped whenever what goes into a key or an entry changes, so old entries miss.
+const CACHE_KEY_VERSION: &str = "1";
+/// Options that change how a run is carried out but not what it finds.
+const IGNORED_OPTIONS: [&str; 4] = [
+    "threads",
+    "memory_budget_mb",
+    "timeout_secs",
+    "e_score_formula",
+];
+
+/// Location of the classification result cache in the app data directory.
+pub(crate) fn result_cache_dir<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<PathBuf, PoleshiftError> {
+    Ok(app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join("classification_cache"))
+}
+
+/// A cache key is the hex SHA-256 `ResultCache::key` produces; anything else is refused so
+/// a key can never name a file outside the cache.
+fn validate_key(key: &str) -> Result<(), PoleshiftError> {
+    if key.len() == 64 && key.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(PoleshiftError::InvalidInput {
+            field: "keys".to_string(),
+            reason: format!("'{}' is not a cache key", key),
+        })
+    }
+}
+
+/// Classification results of earlier runs, one SQLite file per run named by its
+/// cache key, so an identical run can be answered without classifying again.
+pub(crate) struct ResultCache {
+    dir: PathBuf,
+    /// Classify even when the run is cached; the new results replace the entry
+    force: bool,
+}
+
+impl ResultCache {
+    pub fn new(dir: PathBuf, force: bool) -> Self {
+        ResultCache { dir, force }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.sqlite", key))
+    }
+
+    /// Key of classifying `config.input_files` with `options`: a SHA-256 over the
+    /// contents of every input file (in order), the size and modification time of
+    /// every database file, and the options that affect the results.
+    pub fn key(
+        &self,
+        config: &KrakenConfig,
+        options: &ClassificationOptions,
+    ) -> Result<String, PoleshiftError> {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_KEY_VERSION.as_bytes());
+
+        // 1) The options, minus the ones that only change how fast the run is
+        let mut relevant = serde_json::to_value(options)?;
+        if let Some(fields) = relevant.as_object_mut() {
+            for name in IGNORED_OPTIONS {
+                fields.remove(name);
+            }
+        }
+        hasher.update(relevant.to_string().as_bytes());
+
+        // 2) The database; rebuilding or replacing it changes its files
+        hasher.update(config.database_id.as_bytes());
+        for file in database_files(options, config) {
+            let (size, modified) = fingerprint(&file).unwrap_or((-1, -1));
+            hasher.update(file.to_string_lossy().as_bytes());
+            hasher.update(size.to_le_bytes());
+            hasher.update(modified.to_le_bytes());
+        }
+
+        // 3) The reads themselves, by content, so a renamed or copied file still hits
+        for input in &config.input_files {
+            let mut file = File::open(input).map_err(|e| {
+                PoleshiftError::IoError(format!("Cannot read {}: {}", input.display(), e))
+            })?;
+            let mut file_hasher = Sha256::new();
+            std::io::copy(&mut file, &mut file_hasher)?;
+            hasher.update(file_hasher.finalize());
+        }
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// The cached results of the run `key`, unless there are none or `force` is set.
+    /// An entry that cannot be read is treated as missing.
+    pub fn get(&self, key: &str) -> Option<ClassificationResults> {
+        let path = self.entry_path(key);
+        if self.force || !path.is_file() {
+            return None;
+        }
+        match Checkpoint::open(&path, key).and_then(|entry| entry.merged_results()) {
+            Ok(results) => Some(results),
+            Err(e) => {
+                println!("Ignoring unreadable cache entry {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Stores the results of the run `key`. The entry is written under a temporary
+    /// name and renamed once complete, so an interrupted write is never read back.
+    pub fn put(&self, key: &str, results: &ClassificationResults) -> Result<(), PoleshiftError> {
+        let path = self.entry_path(key);
+        let partial = path.with_extension("sqlite.partial");
+        if partial.exists() {
+            std::fs::remove_file(&partial)?;
+        }
+        {
+            let mut entry = Checkpoint::open(&partial, key)?;
+            entry.record_run(results)?;
+        }
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+}
+
+/// One cached classification run.
+#[derive(Debug, Serialize)]
+pub struct CachedRun {
+    pub key: String,
+    pub size_bytes: u64,
+    /// When the entry was written, in seconds since the Unix epoch
+    pub created_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResultCacheSummary {
+    pub directory: String,
+    /// Newest first
+    pub runs: Vec<CachedRun>,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClearedResultCache {
+    pub removed: usize,
+    pub freed_bytes: u64,
+}
+
+/// Complete cache entries in `dir`; partial writes are left out.
+fn cached_runs(dir: &Path) -> Result<Vec<CachedRun>, PoleshiftError> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut runs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("sqlite") {
+            continue;
+        }
+        let Some(key) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let meta = std::fs::metadata(&path)?;
+        runs.push(CachedRun {
+            key: key.to_string(),
+            size_bytes: meta.len(),
+            created_at: meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        });
+    }
+    runs.sort_by_key(|run| Reverse(run.created_at));
+    Ok(runs)
+}
+
+/// Lists the classification runs kept in the result cache and the disk space they
+/// take. `handle_sequence_data` answers a run from here when the same reads were
+/// classified before with the same database and options.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_result_cache<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+) -> Result<StandardResponseNoFiles<ResultCacheSummary>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let dir = result_cache_dir(&app_handle)?;
+        let runs = cached_runs(&dir)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: ResultCacheSummary {
+                directory: dir.to_string_lossy().to_string(),
+                total_bytes: runs.iter().map(|r| r.size_bytes).sum(),
+                runs,
+            },
+        })
+    })
+    .await
+}
+
+/// Removes the cached runs in `keys`, or the whole result cache (including partial
+/// writes) when no keys are given.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn clear_result_cache<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    keys: Option<Vec<String>>,
+) -> Result<StandardResponseNoFiles<ClearedResultCache>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let dir = result_cache_dir(&app_handle)?;
+        let paths: Vec<PathBuf> = match keys {
+            Some(keys) => {
+                for key in &keys {
+                    validate_key(key)?;
+                }
+                keys.iter()
+                    .map(|key| dir.join(format!("{}.sqlite", key)))
+                    .filter(|path| path.is_file())
+                    .collect()
+            }
+            None if dir.is_dir() => std::fs::read_dir(&dir)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|path| path.is_file())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut cleared = ClearedResultCache {
+            removed: 0,
+            freed_bytes: 0,
+        };
+        for path in paths {
+            let size = std::fs::metadata(&path)?.len();
+            std::fs::remove_file(&path)?;
+            cleared.removed += 1;
+            cleared.freed_bytes += size;
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: cleared,
+        })
+    })
+    .await
+}
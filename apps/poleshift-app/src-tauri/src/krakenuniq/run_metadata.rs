@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::devtools::{traced, Invocation};
+use crate::io::source::FastqSource;
+use crate::krakenuniq::parse_fastq_files::{parse_nanopore_header, NanoporeHeader};
+use crate::krakenuniq::RawSequence;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Run-level fields from the Nanopore headers, aggregated once instead of being
+/// repeated on every read.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunMetadata {
+    pub read_count: usize,
+    pub run_ids: BTreeSet<String>,
+    pub flow_cell_ids: BTreeSet<String>,
+    pub protocol_group_ids: BTreeSet<String>,
+    pub basecall_model_version_ids: BTreeSet<String>,
+    pub sample_ids_fastq: BTreeSet<String>,
+    pub barcodes: BTreeSet<String>,
+    /// Earliest and latest `start_time`, compared as RFC 3339 strings
+    pub first_start_time: Option<String>,
+    pub last_start_time: Option<String>,
+    /// Reads per sequencing channel
+    pub channel_reads: BTreeMap<i32, usize>,
+    pub channels_used: usize,
+}
+
+/// The run-level fields of a single read, borrowed from a header or a `RawSequence`.
+struct RunFields<'a> {
+    run_id: &'a str,
+    flow_cell_id: &'a str,
+    protocol_group_id: &'a str,
+    basecall_model_version_id: &'a str,
+    sample_id_fastq: &'a str,
+    barcode: &'a str,
+    start_time: &'a str,
+    ch: i32,
+}
+
+impl RunMetadata {
+    fn add(&mut self, read: RunFields) {
+        self.read_count += 1;
+        for (set, value) in [
+            (&mut self.run_ids, read.run_id),
+            (&mut self.flow_cell_ids, read.flow_cell_id),
+            (&mut self.protocol_group_ids, read.protocol_group_id),
+            (
+                &mut self.basecall_model_version_ids,
+                read.basecall_model_version_id,
+            ),
+            (&mut self.sample_ids_fastq, read.sample_id_fastq),
+            (&mut self.barcodes, read.barcode),
+        ] {
+            if !value.is_empty() && !set.contains(value) {
+                set.insert(value.to_string());
+            }
+        }
+
+        let start_time = read.start_time;
+        if !start_time.is_empty() {
+            if self
+                .first_start_time
+                .as_deref()
+                .is_none_or(|t| start_time < t)
+            {
+                self.first_start_time = Some(start_time.to_string());
+            }
+            if self
+                .last_start_time
+                .as_deref()
+                .is_none_or(|t| start_time > t)
+            {
+                self.last_start_time = Some(start_time.to_string());
+            }
+        }
+
+        if read.ch > 0 {
+            *self.channel_reads.entry(read.ch).or_default() += 1;
+            self.channels_used = self.channel_reads.len();
+        }
+    }
+
+    fn add_header(&mut self, header: &NanoporeHeader) {
+        self.add(RunFields {
+            run_id: &header.run_id,
+            flow_cell_id: &header.flow_cell_id,
+            protocol_group_id: &header.protocol_group_id,
+            basecall_model_version_id: &header.basecall_model_version_id,
+            sample_id_fastq: &header.sample_id_fastq,
+            barcode: &header.barcode,
+            start_time: &header.start_time,
+            ch: header.ch,
+        });
+    }
+
+    /// Aggregates the run-level fields of already parsed reads.
+    pub fn from_sequences(sequences: &[RawSequence]) -> Self {
+        let mut metadata = RunMetadata::default();
+        for seq in sequences {
+            metadata.add(RunFields {
+                run_id: &seq.run_id,
+                flow_cell_id: &seq.flow_cell_id,
+                protocol_group_id: &seq.protocol_group_id,
+                basecall_model_version_id: &seq.basecall_model_version_id,
+                sample_id_fastq: &seq.sample_id_fastq,
+                barcode: &seq.barcode,
+                start_time: &seq.start_time,
+                ch: seq.ch,
+            });
+        }
+        metadata
+    }
+
+    /// Blanks the run-level strings on every read where the whole set shares a single
+    /// value, which `self` then carries. Mixed fields are left on the reads.
+    pub fn strip_shared_fields(&self, sequences: &mut [RawSequence]) {
+        let single = |set: &BTreeSet<String>| set.len() == 1;
+        let (run, flow_cell, protocol, model, sample) = (
+            single(&self.run_ids),
+            single(&self.flow_cell_ids),
+            single(&self.protocol_group_ids),
+            single(&self.basecall_model_version_ids),
+            single(&self.sample_ids_fastq),
+        );
+        for seq in sequences {
+            if run {
+                seq.run_id.clear();
+            }
+            if flow_cell {
+                seq.flow_cell_id.clear();
+            }
+            if protocol {
+                seq.protocol_group_id.clear();
+            }
+            if model {
+                seq.basecall_model_version_id.clear();
+            }
+            if sample {
+                seq.sample_id_fastq.clear();
+            }
+        }
+    }
+}
+
+/// Streams the headers of every input file and aggregates their run-level fields,
+/// without parsing reads into `RawSequence`s.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn summarize_run_metadata(
+    invocation: Invocation,
+    file_paths: Vec<String>,
+) -> Result<StandardResponseNoFiles<RunMetadata>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if file_paths.is_empty() {
+            return Err(PoleshiftError::NoFiles);
+        }
+
+        let metadata = tauri::async_runtime::spawn_blocking(move || {
+            let mut metadata = RunMetadata::default();
+            for path in &file_paths {
+                let mut source = FastqSource::open(&PathBuf::from(path))?;
+                while let Some(record) = source.read_record()? {
+                    metadata.add_header(&parse_nanopore_header(&record.header));
+                }
+            }
+            Ok::<_, PoleshiftError>(metadata)
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(e.to_string()))??;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: metadata,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::packed::PackedSequence;
+
+    fn read(id: &str, flow_cell_id: &str) -> RawSequence {
+        RawSequence {
+            id: id.to_string(),
+            feature_id: id.to_string(),
+            sequence: PackedSequence::pack("ACGT"),
+            quality: "IIII".to_string(),
+            quality_median: 40.0,
+            run_id: "run-1".to_string(),
+            read: 1,
+            ch: 7,
+            start_time: "2024-01-05T10:00:00Z".to_string(),
+            sample_id_fastq: "sample-a".to_string(),
+            barcode: "barcode01".to_string(),
+            barcode_alias: "barcode01".to_string(),
+            parent_read_id: id.to_string(),
+            basecall_model_version_id: "model-1".to_string(),
+            flow_cell_id: flow_cell_id.to_string(),
+            protocol_group_id: "protocol-1".to_string(),
+            umi: None,
+            duplex_parent_ids: Vec::new(),
+            duplex_child_id: None,
+            user_id: String::new(),
+            org_id: String::new(),
+            sample_id: String::new(),
+            raw_data_id: String::new(),
+        }
+    }
+
+    /// Mirror of `restoreSharedRunFields` in `useTauriDataProcessor.ts`.
+    fn restore_shared_fields(metadata: &RunMetadata, sequences: &mut [RawSequence]) {
+        let shared = |set: &BTreeSet<String>| match set.len() {
+            1 => set.iter().next().cloned(),
+            _ => None,
+        };
+        let fields = [
+            shared(&metadata.run_ids),
+            shared(&metadata.flow_cell_ids),
+            shared(&metadata.protocol_group_ids),
+            shared(&metadata.basecall_model_version_ids),
+            shared(&metadata.sample_ids_fastq),
+        ];
+        for seq in sequences {
+            let targets = [
+                &mut seq.run_id,
+                &mut seq.flow_cell_id,
+                &mut seq.protocol_group_id,
+                &mut seq.basecall_model_version_id,
+                &mut seq.sample_id_fastq,
+            ];
+            for (target, value) in targets.into_iter().zip(&fields) {
+                if let (true, Some(value)) = (target.is_empty(), value) {
+                    *target = value.clone();
+                }
+            }
+        }
+    }
+
+    fn run_fields(seq: &RawSequence) -> [String; 5] {
+        [
+            seq.run_id.clone(),
+            seq.flow_cell_id.clone(),
+            seq.protocol_group_id.clone(),
+            seq.basecall_model_version_id.clone(),
+            seq.sample_id_fastq.clone(),
+        ]
+    }
+
+    #[test]
+    fn strip_then_restore_round_trips_run_fields() {
+        let mut sequences = vec![read("r1", "FC1"), read("r2", "FC2")];
+        let original: Vec<_> = sequences.iter().map(run_fields).collect();
+        let metadata = RunMetadata::from_sequences(&sequences);
+
+        metadata.strip_shared_fields(&mut sequences);
+        // Shared fields are blanked, the mixed flow cell stays on each read
+        assert!(sequences[0].run_id.is_empty());
+        assert!(sequences[0].sample_id_fastq.is_empty());
+        assert_eq!(sequences[1].flow_cell_id, "FC2");
+
+        restore_shared_fields(&metadata, &mut sequences);
+        let restored: Vec<_> = sequences.iter().map(run_fields).collect();
+        assert_eq!(restored, original);
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::io::source::FastqSource;
+use crate::krakenuniq::kmer_hash::{HashPath, KmerHasher, MAX_K};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Reference sketches shipped with the app, relative to the resource directory.
+const BUNDLED_SKETCHES: &str = "sketches/references.json";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ScreenOptions {
+    /// Stop after this many reads; a screen needs a sample, not every read
+    pub max_reads: usize,
+    /// References sharing fewer hashes with the sample are not reported
+    pub min_shared_hashes: usize,
+    /// Sketch file to screen against instead of the bundled one
+    pub sketch_path: Option<String>,
+}
+
+impl Default for ScreenOptions {
+    fn default() -> Self {
+        ScreenOptions {
+            max_reads: 100_000,
+            min_shared_hashes: 3,
+            sketch_path: None,
+        }
+    }
+}
+
+/// One reference genome (or taxon) reduced to its FracMinHash sketch.
+#[derive(Debug, Deserialize)]
+struct ReferenceSketch {
+    tax_id: u64,
+    name: String,
+    #[serde(default)]
+    rank: String,
+    hashes: Vec<u64>,
+}
+
+/// A sketch file: every reference sketched with the same `k` and `scaled`, hashing
+/// canonical k-mers with `kmer_hash`.
+#[derive(Debug, Deserialize)]
+struct SketchCollection {
+    k: usize,
+    scaled: u64,
+    references: Vec<ReferenceSketch>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScreenMatch {
+    pub tax_id: u64,
+    pub name: String,
+    pub rank: String,
+    /// Reference hashes also seen in the sample
+    pub shared_hashes: usize,
+    /// Fraction of the reference's hashes seen in the sample
+    pub containment: f64,
+    /// Identity implied by the containment, `containment^(1/k)`
+    pub ani_estimate: f64,
+    /// Share of the sample's k-mers assigned to this reference, counting each k-mer
+    /// for the best remaining match only
+    pub fraction_of_sample: f64,
+}
+
+/// Approximate composition of a sample from sketch comparison alone.
+#[derive(Debug, Serialize)]
+pub struct QuickScreen {
+    pub files: Vec<String>,
+    pub reads_screened: usize,
+    /// `max_reads` was reached before the end of the files
+    pub truncated: bool,
+    pub k: usize,
+    pub scaled: u64,
+    /// How the sample's k-mers were hashed
+    pub hash_path: HashPath,
+    /// Distinct hashes kept from the sample
+    pub sample_hashes: usize,
+    /// Best match first
+    pub matches: Vec<ScreenMatch>,
+    /// Share of the sample's k-mers no reference accounts for
+    pub unmatched_fraction: f64,
+    pub elapsed_ms: u128,
+}
+
+fn load_sketches(path: &Path) -> Result<SketchCollection, PoleshiftError> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        PoleshiftError::IoError(format!("Cannot read sketches {}: {}", path.display(), e))
+    })?;
+    let sketches: SketchCollection = serde_json::from_str(&text)
+        .map_err(|e| PoleshiftError::SerializationError(e.to_string()))?;
+    if sketches.k == 0 || sketches.k > MAX_K || sketches.scaled == 0 {
+        return Err(PoleshiftError::DataError(format!(
+            "Sketch file {} needs k between 1 and {} and a scaled above 0",
+            path.display(),
+            MAX_K
+        )));
+    }
+    Ok(sketches)
+}
+
+fn screen(
+    files: &[String],
+    sketches: &SketchCollection,
+    options: &ScreenOptions,
+) -> Result<QuickScreen, PoleshiftError> {
+    let started = Instant::now();
+    let max_hash = u64::MAX / sketches.scaled;
+
+    // 1) Sketch the first `max_reads` reads of the sample
+    let mut hasher = KmerHasher::new(HashPath::detect());
+    let mut counts: HashMap<u64, u64> = HashMap::new();
+    let mut reads_screened = 0;
+    let mut truncated = false;
+    'files: for file in files {
+        let mut source = FastqSource::open(Path::new(file))?;
+        while let Some(record) = source.read_record()? {
+            if reads_screened >= options.max_reads {
+                truncated = true;
+                break 'files;
+            }
+            hasher.sketch_sequence(
+                record.sequence.as_bytes(),
+                sketches.k,
+                max_hash,
+                &mut counts,
+            );
+            reads_screened += 1;
+        }
+    }
+    let total_weight: u64 = counts.values().sum();
+
+    // 2) Containment of every reference in the sample
+    let references: Vec<(&ReferenceSketch, usize)> = sketches
+        .references
+        .iter()
+        .map(|r| {
+            let shared = r.hashes.iter().filter(|h| counts.contains_key(h)).count();
+            (r, shared)
+        })
+        .filter(|&(_, shared)| shared >= options.min_shared_hashes.max(1))
+        .collect();
+
+    // 3) Greedily hand each sample hash to the reference that explains the most of
+    // what is left, so related references do not claim the same k-mers twice
+    let mut remaining: HashSet<u64> = counts.keys().copied().collect();
+    let mut candidates: Vec<usize> = (0..references.len()).collect();
+    let mut matches = Vec::new();
+    let mut assigned_weight = 0;
+    while !candidates.is_empty() {
+        let (position, best, overlap) = candidates
+            .iter()
+            .enumerate()
+            .map(|(position, &i)| {
+                let overlap = references[i]
+                    .0
+                    .hashes
+                    .iter()
+                    .filter(|h| remaining.contains(h))
+                    .count();
+                (position, i, overlap)
+            })
+            .max_by_key(|&(_, i, overlap)| (overlap, references[i].1))
+            .unwrap_or_default();
+        if overlap < options.min_shared_hashes.max(1) {
+            break;
+        }
+        candidates.swap_remove(position);
+
+        let (reference, shared) = references[best];
+        let weight: u64 = reference
+            .hashes
+            .iter()
+            .filter(|h| remaining.remove(h))
+            .map(|h| counts[h])
+            .sum();
+        assigned_weight += weight;
+        let containment = shared as f64 / reference.hashes.len() as f64;
+        matches.push(ScreenMatch {
+            tax_id: reference.tax_id,
+            name: reference.name.clone(),
+            rank: reference.rank.clone(),
+            shared_hashes: shared,
+            containment,
+            ani_estimate: containment.powf(1.0 / sketches.k as f64),
+            fraction_of_sample: if total_weight > 0 {
+                weight as f64 / total_weight as f64
+            } else {
+                0.0
+            },
+        });
+    }
+
+    Ok(QuickScreen {
+        files: files.to_vec(),
+        reads_screened,
+        truncated,
+        k: sketches.k,
+        scaled: sketches.scaled,
+        hash_path: hasher.path(),
+        sample_hashes: counts.len(),
+        matches,
+        unmatched_fraction: if total_weight > 0 {
+            1.0 - assigned_weight as f64 / total_weight as f64
+        } else {
+            0.0
+        },
+        elapsed_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Estimates the composition of a sample in seconds by comparing a FracMinHash sketch
+/// of its first reads against the bundled reference sketches, so samples can be
+/// triaged before a full classification. Results are approximate: only references in
+/// the sketch file can be found, and shares are by k-mer rather than by read.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn quick_screen_sample<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    file_paths: Vec<String>,
+    options: Option<ScreenOptions>,
+) -> Result<StandardResponseNoFiles<QuickScreen>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if file_paths.is_empty() {
+            return Err(PoleshiftError::NoFiles);
+        }
+        let options = options.unwrap_or_default();
+        let sketch_path = match &options.sketch_path {
+            Some(path) => PathBuf::from(path),
+            None => app_handle
+                .path()
+                .resource_dir()
+                .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+                .join("./resources")
+                .join(BUNDLED_SKETCHES),
+        };
+
+        let report = tauri::async_runtime::spawn_blocking(move || {
+            let sketches = load_sketches(&sketch_path)?;
+            screen(&file_paths, &sketches, &options)
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Quick screen task failed: {}", e)))??;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// NCBI tax ID of "root".
+const ROOT_TAX_ID: u32 = 1;
+
+/// One node of the taxonomy.
+#[derive(Debug, Clone)]
+pub(crate) struct TaxNode {
+    pub parent_tax_id: u32,
+    pub name: String,
+    pub rank: String,
+}
+
+/// The taxonomy of a KrakenUniq database, as stored in its `taxDB` file.
+#[derive(Debug, Default)]
+pub(crate) struct TaxDb {
+    nodes: HashMap<u32, TaxNode>,
+}
+
+impl TaxDb {
+    /// Reads a `taxDB` file: one tab-separated `tax_id, parent_tax_id, name, rank`
+    /// line per node.
+    pub fn load(path: &Path) -> Result<Self, PoleshiftError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut nodes = HashMap::new();
+        for line in reader.lines() {
+            let line = line?;
+            let mut fields = line.split('\t');
+            let (Some(tax_id), Some(parent), Some(name)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let (Ok(tax_id), Ok(parent_tax_id)) = (tax_id.trim().parse(), parent.trim().parse())
+            else {
+                continue;
+            };
+            nodes.insert(
+                tax_id,
+                TaxNode {
+                    parent_tax_id,
+                    name: name.trim().to_string(),
+                    rank: fields.next().unwrap_or("no rank").trim().to_string(),
+                },
+            );
+        }
+        Ok(TaxDb { nodes })
+    }
+
+    /// Builds a taxonomy from `(tax_id, parent_tax_id, name, rank)` tuples.
+    pub fn from_nodes<'a>(nodes: impl IntoIterator<Item = (u32, u32, &'a str, &'a str)>) -> Self {
+        TaxDb {
+            nodes: nodes
+                .into_iter()
+                .map(|(tax_id, parent_tax_id, name, rank)| {
+                    (
+                        tax_id,
+                        TaxNode {
+                            parent_tax_id,
+                            name: name.to_string(),
+                            rank: rank.to_string(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, tax_id: u32) -> Option<&TaxNode> {
+        self.nodes.get(&tax_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &TaxNode)> {
+        self.nodes.iter().map(|(&tax_id, node)| (tax_id, node))
+    }
+
+    /// Ranked ancestors of `tax_id` from the top down, ending with the taxon itself.
+    /// "root" and unranked intermediate nodes (e.g. "cellular organisms") are skipped.
+    pub fn lineage(&self, tax_id: u32) -> Vec<&TaxNode> {
+        self.lineage_ids(tax_id)
+            .into_iter()
+            .filter_map(|id| self.nodes.get(&id))
+            .collect()
+    }
+
+    /// Tax IDs of `lineage`.
+    pub fn lineage_ids(&self, tax_id: u32) -> Vec<u32> {
+        let mut ids = Vec::new();
+        let mut current = tax_id;
+        let mut steps = 0;
+        while current != ROOT_TAX_ID && steps <= self.nodes.len() {
+            let Some(node) = self.nodes.get(&current) else {
+                break;
+            };
+            if current == tax_id || canonical_rank(&node.rank) != "no rank" {
+                ids.push(current);
+            }
+            if node.parent_tax_id == current {
+                break;
+            }
+            current = node.parent_tax_id;
+            steps += 1;
+        }
+        ids.reverse();
+        ids
+    }
+}
+
+/// Normalises a rank to the NCBI spelling: lower case, Kraken report letter codes
+/// expanded, and "superkingdom" under its current name "domain".
+pub fn canonical_rank(rank: &str) -> String {
+    let rank = rank.trim();
+    let expanded = match rank {
+        "D" | "d" => "domain",
+        "K" | "k" => "kingdom",
+        "P" | "p" => "phylum",
+        "C" | "c" => "class",
+        "O" | "o" => "order",
+        "F" | "f" => "family",
+        "G" | "g" => "genus",
+        "S" | "s" => "species",
+        "U" | "u" => "unclassified",
+        "" | "-" | "R" | "r" => "no rank",
+        _ => "",
+    };
+    if !expanded.is_empty() {
+        return expanded.to_string();
+    }
+    match rank.to_lowercase().as_str() {
+        "superkingdom" => "domain".to_string(),
+        "norank" | "no_rank" => "no rank".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Fills in `lineage` and `lineage_ranks` of every report row from `taxdb` and
+/// normalises `rank`, so rows describe their place in the taxonomy on their own.
+pub(crate) fn annotate_lineages(report: &mut [ProcessedKrakenUniqReport], taxdb: &TaxDb) {
+    for row in report.iter_mut() {
+        let tax_id = row.tax_id as u32;
+        if tax_id != 0 && tax_id != ROOT_TAX_ID && taxdb.get(tax_id).is_some() {
+            let lineage = taxdb.lineage(tax_id);
+            row.lineage = Some(
+                lineage
+                    .iter()
+                    .map(|node| node.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(";"),
+            );
+            row.lineage_ranks = Some(
+                lineage
+                    .iter()
+                    .map(|node| canonical_rank(&node.rank))
+                    .collect::<Vec<_>>()
+                    .join(";"),
+            );
+        }
+        row.rank = canonical_rank(&row.rank);
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{load_report, output_store_path};
+use crate::krakenuniq::taxdb::canonical_rank;
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Default and largest number of matches returned.
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// The name, or one of its words, starts with the query
+    #[default]
+    Prefix,
+    /// Like `Prefix`, plus names within a few typos of the query
+    Fuzzy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaxonomyQuery {
+    /// A taxon name (or the start of one), or a numeric tax ID
+    pub query: String,
+    #[serde(default)]
+    pub mode: MatchMode,
+    pub limit: Option<usize>,
+}
+
+/// One taxon of the report, without the per-sample bookkeeping.
+#[derive(Debug, Serialize)]
+pub struct TaxonSummary {
+    pub tax_id: u64,
+    pub tax_name: String,
+    pub rank: String,
+    pub reads: u64,
+    pub tax_reads: u64,
+    pub percentage: f32,
+    pub e_score: f64,
+}
+
+impl From<&ProcessedKrakenUniqReport> for TaxonSummary {
+    fn from(row: &ProcessedKrakenUniqReport) -> Self {
+        TaxonSummary {
+            tax_id: row.tax_id,
+            tax_name: row.tax_name.trim().to_string(),
+            rank: row.rank.clone(),
+            reads: row.reads,
+            tax_reads: row.tax_reads,
+            percentage: row.percentage,
+            e_score: row.e_score,
+        }
+    }
+}
+
+/// Totals over a matched taxon and everything below it.
+#[derive(Debug, Default, Serialize)]
+pub struct SubtreeSummary {
+    /// Taxa in the subtree, the matched taxon included
+    pub taxa: usize,
+    /// Species in the subtree
+    pub species: usize,
+    /// Deepest level below the matched taxon
+    pub depth: usize,
+    /// Sum of direct reads over the subtree; equals the clade count
+    pub tax_reads: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaxonMatch {
+    pub taxon: TaxonSummary,
+    /// Ancestors from the top of the report down to the parent
+    pub ancestors: Vec<TaxonSummary>,
+    /// Direct children, largest clade first
+    pub children: Vec<TaxonSummary>,
+    pub subtree: SubtreeSummary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaxonomySearch {
+    /// Matches before `limit` was applied
+    pub total_matches: usize,
+    pub matches: Vec<TaxonMatch>,
+}
+
+/// Edit distance between two strings, by characters.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How well `name` matches `query` (both lower case); lower is better, `None` is no
+/// match. Exact names rank first, then prefixes, word prefixes and, when fuzzy, near
+/// misses by edit distance.
+fn match_score(name: &str, query: &str, mode: MatchMode) -> Option<usize> {
+    if name == query {
+        return Some(0);
+    }
+    if name.starts_with(query) {
+        return Some(1);
+    }
+    if name.split_whitespace().any(|word| word.starts_with(query)) {
+        return Some(2);
+    }
+    if mode == MatchMode::Fuzzy {
+        // Allow one typo per four characters, compared against the name's start
+        let allowed = (query.chars().count() / 4).max(1);
+        let start: String = name.chars().take(query.chars().count()).collect();
+        let distance = levenshtein(&start, query).min(levenshtein(name, query));
+        if distance <= allowed {
+            return Some(3 + distance);
+        }
+    }
+    None
+}
+
+fn search(report: &[ProcessedKrakenUniqReport], query: &TaxonomyQuery) -> TaxonomySearch {
+    let needle = query.query.trim().to_lowercase();
+    let by_id: HashMap<&str, usize> = report
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row.id.as_str(), i))
+        .collect();
+    let parent_of = |i: usize| {
+        report[i]
+            .parent_id
+            .and_then(|p| by_id.get(p.to_string().as_str()).copied())
+    };
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..report.len() {
+        if let Some(parent) = parent_of(i) {
+            children.entry(parent).or_default().push(i);
+        }
+    }
+
+    // 1) Score every row; a numeric query matches tax IDs exactly
+    let mut hits: Vec<(usize, usize)> = match needle.parse::<u64>() {
+        Ok(tax_id) => report
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.tax_id == tax_id)
+            .map(|(i, _)| (0, i))
+            .collect(),
+        Err(_) if needle.is_empty() => Vec::new(),
+        Err(_) => report
+            .iter()
+            .enumerate()
+            .filter_map(|(i, row)| {
+                match_score(&row.tax_name.trim().to_lowercase(), &needle, query.mode)
+                    .map(|score| (score, i))
+            })
+            .collect(),
+    };
+    hits.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then(report[b.1].reads.cmp(&report[a.1].reads))
+    });
+    let total_matches = hits.len();
+
+    // 2) Describe the best matches with their place in the tree
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let matches = hits
+        .into_iter()
+        .take(limit)
+        .map(|(_, i)| {
+            let mut ancestors = Vec::new();
+            let mut current = parent_of(i);
+            while let Some(p) = current.filter(|_| ancestors.len() < report.len()) {
+                ancestors.push(TaxonSummary::from(&report[p]));
+                current = parent_of(p);
+            }
+            ancestors.reverse();
+
+            let mut direct: Vec<usize> = children.get(&i).cloned().unwrap_or_default();
+            direct.sort_by(|&a, &b| report[b].reads.cmp(&report[a].reads));
+
+            let mut subtree = SubtreeSummary::default();
+            let mut stack = vec![(i, 0usize)];
+            while let Some((node, depth)) = stack.pop() {
+                if subtree.taxa > report.len() {
+                    break;
+                }
+                subtree.taxa += 1;
+                subtree.depth = subtree.depth.max(depth);
+                subtree.tax_reads += report[node].tax_reads;
+                if canonical_rank(&report[node].rank) == "species" {
+                    subtree.species += 1;
+                }
+                for &child in children.get(&node).map(Vec::as_slice).unwrap_or(&[]) {
+                    stack.push((child, depth + 1));
+                }
+            }
+
+            TaxonMatch {
+                taxon: TaxonSummary::from(&report[i]),
+                ancestors,
+                children: direct
+                    .iter()
+                    .map(|&c| TaxonSummary::from(&report[c]))
+                    .collect(),
+                subtree,
+            }
+        })
+        .collect();
+
+    TaxonomySearch {
+        total_matches,
+        matches,
+    }
+}
+
+/// Searches the stored report of `processed_data_id` by taxon name (prefix, or fuzzy
+/// to tolerate typos) or tax ID, returning each match with its ancestor path, direct
+/// children and subtree totals, so the taxonomy browser never needs the whole tree.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn query_taxonomy<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    query: TaxonomyQuery,
+) -> Result<StandardResponseNoFiles<TaxonomySearch>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let report = load_report(&output_store_path(&app_handle, &processed_data_id)?)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: search(&report, &query),
+        })
+    })
+    .await
+}
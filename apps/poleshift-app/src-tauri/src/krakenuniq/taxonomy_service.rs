@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime, State};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::taxdb::{canonical_rank, TaxDb};
+use crate::poleshift_common::types::{KrakenConfig, PoleshiftError, StandardResponseNoFiles};
+use crate::settings::resources_dir;
+
+/// Most taxa `lookup_taxon` returns for a name.
+const MAX_NAME_MATCHES: usize = 100;
+
+/// A loaded `taxDB`, indexed by name and by parent.
+pub(crate) struct TaxonomyIndex {
+    pub taxdb: TaxDb,
+    /// Tax IDs by lowercased name
+    by_name: HashMap<String, Vec<u32>>,
+    children: HashMap<u32, Vec<u32>>,
+}
+
+impl TaxonomyIndex {
+    fn new(taxdb: TaxDb) -> Self {
+        let mut by_name: HashMap<String, Vec<u32>> = HashMap::new();
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (tax_id, node) in taxdb.iter() {
+            by_name
+                .entry(node.name.to_lowercase())
+                .or_default()
+                .push(tax_id);
+            if node.parent_tax_id != tax_id {
+                children.entry(node.parent_tax_id).or_default().push(tax_id);
+            }
+        }
+        for ids in by_name.values_mut().chain(children.values_mut()) {
+            ids.sort_unstable();
+        }
+        TaxonomyIndex {
+            taxdb,
+            by_name,
+            children,
+        }
+    }
+
+    fn info(&self, tax_id: u32) -> Option<TaxonInfo> {
+        let node = self.taxdb.get(tax_id)?;
+        Some(TaxonInfo {
+            tax_id,
+            parent_tax_id: (node.parent_tax_id != tax_id).then_some(node.parent_tax_id),
+            name: node.name.clone(),
+            rank: canonical_rank(&node.rank),
+        })
+    }
+}
+
+/// Taxonomies loaded so far, by `taxDB` path, so every feature needing one shares a
+/// single copy instead of reading the file again.
+#[derive(Default)]
+pub struct TaxonomyCache {
+    loaded: Mutex<HashMap<PathBuf, Arc<TaxonomyIndex>>>,
+}
+
+impl TaxonomyCache {
+    /// The taxonomy at `path`, read on first use.
+    pub(crate) fn get(&self, path: &Path) -> Result<Arc<TaxonomyIndex>, PoleshiftError> {
+        // Loading under the lock keeps two commands from reading the same file at once
+        let mut loaded = self
+            .loaded
+            .lock()
+            .map_err(|_| PoleshiftError::Other("Taxonomy cache lock poisoned".to_string()))?;
+        if let Some(index) = loaded.get(path) {
+            return Ok(index.clone());
+        }
+        let index = Arc::new(TaxonomyIndex::new(TaxDb::load(path)?));
+        loaded.insert(path.to_path_buf(), index.clone());
+        Ok(index)
+    }
+
+    /// The taxonomy of the installed database `database_id`.
+    pub(crate) fn for_database<R: Runtime>(
+        &self,
+        app_handle: &AppHandle<R>,
+        database_id: &str,
+    ) -> Result<Arc<TaxonomyIndex>, PoleshiftError> {
+        let resource_dir = resources_dir(app_handle)?;
+        let config = KrakenConfig::for_database(&resource_dir, database_id, Vec::new())?;
+        self.get(Path::new(&config.taxdb_file))
+    }
+}
+
+/// One taxon of the taxonomy.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaxonInfo {
+    pub tax_id: u32,
+    /// None for the root
+    pub parent_tax_id: Option<u32>,
+    pub name: String,
+    pub rank: String,
+}
+
+/// A taxon to look up, by exact name (case-insensitive) or tax ID.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TaxonKey {
+    TaxId { tax_id: u32 },
+    Name { name: String },
+}
+
+/// Finds taxa of the database's taxonomy by tax ID, or by name (several taxa may
+/// share one, e.g. a genus and a subgenus).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn lookup_taxon<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    cache: State<'_, TaxonomyCache>,
+    database_id: String,
+    key: TaxonKey,
+) -> Result<StandardResponseNoFiles<Vec<TaxonInfo>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let index = cache.for_database(&app_handle, &database_id)?;
+        let taxa = match key {
+            TaxonKey::TaxId { tax_id } => index.info(tax_id).into_iter().collect(),
+            TaxonKey::Name { name } => index
+                .by_name
+                .get(&name.trim().to_lowercase())
+                .into_iter()
+                .flatten()
+                .take(MAX_NAME_MATCHES)
+                .filter_map(|&tax_id| index.info(tax_id))
+                .collect(),
+        };
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: taxa,
+        })
+    })
+    .await
+}
+
+/// The direct children of `tax_id` in the database's taxonomy, by tax ID.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn children_of<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    cache: State<'_, TaxonomyCache>,
+    database_id: String,
+    tax_id: u32,
+) -> Result<StandardResponseNoFiles<Vec<TaxonInfo>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let index = cache.for_database(&app_handle, &database_id)?;
+        let children = index
+            .children
+            .get(&tax_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|&child| index.info(child))
+            .collect();
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: children,
+        })
+    })
+    .await
+}
+
+/// The ranked ancestors of `tax_id` from the top of the taxonomy down, ending with
+/// the taxon itself (see `TaxDb::lineage`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn lineage_of<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    cache: State<'_, TaxonomyCache>,
+    database_id: String,
+    tax_id: u32,
+) -> Result<StandardResponseNoFiles<Vec<TaxonInfo>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let index = cache.for_database(&app_handle, &database_id)?;
+        if index.taxdb.get(tax_id).is_none() {
+            return Err(PoleshiftError::InvalidInput {
+                field: "tax_id".to_string(),
+                reason: format!("{} is not in the taxonomy of {}", tax_id, database_id),
+            });
+        }
+        let lineage = index
+            .taxdb
+            .lineage_ids(tax_id)
+            .into_iter()
+            .filter_map(|id| index.info(id))
+            .collect();
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: lineage,
+        })
+    })
+    .await
+}
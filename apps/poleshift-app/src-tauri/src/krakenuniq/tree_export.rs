@@ -0,0 +1,195 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::ctd::export::csv_field;
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::hierarchy::{build_hierarchy, prune, PruneOptions, TaxonomyNode};
+use crate::krakenuniq::output_store::{load_report, output_store_path};
+use crate::poleshift_common::jobs::JobManager;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// NCBI tax ID of "root"; left out of lineages as every taxon shares it.
+const ROOT_TAX_ID: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeExportFormat {
+    /// Newick, with NHX attributes for reads, tax ID and rank (iTOL, R's treeio)
+    Newick,
+    /// One object per taxon with its lineage path
+    LineageJson,
+    /// One row per taxon with its lineage path joined with ';'
+    LineageCsv,
+}
+
+/// A taxon with the path of names and ranks leading to it.
+#[derive(Debug, Serialize)]
+pub struct LineageRow {
+    pub tax_id: u64,
+    pub name: String,
+    pub rank: String,
+    /// From the top of the tree down to this taxon, without "root"
+    pub lineage: Vec<String>,
+    pub lineage_ranks: Vec<String>,
+    pub reads: u64,
+    pub tax_reads: u64,
+    pub percentage: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeExport {
+    pub output_path: String,
+    pub format: TreeExportFormat,
+    pub taxa: usize,
+}
+
+/// A Newick label, quoted when it holds a character Newick gives a meaning to.
+fn newick_label(name: &str) -> String {
+    if name.contains(|c: char| c.is_whitespace() || "()[]':;,".contains(c)) {
+        format!("'{}'", name.replace('\'', "''"))
+    } else {
+        name.to_string()
+    }
+}
+
+fn write_newick_node(out: &mut String, node: &TaxonomyNode) {
+    if !node.children.is_empty() {
+        out.push('(');
+        for (i, child) in node.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_newick_node(out, child);
+        }
+        out.push(')');
+    }
+    let _ = write!(
+        out,
+        "{}[&&NHX:reads={}:tax_reads={}:taxid={}:rank={}]",
+        newick_label(&node.name),
+        node.reads,
+        node.tax_reads,
+        node.tax_id,
+        node.rank.replace([':', ']', '='], "_")
+    );
+}
+
+/// The tree in Newick, each taxon's reads, direct reads, tax ID and rank as NHX
+/// attributes. Several top-level taxa are joined under an unnamed root.
+pub(crate) fn newick(tree: &[TaxonomyNode]) -> String {
+    let mut out = String::new();
+    match tree {
+        [root] => write_newick_node(&mut out, root),
+        _ => {
+            out.push('(');
+            for (i, root) in tree.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_newick_node(&mut out, root);
+            }
+            out.push(')');
+        }
+    }
+    out.push_str(";\n");
+    out
+}
+
+/// Every taxon of the tree with its lineage, in depth-first order.
+pub(crate) fn lineage_rows(tree: &[TaxonomyNode]) -> Vec<LineageRow> {
+    fn visit(node: &TaxonomyNode, path: &mut Vec<(String, String)>, rows: &mut Vec<LineageRow>) {
+        let on_path = node.tax_id != ROOT_TAX_ID;
+        if on_path {
+            path.push((node.name.clone(), node.rank.clone()));
+        }
+        rows.push(LineageRow {
+            tax_id: node.tax_id,
+            name: node.name.clone(),
+            rank: node.rank.clone(),
+            lineage: path.iter().map(|(name, _)| name.clone()).collect(),
+            lineage_ranks: path.iter().map(|(_, rank)| rank.clone()).collect(),
+            reads: node.reads,
+            tax_reads: node.tax_reads,
+            percentage: node.percentage,
+        });
+        for child in &node.children {
+            visit(child, path, rows);
+        }
+        if on_path {
+            path.pop();
+        }
+    }
+
+    let mut rows = Vec::new();
+    for root in tree {
+        visit(root, &mut Vec::new(), &mut rows);
+    }
+    rows
+}
+
+fn lineage_csv(rows: &[LineageRow]) -> String {
+    let mut out =
+        String::from("tax_id,name,rank,lineage,lineage_ranks,reads,tax_reads,percentage\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            row.tax_id,
+            csv_field(&row.name),
+            csv_field(&row.rank),
+            csv_field(&row.lineage.join(";")),
+            csv_field(&row.lineage_ranks.join(";")),
+            row.reads,
+            row.tax_reads,
+            row.percentage
+        );
+    }
+    out
+}
+
+/// Writes the stored classification's tree to `output_path` as Newick or as flat
+/// lineage JSON or CSV, for opening in iTOL or R; with `prune_options`, only the taxa
+/// `prune_taxonomy_hierarchy` would keep. Runs as an `export` job.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn export_taxonomy_tree<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    output_path: String,
+    format: TreeExportFormat,
+    prune_options: Option<PruneOptions>,
+) -> Result<StandardResponseNoFiles<TreeExport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let store_path = output_store_path(&app_handle, &processed_data_id)?;
+        let jobs = app_handle.state::<JobManager>();
+        jobs.run("export", None, |_| async move {
+            let report = load_report(&store_path)?;
+            let mut tree = build_hierarchy(&report);
+            if let Some(options) = &prune_options {
+                tree = prune(tree, options, &mut 0);
+            }
+
+            let rows = lineage_rows(&tree);
+            let contents = match format {
+                TreeExportFormat::Newick => newick(&tree),
+                TreeExportFormat::LineageJson => serde_json::to_string_pretty(&rows)?,
+                TreeExportFormat::LineageCsv => lineage_csv(&rows),
+            };
+            std::fs::write(Path::new(&output_path), contents)?;
+
+            Ok(StandardResponseNoFiles {
+                status: "Success".to_string(),
+                report: TreeExport {
+                    output_path,
+                    format,
+                    taxa: rows.len(),
+                },
+            })
+        })
+        .await
+    })
+    .await
+}
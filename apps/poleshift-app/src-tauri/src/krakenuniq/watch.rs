@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime, State, Window};
+
+use crate::devtools::{traced, traced_blocking, Invocation};
+use crate::krakenuniq::backend::backend_for;
+use crate::krakenuniq::handle_sequence_data::{
+    maybe_decompress_config_files, run_classification, ClassifierRun,
+};
+use crate::krakenuniq::options::ClassificationOptions;
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
+use crate::poleshift_common::types::{KrakenConfig, PoleshiftError, StandardResponseNoFiles};
+use crate::settings::{current_settings, resources_dir};
+
+/// Default time between directory scans.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Shortest allowed time between directory scans.
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the idle watcher checks for cancellation.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Cumulative counts for one taxon over every file classified so far.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveTaxon {
+    pub tax_id: u32,
+    pub parent_tax_id: Option<u32>,
+    pub tax_name: String,
+    pub rank: String,
+    pub reads: u64,
+    pub tax_reads: u64,
+    pub percentage: f64,
+}
+
+/// The cumulative report of a watch, as `live_classification` returns it. Updated after
+/// each newly classified file, and once more when the watch ends.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveClassificationUpdate {
+    pub job_id: String,
+    pub directory: String,
+    pub files_classified: usize,
+    pub reads_classified: u64,
+    pub latest_file: Option<String>,
+    pub taxa: Vec<LiveTaxon>,
+    /// True on the last update of a watch
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+impl LiveClassificationUpdate {
+    fn status_message(&self) -> String {
+        let latest = self
+            .latest_file
+            .as_deref()
+            .and_then(|path| Path::new(path).file_name())
+            .map(|name| format!(", latest {}", name.to_string_lossy()))
+            .unwrap_or_default();
+        format!(
+            "Classified {} files, {} reads{}",
+            self.files_classified, self.reads_classified, latest
+        )
+    }
+}
+
+/// The latest `LiveClassificationUpdate` of each watch by job ID, kept after the watch
+/// ends until another one starts. Registered as managed state.
+#[derive(Default)]
+pub struct LiveClassifications(Mutex<HashMap<String, LiveClassificationUpdate>>);
+
+impl LiveClassifications {
+    fn store(&self, update: LiveClassificationUpdate) {
+        if let Ok(mut updates) = self.0.lock() {
+            updates.insert(update.job_id.clone(), update);
+        }
+    }
+
+    /// Forgets the watches that ended.
+    fn clear_finished(&self) {
+        if let Ok(mut updates) = self.0.lock() {
+            updates.retain(|_, update| !update.finished);
+        }
+    }
+
+    fn get(&self, job_id: &str) -> Option<LiveClassificationUpdate> {
+        self.0.lock().ok()?.get(job_id).cloned()
+    }
+}
+
+/// Stores `update` for `live_classification` and announces it on the job's channel,
+/// `job://{job_id}/progress`. A watch has no end in sight, so it stays at 0 % until it
+/// finishes.
+fn publish_update<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    window: &Window<R>,
+    job: &JobHandle,
+    update: LiveClassificationUpdate,
+) -> Result<(), PoleshiftError> {
+    let message = update.status_message();
+    let (percentage, state) = match (update.finished, &update.error) {
+        (false, _) => (0, "processing"),
+        (true, None) => (100, "complete"),
+        (true, Some(_)) => (100, "error"),
+    };
+    app_handle.state::<LiveClassifications>().store(update);
+    job.report_progress(window, percentage, &message, state)
+}
+
+#[derive(Debug, Serialize)]
+pub struct WatchStarted {
+    pub job_id: String,
+    pub directory: String,
+}
+
+struct WatchSettings {
+    directory: PathBuf,
+    poll_interval: Duration,
+    include_existing: bool,
+    include_failed: bool,
+    database_id: String,
+    options: ClassificationOptions,
+}
+
+fn is_fastq(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    [".fastq", ".fq", ".fastq.gz", ".fq.gz"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// All FASTQ files below `dir`. MinKNOW's `fastq_fail` folders are skipped unless
+/// `include_failed` is set.
+fn scan_fastq_files(dir: &Path, include_failed: bool, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if !include_failed && entry.file_name() == "fastq_fail" {
+                continue;
+            }
+            scan_fastq_files(&path, include_failed, found);
+        } else if is_fastq(&path) {
+            found.push(path);
+        }
+    }
+}
+
+/// Running totals across files. Clade and direct read counts add up across disjoint
+/// sets of reads; k-mer counts do not, so they are left out.
+#[derive(Default)]
+struct CumulativeReport {
+    taxa: BTreeMap<u32, LiveTaxon>,
+    files_classified: usize,
+    reads_classified: u64,
+}
+
+impl CumulativeReport {
+    fn add(&mut self, results: &krakenuniq_rs::ClassificationResults) {
+        self.files_classified += 1;
+        self.reads_classified += results.kraken_output_lines.len() as u64;
+        for row in results.kraken_report_rows.iter().flatten() {
+            let taxon = self.taxa.entry(row.tax_id).or_insert_with(|| LiveTaxon {
+                tax_id: row.tax_id,
+                parent_tax_id: row.parent_tax_id,
+                tax_name: row.tax_name.trim().to_string(),
+                rank: row.rank.clone(),
+                reads: 0,
+                tax_reads: 0,
+                percentage: 0.0,
+            });
+            taxon.reads += row.reads;
+            taxon.tax_reads += row.tax_reads;
+        }
+    }
+
+    fn update(
+        &self,
+        job: &JobHandle,
+        settings: &WatchSettings,
+        latest_file: Option<&Path>,
+    ) -> LiveClassificationUpdate {
+        let total: u64 = self
+            .taxa
+            .values()
+            .filter(|t| t.parent_tax_id.is_none())
+            .map(|t| t.reads)
+            .sum();
+        let taxa = self
+            .taxa
+            .values()
+            .map(|t| LiveTaxon {
+                percentage: if total > 0 {
+                    t.reads as f64 * 100.0 / total as f64
+                } else {
+                    0.0
+                },
+                ..t.clone()
+            })
+            .collect();
+        LiveClassificationUpdate {
+            job_id: job.id().to_string(),
+            directory: settings.directory.to_string_lossy().to_string(),
+            files_classified: self.files_classified,
+            reads_classified: self.reads_classified,
+            latest_file: latest_file.map(|p| p.to_string_lossy().to_string()),
+            taxa,
+            finished: false,
+            error: None,
+        }
+    }
+}
+
+/// Waits up to `duration`, returning early (with `false`) once the job is cancelled.
+fn sleep_unless_cancelled(job: &JobHandle, duration: Duration) -> bool {
+    let mut waited = Duration::ZERO;
+    while waited < duration {
+        if job.is_cancelled() {
+            return false;
+        }
+        std::thread::sleep(CANCEL_POLL_INTERVAL);
+        waited += CANCEL_POLL_INTERVAL;
+    }
+    !job.is_cancelled()
+}
+
+fn watch_loop<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    window: &Window<R>,
+    job: &JobHandle,
+    settings: &WatchSettings,
+    cumulative: &mut CumulativeReport,
+) -> Result<(), PoleshiftError> {
+    // 1) Resolve and prepare the database once for the whole run
+    let resource_dir = resources_dir(app_handle)?;
+    maybe_decompress_config_files(&KrakenConfig::for_database(
+        &resource_dir,
+        &settings.database_id,
+        Vec::new(),
+    )?)?;
+    let classifier = ClassifierRun {
+        window,
+        job,
+        resource_dir: &resource_dir,
+        database_id: &settings.database_id,
+        options: &settings.options,
+        backend: backend_for(&settings.options, &resource_dir),
+        load_plan: None,
+        contaminants: None,
+        taxdb: None,
+        cache: None,
+        deterministic_ids: false,
+    };
+
+    // 2) Files already present count as seen unless the backlog should be classified
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    if !settings.include_existing {
+        let mut existing = Vec::new();
+        scan_fastq_files(&settings.directory, settings.include_failed, &mut existing);
+        seen.extend(existing);
+    }
+
+    // 3) Poll; a file is classified once its size stayed the same for one interval,
+    // so files MinKNOW is still writing are left alone
+    let mut sizes: HashMap<PathBuf, u64> = HashMap::new();
+    loop {
+        job.set_stage("watching");
+        job.emit_progress(window)?;
+
+        let mut found = Vec::new();
+        scan_fastq_files(&settings.directory, settings.include_failed, &mut found);
+        found.sort();
+        for path in found {
+            if seen.contains(&path) {
+                continue;
+            }
+            let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if size == 0 || sizes.insert(path.clone(), size) != Some(size) {
+                continue;
+            }
+
+            job.set_stage("classifying");
+            let reads_before = job.progress().reads_processed;
+            let config = KrakenConfig::for_database(
+                &resource_dir,
+                &settings.database_id,
+                vec![path.to_string_lossy().to_string()],
+            )?;
+            let results = run_classification(&classifier, config)?;
+            job.set_reads(reads_before + results.kraken_output_lines.len() as u64);
+            cumulative.add(&results);
+
+            seen.insert(path.clone());
+            sizes.remove(&path);
+            let update = cumulative.update(job, settings, Some(&path));
+            publish_update(app_handle, window, job, update)?;
+            job.check_cancelled()?;
+        }
+
+        if !sleep_unless_cancelled(job, settings.poll_interval) {
+            return Ok(());
+        }
+    }
+}
+
+/// Watches a MinKNOW output folder and classifies every new FASTQ file that appears
+/// in it. Runs in the background as a job until stopped with `cancel_job`; returns the
+/// job ID straight away. After each file the job reports on `job://{job_id}/progress`,
+/// and `live_classification` returns the taxa counted so far.
+///
+/// Files already in the folder are classified first unless `include_existing` is
+/// false. `fastq_fail` folders are ignored unless `include_failed` is set.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command(rename_all = "snake_case")]
+pub async fn watch_sequencing_directory<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    directory: String,
+    poll_interval_secs: Option<u64>,
+    include_existing: Option<bool>,
+    include_failed: Option<bool>,
+    database_id: Option<String>,
+    classification_options: Option<ClassificationOptions>,
+    job_id: Option<String>,
+) -> Result<StandardResponseNoFiles<WatchStarted>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let directory = PathBuf::from(&directory);
+        if !directory.is_dir() {
+            return Err(PoleshiftError::DataError(format!(
+                "Not a directory: {}",
+                directory.display()
+            )));
+        }
+        let defaults = current_settings(&app_handle);
+        let options = defaults.classification_options(classification_options);
+        options.validate()?;
+        let settings = WatchSettings {
+            directory,
+            poll_interval: poll_interval_secs
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_POLL_INTERVAL)
+                .max(MIN_POLL_INTERVAL),
+            include_existing: include_existing.unwrap_or(true),
+            include_failed: include_failed.unwrap_or(false),
+            database_id: database_id.unwrap_or(defaults.database_id),
+            options,
+        };
+
+        let window = app_handle
+            .get_window("main")
+            .ok_or(PoleshiftError::WindowNotFound)?;
+        app_handle.state::<LiveClassifications>().clear_finished();
+        let job = app_handle.state::<JobManager>().start("watch", job_id)?;
+        let started = WatchStarted {
+            job_id: job.id().to_string(),
+            directory: settings.directory.to_string_lossy().to_string(),
+        };
+
+        std::thread::spawn(move || {
+            let mut cumulative = CumulativeReport::default();
+            let result = watch_loop(&app_handle, &window, &job, &settings, &mut cumulative);
+
+            let mut last = cumulative.update(&job, &settings, None);
+            last.finished = true;
+            if let Err(e) = &result {
+                if !matches!(e, PoleshiftError::Cancelled(_)) {
+                    println!("Directory watch failed: {}", e);
+                    last.error = Some(e.to_string());
+                }
+            }
+            if let Err(e) = publish_update(&app_handle, &window, &job, last) {
+                println!("Could not report the end of the watch: {}", e);
+            }
+            app_handle
+                .state::<JobManager>()
+                .finish(job.id(), result.as_ref().err());
+        });
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: started,
+        })
+    })
+    .await
+}
+
+/// The taxa counted so far by the watch running as `job_id`, or by the last one that
+/// ran as it.
+#[tauri::command(rename_all = "snake_case")]
+pub fn live_classification(
+    invocation: Invocation,
+    live: State<'_, LiveClassifications>,
+    job_id: String,
+) -> Result<StandardResponseNoFiles<LiveClassificationUpdate>, PoleshiftError> {
+    traced_blocking(invocation, || {
+        let update = live
+            .get(&job_id)
+            .ok_or_else(|| PoleshiftError::InvalidInput {
+                field: "job_id".to_string(),
+                reason: format!("no directory watch ran as {}", job_id),
+            })?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: update,
+        })
+    })
+}
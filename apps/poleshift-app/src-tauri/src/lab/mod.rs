@@ -0,0 +1,363 @@
+//poleshift/src-tauri/src/lab/mod.rs
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::nutrients::replicate_stats;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Measurement types shipped with the app, relative to the resource directory.
+const BUNDLED_SCHEMA: &str = "lab_measurements.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabFieldKind {
+    /// A value or replicates, in `unit` or one of `units`
+    #[default]
+    Number,
+    Text,
+}
+
+/// A field of a measurement type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabField {
+    pub name: String,
+    /// Shown in the UI; the name when not given
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub kind: LabFieldKind,
+    /// Unit numbers are reported in
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Other units numbers may be given in, with the factor taking them to `unit`
+    #[serde(default)]
+    pub units: BTreeMap<String, f64>,
+    /// Valid range of numbers, in `unit`
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    /// Text allowed in a text field; any when empty
+    #[serde(default)]
+    pub choices: Vec<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// An assay, e.g. DOC or flow cytometry counts, and the fields it reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabMeasurementType {
+    pub id: String,
+    pub name: String,
+    #[serde(rename(deserialize = "field"))]
+    pub fields: Vec<LabField>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LabSchema {
+    #[serde(default)]
+    measurement: Vec<LabMeasurementType>,
+}
+
+/// A value as sent for a field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LabValue {
+    Number(f64),
+    Replicates(Vec<f64>),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabFieldInput {
+    pub field: String,
+    pub value: LabValue,
+    /// Unit of a number, when not the field's `unit`
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// A field's value, with numbers in the field's unit.
+#[derive(Debug, Clone, Serialize)]
+pub struct LabFieldResult {
+    pub field: String,
+    pub label: String,
+    pub unit: Option<String>,
+    pub values: Vec<f64>,
+    /// Mean, sample SD and CV (in percent) of the values; SD and CV need replicates
+    pub mean: Option<f64>,
+    pub sd: Option<f64>,
+    pub cv: Option<f64>,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LabMeasurementReport {
+    pub sample_id: String,
+    pub measurement_type: String,
+    pub name: String,
+    /// In the order of the schema's fields
+    pub results: Vec<LabFieldResult>,
+}
+
+/// `µmol C/L` and `umol c/l` alike: lowercase, without spaces, with `u` for µ.
+fn unit_key(unit: &str) -> String {
+    unit.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == 'µ' || c == 'μ' { 'u' } else { c })
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Checks that a schema is usable: unique IDs and field names, units for number
+/// fields, positive conversion factors and ranges with `min` ≤ `max`.
+fn validate_schema(schema: &LabSchema) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for measurement in &schema.measurement {
+        if !ids.insert(measurement.id.as_str()) {
+            return Err(format!("measurement `{}` is defined twice", measurement.id));
+        }
+        let mut names = HashSet::new();
+        for field in &measurement.fields {
+            let at = format!("field `{}` of `{}`", field.name, measurement.id);
+            if !names.insert(field.name.as_str()) {
+                return Err(format!("{} is defined twice", at));
+            }
+            if field.kind == LabFieldKind::Text {
+                continue;
+            }
+            if field.unit.is_none() && !field.units.is_empty() {
+                return Err(format!("{} has `units` but no `unit`", at));
+            }
+            if field
+                .units
+                .values()
+                .any(|factor| !(factor.is_finite() && *factor > 0.0))
+            {
+                return Err(format!("{} has a unit factor that is not above 0", at));
+            }
+            if let (Some(min), Some(max)) = (field.min, field.max) {
+                if min > max {
+                    return Err(format!("{} has `min` above `max`", at));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the measurement types of a schema file.
+pub(crate) fn load_schema(path: &Path) -> Result<Vec<LabMeasurementType>, PoleshiftError> {
+    let invalid = |reason: String| {
+        PoleshiftError::DataError(format!(
+            "Invalid lab measurement schema {}: {}",
+            path.display(),
+            reason
+        ))
+    };
+    let content = std::fs::read_to_string(path)?;
+    let schema: LabSchema = toml::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+    validate_schema(&schema).map_err(invalid)?;
+    Ok(schema.measurement)
+}
+
+/// `schema_path`, or the schema bundled with the app.
+fn schema_path<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    schema_path: Option<String>,
+) -> Result<PathBuf, PoleshiftError> {
+    match schema_path {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(app_handle
+            .path()
+            .resource_dir()
+            .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+            .join("./resources")
+            .join(BUNDLED_SCHEMA)),
+    }
+}
+
+/// A field's input checked against the schema, with numbers converted to its unit.
+fn field_result(
+    field: &LabField,
+    input: Option<&LabFieldInput>,
+) -> Result<LabFieldResult, PoleshiftError> {
+    let invalid = |reason: String| PoleshiftError::InvalidInput {
+        field: format!("values.{}", field.name),
+        reason,
+    };
+    let mut result = LabFieldResult {
+        field: field.name.clone(),
+        label: field.label.clone().unwrap_or_else(|| field.name.clone()),
+        unit: field.unit.clone(),
+        values: Vec::new(),
+        mean: None,
+        sd: None,
+        cv: None,
+        text: None,
+    };
+    let Some(input) = input else {
+        return if field.required {
+            Err(invalid("is required".to_string()))
+        } else {
+            Ok(result)
+        };
+    };
+
+    match field.kind {
+        LabFieldKind::Text => {
+            let LabValue::Text(text) = &input.value else {
+                return Err(invalid("must be text".to_string()));
+            };
+            let text = text.trim();
+            if field.required && text.is_empty() {
+                return Err(invalid("is required".to_string()));
+            }
+            if !field.choices.is_empty() && !field.choices.iter().any(|c| c == text) {
+                return Err(invalid(format!(
+                    "must be one of {}",
+                    field.choices.join(", ")
+                )));
+            }
+            result.text = Some(text.to_string());
+        }
+        LabFieldKind::Number => {
+            // 1) Unit factor
+            let factor = match (&input.unit, &field.unit) {
+                (None, _) => 1.0,
+                (Some(unit), Some(own)) if unit_key(unit) == unit_key(own) => 1.0,
+                (Some(unit), _) => field
+                    .units
+                    .iter()
+                    .find(|(name, _)| unit_key(name) == unit_key(unit))
+                    .map(|(_, factor)| *factor)
+                    .ok_or_else(|| invalid(format!("`{}` is not a unit it takes", unit)))?,
+            };
+
+            // 2) Values, in range
+            let values = match &input.value {
+                LabValue::Number(value) => vec![*value],
+                LabValue::Replicates(values) => values.clone(),
+                LabValue::Text(_) => {
+                    return Err(invalid("must be a number or replicates".to_string()))
+                }
+            };
+            if values.is_empty() {
+                return Err(invalid("must hold a value or its replicates".to_string()));
+            }
+            result.values = values.iter().map(|value| value * factor).collect();
+            for value in &result.values {
+                let below = field.min.is_some_and(|min| *value < min);
+                let above = field.max.is_some_and(|max| *value > max);
+                if !value.is_finite() || below || above {
+                    return Err(invalid(format!(
+                        "{} is outside its range of {} to {}{}",
+                        value,
+                        field.min.map_or("-∞".to_string(), |min| min.to_string()),
+                        field.max.map_or("∞".to_string(), |max| max.to_string()),
+                        field
+                            .unit
+                            .as_ref()
+                            .map_or(String::new(), |u| format!(" {}", u))
+                    )));
+                }
+            }
+            let (mean, sd, cv) = replicate_stats(&result.values);
+            (result.mean, result.sd, result.cv) = (Some(mean), sd, cv);
+        }
+    }
+    Ok(result)
+}
+
+/// Checks a sample's values against its measurement type and reports them in the
+/// type's units.
+pub(crate) fn lab_measurement_report(
+    measurement: &LabMeasurementType,
+    sample_id: &str,
+    values: &[LabFieldInput],
+) -> Result<LabMeasurementReport, PoleshiftError> {
+    for (i, input) in values.iter().enumerate() {
+        if !measurement.fields.iter().any(|f| f.name == input.field) {
+            return Err(PoleshiftError::InvalidInput {
+                field: format!("values[{}].field", i),
+                reason: format!("`{}` has no field `{}`", measurement.id, input.field),
+            });
+        }
+        if values[..i].iter().any(|other| other.field == input.field) {
+            return Err(PoleshiftError::InvalidInput {
+                field: format!("values[{}].field", i),
+                reason: format!("`{}` is given twice", input.field),
+            });
+        }
+    }
+    let results = measurement
+        .fields
+        .iter()
+        .map(|field| field_result(field, values.iter().find(|v| v.field == field.name)))
+        .collect::<Result<_, _>>()?;
+    Ok(LabMeasurementReport {
+        sample_id: sample_id.to_string(),
+        measurement_type: measurement.id.clone(),
+        name: measurement.name.clone(),
+        results,
+    })
+}
+
+/// Lists the measurement types of the bundled schema, or of `schema_path`, for the
+/// UI to build its forms from.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_lab_measurement_types<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    schema_path: Option<String>,
+) -> Result<StandardResponseNoFiles<Vec<LabMeasurementType>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let path = self::schema_path(&app_handle, schema_path)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: load_schema(&path)?,
+        })
+    })
+    .await
+}
+
+/// Processes a lab measurement of any type the schema defines (see
+/// `resources/lab_measurements.toml`): each value is checked against its field's
+/// kind, unit and range, numbers are converted to the field's unit, and replicates
+/// are reported with their mean, SD and CV. New assays only need a schema entry.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn handle_lab_measurement<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    sample_id: String,
+    measurement_type: String,
+    values: Vec<LabFieldInput>,
+    schema_path: Option<String>,
+) -> Result<StandardResponseNoFiles<LabMeasurementReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let path = self::schema_path(&app_handle, schema_path)?;
+        let types = load_schema(&path)?;
+        let measurement = types
+            .iter()
+            .find(|t| t.id == measurement_type)
+            .ok_or_else(|| PoleshiftError::InvalidInput {
+                field: "measurement_type".to_string(),
+                reason: format!(
+                    "`{}` is not defined in {}",
+                    measurement_type,
+                    path.display()
+                ),
+            })?;
+        let report = lab_measurement_report(measurement, &sample_id, &values)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
@@ -1,15 +1,94 @@
+mod auth;
 mod chat;
-mod handle_ctd_data;
+mod ctd;
+mod demo;
+mod devtools;
+mod error_reporting;
+mod fastq_tools;
 mod io;
 mod krakenuniq;
+mod lab;
+mod nutrients;
 mod poleshift_common;
+mod results_store;
+mod settings;
 mod splashscreen;
+mod stats;
+mod supabase_connector;
+mod sync;
 
+use auth::{get_auth_state, restore_auth_session, set_auth_session, AuthManager};
 use chat::create_chatbot_session;
-use handle_ctd_data::handle_ctd_data;
+use ctd::batch::handle_ctd_batch;
+use ctd::bottles::extract_ctd_bottles;
+use ctd::calibration::recalibrate_ctd_rows;
+use ctd::export::{export_ctd_csv, export_ctd_netcdf, export_ctd_odv};
+use ctd::handle_ctd_data::handle_ctd_data;
+use ctd::output_store::{query_ctd_processed_data, query_ctd_raw_data};
+use ctd::sample_match::match_ctd_to_sample;
+use ctd::section::build_ctd_section;
+use demo::generate_demo_data;
+use devtools::{clear_command_trace, get_command_trace, set_dev_mode, CommandInspector};
+use error_reporting::{
+    clear_error_reports, list_error_reports, set_error_reporting_consent, submit_error_reports,
+    ErrorReporter,
+};
+use fastq_tools::merge_fastq_files;
+use krakenuniq::backend::probe::probe_classifier;
+use krakenuniq::extract_reads::extract_reads_by_taxon;
 use krakenuniq::handle_sequence_data::handle_sequence_data;
+use krakenuniq::hierarchy::{
+    benchmark_taxonomy_hierarchy, build_taxonomy_hierarchy, collapse_taxonomy_hierarchy,
+    merge_taxonomy_hierarchies, prune_taxonomy_hierarchy,
+};
+use krakenuniq::import::import_external_classification;
+use krakenuniq::kmer_hash::benchmark_kmer_hashing;
+use krakenuniq::krona::export_krona;
+use krakenuniq::negative_control::subtract_control;
+use krakenuniq::output_store::{
+    query_classification_report, query_classified_reads, query_raw_sequences,
+};
+use krakenuniq::preload::plan_database_load;
+use krakenuniq::queue::queue_classification_jobs;
+use krakenuniq::refilter::refilter_report;
+use krakenuniq::report_parser::inspect_report_file;
+use krakenuniq::result_cache::{clear_result_cache, list_result_cache};
+use krakenuniq::run_metadata::summarize_run_metadata;
+use krakenuniq::screen::quick_screen_sample;
+use krakenuniq::taxonomy_search::query_taxonomy;
+use krakenuniq::taxonomy_service::{children_of, lineage_of, lookup_taxon, TaxonomyCache};
+use krakenuniq::tree_export::export_taxonomy_tree;
+use krakenuniq::watch::{live_classification, watch_sequencing_directory, LiveClassifications};
+use lab::{handle_lab_measurement, list_lab_measurement_types};
+use nutrients::handle_nutrient_data::handle_nutrient_data;
+use nutrients::import::import_nutrient_csv;
+use poleshift_common::jobs::{cancel_job, list_jobs, JobManager};
+use results_store::{delete_stored_result, get_stored_result, list_stored_results};
+use settings::{choose_classifier_executable, get_settings, update_settings, SettingsStore};
 use tauri::Manager;
-use crate::splashscreen::{close_splashscreen, download_resources};
+use crate::splashscreen::{
+    close_splashscreen, download_resources, list_databases, verify_resources,
+};
+use stats::abundance_matrix::build_abundance_matrix;
+use stats::aggregate_export::export_aggregated_stats;
+use stats::compare::compare_classifications;
+use stats::diversity::{compute_alpha_diversity, compute_beta_diversity};
+use stats::rarefaction::compute_rarefaction;
+use stats::region::process_region_stats;
+use stats::sidebar::process_sidebar_stats;
+use stats::sidebar_store::process_stored_sidebar_stats;
+use stats::summary::summarize_report;
+use stats::trends::compute_station_trends;
+use supabase_connector::query::{fetch_processed_data, fetch_samples};
+use supabase_connector::realtime::{
+    subscribe_table_changes, unsubscribe_table_changes, RealtimeBridge,
+};
+use supabase_connector::storage::{download_raw_file, upload_raw_file};
+use supabase_connector::upload::upload_data;
+use sync::{
+    list_sync_conflicts, queue_upload, resolve_sync_conflict, retry_failed_uploads, sync_status,
+    SyncQueue,
+};
 
 pub fn run() {
     let mut builder = tauri::Builder::default();
@@ -18,13 +97,110 @@ pub fn run() {
             .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
                 let _ = app.get_webview_window("main").expect("no main window");
             }))
+            .manage(AuthManager::default())
+            .manage(CommandInspector::default())
+            .manage(ErrorReporter::default())
+            .manage(JobManager::default())
+            .manage(LiveClassifications::default())
+            .manage(TaxonomyCache::default())
+            .manage(SettingsStore::default())
+            .manage(SyncQueue::default())
+            .manage(RealtimeBridge::default())
             // Register your new commands here
             .invoke_handler(tauri::generate_handler![
                 handle_ctd_data,
                 handle_sequence_data,
                 create_chatbot_session,
                 download_resources,
-                close_splashscreen
+                verify_resources,
+                close_splashscreen,
+                export_aggregated_stats,
+                set_dev_mode,
+                get_command_trace,
+                clear_command_trace,
+                merge_fastq_files,
+                generate_demo_data,
+                summarize_run_metadata,
+                cancel_job,
+                list_jobs,
+                list_databases,
+                query_classification_report,
+                query_classified_reads,
+                query_raw_sequences,
+                export_krona,
+                extract_reads_by_taxon,
+                refilter_report,
+                compare_classifications,
+                build_abundance_matrix,
+                compute_alpha_diversity,
+                compute_beta_diversity,
+                compute_rarefaction,
+                watch_sequencing_directory,
+                live_classification,
+                plan_database_load,
+                probe_classifier,
+                query_taxonomy,
+                summarize_report,
+                quick_screen_sample,
+                subtract_control,
+                list_result_cache,
+                clear_result_cache,
+                queue_classification_jobs,
+                benchmark_kmer_hashing,
+                inspect_report_file,
+                import_external_classification,
+                query_ctd_raw_data,
+                query_ctd_processed_data,
+                export_ctd_netcdf,
+                export_ctd_csv,
+                export_ctd_odv,
+                build_ctd_section,
+                extract_ctd_bottles,
+                recalibrate_ctd_rows,
+                handle_ctd_batch,
+                match_ctd_to_sample,
+                process_sidebar_stats,
+                process_region_stats,
+                compute_station_trends,
+                process_stored_sidebar_stats,
+                build_taxonomy_hierarchy,
+                benchmark_taxonomy_hierarchy,
+                prune_taxonomy_hierarchy,
+                collapse_taxonomy_hierarchy,
+                merge_taxonomy_hierarchies,
+                export_taxonomy_tree,
+                lookup_taxon,
+                children_of,
+                lineage_of,
+                handle_nutrient_data,
+                import_nutrient_csv,
+                handle_lab_measurement,
+                list_lab_measurement_types,
+                list_error_reports,
+                set_error_reporting_consent,
+                submit_error_reports,
+                clear_error_reports,
+                get_settings,
+                update_settings,
+                choose_classifier_executable,
+                list_stored_results,
+                get_stored_result,
+                delete_stored_result,
+                queue_upload,
+                sync_status,
+                retry_failed_uploads,
+                set_auth_session,
+                restore_auth_session,
+                get_auth_state,
+                upload_raw_file,
+                download_raw_file,
+                upload_data,
+                list_sync_conflicts,
+                resolve_sync_conflict,
+                subscribe_table_changes,
+                unsubscribe_table_changes,
+                fetch_samples,
+                fetch_processed_data
             ])
             .plugin(tauri_plugin_positioner::init())
             .plugin(tauri_plugin_updater::Builder::new().build())
@@ -34,6 +210,22 @@ pub fn run() {
             .plugin(tauri_plugin_dialog::init());
     }
     builder
+        .setup(|app| {
+            if let Err(e) = app.state::<ErrorReporter>().install(app.handle()) {
+                eprintln!("Error reporting is off: {}", e);
+            }
+            if let Err(e) = app.state::<SettingsStore>().load(app.handle()) {
+                eprintln!("Using default settings: {}", e);
+            }
+            let settings = app.state::<SettingsStore>().get();
+            app.state::<CommandInspector>()
+                .set_enabled(settings.dev_mode, Some(settings.dev_trace_capacity));
+            app.state::<JobManager>().emit_status_to(app.handle().clone());
+            app.state::<SyncQueue>().start(app.handle().clone());
+            app.state::<AuthManager>().start(app.handle().clone());
+            app.state::<RealtimeBridge>().start(app.handle().clone());
+            Ok(())
+        })
         .run(tauri::generate_context!())
         .expect("Error while running Tauri application");
 }
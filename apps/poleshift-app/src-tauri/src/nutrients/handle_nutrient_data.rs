@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::nutrients::speciation::{
+    sample_conditions, speciate, AmmoniaSpeciation, SpeciationOptions,
+};
+use crate::nutrients::{from_micromolar, replicate_stats, to_micromolar, Nutrient, NutrientUnit};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::parse_uuid;
+use crate::results_store::{results_store_path, save_result, ResultKeys, ResultKind};
+
+/// One nutrient measured on a sample: a single value, or replicates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NutrientEntry {
+    pub nutrient: Nutrient,
+    pub values: Vec<f64>,
+    #[serde(default)]
+    pub unit: NutrientUnit,
+    /// Lowest concentration the method reads reliably, in `unit`
+    #[serde(default)]
+    pub detection_limit: Option<f64>,
+}
+
+/// A nutrient's concentration from its replicates.
+#[derive(Debug, Clone, Serialize)]
+pub struct NutrientResult {
+    pub nutrient: Nutrient,
+    /// Unit the values were given in
+    pub unit: NutrientUnit,
+    /// The values as given, in µmol/L
+    pub values: Vec<f64>,
+    /// Mean of the replicates, in µmol/L, mg/L of the compound and mg/L of its N, P
+    /// or Si
+    pub mean: f64,
+    pub mean_mg_per_litre: f64,
+    pub mean_mg_element_per_litre: f64,
+    /// Sample standard deviation of the replicates, in µmol/L; none for one value
+    pub sd: Option<f64>,
+    /// Coefficient of variation of the replicates (SD over mean), in percent
+    pub cv: Option<f64>,
+    /// In µmol/L
+    pub detection_limit: Option<f64>,
+    /// Replicates below the detection limit, and whether their mean is
+    pub replicates_below_detection: usize,
+    pub below_detection: bool,
+}
+
+/// The nutrients of one sample.
+#[derive(Debug, Clone, Serialize)]
+pub struct NutrientReport {
+    pub sample_id: String,
+    /// In the order of `Nutrient`
+    pub results: Vec<NutrientResult>,
+    /// NH₃ and NH₄⁺ of the ammonia reading, when asked for
+    pub ammonia_speciation: Option<AmmoniaSpeciation>,
+    /// Dissolved inorganic nitrogen (ammonium, nitrate and nitrite), in µmol/L; none
+    /// unless one of them was measured
+    pub din: Option<f64>,
+    /// DIN to phosphate, by moles, for Redfield comparisons
+    pub n_to_p: Option<f64>,
+}
+
+fn validate_entries(entries: &[NutrientEntry]) -> Result<(), PoleshiftError> {
+    if entries.is_empty() {
+        return Err(PoleshiftError::InvalidInput {
+            field: "entries".to_string(),
+            reason: "must hold at least one nutrient".to_string(),
+        });
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        let invalid = |reason: &str| PoleshiftError::InvalidInput {
+            field: format!("entries[{}].values", i),
+            reason: reason.to_string(),
+        };
+        if entry.values.is_empty() {
+            return Err(invalid("must hold a value or its replicates"));
+        }
+        if !entry.values.iter().all(|v| v.is_finite() && *v >= 0.0) {
+            return Err(invalid("must be 0 or more"));
+        }
+        if entry
+            .detection_limit
+            .is_some_and(|limit| !(limit.is_finite() && limit >= 0.0))
+        {
+            return Err(PoleshiftError::InvalidInput {
+                field: format!("entries[{}].detection_limit", i),
+                reason: "must be 0 or more".to_string(),
+            });
+        }
+        if entries[..i].iter().any(|e| e.nutrient == entry.nutrient) {
+            return Err(PoleshiftError::InvalidInput {
+                field: format!("entries[{}].nutrient", i),
+                reason: format!("{:?} is given twice", entry.nutrient),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn nutrient_result(entry: &NutrientEntry) -> NutrientResult {
+    let values: Vec<f64> = entry
+        .values
+        .iter()
+        .map(|&value| to_micromolar(entry.nutrient, value, entry.unit))
+        .collect();
+    let (mean, sd, cv) = replicate_stats(&values);
+    let detection_limit = entry
+        .detection_limit
+        .map(|limit| to_micromolar(entry.nutrient, limit, entry.unit));
+    let below = |value: f64| detection_limit.is_some_and(|limit| value < limit);
+    NutrientResult {
+        nutrient: entry.nutrient,
+        unit: entry.unit,
+        mean_mg_per_litre: from_micromolar(entry.nutrient, mean, NutrientUnit::MilligramPerLitre),
+        mean_mg_element_per_litre: from_micromolar(
+            entry.nutrient,
+            mean,
+            NutrientUnit::MilligramElementPerLitre,
+        ),
+        cv,
+        detection_limit,
+        replicates_below_detection: values.iter().filter(|&&v| below(v)).count(),
+        below_detection: below(mean),
+        values,
+        mean,
+        sd,
+    }
+}
+
+/// Sets the report's DIN and N:P ratio from its results. Ammonia stands in for
+/// ammonium where that was not measured, as the total ammonia nitrogen its
+/// speciation gives when the report has one.
+pub(crate) fn derive_totals(report: &mut NutrientReport) {
+    let mean = |nutrient: Nutrient| {
+        report
+            .results
+            .iter()
+            .find(|result| result.nutrient == nutrient)
+            .map(|result| result.mean)
+    };
+    let ammonia = match &report.ammonia_speciation {
+        Some(speciation) => Some(speciation.total_ammonia_nitrogen),
+        None => mean(Nutrient::Ammonia),
+    };
+    let ammonium = mean(Nutrient::Ammonium).or(ammonia);
+    let nitrogen = [ammonium, mean(Nutrient::Nitrate), mean(Nutrient::Nitrite)];
+    let din = nitrogen
+        .iter()
+        .any(Option::is_some)
+        .then(|| nitrogen.iter().flatten().sum::<f64>());
+    report.n_to_p = match (din, mean(Nutrient::Phosphate)) {
+        (Some(din), Some(phosphate)) if phosphate > 0.0 => Some(din / phosphate),
+        _ => None,
+    };
+    report.din = din;
+}
+
+/// Converts a sample's nutrient entries to µmol/L with the mean, SD and CV of their
+/// replicates, flags those under their detection limit, and derives DIN and the N:P
+/// ratio (see `derive_totals`).
+pub(crate) fn nutrient_report(
+    sample_id: &str,
+    entries: &[NutrientEntry],
+) -> Result<NutrientReport, PoleshiftError> {
+    validate_entries(entries)?;
+    let mut results: Vec<NutrientResult> = entries.iter().map(nutrient_result).collect();
+    results.sort_by_key(|result| result.nutrient);
+
+    let mut report = NutrientReport {
+        sample_id: sample_id.to_string(),
+        results,
+        ammonia_speciation: None,
+        din: None,
+        n_to_p: None,
+    };
+    derive_totals(&mut report);
+    Ok(report)
+}
+
+/// Processes the nutrient measurements of a sample (ammonia, ammonium, nitrate,
+/// nitrite, phosphate and silicate), each a single value or replicates in any
+/// supported unit, into one report in µmol/L (see `nutrient_report`). With
+/// `speciation`, an ammonia reading is split into NH₃ and NH₄⁺ at the sample's
+/// temperature, salinity and pH, and DIN counts its total ammonia nitrogen.
+///
+/// With a `processed_data_id` the report is also saved to the local results store
+/// under it and `org_id` (see `list_stored_results`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn handle_nutrient_data<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    sample_id: String,
+    entries: Vec<NutrientEntry>,
+    speciation: Option<SpeciationOptions>,
+    processed_data_id: Option<String>,
+    org_id: Option<String>,
+) -> Result<StandardResponseNoFiles<NutrientReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if let Some(id) = &processed_data_id {
+            parse_uuid("processed_data_id", id)?;
+        }
+        let mut report = nutrient_report(&sample_id, &entries)?;
+        if let Some(options) = speciation {
+            options.validate()?;
+            let ammonia = report
+                .results
+                .iter()
+                .find(|result| result.nutrient == Nutrient::Ammonia)
+                .map(|result| result.mean)
+                .ok_or_else(|| PoleshiftError::InvalidInput {
+                    field: "speciation".to_string(),
+                    reason: "needs an ammonia entry".to_string(),
+                })?;
+            let conditions = sample_conditions(&app_handle, &options)?;
+            report.ammonia_speciation = Some(speciate(ammonia, options.reading, &conditions));
+            derive_totals(&mut report);
+        }
+        if let Some(processed_data_id) = processed_data_id {
+            let keys = ResultKeys {
+                processed_data_id,
+                kind: ResultKind::Nutrients,
+                sample_id,
+                org_id,
+                user_id: None,
+                raw_data_id: None,
+            };
+            save_result(&results_store_path(&app_handle)?, &keys, &report, None)?;
+        }
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
@@ -0,0 +1,532 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::delimited::{pick_delimiter, split_fields, split_header, DelimitedOptions};
+use crate::devtools::{traced, Invocation};
+use crate::nutrients::handle_nutrient_data::{nutrient_report, NutrientEntry, NutrientReport};
+use crate::nutrients::{to_micromolar, Nutrient, NutrientUnit};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Header names taken for the sample-ID column when `sample_column` is not given.
+const SAMPLE_COLUMN_NAMES: &[&str] = &["sample_id", "sample id", "sample", "sample name", "id"];
+const PLATE_ROWS: &str = "ABCDEFGH";
+const PLATE_COLUMNS: usize = 12;
+
+/// What a well of a plate holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WellContent {
+    /// A standard, its concentration in the plate's `unit`
+    Standard { concentration: f64 },
+    /// A reagent blank; their mean is taken off every reading
+    Blank,
+    /// A sample; replicate wells share its ID. `dilution` is the factor it was diluted
+    /// by before reading
+    Sample {
+        sample_id: String,
+        #[serde(default = "default_dilution")]
+        dilution: f64,
+    },
+}
+
+fn default_dilution() -> f64 {
+    1.0
+}
+
+/// A 96-well plate-reader export: an 8×12 grid of readings with a header row of
+/// column numbers and rows labelled A to H, as most readers write it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlateLayout {
+    /// The nutrient the plate was read for
+    pub nutrient: Nutrient,
+    /// Unit of the standards, and so of the concentrations found from them
+    #[serde(default)]
+    pub unit: NutrientUnit,
+    /// Contents by well, e.g. `A1` or `H12`; other wells are not read
+    pub wells: HashMap<String, WellContent>,
+    /// Grid to read when the file holds several (e.g. one per wavelength), from 0
+    #[serde(default)]
+    pub block: usize,
+    /// Fit the standard curve through the origin, for blank-corrected assays
+    #[serde(default)]
+    pub through_origin: bool,
+}
+
+/// A table with a sample-ID column and one column per nutrient; rows sharing a
+/// sample ID are its replicates.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TableLayout {
+    /// Header of the sample-ID column; by default the first of `sample_id`,
+    /// `sample` and the like
+    pub sample_column: Option<String>,
+    /// Unit of nutrient columns whose headers do not carry one as `NO3 [µmol/L]`
+    pub unit: NutrientUnit,
+    /// Nutrient of columns, by header, for headers not named after one
+    pub columns: HashMap<String, Nutrient>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "layout", rename_all = "snake_case")]
+pub enum NutrientFileLayout {
+    Table(TableLayout),
+    Plate(PlateLayout),
+}
+
+/// A lab CSV export to import.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NutrientImportFile {
+    pub path: String,
+    /// Field separator; by default guessed as for CTD CSV files
+    #[serde(default)]
+    pub delimiter: Option<char>,
+    #[serde(flatten)]
+    pub layout: NutrientFileLayout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NutrientImportOptions {
+    /// Standard curves fitting worse than this are reported in `warnings`
+    pub min_r_squared: f64,
+}
+
+impl Default for NutrientImportOptions {
+    fn default() -> Self {
+        NutrientImportOptions {
+            min_r_squared: 0.99,
+        }
+    }
+}
+
+impl NutrientImportOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if !(0.0..=1.0).contains(&self.min_r_squared) {
+            return Err(PoleshiftError::InvalidInput {
+                field: "options.min_r_squared".to_string(),
+                reason: "must be between 0 and 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A standard and its blank-corrected reading.
+#[derive(Debug, Clone, Serialize)]
+pub struct StandardPoint {
+    pub well: String,
+    pub concentration: f64,
+    pub reading: f64,
+}
+
+/// The line fitted through a plate's standards: reading = slope · concentration +
+/// intercept, concentrations in `unit`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StandardCurve {
+    pub path: String,
+    pub nutrient: Nutrient,
+    pub unit: NutrientUnit,
+    pub slope: f64,
+    pub intercept: f64,
+    pub r_squared: Option<f64>,
+    /// Mean reading of the blanks, taken off every other reading
+    pub blank: Option<f64>,
+    pub standards: Vec<StandardPoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NutrientImportReport {
+    /// One per sample, by sample ID
+    pub reports: Vec<NutrientReport>,
+    /// One per plate, in file order
+    pub curves: Vec<StandardCurve>,
+    /// Values left out or extrapolated, and poor standard curves
+    pub warnings: Vec<String>,
+}
+
+/// µmol/L values read for each sample and nutrient, over every file.
+type SampleValues = BTreeMap<String, BTreeMap<Nutrient, Vec<f64>>>;
+
+/// Lines of a file that are neither blank nor `#` comments, without a byte-order mark.
+fn data_lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+}
+
+/// A reading, or None for empty cells and reader flags such as `OVRFLW`.
+fn parse_value(field: &str) -> Option<f64> {
+    field.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// `A1` to `H12` (also written `A01`) as a row and column index.
+fn parse_well(well: &str) -> Option<(usize, usize)> {
+    let well = well.trim().to_uppercase();
+    let row = PLATE_ROWS.find(well.get(..1)?)?;
+    let column: usize = well.get(1..)?.parse().ok()?;
+    (1..=PLATE_COLUMNS)
+        .contains(&column)
+        .then_some((row, column - 1))
+}
+
+/// The `block`th 8×12 grid of readings in a plate-reader export.
+fn read_plate_grid(
+    lines: &[Vec<String>],
+    block: usize,
+) -> Option<Vec<[Option<f64>; PLATE_COLUMNS]>> {
+    let is_header = |fields: &Vec<String>| {
+        fields.len() > PLATE_COLUMNS
+            && (1..=PLATE_COLUMNS).all(|c| fields[c].trim() == c.to_string())
+    };
+    let start = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, fields)| is_header(fields))
+        .nth(block)?
+        .0;
+    let mut grid = Vec::new();
+    for (row, fields) in PLATE_ROWS.chars().zip(&lines[start + 1..]) {
+        if fields[0].trim() != row.to_string() {
+            return None;
+        }
+        let mut readings = [None; PLATE_COLUMNS];
+        for (c, reading) in readings.iter_mut().enumerate() {
+            *reading = fields.get(c + 1).and_then(|f| parse_value(f));
+        }
+        grid.push(readings);
+    }
+    (grid.len() == PLATE_ROWS.len()).then_some(grid)
+}
+
+/// The least-squares line through `(concentration, reading)` points, as slope,
+/// intercept and r².
+fn fit_line(points: &[(f64, f64)], through_origin: bool) -> Option<(f64, f64, Option<f64>)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (slope, intercept) = if through_origin {
+        let sxx: f64 = points.iter().map(|(x, _)| x * x).sum();
+        let sxy: f64 = points.iter().map(|(x, y)| x * y).sum();
+        (sxx > 0.0).then(|| (sxy / sxx, 0.0))?
+    } else {
+        let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        let sxy: f64 = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum();
+        let slope = (sxx > 0.0).then(|| sxy / sxx)?;
+        (slope, mean_y - slope * mean_x)
+    };
+    let ss_total: f64 = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+    let ss_residual: f64 = points
+        .iter()
+        .map(|(x, y)| (y - slope * x - intercept).powi(2))
+        .sum();
+    Some((
+        slope,
+        intercept,
+        (ss_total > 0.0).then(|| 1.0 - ss_residual / ss_total),
+    ))
+}
+
+/// Fits the plate's standard curve and adds the concentration of each sample well.
+fn import_plate(
+    path: &str,
+    lines: &[Vec<String>],
+    layout: &PlateLayout,
+    options: &NutrientImportOptions,
+    values: &mut SampleValues,
+    warnings: &mut Vec<String>,
+) -> Result<StandardCurve, PoleshiftError> {
+    let invalid = |well: &str, reason: &str| PoleshiftError::InvalidInput {
+        field: format!("wells.{}", well),
+        reason: reason.to_string(),
+    };
+
+    // 1) The grid, and the reading of each well of the layout
+    let grid = read_plate_grid(lines, layout.block).ok_or_else(|| {
+        PoleshiftError::DataError(format!(
+            "Could not read {}: no 8×12 plate grid {} (a row of column numbers 1 to 12 \
+             followed by rows A to H)",
+            path, layout.block
+        ))
+    })?;
+    let mut wells: Vec<(&String, &WellContent, f64)> = Vec::new();
+    for (well, content) in &layout.wells {
+        let (row, column) =
+            parse_well(well).ok_or_else(|| invalid(well, "is not a well from A1 to H12"))?;
+        match content {
+            WellContent::Standard { concentration }
+                if !concentration.is_finite() || *concentration < 0.0 =>
+            {
+                return Err(invalid(well, "concentration must be 0 or more"));
+            }
+            WellContent::Sample { dilution, .. } if !dilution.is_finite() || *dilution <= 0.0 => {
+                return Err(invalid(well, "dilution must be more than 0"));
+            }
+            _ => {}
+        }
+        match grid[row][column] {
+            Some(reading) => wells.push((well, content, reading)),
+            None => warnings.push(format!("{}: well {} has no reading", path, well)),
+        }
+    }
+    wells.sort_by_key(|(well, _, _)| parse_well(well));
+
+    // 2) Blank correction
+    let blanks: Vec<f64> = wells
+        .iter()
+        .filter(|(_, content, _)| matches!(content, WellContent::Blank))
+        .map(|(_, _, reading)| *reading)
+        .collect();
+    let blank = (!blanks.is_empty()).then(|| blanks.iter().sum::<f64>() / blanks.len() as f64);
+    let corrected = |reading: f64| reading - blank.unwrap_or(0.0);
+
+    // 3) Standard curve
+    let standards: Vec<StandardPoint> = wells
+        .iter()
+        .filter_map(|(well, content, reading)| match content {
+            WellContent::Standard { concentration } => Some(StandardPoint {
+                well: well.to_string(),
+                concentration: *concentration,
+                reading: corrected(*reading),
+            }),
+            _ => None,
+        })
+        .collect();
+    let points: Vec<(f64, f64)> = standards
+        .iter()
+        .map(|standard| (standard.concentration, standard.reading))
+        .collect();
+    let (slope, intercept, r_squared) = fit_line(&points, layout.through_origin)
+        .filter(|(slope, _, _)| *slope > 0.0)
+        .ok_or_else(|| {
+            PoleshiftError::DataError(format!(
+                "{}: the standards give no rising curve; it needs readings at two or more \
+                 concentrations",
+                path
+            ))
+        })?;
+    if r_squared.is_some_and(|r2| r2 < options.min_r_squared) {
+        warnings.push(format!(
+            "{}: standard curve r² is {:.4}, below {}",
+            path,
+            r_squared.unwrap_or_default(),
+            options.min_r_squared
+        ));
+    }
+    let highest = points.iter().map(|(x, _)| *x).fold(0.0, f64::max);
+
+    // 4) Sample concentrations, in µmol/L
+    for (well, content, reading) in &wells {
+        let WellContent::Sample {
+            sample_id,
+            dilution,
+        } = content
+        else {
+            continue;
+        };
+        let mut concentration = (corrected(*reading) - intercept) / slope;
+        if concentration > highest {
+            warnings.push(format!(
+                "{}: well {} ({}) is above the highest standard and was extrapolated",
+                path, well, sample_id
+            ));
+        } else if concentration < 0.0 {
+            warnings.push(format!(
+                "{}: well {} ({}) reads below the curve and was taken as 0",
+                path, well, sample_id
+            ));
+            concentration = 0.0;
+        }
+        values
+            .entry(sample_id.trim().to_string())
+            .or_default()
+            .entry(layout.nutrient)
+            .or_default()
+            .push(to_micromolar(
+                layout.nutrient,
+                concentration * dilution,
+                layout.unit,
+            ));
+    }
+
+    Ok(StandardCurve {
+        path: path.to_string(),
+        nutrient: layout.nutrient,
+        unit: layout.unit,
+        slope,
+        intercept,
+        r_squared,
+        blank,
+        standards,
+    })
+}
+
+/// Adds the values of each nutrient column of a table export.
+fn import_table(
+    path: &str,
+    lines: &[Vec<String>],
+    layout: &TableLayout,
+    values: &mut SampleValues,
+    warnings: &mut Vec<String>,
+) -> Result<(), PoleshiftError> {
+    let data_error =
+        |reason: String| PoleshiftError::DataError(format!("Could not read {}: {}", path, reason));
+
+    // 1) Sample-ID and nutrient columns
+    let headers: Vec<&str> = lines
+        .first()
+        .ok_or_else(|| data_error("no header line".to_string()))?
+        .iter()
+        .map(|h| h.trim())
+        .collect();
+    let sample_index = match &layout.sample_column {
+        Some(column) => headers.iter().position(|h| *h == column.trim()),
+        None => headers
+            .iter()
+            .position(|h| SAMPLE_COLUMN_NAMES.contains(&h.to_lowercase().as_str())),
+    }
+    .ok_or_else(|| data_error("no sample ID column; name it with `sample_column`".to_string()))?;
+    let mut columns: Vec<(usize, Nutrient, NutrientUnit)> = Vec::new();
+    for (i, header) in headers.iter().enumerate() {
+        let (name, unit) = split_header(header);
+        let Some(nutrient) = layout
+            .columns
+            .get(*header)
+            .copied()
+            .or_else(|| Nutrient::from_name(&name))
+        else {
+            continue;
+        };
+        let unit = match unit {
+            Some(unit) => NutrientUnit::parse(&unit)
+                .ok_or_else(|| data_error(format!("unknown unit `{}` of `{}`", unit, header)))?,
+            None => layout.unit,
+        };
+        columns.push((i, nutrient, unit));
+    }
+    columns.retain(|(i, _, _)| *i != sample_index);
+    if columns.is_empty() {
+        return Err(data_error("no nutrient columns".to_string()));
+    }
+
+    // 2) Values, skipping empty cells and flagged ones such as `<0.1` or `bdl`
+    for (number, fields) in lines.iter().enumerate().skip(1) {
+        let Some(sample_id) = fields
+            .get(sample_index)
+            .map(|id| id.trim())
+            .filter(|id| !id.is_empty())
+        else {
+            continue;
+        };
+        for &(i, nutrient, unit) in &columns {
+            let field = fields.get(i).map_or("", |f| f.trim());
+            if field.is_empty() {
+                continue;
+            }
+            match parse_value(field).filter(|v| *v >= 0.0) {
+                Some(value) => values
+                    .entry(sample_id.to_string())
+                    .or_default()
+                    .entry(nutrient)
+                    .or_default()
+                    .push(to_micromolar(nutrient, value, unit)),
+                None => warnings.push(format!(
+                    "{}: line {}, `{}` of {} is not a concentration and was left out",
+                    path,
+                    number + 1,
+                    field,
+                    headers[i]
+                )),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads nutrient measurements from lab CSV exports into a report per sample.
+pub(crate) fn import_nutrient_files(
+    files: &[NutrientImportFile],
+    options: &NutrientImportOptions,
+) -> Result<NutrientImportReport, PoleshiftError> {
+    let mut values = SampleValues::new();
+    let mut curves = Vec::new();
+    let mut warnings = Vec::new();
+    for file in files {
+        let contents = std::fs::read_to_string(Path::new(&file.path))?;
+        let delimiter_options = DelimitedOptions {
+            delimiter: file.delimiter,
+            ..DelimitedOptions::default()
+        };
+        let delimiter = pick_delimiter(Path::new(&file.path), &contents, &delimiter_options);
+        let lines: Vec<Vec<String>> = data_lines(&contents)
+            .map(|line| split_fields(line, delimiter))
+            .collect();
+        match &file.layout {
+            NutrientFileLayout::Table(layout) => {
+                import_table(&file.path, &lines, layout, &mut values, &mut warnings)?
+            }
+            NutrientFileLayout::Plate(layout) => curves.push(import_plate(
+                &file.path,
+                &lines,
+                layout,
+                options,
+                &mut values,
+                &mut warnings,
+            )?),
+        }
+    }
+
+    let reports = values
+        .into_iter()
+        .map(|(sample_id, nutrients)| {
+            let entries: Vec<NutrientEntry> = nutrients
+                .into_iter()
+                .map(|(nutrient, values)| NutrientEntry {
+                    nutrient,
+                    values,
+                    unit: NutrientUnit::MicromolePerLitre,
+                    detection_limit: None,
+                })
+                .collect();
+            nutrient_report(&sample_id, &entries)
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(NutrientImportReport {
+        reports,
+        curves,
+        warnings,
+    })
+}
+
+/// Imports nutrient measurements from lab CSV exports: tables with a column per
+/// nutrient, or 96-well plate-reader grids whose standard curve is fitted here to
+/// find each sample well's concentration. Replicates of a sample, within a file or
+/// across files, are combined into one `handle_nutrient_data` report per sample ID.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn import_nutrient_csv(
+    invocation: Invocation,
+    files: Vec<NutrientImportFile>,
+    options: Option<NutrientImportOptions>,
+) -> Result<StandardResponseNoFiles<NutrientImportReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let options = options.unwrap_or_default();
+        options.validate()?;
+        if files.is_empty() {
+            return Err(PoleshiftError::InvalidInput {
+                field: "files".to_string(),
+                reason: "must hold at least one file".to_string(),
+            });
+        }
+        let report = import_nutrient_files(&files, &options)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
@@ -0,0 +1,130 @@
+pub mod handle_nutrient_data;
+pub mod import;
+pub mod speciation;
+
+use serde::{Deserialize, Serialize};
+
+/// A dissolved nutrient measured on a water sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Nutrient {
+    /// NH₃, as the ammonia kits read it; reported as the ammonium it stands for
+    Ammonia,
+    /// NH₄⁺
+    Ammonium,
+    /// NO₃⁻
+    Nitrate,
+    /// NO₂⁻
+    Nitrite,
+    /// PO₄³⁻
+    Phosphate,
+    /// Dissolved silica, as SiO₂
+    Silicate,
+}
+
+impl Nutrient {
+    /// Molar mass of the compound, in g/mol.
+    fn molar_mass(self) -> f64 {
+        match self {
+            Nutrient::Ammonia => 17.031,
+            Nutrient::Ammonium => 18.038,
+            Nutrient::Nitrate => 62.004,
+            Nutrient::Nitrite => 46.005,
+            Nutrient::Phosphate => 94.971,
+            Nutrient::Silicate => 60.084,
+        }
+    }
+
+    /// Molar mass of the element the compound is reported as (N, P or Si), in g/mol.
+    fn element_mass(self) -> f64 {
+        match self {
+            Nutrient::Ammonia | Nutrient::Ammonium | Nutrient::Nitrate | Nutrient::Nitrite => {
+                14.007
+            }
+            Nutrient::Phosphate => 30.974,
+            Nutrient::Silicate => 28.086,
+        }
+    }
+
+    /// The nutrient a column or label names, by name or formula, e.g. `Nitrate`,
+    /// `NO3` or `PO4`.
+    pub(crate) fn from_name(name: &str) -> Option<Nutrient> {
+        let name: String = name
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+        match name.trim_end_matches(['-', '+']) {
+            "ammonia" | "nh3" => Some(Nutrient::Ammonia),
+            "ammonium" | "nh4" => Some(Nutrient::Ammonium),
+            "nitrate" | "no3" => Some(Nutrient::Nitrate),
+            "nitrite" | "no2" => Some(Nutrient::Nitrite),
+            "phosphate" | "po4" | "po43" | "po4-3" | "srp" => Some(Nutrient::Phosphate),
+            "silicate" | "silica" | "sio2" | "sio4" | "si(oh)4" | "si" => Some(Nutrient::Silicate),
+            _ => None,
+        }
+    }
+}
+
+/// Unit a nutrient concentration is given in.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NutrientUnit {
+    /// µmol/L, which every result is reported in
+    #[default]
+    MicromolePerLitre,
+    /// mg/L of the compound, e.g. mg NO₃⁻/L
+    MilligramPerLitre,
+    /// mg/L of its nitrogen, phosphorus or silicon, e.g. mg NO₃⁻-N/L
+    MilligramElementPerLitre,
+    /// µg/L of the compound
+    MicrogramPerLitre,
+}
+
+impl NutrientUnit {
+    /// The unit a header or label writes, e.g. `µmol/L`, `uM`, `mg/L` or `mg-N/L`.
+    pub(crate) fn parse(text: &str) -> Option<NutrientUnit> {
+        let text: String = text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c == 'µ' || c == 'μ' { 'u' } else { c })
+            .collect::<String>()
+            .to_lowercase();
+        match text.as_str() {
+            "umol/l" | "umoll-1" | "um" | "mmol/m3" => Some(NutrientUnit::MicromolePerLitre),
+            "mg/l" | "mgl-1" | "ppm" => Some(NutrientUnit::MilligramPerLitre),
+            "mg-n/l" | "mgn/l" | "mg-p/l" | "mgp/l" | "mg-si/l" | "mgsi/l" => {
+                Some(NutrientUnit::MilligramElementPerLitre)
+            }
+            "ug/l" | "ugl-1" | "ppb" => Some(NutrientUnit::MicrogramPerLitre),
+            _ => None,
+        }
+    }
+}
+
+/// `value` of `nutrient` in `unit`, in µmol/L.
+pub(crate) fn to_micromolar(nutrient: Nutrient, value: f64, unit: NutrientUnit) -> f64 {
+    match unit {
+        NutrientUnit::MicromolePerLitre => value,
+        NutrientUnit::MilligramPerLitre => value * 1000.0 / nutrient.molar_mass(),
+        NutrientUnit::MilligramElementPerLitre => value * 1000.0 / nutrient.element_mass(),
+        NutrientUnit::MicrogramPerLitre => value / nutrient.molar_mass(),
+    }
+}
+
+/// `micromolar` µmol/L of `nutrient` in `unit`.
+pub(crate) fn from_micromolar(nutrient: Nutrient, micromolar: f64, unit: NutrientUnit) -> f64 {
+    micromolar / to_micromolar(nutrient, 1.0, unit)
+}
+
+/// Mean, sample standard deviation and coefficient of variation (in percent) of
+/// replicate values; the SD and CV need two or more, the CV a mean above 0.
+pub(crate) fn replicate_stats(values: &[f64]) -> (f64, Option<f64>, Option<f64>) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let sd = (values.len() > 1)
+        .then(|| (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt());
+    let cv = sd.filter(|_| mean > 0.0).map(|sd| sd / mean * 100.0);
+    (mean, sd, cv)
+}
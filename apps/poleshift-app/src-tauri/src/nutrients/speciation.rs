@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Runtime};
+
+use crate::ctd::output_store::ctd_store_path;
+use crate::poleshift_common::types::PoleshiftError;
+use crate::stats::sidebar_store::surface_rows;
+
+/// What an ammonia reading measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmmoniaForm {
+    /// NH₃ and NH₄⁺ together, as most colorimetric kits read them
+    #[default]
+    Total,
+    /// Un-ionized NH₃ only, e.g. from an ammonia gas-sensing electrode
+    Free,
+}
+
+/// The conditions to speciate ammonia at. Values not given are the means of the
+/// surface rows of the sample's stored CTD cast `ctd_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeciationOptions {
+    /// In °C
+    pub temperature: Option<f64>,
+    /// Practical salinity
+    pub salinity: Option<f64>,
+    pub ph: Option<f64>,
+    /// Processed data ID of a CTD cast stored with `persist_outputs`
+    pub ctd_id: Option<String>,
+    /// CTD rows at or above this depth are averaged, in m
+    pub surface_depth: f64,
+    pub reading: AmmoniaForm,
+}
+
+impl Default for SpeciationOptions {
+    fn default() -> Self {
+        SpeciationOptions {
+            temperature: None,
+            salinity: None,
+            ph: None,
+            ctd_id: None,
+            surface_depth: 2.0,
+            reading: AmmoniaForm::Total,
+        }
+    }
+}
+
+impl SpeciationOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let invalid = |field: &str, reason: &str| PoleshiftError::InvalidInput {
+            field: format!("speciation.{}", field),
+            reason: reason.to_string(),
+        };
+        if self
+            .temperature
+            .is_some_and(|t| !(-2.0..=40.0).contains(&t))
+        {
+            return Err(invalid("temperature", "must be between -2 and 40 °C"));
+        }
+        if self.salinity.is_some_and(|s| !(0.0..=45.0).contains(&s)) {
+            return Err(invalid("salinity", "must be between 0 and 45"));
+        }
+        if self.ph.is_some_and(|ph| !(2.0..=12.0).contains(&ph)) {
+            return Err(invalid("ph", "must be between 2 and 12"));
+        }
+        if !(self.surface_depth.is_finite() && self.surface_depth >= 0.0) {
+            return Err(invalid("surface_depth", "must be 0 or more"));
+        }
+        Ok(())
+    }
+}
+
+/// Where a condition came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionSource {
+    Given,
+    Ctd,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleConditions {
+    pub temperature: f64,
+    pub salinity: f64,
+    pub ph: f64,
+    pub temperature_source: ConditionSource,
+    pub salinity_source: ConditionSource,
+    pub ph_source: ConditionSource,
+}
+
+/// An ammonia reading split into NH₃ and NH₄⁺, in µmol/L of N.
+#[derive(Debug, Clone, Serialize)]
+pub struct AmmoniaSpeciation {
+    pub conditions: SampleConditions,
+    pub reading: AmmoniaForm,
+    /// Of NH₄⁺, on the scale of `conditions.ph`
+    pub pka: f64,
+    /// Share of the total that is NH₃
+    pub nh3_fraction: f64,
+    pub free_ammonia: f64,
+    pub ammonium: f64,
+    pub total_ammonia_nitrogen: f64,
+}
+
+/// pKa of NH₄⁺ at `temperature` °C and practical `salinity`: the freshwater value
+/// (Emerson et al. 1975) with the ionic-strength correction of Bell et al. (2007).
+pub(crate) fn ammonium_pka(temperature: f64, salinity: f64) -> f64 {
+    let kelvin = temperature + 273.15;
+    let ionic_strength = 19.9201 * salinity / (1000.0 - 1.00488 * salinity);
+    0.09018 + 2729.92 / kelvin + (0.1552 - 0.000314 * kelvin) * ionic_strength
+}
+
+/// Splits `ammonia` µmol/L, read as `reading`, into NH₃ and NH₄⁺ at `conditions`.
+pub(crate) fn speciate(
+    ammonia: f64,
+    reading: AmmoniaForm,
+    conditions: &SampleConditions,
+) -> AmmoniaSpeciation {
+    let pka = ammonium_pka(conditions.temperature, conditions.salinity);
+    let nh3_fraction = 1.0 / (1.0 + 10f64.powf(pka - conditions.ph));
+    let total = match reading {
+        AmmoniaForm::Total => ammonia,
+        AmmoniaForm::Free => ammonia / nh3_fraction,
+    };
+    AmmoniaSpeciation {
+        conditions: conditions.clone(),
+        reading,
+        pka,
+        nh3_fraction,
+        free_ammonia: total * nh3_fraction,
+        ammonium: total * (1.0 - nh3_fraction),
+        total_ammonia_nitrogen: total,
+    }
+}
+
+/// Mean of a column over CTD rows.
+fn column_mean(rows: &[Map<String, Value>], column: &str) -> Option<f64> {
+    let values: Vec<f64> = rows
+        .iter()
+        .filter_map(|row| row.get(column)?.as_f64())
+        .filter(|value| value.is_finite())
+        .collect();
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// The temperature, salinity and pH to speciate at: those given, else the surface
+/// means of the CTD cast.
+pub(crate) fn sample_conditions<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    options: &SpeciationOptions,
+) -> Result<SampleConditions, PoleshiftError> {
+    let mut rows = Vec::new();
+    let needs_ctd =
+        options.temperature.is_none() || options.salinity.is_none() || options.ph.is_none();
+    if let (true, Some(ctd_id)) = (needs_ctd, &options.ctd_id) {
+        surface_rows(
+            &ctd_store_path(app_handle, ctd_id)?,
+            options.surface_depth,
+            &mut rows,
+        )?;
+    }
+
+    let mut missing = Vec::new();
+    let mut condition = |given: Option<f64>, column: &str| match given {
+        Some(value) => (value, ConditionSource::Given),
+        None => match column_mean(&rows, column) {
+            Some(value) => (value, ConditionSource::Ctd),
+            None => {
+                missing.push(column.to_string());
+                (f64::NAN, ConditionSource::Ctd)
+            }
+        },
+    };
+    let (temperature, temperature_source) = condition(options.temperature, "temperature");
+    let (salinity, salinity_source) = condition(options.salinity, "salinity");
+    let (ph, ph_source) = condition(options.ph, "ph");
+    if !missing.is_empty() {
+        return Err(PoleshiftError::InvalidInput {
+            field: "speciation".to_string(),
+            reason: format!(
+                "needs {}; give them or a CTD cast with them in its surface rows",
+                missing.join(", ")
+            ),
+        });
+    }
+    Ok(SampleConditions {
+        temperature,
+        salinity,
+        ph,
+        temperature_source,
+        salinity_source,
+        ph_source,
+    })
+}
@@ -0,0 +1,432 @@
+//poleshift/src-tauri/src/poleshift_common/jobs.rs
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime, State, Window};
+use uuid::Uuid;
+
+use crate::devtools::{traced_blocking, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, ProgressEvent};
+use crate::poleshift_common::utils::emit_progress;
+
+/// Event carrying a `JobProgress` payload each time a job changes status.
+pub const JOB_STATUS_EVENT: &str = "job-progress";
+/// Finished jobs kept for `list_jobs`, most recent first.
+const MAX_FINISHED_JOBS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// Snapshot of a job, as sent to the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub kind: String,
+    pub stage: String,
+    pub elapsed_secs: f64,
+    /// Reads processed, or rows for CTD jobs and bytes for downloads
+    pub reads_processed: u64,
+    /// Total reads to process, once known
+    pub reads_total: Option<u64>,
+    pub reads_per_sec: f64,
+    pub cancelled: bool,
+    pub status: JobStatus,
+    /// Why a failed job failed
+    pub error: Option<String>,
+}
+
+struct JobState {
+    id: String,
+    kind: String,
+    started: Instant,
+    cancelled: AtomicBool,
+    reads_processed: AtomicU64,
+    reads_total: AtomicU64,
+    /// Last percentage reported with `report_progress`
+    percentage: AtomicU8,
+    stage: Mutex<String>,
+    status: Mutex<JobStatus>,
+    error: Mutex<Option<String>>,
+    /// Seconds the job ran for, once finished
+    ran_secs: Mutex<Option<f64>>,
+    notifier: Option<Notifier>,
+}
+
+/// Shared handle to one job; cheap to clone into worker threads.
+#[derive(Clone)]
+pub struct JobHandle(Arc<JobState>);
+
+impl JobHandle {
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns `PoleshiftError::Cancelled` once the job was cancelled, for use at
+    /// checkpoints between stages.
+    pub fn check_cancelled(&self) -> Result<(), PoleshiftError> {
+        if self.is_cancelled() {
+            Err(PoleshiftError::Cancelled(self.0.id.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Names the stage the job is at; the first stage marks a queued job running.
+    pub fn set_stage(&self, stage: &str) {
+        if let Ok(mut current) = self.0.stage.lock() {
+            *current = stage.to_string();
+        }
+        self.set_status(JobStatus::Queued, JobStatus::Running);
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.0
+            .status
+            .lock()
+            .map(|status| *status)
+            .unwrap_or(JobStatus::Running)
+    }
+
+    /// Moves the job from `from` to `to`, telling the frontend; other statuses are
+    /// left as they are.
+    fn set_status(&self, from: JobStatus, to: JobStatus) {
+        let changed = match self.0.status.lock() {
+            Ok(mut status) if *status == from => {
+                *status = to;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            self.notify();
+        }
+    }
+
+    fn notify(&self) {
+        if let Some(notifier) = &self.0.notifier {
+            notifier(&self.progress());
+        }
+    }
+
+    pub fn add_reads(&self, reads: u64) {
+        self.0.reads_processed.fetch_add(reads, Ordering::Relaxed);
+    }
+
+    pub fn set_reads(&self, reads: u64) {
+        self.0.reads_processed.store(reads, Ordering::Relaxed);
+    }
+
+    pub fn set_reads_total(&self, reads: u64) {
+        self.0.reads_total.store(reads, Ordering::Relaxed);
+    }
+
+    pub fn progress(&self) -> JobProgress {
+        let elapsed_secs = self
+            .0
+            .ran_secs
+            .lock()
+            .ok()
+            .and_then(|secs| *secs)
+            .unwrap_or_else(|| self.0.started.elapsed().as_secs_f64());
+        let reads_processed = self.0.reads_processed.load(Ordering::Relaxed);
+        let reads_total = self.0.reads_total.load(Ordering::Relaxed);
+        JobProgress {
+            job_id: self.0.id.clone(),
+            kind: self.0.kind.clone(),
+            stage: self.0.stage.lock().map(|s| s.clone()).unwrap_or_default(),
+            elapsed_secs,
+            reads_processed,
+            reads_total: (reads_total > 0).then_some(reads_total),
+            reads_per_sec: if elapsed_secs > 0.0 {
+                reads_processed as f64 / elapsed_secs
+            } else {
+                0.0
+            },
+            cancelled: self.is_cancelled(),
+            status: self.status(),
+            error: self.0.error.lock().ok().and_then(|error| error.clone()),
+        }
+    }
+
+    /// Emits a `ProgressEvent` on the job's channel, `job://{id}/progress`.
+    pub fn report_progress<R: Runtime>(
+        &self,
+        window: &Window<R>,
+        progress_percentage: u8,
+        status_message: &str,
+        processing_state: &str,
+    ) -> Result<(), PoleshiftError> {
+        self.0
+            .percentage
+            .store(progress_percentage, Ordering::Relaxed);
+        emit_progress(
+            window,
+            &ProgressEvent {
+                job_id: self.0.id.clone(),
+                progress_percentage,
+                status_message: status_message.to_string(),
+                processing_state: processing_state.to_string(),
+                reads_per_sec: None,
+            },
+        )
+    }
+
+    /// Reports the stage, how many reads (rows, bytes) are done and how fast, at the
+    /// last reported percentage.
+    pub fn emit_progress<R: Runtime>(&self, window: &Window<R>) -> Result<(), PoleshiftError> {
+        let progress = self.progress();
+        let status_message = match progress.reads_total {
+            Some(total) => format!(
+                "{} ({} of {})...",
+                progress.stage, progress.reads_processed, total
+            ),
+            None => format!("{} ({})...", progress.stage, progress.reads_processed),
+        };
+        emit_progress(
+            window,
+            &ProgressEvent {
+                job_id: self.0.id.clone(),
+                progress_percentage: self.0.percentage.load(Ordering::Relaxed),
+                status_message,
+                processing_state: "processing".to_string(),
+                reads_per_sec: Some(progress.reads_per_sec),
+            },
+        )
+    }
+}
+
+/// Called with a job's progress each time its status changes.
+type Notifier = Arc<dyn Fn(&JobProgress) + Send + Sync>;
+
+/// Tracks long-running jobs (classification, CTD processing, downloads, exports) so
+/// they can be listed and cancelled, and reports each change of their status as a
+/// `job-progress` event.
+///
+/// Registered as managed state. Cancellation is cooperative: jobs check their flag
+/// between stages and while waiting on the classifier.
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, JobHandle>>,
+    finished: Mutex<VecDeque<JobProgress>>,
+    notifier: Mutex<Option<Notifier>>,
+}
+
+impl JobManager {
+    /// Sends every job's status changes to the frontend from now on.
+    pub fn emit_status_to<R: Runtime>(&self, app_handle: AppHandle<R>) {
+        let notifier: Notifier = Arc::new(move |progress: &JobProgress| {
+            let _ = app_handle.emit(JOB_STATUS_EVENT, progress);
+        });
+        if let Ok(mut current) = self.notifier.lock() {
+            *current = Some(notifier);
+        }
+    }
+
+    /// Registers a new job; `id` lets the caller pick the ID up front (see
+    /// `start_all`).
+    pub fn start(&self, kind: &str, id: Option<String>) -> Result<JobHandle, PoleshiftError> {
+        Ok(self.start_all(kind, [id])?.remove(0))
+    }
+
+    /// Registers a job of `kind` for each of `ids`, generating the IDs not given. A
+    /// given ID must be made of `A-Z`, `a-z`, `0-9`, `_` and `-`, as it names the job's
+    /// event channel, and must not belong to an active job or appear twice; otherwise
+    /// no job is registered.
+    pub fn start_all(
+        &self,
+        kind: &str,
+        ids: impl IntoIterator<Item = Option<String>>,
+    ) -> Result<Vec<JobHandle>, PoleshiftError> {
+        let invalid = |reason: String| PoleshiftError::InvalidInput {
+            field: "job_id".to_string(),
+            reason,
+        };
+        let ids: Vec<String> = ids
+            .into_iter()
+            .map(|id| id.unwrap_or_else(|| Uuid::new_v4().to_string()))
+            .collect();
+        if let Some(id) = ids.iter().find(|id| {
+            id.is_empty()
+                || !id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        }) {
+            return Err(invalid(format!(
+                "'{}' may only use letters, digits, '_' and '-'",
+                id
+            )));
+        }
+
+        let handles: Vec<JobHandle> = {
+            let mut jobs = self
+                .jobs
+                .lock()
+                .map_err(|_| PoleshiftError::Other("The job list is unavailable".to_string()))?;
+            let mut seen = HashSet::new();
+            if let Some(id) = ids
+                .iter()
+                .find(|id| jobs.contains_key(id.as_str()) || !seen.insert(id.as_str()))
+            {
+                return Err(invalid(format!("'{}' is already in use", id)));
+            }
+            ids.into_iter()
+                .map(|id| {
+                    let handle = self.new_handle(kind, id.clone());
+                    jobs.insert(id, handle.clone());
+                    handle
+                })
+                .collect()
+        };
+        for handle in &handles {
+            handle.notify();
+        }
+        Ok(handles)
+    }
+
+    fn new_handle(&self, kind: &str, id: String) -> JobHandle {
+        JobHandle(Arc::new(JobState {
+            id,
+            kind: kind.to_string(),
+            started: Instant::now(),
+            cancelled: AtomicBool::new(false),
+            reads_processed: AtomicU64::new(0),
+            reads_total: AtomicU64::new(0),
+            percentage: AtomicU8::new(0),
+            stage: Mutex::new("queued".to_string()),
+            status: Mutex::new(JobStatus::Queued),
+            error: Mutex::new(None),
+            ran_secs: Mutex::new(None),
+            notifier: self.notifier.lock().ok().and_then(|n| n.clone()),
+        }))
+    }
+
+    /// Runs `work` as a new job of `kind`: registered, marked running, and finished
+    /// with its outcome. For commands whose work has no stages of its own to report.
+    pub async fn run<T, F, Fut>(
+        &self,
+        kind: &str,
+        id: Option<String>,
+        work: F,
+    ) -> Result<T, PoleshiftError>
+    where
+        F: FnOnce(JobHandle) -> Fut,
+        Fut: Future<Output = Result<T, PoleshiftError>>,
+    {
+        let job = self.start(kind, id)?;
+        job.set_stage("running");
+        let result = work(job.clone()).await;
+        self.finish(job.id(), result.as_ref().err());
+        result
+    }
+
+    /// Flags a job as cancelled; returns false if no such job is running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.jobs.lock().ok().and_then(|jobs| jobs.get(id).cloned()) {
+            Some(job) => {
+                job.0.cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ends a job with the error it failed with, if any, keeping it for `list_jobs`.
+    pub fn finish(&self, id: &str, error: Option<&PoleshiftError>) {
+        let Some(job) = self.jobs.lock().ok().and_then(|mut jobs| jobs.remove(id)) else {
+            return;
+        };
+        // A cancelled job may stop with an error of its own, e.g. an aborted download
+        let status = match error {
+            Some(PoleshiftError::Cancelled(_)) => JobStatus::Cancelled,
+            _ if job.is_cancelled() => JobStatus::Cancelled,
+            Some(_) => JobStatus::Failed,
+            None => JobStatus::Done,
+        };
+        if let (Some(e), Ok(mut slot)) = (error, job.0.error.lock()) {
+            *slot = Some(e.to_string());
+        }
+        if let Ok(mut secs) = job.0.ran_secs.lock() {
+            *secs = Some(job.0.started.elapsed().as_secs_f64());
+        }
+        if let Ok(mut current) = job.0.status.lock() {
+            *current = status;
+        }
+        job.notify();
+        if let Ok(mut finished) = self.finished.lock() {
+            finished.push_front(job.progress());
+            finished.truncate(MAX_FINISHED_JOBS);
+        }
+    }
+
+    /// Running and queued jobs, longest-running first, then recently finished ones.
+    pub fn list(&self) -> Vec<JobProgress> {
+        let mut jobs: Vec<JobProgress> = self
+            .jobs
+            .lock()
+            .map(|jobs| jobs.values().map(JobHandle::progress).collect())
+            .unwrap_or_default();
+        jobs.sort_by(|a, b| b.elapsed_secs.total_cmp(&a.elapsed_secs));
+        if let Ok(finished) = self.finished.lock() {
+            jobs.extend(finished.iter().cloned());
+        }
+        jobs
+    }
+}
+
+/// Requests cancellation of a running job. Returns false if the job is unknown
+/// (e.g. it already finished).
+#[tauri::command(rename_all = "snake_case")]
+pub fn cancel_job(
+    invocation: Invocation,
+    jobs: State<'_, JobManager>,
+    job_id: String,
+) -> Result<bool, PoleshiftError> {
+    traced_blocking(invocation, || Ok(jobs.cancel(&job_id)))
+}
+
+/// Returns the progress of every running or queued job, and of the jobs that
+/// finished most recently.
+#[tauri::command]
+pub fn list_jobs(
+    invocation: Invocation,
+    jobs: State<'_, JobManager>,
+) -> Result<Vec<JobProgress>, PoleshiftError> {
+    traced_blocking(invocation, || Ok(jobs.list()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_ids_in_use_or_unfit_for_event_names() {
+        let jobs = JobManager::default();
+        let job = jobs.start("ctd", Some("sample-1".to_string())).unwrap();
+        assert!(jobs.start("ctd", Some("sample-1".to_string())).is_err());
+        assert!(jobs.start("ctd", Some("a/b".to_string())).is_err());
+        assert!(jobs.start("ctd", Some(String::new())).is_err());
+
+        let batch = [Some("x".to_string()), None, Some("x".to_string())];
+        assert!(jobs.start_all("ctd", batch).is_err());
+        assert!(!jobs.list().iter().any(|progress| progress.job_id == "x"));
+
+        jobs.finish(job.id(), None);
+        assert!(jobs.start("ctd", Some("sample-1".to_string())).is_ok());
+    }
+}
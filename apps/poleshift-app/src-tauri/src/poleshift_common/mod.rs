@@ -1,4 +1,5 @@
 //poleshift/src-tauri/src/poleshift_common/mod.rs
 
+pub mod jobs;
 pub mod types;
 pub(crate) mod utils;
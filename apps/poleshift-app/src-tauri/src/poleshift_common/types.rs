@@ -1,8 +1,10 @@
 use std::path::PathBuf;
 //poleshift/src-tauri/src/poleshift_common/types.rs
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 
+// `remote = "Self"` derives an inherent `serialize`, wrapped by the `Serialize` impl below
 #[derive(Debug, thiserror::Error, serde::Serialize)]
+#[serde(remote = "Self")]
 pub enum PoleshiftError {
     #[error("No input files provided")]
     NoFiles,
@@ -18,10 +20,25 @@ pub enum PoleshiftError {
     ProgressError(String),
     #[error("Serialization error: {0}")]
     SerializationError(String),
+    #[error("Job cancelled: {0}")]
+    Cancelled(String),
+    #[error("Invalid {field}: {reason}")]
+    InvalidInput { field: String, reason: String },
+    #[error("Permission denied: the {role} role is not granted {permission}")]
+    PermissionDenied { permission: String, role: String },
     #[error("Unsupported OS: {0}")]
     Other(String),
 }
 
+/// A command's error reaches the webview serialized, so this is where the failures of
+/// all commands are handed to the error reporter.
+impl Serialize for PoleshiftError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error_reporting::report_command_error(self);
+        PoleshiftError::serialize(self, serializer)
+    }
+}
+
 impl From<std::io::Error> for PoleshiftError {
     fn from(e: std::io::Error) -> Self {
         PoleshiftError::IoError(e.to_string())
@@ -34,6 +51,12 @@ impl From<tauri::Error> for PoleshiftError {
     }
 }
 
+impl From<crate::io::ParseError> for PoleshiftError {
+    fn from(e: crate::io::ParseError) -> Self {
+        PoleshiftError::DataError(e.to_string())
+    }
+}
+
 impl From<serde_json::Error> for PoleshiftError {
     fn from(e: serde_json::Error) -> Self {
         PoleshiftError::SerializationError(e.to_string())
@@ -67,6 +90,27 @@ pub struct StandardResponseNoFiles<T> {
     pub report: T,
 }
 
+/// Progress of a job, as every handler reports it. Emitted on the job's own channel
+/// (see `ProgressEvent::channel`), so a caller that picked the job ID can listen
+/// before invoking the command.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent {
+    pub job_id: String,
+    pub progress_percentage: u8,
+    pub status_message: String,
+    /// A frontend `ProcessingState`: `processing` while running, then `complete`
+    pub processing_state: String,
+    /// Throughput of the job so far, on the periodic updates of a running job
+    pub reads_per_sec: Option<f64>,
+}
+
+impl ProgressEvent {
+    /// `job://{job_id}/progress`
+    pub fn channel(job_id: &str) -> String {
+        format!("job://{}/progress", job_id)
+    }
+}
+
 #[derive(Debug)]
 pub struct KrakenConfig {
     // Direct paths to classification binaries and database files
@@ -75,6 +119,8 @@ pub struct KrakenConfig {
     pub taxdb_file: String,
     pub counts_file: String,
     pub input_files: Vec<PathBuf>,
+    /// Reference database set the files above belong to
+    pub database_id: String,
 }
 
 /*
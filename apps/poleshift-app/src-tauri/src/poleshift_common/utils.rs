@@ -1,22 +1,65 @@
 //poleshift/src-tauri/src/poleshift_common/utils.rs
 
-use crate::poleshift_common::types::PoleshiftError;
-use tauri::{Emitter, Runtime, Window};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::devtools::CommandInspector;
+use crate::poleshift_common::types::{PoleshiftError, ProgressEvent};
+use tauri::{Emitter, Manager, Runtime, Window};
+use uuid::Uuid;
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Emits `event` on its job's channel, `job://{job_id}/progress`.
 pub fn emit_progress<R: Runtime>(
     window: &Window<R>,
-    progress_percentage: u8,
-    status_message: &str,
-    processing_state: &str,
+    event: &ProgressEvent,
 ) -> Result<(), PoleshiftError> {
+    let channel = ProgressEvent::channel(&event.job_id);
+    if let Some(inspector) = window.try_state::<CommandInspector>() {
+        inspector.record_event(&event.job_id, &channel, &serde_json::to_value(event)?);
+    }
     window
-        .emit(
-            "progress",
-            serde_json::json!({
-                "progress_percentage": progress_percentage,
-                "status_message": status_message,
-                "processing_state": processing_state
-            }),
-        )
+        .emit(&channel, event)
         .map_err(|e| PoleshiftError::ProgressError(e.to_string()))
 }
+
+/// Longest ID accepted as part of a file name.
+pub const MAX_PATH_ID_LEN: usize = 64;
+
+/// Checks that an ID that ends up in a file name is 1 to `MAX_PATH_ID_LEN` ASCII
+/// letters, digits, `-` or `_`, so it cannot lead out of the directory it is joined to.
+pub fn validate_path_id(field: &str, value: &str) -> Result<(), PoleshiftError> {
+    let reason = if value.is_empty() {
+        "must not be empty".to_string()
+    } else if value.len() > MAX_PATH_ID_LEN {
+        format!("must be at most {} characters", MAX_PATH_ID_LEN)
+    } else if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        format!(
+            "'{}' may only hold letters, digits, '-' and '_'",
+            value.escape_debug()
+        )
+    } else {
+        return Ok(());
+    };
+    Err(PoleshiftError::InvalidInput {
+        field: field.to_string(),
+        reason,
+    })
+}
+
+/// Parses a user-supplied UUID, naming `field` in the error instead of panicking.
+pub fn parse_uuid(field: &str, value: &str) -> Result<Uuid, PoleshiftError> {
+    Uuid::parse_str(value.trim()).map_err(|e| PoleshiftError::InvalidInput {
+        field: field.to_string(),
+        reason: format!("'{}' is not a UUID ({})", value, e),
+    })
+}
@@ -0,0 +1,472 @@
+//poleshift/src-tauri/src/results_store/mod.rs
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, Value as SqlValue, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::{now_ms, parse_uuid};
+
+/// Schema changes in order; the store's `user_version` is the number applied so far.
+/// Append new ones, never edit one that has shipped.
+const MIGRATIONS: [&str; 4] = [
+    "CREATE TABLE result (
+        processed_data_id TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        sample_id TEXT NOT NULL,
+        org_id TEXT,
+        user_id TEXT,
+        raw_data_id TEXT,
+        created_at_ms INTEGER NOT NULL,
+        report TEXT NOT NULL
+    );
+    CREATE INDEX result_sample ON result (org_id, sample_id);
+    CREATE TABLE raw_sequence_metadata (
+        processed_data_id TEXT PRIMARY KEY
+            REFERENCES result (processed_data_id) ON DELETE CASCADE,
+        summary TEXT NOT NULL,
+        run_metadata TEXT
+    );",
+    // Outbound uploads (see `sync`); not tied to `result`, whose rows are replaced
+    "CREATE TABLE sync_queue (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        processed_data_id TEXT,
+        target_table TEXT NOT NULL,
+        rows TEXT NOT NULL,
+        row_count INTEGER NOT NULL,
+        state TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt_ms INTEGER NOT NULL,
+        last_error TEXT,
+        created_at_ms INTEGER NOT NULL,
+        synced_at_ms INTEGER
+    );
+    CREATE INDEX sync_queue_due ON sync_queue (state, next_attempt_ms);",
+    // Rows of queued uploads whose server copy changed after the local write
+    "CREATE TABLE sync_conflict (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        queue_id INTEGER NOT NULL,
+        processed_data_id TEXT,
+        target_table TEXT NOT NULL,
+        row_id TEXT NOT NULL,
+        local_row TEXT NOT NULL,
+        server_row TEXT NOT NULL,
+        resolution TEXT,
+        detected_at_ms INTEGER NOT NULL,
+        resolved_at_ms INTEGER,
+        UNIQUE (queue_id, row_id)
+    );
+    CREATE INDEX sync_conflict_open ON sync_conflict (resolution, processed_data_id);",
+    // Lets `save_result` skip rewriting a result that did not change
+    "ALTER TABLE result ADD COLUMN report_hash TEXT;",
+];
+
+/// What a stored result holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultKind {
+    /// A `CTDReport`
+    Ctd,
+    /// The taxa of a classification; per-read output stays in its output store
+    Classification,
+    /// A `NutrientReport`
+    Nutrients,
+}
+
+impl ResultKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ResultKind::Ctd => "ctd",
+            ResultKind::Classification => "classification",
+            ResultKind::Nutrients => "nutrients",
+        }
+    }
+}
+
+impl FromSql for ResultKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "ctd" => Ok(ResultKind::Ctd),
+            "classification" => Ok(ResultKind::Classification),
+            "nutrients" => Ok(ResultKind::Nutrients),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// How much of a report the store keeps (the `stored_result_detail` setting).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultDetail {
+    /// Everything but the rows of a CTD report; with `persist_outputs` those stay in
+    /// the report's output store
+    #[default]
+    Summary,
+    /// The whole report, rows included
+    Full,
+}
+
+/// Whom a stored result belongs to.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultKeys {
+    pub processed_data_id: String,
+    pub kind: ResultKind,
+    pub sample_id: String,
+    pub org_id: Option<String>,
+    pub user_id: Option<String>,
+    pub raw_data_id: Option<String>,
+}
+
+/// A stored result without its report, as listed.
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredResultSummary {
+    #[serde(flatten)]
+    pub keys: ResultKeys,
+    pub created_at_ms: i64,
+    /// Size of the stored report JSON
+    pub report_bytes: usize,
+}
+
+/// Read counts and run header fields of a classification's reads.
+#[derive(Debug, Clone, Serialize)]
+pub struct RawSequenceMetadata {
+    pub summary: Value,
+    pub run_metadata: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredResult {
+    #[serde(flatten)]
+    pub keys: ResultKeys,
+    pub created_at_ms: i64,
+    pub report: Value,
+    pub raw_sequence_metadata: Option<RawSequenceMetadata>,
+}
+
+pub(crate) fn sql_error(e: rusqlite::Error) -> PoleshiftError {
+    PoleshiftError::DataError(e.to_string())
+}
+
+/// Location of the results store in the app data directory.
+pub(crate) fn results_store_path<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<PathBuf, PoleshiftError> {
+    Ok(app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join("results.sqlite"))
+}
+
+/// Opens the store at `path`, creating it and applying pending migrations.
+pub(crate) fn open_results_store(path: &Path) -> Result<Connection, PoleshiftError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut conn = Connection::open(path).map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+    // Batch jobs write from several threads at once
+    conn.busy_timeout(Duration::from_secs(10))
+        .map_err(sql_error)?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")
+        .map_err(sql_error)?;
+
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(sql_error)?;
+    if version > MIGRATIONS.len() {
+        return Err(PoleshiftError::DataError(format!(
+            "The results store {} is from a newer version of the app",
+            path.display()
+        )));
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute_batch(migration).map_err(sql_error)?;
+        tx.pragma_update(None, "user_version", index + 1)
+            .map_err(sql_error)?;
+        tx.commit().map_err(sql_error)?;
+    }
+    Ok(conn)
+}
+
+/// Stores `report` under `keys`, replacing an earlier result of the same processed
+/// data ID, with the read metadata of a classification. A result identical to the one
+/// stored is left as it is, with its original time.
+pub(crate) fn save_result<T: Serialize>(
+    path: &Path,
+    keys: &ResultKeys,
+    report: &T,
+    raw_sequence_metadata: Option<&RawSequenceMetadata>,
+) -> Result<(), PoleshiftError> {
+    let mut conn = open_results_store(path)?;
+    let report = serde_json::to_string(report)?;
+    let hash = hex::encode(Sha256::digest(serde_json::to_vec(&(
+        keys,
+        &report,
+        raw_sequence_metadata,
+    ))?));
+    let stored_hash: Option<String> = conn
+        .query_row(
+            "SELECT report_hash FROM result WHERE processed_data_id = ?1",
+            params![keys.processed_data_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(sql_error)?
+        .flatten();
+    if stored_hash.as_deref() == Some(hash.as_str()) {
+        return Ok(());
+    }
+
+    let created_at_ms = now_ms();
+    let tx = conn.transaction().map_err(sql_error)?;
+    // Deleting the old row cascades to its metadata
+    tx.execute(
+        "DELETE FROM result WHERE processed_data_id = ?1",
+        params![keys.processed_data_id],
+    )
+    .map_err(sql_error)?;
+    tx.execute(
+        "INSERT INTO result (processed_data_id, kind, sample_id, org_id, user_id,
+                             raw_data_id, created_at_ms, report, report_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            keys.processed_data_id,
+            keys.kind.as_str(),
+            keys.sample_id,
+            keys.org_id,
+            keys.user_id,
+            keys.raw_data_id,
+            created_at_ms,
+            report,
+            hash,
+        ],
+    )
+    .map_err(sql_error)?;
+    if let Some(metadata) = raw_sequence_metadata {
+        tx.execute(
+            "INSERT INTO raw_sequence_metadata VALUES (?1, ?2, ?3)",
+            params![
+                keys.processed_data_id,
+                serde_json::to_string(&metadata.summary)?,
+                metadata
+                    .run_metadata
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+            ],
+        )
+        .map_err(sql_error)?;
+    }
+    tx.commit().map_err(sql_error)
+}
+
+fn keys_from_row(row: &rusqlite::Row) -> rusqlite::Result<ResultKeys> {
+    Ok(ResultKeys {
+        processed_data_id: row.get(0)?,
+        kind: row.get(1)?,
+        sample_id: row.get(2)?,
+        org_id: row.get(3)?,
+        user_id: row.get(4)?,
+        raw_data_id: row.get(5)?,
+    })
+}
+
+/// Lists the stored results, newest first, of an organization, a sample and a kind
+/// when given; the reports themselves are read with `get_stored_result`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_stored_results<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    org_id: Option<String>,
+    sample_id: Option<String>,
+    kind: Option<ResultKind>,
+) -> Result<StandardResponseNoFiles<Vec<StoredResultSummary>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_results_store(&results_store_path(&app_handle)?)?;
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        for (column, value) in [
+            ("org_id", org_id),
+            ("sample_id", sample_id),
+            ("kind", kind.map(|k| k.as_str().to_string())),
+        ] {
+            if let Some(value) = value {
+                values.push(SqlValue::Text(value));
+                conditions.push(format!("{} = ?{}", column, values.len()));
+            }
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT processed_data_id, kind, sample_id, org_id, user_id, raw_data_id,
+                        created_at_ms, length(report)
+                 FROM result {} ORDER BY created_at_ms DESC",
+                filter
+            ))
+            .map_err(sql_error)?;
+        let results = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok(StoredResultSummary {
+                    keys: keys_from_row(row)?,
+                    created_at_ms: row.get(6)?,
+                    report_bytes: row.get::<_, i64>(7)? as usize,
+                })
+            })
+            .map_err(sql_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sql_error)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: results,
+        })
+    })
+    .await
+}
+
+/// Reads a stored result with its report.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_stored_result<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+) -> Result<StandardResponseNoFiles<StoredResult>, PoleshiftError> {
+    traced(invocation, None, async move {
+        parse_uuid("processed_data_id", &processed_data_id)?;
+        let conn = open_results_store(&results_store_path(&app_handle)?)?;
+        let found = conn
+            .query_row(
+                "SELECT processed_data_id, kind, sample_id, org_id, user_id, raw_data_id,
+                        created_at_ms, report
+                 FROM result WHERE processed_data_id = ?1",
+                params![processed_data_id],
+                |row| {
+                    Ok((
+                        keys_from_row(row)?,
+                        row.get::<_, i64>(6)?,
+                        row.get::<_, String>(7)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(sql_error)?;
+        let Some((keys, created_at_ms, report)) = found else {
+            return Err(PoleshiftError::InvalidInput {
+                field: "processed_data_id".to_string(),
+                reason: format!("no result is stored for {}", processed_data_id),
+            });
+        };
+
+        let metadata = conn
+            .query_row(
+                "SELECT summary, run_metadata FROM raw_sequence_metadata
+                 WHERE processed_data_id = ?1",
+                params![processed_data_id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        let raw_sequence_metadata = match metadata {
+            Some((summary, run_metadata)) => Some(RawSequenceMetadata {
+                summary: serde_json::from_str(&summary)?,
+                run_metadata: run_metadata
+                    .map(|json| serde_json::from_str(&json))
+                    .transpose()?,
+            }),
+            None => None,
+        };
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: StoredResult {
+                keys,
+                created_at_ms,
+                report: serde_json::from_str(&report)?,
+                raw_sequence_metadata,
+            },
+        })
+    })
+    .await
+}
+
+/// Removes a stored result. Returns whether there was one; the output stores of its
+/// rows are left alone.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn delete_stored_result<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+) -> Result<StandardResponseNoFiles<bool>, PoleshiftError> {
+    traced(invocation, None, async move {
+        parse_uuid("processed_data_id", &processed_data_id)?;
+        let conn = open_results_store(&results_store_path(&app_handle)?)?;
+        let deleted = conn
+            .execute(
+                "DELETE FROM result WHERE processed_data_id = ?1",
+                params![processed_data_id],
+            )
+            .map_err(sql_error)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: deleted > 0,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stored(path: &Path, processed_data_id: &str) -> (i64, String) {
+        open_results_store(path)
+            .unwrap()
+            .query_row(
+                "SELECT created_at_ms, report FROM result WHERE processed_data_id = ?1",
+                params![processed_data_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn leaves_an_unchanged_result_alone() {
+        let path = std::env::temp_dir().join(format!(
+            "poleshift-results-store-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let keys = ResultKeys {
+            processed_data_id: "7f0c1e9a-3b7e-4c55-9a55-2f6f3d1c2b10".to_string(),
+            kind: ResultKind::Nutrients,
+            sample_id: "sample".to_string(),
+            org_id: None,
+            user_id: None,
+            raw_data_id: None,
+        };
+
+        save_result(&path, &keys, &json!({ "ammonia": 1.5 }), None).unwrap();
+        let first = stored(&path, &keys.processed_data_id);
+        std::thread::sleep(Duration::from_millis(5));
+        save_result(&path, &keys, &json!({ "ammonia": 1.5 }), None).unwrap();
+        assert_eq!(stored(&path, &keys.processed_data_id), first);
+
+        save_result(&path, &keys, &json!({ "ammonia": 2.0 }), None).unwrap();
+        let (created_at_ms, report) = stored(&path, &keys.processed_data_id);
+        assert!(created_at_ms > first.0);
+        assert_eq!(report, r#"{"ammonia":2.0}"#);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,476 @@
+//poleshift/src-tauri/src/settings/mod.rs
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::devtools::{traced, traced_blocking, Invocation};
+use crate::devtools::{CommandInspector, DEFAULT_TRACE_CAPACITY};
+use crate::krakenuniq::options::{ClassificationOptions, MAX_THREADS};
+use crate::krakenuniq::refilter::ReportThresholds;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::results_store::ResultDetail;
+use crate::splashscreen::{resolve_database_dir, DEFAULT_DATABASE_ID};
+use crate::sync::ConflictPolicy;
+
+/// Event carrying the new `Settings` each time they change.
+pub const SETTINGS_EVENT: &str = "settings-changed";
+/// Settings file, in the app config directory.
+const SETTINGS_FILE: &str = "settings.toml";
+/// Copy of a settings file that failed to load, kept before it is next saved over.
+const INVALID_SETTINGS_FILE: &str = "settings.invalid.toml";
+/// Manifest a resource directory must hold (see `splashscreen::read_resource_config`).
+const RESOURCE_CONFIG: &str = "taxdb_config.toml";
+/// Largest `upload_batch_size`; bigger request bodies run into the API's size limit.
+const MAX_UPLOAD_BATCH_SIZE: usize = 10_000;
+
+/// App-wide settings, used by commands for the options a call leaves out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Directory the reference databases are downloaded to and read from; the bundled
+    /// `resources` directory when not set
+    pub resource_dir: Option<String>,
+    /// Reference database set of runs that do not name one (see `list_databases`)
+    pub database_id: String,
+    /// Classifier worker threads of runs that do not set them; one per core when not
+    /// set
+    pub classifier_threads: Option<usize>,
+    /// Executable the sidecar, Kraken2 and Centrifuge backends run; the bundled one,
+    /// else the one on `PATH`, when not set. Only the user sets it, in a file dialog
+    /// (see `choose_classifier_executable`)
+    pub classifier_path: Option<String>,
+    /// Combined rate of reference database downloads, in KiB/s; unlimited when not set
+    pub download_limit_kib: Option<u64>,
+    /// Files `verify_resources` hashes at once when not told
+    pub verify_parallelism: usize,
+    /// Thresholds `refilter_report` applies when not given its own
+    pub report_thresholds: ReportThresholds,
+    /// Rows sent to Supabase in one call by `upload_data` and the sync queue
+    pub upload_batch_size: usize,
+    /// What the sync queue does with a row changed on the server since it was written
+    /// locally
+    pub conflict_policy: ConflictPolicy,
+    /// How much of each report the local results store keeps
+    pub stored_result_detail: ResultDetail,
+    /// Whether commands are traced for the developer inspector (see `set_dev_mode`)
+    pub dev_mode: bool,
+    /// Finished commands the inspector keeps
+    pub dev_trace_capacity: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            resource_dir: None,
+            database_id: DEFAULT_DATABASE_ID.to_string(),
+            classifier_threads: None,
+            classifier_path: None,
+            download_limit_kib: None,
+            verify_parallelism: 2,
+            report_thresholds: ReportThresholds::default(),
+            upload_batch_size: 1000,
+            conflict_policy: ConflictPolicy::Manual,
+            stored_result_detail: ResultDetail::Summary,
+            dev_mode: false,
+            dev_trace_capacity: DEFAULT_TRACE_CAPACITY,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        let invalid = |field: &str, reason: String| PoleshiftError::InvalidInput {
+            field: field.to_string(),
+            reason,
+        };
+        if self.database_id.trim().is_empty() {
+            return Err(invalid("database_id", "must not be empty".to_string()));
+        }
+        if self
+            .classifier_threads
+            .is_some_and(|threads| threads == 0 || threads > MAX_THREADS)
+        {
+            return Err(invalid(
+                "classifier_threads",
+                format!("must be between 1 and {}", MAX_THREADS),
+            ));
+        }
+        if self.download_limit_kib == Some(0) {
+            return Err(invalid(
+                "download_limit_kib",
+                "must be above 0; leave it out for no limit".to_string(),
+            ));
+        }
+        if self.verify_parallelism == 0 {
+            return Err(invalid(
+                "verify_parallelism",
+                "must be 1 or more".to_string(),
+            ));
+        }
+        if self.upload_batch_size == 0 || self.upload_batch_size > MAX_UPLOAD_BATCH_SIZE {
+            return Err(invalid(
+                "upload_batch_size",
+                format!("must be between 1 and {}", MAX_UPLOAD_BATCH_SIZE),
+            ));
+        }
+        if self.dev_trace_capacity == 0 {
+            return Err(invalid(
+                "dev_trace_capacity",
+                "must be 1 or more".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that the paths changed from `previous` exist. Paths already saved are
+    /// not held to this: one may be missing for a while, e.g. on an unmounted drive, and
+    /// the commands using it report that when they run.
+    pub fn check_changed_paths(&self, previous: &Settings) -> Result<(), PoleshiftError> {
+        let invalid = |field: &str, reason: String| PoleshiftError::InvalidInput {
+            field: field.to_string(),
+            reason,
+        };
+        if let Some(path) = self
+            .classifier_path
+            .as_ref()
+            .filter(|path| previous.classifier_path.as_ref() != Some(*path))
+        {
+            if !PathBuf::from(path).is_file() {
+                return Err(invalid(
+                    "classifier_path",
+                    format!("{} is not a file", path),
+                ));
+            }
+        }
+        if let Some(dir) = self
+            .resource_dir
+            .as_ref()
+            .filter(|dir| previous.resource_dir.as_ref() != Some(*dir))
+        {
+            if !PathBuf::from(dir).is_dir() {
+                return Err(invalid(
+                    "resource_dir",
+                    format!("{} is not a directory", dir),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `options`, or the defaults, with the classifier threads filled in when unset and
+    /// the classifier executable of the settings.
+    pub fn classification_options(
+        &self,
+        options: Option<ClassificationOptions>,
+    ) -> ClassificationOptions {
+        let mut options = options.unwrap_or_default();
+        options.threads = options.threads.or(self.classifier_threads);
+        options.classifier_path = self.classifier_path.clone();
+        options
+    }
+}
+
+/// Holds the settings, loaded from `settings.toml` in the app config directory.
+///
+/// Registered as managed state and loaded with `load` from the app's setup; until
+/// then, and when the file is missing or unreadable, the defaults apply.
+#[derive(Default)]
+pub struct SettingsStore {
+    settings: Mutex<Settings>,
+}
+
+fn settings_path<R: Runtime>(app_handle: &AppHandle<R>) -> Result<PathBuf, PoleshiftError> {
+    Ok(app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join(SETTINGS_FILE))
+}
+
+impl SettingsStore {
+    /// Reads the settings file. An invalid file is reported and the defaults kept, so
+    /// a bad edit cannot stop the app from starting; it is copied to
+    /// `settings.invalid.toml` first, as the next change saves the defaults over it.
+    /// Settings this version does not know are ignored, and missing paths only warned
+    /// about.
+    pub fn load<R: Runtime>(&self, app_handle: &AppHandle<R>) -> Result<(), PoleshiftError> {
+        let path = settings_path(app_handle)?;
+        if !path.exists() {
+            return Ok(());
+        }
+        let content = fs::read_to_string(&path)?;
+        let settings = toml::from_str::<Settings>(&content)
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))
+            .and_then(|settings| settings.validate().map(|_| settings));
+        let settings = match settings {
+            Ok(settings) => settings,
+            Err(e) => {
+                let backup = path.with_file_name(INVALID_SETTINGS_FILE);
+                fs::copy(&path, &backup)?;
+                return Err(PoleshiftError::DataError(format!(
+                    "Invalid settings {} (kept as {}): {}",
+                    path.display(),
+                    backup.display(),
+                    e
+                )));
+            }
+        };
+        if let Err(e) = settings.check_changed_paths(&Settings::default()) {
+            eprintln!("Settings name a missing path: {}", e);
+        }
+        self.set(settings);
+        Ok(())
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings
+            .lock()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    fn set(&self, settings: Settings) {
+        if let Ok(mut current) = self.settings.lock() {
+            *current = settings;
+        }
+    }
+}
+
+/// The current settings, or the defaults where the store is not managed.
+pub(crate) fn current_settings<R: Runtime>(app_handle: &AppHandle<R>) -> Settings {
+    app_handle
+        .try_state::<SettingsStore>()
+        .map(|store| store.get())
+        .unwrap_or_default()
+}
+
+fn bundled_resources_dir<R: Runtime>(app_handle: &AppHandle<R>) -> Result<PathBuf, PoleshiftError> {
+    Ok(app_handle
+        .path()
+        .resource_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join("./resources"))
+}
+
+/// Directory of the reference databases: the `resource_dir` setting, else the bundled
+/// `resources` directory.
+pub(crate) fn resources_dir<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<PathBuf, PoleshiftError> {
+    match current_settings(app_handle).resource_dir {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => bundled_resources_dir(app_handle),
+    }
+}
+
+/// Saves `settings` to `settings.toml`, makes them current and emits
+/// `settings-changed`. The dev mode setting is applied to the command inspector.
+pub(crate) fn save_settings<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    settings: &Settings,
+) -> Result<(), PoleshiftError> {
+    let path = settings_path(app_handle)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = toml::to_string_pretty(settings)
+        .map_err(|e| PoleshiftError::SerializationError(e.to_string()))?;
+    fs::write(&path, content)?;
+    if let Some(store) = app_handle.try_state::<SettingsStore>() {
+        store.set(settings.clone());
+    }
+    if let Some(inspector) = app_handle.try_state::<CommandInspector>() {
+        inspector.set_enabled(settings.dev_mode, Some(settings.dev_trace_capacity));
+    }
+    app_handle
+        .emit(SETTINGS_EVENT, settings)
+        .map_err(|e| PoleshiftError::ProgressError(e.to_string()))
+}
+
+/// Lays `changes` over `current`, merging nested tables key by key.
+fn merge(current: &mut Value, changes: Value) {
+    match (current, changes) {
+        (Value::Object(current), Value::Object(changes)) => {
+            for (key, value) in changes {
+                merge(current.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (current, changes) => *current = changes,
+    }
+}
+
+/// Returns the current settings.
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_settings(
+    invocation: Invocation,
+    store: State<'_, SettingsStore>,
+) -> Result<StandardResponseNoFiles<Settings>, PoleshiftError> {
+    traced_blocking(invocation, || {
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: store.get(),
+        })
+    })
+}
+
+/// Changes the settings given in `changes` (a partial `Settings`; `null` clears an
+/// optional one), saves them to `settings.toml` and emits `settings-changed`.
+///
+/// A new `resource_dir` gets a copy of the bundled database manifest when it has none,
+/// and `database_id` must name a database set of the resource directory in use.
+/// `classifier_path` can only be cleared here, as the app runs whatever it names.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn update_settings<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    store: State<'_, SettingsStore>,
+    changes: Value,
+) -> Result<StandardResponseNoFiles<Settings>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if !changes.is_object() {
+            return Err(PoleshiftError::InvalidInput {
+                field: "changes".to_string(),
+                reason: "must be an object of settings".to_string(),
+            });
+        }
+
+        // 1) Apply the changes and check the result
+        let current = store.get();
+        let mut merged = serde_json::to_value(&current)?;
+        if let Some(unknown) = changes.as_object().and_then(|changes| {
+            changes
+                .keys()
+                .find(|key| merged.get(key.as_str()).is_none())
+        }) {
+            return Err(PoleshiftError::InvalidInput {
+                field: unknown.clone(),
+                reason: "is not a setting".to_string(),
+            });
+        }
+        if changes
+            .get("classifier_path")
+            .is_some_and(|path| !path.is_null())
+        {
+            return Err(PoleshiftError::InvalidInput {
+                field: "classifier_path".to_string(),
+                reason: "is chosen by the user with choose_classifier_executable".to_string(),
+            });
+        }
+        merge(&mut merged, changes);
+        let settings: Settings =
+            serde_json::from_value(merged).map_err(|e| PoleshiftError::InvalidInput {
+                field: "changes".to_string(),
+                reason: e.to_string(),
+            })?;
+        settings.validate()?;
+        settings.check_changed_paths(&current)?;
+
+        // 2) Prepare a new resource directory and check a new database set against the
+        // directory in use
+        if settings.resource_dir != current.resource_dir
+            || settings.database_id != current.database_id
+        {
+            let resources = match &settings.resource_dir {
+                Some(dir) => {
+                    let dir = PathBuf::from(dir);
+                    if !dir.join(RESOURCE_CONFIG).exists() {
+                        fs::copy(
+                            bundled_resources_dir(&app_handle)?.join(RESOURCE_CONFIG),
+                            dir.join(RESOURCE_CONFIG),
+                        )?;
+                    }
+                    dir
+                }
+                None => bundled_resources_dir(&app_handle)?,
+            };
+            resolve_database_dir(&resources, &settings.database_id).map_err(|reason| {
+                PoleshiftError::InvalidInput {
+                    field: "database_id".to_string(),
+                    reason,
+                }
+            })?;
+        }
+
+        // 3) Save, then tell the frontend
+        save_settings(&app_handle, &settings)?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: settings,
+        })
+    })
+    .await
+}
+
+/// Asks the user for the classifier executable in a native file dialog and saves it as
+/// the `classifier_path` setting. The settings are left as they are when the dialog is
+/// closed without a choice.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn choose_classifier_executable<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    store: State<'_, SettingsStore>,
+) -> Result<StandardResponseNoFiles<Settings>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let current = store.get();
+        let Some(picked) = app_handle
+            .dialog()
+            .file()
+            .set_title("Choose the classifier executable")
+            .blocking_pick_file()
+        else {
+            return Ok(StandardResponseNoFiles {
+                status: "Success".to_string(),
+                report: current,
+            });
+        };
+        let path = picked
+            .into_path()
+            .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?;
+        let settings = Settings {
+            classifier_path: Some(path.to_string_lossy().to_string()),
+            ..current.clone()
+        };
+        settings.check_changed_paths(&current)?;
+        save_settings(&app_handle, &settings)?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: settings,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_settings_of_a_newer_version() {
+        let settings: Settings =
+            toml::from_str("database_id = \"pr2\"\nfuture_setting = true\n").unwrap();
+        assert_eq!(settings.database_id, "pr2");
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn only_checks_paths_that_changed() {
+        let missing = std::env::temp_dir().join(format!(
+            "poleshift-missing-classifier-{}",
+            std::process::id()
+        ));
+        let saved = Settings {
+            classifier_path: Some(missing.to_string_lossy().to_string()),
+            ..Settings::default()
+        };
+        assert!(saved.check_changed_paths(&saved).is_ok());
+        assert!(saved.check_changed_paths(&Settings::default()).is_err());
+    }
+}
@@ -1,32 +1,37 @@
 use std::{
     fs::{self, File},
-    io::{BufReader, BufWriter, Read, Write},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use flate2::read::GzDecoder;
-use futures_util::{future::join_all, StreamExt};
+use futures_util::{future::join_all, stream, FutureExt, StreamExt};
 use sha2::{Digest, Sha256};
 use reqwest::header::CONTENT_TYPE;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Manager, Window};
-use tauri::Emitter;
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
+use crate::poleshift_common::types::PoleshiftError;
+use crate::settings::{current_settings, resources_dir};
 
 // -----------------------------------------------------------------------------
 // 1. Data structures & error types
 // -----------------------------------------------------------------------------
 
-#[derive(Debug)]
-pub enum PoleshiftError {
-    PathResolution(String),
-    Other(String),
-}
-
-/// TOML wrapper for your [[resource]] array
+/// TOML wrapper for your [[resource]] and [[database]] arrays
 #[derive(Debug, Deserialize)]
 struct ResourceConfig {
+    #[serde(default)]
     resource: Vec<ResourceTomlEntry>,
+    #[serde(default)]
+    database: Vec<DatabaseTomlEntry>,
 }
 
 /// Represents each [[resource]] table in the TOML
@@ -37,6 +42,32 @@ struct ResourceTomlEntry {
     checksum_compressed: String,
     checksum_decompressed: String,
     compressed: bool,
+    /// Database set this file belongs to; omitted for the default database
+    #[serde(default)]
+    database: Option<String>,
+}
+
+/// Represents each [[database]] table in the TOML
+#[derive(Debug, Deserialize)]
+struct DatabaseTomlEntry {
+    id: String,
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// ID of the database whose files live directly in the resources directory.
+pub const DEFAULT_DATABASE_ID: &str = "default";
+
+/// A named reference database set, as listed to the frontend.
+#[derive(Debug, Serialize, Clone)]
+pub struct DatabaseInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    /// True when every file of the set is present in its final form
+    pub installed: bool,
+    pub file_count: usize,
 }
 
 /// Describes a resource file to download & verify.
@@ -54,76 +85,135 @@ pub struct ResourceFiles {
     pub checksum_decompressed: String,
     /// Whether to decompress after download
     pub compressed: bool,
+    /// Database set this file belongs to
+    pub database_id: String,
 }
 
-/// Progress event payload for download.
-#[derive(serde::Serialize, Clone)]
-struct DownloadProgress {
-    file_name: String,
-    downloaded: u64,
-    total_size: u64,
+/// Job ID under which a resource reports its progress: `resource-` and the file name
+/// in lower case with every other character than a letter or digit made a `-`, e.g.
+/// `resource-database-kdb-gz` for `database.kdb.gz`.
+fn resource_job_id(file_name: &str) -> String {
+    let name: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    format!("resource-{}", name)
 }
 
-/// Progress event payload for checksum.
-#[derive(serde::Serialize, Clone)]
-struct ChecksumProgress {
-    file_name: String,
-    hashed: u64,
-    total_size: u64,
+/// Progress of one resource, reported on its own job (see `resource_job_id`) each time
+/// the whole percentage of the current step changes.
+struct ResourceProgress {
+    window: Window,
+    job: JobHandle,
+    last_percentage: AtomicU8,
 }
 
-/// Progress event payload for decompression (we track compressed bytes read)
-#[derive(serde::Serialize, Clone)]
-struct DecompressionProgress {
-    file_name: String,
-    compressed_read: u64,
-    total_compressed_size: u64,
+impl ResourceProgress {
+    /// Starts a job of `kind` for each resource; none when one of them is already
+    /// being downloaded or verified.
+    fn start_all(
+        app_handle: &AppHandle,
+        window: &Window,
+        kind: &str,
+        resources: &[ResourceFiles],
+    ) -> Result<Vec<Self>, String> {
+        let ids = resources
+            .iter()
+            .map(|res| Some(resource_job_id(&res.file_name)));
+        let jobs = app_handle
+            .state::<JobManager>()
+            .start_all(kind, ids)
+            .map_err(|e| e.to_string())?;
+        Ok(jobs
+            .into_iter()
+            .map(|job| ResourceProgress {
+                window: window.clone(),
+                job,
+                last_percentage: AtomicU8::new(u8::MAX),
+            })
+            .collect())
+    }
+
+    /// Reports `done` of `total` bytes through `step`, e.g. "Downloading".
+    fn report(&self, step: &str, done: u64, total: u64) {
+        self.job.set_stage(step);
+        let percentage = (done.min(total) * 100).checked_div(total).unwrap_or(0) as u8;
+        if self.last_percentage.swap(percentage, Ordering::Relaxed) == percentage {
+            return;
+        }
+        let message = format!("{}: {}%", step, percentage);
+        let _ = self
+            .job
+            .report_progress(&self.window, percentage, &message, "processing");
+    }
+
+    /// Ends the resource's job with the outcome of its steps.
+    fn finish(&self, app_handle: &AppHandle, error: Option<String>) {
+        let state = if error.is_some() { "error" } else { "complete" };
+        let message = error.clone().unwrap_or_else(|| "Ready".to_string());
+        let _ = self.job.report_progress(&self.window, 100, &message, state);
+        app_handle
+            .state::<JobManager>()
+            .finish(self.job.id(), error.map(PoleshiftError::IoError).as_ref());
+    }
 }
 
+/// Outcome of verifying a single resource file.
+#[derive(Debug, Serialize, Clone)]
+pub struct ResourceVerification {
+    pub file_name: String,
+    /// "ok", "missing", "mismatch" or "error"
+    pub status: String,
+    /// "full" when the whole file was hashed, "fast" when the sampled fingerprint was used
+    pub mode: String,
+    pub message: Option<String>,
+}
+
+/// Written next to a verified file so later startups can run a cheap sampled check
+/// instead of re-hashing tens of gigabytes.
+#[derive(Debug, Serialize, Deserialize)]
+struct VerificationStamp {
+    size: u64,
+    sample_hash: String,
+    checksum: String,
+}
+
+/// Number of evenly spaced blocks read for a fast fingerprint.
+const FAST_SAMPLE_BLOCKS: u64 = 16;
+/// Size of each sampled block.
+const FAST_SAMPLE_BLOCK_SIZE: usize = 1 << 20;
+
 // -----------------------------------------------------------------------------
 // 2. A custom "CountingReader" to measure compressed bytes read
 // -----------------------------------------------------------------------------
 
 /// A wrapper around a `Read` that counts how many bytes have been read,
-/// and emits "decompression-progress" events via Tauri.
-struct CountingReader<R> {
+/// and reports them as the resource's decompression progress.
+struct CountingReader<'a, R> {
     inner: R,
     bytes_read: u64,
     total_size: u64,
-    file_name: String,
-    app_handle: AppHandle,
+    progress: &'a ResourceProgress,
 }
 
-impl<R: Read> CountingReader<R> {
-    fn new(
-        inner: R,
-        total_size: u64,
-        file_name: String,
-        app_handle: AppHandle,
-    ) -> Self {
+impl<'a, R: Read> CountingReader<'a, R> {
+    fn new(inner: R, total_size: u64, progress: &'a ResourceProgress) -> Self {
         CountingReader {
             inner,
             bytes_read: 0,
             total_size,
-            file_name,
-            app_handle,
+            progress,
         }
     }
 }
 
-impl<R: Read> Read for CountingReader<R> {
+impl<R: Read> Read for CountingReader<'_, R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let n = self.inner.read(buf)?;
         if n > 0 {
             self.bytes_read += n as u64;
-
-            // Emit an event for each chunk read
-            let payload = DecompressionProgress {
-                file_name: self.file_name.clone(),
-                compressed_read: self.bytes_read,
-                total_compressed_size: self.total_size,
-            };
-            let _ = self.app_handle.emit("decompression-progress", payload);
+            self.progress
+                .report("Decompressing", self.bytes_read, self.total_size);
         }
         Ok(n)
     }
@@ -135,41 +225,92 @@ impl<R: Read> Read for CountingReader<R> {
 
 /// Closes the splashscreen window and shows the main window.
 #[tauri::command]
-pub async fn close_splashscreen(window: Window) {
-    if let Some(splash) = window.get_window("splashscreen") {
-        let _ = splash.close();
-    }
-    if let Some(main) = window.get_window("main") {
-        let _ = main.show();
-    }
+pub async fn close_splashscreen(invocation: Invocation, window: Window) -> Result<(), String> {
+    traced(invocation, None, async move {
+        if let Some(splash) = window.get_window("splashscreen") {
+            let _ = splash.close();
+        }
+        if let Some(main) = window.get_window("main") {
+            let _ = main.show();
+        }
+        Ok(())
+    })
+    .await
 }
 
 /// Main command: downloads, decompresses (if needed), and verifies multiple resources in parallel.
+/// Runs as a `download` job, so it is listed by `list_jobs` and can be cancelled. Each
+/// resource reports its progress on `job://resource-{name}/progress` (see
+/// `resource_job_id`).
 #[tauri::command]
-pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
+pub async fn download_resources(
+    invocation: Invocation,
+    app_handle: AppHandle,
+    window: Window,
+) -> Result<(), String> {
+    let jobs = app_handle.state::<JobManager>();
+    let job = jobs.start("download", None).map_err(|e| e.to_string())?;
+    job.set_stage("downloading");
+    let result = traced(
+        invocation,
+        Some(job.id()),
+        fetch_resources(app_handle.clone(), window, job.clone()),
+    )
+    .await;
+    let error = result.as_ref().err().map(|e| PoleshiftError::IoError(e.clone()));
+    jobs.finish(job.id(), error.as_ref());
+    result
+}
+
+/// Waits until `downloaded` bytes since `started` are within `limit_kib` KiB/s.
+async fn throttle(limit_kib: Option<u64>, started: Instant, downloaded: u64) {
+    let Some(limit_kib) = limit_kib else { return };
+    let due = Duration::from_secs_f64(downloaded as f64 / (limit_kib as f64 * 1024.0));
+    if let Some(wait) = due.checked_sub(started.elapsed()) {
+        let _ = tauri::async_runtime::spawn_blocking(move || std::thread::sleep(wait)).await;
+    }
+}
+
+/// The work of `download_resources`; stops downloading once `job` is cancelled. All
+/// downloads together keep to the `download_limit_kib` setting.
+async fn fetch_resources(
+    app_handle: AppHandle,
+    window: Window,
+    job: JobHandle,
+) -> Result<(), String> {
     // 1) Find/create the resource directory
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))
-        .map_err(|e| format!("Failed to get resource dir: {:?}", e))?
-        .join("resources");
+    let resource_dir = resources_dir(&app_handle)
+        .map_err(|e| format!("Failed to get resource dir: {}", e))?;
 
     fs::create_dir_all(&resource_dir)
         .map_err(|e| format!("Failed to create resource directory: {e}"))?;
 
-    // 2) Load the resources from TOML
+    // 2) Load the resources from TOML, creating the directory of each database set
     let resources = load_resource_configs(&resource_dir)
         .map_err(|e| format!("Could not load resource config: {e}"))?;
+    for res in &resources {
+        if let Some(parent) = Path::new(&res.file_path).parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+        }
+    }
 
-    // 3) Build a future for each resource
+    // 3) Build a future for each resource, sharing the download rate limit
     let client = Arc::new(reqwest::Client::new());
+    let download_limit_kib = current_settings(&app_handle).download_limit_kib;
+    let started = Instant::now();
+    let downloaded_total = Arc::new(AtomicU64::new(0));
     let app_handle = Arc::new(app_handle);
 
-    let tasks = resources.into_iter().map(|res| {
+    let progresses = ResourceProgress::start_all(&app_handle, &window, "download", &resources)?;
+    let tasks = resources.into_iter().zip(progresses).map(|(res, progress)| {
         let client = client.clone();
         let app_handle = app_handle.clone();
+        let job = job.clone();
         let resource_dir = resource_dir.clone();
+        let downloaded_total = downloaded_total.clone();
+        let progress = Arc::new(progress);
+        let finished = (app_handle.clone(), progress.clone());
 
         async move {
             let compressed_path = resource_dir.join(&res.file_name);
@@ -190,8 +331,7 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
                 if !res.checksum_compressed.is_empty() {
                     match sha256_of_file_with_progress(
                         &compressed_unchecked_path,
-                        &res.file_name,
-                        &app_handle,
+                        &progress,
                     ) {
                         Ok(hash) => {
                             if hash != res.checksum_compressed {
@@ -262,20 +402,23 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
                 while let Some(chunk_result) = stream.next().await {
                     let chunk = chunk_result
                         .map_err(|e| format!("Error reading chunk for {}: {e}", res.file_name))?;
+                    if job.is_cancelled() || progress.job.is_cancelled() {
+                        drop(writer);
+                        let _ = fs::remove_file(&compressed_unchecked_path);
+                        return Err(format!("Download of {} was cancelled", res.file_name));
+                    }
                     writer.write_all(&chunk).map_err(|e| {
                         format!("Failed to write chunk for {}: {e}", res.file_name)
                     })?;
 
-                    // Emit partial download progress
+                    // Report partial download progress
                     downloaded += chunk.len() as u64;
-                    let payload = DownloadProgress {
-                        file_name: res.file_name.clone(),
-                        downloaded,
-                        total_size,
-                    };
-                    app_handle
-                        .emit("download-progress", payload)
-                        .map_err(|e| format!("Failed to emit download progress: {e}"))?;
+                    job.add_reads(chunk.len() as u64);
+                    progress.report("Downloading", downloaded, total_size);
+
+                    let total = downloaded_total.fetch_add(chunk.len() as u64, Ordering::Relaxed)
+                        + chunk.len() as u64;
+                    throttle(download_limit_kib, started, total).await;
                 }
                 drop(writer);
 
@@ -283,8 +426,7 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
                 if !res.checksum_compressed.is_empty() {
                     match sha256_of_file_with_progress(
                         &compressed_unchecked_path,
-                        &res.file_name,
-                        &app_handle,
+                        &progress,
                     ) {
                         Ok(hash) => {
                             if hash != res.checksum_compressed {
@@ -336,8 +478,7 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
                     if !res.checksum_decompressed.is_empty() {
                         match sha256_of_file_with_progress(
                             &final_unchecked_path,
-                            &res.file_name,
-                            &app_handle,
+                            &progress,
                         ) {
                             Ok(hash) => {
                                 if hash != res.checksum_decompressed {
@@ -394,8 +535,7 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
                     let counting_reader = CountingReader::new(
                         BufReader::new(compressed_file),
                         total_size,
-                        res.file_name.clone(),
-                        app_handle.as_ref().clone(),
+                        &progress,
                     );
 
                     let mut gz_decoder = GzDecoder::new(counting_reader);
@@ -404,7 +544,7 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
                             format!("Cannot create {}: {e}", final_unchecked_path.display())
                         })?);
 
-                    // Decompress in chunks; the CountingReader emits progress
+                    // Decompress in chunks; the CountingReader reports progress
                     std::io::copy(&mut gz_decoder, &mut output_file)
                         .map_err(|e| format!("Error decompressing {}: {e}", res.file_name))?;
 
@@ -412,8 +552,7 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
                     if !res.checksum_decompressed.is_empty() {
                         match sha256_of_file_with_progress(
                             &final_unchecked_path,
-                            &res.file_name,
-                            &app_handle,
+                            &progress,
                         ) {
                             Ok(hash) => {
                                 if hash != res.checksum_decompressed {
@@ -458,6 +597,11 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
 
             Ok::<_, String>(())
         }
+        .map(move |result| {
+            let (app_handle, progress) = finished;
+            progress.finish(&app_handle, result.as_ref().err().cloned());
+            result
+        })
     });
 
     // 4) Run tasks concurrently
@@ -473,14 +617,112 @@ pub async fn download_resources(app_handle: AppHandle) -> Result<(), String> {
     Ok(())
 }
 
+/// Verifies every resource listed in `taxdb_config.toml` without downloading anything.
+///
+/// Files are hashed concurrently, at most `max_parallel` at a time. With `fast = true`,
+/// files that carry a stamp from an earlier full verification are only checked by size
+/// and a hash of sampled blocks; files without a stamp fall back to a full hash.
+///
+/// Runs as a `verify` job, reporting each verified file on `job://{job_id}/progress`;
+/// each file reports its hashing on its own job, as with `download_resources`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn verify_resources(
+    invocation: Invocation,
+    app_handle: AppHandle,
+    window: Window,
+    fast: bool,
+    max_parallel: Option<usize>,
+    job_id: Option<String>,
+) -> Result<Vec<ResourceVerification>, String> {
+    let jobs = app_handle.state::<JobManager>();
+    let job = jobs.start("verify", job_id).map_err(|e| e.to_string())?;
+    job.set_stage("verifying");
+    let result = traced(
+        invocation,
+        Some(job.id()),
+        check_resources(&app_handle, &window, &job, fast, max_parallel),
+    )
+    .await;
+    let error = result.as_ref().err().map(|e| PoleshiftError::IoError(e.clone()));
+    jobs.finish(job.id(), error.as_ref());
+    result
+}
+
+/// The work of `verify_resources`.
+async fn check_resources(
+    app_handle: &AppHandle,
+    window: &Window,
+    job: &JobHandle,
+    fast: bool,
+    max_parallel: Option<usize>,
+) -> Result<Vec<ResourceVerification>, String> {
+    let resource_dir = resources_dir(app_handle)
+        .map_err(|e| format!("Failed to get resource dir: {}", e))?;
+
+    let resources = load_resource_configs(&resource_dir)
+        .map_err(|e| format!("Could not load resource config: {e}"))?;
+
+    let total = resources.len();
+    job.set_reads_total(total as u64);
+    let parallelism = max_parallel
+        .unwrap_or(current_settings(app_handle).verify_parallelism)
+        .max(1);
+    let completed = Arc::new(AtomicUsize::new(0));
+
+    let progresses = ResourceProgress::start_all(app_handle, window, "verify", &resources)?;
+    let pending = resources.into_iter().zip(progresses);
+    let results = stream::iter(pending.map(|(res, progress)| {
+        let completed = completed.clone();
+
+        async move {
+            let file_name = res.file_name.clone();
+            let progress = Arc::new(progress);
+            let blocking_progress = progress.clone();
+            let outcome = tauri::async_runtime::spawn_blocking(move || {
+                verify_resource_file(&res, fast, &blocking_progress)
+            })
+            .await
+            .unwrap_or_else(|e| ResourceVerification {
+                file_name: file_name.clone(),
+                status: "error".to_string(),
+                mode: "full".to_string(),
+                message: Some(format!("Verification task failed: {e}")),
+            });
+
+            let error = (outcome.status != "ok").then(|| {
+                outcome
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| outcome.status.clone())
+            });
+            progress.finish(app_handle, error);
+
+            let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+            job.set_reads(done as u64);
+            let _ = job.report_progress(
+                window,
+                (done * 100 / total) as u8,
+                &format!("Verified {} ({} of {})", file_name, done, total),
+                "processing",
+            );
+            outcome
+        }
+    }))
+    .buffer_unordered(parallelism)
+    .collect::<Vec<_>>()
+    .await;
+
+    Ok(results)
+}
+
 // -----------------------------------------------------------------------------
 // 4. Support utilities: config loader + hashing with progress
 // -----------------------------------------------------------------------------
 
-/// Reads `taxdb_config.toml` in the given `resource_dir`.
-fn load_resource_configs(
+/// Reads and parses `taxdb_config.toml` in the given `resource_dir`.
+fn read_resource_config(
     resource_dir: &Path,
-) -> Result<Vec<ResourceFiles>, Box<dyn std::error::Error>> {
+) -> Result<ResourceConfig, Box<dyn std::error::Error>> {
     // We expect a file `taxdb_config.toml` in the `resources` directory
     let config_path = resource_dir.join("taxdb_config.toml");
     if !config_path.exists() {
@@ -491,9 +733,46 @@ fn load_resource_configs(
     let toml_content = fs::read_to_string(&config_path)?;
     let parsed: ResourceConfig = toml::from_str(&toml_content)?;
 
+    // Database IDs become directory names, so keep them to a safe alphabet
+    for database in &parsed.database {
+        if !is_valid_database_id(&database.id) {
+            return Err(format!("Invalid database id '{}'", database.id).into());
+        }
+    }
+    for entry in &parsed.resource {
+        if let Some(id) = &entry.database {
+            if id != DEFAULT_DATABASE_ID && !parsed.database.iter().any(|d| d.id == *id) {
+                return Err(
+                    format!("{} refers to unknown database '{}'", entry.file_name, id).into(),
+                );
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+fn is_valid_database_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Reads `taxdb_config.toml` in the given `resource_dir`.
+///
+/// Files of the default database live directly in `resource_dir`; files of any other
+/// set live in `resource_dir/<database id>/`, and their `file_name` carries that prefix
+/// so downloads land there and progress events stay unambiguous.
+fn load_resource_configs(
+    resource_dir: &Path,
+) -> Result<Vec<ResourceFiles>, Box<dyn std::error::Error>> {
+    let parsed = read_resource_config(resource_dir)?;
+    Ok(to_resource_files(resource_dir, parsed.resource))
+}
+
+fn to_resource_files(resource_dir: &Path, entries: Vec<ResourceTomlEntry>) -> Vec<ResourceFiles> {
     // Convert each TOML entry into the final ResourceFiles
-    let resource_files = parsed
-        .resource
+    entries
         .into_iter()
         .map(|entry| {
             // We'll remove the trailing ".gz" if entry is compressed
@@ -503,25 +782,107 @@ fn load_resource_configs(
                 entry.file_name.clone()
             };
 
+            let database_id = entry
+                .database
+                .unwrap_or_else(|| DEFAULT_DATABASE_ID.to_string());
+            let (file_name, database_dir) = if database_id == DEFAULT_DATABASE_ID {
+                (entry.file_name, resource_dir.to_path_buf())
+            } else {
+                (
+                    format!("{}/{}", database_id, entry.file_name),
+                    resource_dir.join(&database_id),
+                )
+            };
+
             ResourceFiles {
-                file_name: entry.file_name,
+                file_name,
                 file_url: entry.file_url,
-                file_path: resource_dir.join(decompressed_name).to_string_lossy().to_string(),
+                file_path: database_dir.join(decompressed_name).to_string_lossy().to_string(),
                 checksum_compressed: entry.checksum_compressed,
                 checksum_decompressed: entry.checksum_decompressed,
                 compressed: entry.compressed,
+                database_id,
             }
         })
+        .collect()
+}
+
+/// Lists the reference database sets declared in `taxdb_config.toml`. The default set
+/// is always listed, even when the manifest does not describe it.
+pub(crate) fn load_database_catalog(resource_dir: &Path) -> Result<Vec<DatabaseInfo>, String> {
+    let parsed = read_resource_config(resource_dir)
+        .map_err(|e| format!("Could not load resource config: {e}"))?;
+    let resources = to_resource_files(resource_dir, parsed.resource);
+
+    let mut entries: Vec<(String, String, String)> = parsed
+        .database
+        .into_iter()
+        .map(|d| (d.id, d.name, d.description))
         .collect();
+    if !entries.iter().any(|(id, ..)| id == DEFAULT_DATABASE_ID) {
+        entries.insert(
+            0,
+            (
+                DEFAULT_DATABASE_ID.to_string(),
+                "Default".to_string(),
+                String::new(),
+            ),
+        );
+    }
 
-    Ok(resource_files)
+    Ok(entries
+        .into_iter()
+        .map(|(id, name, description)| {
+            let files: Vec<&ResourceFiles> =
+                resources.iter().filter(|r| r.database_id == id).collect();
+            DatabaseInfo {
+                installed: !files.is_empty()
+                    && files.iter().all(|r| Path::new(&r.file_path).exists()),
+                file_count: files.len(),
+                id,
+                name,
+                description,
+            }
+        })
+        .collect())
 }
 
-/// Computes the SHA-256 hash of a file, emitting partial progress events.
+/// Resolves the directory holding the files of `database_id`, rejecting IDs that the
+/// manifest does not declare.
+pub(crate) fn resolve_database_dir(
+    resource_dir: &Path,
+    database_id: &str,
+) -> Result<PathBuf, String> {
+    if database_id == DEFAULT_DATABASE_ID {
+        return Ok(resource_dir.to_path_buf());
+    }
+    let catalog = load_database_catalog(resource_dir)?;
+    if catalog.iter().any(|d| d.id == database_id) {
+        Ok(resource_dir.join(database_id))
+    } else {
+        Err(format!("Unknown database '{}'", database_id))
+    }
+}
+
+/// Lists the available reference databases and whether each is installed.
+#[tauri::command]
+pub async fn list_databases(
+    invocation: Invocation,
+    app_handle: AppHandle,
+) -> Result<Vec<DatabaseInfo>, String> {
+    traced(invocation, None, async move {
+        let resource_dir = resources_dir(&app_handle)
+            .map_err(|e| format!("Failed to get resource dir: {}", e))?;
+
+        load_database_catalog(&resource_dir)
+    })
+    .await
+}
+
+/// Computes the SHA-256 hash of a file, reporting partial progress as it goes.
 fn sha256_of_file_with_progress(
     path: &std::path::Path,
-    file_name: &str,
-    app_handle: &tauri::AppHandle,
+    progress: &ResourceProgress,
 ) -> Result<String, std::io::Error> {
     use std::io::{BufReader, Read};
     use std::fs::File;
@@ -543,16 +904,132 @@ fn sha256_of_file_with_progress(
         hasher.update(&buffer[..n]);
         hashed += n as u64;
 
-        // Emit partial progress: "checksum-progress"
-        let payload = ChecksumProgress {
-            file_name: file_name.to_string(),
-            hashed,
-            total_size,
-        };
-        let _ = app_handle.emit("checksum-progress", payload);
+        // Report partial progress
+        progress.report("Verifying", hashed, total_size);
     }
 
     // Convert final digest to hex
     let digest = hasher.finalize();
     Ok(hex::encode(digest))
 }
+
+/// Verifies one resource, choosing between the sampled fingerprint and a full hash.
+fn verify_resource_file(
+    res: &ResourceFiles,
+    fast: bool,
+    progress: &ResourceProgress,
+) -> ResourceVerification {
+    // Compressed resources are checked in their final (decompressed) form
+    let path = PathBuf::from(&res.file_path);
+    let expected = if res.compressed {
+        &res.checksum_decompressed
+    } else {
+        &res.checksum_compressed
+    };
+    let stamp_path = PathBuf::from(format!("{}.verified", path.display()));
+
+    let result = |status: &str, mode: &str, message: Option<String>| ResourceVerification {
+        file_name: res.file_name.clone(),
+        status: status.to_string(),
+        mode: mode.to_string(),
+        message,
+    };
+
+    if !path.exists() {
+        return result("missing", "full", Some(format!("{} not found", path.display())));
+    }
+
+    if fast {
+        if let Some(stamp) = read_verification_stamp(&stamp_path) {
+            if stamp.checksum == *expected {
+                return match sampled_fingerprint(&path) {
+                    Ok((size, sample_hash)) => {
+                        if size == stamp.size && sample_hash == stamp.sample_hash {
+                            result("ok", "fast", None)
+                        } else {
+                            result(
+                                "mismatch",
+                                "fast",
+                                Some("Sampled fingerprint differs from last verification".into()),
+                            )
+                        }
+                    }
+                    Err(e) => result("error", "fast", Some(e.to_string())),
+                };
+            }
+        }
+    }
+
+    if expected.is_empty() {
+        return result("ok", "full", Some("No checksum configured".into()));
+    }
+
+    match sha256_of_file_with_progress(&path, progress) {
+        Ok(hash) if hash == *expected => {
+            if let Err(e) = write_verification_stamp(&path, &stamp_path, expected) {
+                println!("Could not write verification stamp for {}: {e}", res.file_name);
+            }
+            result("ok", "full", None)
+        }
+        Ok(hash) => {
+            let _ = fs::remove_file(&stamp_path);
+            result(
+                "mismatch",
+                "full",
+                Some(format!("Expected: {}\nFound: {}", expected, hash)),
+            )
+        }
+        Err(e) => result("error", "full", Some(e.to_string())),
+    }
+}
+
+/// Computes the file size plus a SHA-256 over evenly spaced blocks of the file.
+fn sampled_fingerprint(path: &Path) -> Result<(u64, String), std::io::Error> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+
+    let mut buffer = vec![0u8; FAST_SAMPLE_BLOCK_SIZE];
+    let block_size = FAST_SAMPLE_BLOCK_SIZE as u64;
+    let span = size.saturating_sub(block_size);
+
+    for i in 0..FAST_SAMPLE_BLOCKS {
+        // Spread the blocks from the start to the very end of the file
+        let offset = span * i / (FAST_SAMPLE_BLOCKS - 1);
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let n = file.read(&mut buffer[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        hasher.update(&buffer[..filled]);
+    }
+
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+fn read_verification_stamp(stamp_path: &Path) -> Option<VerificationStamp> {
+    let content = fs::read_to_string(stamp_path).ok()?;
+    toml::from_str(&content).ok()
+}
+
+fn write_verification_stamp(
+    path: &Path,
+    stamp_path: &Path,
+    checksum: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (size, sample_hash) = sampled_fingerprint(path)?;
+    let stamp = VerificationStamp {
+        size,
+        sample_hash,
+        checksum: checksum.to_string(),
+    };
+    fs::write(stamp_path, toml::to_string(&stamp)?)?;
+    Ok(())
+}
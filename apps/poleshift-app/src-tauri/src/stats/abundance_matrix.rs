@@ -0,0 +1,201 @@
+//poleshift/src-tauri/src/stats/abundance_matrix.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::stats::sample_reports::{load_sample_reports, SampleOverview, SampleReport};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MatrixOptions {
+    /// Only keep taxa of this rank (e.g. "genus"), so rows do not overlap
+    pub rank: Option<String>,
+    /// Keep the unclassified bin as a row
+    pub include_unclassified: bool,
+    /// Also write the matrix to this file; `.csv` and `.tsv` are supported
+    pub output_path: Option<String>,
+}
+
+/// Row labels of the matrix.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatrixTaxon {
+    pub tax_id: u64,
+    pub tax_name: String,
+    pub rank: String,
+    pub lineage: String,
+}
+
+/// Taxa × samples counts. `reads[t][s]` are the clade reads of `taxa[t]` in
+/// `samples[s]`; `relative_abundance` divides them by the sample's total reads.
+#[derive(Debug, Serialize)]
+pub struct AbundanceMatrix {
+    pub samples: Vec<SampleOverview>,
+    pub taxa: Vec<MatrixTaxon>,
+    pub reads: Vec<Vec<u64>>,
+    pub relative_abundance: Vec<Vec<f64>>,
+    pub output_path: Option<String>,
+}
+
+/// Merges the sample reports into one matrix keyed by tax ID. A taxon's name and
+/// lineage come from the first sample that has it, so they are the same in every
+/// column.
+pub(crate) fn build_matrix(samples: &[SampleReport], options: &MatrixOptions) -> AbundanceMatrix {
+    let rank = options.rank.as_deref().map(str::to_lowercase);
+    let keep = |tax_id: u64, taxon_rank: &str| {
+        if tax_id == 0 {
+            return options.include_unclassified;
+        }
+        rank.as_deref()
+            .is_none_or(|r| taxon_rank.eq_ignore_ascii_case(r))
+    };
+
+    let mut taxa: BTreeMap<u64, MatrixTaxon> = BTreeMap::new();
+    let per_sample: Vec<HashMap<u64, u64>> = samples
+        .iter()
+        .map(|sample| {
+            sample
+                .taxa
+                .iter()
+                .filter(|t| keep(t.tax_id, &t.rank))
+                .map(|t| {
+                    taxa.entry(t.tax_id).or_insert_with(|| MatrixTaxon {
+                        tax_id: t.tax_id,
+                        tax_name: t.tax_name.clone(),
+                        rank: t.rank.clone(),
+                        lineage: t.lineage.clone(),
+                    });
+                    (t.tax_id, t.reads)
+                })
+                .collect()
+        })
+        .collect();
+
+    let taxa: Vec<MatrixTaxon> = taxa.into_values().collect();
+    let reads: Vec<Vec<u64>> = taxa
+        .iter()
+        .map(|taxon| {
+            per_sample
+                .iter()
+                .map(|counts| counts.get(&taxon.tax_id).copied().unwrap_or(0))
+                .collect()
+        })
+        .collect();
+    let relative_abundance = reads
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(samples)
+                .map(|(&r, s)| {
+                    if s.total_reads > 0 {
+                        r as f64 / s.total_reads as f64
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    AbundanceMatrix {
+        samples: samples.iter().map(SampleOverview::from).collect(),
+        taxa,
+        reads,
+        relative_abundance,
+        output_path: None,
+    }
+}
+
+fn quote_field(value: &str, delimiter: char) -> String {
+    if value.contains(delimiter) || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes one row per taxon, with a reads and a relative abundance column per sample.
+fn write_matrix(matrix: &AbundanceMatrix, path: &Path) -> Result<(), PoleshiftError> {
+    let delimiter = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => ',',
+        Some(ext) if ext.eq_ignore_ascii_case("tsv") => '\t',
+        _ => {
+            return Err(PoleshiftError::DataError(format!(
+                "Unsupported matrix format for {}; use .csv or .tsv",
+                path.display()
+            )))
+        }
+    };
+    let sep = delimiter.to_string();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut header: Vec<String> = ["tax_id", "tax_name", "rank", "lineage"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+    for sample in &matrix.samples {
+        header.push(quote_field(
+            &format!("{}_reads", sample.processed_data_id),
+            delimiter,
+        ));
+    }
+    for sample in &matrix.samples {
+        header.push(quote_field(
+            &format!("{}_relative_abundance", sample.processed_data_id),
+            delimiter,
+        ));
+    }
+    writeln!(writer, "{}", header.join(&sep))?;
+
+    for (t, taxon) in matrix.taxa.iter().enumerate() {
+        let mut fields = vec![
+            taxon.tax_id.to_string(),
+            quote_field(&taxon.tax_name, delimiter),
+            quote_field(&taxon.rank, delimiter),
+            quote_field(&taxon.lineage, delimiter),
+        ];
+        fields.extend(matrix.reads[t].iter().map(|r| r.to_string()));
+        fields.extend(matrix.relative_abundance[t].iter().map(|r| r.to_string()));
+        writeln!(writer, "{}", fields.join(&sep))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Merges the stored reports of `processed_data_ids` into a taxa × samples matrix of
+/// read counts and relative abundances, optionally also written to CSV or TSV.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn build_abundance_matrix<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_ids: Vec<String>,
+    options: Option<MatrixOptions>,
+) -> Result<StandardResponseNoFiles<AbundanceMatrix>, PoleshiftError> {
+    traced(invocation, None, async move {
+        if processed_data_ids.is_empty() {
+            return Err(PoleshiftError::DataError(
+                "No samples given for the abundance matrix".to_string(),
+            ));
+        }
+        let options = options.unwrap_or_default();
+        let samples = load_sample_reports(&app_handle, &processed_data_ids)?;
+        let mut matrix = build_matrix(&samples, &options);
+
+        if let Some(output_path) = &options.output_path {
+            write_matrix(&matrix, Path::new(output_path))?;
+            matrix.output_path = Some(output_path.clone());
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: matrix,
+        })
+    })
+    .await
+}
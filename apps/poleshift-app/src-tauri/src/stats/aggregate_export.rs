@@ -0,0 +1,321 @@
+//poleshift/src-tauri/src/stats/aggregate_export.rs
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// One sample's numeric values, tagged with the dashboard group it belongs to
+/// (e.g. a location name or a cruise leg).
+#[derive(Debug, Deserialize)]
+pub struct SampleMeasurement {
+    pub sample_id: String,
+    pub group: String,
+    pub values: HashMap<String, f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregationOptions {
+    /// Groups (and variables within a group) backed by fewer samples are suppressed
+    #[serde(default = "default_min_sample_count")]
+    pub min_sample_count: usize,
+    /// Privacy budget for Laplace noise, split evenly over each group's sample count
+    /// and variable means; no noise is added when absent
+    pub noise_epsilon: Option<f64>,
+    /// Public lower/upper bounds per variable, required for every noised variable
+    #[serde(default)]
+    pub value_bounds: HashMap<String, (f64, f64)>,
+}
+
+fn default_min_sample_count() -> usize {
+    5
+}
+
+#[derive(Debug, Serialize)]
+pub struct AggregatedVariable {
+    pub name: String,
+    /// Only reported when no noise is requested
+    pub sample_count: Option<usize>,
+    pub mean: f64,
+    /// Only reported when no noise is requested
+    pub std_dev: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AggregatedGroup {
+    pub group: String,
+    /// Noised and rounded when noise is requested
+    pub sample_count: usize,
+    pub variables: Vec<AggregatedVariable>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AggregatedExport {
+    /// Human-readable label to display next to any shared chart
+    pub label: String,
+    pub min_sample_count: usize,
+    pub noise_added: bool,
+    pub noise_epsilon: Option<f64>,
+    pub groups: Vec<AggregatedGroup>,
+    /// Number of groups withheld for having too few samples
+    pub suppressed_groups: usize,
+}
+
+/// Builds a dashboard-safe export: only group-level statistics backed by at least
+/// `min_sample_count` samples are emitted, optionally with Laplace noise.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn export_aggregated_stats(
+    invocation: Invocation,
+    measurements: Vec<SampleMeasurement>,
+    options: AggregationOptions,
+) -> Result<StandardResponseNoFiles<AggregatedExport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let report = aggregate_measurements(&measurements, &options)?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
+
+pub fn aggregate_measurements(
+    measurements: &[SampleMeasurement],
+    options: &AggregationOptions,
+) -> Result<AggregatedExport, PoleshiftError> {
+    if options.min_sample_count == 0 {
+        return Err(PoleshiftError::DataError(
+            "min_sample_count must be at least 1".to_string(),
+        ));
+    }
+    if let Some(epsilon) = options.noise_epsilon {
+        if !(epsilon > 0.0 && epsilon.is_finite()) {
+            return Err(PoleshiftError::DataError(
+                "noise_epsilon must be a positive number".to_string(),
+            ));
+        }
+    }
+    // `f64::clamp` panics on reversed or NaN bounds
+    for (name, (lo, hi)) in &options.value_bounds {
+        if !(lo.is_finite() && hi.is_finite() && lo < hi) {
+            return Err(PoleshiftError::DataError(format!(
+                "value_bounds for '{}' must be finite with lower < upper, got ({}, {})",
+                name, lo, hi
+            )));
+        }
+    }
+
+    // group -> variable -> sample -> values (BTreeMap keeps the export order stable)
+    let mut grouped: BTreeMap<&str, BTreeMap<&str, HashMap<&str, Vec<f64>>>> = BTreeMap::new();
+    let mut group_samples: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for m in measurements {
+        group_samples
+            .entry(m.group.as_str())
+            .or_default()
+            .insert(m.sample_id.as_str());
+        let vars = grouped.entry(m.group.as_str()).or_default();
+        for (name, value) in &m.values {
+            if value.is_finite() {
+                vars.entry(name.as_str())
+                    .or_default()
+                    .entry(m.sample_id.as_str())
+                    .or_default()
+                    .push(*value);
+            }
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut groups = Vec::new();
+    let mut suppressed_groups = 0;
+
+    for (group, vars) in grouped {
+        let sample_count = group_samples.get(group).map(|s| s.len()).unwrap_or(0);
+        if sample_count < options.min_sample_count {
+            suppressed_groups += 1;
+            continue;
+        }
+
+        // A sample measured more than once counts once, with the mean of its values
+        let vars: Vec<(&str, Vec<f64>)> = vars
+            .into_iter()
+            .map(|(name, samples)| {
+                let values = samples
+                    .into_values()
+                    .map(|values| values.iter().sum::<f64>() / values.len() as f64)
+                    .collect::<Vec<_>>();
+                (name, values)
+            })
+            .filter(|(_, values)| values.len() >= options.min_sample_count)
+            .collect();
+        // The group's count and each of its variable means take an equal share
+        let share = options
+            .noise_epsilon
+            .map(|epsilon| epsilon / (vars.len() + 1) as f64);
+
+        let mut variables = Vec::new();
+        for (name, values) in vars {
+            let variable = match share {
+                Some(epsilon) => {
+                    let (lo, hi) = options.value_bounds.get(name).copied().ok_or_else(|| {
+                        PoleshiftError::DataError(format!(
+                            "value_bounds missing for noised variable '{}'",
+                            name
+                        ))
+                    })?;
+                    let n = values.len() as f64;
+                    let clamped_mean = values.iter().map(|v| v.clamp(lo, hi)).sum::<f64>() / n;
+                    // Sensitivity of a bounded mean over n samples is (hi - lo) / n
+                    let scale = (hi - lo).abs() / n / epsilon;
+                    AggregatedVariable {
+                        name: name.to_string(),
+                        sample_count: None,
+                        mean: clamped_mean + laplace_noise(&mut rng, scale),
+                        std_dev: None,
+                    }
+                }
+                None => {
+                    let n = values.len() as f64;
+                    let mean = values.iter().sum::<f64>() / n;
+                    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+                    AggregatedVariable {
+                        name: name.to_string(),
+                        sample_count: Some(values.len()),
+                        mean,
+                        std_dev: Some(variance.sqrt()),
+                    }
+                }
+            };
+            variables.push(variable);
+        }
+
+        // A count changes by at most 1 with one sample
+        let sample_count = match share {
+            Some(epsilon) => {
+                let noised = sample_count as f64 + laplace_noise(&mut rng, 1.0 / epsilon);
+                noised.round().max(0.0) as usize
+            }
+            None => sample_count,
+        };
+        groups.push(AggregatedGroup {
+            group: group.to_string(),
+            sample_count,
+            variables,
+        });
+    }
+
+    let label = match options.noise_epsilon {
+        Some(epsilon) => format!(
+            "Aggregated statistics (groups with >= {} samples, Laplace noise with a total epsilon of {} per group). Per-sample values withheld.",
+            options.min_sample_count, epsilon
+        ),
+        None => format!(
+            "Aggregated statistics (groups with >= {} samples). Per-sample values withheld.",
+            options.min_sample_count
+        ),
+    };
+
+    Ok(AggregatedExport {
+        label,
+        min_sample_count: options.min_sample_count,
+        noise_added: options.noise_epsilon.is_some(),
+        noise_epsilon: options.noise_epsilon,
+        groups,
+        suppressed_groups,
+    })
+}
+
+/// Draws a sample from a zero-centred Laplace distribution with the given scale.
+fn laplace_noise<R: Rng>(rng: &mut R, scale: f64) -> f64 {
+    if scale == 0.0 {
+        return 0.0;
+    }
+    // `u` must lie in the open interval: at -0.5 the logarithm below is ln(0)
+    let u = loop {
+        let u: f64 = rng.gen_range(-0.5..0.5);
+        if u != -0.5 {
+            break u;
+        }
+    };
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    fn measurements() -> Vec<SampleMeasurement> {
+        (0..5)
+            .map(|i| SampleMeasurement {
+                sample_id: format!("s{}", i),
+                group: "station".to_string(),
+                values: HashMap::from([("temperature".to_string(), i as f64)]),
+            })
+            .collect()
+    }
+
+    fn noised(bounds: (f64, f64)) -> AggregationOptions {
+        AggregationOptions {
+            min_sample_count: 5,
+            noise_epsilon: Some(1.0),
+            value_bounds: HashMap::from([("temperature".to_string(), bounds)]),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_bounds_instead_of_panicking() {
+        for bounds in [
+            (4.0, 1.0),
+            (1.0, 1.0),
+            (f64::NAN, 1.0),
+            (0.0, f64::INFINITY),
+        ] {
+            let result = aggregate_measurements(&measurements(), &noised(bounds));
+            assert!(
+                matches!(result, Err(PoleshiftError::DataError(_))),
+                "{:?}",
+                bounds
+            );
+        }
+    }
+
+    #[test]
+    fn noises_the_clamped_mean_within_valid_bounds() {
+        let export = aggregate_measurements(&measurements(), &noised((0.0, 4.0))).unwrap();
+        assert!(export.groups[0].variables[0].mean.is_finite());
+    }
+
+    #[test]
+    fn counts_a_sample_measured_twice_once() {
+        let mut repeated = measurements();
+        repeated.truncate(4);
+        repeated.push(SampleMeasurement {
+            sample_id: "s0".to_string(),
+            group: "station".to_string(),
+            values: HashMap::from([("temperature".to_string(), 2.0)]),
+        });
+        let options = AggregationOptions {
+            min_sample_count: 4,
+            noise_epsilon: None,
+            value_bounds: HashMap::new(),
+        };
+        let export = aggregate_measurements(&repeated, &options).unwrap();
+        let variable = &export.groups[0].variables[0];
+        assert_eq!(export.groups[0].sample_count, 4);
+        assert_eq!(variable.sample_count, Some(4));
+        // s0 is (0 + 2) / 2 = 1, so the mean is (1 + 1 + 2 + 3) / 4
+        assert_eq!(variable.mean, 1.75);
+    }
+
+    #[test]
+    fn laplace_noise_is_finite_at_the_interval_edge() {
+        // The first draw is exactly -0.5, which must be rejected rather than used
+        let mut rng = StepRng::new(0, 1 << 40);
+        assert!(laplace_noise(&mut rng, 1.0).is_finite());
+    }
+}
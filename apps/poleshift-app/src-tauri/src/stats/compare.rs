@@ -0,0 +1,223 @@
+//poleshift/src-tauri/src/stats/compare.rs
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::stats::sample_reports::{
+    load_sample_reports, SampleOverview, SampleReport, TaxonAbundance,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ComparisonOptions {
+    /// Reads a taxon needs in a sample to count as present there
+    #[serde(default = "default_min_reads")]
+    pub min_reads: u64,
+    /// Reads added to every count before taking fold changes, so absent taxa stay finite
+    #[serde(default = "default_pseudocount")]
+    pub pseudocount: f64,
+}
+
+fn default_min_reads() -> u64 {
+    1
+}
+
+fn default_pseudocount() -> f64 {
+    1.0
+}
+
+impl Default for ComparisonOptions {
+    fn default() -> Self {
+        ComparisonOptions {
+            min_reads: default_min_reads(),
+            pseudocount: default_pseudocount(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+    /// Present in every sample
+    Shared,
+    /// Present in exactly one sample
+    Unique,
+    /// Present in some, but not all or just one, of the samples
+    Partial,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaxonComparison {
+    pub tax_id: u64,
+    pub tax_name: String,
+    pub rank: String,
+    pub lineage: String,
+    /// Clade reads per sample, in sample order
+    pub reads: Vec<u64>,
+    /// Clade reads as a fraction of each sample's total reads
+    pub relative_abundance: Vec<f64>,
+    pub present: Vec<bool>,
+    pub presence: Presence,
+    /// log2 fold change of the relative abundance against the first sample
+    pub log2_fold_change: Vec<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RankComparison {
+    pub rank: String,
+    /// Taxa present per sample, in sample order
+    pub taxa_present: Vec<usize>,
+    pub shared: usize,
+    /// Taxa present only in that sample, in sample order
+    pub unique: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClassificationComparison {
+    /// The first sample is the baseline for fold changes
+    pub samples: Vec<SampleOverview>,
+    pub taxa: Vec<TaxonComparison>,
+    pub ranks: Vec<RankComparison>,
+}
+
+pub fn compare_samples(
+    samples: &[SampleReport],
+    options: &ComparisonOptions,
+) -> Result<ClassificationComparison, PoleshiftError> {
+    if samples.len() < 2 {
+        return Err(PoleshiftError::DataError(
+            "At least two samples are needed for a comparison".to_string(),
+        ));
+    }
+    if !(options.pseudocount > 0.0 && options.pseudocount.is_finite()) {
+        return Err(PoleshiftError::DataError(
+            "pseudocount must be a positive number".to_string(),
+        ));
+    }
+
+    // 1) Union of taxa; the first sample that has a taxon supplies its name and lineage
+    let mut union: BTreeMap<u64, &TaxonAbundance> = BTreeMap::new();
+    let per_sample: Vec<HashMap<u64, u64>> = samples
+        .iter()
+        .map(|sample| {
+            sample
+                .taxa
+                .iter()
+                .map(|taxon| {
+                    union.entry(taxon.tax_id).or_insert(taxon);
+                    (taxon.tax_id, taxon.reads)
+                })
+                .collect()
+        })
+        .collect();
+
+    // 2) Per-taxon abundances, presence and fold changes
+    let taxa: Vec<TaxonComparison> = union
+        .into_values()
+        .map(|taxon| {
+            let reads: Vec<u64> = per_sample
+                .iter()
+                .map(|counts| counts.get(&taxon.tax_id).copied().unwrap_or(0))
+                .collect();
+            let relative_abundance: Vec<f64> = reads
+                .iter()
+                .zip(samples)
+                .map(|(&r, s)| relative(r as f64, s.total_reads))
+                .collect();
+            let smoothed: Vec<f64> = reads
+                .iter()
+                .zip(samples)
+                .map(|(&r, s)| {
+                    (r as f64 + options.pseudocount) / (s.total_reads as f64 + options.pseudocount)
+                })
+                .collect();
+            let present: Vec<bool> = reads.iter().map(|&r| r >= options.min_reads).collect();
+            let present_count = present.iter().filter(|&&p| p).count();
+            let presence = if present_count == samples.len() {
+                Presence::Shared
+            } else if present_count == 1 {
+                Presence::Unique
+            } else {
+                Presence::Partial
+            };
+            TaxonComparison {
+                tax_id: taxon.tax_id,
+                tax_name: taxon.tax_name.clone(),
+                rank: taxon.rank.clone(),
+                lineage: taxon.lineage.clone(),
+                log2_fold_change: smoothed.iter().map(|v| (v / smoothed[0]).log2()).collect(),
+                reads,
+                relative_abundance,
+                present,
+                presence,
+            }
+        })
+        // Taxa absent everywhere (e.g. emptied by filtering) carry no information
+        .filter(|taxon| taxon.present.iter().any(|&p| p))
+        .collect();
+
+    // 3) Rank-level presence summaries
+    let mut by_rank: BTreeMap<&str, Vec<&TaxonComparison>> = BTreeMap::new();
+    for taxon in &taxa {
+        by_rank.entry(taxon.rank.as_str()).or_default().push(taxon);
+    }
+    let ranks = by_rank
+        .into_iter()
+        .map(|(rank, members)| RankComparison {
+            rank: rank.to_string(),
+            taxa_present: (0..samples.len())
+                .map(|i| members.iter().filter(|t| t.present[i]).count())
+                .collect(),
+            shared: members
+                .iter()
+                .filter(|t| t.presence == Presence::Shared)
+                .count(),
+            unique: (0..samples.len())
+                .map(|i| {
+                    members
+                        .iter()
+                        .filter(|t| t.presence == Presence::Unique && t.present[i])
+                        .count()
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(ClassificationComparison {
+        samples: samples.iter().map(SampleOverview::from).collect(),
+        taxa,
+        ranks,
+    })
+}
+
+fn relative(reads: f64, total: u64) -> f64 {
+    if total > 0 {
+        reads / total as f64
+    } else {
+        0.0
+    }
+}
+
+/// Compares the stored reports of two or more classifications taxon by taxon, e.g. a
+/// station's surface and depth samples. Fold changes are relative to the first ID.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn compare_classifications<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_ids: Vec<String>,
+    options: Option<ComparisonOptions>,
+) -> Result<StandardResponseNoFiles<ClassificationComparison>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let samples = load_sample_reports(&app_handle, &processed_data_ids)?;
+        let report = compare_samples(&samples, &options.unwrap_or_default())?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
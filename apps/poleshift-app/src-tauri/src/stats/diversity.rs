@@ -0,0 +1,206 @@
+//poleshift/src-tauri/src/stats/diversity.rs
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::stats::abundance_matrix::{build_matrix, AbundanceMatrix, MatrixOptions};
+use crate::stats::sample_reports::{load_sample_reports, SampleOverview};
+
+#[derive(Debug, Deserialize)]
+pub struct DiversityOptions {
+    /// Rank the taxa are counted at; clade counts of different ranks overlap
+    #[serde(default = "default_rank")]
+    pub rank: String,
+}
+
+fn default_rank() -> String {
+    "species".to_string()
+}
+
+impl Default for DiversityOptions {
+    fn default() -> Self {
+        DiversityOptions {
+            rank: default_rank(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlphaDiversity {
+    pub sample: SampleOverview,
+    /// Reads classified at the chosen rank
+    pub reads: u64,
+    pub observed_richness: usize,
+    /// Shannon index H' (natural log)
+    pub shannon: f64,
+    /// Gini-Simpson index, 1 - sum(p^2)
+    pub simpson: f64,
+    /// Bias-corrected Chao1 richness estimate
+    pub chao1: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BetaDiversity {
+    pub rank: String,
+    pub samples: Vec<SampleOverview>,
+    /// Bray-Curtis dissimilarity of the relative abundances at the chosen rank
+    pub bray_curtis: Vec<Vec<f64>>,
+    /// Jaccard distance of the taxa present at the chosen rank
+    pub jaccard: Vec<Vec<f64>>,
+}
+
+/// Counts per sample (columns of the matrix), unclassified reads left out.
+fn sample_counts(matrix: &AbundanceMatrix) -> Vec<Vec<u64>> {
+    (0..matrix.samples.len())
+        .map(|s| matrix.reads.iter().map(|row| row[s]).collect())
+        .collect()
+}
+
+fn alpha_diversity(sample: SampleOverview, counts: &[u64]) -> AlphaDiversity {
+    let total: u64 = counts.iter().sum();
+    let observed = counts.iter().filter(|&&c| c > 0).count();
+    if total == 0 {
+        return AlphaDiversity {
+            sample,
+            reads: 0,
+            observed_richness: 0,
+            shannon: 0.0,
+            simpson: 0.0,
+            chao1: 0.0,
+        };
+    }
+
+    let (mut shannon, mut sum_p2) = (0.0, 0.0);
+    for &c in counts.iter().filter(|&&c| c > 0) {
+        let p = c as f64 / total as f64;
+        shannon -= p * p.ln();
+        sum_p2 += p * p;
+    }
+
+    let singletons = counts.iter().filter(|&&c| c == 1).count() as f64;
+    let doubletons = counts.iter().filter(|&&c| c == 2).count() as f64;
+    let chao1 = observed as f64 + singletons * (singletons - 1.0) / (2.0 * (doubletons + 1.0));
+
+    AlphaDiversity {
+        sample,
+        reads: total,
+        observed_richness: observed,
+        shannon,
+        simpson: 1.0 - sum_p2,
+        chao1,
+    }
+}
+
+fn bray_curtis(a: &[u64], b: &[u64]) -> f64 {
+    let (total_a, total_b) = (a.iter().sum::<u64>(), b.iter().sum::<u64>());
+    if total_a == 0 || total_b == 0 {
+        return if total_a == total_b { 0.0 } else { 1.0 };
+    }
+    let (mut shared, mut sum) = (0.0, 0.0);
+    for (&x, &y) in a.iter().zip(b) {
+        let (x, y) = (x as f64 / total_a as f64, y as f64 / total_b as f64);
+        shared += x.min(y);
+        sum += x + y;
+    }
+    1.0 - 2.0 * shared / sum
+}
+
+fn jaccard(a: &[u64], b: &[u64]) -> f64 {
+    let (mut both, mut either) = (0usize, 0usize);
+    for (&x, &y) in a.iter().zip(b) {
+        if x > 0 && y > 0 {
+            both += 1;
+        }
+        if x > 0 || y > 0 {
+            either += 1;
+        }
+    }
+    if either == 0 {
+        0.0
+    } else {
+        1.0 - both as f64 / either as f64
+    }
+}
+
+fn distance_matrix(counts: &[Vec<u64>], distance: fn(&[u64], &[u64]) -> f64) -> Vec<Vec<f64>> {
+    counts
+        .iter()
+        .map(|a| counts.iter().map(|b| distance(a, b)).collect())
+        .collect()
+}
+
+fn rank_matrix<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    processed_data_ids: &[String],
+    options: &DiversityOptions,
+) -> Result<AbundanceMatrix, PoleshiftError> {
+    if processed_data_ids.is_empty() {
+        return Err(PoleshiftError::DataError(
+            "No samples given for diversity metrics".to_string(),
+        ));
+    }
+    let samples = load_sample_reports(app_handle, processed_data_ids)?;
+    Ok(build_matrix(
+        &samples,
+        &MatrixOptions {
+            rank: Some(options.rank.clone()),
+            ..MatrixOptions::default()
+        },
+    ))
+}
+
+/// Observed richness, Shannon, Gini-Simpson and Chao1 for each stored classification,
+/// counting reads at `options.rank` (species by default).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn compute_alpha_diversity<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_ids: Vec<String>,
+    options: Option<DiversityOptions>,
+) -> Result<StandardResponseNoFiles<Vec<AlphaDiversity>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let options = options.unwrap_or_default();
+        let matrix = rank_matrix(&app_handle, &processed_data_ids, &options)?;
+
+        let report = sample_counts(&matrix)
+            .iter()
+            .zip(matrix.samples)
+            .map(|(counts, sample)| alpha_diversity(sample, counts))
+            .collect();
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
+
+/// Bray-Curtis and Jaccard distance matrices across the stored classifications, in
+/// the order of `processed_data_ids`, counting reads at `options.rank`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn compute_beta_diversity<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_ids: Vec<String>,
+    options: Option<DiversityOptions>,
+) -> Result<StandardResponseNoFiles<BetaDiversity>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let options = options.unwrap_or_default();
+        let matrix = rank_matrix(&app_handle, &processed_data_ids, &options)?;
+        let counts = sample_counts(&matrix);
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: BetaDiversity {
+                rank: options.rank,
+                samples: matrix.samples,
+                bray_curtis: distance_matrix(&counts, bray_curtis),
+                jaccard: distance_matrix(&counts, jaccard),
+            },
+        })
+    })
+    .await
+}
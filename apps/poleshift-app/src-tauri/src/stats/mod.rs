@@ -0,0 +1,13 @@
+//poleshift/src-tauri/src/stats/mod.rs
+
+pub mod abundance_matrix;
+pub mod aggregate_export;
+pub mod compare;
+pub mod diversity;
+pub mod rarefaction;
+pub mod region;
+mod sample_reports;
+pub mod sidebar;
+pub mod sidebar_store;
+pub mod summary;
+pub mod trends;
@@ -0,0 +1,209 @@
+//poleshift/src-tauri/src/stats/rarefaction.rs
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{load_report, open_store, output_store_path};
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Most depths a single curve may be evaluated at.
+const MAX_STEPS: usize = 1_000;
+/// Most subsampling repeats per sample.
+const MAX_REPEATS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RarefactionOptions {
+    /// Number of evenly spaced depths up to each sample's read count
+    pub steps: usize,
+    /// Subsamples drawn per depth; the curve reports their mean and spread
+    pub repeats: usize,
+    /// Seed for the subsampling, so curves are reproducible
+    pub seed: u64,
+    /// Count distinct taxa at this rank (e.g. "genus") instead of distinct assignments
+    pub rank: Option<String>,
+}
+
+impl Default for RarefactionOptions {
+    fn default() -> Self {
+        RarefactionOptions {
+            steps: 20,
+            repeats: 10,
+            seed: 42,
+            rank: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RarefactionPoint {
+    pub depth: usize,
+    pub mean_taxa: f64,
+    pub min_taxa: usize,
+    pub max_taxa: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RarefactionCurve {
+    pub processed_data_id: String,
+    /// Reads in the stored per-read output, i.e. the largest depth
+    pub reads: usize,
+    pub points: Vec<RarefactionPoint>,
+}
+
+/// Maps each tax ID of the report to the tax ID of its ancestor at `rank`. Taxa above
+/// that rank have no entry.
+fn ancestors_at_rank(report: &[ProcessedKrakenUniqReport], rank: &str) -> HashMap<u64, u64> {
+    let by_id: HashMap<&str, &ProcessedKrakenUniqReport> =
+        report.iter().map(|row| (row.id.as_str(), row)).collect();
+    let mut ancestors = HashMap::new();
+    for row in report {
+        let mut current = Some(row);
+        let mut steps = 0;
+        while let Some(r) = current {
+            if r.rank.eq_ignore_ascii_case(rank) {
+                ancestors.insert(row.tax_id, r.tax_id);
+                break;
+            }
+            steps += 1;
+            current = r
+                .parent_id
+                .and_then(|p| by_id.get(p.to_string().as_str()).copied())
+                // Guard against cycles in malformed reports
+                .filter(|_| steps <= report.len());
+        }
+    }
+    ancestors
+}
+
+/// Taxon of every read in the stored per-read output; `None` for reads that do not
+/// count towards richness (unclassified, or above the chosen rank).
+fn read_taxa(store: &Path, rank: Option<&str>) -> Result<Vec<Option<u64>>, PoleshiftError> {
+    let ancestors = match rank {
+        Some(rank) => Some(ancestors_at_rank(&load_report(store)?, rank)),
+        None => None,
+    };
+
+    let conn = open_store(store)?;
+    let mut stmt = conn
+        .prepare("SELECT tax_id FROM stdout ORDER BY rowid")
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let tax_ids = stmt
+        .query_map([], |row| row.get::<_, i64>(0))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+        .collect::<Result<Vec<i64>, _>>()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+
+    Ok(tax_ids
+        .into_iter()
+        .map(|tax_id| {
+            if tax_id <= 0 {
+                return None;
+            }
+            match &ancestors {
+                Some(ancestors) => ancestors.get(&(tax_id as u64)).copied(),
+                None => Some(tax_id as u64),
+            }
+        })
+        .collect())
+}
+
+/// Subsamples `reads` without replacement at each depth and counts the distinct taxa.
+/// Each repeat shuffles once and reads the depths off growing prefixes, which is the
+/// same as drawing every depth independently from that repeat's permutation.
+fn rarefy(reads: &[Option<u64>], options: &RarefactionOptions, seed: u64) -> Vec<RarefactionPoint> {
+    let steps = options.steps.max(1);
+    let depths: Vec<usize> = (1..=steps)
+        .map(|i| reads.len() * i / steps)
+        .filter(|&d| d > 0)
+        .collect();
+
+    let mut per_depth: Vec<Vec<usize>> = vec![Vec::new(); depths.len()];
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut order: Vec<usize> = (0..reads.len()).collect();
+    for _ in 0..options.repeats.max(1) {
+        order.shuffle(&mut rng);
+        let mut seen: HashSet<u64> = HashSet::new();
+        let mut drawn = 0;
+        for (d, &depth) in depths.iter().enumerate() {
+            for &i in &order[drawn..depth] {
+                if let Some(taxon) = reads[i] {
+                    seen.insert(taxon);
+                }
+            }
+            drawn = depth;
+            per_depth[d].push(seen.len());
+        }
+    }
+
+    depths
+        .into_iter()
+        .zip(per_depth)
+        .map(|(depth, counts)| RarefactionPoint {
+            depth,
+            mean_taxa: counts.iter().sum::<usize>() as f64 / counts.len() as f64,
+            min_taxa: counts.iter().copied().min().unwrap_or(0),
+            max_taxa: counts.iter().copied().max().unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Taxon-accumulation curves for the stored classifications, from repeated seeded
+/// subsampling of their per-read output. A curve that is still climbing at full depth
+/// means more sequencing would have found more taxa. When only classified reads were
+/// stored, depths count classified reads only.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn compute_rarefaction<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_ids: Vec<String>,
+    options: Option<RarefactionOptions>,
+) -> Result<StandardResponseNoFiles<Vec<RarefactionCurve>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let options = options.unwrap_or_default();
+        if options.steps > MAX_STEPS || options.repeats > MAX_REPEATS {
+            return Err(PoleshiftError::DataError(format!(
+                "steps must be at most {} and repeats at most {}",
+                MAX_STEPS, MAX_REPEATS
+            )));
+        }
+
+        let stores = processed_data_ids
+            .iter()
+            .map(|id| Ok((id.clone(), output_store_path(&app_handle, id)?)))
+            .collect::<Result<Vec<_>, PoleshiftError>>()?;
+
+        let report = tauri::async_runtime::spawn_blocking(move || {
+            stores
+                .into_iter()
+                .enumerate()
+                .map(|(index, (processed_data_id, store))| {
+                    let reads = read_taxa(&store, options.rank.as_deref())?;
+                    // Each sample draws from its own stream
+                    let points = rarefy(&reads, &options, options.seed.wrapping_add(index as u64));
+                    Ok(RarefactionCurve {
+                        processed_data_id,
+                        reads: reads.len(),
+                        points,
+                    })
+                })
+                .collect::<Result<Vec<_>, PoleshiftError>>()
+        })
+        .await
+        .map_err(|e| PoleshiftError::Other(format!("Rarefaction task failed: {}", e)))??;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
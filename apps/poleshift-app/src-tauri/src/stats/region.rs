@@ -0,0 +1,266 @@
+//poleshift/src-tauri/src/stats/region.rs
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::{civil_from_epoch_secs, epoch_secs};
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::stats::sidebar::{sidebar_stats, ProcessedStats, SidebarOptions, SidebarSample};
+
+/// A sample with where and when it was collected.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RegionSample {
+    #[serde(flatten)]
+    pub sample: SidebarSample,
+    pub location_id: Option<String>,
+    /// Collection position, in decimal degrees
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Collection time, in ms since the Unix epoch
+    pub tstamp: Option<i64>,
+}
+
+/// An area of the map, in decimal degrees. A `min_longitude` east of `max_longitude`
+/// is a box across the antimeridian.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub max_latitude: f64,
+    pub min_longitude: f64,
+    pub max_longitude: f64,
+}
+
+impl BoundingBox {
+    fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        let in_longitude = if self.min_longitude <= self.max_longitude {
+            (self.min_longitude..=self.max_longitude).contains(&longitude)
+        } else {
+            longitude >= self.min_longitude || longitude <= self.max_longitude
+        };
+        (self.min_latitude..=self.max_latitude).contains(&latitude) && in_longitude
+    }
+}
+
+/// What the selected samples are split into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionGrouping {
+    /// The whole selection as one region
+    #[default]
+    Selection,
+    /// A region per location
+    Location,
+}
+
+/// Length of the periods the selected samples are split into, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeBucket {
+    /// The whole date range as one period
+    #[default]
+    All,
+    Day,
+    /// Weeks starting on Monday
+    Week,
+    Month,
+    Year,
+}
+
+impl TimeBucket {
+    /// Start of the period holding `tstamp`, in ms since the Unix epoch, and its label.
+    fn period(self, tstamp: i64) -> Option<(i64, String)> {
+        let (year, month, day, _) = civil_from_epoch_secs(tstamp.div_euclid(1000));
+        let (year, month, day) = match self {
+            TimeBucket::All => return None,
+            TimeBucket::Day | TimeBucket::Week => (year, month, day),
+            TimeBucket::Month => (year, month, 1),
+            TimeBucket::Year => (year, 1, 1),
+        };
+        let mut start = epoch_secs(year, month, day, 0);
+        if self == TimeBucket::Week {
+            // The epoch was a Thursday
+            start -= (start.div_euclid(86_400) + 3).rem_euclid(7) * 86_400;
+        }
+        let (year, month, day, _) = civil_from_epoch_secs(start);
+        let label = match self {
+            TimeBucket::Month => format!("{:04}-{:02}", year, month),
+            TimeBucket::Year => format!("{:04}", year),
+            _ => format!("{:04}-{:02}-{:02}", year, month, day),
+        };
+        Some((start * 1000, label))
+    }
+}
+
+/// Which samples the map selection covers, how they are split, and the statistics
+/// computed for each part.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RegionStatsRequest {
+    pub samples: Vec<RegionSample>,
+    /// Samples collected inside this box are selected
+    pub bounding_box: Option<BoundingBox>,
+    /// Samples at these locations are selected, as well as those in `bounding_box`
+    pub location_ids: Vec<String>,
+    /// Samples collected from `start_tstamp` up to `end_tstamp` are selected, in ms
+    /// since the Unix epoch
+    pub start_tstamp: Option<i64>,
+    pub end_tstamp: Option<i64>,
+    pub grouping: RegionGrouping,
+    pub time_bucket: TimeBucket,
+    #[serde(flatten)]
+    pub options: SidebarOptions,
+}
+
+impl RegionStatsRequest {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if let Some(area) = &self.bounding_box {
+            let latitudes = [area.min_latitude, area.max_latitude];
+            let longitudes = [area.min_longitude, area.max_longitude];
+            if !latitudes.iter().all(|lat| (-90.0..=90.0).contains(lat))
+                || !longitudes.iter().all(|lon| (-180.0..=180.0).contains(lon))
+            {
+                return Err(PoleshiftError::InvalidInput {
+                    field: "bounding_box".to_string(),
+                    reason: "latitudes must be within ±90° and longitudes ±180°".to_string(),
+                });
+            }
+            if area.min_latitude > area.max_latitude {
+                return Err(PoleshiftError::InvalidInput {
+                    field: "bounding_box".to_string(),
+                    reason: "min_latitude must not be above max_latitude".to_string(),
+                });
+            }
+        }
+        if let (Some(start), Some(end)) = (self.start_tstamp, self.end_tstamp) {
+            if start > end {
+                return Err(PoleshiftError::InvalidInput {
+                    field: "start_tstamp".to_string(),
+                    reason: "must not be after end_tstamp".to_string(),
+                });
+            }
+        }
+        self.options.validate()
+    }
+
+    /// Whether the selection takes in `sample`.
+    fn selects(&self, sample: &RegionSample) -> bool {
+        let by_location = sample
+            .location_id
+            .as_ref()
+            .is_some_and(|id| self.location_ids.contains(id));
+        let by_area = self.bounding_box.is_some_and(|area| {
+            matches!(
+                (sample.latitude, sample.longitude),
+                (Some(lat), Some(lon)) if area.contains(lat, lon)
+            )
+        });
+        let no_area = self.bounding_box.is_none() && self.location_ids.is_empty();
+        let in_range = match sample.tstamp {
+            Some(tstamp) => {
+                self.start_tstamp.is_none_or(|start| tstamp >= start)
+                    && self.end_tstamp.is_none_or(|end| tstamp <= end)
+            }
+            None => {
+                self.start_tstamp.is_none()
+                    && self.end_tstamp.is_none()
+                    && self.time_bucket == TimeBucket::All
+            }
+        };
+        (no_area || by_location || by_area) && in_range
+    }
+}
+
+/// The statistics of one region over one period.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionStats {
+    /// The location, with `RegionGrouping::Location`; samples without one are grouped
+    /// under none
+    pub location_id: Option<String>,
+    /// Start of the period, in ms since the Unix epoch, and its date; none with
+    /// `TimeBucket::All`
+    pub period_start: Option<i64>,
+    pub period: Option<String>,
+    pub samples: usize,
+    pub stats: ProcessedStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionStatsReport {
+    /// By location, then period
+    pub regions: Vec<RegionStats>,
+    /// Samples inside the selection
+    pub selected: usize,
+    /// Samples outside it, or without the position or time to tell
+    pub excluded: usize,
+}
+
+/// Selects the samples inside the request's area and date range, splits them by
+/// location and period as asked, and computes the sidebar statistics of each part
+/// (see `sidebar_stats`).
+pub fn region_stats(request: &RegionStatsRequest) -> RegionStatsReport {
+    // 1) The selection
+    let selected: Vec<&RegionSample> = request
+        .samples
+        .iter()
+        .filter(|sample| request.selects(sample))
+        .collect();
+
+    // 2) Regions and periods
+    type Key = (Option<String>, Option<(i64, String)>);
+    let mut groups: BTreeMap<Key, Vec<&SidebarSample>> = BTreeMap::new();
+    for sample in &selected {
+        let location = match request.grouping {
+            RegionGrouping::Selection => None,
+            RegionGrouping::Location => sample.location_id.clone(),
+        };
+        let period = sample
+            .tstamp
+            .and_then(|tstamp| request.time_bucket.period(tstamp));
+        groups
+            .entry((location, period))
+            .or_default()
+            .push(&sample.sample);
+    }
+
+    // 3) Statistics of each
+    let regions = groups
+        .into_iter()
+        .map(|((location_id, period), samples)| {
+            let (period_start, period) = period.unzip();
+            RegionStats {
+                location_id,
+                period_start,
+                period,
+                samples: samples.len(),
+                stats: sidebar_stats(samples, &request.options),
+            }
+        })
+        .collect();
+
+    RegionStatsReport {
+        regions,
+        selected: selected.len(),
+        excluded: request.samples.len() - selected.len(),
+    }
+}
+
+/// Computes the sidebar statistics of the samples in an area of the map and a date
+/// range, per location and period (see `region_stats`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn process_region_stats(
+    invocation: Invocation,
+    request: RegionStatsRequest,
+) -> Result<StandardResponseNoFiles<RegionStatsReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        request.validate()?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: region_stats(&request),
+        })
+    })
+    .await
+}
@@ -0,0 +1,131 @@
+//poleshift/src-tauri/src/stats/sample_reports.rs
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tauri::{AppHandle, Runtime};
+
+use crate::krakenuniq::output_store::{load_report, output_store_path};
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// NCBI tax ID of "root"; left out of lineage strings as every taxon shares it.
+const ROOT_TAX_ID: u64 = 1;
+
+/// One taxon of a sample's report, flattened for cross-sample statistics.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaxonAbundance {
+    pub tax_id: u64,
+    pub tax_name: String,
+    pub rank: String,
+    /// Names from the top of the taxonomy down to this taxon, joined with ';'
+    pub lineage: String,
+    /// Reads assigned to this taxon or anything below it
+    pub reads: u64,
+    /// Reads assigned to exactly this taxon
+    pub tax_reads: u64,
+}
+
+/// A stored classification report reduced to what the statistics need.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleReport {
+    pub processed_data_id: String,
+    pub sample_id: String,
+    /// All reads of the sample, classified or not
+    pub total_reads: u64,
+    pub taxa: Vec<TaxonAbundance>,
+}
+
+/// Identifies a sample in multi-sample results, without its taxa.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleOverview {
+    pub processed_data_id: String,
+    pub sample_id: String,
+    pub total_reads: u64,
+}
+
+impl From<&SampleReport> for SampleOverview {
+    fn from(sample: &SampleReport) -> Self {
+        SampleOverview {
+            processed_data_id: sample.processed_data_id.clone(),
+            sample_id: sample.sample_id.clone(),
+            total_reads: sample.total_reads,
+        }
+    }
+}
+
+/// Flattens report rows. Lineages resolved from the taxDB are used as they are; older
+/// reports without them get one derived from the parent links.
+pub(crate) fn sample_report_from_rows(
+    processed_data_id: &str,
+    rows: &[ProcessedKrakenUniqReport],
+) -> SampleReport {
+    let by_id: HashMap<&str, &ProcessedKrakenUniqReport> =
+        rows.iter().map(|row| (row.id.as_str(), row)).collect();
+    let parent = |row: &ProcessedKrakenUniqReport| {
+        row.parent_id
+            .and_then(|p| by_id.get(p.to_string().as_str()).copied())
+    };
+
+    let taxa = rows
+        .iter()
+        .map(|row| {
+            if let Some(lineage) = &row.lineage {
+                return TaxonAbundance {
+                    tax_id: row.tax_id,
+                    tax_name: row.tax_name.trim().to_string(),
+                    rank: row.rank.clone(),
+                    lineage: lineage.clone(),
+                    reads: row.reads,
+                    tax_reads: row.tax_reads,
+                };
+            }
+            let mut names = Vec::new();
+            let mut current = Some(row);
+            while let Some(r) = current {
+                if r.tax_id != ROOT_TAX_ID {
+                    names.push(r.tax_name.trim());
+                }
+                // Guard against cycles in malformed reports
+                current = parent(r).filter(|_| names.len() <= rows.len());
+            }
+            names.reverse();
+            TaxonAbundance {
+                tax_id: row.tax_id,
+                tax_name: row.tax_name.trim().to_string(),
+                rank: row.rank.clone(),
+                lineage: names.join(";"),
+                reads: row.reads,
+                tax_reads: row.tax_reads,
+            }
+        })
+        .collect();
+
+    SampleReport {
+        processed_data_id: processed_data_id.to_string(),
+        sample_id: rows
+            .first()
+            .map(|row| row.sample_id.clone())
+            .unwrap_or_default(),
+        total_reads: rows
+            .iter()
+            .filter(|row| parent(row).is_none())
+            .map(|row| row.reads)
+            .sum(),
+        taxa,
+    }
+}
+
+/// Loads the stored report of every `processed_data_ids` entry, in order.
+pub(crate) fn load_sample_reports<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    processed_data_ids: &[String],
+) -> Result<Vec<SampleReport>, PoleshiftError> {
+    processed_data_ids
+        .iter()
+        .map(|id| {
+            let rows = load_report(&output_store_path(app_handle, id)?)?;
+            Ok(sample_report_from_rows(id, &rows))
+        })
+        .collect()
+}
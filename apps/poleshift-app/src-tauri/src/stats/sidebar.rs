@@ -0,0 +1,593 @@
+//poleshift/src-tauri/src/stats/sidebar.rs
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::ctd::teos10::sigma0_from_sp;
+use crate::devtools::{traced, Invocation};
+use crate::nutrients::handle_nutrient_data::{nutrient_report, NutrientEntry};
+use crate::nutrients::Nutrient;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// How the surface values of a location are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    #[default]
+    Mean,
+    Median,
+}
+
+/// Column names of the CTD rows, matched case-insensitively.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SidebarChannels {
+    pub depth: String,
+    pub temperature: String,
+    pub salinity: String,
+    pub chlorophyll: String,
+    /// Potential density anomaly, in kg/m³; computed from temperature, salinity and
+    /// depth for rows without it
+    pub density: String,
+}
+
+impl Default for SidebarChannels {
+    fn default() -> Self {
+        SidebarChannels {
+            depth: "depth".to_string(),
+            temperature: "temperature".to_string(),
+            salinity: "salinity".to_string(),
+            chlorophyll: "chlorophyll_a".to_string(),
+            density: "sigma0".to_string(),
+        }
+    }
+}
+
+/// A taxon of a sample's classification report.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SidebarTaxon {
+    pub tax_name: String,
+    pub rank: String,
+    /// Share of the sample's reads, in percent
+    pub percentage: f64,
+    /// Reads assigned to the taxon and its descendants
+    #[serde(default)]
+    pub reads: Option<u64>,
+}
+
+/// The data of one sample at the selected location.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SidebarSample {
+    pub sample_id: String,
+    /// Processed CTD rows, as objects of column name to value
+    pub ctd_rows: Vec<Map<String, Value>>,
+    /// The sample's stored CTD data, read when `ctd_rows` is empty: a CTD report
+    /// (see `report_rows`) or the legacy `channels` plus `channelNN` layout
+    pub ctd_report: Option<Value>,
+    /// Ammonium measurements, each taken as a sample value of its own
+    pub ammonium: Vec<f64>,
+    /// Nutrient measurements with their replicates (see `handle_nutrient_data`)
+    pub nutrients: Vec<NutrientEntry>,
+    pub taxa: Vec<SidebarTaxon>,
+    /// Reads sequenced for the sample; the most reads of any of its taxa (the root's,
+    /// in a full report) when not given
+    pub total_reads: Option<u64>,
+}
+
+/// How the samples are summarized.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SidebarOptions {
+    /// CTD rows at or above this depth are surface rows, in m
+    pub surface_depth: f64,
+    pub aggregation: Aggregation,
+    pub channels: SidebarChannels,
+    /// Taxa above this share of a sample's reads are counted, in percent
+    pub abundance_threshold: f64,
+}
+
+impl Default for SidebarOptions {
+    fn default() -> Self {
+        SidebarOptions {
+            surface_depth: 2.0,
+            aggregation: Aggregation::Mean,
+            channels: SidebarChannels::default(),
+            abundance_threshold: 25.0,
+        }
+    }
+}
+
+impl SidebarOptions {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if !(self.surface_depth.is_finite() && self.surface_depth >= 0.0) {
+            return Err(PoleshiftError::InvalidInput {
+                field: "surface_depth".to_string(),
+                reason: "must be 0 or more".to_string(),
+            });
+        }
+        if !(0.0..=100.0).contains(&self.abundance_threshold) {
+            return Err(PoleshiftError::InvalidInput {
+                field: "abundance_threshold".to_string(),
+                reason: "must be between 0 and 100".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// What the location sidebar summarizes, and how.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProcessRequest {
+    pub samples: Vec<SidebarSample>,
+    #[serde(flatten)]
+    pub options: SidebarOptions,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValueStats {
+    pub average: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub count: usize,
+}
+
+/// A nutrient over the samples, from each sample's mean of its replicates, in µmol/L.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NutrientStats {
+    #[serde(flatten)]
+    pub values: ValueStats,
+    /// Standard deviation of `average` carried over from the SDs of the samples'
+    /// replicates; none when no sample has replicates
+    pub uncertainty: Option<f64>,
+    /// Mean coefficient of variation of the samples' replicates, in percent
+    pub mean_cv: Option<f64>,
+    /// Samples whose mean is below the detection limit
+    pub below_detection: usize,
+}
+
+/// Something about a sample that left it out of a statistic.
+#[derive(Debug, Clone, Serialize)]
+pub struct SidebarWarning {
+    pub sample_id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProcessedStats {
+    /// Surface temperature, salinity, chlorophyll and potential density anomaly,
+    /// combined by the request's aggregation
+    pub average_temperature: Option<f64>,
+    pub average_salinity: Option<f64>,
+    pub average_chlorophyll: Option<f64>,
+    pub average_density: Option<f64>,
+    pub ammonium_stats: ValueStats,
+    /// By nutrient, for samples with `nutrients`
+    pub nutrient_stats: BTreeMap<Nutrient, NutrientStats>,
+    /// Samples each species, genus, family and order is abundant in
+    pub species_data: BTreeMap<String, usize>,
+    pub genus_data: BTreeMap<String, usize>,
+    pub family_data: BTreeMap<String, usize>,
+    pub order_data: BTreeMap<String, usize>,
+    /// Reads of each sample with a classification report, by sample id
+    pub read_totals: BTreeMap<String, u64>,
+    pub warnings: Vec<SidebarWarning>,
+}
+
+/// The value of `column` in a row, matched case-insensitively; numbers stored as
+/// text are read too.
+fn number(row: &Map<String, Value>, column: &str) -> Option<f64> {
+    let value = row.get(column).or_else(|| {
+        row.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(column))
+            .map(|(_, value)| value)
+    })?;
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|value: &f64| value.is_finite())
+}
+
+fn aggregate(values: &mut [f64], aggregation: Aggregation) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    match aggregation {
+        Aggregation::Mean => Some(values.iter().sum::<f64>() / values.len() as f64),
+        Aggregation::Median => {
+            values.sort_by(f64::total_cmp);
+            let middle = values.len() / 2;
+            if values.len().is_multiple_of(2) {
+                Some((values[middle - 1] + values[middle]) / 2.0)
+            } else {
+                Some(values[middle])
+            }
+        }
+    }
+}
+
+/// The value of the first of `keys` present in an object.
+fn field<'a>(object: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().find_map(|key| object.get(*key))
+}
+
+/// Rows of the legacy layout: `channels` describing each channel by id and long name,
+/// and `data` rows holding the channel values under `channelNN`. Each row is keyed by
+/// the lowercased long names instead, keeping its other fields (e.g. `tstamp`).
+fn legacy_rows(report: &Map<String, Value>) -> Option<Vec<Map<String, Value>>> {
+    let channels = field(report, &["channels"])?.as_array()?;
+    let data = field(report, &["data"])?.as_array()?;
+    let mut names = BTreeMap::new();
+    for channel in channels {
+        let id = field(channel.as_object()?, &["channelID", "channel_id"])?;
+        let id = id
+            .as_u64()
+            .or_else(|| id.as_str().and_then(|id| id.trim().parse().ok()))?;
+        let name = field(channel.as_object()?, &["longName", "long_name"])?.as_str()?;
+        names.insert(format!("channel{:02}", id), name.trim().to_lowercase());
+    }
+    Some(
+        data.iter()
+            .filter_map(Value::as_object)
+            .map(|row| {
+                row.iter()
+                    .map(|(key, value)| {
+                        let key = names.get(&key.to_lowercase()).unwrap_or(key);
+                        (key.clone(), value.clone())
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Rows of a stored CTD report as `handle_ctd_data` emits it: its processed rows,
+/// leaving out those only kept with `keep_dropped_rows`, else its raw rows. Reports
+/// in the legacy channel layout are read with `legacy_rows`; none when `report` is
+/// neither.
+pub(crate) fn report_rows(report: &Value) -> Option<Vec<Map<String, Value>>> {
+    let report = report.as_object()?;
+    let rows = |keys: &[&str]| {
+        field(report, keys)
+            .and_then(Value::as_array)
+            .filter(|rows| !rows.is_empty())
+    };
+    if let Some(processed) = rows(&["processed_data", "processedData"]) {
+        return Some(
+            processed
+                .iter()
+                .filter_map(Value::as_object)
+                .filter(|row| row.get("dropped").is_none_or(Value::is_null))
+                .cloned()
+                .collect(),
+        );
+    }
+    if let Some(raw) = rows(&["raw_data", "rawData"]) {
+        return Some(raw.iter().filter_map(Value::as_object).cloned().collect());
+    }
+    if field(
+        report,
+        &["processed_data", "processedData", "raw_data", "rawData"],
+    )
+    .is_some()
+    {
+        return Some(Vec::new());
+    }
+    legacy_rows(report)
+}
+
+/// The CTD rows of a sample: its `ctd_rows`, else the rows of its `ctd_report`, with
+/// a warning when the report's layout is not one `report_rows` reads.
+fn sample_rows<'a>(
+    sample: &'a SidebarSample,
+    warnings: &mut Vec<SidebarWarning>,
+) -> Cow<'a, [Map<String, Value>]> {
+    if !sample.ctd_rows.is_empty() {
+        return Cow::Borrowed(&sample.ctd_rows);
+    }
+    match &sample.ctd_report {
+        None | Some(Value::Null) => Cow::Borrowed(&[]),
+        Some(report) => match report_rows(report) {
+            Some(rows) => Cow::Owned(rows),
+            None => {
+                warnings.push(SidebarWarning {
+                    sample_id: sample.sample_id.clone(),
+                    message: "its CTD data is neither a CTD report nor in the legacy \
+                              channel layout"
+                        .to_string(),
+                });
+                Cow::Borrowed(&[])
+            }
+        },
+    }
+}
+
+/// Collects the surface values of `column` from one sample's CTD rows, with a warning
+/// when the sample has rows but none holds the column.
+fn surface_values(
+    sample: &SidebarSample,
+    surface: &[&Map<String, Value>],
+    column: &str,
+    values: &mut Vec<f64>,
+    warnings: &mut Vec<SidebarWarning>,
+) {
+    let before = values.len();
+    values.extend(surface.iter().filter_map(|row| number(row, column)));
+    if values.len() == before && !surface.is_empty() {
+        warnings.push(SidebarWarning {
+            sample_id: sample.sample_id.clone(),
+            message: format!("no `{}` values in its surface CTD rows", column),
+        });
+    }
+}
+
+/// Potential density anomaly of a surface row: its density column, else computed from
+/// its temperature, salinity and depth, taking a metre as a decibar this near the
+/// surface.
+fn surface_density(row: &Map<String, Value>, channels: &SidebarChannels) -> Option<f64> {
+    number(row, &channels.density).or_else(|| {
+        sigma0_from_sp(
+            number(row, &channels.salinity)?,
+            number(row, &channels.temperature)?,
+            number(row, &channels.depth)?.max(0.0),
+        )
+    })
+}
+
+/// A sample's mean of a nutrient, with its replicates' SD and CV and whether it is
+/// below the detection limit.
+type SampleNutrient = (f64, Option<f64>, Option<f64>, bool);
+
+fn value_stats(values: &mut [f64]) -> ValueStats {
+    ValueStats {
+        min: values.iter().copied().reduce(f64::min),
+        max: values.iter().copied().reduce(f64::max),
+        count: values.len(),
+        average: aggregate(values, Aggregation::Mean),
+    }
+}
+
+/// Stats of a nutrient over samples. The average's uncertainty is that of a mean of
+/// independent values, √(Σ sdᵢ²) / n, counting samples without replicates as exact.
+fn nutrient_stats(samples: &[SampleNutrient]) -> NutrientStats {
+    let mut means: Vec<f64> = samples.iter().map(|(mean, ..)| *mean).collect();
+    let sds: Vec<f64> = samples.iter().filter_map(|(_, sd, ..)| *sd).collect();
+    let cvs: Vec<f64> = samples.iter().filter_map(|(_, _, cv, _)| *cv).collect();
+    NutrientStats {
+        values: value_stats(&mut means),
+        uncertainty: (!sds.is_empty())
+            .then(|| sds.iter().map(|sd| sd * sd).sum::<f64>().sqrt() / samples.len() as f64),
+        mean_cv: (!cvs.is_empty()).then(|| cvs.iter().sum::<f64>() / cvs.len() as f64),
+        below_detection: samples.iter().filter(|(.., below)| *below).count(),
+    }
+}
+
+/// Summarizes the samples of a location for the sidebar: surface temperature,
+/// salinity, chlorophyll and density, ammonium and the other nutrients, the species,
+/// genera, families and orders abundant in each sample, and each sample's reads.
+/// Samples missing a CTD column, or without rows in the surface window, are left out
+/// of that statistic with a warning rather than failing the request.
+pub fn sidebar_stats<'a>(
+    samples: impl IntoIterator<Item = &'a SidebarSample>,
+    options: &SidebarOptions,
+) -> ProcessedStats {
+    let channels = &options.channels;
+    let mut stats = ProcessedStats::default();
+    let mut temperatures = Vec::new();
+    let mut salinities = Vec::new();
+    let mut chlorophylls = Vec::new();
+    let mut densities = Vec::new();
+    let mut ammonium = Vec::new();
+    let mut nutrients: BTreeMap<Nutrient, Vec<SampleNutrient>> = BTreeMap::new();
+
+    for sample in samples {
+        // 1) Surface CTD rows
+        let rows = sample_rows(sample, &mut stats.warnings);
+        if !rows.is_empty() {
+            let with_depth: Vec<(&Map<String, Value>, f64)> = rows
+                .iter()
+                .filter_map(|row| Some((row, number(row, &channels.depth)?)))
+                .collect();
+            let surface: Vec<&Map<String, Value>> = with_depth
+                .iter()
+                .filter(|(_, depth)| *depth <= options.surface_depth)
+                .map(|(row, _)| *row)
+                .collect();
+            if with_depth.is_empty() {
+                stats.warnings.push(SidebarWarning {
+                    sample_id: sample.sample_id.clone(),
+                    message: format!("no `{}` values in its CTD rows", channels.depth),
+                });
+            } else if surface.is_empty() {
+                stats.warnings.push(SidebarWarning {
+                    sample_id: sample.sample_id.clone(),
+                    message: format!(
+                        "no CTD rows within {} m of the surface",
+                        options.surface_depth
+                    ),
+                });
+            }
+            for (column, values) in [
+                (&channels.temperature, &mut temperatures),
+                (&channels.salinity, &mut salinities),
+                (&channels.chlorophyll, &mut chlorophylls),
+            ] {
+                surface_values(sample, &surface, column, values, &mut stats.warnings);
+            }
+            let before = densities.len();
+            densities.extend(
+                surface
+                    .iter()
+                    .filter_map(|row| surface_density(row, channels)),
+            );
+            if densities.len() == before && !surface.is_empty() {
+                stats.warnings.push(SidebarWarning {
+                    sample_id: sample.sample_id.clone(),
+                    message: format!(
+                        "no `{}` values in its surface CTD rows, nor the temperature and \
+                         salinity to compute them",
+                        channels.density
+                    ),
+                });
+            }
+        }
+
+        // 2) Nutrients, one mean per sample and nutrient with its replicates' SD and CV
+        ammonium.extend(sample.ammonium.iter().filter(|value| value.is_finite()));
+        if !sample.nutrients.is_empty() {
+            match nutrient_report(&sample.sample_id, &sample.nutrients) {
+                Ok(report) => {
+                    // Ammonia stands in for ammonium where that was not measured
+                    if let Some(result) = [Nutrient::Ammonium, Nutrient::Ammonia]
+                        .iter()
+                        .find_map(|&n| report.results.iter().find(|r| r.nutrient == n))
+                    {
+                        ammonium.push(result.mean);
+                    }
+                    for result in report.results {
+                        nutrients.entry(result.nutrient).or_default().push((
+                            result.mean,
+                            result.sd,
+                            result.cv,
+                            result.below_detection,
+                        ));
+                    }
+                }
+                Err(e) => stats.warnings.push(SidebarWarning {
+                    sample_id: sample.sample_id.clone(),
+                    message: format!("nutrients left out: {}", e),
+                }),
+            }
+        }
+
+        // 3) Abundant taxa
+        for taxon in &sample.taxa {
+            if taxon.percentage <= options.abundance_threshold {
+                continue;
+            }
+            let counts = match taxon.rank.as_str() {
+                "species" => &mut stats.species_data,
+                "genus" => &mut stats.genus_data,
+                "family" => &mut stats.family_data,
+                "order" => &mut stats.order_data,
+                _ => continue,
+            };
+            *counts.entry(taxon.tax_name.clone()).or_default() += 1;
+        }
+
+        // 4) Reads
+        if let Some(reads) = sample
+            .total_reads
+            .or_else(|| sample.taxa.iter().filter_map(|taxon| taxon.reads).max())
+        {
+            stats.read_totals.insert(sample.sample_id.clone(), reads);
+        }
+    }
+
+    stats.average_temperature = aggregate(&mut temperatures, options.aggregation);
+    stats.average_salinity = aggregate(&mut salinities, options.aggregation);
+    stats.average_chlorophyll = aggregate(&mut chlorophylls, options.aggregation);
+    stats.average_density = aggregate(&mut densities, options.aggregation);
+    stats.ammonium_stats = value_stats(&mut ammonium);
+    stats.nutrient_stats = nutrients
+        .into_iter()
+        .map(|(nutrient, samples)| (nutrient, nutrient_stats(&samples)))
+        .collect();
+    stats
+}
+
+/// Computes the statistics of the location sidebar (see `sidebar_stats`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn process_sidebar_stats(
+    invocation: Invocation,
+    request: ProcessRequest,
+) -> Result<StandardResponseNoFiles<ProcessedStats>, PoleshiftError> {
+    traced(invocation, None, async move {
+        request.options.validate()?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: sidebar_stats(&request.samples, &request.options),
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample(ctd_report: Value) -> SidebarSample {
+        SidebarSample {
+            sample_id: "s1".to_string(),
+            ctd_report: Some(ctd_report),
+            ..SidebarSample::default()
+        }
+    }
+
+    #[test]
+    fn reads_processed_rows_of_a_ctd_report() {
+        // Reports also list their channels; the rows must win over the legacy reading
+        let report = json!({
+            "raw_data": [{ "depth": 1.0, "temperature": 99.0 }],
+            "processed_data": [
+                { "depth": 1.0, "temperature": 4.0, "salinity": 33.0 },
+                { "depth": 1.5, "temperature": 6.0, "salinity": 34.0 },
+                { "depth": 1.2, "temperature": 50.0, "dropped": "spike" },
+                { "depth": 10.0, "temperature": 1.0 },
+            ],
+            "channels": [{ "channelID": 1, "longName": "Temperature" }],
+        });
+        let rows = report_rows(&report).unwrap();
+        assert_eq!(rows.len(), 3);
+
+        let stats = sidebar_stats(&[sample(report)], &SidebarOptions::default());
+        assert_eq!(stats.average_temperature, Some(5.0));
+        assert_eq!(stats.average_salinity, Some(33.5));
+    }
+
+    #[test]
+    fn falls_back_to_raw_rows_of_a_ctd_report() {
+        let report = json!({
+            "rawData": [{ "depth": 0.5, "temperature": 3.0 }],
+            "processedData": [],
+        });
+        let stats = sidebar_stats(&[sample(report)], &SidebarOptions::default());
+        assert_eq!(stats.average_temperature, Some(3.0));
+    }
+
+    #[test]
+    fn reads_the_legacy_channel_layout() {
+        let report = json!({
+            "channels": [
+                { "channelID": 1, "longName": "Depth" },
+                { "channelID": "2", "long_name": "Temperature" },
+            ],
+            "data": [
+                { "channel01": 0.8, "channel02": "2.5", "tstamp": 1 },
+                { "channel01": 1.6, "channel02": 3.5, "tstamp": 2 },
+            ],
+        });
+        let rows = report_rows(&report).unwrap();
+        assert_eq!(rows[0].get("tstamp"), Some(&json!(1)));
+        assert_eq!(number(&rows[0], "temperature"), Some(2.5));
+
+        let stats = sidebar_stats(&[sample(report)], &SidebarOptions::default());
+        assert_eq!(stats.average_temperature, Some(3.0));
+    }
+
+    #[test]
+    fn warns_about_unknown_report_layouts() {
+        let stats = sidebar_stats(&[sample(json!({ "rows": [] }))], &SidebarOptions::default());
+        assert_eq!(stats.average_temperature, None);
+        assert_eq!(stats.warnings.len(), 1);
+        assert_eq!(stats.warnings[0].sample_id, "s1");
+    }
+}
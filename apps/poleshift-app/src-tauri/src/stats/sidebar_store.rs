@@ -0,0 +1,186 @@
+//poleshift/src-tauri/src/stats/sidebar_store.rs
+
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Runtime};
+
+use crate::ctd::output_store::{ctd_store_path, open_ctd_store};
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{open_store, output_store_path};
+use crate::krakenuniq::taxdb::canonical_rank;
+use crate::nutrients::handle_nutrient_data::NutrientEntry;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::stats::sidebar::{
+    sidebar_stats, ProcessedStats, SidebarChannels, SidebarOptions, SidebarSample, SidebarTaxon,
+    SidebarWarning,
+};
+
+/// A sample whose results are in the local output stores, by processed data ID.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StoredSidebarSample {
+    pub sample_id: String,
+    /// CTD files processed with `persist_outputs`
+    pub ctd_ids: Vec<String>,
+    /// Persisted classifications
+    pub classification_ids: Vec<String>,
+    /// Ammonium measurements
+    pub ammonium: Vec<f64>,
+    /// Nutrient measurements with their replicates
+    pub nutrients: Vec<NutrientEntry>,
+}
+
+/// `ProcessRequest` for results read from the output stores rather than sent along.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StoredStatsRequest {
+    pub samples: Vec<StoredSidebarSample>,
+    /// `channels` is not used: stored rows always have the processed row's columns
+    #[serde(flatten)]
+    pub options: SidebarOptions,
+}
+
+/// The surface rows of a stored CTD file: only rows at or above `surface_depth` and
+/// not kept with `keep_dropped_rows` leave SQLite.
+pub(crate) fn surface_rows(
+    path: &Path,
+    surface_depth: f64,
+    rows: &mut Vec<Map<String, Value>>,
+) -> Result<(), PoleshiftError> {
+    let conn = open_ctd_store(path)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT row FROM processed_data
+             WHERE json_extract(row, '$.depth') <= ?1
+               AND json_extract(row, '$.dropped') IS NULL
+             ORDER BY rowid",
+        )
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let mut cursor = stmt
+        .query([surface_depth])
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    while let Some(row) = cursor
+        .next()
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?
+    {
+        let json: String = row
+            .get(0)
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        rows.push(serde_json::from_str(&json)?);
+    }
+    Ok(())
+}
+
+/// The taxa of a stored classification above `threshold` percent of its reads, and
+/// the clade reads of its root.
+fn abundant_taxa(
+    path: &Path,
+    threshold: f64,
+    taxa: &mut Vec<SidebarTaxon>,
+) -> Result<Option<u64>, PoleshiftError> {
+    let conn = open_store(path)?;
+    let mut stmt = conn
+        .prepare("SELECT tax_name, rank, percentage, reads FROM report WHERE percentage > ?1")
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    let rows = stmt
+        .query_map([threshold], |row| {
+            Ok(SidebarTaxon {
+                tax_name: row.get(0)?,
+                rank: canonical_rank(&row.get::<_, String>(1)?),
+                percentage: row.get(2)?,
+                reads: Some(row.get::<_, i64>(3)?.max(0) as u64),
+            })
+        })
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    for row in rows {
+        taxa.push(row.map_err(|e| PoleshiftError::DataError(e.to_string()))?);
+    }
+    let total: Option<i64> = conn
+        .query_row("SELECT MAX(reads) FROM report", [], |row| row.get(0))
+        .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+    Ok(total.map(|reads| reads.max(0) as u64))
+}
+
+/// Reads what the sidebar needs of each sample from its stored CTD rows and
+/// classifications, and summarizes it as `sidebar_stats` does. A store that is
+/// missing or unreadable leaves its sample out of that statistic with a warning.
+pub(crate) fn stored_sidebar_stats<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    request: &StoredStatsRequest,
+) -> ProcessedStats {
+    let mut warnings = Vec::new();
+    let mut samples = Vec::with_capacity(request.samples.len());
+    for stored in &request.samples {
+        let mut sample = SidebarSample {
+            sample_id: stored.sample_id.clone(),
+            ammonium: stored.ammonium.clone(),
+            nutrients: stored.nutrients.clone(),
+            ..SidebarSample::default()
+        };
+        let mut warn = |kind: &str, id: &str, error: PoleshiftError| {
+            warnings.push(SidebarWarning {
+                sample_id: stored.sample_id.clone(),
+                message: format!("its {} {} could not be read: {}", kind, id, error),
+            });
+        };
+
+        // 1) Surface CTD rows
+        for id in &stored.ctd_ids {
+            let read = ctd_store_path(app_handle, id).and_then(|path| {
+                surface_rows(&path, request.options.surface_depth, &mut sample.ctd_rows)
+            });
+            if let Err(error) = read {
+                warn("CTD data", id, error);
+            }
+        }
+
+        // 2) Abundant taxa and reads
+        for id in &stored.classification_ids {
+            let read = output_store_path(app_handle, id).and_then(|path| {
+                abundant_taxa(&path, request.options.abundance_threshold, &mut sample.taxa)
+            });
+            match read {
+                Ok(reads) => {
+                    sample.total_reads = match (sample.total_reads, reads) {
+                        (Some(a), Some(b)) => Some(a + b),
+                        (a, b) => a.or(b),
+                    }
+                }
+                Err(error) => warn("classification", id, error),
+            }
+        }
+        samples.push(sample);
+    }
+
+    let options = SidebarOptions {
+        channels: SidebarChannels::default(),
+        ..request.options.clone()
+    };
+    let mut stats = sidebar_stats(&samples, &options);
+    warnings.append(&mut stats.warnings);
+    stats.warnings = warnings;
+    stats
+}
+
+/// `process_sidebar_stats` over results in the local output stores, so the frontend
+/// sends processed data IDs instead of the processed data (see
+/// `stored_sidebar_stats`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn process_stored_sidebar_stats<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    request: StoredStatsRequest,
+) -> Result<StandardResponseNoFiles<ProcessedStats>, PoleshiftError> {
+    traced(invocation, None, async move {
+        request.options.validate()?;
+        let report = stored_sidebar_stats(&app_handle, &request);
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
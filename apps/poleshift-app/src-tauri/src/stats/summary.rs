@@ -0,0 +1,243 @@
+//poleshift/src-tauri/src/stats/summary.rs
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::devtools::{traced, Invocation};
+use crate::krakenuniq::output_store::{load_report, output_store_path};
+use crate::krakenuniq::taxdb::canonical_rank;
+use crate::krakenuniq::ProcessedKrakenUniqReport;
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+
+/// Largest number of taxa listed per rank.
+const MAX_TOP_N: usize = 1_000;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SummaryOptions {
+    /// Taxa listed per rank in `ranks`, largest first
+    pub top_n: usize,
+    /// Ranks to list the top taxa of
+    pub ranks: Vec<String>,
+    /// Rank every read is collapsed to for `rollup`
+    pub rollup_rank: String,
+    /// Taxa of the rollup below this percentage of all reads go to "other"
+    pub min_percentage: f64,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        SummaryOptions {
+            top_n: 10,
+            ranks: [
+                "domain", "phylum", "class", "order", "family", "genus", "species",
+            ]
+            .iter()
+            .map(|r| r.to_string())
+            .collect(),
+            rollup_rank: "genus".to_string(),
+            min_percentage: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SummaryTaxon {
+    pub tax_id: u64,
+    pub tax_name: String,
+    /// Reads assigned to this taxon or anything below it
+    pub reads: u64,
+    /// Share of all reads of the sample
+    pub percentage: f64,
+}
+
+/// Reads of a sample split by the taxa at one rank. `taxa`, `other`, `unresolved` and
+/// `unclassified` together add up to all reads.
+#[derive(Debug, Serialize)]
+pub struct RankSummary {
+    pub rank: String,
+    /// Distinct taxa at this rank in the report
+    pub taxa_at_rank: usize,
+    pub taxa: Vec<SummaryTaxon>,
+    /// Reads of the taxa at this rank that were not listed
+    pub other: SummaryTaxon,
+    /// Classified reads that stop above this rank
+    pub unresolved: SummaryTaxon,
+    pub unclassified: SummaryTaxon,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportSummary {
+    pub processed_data_id: String,
+    pub total_reads: u64,
+    /// Top taxa of each requested rank
+    pub ranks: Vec<RankSummary>,
+    /// Every read collapsed to `rollup_rank`, small taxa bucketed into "other"
+    pub rollup: RankSummary,
+}
+
+/// Clade reads of each taxon at `rank`. Reads are attributed to the topmost ancestor
+/// at that rank, so a taxon nested below another of the same rank is not counted twice.
+fn clades_at_rank<'a>(
+    report: &'a [ProcessedKrakenUniqReport],
+    rank: &str,
+) -> Vec<(&'a ProcessedKrakenUniqReport, u64)> {
+    let by_id: HashMap<&str, usize> = report
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row.id.as_str(), i))
+        .collect();
+    let ranks: Vec<String> = report.iter().map(|row| canonical_rank(&row.rank)).collect();
+
+    let mut reads: HashMap<usize, u64> = HashMap::new();
+    for (i, row) in report.iter().enumerate() {
+        let mut current = Some(i);
+        let mut topmost = None;
+        let mut steps = 0;
+        while let Some(c) = current {
+            if ranks[c] == rank {
+                topmost = Some(c);
+            }
+            steps += 1;
+            current = report[c]
+                .parent_id
+                .and_then(|p| by_id.get(p.to_string().as_str()).copied())
+                // Guard against cycles in malformed reports
+                .filter(|_| steps <= report.len());
+        }
+        if let Some(t) = topmost {
+            *reads.entry(t).or_default() += row.tax_reads;
+        }
+    }
+
+    let mut clades: Vec<(&ProcessedKrakenUniqReport, u64)> = reads
+        .into_iter()
+        .filter(|&(_, r)| r > 0)
+        .map(|(i, r)| (&report[i], r))
+        .collect();
+    clades.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.tax_id.cmp(&b.0.tax_id)));
+    clades
+}
+
+/// Splits the reads of `report` by the taxa at `rank`, listing those picked by `keep`
+/// (given their position and percentage) and bucketing the rest.
+fn summarize_rank(
+    report: &[ProcessedKrakenUniqReport],
+    rank: &str,
+    total_reads: u64,
+    unclassified_reads: u64,
+    keep: impl Fn(usize, f64) -> bool,
+) -> RankSummary {
+    let percentage = |reads: u64| {
+        if total_reads > 0 {
+            reads as f64 * 100.0 / total_reads as f64
+        } else {
+            0.0
+        }
+    };
+    let bucket = |tax_id: u64, name: &str, reads: u64| SummaryTaxon {
+        tax_id,
+        tax_name: name.to_string(),
+        reads,
+        percentage: percentage(reads),
+    };
+
+    let clades = clades_at_rank(report, rank);
+    let at_rank: u64 = clades.iter().map(|(_, r)| r).sum();
+    let taxa_at_rank = clades.len();
+    let taxa: Vec<SummaryTaxon> = clades
+        .into_iter()
+        .enumerate()
+        .filter(|&(i, (_, reads))| keep(i, percentage(reads)))
+        .map(|(_, (row, reads))| bucket(row.tax_id, row.tax_name.trim(), reads))
+        .collect();
+    let listed: u64 = taxa.iter().map(|t| t.reads).sum();
+    let unresolved = total_reads.saturating_sub(at_rank + unclassified_reads);
+
+    RankSummary {
+        rank: rank.to_string(),
+        taxa_at_rank,
+        taxa,
+        other: bucket(0, "Other", at_rank - listed),
+        unresolved: bucket(0, &format!("Unresolved at {}", rank), unresolved),
+        unclassified: bucket(0, "Unclassified", unclassified_reads),
+    }
+}
+
+fn summarize(
+    processed_data_id: &str,
+    report: &[ProcessedKrakenUniqReport],
+    options: &SummaryOptions,
+) -> Result<ReportSummary, PoleshiftError> {
+    // 1) Check the ranks; "no rank" taxa do not partition the reads
+    let rollup_rank = canonical_rank(&options.rollup_rank);
+    let ranks: Vec<String> = options.ranks.iter().map(|r| canonical_rank(r)).collect();
+    for rank in ranks.iter().chain([&rollup_rank]) {
+        if rank.is_empty() || rank == "no rank" || rank == "unclassified" {
+            return Err(PoleshiftError::InvalidInput {
+                field: "rank".to_string(),
+                reason: format!("cannot summarize at rank '{}'", rank),
+            });
+        }
+    }
+
+    // 2) Totals over the whole report
+    let total_reads: u64 = report
+        .iter()
+        .filter(|row| row.parent_id.is_none())
+        .map(|row| row.reads)
+        .sum();
+    let unclassified_reads: u64 = report
+        .iter()
+        .filter(|row| row.tax_id == 0)
+        .map(|row| row.tax_reads)
+        .sum();
+
+    // 3) Top taxa per rank, then the rollup with small taxa bucketed
+    let top_n = options.top_n.min(MAX_TOP_N);
+    let ranks = ranks
+        .iter()
+        .map(|rank| {
+            summarize_rank(report, rank, total_reads, unclassified_reads, |i, _| {
+                i < top_n
+            })
+        })
+        .collect();
+    let rollup = summarize_rank(
+        report,
+        &rollup_rank,
+        total_reads,
+        unclassified_reads,
+        |_, percentage| percentage >= options.min_percentage,
+    );
+
+    Ok(ReportSummary {
+        processed_data_id: processed_data_id.to_string(),
+        total_reads,
+        ranks,
+        rollup,
+    })
+}
+
+/// Summarizes the stored report of `processed_data_id` for charts: the top taxa of
+/// each rank, and every read collapsed to one rank with small taxa bucketed into
+/// "other", so the frontend never has to walk the full report.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn summarize_report<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: String,
+    options: Option<SummaryOptions>,
+) -> Result<StandardResponseNoFiles<ReportSummary>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let report = load_report(&output_store_path(&app_handle, &processed_data_id)?)?;
+        let options = options.unwrap_or_default();
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: summarize(&processed_data_id, &report, &options)?,
+        })
+    })
+    .await
+}
@@ -0,0 +1,289 @@
+//poleshift/src-tauri/src/stats/trends.rs
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctd::{civil_from_epoch_secs, format_iso8601};
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::stats::region::RegionSample;
+use crate::stats::sidebar::{sidebar_stats, ProcessedStats, SidebarOptions};
+
+const MS_PER_YEAR: f64 = 365.2425 * 86_400_000.0;
+
+/// A surface CTD statistic followed over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrendVariable {
+    Temperature,
+    Salinity,
+    Chlorophyll,
+    Density,
+}
+
+impl TrendVariable {
+    fn value(self, stats: &ProcessedStats) -> Option<f64> {
+        match self {
+            TrendVariable::Temperature => stats.average_temperature,
+            TrendVariable::Salinity => stats.average_salinity,
+            TrendVariable::Chlorophyll => stats.average_chlorophyll,
+            TrendVariable::Density => stats.average_density,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TrendVariable::Temperature => "temperature",
+            TrendVariable::Salinity => "salinity",
+            TrendVariable::Chlorophyll => "chlorophyll",
+            TrendVariable::Density => "density",
+        }
+    }
+}
+
+/// Samples of repeatedly visited stations, and what to follow at them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StationTrendRequest {
+    /// Samples with a location and a collection time; others are left out
+    pub samples: Vec<RegionSample>,
+    pub variables: Vec<TrendVariable>,
+    /// Taxa whose share of each sample's reads is followed, by name
+    pub taxa: Vec<String>,
+    /// Stations visited at fewer distinct times are left out
+    pub min_visits: usize,
+    #[serde(flatten)]
+    pub options: SidebarOptions,
+}
+
+impl Default for StationTrendRequest {
+    fn default() -> Self {
+        StationTrendRequest {
+            samples: Vec::new(),
+            variables: vec![TrendVariable::Temperature, TrendVariable::Chlorophyll],
+            taxa: Vec::new(),
+            min_visits: 2,
+            options: SidebarOptions::default(),
+        }
+    }
+}
+
+impl StationTrendRequest {
+    pub fn validate(&self) -> Result<(), PoleshiftError> {
+        if self.min_visits < 2 {
+            return Err(PoleshiftError::InvalidInput {
+                field: "min_visits".to_string(),
+                reason: "must be at least 2".to_string(),
+            });
+        }
+        self.options.validate()
+    }
+}
+
+/// What a series follows.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TrendSubject {
+    /// A surface CTD statistic, combined by the request's aggregation
+    Ctd { variable: String },
+    /// A taxon's share of the sample's reads, in percent
+    Taxon { tax_name: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendPoint {
+    pub sample_id: String,
+    /// Collection time, in ms since the Unix epoch, and in ISO 8601 UTC
+    pub tstamp: i64,
+    pub time_utc: String,
+    pub value: f64,
+}
+
+/// Least-squares line through a series.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendFit {
+    /// Change per year, in the series' units
+    pub slope_per_year: f64,
+    /// Value of the line at the first point
+    pub intercept: f64,
+    pub r_squared: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendSeries {
+    #[serde(flatten)]
+    pub subject: TrendSubject,
+    /// In time order
+    pub points: Vec<TrendPoint>,
+    /// None with points at fewer than two distinct times
+    pub trend: Option<TrendFit>,
+    /// Mean of the points of each calendar month (1 to 12), in UTC
+    pub monthly_means: BTreeMap<i64, f64>,
+}
+
+/// The series of one station.
+#[derive(Debug, Clone, Serialize)]
+pub struct StationTrends {
+    pub location_id: String,
+    /// Distinct collection times
+    pub visits: usize,
+    pub series: Vec<TrendSeries>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StationTrendReport {
+    /// By location id
+    pub stations: Vec<StationTrends>,
+    /// Samples without a location or time, or at stations visited too few times
+    pub excluded: usize,
+}
+
+/// The least-squares line through `points`, in time order.
+fn fit(points: &[TrendPoint]) -> Option<TrendFit> {
+    let origin = points.first()?.tstamp;
+    let xs: Vec<f64> = points
+        .iter()
+        .map(|point| (point.tstamp - origin) as f64 / MS_PER_YEAR)
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = points.iter().map(|point| point.value).sum::<f64>() / n;
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for (x, point) in xs.iter().zip(points) {
+        let (dx, dy) = (x - mean_x, point.value - mean_y);
+        sxx += dx * dx;
+        sxy += dx * dy;
+        syy += dy * dy;
+    }
+    if sxx <= 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some(TrendFit {
+        slope_per_year: slope,
+        intercept: mean_y - slope * mean_x,
+        r_squared: (syy > 0.0).then(|| sxy * sxy / (sxx * syy)),
+    })
+}
+
+fn series(subject: TrendSubject, mut points: Vec<TrendPoint>) -> TrendSeries {
+    points.sort_by_key(|point| point.tstamp);
+    let mut months: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for point in &points {
+        let (_, month, _, _) = civil_from_epoch_secs(point.tstamp.div_euclid(1000));
+        let entry = months.entry(month).or_default();
+        entry.0 += point.value;
+        entry.1 += 1;
+    }
+    TrendSeries {
+        subject,
+        trend: fit(&points),
+        points,
+        monthly_means: months
+            .into_iter()
+            .map(|(month, (sum, count))| (month, sum / count as f64))
+            .collect(),
+    }
+}
+
+/// Follows the requested CTD statistics and taxa over time at each station visited at
+/// `min_visits` distinct times or more, with a least-squares trend and monthly means
+/// per series. CTD values are each sample's surface statistics (see `sidebar_stats`);
+/// a taxon absent from a classified sample counts as 0%.
+pub fn station_trends(request: &StationTrendRequest) -> StationTrendReport {
+    // 1) Samples by station
+    let mut stations: BTreeMap<&str, Vec<(&RegionSample, i64)>> = BTreeMap::new();
+    for sample in &request.samples {
+        if let (Some(location_id), Some(tstamp)) = (&sample.location_id, sample.tstamp) {
+            stations
+                .entry(location_id)
+                .or_default()
+                .push((sample, tstamp));
+        }
+    }
+
+    // 2) Series of the stations visited often enough
+    let mut report = StationTrendReport {
+        stations: Vec::new(),
+        excluded: request.samples.len(),
+    };
+    for (location_id, samples) in stations {
+        let mut times: Vec<i64> = samples.iter().map(|(_, tstamp)| *tstamp).collect();
+        times.sort_unstable();
+        times.dedup();
+        if times.len() < request.min_visits {
+            continue;
+        }
+        report.excluded -= samples.len();
+
+        let point = |sample: &RegionSample, tstamp: i64, value: f64| TrendPoint {
+            sample_id: sample.sample.sample_id.clone(),
+            tstamp,
+            time_utc: format_iso8601(tstamp, 0),
+            value,
+        };
+        let stats: Vec<ProcessedStats> = samples
+            .iter()
+            .map(|(sample, _)| sidebar_stats([&sample.sample], &request.options))
+            .collect();
+        let mut station = StationTrends {
+            location_id: location_id.to_string(),
+            visits: times.len(),
+            series: Vec::new(),
+        };
+        for &variable in &request.variables {
+            let points = samples
+                .iter()
+                .zip(&stats)
+                .filter_map(|((sample, tstamp), stats)| {
+                    Some(point(sample, *tstamp, variable.value(stats)?))
+                })
+                .collect();
+            let subject = TrendSubject::Ctd {
+                variable: variable.name().to_string(),
+            };
+            station.series.push(series(subject, points));
+        }
+        for tax_name in &request.taxa {
+            let points = samples
+                .iter()
+                .filter(|(sample, _)| !sample.sample.taxa.is_empty())
+                .map(|(sample, tstamp)| {
+                    let share = sample
+                        .sample
+                        .taxa
+                        .iter()
+                        .filter(|taxon| &taxon.tax_name == tax_name)
+                        .map(|taxon| taxon.percentage)
+                        .fold(0.0, f64::max);
+                    point(sample, *tstamp, share)
+                })
+                .collect();
+            let subject = TrendSubject::Taxon {
+                tax_name: tax_name.clone(),
+            };
+            station.series.push(series(subject, points));
+        }
+        report.stations.push(station);
+    }
+    report
+}
+
+/// Time series and trends of CTD statistics and taxa at repeatedly sampled stations,
+/// ready to plot (see `station_trends`).
+#[tauri::command(rename_all = "snake_case")]
+pub async fn compute_station_trends(
+    invocation: Invocation,
+    request: StationTrendRequest,
+) -> Result<StandardResponseNoFiles<StationTrendReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        request.validate()?;
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: station_trends(&request),
+        })
+    })
+    .await
+}
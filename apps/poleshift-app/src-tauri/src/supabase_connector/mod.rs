@@ -0,0 +1,102 @@
+//poleshift/src-tauri/src/supabase_connector/mod.rs
+
+pub mod permissions;
+pub mod query;
+pub mod realtime;
+pub mod storage;
+pub mod upload;
+
+use reqwest::header::AUTHORIZATION;
+use reqwest::RequestBuilder;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::auth::AuthManager;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Most IDs put in one `id=in.(...)` filter, to keep the URL short.
+pub(crate) const MAX_FILTER_IDS: usize = 200;
+
+/// The signed-in user's connection to the Supabase project, for calls the backend
+/// makes on their behalf.
+#[derive(Clone)]
+pub(crate) struct SupabaseConnection {
+    pub url: String,
+    pub api_key: String,
+    pub access_token: String,
+}
+
+impl SupabaseConnection {
+    /// URL of `path` (e.g. `storage/v1/object/...`) in the project.
+    pub fn endpoint(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Adds the project key and the user's access token to `request`.
+    pub fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("apikey", &self.api_key)
+            .header(AUTHORIZATION, format!("Bearer {}", self.access_token))
+    }
+}
+
+/// The current session's connection; an error when no one is signed in.
+pub(crate) fn connection<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<SupabaseConnection, PoleshiftError> {
+    let current = app_handle
+        .try_state::<AuthManager>()
+        .and_then(|auth| Some((auth.project()?, auth.session()?)));
+    match current {
+        Some((project, session)) => Ok(SupabaseConnection {
+            url: project.supabase_url,
+            api_key: project.api_key,
+            access_token: session.access_token,
+        }),
+        None => Err(PoleshiftError::InvalidInput {
+            field: "session".to_string(),
+            reason: "sign in to reach Supabase".to_string(),
+        }),
+    }
+}
+
+/// Checks that `table` is a plain table name, as it ends up in a URL.
+pub(crate) fn validate_table(table: &str) -> Result<(), PoleshiftError> {
+    if table.is_empty()
+        || !table
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return Err(PoleshiftError::InvalidInput {
+            field: "table".to_string(),
+            reason: format!("'{}' is not a table name", table),
+        });
+    }
+    Ok(())
+}
+
+/// Percent-encodes each segment of an object or table path for use in a URL.
+pub(crate) fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// PostgREST `in.(...)` filter matching `ids`.
+pub(crate) fn id_filter<'a>(ids: impl IntoIterator<Item = &'a str>) -> String {
+    let ids: Vec<String> = ids
+        .into_iter()
+        .map(|id| format!("\"{}\"", id.replace('"', "")))
+        .collect();
+    format!("in.({})", ids.join(","))
+}
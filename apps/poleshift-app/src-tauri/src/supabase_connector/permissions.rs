@@ -0,0 +1,150 @@
+//poleshift/src-tauri/src/supabase_connector/permissions.rs
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::auth::AuthManager;
+use crate::poleshift_common::types::PoleshiftError;
+
+/// Role of a user in their organisation, as the `user_role` claim names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    Admin,
+    Lead,
+    Researcher,
+    Viewer,
+}
+
+/// What a role may do; mirrors `PoleshiftPermissions` in the frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Permission {
+    #[serde(rename = "organizations.add_user")]
+    AddUser,
+    #[serde(rename = "organizations.remove_user")]
+    RemoveUser,
+    #[serde(rename = "organizations.view_user")]
+    ViewUser,
+    #[serde(rename = "organizations.modify_user")]
+    ModifyUser,
+    #[serde(rename = "sample_groups.delete")]
+    DeleteSampleGroup,
+    #[serde(rename = "sample_groups.create")]
+    CreateSampleGroup,
+    #[serde(rename = "sample_groups.modify")]
+    ModifySampleGroup,
+    #[serde(rename = "sample_groups.share")]
+    ShareSampleGroup,
+}
+
+const ADMIN_PERMISSIONS: [Permission; 8] = [
+    Permission::AddUser,
+    Permission::RemoveUser,
+    Permission::ViewUser,
+    Permission::ModifyUser,
+    Permission::DeleteSampleGroup,
+    Permission::CreateSampleGroup,
+    Permission::ModifySampleGroup,
+    Permission::ShareSampleGroup,
+];
+
+const RESEARCHER_PERMISSIONS: [Permission; 3] = [
+    Permission::ViewUser,
+    Permission::CreateSampleGroup,
+    Permission::ModifySampleGroup,
+];
+
+impl Permission {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::AddUser => "organizations.add_user",
+            Permission::RemoveUser => "organizations.remove_user",
+            Permission::ViewUser => "organizations.view_user",
+            Permission::ModifyUser => "organizations.modify_user",
+            Permission::DeleteSampleGroup => "sample_groups.delete",
+            Permission::CreateSampleGroup => "sample_groups.create",
+            Permission::ModifySampleGroup => "sample_groups.modify",
+            Permission::ShareSampleGroup => "sample_groups.share",
+        }
+    }
+}
+
+impl UserRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UserRole::Admin => "admin",
+            UserRole::Lead => "lead",
+            UserRole::Researcher => "researcher",
+            UserRole::Viewer => "viewer",
+        }
+    }
+
+    /// The permissions the role grants; a viewer has none.
+    pub fn permissions(self) -> &'static [Permission] {
+        match self {
+            UserRole::Admin | UserRole::Lead => &ADMIN_PERMISSIONS,
+            UserRole::Researcher => &RESEARCHER_PERMISSIONS,
+            UserRole::Viewer => &[],
+        }
+    }
+}
+
+/// Claims of the session's access token the backend acts on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionClaims {
+    /// The user's ID
+    pub sub: Option<String>,
+    pub user_role: Option<UserRole>,
+    pub user_org: Option<String>,
+    /// When the token expires, in seconds since the Unix epoch
+    pub exp: Option<i64>,
+}
+
+/// Reads the claims of an access token. The signature is not checked: Supabase does
+/// that on every call, and the claims only decide what the app lets a user try.
+pub(crate) fn decode_claims(access_token: &str) -> Result<SessionClaims, PoleshiftError> {
+    let unreadable = |reason: String| PoleshiftError::InvalidInput {
+        field: "access_token".to_string(),
+        reason,
+    };
+    let payload = access_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| unreadable("not a JWT".to_string()))?;
+    let json = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|e| unreadable(e.to_string()))?;
+    serde_json::from_slice(&json).map_err(|e| unreadable(e.to_string()))
+}
+
+/// Claims of the current session; an error when no one is signed in.
+pub(crate) fn session_claims<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<SessionClaims, PoleshiftError> {
+    let session = app_handle
+        .try_state::<AuthManager>()
+        .and_then(|auth| auth.session())
+        .ok_or_else(|| PoleshiftError::InvalidInput {
+            field: "session".to_string(),
+            reason: "sign in to reach Supabase".to_string(),
+        })?;
+    decode_claims(&session.access_token)
+}
+
+/// Guards a command that changes shared data: returns the session's claims when its
+/// role grants `permission`, and `PermissionDenied` otherwise.
+pub(crate) fn require_permission<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    permission: Permission,
+) -> Result<SessionClaims, PoleshiftError> {
+    let claims = session_claims(app_handle)?;
+    match claims.user_role {
+        Some(role) if role.permissions().contains(&permission) => Ok(claims),
+        role => Err(PoleshiftError::PermissionDenied {
+            permission: permission.as_str().to_string(),
+            role: role.map_or("none", UserRole::as_str).to_string(),
+        }),
+    }
+}
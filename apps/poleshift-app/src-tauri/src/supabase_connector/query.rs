@@ -0,0 +1,273 @@
+//poleshift/src-tauri/src/supabase_connector/query.rs
+
+use reqwest::header::CONTENT_RANGE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Runtime};
+
+use crate::ctd::delimited::parse_iso8601;
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::parse_uuid;
+use crate::supabase_connector::connection;
+use crate::supabase_connector::permissions::session_claims;
+
+/// Rows returned when a read does not set its own limit.
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+/// Values of `processed_data_improved.data_type` and `processing_state`.
+const DATA_TYPES: [&str; 3] = ["ctd", "sequence", "nutrient_ammonia"];
+const PROCESSING_STATES: [&str; 5] = ["initiated", "processing", "complete", "error", "saving"];
+
+/// Which rows of a read to return.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Page {
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page {
+            limit: DEFAULT_PAGE_SIZE,
+            offset: 0,
+        }
+    }
+}
+
+/// Filters of `fetch_samples`; all optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SampleFilter {
+    pub loc_id: Option<String>,
+    /// First and last collection dates, `YYYY-MM-DD`, both included
+    pub collected_from: Option<String>,
+    pub collected_to: Option<String>,
+    /// Part of the human-readable sample ID, in any case
+    pub search: Option<String>,
+    /// Whether samples marked excluded are returned; they are not by default
+    pub include_excluded: bool,
+}
+
+/// Filters of `fetch_processed_data`; all optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProcessedDataFilter {
+    /// `ctd`, `sequence` or `nutrient_ammonia`
+    pub data_type: Option<String>,
+    pub processing_state: Option<String>,
+}
+
+/// A page of rows and where it sits in the whole result.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryPage {
+    pub rows: Vec<Value>,
+    /// Rows matching the filters across all pages
+    pub total: Option<usize>,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+/// A PostgREST read of `table`, with its filters as `(column, operator.value)` pairs.
+struct Query {
+    table: &'static str,
+    filters: Vec<(String, String)>,
+    order: &'static str,
+}
+
+impl Query {
+    /// A read of `table` limited to the rows the session may see: those of the user's
+    /// organisation, or the user's own when they belong to none. Row-level security
+    /// holds the same line on the server; this keeps every read within it on purpose
+    /// rather than by policy alone.
+    fn scoped<R: Runtime>(
+        app_handle: &AppHandle<R>,
+        table: &'static str,
+        order: &'static str,
+    ) -> Result<Self, PoleshiftError> {
+        let claims = session_claims(app_handle)?;
+        let scope = match (claims.user_org, claims.sub) {
+            (Some(org), _) => ("org_id", org),
+            (None, Some(user)) => ("user_id", user),
+            (None, None) => {
+                return Err(PoleshiftError::InvalidInput {
+                    field: "access_token".to_string(),
+                    reason: "names neither a user nor an organisation".to_string(),
+                })
+            }
+        };
+        let mut query = Query {
+            table,
+            filters: Vec::new(),
+            order,
+        };
+        query.filter(scope.0, "eq", &scope.1);
+        Ok(query)
+    }
+
+    fn filter(&mut self, column: &str, operator: &str, value: &str) {
+        self.filters
+            .push((column.to_string(), format!("{}.{}", operator, value)));
+    }
+
+    /// Runs the read for `page`, counting the matching rows as it goes.
+    async fn fetch<R: Runtime>(
+        self,
+        app_handle: &AppHandle<R>,
+        page: Page,
+    ) -> Result<QueryPage, PoleshiftError> {
+        if page.limit == 0 || page.limit > MAX_PAGE_SIZE {
+            return Err(PoleshiftError::InvalidInput {
+                field: "limit".to_string(),
+                reason: format!("must be between 1 and {}", MAX_PAGE_SIZE),
+            });
+        }
+        let conn = connection(app_handle)?;
+        let request = reqwest::Client::new()
+            .get(conn.endpoint(&format!("rest/v1/{}", self.table)))
+            .query(&[("select", "*"), ("order", self.order)])
+            .query(&self.filters)
+            .query(&[("limit", page.limit), ("offset", page.offset)])
+            .header("Prefer", "count=exact");
+        let response = conn
+            .authorize(request)
+            .send()
+            .await
+            .map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(PoleshiftError::DataError(format!(
+                "Reading {} failed: {}: {}",
+                self.table,
+                status,
+                response.text().await.unwrap_or_default()
+            )));
+        }
+        // `Content-Range: 0-99/1234`; the total is `*` when unknown
+        let total = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|total| total.parse().ok());
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| PoleshiftError::IoError(e.to_string()))?;
+        Ok(QueryPage {
+            rows: serde_json::from_slice(&body)?,
+            total,
+            limit: page.limit,
+            offset: page.offset,
+        })
+    }
+}
+
+/// Checks that `date` is a `YYYY-MM-DD` date.
+fn validate_date(field: &str, date: &str) -> Result<(), PoleshiftError> {
+    let valid = date.len() == 10 && parse_iso8601(&format!("{}T00:00:00Z", date)).is_some();
+    if !valid {
+        return Err(PoleshiftError::InvalidInput {
+            field: field.to_string(),
+            reason: format!("'{}' is not a YYYY-MM-DD date", date),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that `value` is one of `allowed`.
+fn validate_choice(field: &str, value: &str, allowed: &[&str]) -> Result<(), PoleshiftError> {
+    if !allowed.contains(&value) {
+        return Err(PoleshiftError::InvalidInput {
+            field: field.to_string(),
+            reason: format!("'{}' is not one of {}", value, allowed.join(", ")),
+        });
+    }
+    Ok(())
+}
+
+/// Lists the samples the signed-in user may see, most recently collected first,
+/// narrowed by `filter` and paged by `page`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn fetch_samples<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    filter: Option<SampleFilter>,
+    page: Option<Page>,
+) -> Result<StandardResponseNoFiles<QueryPage>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let filter = filter.unwrap_or_default();
+        let mut query = Query::scoped(
+            &app_handle,
+            "sample_group_metadata",
+            "collection_date.desc,id",
+        )?;
+        if let Some(loc_id) = &filter.loc_id {
+            query.filter("loc_id", "eq", &parse_uuid("loc_id", loc_id)?.to_string());
+        }
+        if let Some(from) = &filter.collected_from {
+            validate_date("collected_from", from)?;
+            query.filter("collection_date", "gte", from);
+        }
+        if let Some(to) = &filter.collected_to {
+            validate_date("collected_to", to)?;
+            query.filter("collection_date", "lte", to);
+        }
+        // PostgREST wildcards and list syntax are taken out of the search term
+        let search = filter
+            .search
+            .as_deref()
+            .map(|search| search.replace(['*', '%', ',', '(', ')'], ""))
+            .filter(|search| !search.trim().is_empty());
+        if let Some(search) = search {
+            query.filter(
+                "human_readable_sample_id",
+                "ilike",
+                &format!("*{}*", search.trim()),
+            );
+        }
+        if !filter.include_excluded {
+            query.filter("excluded", "eq", "false");
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: query.fetch(&app_handle, page.unwrap_or_default()).await?,
+        })
+    })
+    .await
+}
+
+/// Lists the processed data of `sample_id` the signed-in user may see, newest first,
+/// narrowed by `filter` and paged by `page`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn fetch_processed_data<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    sample_id: String,
+    filter: Option<ProcessedDataFilter>,
+    page: Option<Page>,
+) -> Result<StandardResponseNoFiles<QueryPage>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let filter = filter.unwrap_or_default();
+        let sample_id = parse_uuid("sample_id", &sample_id)?;
+        let mut query =
+            Query::scoped(&app_handle, "processed_data_improved", "created_at.desc,id")?;
+        query.filter("sample_id", "eq", &sample_id.to_string());
+        if let Some(data_type) = &filter.data_type {
+            validate_choice("data_type", data_type, &DATA_TYPES)?;
+            query.filter("data_type", "eq", data_type);
+        }
+        if let Some(state) = &filter.processing_state {
+            validate_choice("processing_state", state, &PROCESSING_STATES)?;
+            query.filter("processing_state", "eq", state);
+        }
+
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: query.fetch(&app_handle, page.unwrap_or_default()).await?,
+        })
+    })
+    .await
+}
@@ -0,0 +1,345 @@
+//poleshift/src-tauri/src/supabase_connector/realtime.rs
+
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::devtools::{traced_blocking, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::supabase_connector::{connection, validate_table, SupabaseConnection};
+
+/// Event carrying each `TableChange`.
+pub const TABLE_CHANGE_EVENT: &str = "table-change";
+/// Realtime closes connections that stay silent for longer than 60 s.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(25);
+/// How often the connection looks up from reading to heartbeat and check for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest the bridge sleeps with nothing to subscribe to, or no session to do it with.
+const IDLE_DELAY: Duration = Duration::from_secs(30);
+/// Wait before reconnecting after the first failure, doubled on each further one.
+const BASE_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Kind of row change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    #[serde(alias = "INSERT")]
+    Insert,
+    #[serde(alias = "UPDATE")]
+    Update,
+    #[serde(alias = "DELETE")]
+    Delete,
+}
+
+/// A row change on a subscribed table, as emitted on `table-change`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableChange {
+    pub table: String,
+    #[serde(rename(deserialize = "type"))]
+    pub kind: ChangeKind,
+    /// The row after an insert or update
+    #[serde(default)]
+    pub record: Value,
+    /// The row before an update or delete; only its primary key unless the table's
+    /// replica identity is full
+    #[serde(default)]
+    pub old_record: Value,
+    pub commit_timestamp: Option<String>,
+}
+
+/// A Phoenix channel message, the framing Realtime speaks.
+#[derive(Deserialize)]
+struct ChannelMessage {
+    topic: String,
+    event: String,
+    #[serde(default)]
+    payload: Value,
+}
+
+/// How a connection ended without failing.
+enum Ended {
+    /// The subscribed tables or the session changed; connect again with the new ones
+    Resubscribe,
+    /// The app is closing
+    Stop,
+}
+
+/// Bridges Supabase Realtime row changes to the frontend.
+///
+/// Tables are added with `subscribe_table_changes`; a background thread, started with
+/// `start` from the app's setup, keeps one websocket open to the project's Realtime
+/// endpoint with a channel per table, and re-emits each insert, update and delete as a
+/// `table-change` event. Row-level security applies as for any other read, so users
+/// see the changes of their organisation. The connection is opened with the current
+/// session and closed once that session is renewed or signed out of, as its channels
+/// were joined with the old token; it is reopened with a growing delay when it drops.
+#[derive(Default)]
+pub struct RealtimeBridge {
+    tables: Mutex<BTreeSet<String>>,
+    wake: Mutex<Option<Sender<()>>>,
+}
+
+impl RealtimeBridge {
+    /// Starts the connection thread.
+    pub fn start<R: Runtime>(&self, app_handle: AppHandle<R>) {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut wake) = self.wake.lock() {
+            *wake = Some(sender);
+        }
+        std::thread::spawn(move || {
+            let mut failures = 0;
+            let mut delay = IDLE_DELAY;
+            while let Ok(()) | Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(delay) {
+                let Some(bridge) = app_handle.try_state::<RealtimeBridge>() else {
+                    break;
+                };
+                let tables = bridge.tables();
+                let conn = match connection(&app_handle) {
+                    Ok(conn) if !tables.is_empty() => conn,
+                    _ => {
+                        delay = IDLE_DELAY;
+                        continue;
+                    }
+                };
+                delay = match bridge.run(&app_handle, &receiver, conn, tables, &mut failures) {
+                    Ok(Ended::Resubscribe) => Duration::ZERO,
+                    Ok(Ended::Stop) => break,
+                    Err(e) => {
+                        println!("Realtime connection lost: {}", e);
+                        failures += 1;
+                        BASE_RECONNECT_DELAY
+                            .saturating_mul(1 << (failures - 1).min(10u32))
+                            .min(MAX_RECONNECT_DELAY)
+                    }
+                };
+            }
+        });
+    }
+
+    /// Asks the connection thread to pick up a change of tables now.
+    fn wake(&self) {
+        if let Some(sender) = self.wake.lock().ok().and_then(|wake| wake.clone()) {
+            let _ = sender.send(());
+        }
+    }
+
+    fn tables(&self) -> BTreeSet<String> {
+        self.tables
+            .lock()
+            .map(|tables| tables.clone())
+            .unwrap_or_default()
+    }
+
+    /// Changes the subscribed tables with `change` and returns the new set.
+    fn update_tables(&self, change: impl FnOnce(&mut BTreeSet<String>)) -> Vec<String> {
+        let tables = match self.tables.lock() {
+            Ok(mut tables) => {
+                change(&mut tables);
+                tables.iter().cloned().collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        self.wake();
+        tables
+    }
+
+    /// Opens a connection subscribed to `tables` and relays their changes until the
+    /// set or the session changes, the app closes or the connection fails.
+    fn run<R: Runtime>(
+        &self,
+        app_handle: &AppHandle<R>,
+        receiver: &Receiver<()>,
+        conn: SupabaseConnection,
+        tables: BTreeSet<String>,
+        failures: &mut u32,
+    ) -> Result<Ended, PoleshiftError> {
+        let realtime_error = |e: tungstenite::Error| PoleshiftError::Other(e.to_string());
+
+        // 1) Connect; the project key goes in the URL, the user's token in each join
+        let endpoint = conn.endpoint("realtime/v1/websocket");
+        let url = format!(
+            "{}?apikey={}&vsn=1.0.0",
+            endpoint
+                .replacen("https://", "wss://", 1)
+                .replacen("http://", "ws://", 1),
+            conn.api_key
+        );
+        let (mut socket, _) = tungstenite::connect(url).map_err(realtime_error)?;
+        set_read_timeout(&socket, POLL_INTERVAL)?;
+        *failures = 0;
+
+        // 2) Join a channel per table
+        let mut reference = 0u64;
+        let mut send = |socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+                        topic: &str,
+                        event: &str,
+                        payload: Value| {
+            reference += 1;
+            let message = json!({
+                "topic": topic,
+                "event": event,
+                "payload": payload,
+                "ref": reference.to_string(),
+            });
+            socket
+                .send(Message::text(message.to_string()))
+                .map_err(realtime_error)
+        };
+        for table in &tables {
+            let payload = json!({
+                "config": {
+                    "broadcast": { "self": false },
+                    "presence": { "key": "" },
+                    "postgres_changes": [{ "event": "*", "schema": "public", "table": table }],
+                },
+                "access_token": conn.access_token,
+            });
+            send(&mut socket, &topic(table), "phx_join", payload)?;
+        }
+
+        // 3) Relay changes, heartbeating and watching the session between reads
+        let access_token = conn.access_token;
+        let mut last_heartbeat = Instant::now();
+        loop {
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    if let Ok(message) = serde_json::from_str::<ChannelMessage>(&text) {
+                        self.handle(app_handle, message)?;
+                    }
+                }
+                Ok(Message::Close(frame)) => {
+                    return Err(PoleshiftError::Other(format!(
+                        "closed by the server: {}",
+                        frame.map(|f| f.reason.to_string()).unwrap_or_default()
+                    )))
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(realtime_error(e)),
+            }
+
+            match receiver.try_recv() {
+                Ok(()) if self.tables() != tables => {
+                    let _ = socket.close(None);
+                    return Ok(Ended::Resubscribe);
+                }
+                Err(TryRecvError::Disconnected) => return Ok(Ended::Stop),
+                _ => {}
+            }
+
+            if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+                send(&mut socket, "phoenix", "heartbeat", json!({}))?;
+                last_heartbeat = Instant::now();
+            }
+
+            // Nothing more is relayed on the old session's token, nor once signed out
+            if !connection(app_handle).is_ok_and(|conn| conn.access_token == access_token) {
+                let _ = socket.close(None);
+                return Ok(Ended::Resubscribe);
+            }
+        }
+    }
+
+    /// Emits the change a message carries; a channel Realtime turned down or closed
+    /// is reported, and fails the connection so it is joined again.
+    fn handle<R: Runtime>(
+        &self,
+        app_handle: &AppHandle<R>,
+        message: ChannelMessage,
+    ) -> Result<(), PoleshiftError> {
+        match message.event.as_str() {
+            "postgres_changes" => {
+                match serde_json::from_value::<TableChange>(message.payload["data"].clone()) {
+                    Ok(change) => {
+                        let _ = app_handle.emit(TABLE_CHANGE_EVENT, &change);
+                    }
+                    Err(e) => println!("Unreadable change on {}: {}", message.topic, e),
+                }
+            }
+            "phx_reply" if message.payload["status"] == "error" => {
+                println!(
+                    "Realtime refused {}: {}",
+                    message.topic, message.payload["response"]
+                );
+            }
+            "phx_error" | "phx_close" => {
+                return Err(PoleshiftError::Other(format!(
+                    "channel {} closed",
+                    message.topic
+                )));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn topic(table: &str) -> String {
+    format!("realtime:poleshift:{}", table)
+}
+
+/// Makes reads on `socket` give up after `timeout`, so the connection loop gets a turn.
+fn set_read_timeout(
+    socket: &WebSocket<MaybeTlsStream<TcpStream>>,
+    timeout: Duration,
+) -> Result<(), PoleshiftError> {
+    let stream = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream,
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref(),
+        _ => return Ok(()),
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    Ok(())
+}
+
+/// Subscribes to the row changes of `tables`, in addition to those already
+/// subscribed, and returns all the subscribed tables. Changes arrive as `table-change`
+/// events once signed in and online.
+#[tauri::command(rename_all = "snake_case")]
+pub fn subscribe_table_changes(
+    invocation: Invocation,
+    realtime: State<'_, RealtimeBridge>,
+    tables: Vec<String>,
+) -> Result<StandardResponseNoFiles<Vec<String>>, PoleshiftError> {
+    traced_blocking(invocation, || {
+        for table in &tables {
+            validate_table(table)?;
+        }
+        let subscribed = realtime.update_tables(|subscribed| subscribed.extend(tables));
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: subscribed,
+        })
+    })
+}
+
+/// Stops the row changes of `tables`, or of all tables when not given, and returns the
+/// tables still subscribed.
+#[tauri::command(rename_all = "snake_case")]
+pub fn unsubscribe_table_changes(
+    invocation: Invocation,
+    realtime: State<'_, RealtimeBridge>,
+    tables: Option<Vec<String>>,
+) -> Result<StandardResponseNoFiles<Vec<String>>, PoleshiftError> {
+    traced_blocking(invocation, || {
+        let subscribed = realtime.update_tables(|subscribed| match tables {
+            Some(tables) => subscribed.retain(|table| !tables.contains(table)),
+            None => subscribed.clear(),
+        });
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: subscribed,
+        })
+    })
+}
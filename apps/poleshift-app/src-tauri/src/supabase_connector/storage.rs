@@ -0,0 +1,518 @@
+//poleshift/src-tauri/src/supabase_connector/storage.rs
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_util::StreamExt;
+use reqwest::header::{CONTENT_TYPE, LOCATION, RANGE};
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, Runtime, Window};
+
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::jobs::{JobHandle, JobManager};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::supabase_connector::permissions::{require_permission, Permission};
+use crate::supabase_connector::{connection, encode_path, SupabaseConnection};
+
+/// Chunk size of resumable uploads; Supabase Storage only takes 6 MiB chunks.
+const UPLOAD_CHUNK_SIZE: usize = 6 * 1024 * 1024;
+/// Suffix of the object holding a raw file's SHA-256, next to the file's own object.
+const CHECKSUM_SUFFIX: &str = ".sha256";
+const TUS_VERSION: &str = "1.0.0";
+
+/// Outcome of an upload or download.
+#[derive(Debug, Serialize)]
+pub struct StorageTransfer {
+    pub bucket: String,
+    pub object_path: String,
+    pub local_path: String,
+    pub bytes: u64,
+    pub sha256: String,
+    /// Bytes an earlier, interrupted attempt had already moved
+    pub resumed_from: u64,
+    /// Whether the checksum was checked against the stored one; objects uploaded
+    /// without `upload_raw_file` have none
+    pub verified: bool,
+}
+
+/// Where an interrupted upload picks up again.
+#[derive(Serialize, Deserialize)]
+struct UploadState {
+    upload_url: String,
+    bytes: u64,
+    sha256: String,
+}
+
+fn storage_error(action: &str, e: impl std::fmt::Display) -> PoleshiftError {
+    PoleshiftError::DataError(format!("{}: {}", action, e))
+}
+
+/// Fails on a non-success status, with the body Supabase explained it in.
+async fn check_status(action: &str, response: Response) -> Result<Response, PoleshiftError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(storage_error(action, format!("{} {}", status, body)))
+}
+
+fn validate_object(bucket: &str, object_path: &str) -> Result<(), PoleshiftError> {
+    let invalid = |field: &str, reason: &str| PoleshiftError::InvalidInput {
+        field: field.to_string(),
+        reason: reason.to_string(),
+    };
+    if bucket.is_empty() || bucket.contains('/') {
+        return Err(invalid("bucket", "must be a bucket name"));
+    }
+    if object_path.is_empty()
+        || object_path.starts_with('/')
+        || object_path.split('/').any(|s| s.is_empty() || s == "..")
+    {
+        return Err(invalid(
+            "object_path",
+            "must be a relative path without empty or '..' segments",
+        ));
+    }
+    Ok(())
+}
+
+/// Where a download may be written: `file_path` must be an absolute path, without
+/// `..` segments, inside the app data directory or the user's download directory.
+fn download_destination<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    file_path: &str,
+) -> Result<PathBuf, PoleshiftError> {
+    let path = PathBuf::from(file_path);
+    let allowed = [
+        app_handle.path().app_data_dir(),
+        app_handle.path().download_dir(),
+    ];
+    let inside = path.is_absolute()
+        && !path.components().any(|c| c == Component::ParentDir)
+        && allowed
+            .iter()
+            .flatten()
+            .any(|dir| path.starts_with(dir) && path != *dir);
+    if !inside {
+        return Err(PoleshiftError::InvalidInput {
+            field: "file_path".to_string(),
+            reason: "must be a file in the app data or download directory".to_string(),
+        });
+    }
+    Ok(path)
+}
+
+fn sha256_of_file(path: &Path, job: &JobHandle) -> Result<String, PoleshiftError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = vec![0u8; 1 << 20];
+    let mut hasher = Sha256::new();
+    job.set_reads(0);
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        job.add_reads(n as u64);
+        job.check_cancelled()?;
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hashes `path` on the blocking pool.
+async fn hash_file(path: &Path, job: &JobHandle) -> Result<String, PoleshiftError> {
+    let (path, job) = (path.to_path_buf(), job.clone());
+    tauri::async_runtime::spawn_blocking(move || sha256_of_file(&path, &job))
+        .await
+        .map_err(|e| PoleshiftError::Other(e.to_string()))?
+}
+
+/// File keeping the resume state of uploads to `bucket`/`object_path`.
+fn upload_state_path<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    bucket: &str,
+    object_path: &str,
+) -> Result<PathBuf, PoleshiftError> {
+    let key = hex::encode(Sha256::digest(format!("{}/{}", bucket, object_path)));
+    Ok(app_handle
+        .path()
+        .app_cache_dir()
+        .map_err(|e| PoleshiftError::PathResolution(e.to_string()))?
+        .join("storage_uploads")
+        .join(format!("{}.json", key)))
+}
+
+/// Offset the server has of an upload started earlier, or `None` when it is gone.
+async fn upload_offset(
+    client: &reqwest::Client,
+    conn: &SupabaseConnection,
+    upload_url: &str,
+) -> Result<Option<u64>, PoleshiftError> {
+    let response = conn
+        .authorize(client.head(upload_url))
+        .header("Tus-Resumable", TUS_VERSION)
+        .send()
+        .await
+        .map_err(|e| storage_error("Checking an earlier upload", e))?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    Ok(header_u64(&response, "Upload-Offset"))
+}
+
+fn header_u64(response: &Response, name: &str) -> Option<u64> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Starts a resumable upload of `bytes` to `bucket`/`object_path`; returns its URL.
+async fn create_upload(
+    client: &reqwest::Client,
+    conn: &SupabaseConnection,
+    bucket: &str,
+    object_path: &str,
+    bytes: u64,
+) -> Result<String, PoleshiftError> {
+    let metadata = format!(
+        "bucketName {},objectName {},contentType {}",
+        STANDARD.encode(bucket.as_bytes()),
+        STANDARD.encode(object_path.as_bytes()),
+        STANDARD.encode(b"application/octet-stream")
+    );
+    let response = conn
+        .authorize(client.post(conn.endpoint("storage/v1/upload/resumable")))
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Upload-Length", bytes.to_string())
+        .header("Upload-Metadata", metadata)
+        .header("x-upsert", "true")
+        .send()
+        .await
+        .map_err(|e| storage_error("Starting the upload", e))?;
+    let response = check_status("Starting the upload", response).await?;
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| storage_error("Starting the upload", "no upload URL was returned"))?;
+    // The location may be relative to the project
+    Ok(if location.starts_with("http") {
+        location.to_string()
+    } else {
+        conn.endpoint(location)
+    })
+}
+
+/// Streams a local raw file (FASTQ, RSK, ...) to `bucket`/`object_path` in Supabase
+/// Storage in 6 MiB chunks, with its SHA-256 stored next to it as
+/// `{object_path}.sha256`. Runs as an `upload` job reporting on
+/// `job://{job_id}/progress`.
+///
+/// An upload that was cancelled or lost its connection resumes where it stopped when
+/// the same file is uploaded to the same path again.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn upload_raw_file<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    file_path: String,
+    bucket: String,
+    object_path: String,
+    job_id: Option<String>,
+) -> Result<StandardResponseNoFiles<StorageTransfer>, PoleshiftError> {
+    let trace_handle = app_handle.clone();
+    let jobs = trace_handle.state::<JobManager>();
+    let job = jobs.start("upload", job_id)?;
+    let result = traced(
+        invocation,
+        Some(job.id()),
+        run_upload(app_handle, file_path, bucket, object_path, job.clone()),
+    )
+    .await;
+    jobs.finish(job.id(), result.as_ref().err());
+    result
+}
+
+async fn run_upload<R: Runtime>(
+    app_handle: AppHandle<R>,
+    file_path: String,
+    bucket: String,
+    object_path: String,
+    job: JobHandle,
+) -> Result<StandardResponseNoFiles<StorageTransfer>, PoleshiftError> {
+    validate_object(&bucket, &object_path)?;
+    require_permission(&app_handle, Permission::ModifySampleGroup)?;
+    let conn = connection(&app_handle)?;
+    let window = main_window(&app_handle)?;
+    let path = PathBuf::from(&file_path);
+    let bytes = fs::metadata(&path)?.len();
+    job.set_reads_total(bytes);
+
+    // 1) Hash the file, for the stored checksum and to recognize a resumed upload
+    job.set_stage("hashing");
+    job.report_progress(&window, 0, "Computing checksum...", "processing")?;
+    let sha256 = hash_file(&path, &job).await?;
+
+    // 2) Resume the earlier upload of this file, or start a new one
+    let client = reqwest::Client::new();
+    let state_path = upload_state_path(&app_handle, &bucket, &object_path)?;
+    let earlier = fs::read(&state_path)
+        .ok()
+        .and_then(|json| serde_json::from_slice::<UploadState>(&json).ok())
+        .filter(|state| state.bytes == bytes && state.sha256 == sha256);
+    let resumed = match earlier {
+        Some(state) => upload_offset(&client, &conn, &state.upload_url)
+            .await?
+            .map(|offset| (state.upload_url, offset)),
+        None => None,
+    };
+    let (upload_url, mut offset) = match resumed {
+        Some(resumed) => resumed,
+        None => {
+            let upload_url = create_upload(&client, &conn, &bucket, &object_path, bytes).await?;
+            if let Some(parent) = state_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let state = UploadState {
+                upload_url: upload_url.clone(),
+                bytes,
+                sha256: sha256.clone(),
+            };
+            fs::write(&state_path, serde_json::to_vec(&state)?)?;
+            (upload_url, 0)
+        }
+    };
+    let resumed_from = offset;
+
+    // 3) Send the rest in chunks; a cancelled upload keeps its state to resume from
+    job.set_stage("uploading");
+    job.set_reads(offset);
+    let mut file = File::open(&path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buffer = vec![0u8; UPLOAD_CHUNK_SIZE];
+    while offset < bytes {
+        job.check_cancelled()?;
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let n = file.read(&mut buffer[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        let response = conn
+            .authorize(client.patch(&upload_url))
+            .header("Tus-Resumable", TUS_VERSION)
+            .header("Upload-Offset", offset.to_string())
+            .header(CONTENT_TYPE, "application/offset+octet-stream")
+            .body(buffer[..filled].to_vec())
+            .send()
+            .await
+            .map_err(|e| storage_error("Uploading a chunk", e))?;
+        let response = check_status("Uploading a chunk", response).await?;
+        offset = header_u64(&response, "Upload-Offset").unwrap_or(offset + filled as u64);
+        job.set_reads(offset);
+        job.report_progress(
+            &window,
+            (offset * 95 / bytes.max(1)) as u8,
+            &format!("Uploaded {} of {} bytes...", offset, bytes),
+            "processing",
+        )?;
+    }
+
+    // 4) Store the checksum next to the file
+    let response = conn
+        .authorize(client.post(conn.endpoint(&format!(
+            "storage/v1/object/{}/{}{}",
+            encode_path(&bucket),
+            encode_path(&object_path),
+            CHECKSUM_SUFFIX
+        ))))
+        .header("x-upsert", "true")
+        .header(CONTENT_TYPE, "text/plain")
+        .body(sha256.clone())
+        .send()
+        .await
+        .map_err(|e| storage_error("Storing the checksum", e))?;
+    check_status("Storing the checksum", response).await?;
+    let _ = fs::remove_file(&state_path);
+
+    job.report_progress(&window, 100, "Upload complete", "complete")?;
+    Ok(StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report: StorageTransfer {
+            bucket,
+            object_path,
+            local_path: file_path,
+            bytes,
+            sha256,
+            resumed_from,
+            verified: true,
+        },
+    })
+}
+
+/// Streams `bucket`/`object_path` from Supabase Storage to `file_path`, checking it
+/// against the checksum `upload_raw_file` stored with it. Runs as a `download` job
+/// reporting on `job://{job_id}/progress`.
+///
+/// `file_path` must lie in the app data directory or the user's download directory.
+/// The file is written to `{file_path}.part` and renamed once verified; an interrupted
+/// download resumes from what the `.part` file holds.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn download_raw_file<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    bucket: String,
+    object_path: String,
+    file_path: String,
+    job_id: Option<String>,
+) -> Result<StandardResponseNoFiles<StorageTransfer>, PoleshiftError> {
+    let trace_handle = app_handle.clone();
+    let jobs = trace_handle.state::<JobManager>();
+    let job = jobs.start("download", job_id)?;
+    let result = traced(
+        invocation,
+        Some(job.id()),
+        run_download(app_handle, bucket, object_path, file_path, job.clone()),
+    )
+    .await;
+    jobs.finish(job.id(), result.as_ref().err());
+    result
+}
+
+async fn run_download<R: Runtime>(
+    app_handle: AppHandle<R>,
+    bucket: String,
+    object_path: String,
+    file_path: String,
+    job: JobHandle,
+) -> Result<StandardResponseNoFiles<StorageTransfer>, PoleshiftError> {
+    validate_object(&bucket, &object_path)?;
+    let path = download_destination(&app_handle, &file_path)?;
+    let conn = connection(&app_handle)?;
+    let window = main_window(&app_handle)?;
+    let client = reqwest::Client::new();
+    let object_url = conn.endpoint(&format!(
+        "storage/v1/object/authenticated/{}/{}",
+        encode_path(&bucket),
+        encode_path(&object_path)
+    ));
+
+    // 1) The stored checksum, when the file was uploaded with one
+    let response = conn
+        .authorize(client.get(format!("{}{}", object_url, CHECKSUM_SUFFIX)))
+        .send()
+        .await
+        .map_err(|e| storage_error("Reading the checksum", e))?;
+    let expected = if response.status().is_success() {
+        let text = response
+            .text()
+            .await
+            .map_err(|e| storage_error("Reading the checksum", e))?;
+        Some(text.trim().to_string())
+    } else {
+        None
+    };
+
+    // 2) Fetch what the .part file is missing
+    let part_path = PathBuf::from(format!("{}.part", file_path));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut offset = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+    let mut request = conn.authorize(client.get(&object_url));
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| storage_error("Downloading", e))?;
+    job.set_stage("downloading");
+    let resumed_from = if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The .part file already holds the whole object
+        offset
+    } else {
+        let response = check_status("Downloading", response).await?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            offset = 0;
+        }
+        let bytes = offset + response.content_length().unwrap_or(0);
+        job.set_reads_total(bytes);
+        job.set_reads(offset);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(offset > 0)
+            .write(true)
+            .truncate(offset == 0)
+            .open(&part_path)?;
+        let resumed_from = offset;
+        let mut reported = None;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            // A cancelled download keeps its .part file to resume from
+            job.check_cancelled()?;
+            let chunk = chunk.map_err(|e| storage_error("Downloading", e))?;
+            file.write_all(&chunk)?;
+            offset += chunk.len() as u64;
+            job.set_reads(offset);
+            let percentage = (offset * 90 / bytes.max(1)) as u8;
+            if reported != Some(percentage) {
+                reported = Some(percentage);
+                job.report_progress(
+                    &window,
+                    percentage,
+                    &format!("Downloaded {} of {} bytes...", offset, bytes),
+                    "processing",
+                )?;
+            }
+        }
+        file.flush()?;
+        resumed_from
+    };
+
+    // 3) Verify, then move into place
+    job.set_stage("verifying");
+    job.report_progress(&window, 90, "Verifying checksum...", "processing")?;
+    let bytes = fs::metadata(&part_path)?.len();
+    job.set_reads_total(bytes);
+    let sha256 = hash_file(&part_path, &job).await?;
+    if let Some(expected) = &expected {
+        if *expected != sha256 {
+            let _ = fs::remove_file(&part_path);
+            return Err(PoleshiftError::DataError(format!(
+                "Checksum mismatch for {}/{}. Expected: {} Found: {}",
+                bucket, object_path, expected, sha256
+            )));
+        }
+    }
+    fs::rename(&part_path, &path)?;
+
+    job.report_progress(&window, 100, "Download complete", "complete")?;
+    Ok(StandardResponseNoFiles {
+        status: "Success".to_string(),
+        report: StorageTransfer {
+            bucket,
+            object_path,
+            local_path: file_path,
+            bytes,
+            sha256,
+            resumed_from,
+            verified: expected.is_some(),
+        },
+    })
+}
+
+fn main_window<R: Runtime>(app_handle: &AppHandle<R>) -> Result<Window<R>, PoleshiftError> {
+    app_handle
+        .get_window("main")
+        .ok_or(PoleshiftError::WindowNotFound)
+}
@@ -0,0 +1,426 @@
+//poleshift/src-tauri/src/supabase_connector/upload.rs
+
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::auth::AuthManager;
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::settings::current_settings;
+use crate::supabase_connector::permissions::{require_permission, Permission};
+use crate::supabase_connector::{
+    connection, id_filter, validate_table, SupabaseConnection, MAX_FILTER_IDS,
+};
+
+/// Attempts of a batch that fails for a passing reason (network, 5xx, 408, 429).
+const MAX_BATCH_ATTEMPTS: u32 = 3;
+/// Wait before the second attempt of a batch, growing by as much on each further one.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Kind of a local write, as PowerSync names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CrudOp {
+    Put,
+    Patch,
+    Delete,
+}
+
+/// One local write to replay on Supabase.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrudOperation {
+    pub table: String,
+    pub op: CrudOp,
+    pub id: String,
+    /// Row of a `PUT`, changed columns of a `PATCH`
+    #[serde(default)]
+    pub data: Option<Map<String, Value>>,
+}
+
+/// A write Supabase did not take, with its reason.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedRecord {
+    pub table: String,
+    pub op: CrudOp,
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct UploadReport {
+    pub succeeded: usize,
+    pub failed: Vec<FailedRecord>,
+    /// REST calls made, retries included
+    pub requests: usize,
+    /// Whether the session's token was turned down, ending the upload; the writes not
+    /// sent are among the failed ones, and the session is being renewed
+    pub unauthorized: bool,
+}
+
+/// A batch of writes to the same table, sent as one call.
+struct Batch {
+    table: String,
+    op: CrudOp,
+    operations: Vec<CrudOperation>,
+}
+
+enum BatchError {
+    /// Worth another attempt: the network, a 5xx, a 408 or a 429
+    Transient(String),
+    /// Refused for what one of its rows holds (400, 409, 422) or for its size (413);
+    /// worth splitting up
+    Rejected(String),
+    /// Refused as a whole, for a reason no smaller batch avoids
+    Refused(String),
+    /// The session's token was turned down (401, 403); nothing else will get through
+    Unauthorized(String),
+}
+
+impl BatchError {
+    /// Error of a call answered with `status`.
+    fn from_status(status: StatusCode, error: String) -> Self {
+        match status.as_u16() {
+            401 | 403 => BatchError::Unauthorized(error),
+            408 | 429 | 500..=599 => BatchError::Transient(error),
+            400 | 409 | 413 | 422 => BatchError::Rejected(error),
+            _ => BatchError::Refused(error),
+        }
+    }
+}
+
+impl CrudOp {
+    /// The permission a write of this kind needs.
+    fn permission(self) -> Permission {
+        match self {
+            CrudOp::Put | CrudOp::Patch => Permission::ModifySampleGroup,
+            CrudOp::Delete => Permission::DeleteSampleGroup,
+        }
+    }
+}
+
+impl Batch {
+    /// Sends the batch once: a bulk upsert, one update of rows sharing the same
+    /// changes, or one delete.
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        conn: &SupabaseConnection,
+    ) -> Result<(), BatchError> {
+        let url = conn.endpoint(&format!("rest/v1/{}", self.table));
+        let request = match self.op {
+            CrudOp::Put => {
+                let rows: Vec<Value> = self
+                    .operations
+                    .iter()
+                    .map(|operation| {
+                        let mut row = operation.data.clone().unwrap_or_default();
+                        row.insert("id".to_string(), Value::String(operation.id.clone()));
+                        Value::Object(row)
+                    })
+                    .collect();
+                let body =
+                    serde_json::to_vec(&rows).map_err(|e| BatchError::Rejected(e.to_string()))?;
+                client
+                    .post(url)
+                    .header("Prefer", "resolution=merge-duplicates,return=minimal")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+            }
+            CrudOp::Patch => {
+                let changes = self.operations[0].data.clone().unwrap_or_default();
+                let body = serde_json::to_vec(&changes)
+                    .map_err(|e| BatchError::Rejected(e.to_string()))?;
+                client
+                    .request(Method::PATCH, url)
+                    .query(&[("id", self.id_filter())])
+                    .header("Prefer", "return=minimal")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+            }
+            CrudOp::Delete => client
+                .delete(url)
+                .query(&[("id", self.id_filter())])
+                .header("Prefer", "return=minimal"),
+        };
+
+        let response = conn
+            .authorize(request)
+            .send()
+            .await
+            .map_err(|e| BatchError::Transient(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error = format!("{}: {}", status, response.text().await.unwrap_or_default());
+        Err(BatchError::from_status(status, error))
+    }
+
+    fn id_filter(&self) -> String {
+        id_filter(
+            self.operations
+                .iter()
+                .map(|operation| operation.id.as_str()),
+        )
+    }
+
+    /// The two halves of a batch of more than one write.
+    fn split(mut self) -> (Batch, Batch) {
+        let second = self.operations.split_off(self.operations.len() / 2);
+        let other = Batch {
+            table: self.table.clone(),
+            op: self.op,
+            operations: second,
+        };
+        (self, other)
+    }
+
+    fn fail(self, error: &str, report: &mut UploadReport) {
+        report
+            .failed
+            .extend(self.operations.into_iter().map(|operation| FailedRecord {
+                table: self.table.clone(),
+                op: self.op,
+                id: operation.id,
+                error: error.to_string(),
+            }));
+    }
+}
+
+/// Groups `operations` into batches by table and kind, in the order each group first
+/// appears: upserts by their columns and `batch_size`, updates by the changes they
+/// make and deletes by `MAX_FILTER_IDS`. A row written again ends the groups so far,
+/// so its writes still land in the order they were made.
+fn batches(operations: Vec<CrudOperation>, batch_size: usize) -> Vec<Batch> {
+    let mut batches = Vec::new();
+    let mut groups: Vec<(String, CrudOp, String, Vec<CrudOperation>)> = Vec::new();
+    let mut pending = HashSet::new();
+    for operation in operations {
+        if !pending.insert((operation.table.clone(), operation.id.clone())) {
+            flush_groups(&mut groups, batch_size, &mut batches);
+            pending.clear();
+            pending.insert((operation.table.clone(), operation.id.clone()));
+        }
+        // Upserted rows share a call when they have the same columns, updates when they
+        // change the same columns the same way
+        let changes = match (&operation.op, &operation.data) {
+            (CrudOp::Put, Some(row)) => row.keys().cloned().collect::<Vec<_>>().join(","),
+            (CrudOp::Patch, data) => serde_json::to_string(data).unwrap_or_default(),
+            _ => String::new(),
+        };
+        match groups.iter_mut().find(|(table, op, key, _)| {
+            *table == operation.table && *op == operation.op && *key == changes
+        }) {
+            Some((_, _, _, group)) => group.push(operation),
+            None => groups.push((
+                operation.table.clone(),
+                operation.op,
+                changes,
+                vec![operation],
+            )),
+        }
+    }
+    flush_groups(&mut groups, batch_size, &mut batches);
+    batches
+}
+
+/// Moves `groups` into `batches`, split to the size each kind of write allows.
+fn flush_groups(
+    groups: &mut Vec<(String, CrudOp, String, Vec<CrudOperation>)>,
+    batch_size: usize,
+    batches: &mut Vec<Batch>,
+) {
+    for (table, op, _, group) in groups.drain(..) {
+        let size = match op {
+            CrudOp::Put => batch_size,
+            CrudOp::Patch | CrudOp::Delete => batch_size.min(MAX_FILTER_IDS),
+        };
+        for chunk in group.chunks(size) {
+            batches.push(Batch {
+                table: table.clone(),
+                op,
+                operations: chunk.to_vec(),
+            });
+        }
+    }
+}
+
+/// Replays `operations` on Supabase in bulk. A batch failing for a passing reason is
+/// retried up to `MAX_BATCH_ATTEMPTS` times; one Supabase refuses for what a row holds
+/// is split in halves until the writes it refuses are singled out, so the report names
+/// exactly those. A token Supabase turns down ends the upload, failing the writes left.
+pub(crate) async fn upload_operations(
+    client: &reqwest::Client,
+    conn: &SupabaseConnection,
+    operations: Vec<CrudOperation>,
+    batch_size: usize,
+) -> UploadReport {
+    let mut report = UploadReport::default();
+    let mut pending: VecDeque<Batch> = batches(operations, batch_size.max(1)).into();
+    while let Some(batch) = pending.pop_front() {
+        let mut attempt = 1;
+        let outcome = loop {
+            report.requests += 1;
+            match batch.send(client, conn).await {
+                Err(BatchError::Transient(_)) if attempt < MAX_BATCH_ATTEMPTS => {
+                    let wait = RETRY_DELAY * attempt;
+                    let _ = tauri::async_runtime::spawn_blocking(move || std::thread::sleep(wait))
+                        .await;
+                    attempt += 1;
+                }
+                outcome => break outcome,
+            }
+        };
+        match outcome {
+            Ok(()) => report.succeeded += batch.operations.len(),
+            Err(BatchError::Rejected(_)) if batch.operations.len() > 1 => {
+                let (first, second) = batch.split();
+                pending.push_front(second);
+                pending.push_front(first);
+            }
+            Err(BatchError::Unauthorized(error)) => {
+                report.unauthorized = true;
+                batch.fail(&error, &mut report);
+                for batch in pending.drain(..) {
+                    batch.fail(&error, &mut report);
+                }
+            }
+            Err(
+                BatchError::Rejected(error)
+                | BatchError::Refused(error)
+                | BatchError::Transient(error),
+            ) => batch.fail(&error, &mut report),
+        }
+    }
+    report
+}
+
+/// Replays local writes (PowerSync CRUD entries) on Supabase with bulk calls of up to
+/// `batch_size` rows, the `upload_batch_size` setting when not given. Writes that
+/// fail are listed in the report rather than failing the call, so the caller can tell
+/// exactly which records did not land. The session's role must grant the permission
+/// of each kind of write; otherwise nothing is sent. When Supabase turns the session's
+/// token down, the upload stops and the session is renewed for the next one.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn upload_data<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    operations: Vec<CrudOperation>,
+    batch_size: Option<usize>,
+) -> Result<StandardResponseNoFiles<UploadReport>, PoleshiftError> {
+    traced(invocation, None, async move {
+        for operation in &operations {
+            validate_table(&operation.table)?;
+            if operation.op == CrudOp::Patch && operation.data.is_none() {
+                return Err(PoleshiftError::InvalidInput {
+                    field: "operations".to_string(),
+                    reason: format!("the PATCH of {} has no data", operation.id),
+                });
+            }
+        }
+        if batch_size == Some(0) {
+            return Err(PoleshiftError::InvalidInput {
+                field: "batch_size".to_string(),
+                reason: "must be 1 or more".to_string(),
+            });
+        }
+        for permission in [Permission::ModifySampleGroup, Permission::DeleteSampleGroup] {
+            if operations.iter().any(|o| o.op.permission() == permission) {
+                require_permission(&app_handle, permission)?;
+            }
+        }
+        let conn = connection(&app_handle)?;
+        let batch_size = batch_size.unwrap_or(current_settings(&app_handle).upload_batch_size);
+        let report =
+            upload_operations(&reqwest::Client::new(), &conn, operations, batch_size).await;
+        if report.unauthorized {
+            if let Some(auth) = app_handle.try_state::<AuthManager>() {
+                auth.request_renewal();
+            }
+        }
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(status: u16) -> &'static str {
+        let status = StatusCode::from_u16(status).unwrap();
+        match BatchError::from_status(status, String::new()) {
+            BatchError::Transient(_) => "transient",
+            BatchError::Rejected(_) => "rejected",
+            BatchError::Refused(_) => "refused",
+            BatchError::Unauthorized(_) => "unauthorized",
+        }
+    }
+
+    #[test]
+    fn only_row_specific_and_oversized_batches_are_split() {
+        for status in [400, 409, 413, 422] {
+            assert_eq!(kind(status), "rejected", "{}", status);
+        }
+        for status in [404, 405] {
+            assert_eq!(kind(status), "refused", "{}", status);
+        }
+    }
+
+    fn operation(op: CrudOp, id: &str) -> CrudOperation {
+        let data = (op == CrudOp::Put).then(|| {
+            let mut row = Map::new();
+            row.insert("id".to_string(), Value::from(id));
+            row
+        });
+        CrudOperation {
+            table: "samples".to_string(),
+            op,
+            id: id.to_string(),
+            data,
+        }
+    }
+
+    #[test]
+    fn keeps_the_order_of_writes_to_the_same_row() {
+        let operations = vec![
+            operation(CrudOp::Put, "a"),
+            operation(CrudOp::Put, "b"),
+            operation(CrudOp::Delete, "a"),
+            operation(CrudOp::Put, "a"),
+        ];
+        let sent: Vec<(CrudOp, Vec<String>)> = batches(operations, 100)
+            .into_iter()
+            .map(|batch| {
+                let ids = batch.operations.into_iter().map(|o| o.id).collect();
+                (batch.op, ids)
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                (CrudOp::Put, vec!["a".to_string(), "b".to_string()]),
+                (CrudOp::Delete, vec!["a".to_string()]),
+                (CrudOp::Put, vec!["a".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_auth_and_passing_failures() {
+        assert_eq!(kind(401), "unauthorized");
+        assert_eq!(kind(403), "unauthorized");
+        for status in [408, 429, 500, 503] {
+            assert_eq!(kind(status), "transient", "{}", status);
+        }
+    }
+}
@@ -0,0 +1,894 @@
+//poleshift/src-tauri/src/sync/mod.rs
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, RequestBuilder};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::auth::AuthManager;
+use crate::ctd::delimited::parse_iso8601;
+use crate::ctd::format_iso8601;
+use crate::devtools::{traced, Invocation};
+use crate::poleshift_common::types::{PoleshiftError, StandardResponseNoFiles};
+use crate::poleshift_common::utils::{now_ms, parse_uuid};
+use crate::results_store::{open_results_store, results_store_path, sql_error};
+use crate::settings::current_settings;
+use crate::supabase_connector::permissions::{require_permission, Permission};
+use crate::supabase_connector::{id_filter, validate_table, SupabaseConnection, MAX_FILTER_IDS};
+
+/// Event carrying the `SyncStatus` counts after each flush that got anywhere.
+pub const SYNC_EVENT: &str = "sync-status";
+/// How often the queue is flushed when nothing wakes it sooner.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+/// Failed uploads of a record before it is left for `retry_failed_uploads`.
+const MAX_ATTEMPTS: u32 = 8;
+/// Wait after the first failed upload of a record, doubled on each further one.
+const BASE_BACKOFF_MS: i64 = 5_000;
+const MAX_BACKOFF_MS: i64 = 30 * 60 * 1000;
+/// Time an upload may take before the server counts as unreachable.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Where a queued record stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncState {
+    /// Waiting for its first or next attempt
+    Pending,
+    /// Upserted into Supabase
+    Synced,
+    /// Gave up after `MAX_ATTEMPTS`; see `last_error`
+    Failed,
+}
+
+impl SyncState {
+    fn as_str(self) -> &'static str {
+        match self {
+            SyncState::Pending => "pending",
+            SyncState::Synced => "synced",
+            SyncState::Failed => "failed",
+        }
+    }
+}
+
+impl FromSql for SyncState {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "pending" => Ok(SyncState::Pending),
+            "synced" => Ok(SyncState::Synced),
+            "failed" => Ok(SyncState::Failed),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// A batch of rows waiting to be, or already, upserted into a Supabase table.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncRecord {
+    pub id: i64,
+    pub processed_data_id: Option<String>,
+    pub target_table: String,
+    pub row_count: usize,
+    pub state: SyncState,
+    pub attempts: u32,
+    pub next_attempt_ms: i64,
+    pub last_error: Option<String>,
+    pub created_at_ms: i64,
+    pub synced_at_ms: Option<i64>,
+}
+
+/// What the queue does with a row whose server copy changed after the local write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Keep the server's copy and drop the local write
+    ServerWins,
+    /// Upload the local write over the server's copy
+    ClientWins,
+    /// Hold the local write until `resolve_sync_conflict` settles it
+    Manual,
+}
+
+/// The copy a conflict was settled in favour of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictSide {
+    Server,
+    Client,
+}
+
+impl ConflictSide {
+    fn as_str(self) -> &'static str {
+        match self {
+            ConflictSide::Server => "server",
+            ConflictSide::Client => "client",
+        }
+    }
+}
+
+impl FromSql for ConflictSide {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "server" => Ok(ConflictSide::Server),
+            "client" => Ok(ConflictSide::Client),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// A queued row whose server copy changed after it was written locally.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncConflict {
+    pub id: i64,
+    pub processed_data_id: Option<String>,
+    pub target_table: String,
+    pub row_id: String,
+    pub local_row: Value,
+    pub server_row: Value,
+    /// `None` while waiting for `resolve_sync_conflict`
+    pub resolution: Option<ConflictSide>,
+    pub detected_at_ms: i64,
+    pub resolved_at_ms: Option<i64>,
+}
+
+/// Where the queue stands, as returned by `sync_status` and emitted on `sync-status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncStatus {
+    /// Whether the last flush reached Supabase
+    pub online: bool,
+    /// Whether there is a session to upload with
+    pub has_credentials: bool,
+    pub pending: usize,
+    pub synced: usize,
+    pub failed: usize,
+    /// Conflicts waiting for `resolve_sync_conflict`
+    pub conflicts: usize,
+    pub last_flush_ms: Option<i64>,
+    /// The records not yet synced, oldest first; left out of the event
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<SyncRecord>,
+}
+
+/// Supabase project and session uploads are made with.
+#[derive(Clone, Deserialize)]
+pub struct SyncCredentials {
+    pub supabase_url: String,
+    pub api_key: String,
+    pub access_token: String,
+}
+
+impl SyncCredentials {
+    /// A `method` call on the REST endpoint of `table`, signed with the session.
+    fn table_request(
+        &self,
+        client: &reqwest::Client,
+        method: Method,
+        table: &str,
+    ) -> RequestBuilder {
+        let conn = SupabaseConnection {
+            url: self.supabase_url.clone(),
+            api_key: self.api_key.clone(),
+            access_token: self.access_token.clone(),
+        };
+        conn.authorize(client.request(method, conn.endpoint(&format!("rest/v1/{}", table))))
+    }
+}
+
+/// Outbound queue of writes to Supabase, kept in the results store.
+///
+/// Writes are queued locally with `queue_upload` and flushed in order by a background
+/// thread, started with `start` from the app's setup, whenever it is woken or every
+/// `FLUSH_INTERVAL`. A record that cannot be sent because the server is unreachable
+/// or busy (408, 429, 5xx) waits for the next flush without losing an attempt; one the
+/// server refuses is retried with a growing backoff and marked failed after
+/// `MAX_ATTEMPTS`. Rows whose server copy changed after they were written are settled
+/// by the `conflict_policy` setting and listed by `list_sync_conflicts`.
+#[derive(Default)]
+pub struct SyncQueue {
+    credentials: Mutex<Option<SyncCredentials>>,
+    wake: Mutex<Option<Sender<()>>>,
+    online: AtomicBool,
+    last_flush_ms: AtomicI64,
+}
+
+impl SyncQueue {
+    /// Starts the flushing thread.
+    pub fn start<R: Runtime>(&self, app_handle: AppHandle<R>) {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut wake) = self.wake.lock() {
+            *wake = Some(sender);
+        }
+        std::thread::spawn(move || {
+            // Woken by `wake`, or flushing every `FLUSH_INTERVAL`
+            while let Ok(()) | Err(RecvTimeoutError::Timeout) =
+                receiver.recv_timeout(FLUSH_INTERVAL)
+            {
+                let Some(queue) = app_handle.try_state::<SyncQueue>() else {
+                    break;
+                };
+                if let Err(e) = queue.flush(&app_handle) {
+                    println!("Sync flush failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Asks the flushing thread for a flush now.
+    fn wake(&self) {
+        if let Some(sender) = self.wake.lock().ok().and_then(|wake| wake.clone()) {
+            let _ = sender.send(());
+        }
+    }
+
+    /// Sets the project and session to upload with (see `AuthManager`) and flushes.
+    pub(crate) fn set_credentials(&self, credentials: Option<SyncCredentials>) {
+        if let Ok(mut current) = self.credentials.lock() {
+            *current = credentials;
+        }
+        self.wake();
+    }
+
+    fn credentials(&self) -> Option<SyncCredentials> {
+        self.credentials.lock().ok().and_then(|c| c.clone())
+    }
+
+    /// Uploads the due records in queue order. A record whose upload fails holds back
+    /// the later ones of its processed data, so children never land before parents.
+    fn flush<R: Runtime>(&self, app_handle: &AppHandle<R>) -> Result<(), PoleshiftError> {
+        let Some(credentials) = self.credentials() else {
+            return Ok(());
+        };
+        let conn = open_results_store(&results_store_path(app_handle)?)?;
+        let due = due_records(&conn)?;
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        let policy = current_settings(app_handle).conflict_policy;
+        let client = reqwest::Client::builder()
+            .timeout(UPLOAD_TIMEOUT)
+            .build()
+            .map_err(|e| PoleshiftError::DataError(e.to_string()))?;
+        let mut held_back: Vec<String> = Vec::new();
+        for (record, rows) in due {
+            if record
+                .processed_data_id
+                .as_ref()
+                .is_some_and(|id| held_back.contains(id))
+            {
+                continue;
+            }
+            let rows: Vec<Value> = serde_json::from_str(&rows)?;
+            let checked = tauri::async_runtime::block_on(newer_server_rows(
+                &client,
+                &credentials,
+                &record.target_table,
+                &rows,
+            ));
+            let outcome = match checked {
+                Ok(newer) => match settle_conflicts(&conn, &record, rows, newer, policy)? {
+                    Some(rows) => tauri::async_runtime::block_on(upload(
+                        &client,
+                        &credentials,
+                        &record.target_table,
+                        rows,
+                    )),
+                    None => Upload::Done,
+                },
+                Err(outcome) => outcome,
+            };
+            match outcome {
+                Upload::Done => {
+                    self.online.store(true, Ordering::Relaxed);
+                    conn.execute(
+                        "UPDATE sync_queue SET state = ?2, synced_at_ms = ?3, last_error = NULL
+                         WHERE id = ?1",
+                        params![record.id, SyncState::Synced.as_str(), now_ms()],
+                    )
+                    .map_err(sql_error)?;
+                }
+                // Nothing else can get through either; wait for the next flush
+                Upload::Offline(error) => {
+                    self.online.store(false, Ordering::Relaxed);
+                    record_error(&conn, record.id, &error)?;
+                    break;
+                }
+                // Tried again on the next flush, without costing the record an attempt
+                Upload::Unavailable(error) => {
+                    self.online.store(true, Ordering::Relaxed);
+                    record_error(&conn, record.id, &error)?;
+                    break;
+                }
+                Upload::Unauthorized(error) => {
+                    self.online.store(true, Ordering::Relaxed);
+                    record_error(&conn, record.id, &error)?;
+                    if let Some(auth) = app_handle.try_state::<AuthManager>() {
+                        auth.request_renewal();
+                    }
+                    break;
+                }
+                Upload::Refused(error) => {
+                    self.online.store(true, Ordering::Relaxed);
+                    let attempts = record.attempts + 1;
+                    let state = if attempts >= MAX_ATTEMPTS {
+                        SyncState::Failed
+                    } else {
+                        SyncState::Pending
+                    };
+                    conn.execute(
+                        "UPDATE sync_queue
+                         SET state = ?2, attempts = ?3, next_attempt_ms = ?4, last_error = ?5
+                         WHERE id = ?1",
+                        params![
+                            record.id,
+                            state.as_str(),
+                            attempts,
+                            now_ms() + backoff_ms(attempts),
+                            error
+                        ],
+                    )
+                    .map_err(sql_error)?;
+                    held_back.extend(record.processed_data_id.clone());
+                }
+            }
+        }
+        self.last_flush_ms.store(now_ms(), Ordering::Relaxed);
+
+        let status = self.status(&conn, None, false)?;
+        let _ = app_handle.emit(SYNC_EVENT, &status);
+        Ok(())
+    }
+
+    fn status(
+        &self,
+        conn: &Connection,
+        processed_data_id: Option<&str>,
+        with_records: bool,
+    ) -> Result<SyncStatus, PoleshiftError> {
+        let mut status = SyncStatus {
+            online: self.online.load(Ordering::Relaxed),
+            has_credentials: self.credentials().is_some(),
+            pending: 0,
+            synced: 0,
+            failed: 0,
+            conflicts: 0,
+            last_flush_ms: Some(self.last_flush_ms.load(Ordering::Relaxed)).filter(|&ms| ms > 0),
+            records: Vec::new(),
+        };
+        let mut stmt = conn
+            .prepare(
+                "SELECT state, count(*) FROM sync_queue
+                 WHERE ?1 IS NULL OR processed_data_id = ?1 GROUP BY state",
+            )
+            .map_err(sql_error)?;
+        let counts = stmt
+            .query_map(params![processed_data_id], |row| {
+                Ok((row.get::<_, SyncState>(0)?, row.get::<_, i64>(1)? as usize))
+            })
+            .map_err(sql_error)?;
+        for count in counts {
+            let (state, count) = count.map_err(sql_error)?;
+            match state {
+                SyncState::Pending => status.pending = count,
+                SyncState::Synced => status.synced = count,
+                SyncState::Failed => status.failed = count,
+            }
+        }
+        status.conflicts = conn
+            .query_row(
+                "SELECT count(*) FROM sync_conflict
+                 WHERE resolution IS NULL AND (?1 IS NULL OR processed_data_id = ?1)",
+                params![processed_data_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(sql_error)? as usize;
+        if with_records {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT {} FROM sync_queue
+                     WHERE state != 'synced' AND (?1 IS NULL OR processed_data_id = ?1)
+                     ORDER BY id",
+                    RECORD_COLUMNS
+                ))
+                .map_err(sql_error)?;
+            status.records = stmt
+                .query_map(params![processed_data_id], record_from_row)
+                .map_err(sql_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(sql_error)?;
+        }
+        Ok(status)
+    }
+}
+
+const RECORD_COLUMNS: &str = "id, processed_data_id, target_table, row_count, state, attempts,
+    next_attempt_ms, last_error, created_at_ms, synced_at_ms";
+
+fn record_from_row(row: &rusqlite::Row) -> rusqlite::Result<SyncRecord> {
+    Ok(SyncRecord {
+        id: row.get(0)?,
+        processed_data_id: row.get(1)?,
+        target_table: row.get(2)?,
+        row_count: row.get::<_, i64>(3)? as usize,
+        state: row.get(4)?,
+        attempts: row.get(5)?,
+        next_attempt_ms: row.get(6)?,
+        last_error: row.get(7)?,
+        created_at_ms: row.get(8)?,
+        synced_at_ms: row.get(9)?,
+    })
+}
+
+/// Pending records whose backoff has run out, oldest first, with their rows. Records
+/// behind a failed or waiting one of the same processed data are left for later.
+fn due_records(conn: &Connection) -> Result<Vec<(SyncRecord, String)>, PoleshiftError> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {}, rows FROM sync_queue AS record
+             WHERE state = 'pending' AND next_attempt_ms <= ?1
+               AND NOT EXISTS (
+                   SELECT 1 FROM sync_queue AS earlier
+                   WHERE earlier.processed_data_id = record.processed_data_id
+                     AND earlier.id < record.id
+                     AND (earlier.state = 'failed'
+                          OR (earlier.state = 'pending' AND earlier.next_attempt_ms > ?1))
+               )
+             ORDER BY id",
+            RECORD_COLUMNS
+        ))
+        .map_err(sql_error)?;
+    let records = stmt
+        .query_map(params![now_ms()], |row| {
+            Ok((record_from_row(row)?, row.get::<_, String>(10)?))
+        })
+        .map_err(sql_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(sql_error)?;
+    Ok(records)
+}
+
+fn record_error(conn: &Connection, id: i64, error: &str) -> Result<(), PoleshiftError> {
+    conn.execute(
+        "UPDATE sync_queue SET last_error = ?2 WHERE id = ?1",
+        params![id, error],
+    )
+    .map_err(sql_error)?;
+    Ok(())
+}
+
+/// Wait before the next attempt of a record that has failed `attempts` times.
+fn backoff_ms(attempts: u32) -> i64 {
+    BASE_BACKOFF_MS
+        .saturating_mul(1 << attempts.saturating_sub(1).min(20))
+        .min(MAX_BACKOFF_MS)
+}
+
+enum Upload {
+    Done,
+    /// The server could not be reached
+    Offline(String),
+    /// The server answered but cannot take rows now (408, 429, 5xx)
+    Unavailable(String),
+    /// The session expired or may not write; uploads resume once the `AuthManager`
+    /// renews it
+    Unauthorized(String),
+    /// The server turned the rows down
+    Refused(String),
+}
+
+/// Upserts `rows` (a JSON array) into `table` through the Supabase REST API.
+async fn upload(
+    client: &reqwest::Client,
+    credentials: &SyncCredentials,
+    table: &str,
+    rows: String,
+) -> Upload {
+    let response = credentials
+        .table_request(client, Method::POST, table)
+        .header(CONTENT_TYPE, "application/json")
+        .header("Prefer", "resolution=merge-duplicates,return=minimal")
+        .body(rows)
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => Upload::Done,
+        Ok(response) => refusal(response).await,
+        Err(e) => Upload::Offline(e.to_string()),
+    }
+}
+
+/// The outcome of a call the server answered with an error status.
+async fn refusal(response: reqwest::Response) -> Upload {
+    let status = response.status();
+    let error = format!("{}: {}", status, response.text().await.unwrap_or_default());
+    match status.as_u16() {
+        401 | 403 => Upload::Unauthorized(error),
+        408 | 429 | 500..=599 => Upload::Unavailable(error),
+        _ => Upload::Refused(error),
+    }
+}
+
+/// Whether `server` was written after `local`: a later `updated_at`, or when the two
+/// do not both have one, a higher `version`.
+fn is_newer(server: &Map<String, Value>, local: &Map<String, Value>) -> bool {
+    let updated_at = |row: &Map<String, Value>| {
+        row.get("updated_at")
+            .and_then(Value::as_str)
+            .and_then(parse_iso8601)
+    };
+    if let (Some(server), Some(local)) = (updated_at(server), updated_at(local)) {
+        return server > local;
+    }
+    let version = |row: &Map<String, Value>| row.get("version").and_then(Value::as_i64);
+    matches!((version(server), version(local)), (Some(server), Some(local)) if server > local)
+}
+
+/// Server copies of those of `rows` that changed after the local write, by row ID.
+/// Only rows with an `updated_at` or `version` column are looked up; an error is the
+/// outcome the flush reports for the record.
+async fn newer_server_rows(
+    client: &reqwest::Client,
+    credentials: &SyncCredentials,
+    table: &str,
+    rows: &[Value],
+) -> Result<HashMap<String, Map<String, Value>>, Upload> {
+    let versioned: HashMap<&str, &Map<String, Value>> = rows
+        .iter()
+        .filter_map(Value::as_object)
+        .filter(|row| row.contains_key("updated_at") || row.contains_key("version"))
+        .filter_map(|row| Some((row.get("id")?.as_str()?, row)))
+        .collect();
+    let ids: Vec<&str> = versioned.keys().copied().collect();
+    let mut newer = HashMap::new();
+    for chunk in ids.chunks(MAX_FILTER_IDS) {
+        let response = credentials
+            .table_request(client, Method::GET, table)
+            .query(&[
+                ("select", "*".to_string()),
+                ("id", id_filter(chunk.iter().copied())),
+            ])
+            .send()
+            .await
+            .map_err(|e| Upload::Offline(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(refusal(response).await);
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| Upload::Offline(e.to_string()))?;
+        let server_rows: Vec<Map<String, Value>> =
+            serde_json::from_slice(&body).map_err(|e| Upload::Refused(e.to_string()))?;
+        for server in server_rows {
+            let Some(id) = server.get("id").and_then(Value::as_str) else {
+                continue;
+            };
+            if versioned
+                .get(id)
+                .is_some_and(|local| is_newer(&server, local))
+            {
+                newer.insert(id.to_string(), server);
+            }
+        }
+    }
+    Ok(newer)
+}
+
+/// Records the rows of `record` that have a newer server copy in `newer`, settled by
+/// `policy` or left open when it is `Manual`, and returns the rows left to upload, or
+/// `None` when there are none. The record keeps only those rows, so a later attempt
+/// does not run into the same conflicts.
+fn settle_conflicts(
+    conn: &Connection,
+    record: &SyncRecord,
+    rows: Vec<Value>,
+    newer: HashMap<String, Map<String, Value>>,
+    policy: ConflictPolicy,
+) -> Result<Option<String>, PoleshiftError> {
+    if newer.is_empty() {
+        return Ok(Some(serde_json::to_string(&rows)?));
+    }
+    let resolution = match policy {
+        ConflictPolicy::ServerWins => Some(ConflictSide::Server),
+        ConflictPolicy::ClientWins => Some(ConflictSide::Client),
+        ConflictPolicy::Manual => None,
+    };
+    let now = now_ms();
+    let tx = conn.unchecked_transaction().map_err(sql_error)?;
+    let mut remaining = Vec::new();
+    for row in rows {
+        let id = row.get("id").and_then(Value::as_str);
+        let Some((id, server)) = id.and_then(|id| newer.get_key_value(id)) else {
+            remaining.push(row);
+            continue;
+        };
+        // The same record meets a conflict it kept again on each attempt
+        tx.execute(
+            "INSERT OR IGNORE INTO sync_conflict
+                 (queue_id, processed_data_id, target_table, row_id, local_row, server_row,
+                  resolution, detected_at_ms, resolved_at_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                record.id,
+                record.processed_data_id,
+                record.target_table,
+                id,
+                row.to_string(),
+                serde_json::to_string(server)?,
+                resolution.map(ConflictSide::as_str),
+                now,
+                resolution.map(|_| now)
+            ],
+        )
+        .map_err(sql_error)?;
+        if policy == ConflictPolicy::ClientWins {
+            remaining.push(row);
+        }
+    }
+    let rows = serde_json::to_string(&remaining)?;
+    tx.execute(
+        "UPDATE sync_queue SET rows = ?2, row_count = ?3 WHERE id = ?1",
+        params![record.id, rows, remaining.len() as i64],
+    )
+    .map_err(sql_error)?;
+    tx.commit().map_err(sql_error)?;
+    Ok(Some(rows).filter(|_| !remaining.is_empty()))
+}
+
+/// Queues `rows` for upsert into the Supabase `table`, split into records of
+/// `batch_size` rows. Returns the IDs of the queued records.
+pub(crate) fn enqueue_upload(
+    path: &Path,
+    processed_data_id: Option<&str>,
+    table: &str,
+    rows: &[Value],
+    batch_size: usize,
+) -> Result<Vec<i64>, PoleshiftError> {
+    let mut conn = open_results_store(path)?;
+    let tx = conn.transaction().map_err(sql_error)?;
+    let now = now_ms();
+    let mut ids = Vec::new();
+    for batch in rows.chunks(batch_size.max(1)) {
+        tx.execute(
+            "INSERT INTO sync_queue
+                 (processed_data_id, target_table, rows, row_count, state, next_attempt_ms,
+                  created_at_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+            params![
+                processed_data_id,
+                table,
+                serde_json::to_string(batch)?,
+                batch.len() as i64,
+                SyncState::Pending.as_str(),
+                now
+            ],
+        )
+        .map_err(sql_error)?;
+        ids.push(tx.last_insert_rowid());
+    }
+    tx.commit().map_err(sql_error)?;
+    Ok(ids)
+}
+
+/// Writes `rows` to the local queue for upsert into the Supabase `table`, in records
+/// of the `upload_batch_size` setting; they are
+/// uploaded in the background once online. `processed_data_id` groups the records of
+/// one dataset, so its later records wait for an earlier one that failed.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn queue_upload<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    queue: State<'_, SyncQueue>,
+    table: String,
+    rows: Vec<Value>,
+    processed_data_id: Option<String>,
+) -> Result<StandardResponseNoFiles<Vec<i64>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        validate_table(&table)?;
+        if let Some(id) = &processed_data_id {
+            parse_uuid("processed_data_id", id)?;
+        }
+        require_permission(&app_handle, Permission::ModifySampleGroup)?;
+        let ids = enqueue_upload(
+            &results_store_path(&app_handle)?,
+            processed_data_id.as_deref(),
+            &table,
+            &rows,
+            current_settings(&app_handle).upload_batch_size,
+        )?;
+        queue.wake();
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: ids,
+        })
+    })
+    .await
+}
+
+/// Returns the queue's counts and the records not yet synced, of one processed data ID
+/// when given.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn sync_status<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    queue: State<'_, SyncQueue>,
+    processed_data_id: Option<String>,
+) -> Result<StandardResponseNoFiles<SyncStatus>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_results_store(&results_store_path(&app_handle)?)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: queue.status(&conn, processed_data_id.as_deref(), true)?,
+        })
+    })
+    .await
+}
+
+/// Puts failed records, or those of one processed data ID, back in the queue with
+/// their attempts reset. Returns how many were requeued.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn retry_failed_uploads<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    queue: State<'_, SyncQueue>,
+    processed_data_id: Option<String>,
+) -> Result<StandardResponseNoFiles<usize>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_results_store(&results_store_path(&app_handle)?)?;
+        let requeued = conn
+            .execute(
+                "UPDATE sync_queue SET state = 'pending', attempts = 0, next_attempt_ms = ?2
+                 WHERE state = 'failed' AND (?1 IS NULL OR processed_data_id = ?1)",
+                params![processed_data_id, now_ms()],
+            )
+            .map_err(sql_error)?;
+        queue.wake();
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: requeued,
+        })
+    })
+    .await
+}
+
+const CONFLICT_COLUMNS: &str = "id, processed_data_id, target_table, row_id, local_row,
+    server_row, resolution, detected_at_ms, resolved_at_ms";
+
+fn conflict_from_row(row: &rusqlite::Row) -> rusqlite::Result<SyncConflict> {
+    let json = |index: usize| -> rusqlite::Result<Value> {
+        let text: String = row.get(index)?;
+        serde_json::from_str(&text).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, e.into())
+        })
+    };
+    Ok(SyncConflict {
+        id: row.get(0)?,
+        processed_data_id: row.get(1)?,
+        target_table: row.get(2)?,
+        row_id: row.get(3)?,
+        local_row: json(4)?,
+        server_row: json(5)?,
+        resolution: row.get(6)?,
+        detected_at_ms: row.get(7)?,
+        resolved_at_ms: row.get(8)?,
+    })
+}
+
+/// Lists the conflicts waiting to be resolved, oldest first, of one processed data ID
+/// when given; `include_resolved` adds those already settled.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_sync_conflicts<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    processed_data_id: Option<String>,
+    include_resolved: Option<bool>,
+) -> Result<StandardResponseNoFiles<Vec<SyncConflict>>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let conn = open_results_store(&results_store_path(&app_handle)?)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM sync_conflict
+                 WHERE (?1 IS NULL OR processed_data_id = ?1) AND (?2 OR resolution IS NULL)
+                 ORDER BY id",
+                CONFLICT_COLUMNS
+            ))
+            .map_err(sql_error)?;
+        let conflicts = stmt
+            .query_map(
+                params![processed_data_id, include_resolved.unwrap_or(false)],
+                conflict_from_row,
+            )
+            .map_err(sql_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sql_error)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: conflicts,
+        })
+    })
+    .await
+}
+
+/// Settles an open conflict. Keeping the client's row queues it again as a new write,
+/// its `updated_at` set to now and its `version` past the server's, so it lands over
+/// the server's copy; keeping the server's drops it. Returns the settled conflict.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn resolve_sync_conflict<R: Runtime>(
+    invocation: Invocation<R>,
+    app_handle: AppHandle<R>,
+    queue: State<'_, SyncQueue>,
+    conflict_id: i64,
+    keep: ConflictSide,
+) -> Result<StandardResponseNoFiles<SyncConflict>, PoleshiftError> {
+    traced(invocation, None, async move {
+        let path = results_store_path(&app_handle)?;
+        let conn = open_results_store(&path)?;
+        let select = format!(
+            "SELECT {} FROM sync_conflict WHERE id = ?1",
+            CONFLICT_COLUMNS
+        );
+        let conflict = conn
+            .query_row(&select, params![conflict_id], conflict_from_row)
+            .optional()
+            .map_err(sql_error)?
+            .ok_or_else(|| PoleshiftError::InvalidInput {
+                field: "conflict_id".to_string(),
+                reason: format!("no sync conflict {}", conflict_id),
+            })?;
+        if conflict.resolution.is_some() {
+            return Err(PoleshiftError::InvalidInput {
+                field: "conflict_id".to_string(),
+                reason: format!("sync conflict {} is already resolved", conflict_id),
+            });
+        }
+
+        if keep == ConflictSide::Client {
+            require_permission(&app_handle, Permission::ModifySampleGroup)?;
+            let mut row = conflict.local_row.clone();
+            if let Some(row) = row.as_object_mut() {
+                if row.contains_key("updated_at") {
+                    row.insert(
+                        "updated_at".to_string(),
+                        Value::String(format_iso8601(now_ms(), 0)),
+                    );
+                }
+                let version = |row: &Value| row.get("version").and_then(Value::as_i64);
+                if let Some(local) = version(&conflict.local_row) {
+                    let server = version(&conflict.server_row).unwrap_or(local);
+                    row.insert("version".to_string(), Value::from(local.max(server) + 1));
+                }
+            }
+            enqueue_upload(
+                &path,
+                conflict.processed_data_id.as_deref(),
+                &conflict.target_table,
+                &[row],
+                1,
+            )?;
+        }
+        conn.execute(
+            "UPDATE sync_conflict SET resolution = ?2, resolved_at_ms = ?3 WHERE id = ?1",
+            params![conflict_id, keep.as_str(), now_ms()],
+        )
+        .map_err(sql_error)?;
+        queue.wake();
+
+        let conflict = conn
+            .query_row(&select, params![conflict_id], conflict_from_row)
+            .map_err(sql_error)?;
+        Ok(StandardResponseNoFiles {
+            status: "Success".to_string(),
+            report: conflict,
+        })
+    })
+    .await
+}
@@ -57,7 +57,11 @@ const dropboxConfig: DropboxConfigItem[] = [
     id: DataType.CTD,
     label: 'CTD Data',
     dataType: DataType.CTD,
-    expectedFileTypes: { 'application/octet-stream': ['.rsk'] },
+    expectedFileTypes: {
+      'application/octet-stream': ['.rsk', '.cnv'],
+      'text/csv': ['.csv'],
+      'text/tab-separated-values': ['.tsv'],
+    },
     isEnabled: true,
     isModalInput: false,
     processFunctionName: 'handle_ctd_data',
@@ -16,7 +16,9 @@ import {
   ProcessedNutrientAmmoniaData,
   HandleCtdDataResult,
   HandleSequenceDataResult,
-  ProgressPayload
+  ProgressPayload,
+  RawFastqData,
+  RunMetadata,
 } from '@/types';
 
 import {
@@ -28,6 +30,34 @@ import {
   raw_nutrient_ammonia_data,
 } from '../lib/powersync/DrizzleSchema.ts';
 
+/**
+ * Fills back the run-level fields the backend blanks on every read when the whole
+ * run shares one value (see `RunMetadata::strip_shared_fields`).
+ */
+function restoreSharedRunFields(
+  rawSequences: RawFastqData[],
+  runMetadata: RunMetadata | null
+): RawFastqData[] {
+  if (!runMetadata) return rawSequences;
+  const shared = (values: string[]) => (values.length === 1 ? values[0] : null);
+  const fields: [keyof RawFastqData, string | null][] = [
+    ['run_id', shared(runMetadata.run_ids)],
+    ['flow_cell_id', shared(runMetadata.flow_cell_ids)],
+    ['protocol_group_id', shared(runMetadata.protocol_group_ids)],
+    ['basecall_model_version_id', shared(runMetadata.basecall_model_version_ids)],
+    ['sample_id_fastq', shared(runMetadata.sample_ids_fastq)],
+  ];
+  return rawSequences.map((sequence) => {
+    const restored = { ...sequence };
+    for (const [field, value] of fields) {
+      if (value !== null && !restored[field]) {
+        (restored as Record<string, unknown>)[field] = value;
+      }
+    }
+    return restored;
+  });
+}
+
 /**
  * Helper to create a placeholder in the `processed_data_improved` table.
  */
@@ -216,7 +246,7 @@ export function useTauriDataProcessor() {
       try {
           // Listen to progress events
           progressUnlisten = await listen<ProgressPayload>(
-              'progress',
+              `job://${processedDataId}/progress`,
               async ({ payload }) => {
                   const { progress_percentage, status_message, processing_state } =
                       payload;
@@ -240,6 +270,7 @@ export function useTauriDataProcessor() {
                   raw_data_id: rawDataId,
                   processed_data_id: processedDataId,
                   file_paths: filePaths,
+                  job_id: processedDataId,
               }
           );
 
@@ -477,7 +508,7 @@ export function useTauriDataProcessor() {
     try {
       // Listen to progress events
       progressUnlisten = await listen<ProgressPayload>(
-        'progress',
+        `job://${processedDataId}/progress`,
         async ({ payload }) => {
           const { progress_percentage, status_message, processing_state } =
             payload;
@@ -501,6 +532,7 @@ export function useTauriDataProcessor() {
           user_id: userId,
           org_id: organizationId,
           sample_id: sampleGroupId,
+          job_id: processedDataId,
         }
       );
 
@@ -525,6 +557,7 @@ export function useTauriDataProcessor() {
         raw_sequences,
         processed_kraken_uniq_report,
         processed_kraken_uniq_stdout,
+        run_metadata,
       } = report;
 
       // Bulk insert raw FASTQ data
@@ -553,7 +586,7 @@ export function useTauriDataProcessor() {
           'org_id',
           'sample_id',
         ],
-        raw_sequences
+        restoreSharedRunFields(raw_sequences, run_metadata)
       );
 
       await bulkInsertJSON1(
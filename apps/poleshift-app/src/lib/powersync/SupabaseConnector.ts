@@ -1,9 +1,10 @@
 import { createClient, Session, SupabaseClient } from '@supabase/supabase-js';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { useAuthStore } from '@/stores/authStore';
 import {
   AbstractPowerSyncDatabase,
   CrudEntry,
-  UpdateType,
 } from '@powersync/web';
 import { jwtDecode, JwtPayload } from 'jwt-decode';
 import {
@@ -21,30 +22,33 @@ interface SupabaseJwtPayload extends JwtPayload {
   user_org?: string;
 }
 
-/**
- * Helper to chunk an array into subarrays of a specific size
- */
-function chunkArray<T>(array: T[], size: number): T[][] {
-  const chunks: T[][] = [];
-  for (let i = 0; i < array.length; i += size) {
-    chunks.push(array.slice(i, i + size));
-  }
-  return chunks;
+/** Outcome of the backend's `upload_data`. */
+interface UploadReport {
+  succeeded: number;
+  failed: { table: string; op: string; id: string; error: string }[];
+  requests: number;
+  /** The session's token was refused; the backend is renewing it */
+  unauthorized: boolean;
 }
 
-function groupByTableAndOp(ops: CrudEntry[]): Record<string, CrudEntry[]> {
-  // Example of grouping by `${table}-${opType}`
-  return ops.reduce(
-    (acc, op) => {
-      const key = `${op.table}-${op.op}`;
-      acc[key] = acc[key] || [];
-      acc[key].push(op);
-      return acc;
-    },
-    {} as Record<string, CrudEntry[]>
-  );
+/** Session state the backend emits on `auth-state`. */
+interface AuthState {
+  signed_in: boolean;
+  user_id: string | null;
+  expires_at: number | null;
+  session: {
+    access_token: string;
+    refresh_token: string;
+    expires_at: number;
+    user_id: string | null;
+  } | null;
 }
 
+const SUPABASE_PROJECT = {
+  supabase_url: import.meta.env.VITE_SUPABASE_URL,
+  api_key: import.meta.env.VITE_SUPABASE_ANON_KEY,
+};
+
 export class SupabaseConnector {
   readonly client: SupabaseClient;
   private lastUserId: string | null;
@@ -55,8 +59,9 @@ export class SupabaseConnector {
       import.meta.env.VITE_SUPABASE_ANON_KEY!,
       {
         auth: {
-          persistSession: true,
-          autoRefreshToken: true,
+          // The backend keeps the session in the OS keychain and renews it
+          persistSession: false,
+          autoRefreshToken: false,
         },
       }
     );
@@ -75,19 +80,60 @@ export class SupabaseConnector {
         ].includes(event)
       ) {
         console.debug(session);
+        if (event !== 'INITIAL_SESSION') {
+          this.sendSessionToBackend(session);
+        }
         this.handleSessionChange(session);
       }
     });
+
+    // Adopt each session the backend renews, and its sign-out
+    listen<AuthState>('auth-state', ({ payload }) => {
+      this.adoptSession(payload);
+    });
     this.initSession();
   }
 
   private async initSession() {
-    const { data, error } = await this.client.auth.getSession();
-    if (error) {
+    try {
+      const { report } = await invoke<{ report: AuthState }>(
+        'restore_auth_session',
+        { project: SUPABASE_PROJECT }
+      );
+      await this.adoptSession(report);
+    } catch (error) {
       console.error('Error initializing session:', error);
-      return;
     }
-    this.handleSessionChange(data.session);
+  }
+
+  private async adoptSession(state: AuthState) {
+    const { data } = await this.client.auth.getSession();
+    if (state.session) {
+      if (data.session?.access_token !== state.session.access_token) {
+        await this.client.auth.setSession({
+          access_token: state.session.access_token,
+          refresh_token: state.session.refresh_token,
+        });
+      }
+    } else if (data.session) {
+      await this.client.auth.signOut({ scope: 'local' });
+    }
+  }
+
+  private sendSessionToBackend(session: Session | null) {
+    invoke('set_auth_session', {
+      project: SUPABASE_PROJECT,
+      session: session
+        ? {
+            access_token: session.access_token,
+            refresh_token: session.refresh_token,
+            expires_at: session.expires_at ?? 0,
+            user_id: session.user.id,
+          }
+        : null,
+    }).catch((error) =>
+      console.error('Error passing the session to the backend:', error)
+    );
   }
 
   private handleSessionChange(session: Session | null) {
@@ -240,18 +286,14 @@ export class SupabaseConnector {
   }
 
   /**
-   * Batches the CRUD operations by table & operation type to reduce the number of network calls,
-   * and limits each batch to `maxBatchSize`.
+   * Replays the next CRUD transaction on Supabase through the backend's bulk
+   * `upload_data`, which batches the writes and retries passing failures itself.
    *
-   * This version does NOT discard failed transactions. Instead, it retries them.
-   * Only if it succeeds does it call transaction.complete().
+   * The transaction is completed only once every write landed; otherwise the
+   * writes Supabase refused are logged and the transaction is kept for the next
+   * upload.
    */
-  async uploadData(
-    database: AbstractPowerSyncDatabase,
-    attempt = 1,
-    maxAttempts = 10,
-    maxBatchSize = 10000 // <-- Add your default max batch size here
-  ): Promise<void> {
+  async uploadData(database: AbstractPowerSyncDatabase): Promise<void> {
     const transaction = await database.getNextCrudTransaction();
 
     // If there is no transaction, do nothing
@@ -260,99 +302,36 @@ export class SupabaseConnector {
       return;
     }
 
-    let lastOp: CrudEntry | null = null;
+    const operations = transaction.crud.map((op: CrudEntry) => ({
+      table: op.table,
+      op: op.op,
+      id: op.id,
+      data: op.opData ?? null,
+    }));
+    const { report } = await invoke<{ report: UploadReport }>('upload_data', {
+      operations,
+    });
 
-    try {
-      console.debug(
-        `Uploading data (attempt ${attempt}/${maxAttempts}) with batching...`
+    if (report.unauthorized) {
+      throw new Error(
+        'Upload stopped: the session was refused and is being renewed'
       );
+    }
 
-      // 1. Group the ops by table and operation type
-      const groupedOps = groupByTableAndOp(transaction.crud);
-
-      // 2. Loop through each group and perform chunked bulk operations
-      for (const key of Object.keys(groupedOps)) {
-        const ops = groupedOps[key];
-        if (ops.length === 0) continue;
-
-        // Break down large ops array into sub-chunks of maxBatchSize
-        const opChunks = chunkArray(ops, maxBatchSize);
-
-        const [tableName, opType] = key.split('-') as [string, UpdateType];
-        const table = this.client.from(tableName);
-
-        // Process each sub-chunk
-        for (const chunk of opChunks) {
-          switch (opType) {
-            case UpdateType.PUT: {
-              // For PUT -> Use upsert
-              const rowsToUpsert = chunk.map((op) => {
-                lastOp = op;
-                return { ...op.opData, id: op.id };
-              });
-              const result = await table.upsert(rowsToUpsert);
-              if (result.error) throw result.error;
-              break;
-            }
-            case UpdateType.PATCH: {
-              // For PATCH -> Use update
-              // We'll keep doing them one by one, but in a chunk-limited loop
-              for (const op of chunk) {
-                lastOp = op;
-                const { error } = await table.update(op.opData).eq('id', op.id);
-                if (error) throw error;
-              }
-              break;
-            }
-            case UpdateType.DELETE: {
-              // For DELETE -> Use delete in
-              const idsToDelete = chunk.map((op) => {
-                lastOp = op;
-                return op.id;
-              });
-              const result = await table.delete().in('id', idsToDelete);
-              if (result.error) throw result.error;
-              break;
-            }
-            default:
-              throw new Error(`Unsupported operation type: ${opType}`);
-          }
-        }
-      }
-
-      // 3. Complete the transaction on success
-      await transaction.complete();
-      console.debug('Data upload successful.');
-    } catch (error) {
+    if (report.failed.length > 0) {
       console.error(
-        'Error uploading data in batch:',
-        error,
-        'Last operation:',
-        lastOp
+        `${report.failed.length} of ${operations.length} writes were not uploaded:`,
+        report.failed
+      );
+      throw new Error(
+        `Upload incomplete: ${report.failed.length} writes failed`
       );
-
-      // Retry logic: only retry up to `maxAttempts` times
-      if (attempt < maxAttempts) {
-        // Optional: Exponential backoff or just a delay
-        const delayMs = 1000 * attempt;
-        console.debug(
-          `Retrying after ${delayMs}ms... (attempt ${attempt + 1} of ${maxAttempts})`
-        );
-        await new Promise((resolve) => setTimeout(resolve, delayMs));
-
-        // Recursively call uploadData with incremented attempt
-        return this.uploadData(
-          database,
-          attempt + 1,
-          maxAttempts,
-          maxBatchSize
-        );
-      } else {
-        console.error(
-          `Max retry attempts (${maxAttempts}) reached. Not discarding transaction, but will not retry again.`
-        );
-      }
     }
+
+    await transaction.complete();
+    console.debug(
+      `Uploaded ${report.succeeded} writes in ${report.requests} requests.`
+    );
   }
 }
 
@@ -27,11 +27,32 @@ export interface HandleSequenceDataResult {
     processed_kraken_uniq_report: ProcessedKrakenUniqReport[];
     processed_kraken_uniq_stdout: ProcessedKrakenUniqStdout[];
     raw_sequences: RawFastqData[];
+    /** Run-level header fields; a field with a single value is blanked on every read */
+    run_metadata: RunMetadata | null;
   };
 }
 
+/** Run-level header fields of a sequencing run, as aggregated by the backend. */
+export interface RunMetadata {
+  read_count: number;
+  run_ids: string[];
+  flow_cell_ids: string[];
+  protocol_group_ids: string[];
+  basecall_model_version_ids: string[];
+  sample_ids_fastq: string[];
+  barcodes: string[];
+  first_start_time: string | null;
+  last_start_time: string | null;
+  channel_reads: Record<string, number>;
+  channels_used: number;
+}
+
+/** Emitted on `job://{job_id}/progress` by every long-running command. */
 export interface ProgressPayload {
+  job_id: string;
   progress_percentage: number;
   status_message: string | null;
   processing_state: ProcessingState;
+  /** Throughput so far, on the periodic updates of a running job */
+  reads_per_sec: number | null;
 }